@@ -0,0 +1,33 @@
+#!/bin/bash
+
+# Identity certificates for testing the validation of identities.
+# Uses ec_parameters.pem and the identity CA made by
+# generate-test-certificates-and-signatures.sh
+
+# openssl ca keeps a database of the certificates it signs
+touch identity_test_ca_index.txt
+echo 01 > identity_test_ca_serial
+
+# An identity certificate whose validity period has ended
+openssl req -newkey param:ec_parameters.pem -keyout expired_key.pem -nodes -out expired_certificate_request.pem -subj "/O=Example Organization/CN=expired_participant_common_name"
+openssl ca -batch -config identity_test_ca.cnf -in expired_certificate_request.pem -cert identity_ca.cert.pem -keyfile identity_ca_private_key.pem -passin file:password -startdate 20200101000000Z -enddate 20210101000000Z -out expired_cert.pem -notext
+
+# An identity certificate whose validity period has not yet started
+openssl req -newkey param:ec_parameters.pem -keyout not_yet_valid_key.pem -nodes -out not_yet_valid_certificate_request.pem -subj "/O=Example Organization/CN=not_yet_valid_participant_common_name"
+openssl ca -batch -config identity_test_ca.cnf -in not_yet_valid_certificate_request.pem -cert identity_ca.cert.pem -keyfile identity_ca_private_key.pem -passin file:password -startdate 21000101000000Z -enddate 21010101000000Z -out not_yet_valid_cert.pem -notext
+
+# An identity certificate signed by some other CA than the identity CA
+openssl req -x509 -newkey param:ec_parameters.pem -keyout other_ca_private_key.pem -passout file:password -out other_ca.cert.pem -days 999999 -subj "/O=Other Organization/CN=other_ca_common_name"
+openssl req -newkey param:ec_parameters.pem -keyout wrong_ca_key.pem -nodes -out wrong_ca_certificate_request.pem -subj "/O=Example Organization/CN=wrong_ca_participant_common_name"
+openssl x509 -req -days 999999 -in wrong_ca_certificate_request.pem -CA other_ca.cert.pem -CAkey other_ca_private_key.pem -passin file:password -out wrong_ca_cert.pem -set_serial 1
+
+# An identity certificate signed by the other CA, for a subject that the permissions allow
+openssl req -newkey param:ec_parameters.pem -keyout untrusted_key.pem -nodes -out untrusted_certificate_request.pem -subj "/O=Example Organization/CN=participant2_common_name"
+openssl x509 -req -days 999999 -in untrusted_certificate_request.pem -CA other_ca.cert.pem -CAkey other_ca_private_key.pem -passin file:password -out untrusted_cert.pem -set_serial 2
+
+# The participant identity protected with the password: an encrypted PKCS#8 key and a PKCS#12 bundle
+openssl pkcs8 -topk8 -in key.pem -out encrypted_key.pem -passout file:password
+openssl pkcs12 -export -in cert.pem -inkey key.pem -out identity.p12 -passout file:password -name participant1
+
+# Remove the database and the copies of the signed certificates
+rm -f identity_test_ca_index.txt* identity_test_ca_serial* 01.pem 02.pem
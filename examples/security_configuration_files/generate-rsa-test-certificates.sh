@@ -0,0 +1,13 @@
+#!/bin/bash
+
+# An identity CA and an identity certificate with RSA-2048 keys, for testing
+# RSA identities in the authentication handshake
+
+# Create the RSA identity CA
+openssl req -x509 -newkey rsa:2048 -keyout rsa_identity_ca_private_key.pem -passout file:password -out rsa_identity_ca.cert.pem -days 999999 -subj "/O=Example Organization/CN=rsa_identity_ca_common_name"
+
+# Create a certificate request for the participant identity certificate
+openssl req -newkey rsa:2048 -keyout rsa_key.pem -nodes -out rsa_identity_certificate_request.pem -subj "/O=Example Organization/CN=rsa_participant_common_name"
+
+# Sign the certificate request
+openssl x509 -req -days 999999 -in rsa_identity_certificate_request.pem -CA rsa_identity_ca.cert.pem -CAkey rsa_identity_ca_private_key.pem -passin file:password -out rsa_cert.pem -set_serial 1
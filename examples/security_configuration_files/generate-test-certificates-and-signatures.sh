@@ -9,6 +9,8 @@ openssl req -x509 -newkey param:ec_parameters.pem -keyout permissions_ca_private
 # Sign the configuration documents
 openssl smime -sign -in governance_unsigned.xml -text -out governance.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
 openssl smime -sign -in permissions_unsigned.xml -text -out permissions.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
+openssl smime -sign -nodetach -in governance_unsigned.xml -text -out governance_wrapped.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
+openssl smime -sign -nodetach -in permissions_unsigned.xml -text -out permissions_wrapped.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
 
 
 # Create the identity CA
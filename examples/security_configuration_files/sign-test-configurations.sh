@@ -2,4 +2,11 @@
 
 # Sign test configurations
 openssl smime -sign -in governance_unsigned.xml -text -out governance.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
-openssl smime -sign -in permissions_unsigned.xml -text -out permissions.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
\ No newline at end of file
+openssl smime -sign -in permissions_unsigned.xml -text -out permissions.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
+
+# Governance that lets participants without security use the unprotected topics
+openssl smime -sign -in governance_allow_unauthenticated_unsigned.xml -text -out governance_allow_unauthenticated.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
+
+# Same documents with the signature wrapping the content (application/pkcs7-mime)
+openssl smime -sign -nodetach -in governance_unsigned.xml -text -out governance_wrapped.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
+openssl smime -sign -nodetach -in permissions_unsigned.xml -text -out permissions_wrapped.p7s -signer permissions_ca.cert.pem -inkey permissions_ca_private_key.pem -passin file:password
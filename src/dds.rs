@@ -40,3 +40,11 @@ pub mod with_key;
 
 /// Serializer/deserializer adapters to connect serialization to RTPS.
 pub mod adapters;
+
+/// Protocol statistics compatible with the Fast DDS statistics module.
+#[cfg(feature = "fastdds_statistics")]
+pub mod statistics;
+
+/// Replay of captured RTPS traffic for testing and troubleshooting.
+#[cfg(feature = "replay")]
+pub mod replay;
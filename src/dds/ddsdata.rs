@@ -12,7 +12,8 @@ use crate::{
 #[derive(Debug, PartialEq, Eq, Clone)]
 // Contents of a DATA submessage or several DATAFRAG submessages. This is either
 // a new sample, or key, or a key hash. The latter two are used to indicate
-// dispose or unregister.
+// dispose or unregister. A key may come with its key hash, which is then sent
+// along as inline QoS.
 pub enum DDSData {
   Data {
     serialized_payload: SerializedPayload,
@@ -20,6 +21,7 @@ pub enum DDSData {
   DisposeByKey {
     change_kind: ChangeKind,
     key: SerializedPayload,
+    key_hash: Option<KeyHash>,
   },
   DisposeByKeyHash {
     change_kind: ChangeKind,
@@ -32,7 +34,23 @@ impl DDSData {
     Self::Data { serialized_payload }
   }
   pub fn new_disposed_by_key(change_kind: ChangeKind, key: SerializedPayload) -> Self {
-    Self::DisposeByKey { change_kind, key }
+    Self::DisposeByKey {
+      change_kind,
+      key,
+      key_hash: None,
+    }
+  }
+
+  pub fn new_disposed_by_key_and_hash(
+    change_kind: ChangeKind,
+    key: SerializedPayload,
+    key_hash: KeyHash,
+  ) -> Self {
+    Self::DisposeByKey {
+      change_kind,
+      key,
+      key_hash: Some(key_hash),
+    }
   }
 
   pub fn new_disposed_by_key_hash(change_kind: ChangeKind, key_hash: KeyHash) -> Self {
@@ -49,6 +67,14 @@ impl DDSData {
     }
   }
 
+  pub fn key_hash(&self) -> Option<KeyHash> {
+    match self {
+      DDSData::Data { .. } => None,
+      DDSData::DisposeByKey { key_hash, .. } => *key_hash,
+      DDSData::DisposeByKeyHash { key_hash, .. } => Some(*key_hash),
+    }
+  }
+
   // What is the serialized size of this?
   pub fn payload_size(&self) -> usize {
     match self {
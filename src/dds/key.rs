@@ -227,3 +227,31 @@ impl BuiltInTopicKey {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn key_hash_of_short_key_is_padded_big_endian_cdr() {
+    assert_eq!(
+      0x0102_0304_i32.hash_key(false).to_vec(),
+      [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+  }
+
+  #[test]
+  fn key_hash_of_unbounded_key_is_md5_of_big_endian_cdr() {
+    // MD5 of 00 00 00 04 'a' 'b' 'c' 00
+    let expected = [
+      0x1a, 0x69, 0x74, 0xca, 0xe0, 0xba, 0x21, 0xbf, 0x15, 0xf8, 0x8d, 0x75, 0x9c, 0x31, 0xea,
+      0xf8,
+    ];
+    assert_eq!("abc".to_string().hash_key(false).to_vec(), expected);
+    // DDS Security requires MD5 for all keys
+    assert_eq!(
+      0x0102_0304_i32.hash_key(true).to_vec(),
+      md5::compute([1, 2, 3, 4]).to_vec()
+    );
+  }
+}
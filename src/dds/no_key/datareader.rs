@@ -8,9 +8,9 @@ use futures::stream::{FusedStream, Stream};
 
 use crate::{
   dds::{
-    adapters::no_key::{DefaultDecoder, DeserializerAdapter},
+    adapters::no_key::{Decode, DefaultDecoder, DeserializerAdapter},
     no_key::datasample::DataSample,
-    qos::{HasQoSPolicy, QosPolicies},
+    qos::{HasQoSPolicy, MatchedContract, QosPolicies},
     readcondition::ReadCondition,
     result::ReadResult,
     statusevents::DataReaderStatus,
@@ -25,7 +25,7 @@ use crate::{
   structure::entity::RTPSEntity,
   StatusEvented, GUID,
 };
-use super::wrappers::{DAWrapper, NoKeyWrapper};
+use super::wrappers::{DAWrapper, DecodeWrapper, NoKeyWrapper};
 
 /// Simplified type for CDR encoding
 pub type DataReaderCdr<D> = DataReader<D, CDRDeserializerAdapter<D>>;
@@ -70,6 +70,32 @@ where
       keyed_datareader: keyed,
     }
   }
+
+  /// Same as [`take`](Self::take), but decodes the samples with the given
+  /// decoder instead of the default one.
+  ///
+  /// If a sample cannot be decoded, the error is returned and the sample is
+  /// skipped. Samples decoded before it remain available for the next call.
+  pub fn take_with<S>(
+    &mut self,
+    max_samples: usize,
+    read_condition: ReadCondition,
+    decoder: S,
+  ) -> ReadResult<Vec<DataSample<D>>>
+  where
+    S: Decode<DA::Decoded> + Clone,
+  {
+    let values: Vec<WithKeyDataSample<NoKeyWrapper<D>>> =
+      self
+        .keyed_datareader
+        .take_with(max_samples, read_condition, DecodeWrapper::new(decoder))?;
+    Ok(
+      values
+        .into_iter()
+        .filter_map(DataSample::<D>::from_with_key)
+        .collect(),
+    )
+  }
 }
 
 impl<D: 'static, DA> DataReader<D, DA>
@@ -421,6 +447,19 @@ where
   }
   */
 
+  /// The QoS contract with a matched DataWriter. See
+  /// [`with_key::DataReader::matched_contract`](crate::with_key::DataReader::matched_contract).
+  pub fn matched_contract(&self, writer: GUID) -> Option<MatchedContract> {
+    self.keyed_datareader.matched_contract(writer)
+  }
+
+  /// Number of samples dropped so far, because the
+  /// [`StaleSampleFilter`](crate::policy::StaleSampleFilter) policy found them
+  /// too old compared to the newest sample.
+  pub fn stale_sample_count(&self) -> u64 {
+    self.keyed_datareader.stale_sample_count()
+  }
+
   /// An async stream for reading the (bare) data samples
   pub fn async_sample_stream(self) -> DataReaderStream<D, DA> {
     DataReaderStream {
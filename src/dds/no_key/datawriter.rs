@@ -6,7 +6,7 @@ use crate::{
   dds::{
     adapters::no_key::SerializerAdapter,
     pubsub::Publisher,
-    qos::{HasQoSPolicy, QosPolicies},
+    qos::{HasQoSPolicy, MatchedContract, QosPolicies},
     result::{unwrap_no_key_write_error, WriteResult},
     statusevents::{DataWriterStatus, StatusReceiverStream},
     topic::Topic,
@@ -128,6 +128,13 @@ where
   pub fn wait_for_acknowledgments(&self, max_wait: Duration) -> WriteResult<bool, ()> {
     self.keyed_datawriter.wait_for_acknowledgments(max_wait)
   }
+
+  /// Sends the samples that are waiting to be packed into the same RTPS
+  /// message with later ones. See
+  /// [`with_key::DataWriter::flush`](crate::with_key::DataWriter::flush).
+  pub fn flush(&self) -> WriteResult<(), ()> {
+    self.keyed_datawriter.flush()
+  }
   /*
   // status queries
   /// Unimplemented. <b>Do not use</b>.
@@ -359,6 +366,12 @@ where
   pub fn get_matched_subscriptions(&self) -> Vec<SubscriptionBuiltinTopicData> {
     self.keyed_datawriter.get_matched_subscriptions()
   }
+
+  /// The QoS contract with a matched DataReader. See
+  /// [`with_key::DataWriter::matched_contract`](crate::with_key::DataWriter::matched_contract).
+  pub fn matched_contract(&self, reader: GUID) -> Option<MatchedContract> {
+    self.keyed_datawriter.matched_contract(reader)
+  }
   /*
   /// Gets mio receiver for all implemented Status changes
   ///
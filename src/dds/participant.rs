@@ -1,6 +1,6 @@
 // use mio::Token;
 use std::{
-  collections::HashMap,
+  collections::{BTreeMap, HashMap},
   io,
   io::ErrorKind,
   net::Ipv4Addr,
@@ -20,7 +20,7 @@ use futures::stream::{FusedStream, Stream};
 use log::{debug, error, info, trace, warn};
 
 use crate::{
-  create_error_out_of_resources, create_error_poisoned,
+  create_error_bad_parameter, create_error_out_of_resources, create_error_poisoned,
   dds::{
     pubsub::*,
     qos::*,
@@ -33,7 +33,8 @@ use crate::{
   },
   discovery::{
     discovery::{Discovery, DiscoveryCommand},
-    discovery_db::DiscoveryDB,
+    discovery_db::{discovery_db_read, discovery_db_write, DiscoveryDB},
+    discovery_limits::DiscoveryLimits,
     sedp_messages::DiscoveredTopicData,
   },
   network::{constant::*, udp_listener::UDPListener},
@@ -41,7 +42,7 @@ use crate::{
     constant::*,
     dp_event_loop::{DPEventLoop, DomainInfo, EventLoopCommand},
     reader::*,
-    writer::WriterIngredients,
+    writer::{MessageAggregation, WriterIngredients},
   },
   structure::{dds_cache::DDSCache, entity::RTPSEntity, guid::*, locator::Locator},
   StatusEvented,
@@ -49,15 +50,28 @@ use crate::{
 #[cfg(feature = "security")]
 use crate::{
   create_error_internal, create_error_not_allowed_by_security,
+  discovery::secure_discovery::{
+    QOS_HANDSHAKE_MAX_ATTEMPTS_PROPERTY_NAME, QOS_HANDSHAKE_RESEND_PERIOD_PROPERTY_NAME,
+    QOS_SECURE_GOODBYE_PROPERTY_NAME,
+  },
   security::{
     self,
     config::DomainParticipantSecurityConfigFiles,
-    security_plugins::{SecurityPlugins, SecurityPluginsHandle},
+    cryptographic::{CryptoStatistics, SecurityEventListener},
+    security_plugins::{
+      SecurityPlugins, SecurityPluginsHandle, QOS_ACCEPT_UNPROTECTED_SUBMESSAGES_PROPERTY_NAME,
+    },
     AccessControl, Authentication, Cryptographic,
   },
 };
 #[cfg(not(feature = "security"))]
 use crate::no_security::SecurityPluginsHandle;
+#[cfg(feature = "fastdds_statistics")]
+use crate::dds::statistics::ProtocolStatistics;
+#[cfg(feature = "replay")]
+use crate::network::mock_transport::{mock_self_locators, MockTransport};
+#[cfg(any(test, feature = "replay"))]
+use crate::structure::clock::SimulatedClock;
 
 pub struct DomainParticipantBuilder {
   domain_id: u16,
@@ -66,10 +80,24 @@ pub struct DomainParticipantBuilder {
   which interfaces the DomainParticipant will talk to. */
   only_networks: Option<Vec<String>>, // if specified, run RTPS only over these interfaces
 
+  discovery_limits: DiscoveryLimits,
+  fragment_size: u16,
+  message_aggregation: MessageAggregation,
+
   #[cfg(feature = "security")]
   security_plugins: Option<SecurityPlugins>,
   #[cfg(feature = "security")]
   sec_properties: Option<policy::Property>, // Properties for configuring security plugins
+  #[cfg(feature = "security")]
+  secure_goodbye: bool,
+  #[cfg(feature = "security")]
+  accept_unprotected_submessages: bool,
+  #[cfg(feature = "security")]
+  security_event_listener: Option<Arc<dyn SecurityEventListener>>,
+  #[cfg(feature = "security")]
+  handshake_resends: Option<(Duration, u32)>, // (resend period, max attempts)
+  #[cfg(feature = "replay")]
+  mock_transport: Option<MockTransport>,
 }
 
 impl DomainParticipantBuilder {
@@ -77,10 +105,23 @@ impl DomainParticipantBuilder {
     DomainParticipantBuilder {
       domain_id,
       only_networks: None,
+      discovery_limits: DiscoveryLimits::default(),
+      fragment_size: DEFAULT_FRAGMENT_SIZE,
+      message_aggregation: MessageAggregation::default(),
       #[cfg(feature = "security")]
       security_plugins: None,
       #[cfg(feature = "security")]
       sec_properties: None,
+      #[cfg(feature = "security")]
+      secure_goodbye: false,
+      #[cfg(feature = "security")]
+      accept_unprotected_submessages: false,
+      #[cfg(feature = "security")]
+      security_event_listener: None,
+      #[cfg(feature = "security")]
+      handshake_resends: None,
+      #[cfg(feature = "replay")]
+      mock_transport: None,
     }
   }
 
@@ -88,9 +129,9 @@ impl DomainParticipantBuilder {
   /// Low-level security configuration, which allows supplying custom plugins.
   pub fn security(
     &mut self,
-    auth: Box<impl Authentication + 'static>,
-    access: Box<impl AccessControl + 'static>,
-    crypto: Box<impl Cryptographic + 'static>,
+    auth: Box<dyn Authentication>,
+    access: Box<dyn AccessControl>,
+    crypto: Box<dyn Cryptographic>,
     sec_properties: policy::Property,
   ) -> &mut DomainParticipantBuilder {
     self.security_plugins = Some(SecurityPlugins::new(auth, access, crypto));
@@ -100,15 +141,211 @@ impl DomainParticipantBuilder {
 
   #[cfg(feature = "security")]
   /// Easier way to configure security.
-  pub fn builtin_security(mut self, configs: DomainParticipantSecurityConfigFiles) -> Self {
+  pub fn builtin_security(self, configs: DomainParticipantSecurityConfigFiles) -> Self {
+    self.builtin_security_with_crypto(configs, Box::new(security::CryptographicBuiltin::new()))
+  }
+
+  #[cfg(feature = "security")]
+  /// Like [`builtin_security`](Self::builtin_security), but with the given
+  /// cryptographic plugin instead of the builtin one, e.g. for other
+  /// transformations or a hardware-backed key store. Authentication and access
+  /// control use the builtin plugins.
+  // Crate-private, because the Cryptographic trait and the types in its
+  // methods are not public.
+  pub(crate) fn builtin_security_with_crypto(
+    mut self,
+    configs: DomainParticipantSecurityConfigFiles,
+    crypto: Box<dyn Cryptographic>,
+  ) -> Self {
     let auth = Box::new(security::AuthenticationBuiltin::new());
     let access = Box::new(security::AccessControlBuiltin::new());
-    let crypto = Box::new(security::CryptographicBuiltin::new());
     self.security(auth, access, crypto, configs.into_property_policy());
     self
   }
 
+  #[cfg(feature = "security")]
+  /// Like [`builtin_security`](Self::builtin_security), but the builtin
+  /// plugins take their random bytes (key material, key ids, handshake
+  /// challenges) from the given source instead of the operating system.
+  pub fn builtin_security_with_entropy_source(
+    mut self,
+    configs: DomainParticipantSecurityConfigFiles,
+    entropy_source: Arc<dyn security::entropy::EntropySource>,
+  ) -> Self {
+    let auth = Box::new(security::AuthenticationBuiltin::with_entropy_source(
+      entropy_source.clone(),
+    ));
+    let access = Box::new(security::AccessControlBuiltin::new());
+    let crypto = Box::new(security::CryptographicBuiltin::with_entropy_source(
+      entropy_source,
+    ));
+    self.security(auth, access, crypto, configs.into_property_policy());
+    self
+  }
+
+  #[cfg(feature = "security")]
+  /// Say an authenticated goodbye to remote participants on shutdown.
+  ///
+  /// When enabled, dropping the DomainParticipant sends a message on the
+  /// DCPSParticipantVolatileMessageSecure topic to every authenticated remote
+  /// participant. A RustDDS remote then removes this participant and its keys
+  /// immediately, instead of waiting for the lease duration to expire. The
+  /// message class id `"rustdds.sec.participant_goodbye"` is a vendor
+  /// extension, which other implementations ignore.
+  ///
+  /// Has no effect unless security is configured. Disabled by default.
+  pub fn secure_goodbye(mut self, enabled: bool) -> Self {
+    self.secure_goodbye = enabled;
+    self
+  }
+
+  #[cfg(feature = "security")]
+  /// Accept submessages that arrive without protection also for the local
+  /// DataReaders and DataWriters whose governance requires submessage
+  /// protection.
+  ///
+  /// By default such submessages (e.g. DATA, HEARTBEAT and GAP outside a
+  /// SecurePrefix and SecurePostfix) are dropped, counted in
+  /// [`CryptoStatistics::unprotected_submessage_drops`] and reported to the
+  /// [security event listener](Self::security_event_listener). Accepting them
+  /// defeats the submessage protection, so this is meant only for migrating
+  /// remote participants to a governance that protects submessages.
+  ///
+  /// Has no effect unless security is configured.
+  pub fn accept_unprotected_submessages(mut self, accept: bool) -> Self {
+    self.accept_unprotected_submessages = accept;
+    self
+  }
+
+  #[cfg(feature = "security")]
+  /// Report the data that the cryptographic plugin rejects to the listener:
+  /// messages, submessages and payloads that fail verification or are
+  /// protected with an unknown key, and rejected crypto tokens. Submessages
+  /// from authenticated participants that are dropped for lacking the
+  /// required protection are reported too.
+  ///
+  /// The listener is called on a thread of its own, so it may use the
+  /// DomainParticipant. Building the DomainParticipant fails if the
+  /// cryptographic plugin does not support listeners. Has no effect unless
+  /// security is configured.
+  pub fn security_event_listener(mut self, listener: Arc<dyn SecurityEventListener>) -> Self {
+    self.security_event_listener = Some(listener);
+    self
+  }
+
+  #[cfg(feature = "security")]
+  /// Resend an unanswered authentication handshake message every `period`,
+  /// at most `max_attempts` times. If the remote participant still does not
+  /// answer, the handshake is given up and the participant is reported lost
+  /// with [`LostReason::AuthenticationFailed`](crate::LostReason), after a
+  /// [`ParticipantAuthenticationStatus::Rejected`](crate::ParticipantAuthenticationStatus)
+  /// status event. A new handshake starts when the participant is discovered
+  /// again.
+  ///
+  /// The same can be configured with the participant properties
+  /// `dds.sec.auth.handshake_resend_period` (in milliseconds) and
+  /// `dds.sec.auth.handshake_max_attempts`. The default is every second, at
+  /// most 10 times. Has no effect unless security is configured.
+  pub fn handshake_resends(mut self, period: Duration, max_attempts: u32) -> Self {
+    self.handshake_resends = Some((period, max_attempts));
+    self
+  }
+
+  /// Bounds the Discovery data accepted from remote participants. See
+  /// [`DiscoveryLimits`] for the defaults.
+  pub fn discovery_limits(mut self, limits: DiscoveryLimits) -> Self {
+    self.discovery_limits = limits;
+    self
+  }
+
+  /// Sets the size in bytes of the fragments that DataWriters split large
+  /// samples into. Samples larger than this are sent as DATA_FRAG submessages.
+  /// Each fragment is sent in its own UDP datagram, so larger fragments mean
+  /// fewer datagrams, but the fragment and the RTPS headers must fit in one
+  /// datagram. The default is 1024. A zero size, or one larger than 64483,
+  /// is an error in [`build`](Self::build).
+  pub fn fragment_size(mut self, size: u16) -> Self {
+    self.fragment_size = size;
+    self
+  }
+
+  /// Sets the size limit in bytes of the RTPS messages that DataWriters pack
+  /// their submessages into. Samples written in quick succession to the same
+  /// Readers then share one UDP datagram, and the HEARTBEAT is sent only with
+  /// the last one. The default is 1452, which is the Ethernet MTU less the
+  /// IPv6 and UDP headers. Security protection, if any, is added on top of
+  /// the limit. Zero sends every sample in its own datagram.
+  pub fn max_message_size(mut self, size: u16) -> Self {
+    self.message_aggregation.max_message_size = usize::from(size);
+    self
+  }
+
+  /// Sets how long a DataWriter may hold back a message that has room for
+  /// more samples, see [`max_message_size`](Self::max_message_size). The
+  /// default is zero, which sends the message as soon as the DataWriter has
+  /// processed the samples written so far. Heartbeats, repairs,
+  /// [`DataWriter::flush`](crate::with_key::DataWriter::flush) and
+  /// [`DataWriter::wait_for_acknowledgments`](crate::with_key::DataWriter::wait_for_acknowledgments)
+  /// send the message without waiting.
+  pub fn message_flush_delay(mut self, delay: Duration) -> Self {
+    self.message_aggregation.flush_delay = delay;
+    self
+  }
+
+  /// Replaces the UDP sockets of the participant with an in-process transport,
+  /// see [`replay`](crate::replay).
+  #[cfg(feature = "replay")]
+  pub(crate) fn mock_transport(mut self, transport: MockTransport) -> Self {
+    self.mock_transport = Some(transport);
+    self
+  }
+
   pub fn build(#[allow(unused_mut)] mut self) -> CreateResult<DomainParticipant> {
+    if self.fragment_size == 0 {
+      return create_error_bad_parameter!("Fragment size must not be zero");
+    }
+    if self.fragment_size > MAX_FRAGMENT_SIZE {
+      return create_error_bad_parameter!(
+        "Fragment size {} does not fit in a UDP datagram with the headers. The maximum is {}",
+        self.fragment_size,
+        MAX_FRAGMENT_SIZE
+      );
+    }
+    #[cfg(feature = "security")]
+    if let (true, Some(properties)) = (self.secure_goodbye, self.sec_properties.as_mut()) {
+      properties.value.push(security::types::Property {
+        name: QOS_SECURE_GOODBYE_PROPERTY_NAME.to_string(),
+        value: "true".to_string(),
+        propagate: false,
+      });
+    }
+    #[cfg(feature = "security")]
+    if let (Some((period, max_attempts)), Some(properties)) =
+      (self.handshake_resends, self.sec_properties.as_mut())
+    {
+      properties.value.push(security::types::Property {
+        name: QOS_HANDSHAKE_RESEND_PERIOD_PROPERTY_NAME.to_string(),
+        value: period.as_millis().to_string(),
+        propagate: false,
+      });
+      properties.value.push(security::types::Property {
+        name: QOS_HANDSHAKE_MAX_ATTEMPTS_PROPERTY_NAME.to_string(),
+        value: max_attempts.to_string(),
+        propagate: false,
+      });
+    }
+    #[cfg(feature = "security")]
+    if let (true, Some(properties)) = (
+      self.accept_unprotected_submessages,
+      self.sec_properties.as_mut(),
+    ) {
+      properties.value.push(security::types::Property {
+        name: QOS_ACCEPT_UNPROTECTED_SUBMESSAGES_PROPERTY_NAME.to_string(),
+        value: "true".to_string(),
+        propagate: false,
+      });
+    }
+
     // QosPolicies with possible security properties, otherwise default
     let participant_qos = QosPolicies {
       #[cfg(feature = "security")]
@@ -220,13 +457,24 @@ impl DomainParticipantBuilder {
     let (discovery_command_sender, discovery_command_receiver) =
       mio_channel::sync_channel::<DiscoveryCommand>(64);
 
-    // Channel used to report noteworthy events to DomainParticipant
-    let (status_sender, status_receiver) = sync_status_channel(16)?;
+    // Channel used to report noteworthy events to DomainParticipant. Discovering
+    // many participants at once produces bursts of events, and the events are
+    // dropped if the channel is full.
+    let (status_sender, status_receiver) = sync_status_channel(256)?;
 
     #[cfg(not(feature = "security"))]
     let security_plugins_handle = None;
     #[cfg(feature = "security")]
     let security_plugins_handle = self.security_plugins.map(SecurityPluginsHandle::new);
+    #[cfg(feature = "security")]
+    if let (Some(handle), Some(listener)) = (
+      security_plugins_handle.as_ref(),
+      self.security_event_listener,
+    ) {
+      if let Err(e) = handle.dispatch_security_events(listener) {
+        return create_error_internal!("Could not set the security event listener: {}", e.msg);
+      }
+    }
 
     // intermediate DP wrapper
     let dp = DomainParticipantDisc::new(
@@ -240,21 +488,41 @@ impl DomainParticipantBuilder {
       status_sender.clone(),
       status_receiver,
       security_plugins_handle.clone(),
+      #[cfg(feature = "replay")]
+      self.mock_transport,
     )?;
 
     // outer DP wrapper
     let dp = DomainParticipant {
+      identity: Arc::new(ParticipantIdentity {
+        guid: dp.guid(),
+        domain_id: dp.domain_id(),
+        participant_id: dp.participant_id(),
+        entity_id_generator: atomic::AtomicU32::new(0),
+      }),
       dpi: Arc::new(Mutex::new(dp)),
     };
 
     let (discovery_started_sender, discovery_started_receiver) = std::sync::mpsc::channel();
 
+    discovery_db_write(&dp.discovery_db()).set_limits(self.discovery_limits);
+    // Set before Discovery starts, so that also the built-in writers use them
+    {
+      let mut dp_disc = dp.dpi.lock()?;
+      dp_disc.dpi.fragment_size = self.fragment_size;
+      dp_disc.dpi.message_aggregation = self.message_aggregation;
+    }
+
     // Construct and start background thread
     let dp_clone = dp.weak_clone();
     let disc_db_clone = dp.discovery_db();
+    #[cfg(any(test, feature = "replay"))]
+    let simulated_clock = SimulatedClock::current();
     let discovery_handle = thread::Builder::new()
       .name("RustDDS discovery thread".to_string())
       .spawn(move || {
+        #[cfg(any(test, feature = "replay"))]
+        let _clock = simulated_clock.map(|clock| clock.enter());
         if let Ok(mut discovery) = Discovery::new(
           dp_clone,
           disc_db_clone,
@@ -300,10 +568,44 @@ impl DomainParticipantBuilder {
 /// Domains are identified by a domain identifier, which is, in Rust terms, a
 /// `u16`. Domain identifier values are application-specific, but `0` is usually
 /// the default.
+///
+/// # Sharing between threads
+///
+/// `DomainParticipant` is a handle: it is `Send + Sync`, and cloning it is
+/// cheap. All clones refer to the same participant, which is shut down when the
+/// last clone, and the last entity created from it, is dropped.
+///
+/// The methods may be called concurrently from several threads:
+/// * [`guid`](RTPSEntity::guid), [`domain_id`](Self::domain_id) and
+///   [`participant_id`](Self::participant_id) never block.
+/// * [`create_publisher`](Self::create_publisher),
+///   [`create_subscriber`](Self::create_subscriber),
+///   [`create_topic`](Self::create_topic),
+///   [`discovered_topics`](Self::discovered_topics) and
+///   [`assert_liveliness`](Self::assert_liveliness) hold an internal
+///   participant lock for a short time, so concurrent calls are serialized.
+/// * [`find_topic`](Self::find_topic) holds the participant lock while it
+///   waits, i.e. up to the given timeout. Other calls needing the lock wait for
+///   it.
+///
+/// DataReaders and DataWriters are created through a [`Subscriber`] or
+/// [`Publisher`], which serialize creation only within themselves. Entities of
+/// different Publishers and Subscribers can be created in parallel.
 #[derive(Clone)]
 // This is a smart pointer for DomainParticipant for easier manipulation.
 pub struct DomainParticipant {
   dpi: Arc<Mutex<DomainParticipantDisc>>,
+  identity: Arc<ParticipantIdentity>,
+}
+
+// Participant properties that do not change after construction. These are
+// shared by all handles and can be accessed without locking the participant.
+struct ParticipantIdentity {
+  guid: GUID,
+  domain_id: u16,
+  participant_id: u16,
+  // This allows deterministic generation of EntityIds for DataReader, DataWriter, etc.
+  entity_id_generator: atomic::AtomicU32,
 }
 
 impl DomainParticipant {
@@ -408,7 +710,7 @@ impl DomainParticipant {
   /// let domain_id = domain_participant.domain_id();
   /// ```
   pub fn domain_id(&self) -> u16 {
-    self.dpi.lock().unwrap().domain_id()
+    self.identity.domain_id
   }
 
   /// # Examples
@@ -420,7 +722,7 @@ impl DomainParticipant {
   /// let participant_id = domain_participant.participant_id();
   /// ```
   pub fn participant_id(&self) -> u16 {
-    self.dpi.lock().unwrap().participant_id()
+    self.identity.participant_id
   }
 
   /// Gets all DiscoveredTopics from DDS network
@@ -456,6 +758,20 @@ impl DomainParticipant {
     self.dpi.lock()?.assert_liveliness()
   }
 
+  /// How many times each remote participant has sent Discovery data exceeding
+  /// the [`DiscoveryLimits`]
+  pub fn discovery_limit_violations(&self) -> BTreeMap<GuidPrefix, u64> {
+    discovery_db_read(&self.discovery_db()).limit_violations()
+  }
+
+  /// How many submessages the cryptographic plugin has encoded and decoded,
+  /// and how many messages it has rejected. `None` if security is not enabled
+  /// or the plugin does not keep the counts.
+  #[cfg(feature = "security")]
+  pub fn crypto_statistics(&self) -> Option<CryptoStatistics> {
+    self.dpi.lock().ok()?.dpi.crypto_statistics()
+  }
+
   /// Get a `DomainDomainParticipantStatusListener` that can be used
   /// to get `DomainParticipantStatusEvent`s for this DomainParticipant.
   pub fn status_listener(&self) -> DomainParticipantStatusListener {
@@ -468,6 +784,11 @@ impl DomainParticipant {
     DomainParticipantWeak::new(self)
   }
 
+  #[cfg(feature = "fastdds_statistics")]
+  pub(crate) fn protocol_statistics(&self) -> CreateResult<Arc<ProtocolStatistics>> {
+    Ok(Arc::clone(&self.dpi.lock()?.dpi.domain_info.statistics))
+  }
+
   pub(crate) fn dds_cache(&self) -> Arc<RwLock<DDSCache>> {
     self.dpi.lock().unwrap().dds_cache()
   }
@@ -481,8 +802,15 @@ impl DomainParticipant {
     self.dpi.lock().unwrap().dpi.discovery_db.clone()
   }
 
+  // This generates identifiers that consist of given EntityKind and arbitrary,
+  // unique identifier.
   pub(crate) fn new_entity_id(&self, entity_kind: EntityKind) -> EntityId {
-    self.dpi.lock().unwrap().new_entity_id(entity_kind)
+    let [_goldilocks, papa_byte, mama_byte, baby_byte] = self
+      .identity
+      .entity_id_generator
+      .fetch_add(1, atomic::Ordering::Relaxed)
+      .to_be_bytes();
+    EntityId::new([papa_byte, mama_byte, baby_byte], entity_kind)
   }
 
   pub(crate) fn self_locators(&self) -> HashMap<mio_06::Token, Vec<Locator>> {
@@ -658,9 +986,7 @@ impl PartialEq for DomainParticipant {
 pub struct DomainParticipantWeak {
   dpi: Weak<Mutex<DomainParticipantDisc>>,
   // This struct caches some items to avoid construction deadlocks
-  #[cfg(feature = "security")] // just to avoid warning
-  domain_id: u16,
-  guid: GUID,
+  identity: Arc<ParticipantIdentity>,
   #[cfg(feature = "security")] // just to avoid warning
   qos: QosPolicies,
 }
@@ -669,9 +995,7 @@ impl DomainParticipantWeak {
   pub fn new(dp: &DomainParticipant) -> Self {
     Self {
       dpi: Arc::downgrade(&dp.dpi),
-      #[cfg(feature="security")] // just to avoid warning
-      domain_id: dp.domain_id(),
-      guid: dp.guid(),
+      identity: Arc::clone(&dp.identity),
       #[cfg(feature="security")] // just to avoid warning
       qos: dp.qos(),
     }
@@ -699,7 +1023,7 @@ impl DomainParticipantWeak {
 
   #[cfg(feature = "security")] // just to avoid warning
   pub fn domain_id(&self) -> u16 {
-    self.domain_id
+    self.identity.domain_id
   }
 
   #[cfg(feature = "security")] // just to avoid warning
@@ -728,13 +1052,17 @@ impl DomainParticipantWeak {
   }
 
   pub fn upgrade(self) -> Option<DomainParticipant> {
-    self.dpi.upgrade().map(|d| DomainParticipant { dpi: d })
+    let identity = self.identity;
+    self
+      .dpi
+      .upgrade()
+      .map(|dpi| DomainParticipant { dpi, identity })
   }
 } // end impl
 
 impl RTPSEntity for DomainParticipantWeak {
   fn guid(&self) -> GUID {
-    self.guid
+    self.identity.guid
   }
 }
 
@@ -745,8 +1073,6 @@ pub(crate) struct DomainParticipantDisc {
   // Discovery control
   discovery_command_sender: mio_channel::SyncSender<DiscoveryCommand>,
   discovery_join_handle: mio_channel::Receiver<JoinHandle<()>>,
-  // This allows deterministic generation of EntityIds for DataReader, DataWriter, etc.
-  entity_id_generator: atomic::AtomicU32,
 }
 
 impl DomainParticipantDisc {
@@ -762,6 +1088,7 @@ impl DomainParticipantDisc {
     status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
     status_receiver: StatusChannelReceiver<DomainParticipantStatusEvent>,
     security_plugins_handle: Option<SecurityPluginsHandle>,
+    #[cfg(feature = "replay")] mock_transport: Option<MockTransport>,
   ) -> CreateResult<Self> {
     let dpi = DomainParticipantInner::new(
       domain_id,
@@ -773,26 +1100,17 @@ impl DomainParticipantDisc {
       status_sender,
       status_receiver,
       security_plugins_handle,
+      #[cfg(feature = "replay")]
+      mock_transport,
     )?;
 
     Ok(Self {
       dpi,
       discovery_command_sender,
       discovery_join_handle,
-      entity_id_generator: atomic::AtomicU32::new(0),
     })
   }
 
-  // This generates identifiers that consist of given EntityKind and arbitrary,
-  // unique identifier.
-  pub(crate) fn new_entity_id(&self, entity_kind: EntityKind) -> EntityId {
-    let [_goldilocks, papa_byte, mama_byte, baby_byte] = self
-      .entity_id_generator
-      .fetch_add(1, atomic::Ordering::Relaxed)
-      .to_be_bytes();
-    EntityId::new([papa_byte, mama_byte, baby_byte], entity_kind)
-  }
-
   pub fn create_publisher(
     &self,
     dp: &DomainParticipantWeak,
@@ -949,6 +1267,11 @@ pub(crate) struct DomainParticipantInner {
   self_locators: HashMap<mio_06::Token, Vec<Locator>>,
 
   security_plugins_handle: Option<SecurityPluginsHandle>,
+
+  // Fragment size and message aggregation of the DataWriters created from now
+  // on
+  fragment_size: u16,
+  message_aggregation: MessageAggregation,
 }
 
 impl Drop for DomainParticipantInner {
@@ -987,94 +1310,19 @@ impl DomainParticipantInner {
     status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
     status_receiver: StatusChannelReceiver<DomainParticipantStatusEvent>,
     security_plugins_handle: Option<SecurityPluginsHandle>,
+    #[cfg(feature = "replay")] mock_transport: Option<MockTransport>,
   ) -> CreateResult<Self> {
     #[cfg(not(feature = "security"))]
     let _dummy = _qos_policies; // to make clippy happy
 
-    let mut listeners = HashMap::new();
-
-    match UDPListener::new_multicast(
-      "0.0.0.0",
-      spdp_well_known_multicast_port(domain_id),
-      Ipv4Addr::new(239, 255, 0, 1),
-    ) {
-      Ok(l) => {
-        listeners.insert(DISCOVERY_MUL_LISTENER_TOKEN, l);
-      }
-      Err(e) => warn!("Cannot get multicast discovery listener: {e:?}"),
-    }
-
-    let mut participant_id = 0;
-
-    let mut discovery_listener = None;
-
-    // Magic value 120 below is from RTPS spec 2.5 Section "9.6.2.3 Default Port
-    // Numbers"
-    while discovery_listener.is_none() && participant_id < 120 {
-      discovery_listener = UDPListener::new_unicast(
-        "0.0.0.0",
-        spdp_well_known_unicast_port(domain_id, participant_id),
-      )
-      .ok();
-      if discovery_listener.is_none() {
-        participant_id += 1;
-      }
-    }
-
-    info!("ParticipantId {} selected.", participant_id);
-
-    // here discovery_listener is redefined (shadowed)
-    let discovery_listener = match discovery_listener {
-      Some(dl) => dl,
-      None => return create_error_out_of_resources!("Could not find free ParticipantId"),
+    #[cfg(feature = "replay")]
+    let (participant_id, listeners, self_locators) = if mock_transport.is_some() {
+      (0, HashMap::new(), mock_self_locators(domain_id))
+    } else {
+      Self::open_udp_listeners(domain_id)?
     };
-    listeners.insert(DISCOVERY_LISTENER_TOKEN, discovery_listener);
-
-    // Now the user traffic listeners
-
-    match UDPListener::new_multicast(
-      "0.0.0.0",
-      user_traffic_multicast_port(domain_id),
-      Ipv4Addr::new(239, 255, 0, 1),
-    ) {
-      Ok(l) => {
-        listeners.insert(USER_TRAFFIC_MUL_LISTENER_TOKEN, l);
-      }
-      Err(e) => warn!("Cannot get multicast user traffic listener: {e:?}"),
-    }
-
-    let user_traffic_listener = UDPListener::new_unicast(
-      "0.0.0.0",
-      user_traffic_unicast_port(domain_id, participant_id),
-    )
-    .or_else(|e| {
-      if matches!(e.kind(), ErrorKind::AddrInUse) {
-        // If we do not get the preferred listening port,
-        // try again, with "any" port number.
-        UDPListener::new_unicast("0.0.0.0", 0).or_else(|e| {
-          create_error_out_of_resources!(
-            "Could not open unicast user traffic listener, any port number: {:?}",
-            e
-          )
-        })
-      } else {
-        create_error_out_of_resources!("Could not open unicast user traffic listener: {e:?}")
-      }
-    })?;
-
-    listeners.insert(USER_TRAFFIC_LISTENER_TOKEN, user_traffic_listener);
-
-    // construct our own Locators
-    let self_locators: HashMap<mio_06::Token, Vec<Locator>> = listeners
-      .iter()
-      .map(|(t, l)| match l.to_locator_address() {
-        Ok(locs) => (*t, locs),
-        Err(e) => {
-          error!("No local network address for token {:?}: {:?}", t, e);
-          (*t, vec![])
-        }
-      })
-      .collect();
+    #[cfg(not(feature = "replay"))]
+    let (participant_id, listeners, self_locators) = Self::open_udp_listeners(domain_id)?;
 
     // Adding readers
     let (sender_add_reader, receiver_add_reader) =
@@ -1090,6 +1338,8 @@ impl DomainParticipantInner {
       domain_participant_guid: participant_guid,
       domain_id,
       participant_id,
+      #[cfg(feature = "fastdds_statistics")]
+      statistics: Arc::new(ProtocolStatistics::new()),
     };
     let domain_info_clone = domain_info.clone();
 
@@ -1111,9 +1361,13 @@ impl DomainParticipantInner {
     // Launch the background thread for DomainParticipant
     let disc_db_clone = discovery_db.clone();
     let security_plugins_clone = security_plugins_handle.clone();
+    #[cfg(any(test, feature = "replay"))]
+    let simulated_clock = SimulatedClock::current();
     let ev_loop_handle = thread::Builder::new()
       .name(format!("RustDDS Participant {} event loop", participant_id))
       .spawn(move || {
+        #[cfg(any(test, feature = "replay"))]
+        let _clock = simulated_clock.map(|clock| clock.enter());
         let dp_event_loop = DPEventLoop::new(
           domain_info_clone,
           dds_cache_clone,
@@ -1142,6 +1396,8 @@ impl DomainParticipantInner {
           spdp_liveness_sender,
           status_sender,
           security_plugins_clone,
+          #[cfg(feature = "replay")]
+          mock_transport,
         );
         dp_event_loop.event_loop();
       })?;
@@ -1173,13 +1429,121 @@ impl DomainParticipantInner {
       status_receiver,
       self_locators,
       security_plugins_handle,
+      fragment_size: DEFAULT_FRAGMENT_SIZE,
+      message_aggregation: MessageAggregation::default(),
     })
   }
 
+  // Opens the UDP sockets of the participant. Returns the selected
+  // ParticipantId, the listeners, and the locators at which they are reached.
+  #[allow(clippy::type_complexity)]
+  fn open_udp_listeners(
+    domain_id: u16,
+  ) -> CreateResult<(
+    u16,
+    HashMap<mio_06::Token, UDPListener>,
+    HashMap<mio_06::Token, Vec<Locator>>,
+  )> {
+    let mut listeners = HashMap::new();
+
+    match UDPListener::new_multicast(
+      "0.0.0.0",
+      spdp_well_known_multicast_port(domain_id),
+      Ipv4Addr::new(239, 255, 0, 1),
+    ) {
+      Ok(l) => {
+        listeners.insert(DISCOVERY_MUL_LISTENER_TOKEN, l);
+      }
+      Err(e) => warn!("Cannot get multicast discovery listener: {e:?}"),
+    }
+
+    let mut participant_id = 0;
+
+    let mut discovery_listener = None;
+
+    // Magic value 120 below is from RTPS spec 2.5 Section "9.6.2.3 Default Port
+    // Numbers"
+    while discovery_listener.is_none() && participant_id < 120 {
+      discovery_listener = UDPListener::new_unicast(
+        "0.0.0.0",
+        spdp_well_known_unicast_port(domain_id, participant_id),
+      )
+      .ok();
+      if discovery_listener.is_none() {
+        participant_id += 1;
+      }
+    }
+
+    info!("ParticipantId {} selected.", participant_id);
+
+    // here discovery_listener is redefined (shadowed)
+    let discovery_listener = match discovery_listener {
+      Some(dl) => dl,
+      None => return create_error_out_of_resources!("Could not find free ParticipantId"),
+    };
+    listeners.insert(DISCOVERY_LISTENER_TOKEN, discovery_listener);
+
+    // Now the user traffic listeners
+
+    match UDPListener::new_multicast(
+      "0.0.0.0",
+      user_traffic_multicast_port(domain_id),
+      Ipv4Addr::new(239, 255, 0, 1),
+    ) {
+      Ok(l) => {
+        listeners.insert(USER_TRAFFIC_MUL_LISTENER_TOKEN, l);
+      }
+      Err(e) => warn!("Cannot get multicast user traffic listener: {e:?}"),
+    }
+
+    let user_traffic_listener = UDPListener::new_unicast(
+      "0.0.0.0",
+      user_traffic_unicast_port(domain_id, participant_id),
+    )
+    .or_else(|e| {
+      if matches!(e.kind(), ErrorKind::AddrInUse) {
+        // If we do not get the preferred listening port,
+        // try again, with "any" port number.
+        UDPListener::new_unicast("0.0.0.0", 0).or_else(|e| {
+          create_error_out_of_resources!(
+            "Could not open unicast user traffic listener, any port number: {:?}",
+            e
+          )
+        })
+      } else {
+        create_error_out_of_resources!("Could not open unicast user traffic listener: {e:?}")
+      }
+    })?;
+
+    listeners.insert(USER_TRAFFIC_LISTENER_TOKEN, user_traffic_listener);
+
+    // construct our own Locators
+    let self_locators: HashMap<mio_06::Token, Vec<Locator>> = listeners
+      .iter()
+      .map(|(t, l)| match l.to_locator_address() {
+        Ok(locs) => (*t, locs),
+        Err(e) => {
+          error!("No local network address for token {:?}: {:?}", t, e);
+          (*t, vec![])
+        }
+      })
+      .collect();
+
+    Ok((participant_id, listeners, self_locators))
+  }
+
   pub fn dds_cache(&self) -> Arc<RwLock<DDSCache>> {
     self.dds_cache.clone()
   }
 
+  #[cfg(feature = "security")]
+  pub(crate) fn crypto_statistics(&self) -> Option<CryptoStatistics> {
+    self
+      .security_plugins_handle
+      .as_ref()
+      .and_then(|handle| handle.read_plugins().crypto_statistics())
+  }
+
   #[cfg(feature = "security")] // just to avoid warning
   pub(crate) fn qos(&self) -> QosPolicies {
     self.my_qos_policies.clone()
@@ -1205,6 +1569,8 @@ impl DomainParticipantInner {
       self.remove_writer_sender.clone(),
       discovery_command,
       self.security_plugins_handle.clone(),
+      self.fragment_size,
+      self.message_aggregation,
     ))
   }
 
@@ -1413,7 +1779,7 @@ impl DomainParticipantInner {
 
 impl RTPSEntity for DomainParticipant {
   fn guid(&self) -> GUID {
-    self.dpi.lock().unwrap().guid()
+    self.identity.guid
   }
 }
 
@@ -1450,13 +1816,13 @@ mod tests {
   use byteorder::LittleEndian;
 
   use crate::{
-    dds::{qos::QosPolicies, topic::TopicKind},
+    dds::{qos::QosPolicies, result::CreateError, topic::TopicKind},
     messages::{
       header::Header, protocol_id::ProtocolId, protocol_version::ProtocolVersion,
       submessages::submessages::*, vendor_id::VendorId,
     },
     network::{constant::user_traffic_unicast_port, udp_sender::UDPSender},
-    rtps::{submessage::*, Message},
+    rtps::{constant::MAX_FRAGMENT_SIZE, submessage::*, Message},
     serialization::CDRSerializerAdapter,
     structure::{
       guid::{EntityId, GUID},
@@ -1465,7 +1831,7 @@ mod tests {
     },
     test::random_data::RandomData,
   };
-  use super::DomainParticipant;
+  use super::{DomainParticipant, DomainParticipantBuilder};
 
   // TODO: improve basic test when more or the structure is known
   #[test]
@@ -1480,6 +1846,17 @@ mod tests {
 
     // TODO: get result data from Reader
   }
+  #[test]
+  fn fragment_size_must_fit_in_a_datagram() {
+    for size in [0, MAX_FRAGMENT_SIZE + 1, u16::MAX] {
+      let result = DomainParticipantBuilder::new(0).fragment_size(size).build();
+      assert!(
+        matches!(result, Err(CreateError::BadParameter { .. })),
+        "{size}: {result:?}"
+      );
+    }
+  }
+
   #[test]
   fn dp_writer_heartbeat_test() {
     let domain_participant = DomainParticipant::new(0).expect("Participant creation failed!");
@@ -35,7 +35,7 @@ use crate::{
   mio_source,
   rtps::{
     reader::ReaderIngredients,
-    writer::{WriterCommand, WriterIngredients},
+    writer::{MessageAggregation, WriterCommand, WriterIngredients},
   },
   serialization::{CDRDeserializerAdapter, CDRSerializerAdapter},
   structure::{
@@ -89,6 +89,9 @@ use crate::no_security::{security_plugins::SecurityPluginsHandle, EndpointSecuri
 #[derive(Clone)]
 pub struct Publisher {
   inner: Arc<Mutex<InnerPublisher>>,
+  // Kept outside the lock, because a DataWriter may be dropped while the lock is
+  // held, e.g. when create_datawriter fails.
+  remove_writer_sender: mio_channel::SyncSender<GUID>,
 }
 
 impl Publisher {
@@ -102,6 +105,8 @@ impl Publisher {
     remove_writer_sender: mio_channel::SyncSender<GUID>,
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
     security_plugins_handle: Option<SecurityPluginsHandle>,
+    fragment_size: u16,
+    message_aggregation: MessageAggregation,
   ) -> Self {
     Self {
       inner: Arc::new(Mutex::new(InnerPublisher::new(
@@ -110,10 +115,12 @@ impl Publisher {
         qos,
         default_dw_qos,
         add_writer_sender,
-        remove_writer_sender,
         discovery_command,
         security_plugins_handle,
+        fragment_size,
+        message_aggregation,
       ))),
+      remove_writer_sender,
     }
   }
 
@@ -377,9 +384,14 @@ impl Publisher {
     self.inner_lock().set_default_datawriter_qos(q);
   }
 
+  pub(crate) fn discovery_db(&self) -> Arc<RwLock<DiscoveryDB>> {
+    self.inner_lock().discovery_db.clone()
+  }
+
   // This is used on DataWriter .drop()
   pub(crate) fn remove_writer(&self, guid: GUID) {
-    self.inner_lock().remove_writer(guid);
+    try_send_timeout(&self.remove_writer_sender, guid, None)
+      .unwrap_or_else(|e| error!("Cannot remove Writer {:?} : {:?}", guid, e));
   }
 } // impl
 
@@ -407,9 +419,10 @@ struct InnerPublisher {
   my_qos_policies: QosPolicies,
   default_datawriter_qos: QosPolicies, // used when creating a new DataWriter
   add_writer_sender: mio_channel::SyncSender<WriterIngredients>,
-  remove_writer_sender: mio_channel::SyncSender<GUID>,
   discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
   security_plugins_handle: Option<SecurityPluginsHandle>,
+  fragment_size: u16,
+  message_aggregation: MessageAggregation,
 }
 
 // public interface for Publisher
@@ -421,9 +434,10 @@ impl InnerPublisher {
     qos: QosPolicies,
     default_dw_qos: QosPolicies,
     add_writer_sender: mio_channel::SyncSender<WriterIngredients>,
-    remove_writer_sender: mio_channel::SyncSender<GUID>,
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
     security_plugins_handle: Option<SecurityPluginsHandle>,
+    fragment_size: u16,
+    message_aggregation: MessageAggregation,
   ) -> Self {
     // We generate an arbitrary but unique id to distinguish Publishers from each
     // other. EntityKind is just some value, since we do not show it to anyone.
@@ -437,9 +451,10 @@ impl InnerPublisher {
       my_qos_policies: qos,
       default_datawriter_qos: default_dw_qos,
       add_writer_sender,
-      remove_writer_sender,
       discovery_command,
       security_plugins_handle,
+      fragment_size,
+      message_aggregation,
     }
   }
 
@@ -516,9 +531,12 @@ impl InnerPublisher {
           .get_plugins()
           .get_writer_sec_attributes(guid, topic.name()); // Release lock
         writer_security_attributes.and_then(|attributes| {
-          sec_handle
-            .get_plugins()
-            .register_local_writer(guid, writer_qos.property(), attributes)
+          sec_handle.get_plugins().register_local_writer(
+            guid,
+            topic.name(),
+            writer_qos.property(),
+            attributes,
+          )
         })
       } {
         return create_error_internal!(
@@ -531,6 +549,17 @@ impl InnerPublisher {
       }
     }
 
+    // Discovery must not wait. Best-effort built-in writers have no heartbeats
+    // that would flush their messages.
+    let message_aggregation = if entity_id.kind().is_built_in() {
+      MessageAggregation {
+        flush_delay: std::time::Duration::ZERO,
+        ..self.message_aggregation
+      }
+    } else {
+      self.message_aggregation
+    };
+
     let new_writer = WriterIngredients {
       guid,
       writer_command_receiver: hccc_download,
@@ -540,6 +569,8 @@ impl InnerPublisher {
       qos_policies: writer_qos.clone(),
       status_sender,
       security_plugins: self.security_plugins_handle.clone(),
+      fragment_size: self.fragment_size,
+      message_aggregation,
     };
 
     // Send writer ingredients to DP event loop, where the actual writer will be
@@ -612,11 +643,15 @@ impl InnerPublisher {
       );
     }
 
-    // Inform Discovery about the new writer
+    // Discovery needs the DB to process our command
+    drop(db);
+
+    // Inform Discovery about the new writer. Discovery may be busy, e.g. when
+    // many endpoints are created at once, so wait for room in the channel.
     let writer_guid = self.domain_participant.guid().from_prefix(entity_id);
     self
       .discovery_command
-      .try_send(DiscoveryCommand::AddLocalWriter { guid: writer_guid })
+      .send(DiscoveryCommand::AddLocalWriter { guid: writer_guid })
       .or_else(|e| {
         create_error_internal!(
           "Cannot inform Discovery about the new writer {writer_guid:?}. Error: {}",
@@ -673,11 +708,6 @@ impl InnerPublisher {
     entity_id_opt.unwrap_or_else(|| self.participant().unwrap().new_entity_id(entity_kind))
   }
 
-  pub(crate) fn remove_writer(&self, guid: GUID) {
-    try_send_timeout(&self.remove_writer_sender, guid, None)
-      .unwrap_or_else(|e| error!("Cannot remove Writer {:?} : {:?}", guid, e));
-  }
-
   pub(crate) fn identity(&self) -> EntityId {
     self.id
   }
@@ -949,6 +979,10 @@ impl Subscriber {
   pub(crate) fn remove_reader(&self, guid: GUID) {
     self.inner.remove_reader(guid);
   }
+
+  pub(crate) fn discovery_db(&self) -> Arc<RwLock<DiscoveryDB>> {
+    self.inner.discovery_db.clone()
+  }
 }
 
 #[derive(Clone)]
@@ -1200,11 +1234,12 @@ impl InnerSubscriber {
       .try_send(new_reader)
       .or_else(|e| create_error_poisoned!("Cannot add DataReader. Error: {}", e))?;
 
-    // Inform Discovery about the new reader
+    // Inform Discovery about the new reader. Discovery may be busy, e.g. when
+    // many endpoints are created at once, so wait for room in the channel.
     let reader_guid = self.domain_participant.guid().from_prefix(entity_id);
     self
       .discovery_command
-      .try_send(DiscoveryCommand::AddLocalReader { guid: reader_guid })
+      .send(DiscoveryCommand::AddLocalReader { guid: reader_guid })
       .or_else(|e| {
         create_error_internal!(
           "Cannot inform Discovery about the new reader {reader_guid:?}. Error: {}",
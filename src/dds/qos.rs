@@ -44,11 +44,11 @@ pub enum QosPolicyId {
   // OwnershipStrength, // 7
   Liveliness,
   TimeBasedFilter, // 9
-  // Partition,
+  Partition,
 
-  // Note: If "Partition" is ever implemented, observe also DDS Security spec v1.1
-  // Section "7.3.5 Immutability of Publisher Partition Qos in combination with non-volatile
-  // Durability kind" when implementing.
+  // Note: Changing "Partition" after creation is not supported. If it ever is,
+  // observe also DDS Security spec v1.1 Section "7.3.5 Immutability of Publisher
+  // Partition Qos in combination with non-volatile Durability kind".
   Reliability, // 11
   DestinationOrder,
   History, // 13
@@ -64,6 +64,39 @@ pub enum QosPolicyId {
   Property, // No Id in the security spec (But this is from older DDS/RTPs spec.)
 }
 
+/// The values of a QoS policy in a [`MatchedContract`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolicyContract<P> {
+  /// Value of the local endpoint
+  pub local: Option<P>,
+  /// Value of the matched remote endpoint, as told by Discovery
+  pub remote: Option<P>,
+  /// Value the local endpoint acts on with this match
+  pub effective: Option<P>,
+}
+
+/// The QoS contract between a local endpoint and a matched remote endpoint.
+///
+/// For the request-offered policies, the effective value is the one requested
+/// by the DataReader. The offered value of a matched DataWriter is at least as
+/// strong. The remaining policies are not matched, and each endpoint acts on
+/// its own value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchedContract {
+  pub durability: PolicyContract<policy::Durability>,
+  pub presentation: PolicyContract<policy::Presentation>,
+  pub deadline: PolicyContract<policy::Deadline>,
+  pub latency_budget: PolicyContract<policy::LatencyBudget>,
+  pub ownership: PolicyContract<policy::Ownership>,
+  pub liveliness: PolicyContract<policy::Liveliness>,
+  pub reliability: PolicyContract<policy::Reliability>,
+  pub destination_order: PolicyContract<policy::DestinationOrder>,
+  pub time_based_filter: PolicyContract<policy::TimeBasedFilter>,
+  pub history: PolicyContract<policy::History>,
+  pub resource_limits: PolicyContract<policy::ResourceLimits>,
+  pub lifespan: PolicyContract<policy::Lifespan>,
+}
+
 /// Utility for building [QosPolicies]
 #[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct QosPolicyBuilder {
@@ -79,6 +112,11 @@ pub struct QosPolicyBuilder {
   history: Option<policy::History>,
   resource_limits: Option<policy::ResourceLimits>,
   lifespan: Option<policy::Lifespan>,
+  partition: Option<policy::Partition>,
+  stale_sample_filter: Option<policy::StaleSampleFilter>,
+  clock_skew_tolerance: Option<policy::ClockSkewTolerance>,
+  #[cfg(feature = "history_spill")]
+  history_spill: Option<policy::HistorySpill>,
   #[cfg(feature = "security")]
   property: Option<policy::Property>,
 }
@@ -172,6 +210,37 @@ impl QosPolicyBuilder {
     self
   }
 
+  #[must_use]
+  pub const fn stale_sample_filter(
+    mut self,
+    stale_sample_filter: policy::StaleSampleFilter,
+  ) -> Self {
+    self.stale_sample_filter = Some(stale_sample_filter);
+    self
+  }
+
+  #[must_use]
+  pub const fn clock_skew_tolerance(
+    mut self,
+    clock_skew_tolerance: policy::ClockSkewTolerance,
+  ) -> Self {
+    self.clock_skew_tolerance = Some(clock_skew_tolerance);
+    self
+  }
+
+  #[must_use]
+  pub fn partition(mut self, partition: policy::Partition) -> Self {
+    self.partition = Some(partition);
+    self
+  }
+
+  #[cfg(feature = "history_spill")]
+  #[must_use]
+  pub fn history_spill(mut self, history_spill: policy::HistorySpill) -> Self {
+    self.history_spill = Some(history_spill);
+    self
+  }
+
   #[cfg(feature = "security")]
   #[must_use]
   pub fn property(mut self, property: policy::Property) -> Self {
@@ -193,6 +262,11 @@ impl QosPolicyBuilder {
       history: self.history,
       resource_limits: self.resource_limits,
       lifespan: self.lifespan,
+      partition: self.partition,
+      stale_sample_filter: self.stale_sample_filter,
+      clock_skew_tolerance: self.clock_skew_tolerance,
+      #[cfg(feature = "history_spill")]
+      history_spill: self.history_spill,
       #[cfg(feature = "security")]
       property: self.property,
     }
@@ -217,6 +291,11 @@ pub struct QosPolicies {
   pub(crate) history: Option<policy::History>,
   pub(crate) resource_limits: Option<policy::ResourceLimits>,
   pub(crate) lifespan: Option<policy::Lifespan>,
+  pub(crate) partition: Option<policy::Partition>,
+  pub(crate) stale_sample_filter: Option<policy::StaleSampleFilter>,
+  pub(crate) clock_skew_tolerance: Option<policy::ClockSkewTolerance>,
+  #[cfg(feature = "history_spill")]
+  pub(crate) history_spill: Option<policy::HistorySpill>,
   #[cfg(feature = "security")]
   pub(crate) property: Option<policy::Property>,
 }
@@ -296,6 +375,23 @@ impl QosPolicies {
     self.lifespan
   }
 
+  pub fn partition(&self) -> Option<policy::Partition> {
+    self.partition.clone()
+  }
+
+  pub const fn stale_sample_filter(&self) -> Option<policy::StaleSampleFilter> {
+    self.stale_sample_filter
+  }
+
+  pub const fn clock_skew_tolerance(&self) -> Option<policy::ClockSkewTolerance> {
+    self.clock_skew_tolerance
+  }
+
+  #[cfg(feature = "history_spill")]
+  pub fn history_spill(&self) -> Option<policy::HistorySpill> {
+    self.history_spill.clone()
+  }
+
   #[cfg(feature = "security")]
   pub fn property(&self) -> Option<policy::Property> {
     self.property.clone()
@@ -320,6 +416,11 @@ impl QosPolicies {
       history: other.history.or(self.history),
       resource_limits: other.resource_limits.or(self.resource_limits),
       lifespan: other.lifespan.or(self.lifespan),
+      partition: other.partition.clone().or(self.partition.clone()),
+      stale_sample_filter: other.stale_sample_filter.or(self.stale_sample_filter),
+      clock_skew_tolerance: other.clock_skew_tolerance.or(self.clock_skew_tolerance),
+      #[cfg(feature = "history_spill")]
+      history_spill: other.history_spill.clone().or(self.history_spill.clone()),
       #[cfg(feature = "security")]
       property: other.property.clone().or(self.property.clone()),
     }
@@ -429,6 +530,66 @@ impl QosPolicies {
     None
   }
 
+  /// The QoS contract of a local endpoint having `self` as QoS with a remote
+  /// endpoint having `remote` as QoS.
+  ///
+  /// `local_is_writer` tells which side offers and which requests. Fails
+  /// with (any) one of the incompatible policies, like
+  /// [`compliance_failure_wrt`](Self::compliance_failure_wrt).
+  pub(crate) fn matched_contract(
+    &self,
+    remote: &Self,
+    local_is_writer: bool,
+  ) -> Result<MatchedContract, QosPolicyId> {
+    let (offered, requested) = if local_is_writer {
+      (self, remote)
+    } else {
+      (remote, self)
+    };
+    if let Some(bad_policy_id) = offered.compliance_failure_wrt(requested) {
+      return Err(bad_policy_id);
+    }
+
+    // Compatibility means that the offered value is at least as strong as the
+    // requested one, and the runtime acts on the requested one: the Reader
+    // checks its own deadline, and the Writer serves the Reader as reliable
+    // only if it requested so.
+    macro_rules! requested {
+      ($policy:ident) => {
+        PolicyContract {
+          local: self.$policy,
+          remote: remote.$policy,
+          effective: requested.$policy,
+        }
+      };
+    }
+    // Not matched, each side acts on its own value
+    macro_rules! local {
+      ($policy:ident) => {
+        PolicyContract {
+          local: self.$policy,
+          remote: remote.$policy,
+          effective: self.$policy,
+        }
+      };
+    }
+
+    Ok(MatchedContract {
+      durability: requested!(durability),
+      presentation: requested!(presentation),
+      deadline: requested!(deadline),
+      latency_budget: requested!(latency_budget),
+      ownership: requested!(ownership),
+      liveliness: requested!(liveliness),
+      reliability: requested!(reliability),
+      destination_order: requested!(destination_order),
+      time_based_filter: local!(time_based_filter),
+      history: local!(history),
+      resource_limits: local!(resource_limits),
+      lifespan: local!(lifespan),
+    })
+  }
+
   // serialization
   pub fn to_parameter_list(
     &self,
@@ -450,6 +611,11 @@ impl QosPolicies {
       history,
       resource_limits,
       lifespan,
+      partition,
+      stale_sample_filter: _,  // local to the Reader, not sent in Discovery
+      clock_skew_tolerance: _, // local to the Reader, not sent in Discovery
+      #[cfg(feature = "history_spill")]
+        history_spill: _, // local to the Writer, not sent in Discovery
       #[cfg(feature = "security")]
         property: _, // TODO: properties to parameter list?
     } = self;
@@ -531,6 +697,7 @@ impl QosPolicies {
     }
     emit_option!(PID_RESOURCE_LIMITS, resource_limits, policy::ResourceLimits);
     emit_option!(PID_LIFESPAN, lifespan, policy::Lifespan);
+    emit_option!(PID_PARTITION, partition, policy::Partition);
 
     Ok(pl)
   }
@@ -595,6 +762,7 @@ impl QosPolicies {
 
     let resource_limits: Option<policy::ResourceLimits> = get_option!(PID_RESOURCE_LIMITS);
     let lifespan: Option<policy::Lifespan> = get_option!(PID_LIFESPAN);
+    let partition: Option<policy::Partition> = get_option!(PID_PARTITION);
 
     #[cfg(feature = "security")]
     let property: Option<policy::Property> = None; // TODO: Should also properties be read?
@@ -614,6 +782,11 @@ impl QosPolicies {
       history,
       resource_limits,
       lifespan,
+      partition,
+      stale_sample_filter: None,
+      clock_skew_tolerance: None,
+      #[cfg(feature = "history_spill")]
+      history_spill: None,
       #[cfg(feature = "security")]
       property,
     })
@@ -662,12 +835,11 @@ pub mod policy {
   use serde::{Deserialize, Serialize};
   #[allow(unused_imports)]
   use log::{debug, error, info, trace, warn};
+  use speedy::{Context, Reader, Writer};
   #[cfg(feature = "security")]
-  use speedy::{Context, IsEof, Reader, Writer};
+  use speedy::IsEof;
 
-  use crate::structure::duration::Duration;
-  #[cfg(feature = "security")]
-  use crate::serialization::speedy_pl_cdr_helpers::*;
+  use crate::{serialization::speedy_pl_cdr_helpers::*, structure::duration::Duration};
 
   /*
   pub struct UserData {
@@ -693,6 +865,62 @@ pub mod policy {
     pub duration: Duration,
   }
 
+  /// Reader-side filter for stale samples. This is a RustDDS extension, not a
+  /// DDS policy, so it is not sent in Discovery and does not affect matching.
+  ///
+  /// A Reader with this policy drops samples for sequence numbers it has
+  /// already declared lost, and samples whose source timestamp is more than
+  /// `max_lag` older than the newest delivered sample of the same instance.
+  /// Dropped samples are still acknowledged to the Writer. Without this
+  /// policy, such samples are delivered as the DDS specification requires.
+  #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+  pub struct StaleSampleFilter {
+    pub max_lag: Duration,
+  }
+
+  /// Reader-side tolerance for differences between the wall clocks of the
+  /// Writer and the Reader. This is a RustDDS extension, not a DDS policy, so
+  /// it is not sent in Discovery and does not affect matching.
+  ///
+  /// Source timestamps come from the wall clock of the Writer, so checks that
+  /// compare them to the clock of the Reader allow `max_skew` of error: a
+  /// sample expires only when it is older than its Lifespan plus `max_skew`,
+  /// and with the BY_SOURCE_TIMESTAMP destination order, a sample stamped up
+  /// to `max_skew` before the newest one of its instance still becomes the
+  /// latest one.
+  ///
+  /// If `rebaseline_after_clock_jump` is set, a step of the local wall clock,
+  /// e.g. by NTP, makes the Reader forget the newest source timestamps of its
+  /// instances, so that the [`StaleSampleFilter`] does not drop the samples
+  /// stamped after a backwards step.
+  #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+  pub struct ClockSkewTolerance {
+    pub max_skew: Duration,
+    pub rebaseline_after_clock_jump: bool,
+  }
+
+  /// Writer-side disk storage for a large retained history. This is a RustDDS
+  /// extension, not a DDS policy, so it is not sent in Discovery and does not
+  /// affect matching. Requires the feature `history_spill`.
+  ///
+  /// When the samples in the history of a Writer take more than
+  /// `memory_budget` bytes, the oldest samples that all matched Readers have
+  /// acknowledged are moved to a file in `directory`, and read back from there
+  /// when a Reader, e.g. a late-joining one, requests them. Samples that are
+  /// not acknowledged yet stay in memory. The file is removed when the Writer
+  /// is dropped.
+  ///
+  /// With this policy the History depth is not capped by the default resource
+  /// limit of the Writer: KEEP_LAST keeps `depth` samples, and KEEP_ALL keeps
+  /// up to `max_samples` of the ResourceLimits policy, or everything if that
+  /// is not set. Samples older than the Lifespan duration are dropped.
+  #[cfg(feature = "history_spill")]
+  #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+  pub struct HistorySpill {
+    pub memory_budget: usize,
+    pub directory: std::path::PathBuf,
+  }
+
   /// DDS 2.2.3.4 DURABILITY
   #[derive(
     Copy,
@@ -823,11 +1051,58 @@ pub mod policy {
     pub minimum_separation: Duration,
   }
 
-  /*
+  /// DDS 2.2.3.13 PARTITION
+  ///
+  /// The names of the partitions a Publisher or Subscriber belongs to. An
+  /// empty list means the default partition, whose name is the empty string.
+  ///
+  /// Partitions are advertised in Discovery and checked by DDS Security access
+  /// control, but not yet used to decide which endpoints match.
+  #[derive(Clone, Debug, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
   pub struct Partition {
-    pub name: Vec<Vec<u8>>,
+    pub names: Vec<String>,
+  }
+
+  impl Partition {
+    pub fn new<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+      Self {
+        names: names.into_iter().map(Into::into).collect(),
+      }
+    }
+  }
+
+  // Serialized as sequence<string>. Each string is aligned to 4 bytes, see
+  // DataTag.
+  impl<'a, C: Context> Readable<'a, C> for Partition {
+    fn read_from<R: Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
+      let count = reader.read_u32()?;
+      let mut names = Vec::new();
+
+      let mut prev_len = 0;
+      for _ in 0..count {
+        read_pad(reader, prev_len, 4)?;
+        let name: StringWithNul = reader.read_value()?;
+        prev_len = name.len();
+        names.push(name.into());
+      }
+      Ok(Partition { names })
+    }
+  }
+
+  impl<C: Context> Writable<C> for Partition {
+    fn write_to<T: ?Sized + Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
+      writer.write_u32(self.names.len() as u32)?;
+
+      let mut prev_len = 0;
+      for name in &self.names {
+        write_pad(writer, prev_len, 4)?;
+        let name = StringWithNul::from(name);
+        writer.write_value(&name)?;
+        prev_len = name.len();
+      }
+      Ok(())
+    }
   }
-  */
 
   /// DDS 2.2.3.14 RELIABILITY
   #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -0,0 +1,653 @@
+//! Replay of captured RTPS traffic into a [`DomainParticipant`].
+//!
+//! Interoperability problems are often reported as packet captures. This
+//! module loads such a capture, extracts the RTPS messages from it, and sends
+//! them to a local [`DomainParticipant`], so that the problem can be
+//! reproduced as a test case.
+//!
+//! Supported capture formats are
+//! * pcapng, as written by Wireshark and tcpdump. Link types Ethernet, BSD
+//!   loopback, raw IP and Linux cooked capture (v1 and v2) are understood.
+//! * Raw frames: a sequence of Ethernet frames, each preceded by its length as
+//!   a big-endian `u32`.
+//!
+//! Only UDP over IPv4 or IPv6 is extracted. Fragmented IP datagrams are
+//! skipped.
+//!
+//! The messages are replayed into a DomainParticipant built by the
+//! [`Replayer`]. It has no UDP sockets: the messages are passed directly to its
+//! receive path, and the messages it sends are recorded instead. RustDDS does
+//! not use the UDP source address of received messages, so they are processed
+//! as if they had arrived from the recorded source locators.
+//!
+//! The participant runs on a simulated clock, which stands still unless
+//! advanced with [`Replayer::advance_clock`]. Recorded timestamps are not
+//! followed. Periodic actions, such as announcements and lease checks, are
+//! still triggered by timers, but leases expire only as simulated time passes.
+//!
+//! The results of a replay are observed through discovery events via
+//! [`Replayer::wait_for_status`], the messages sent by the participant via
+//! [`Replayer::take_sent`], and data samples via DataReaders created on the
+//! participant before the replay.
+
+use std::{
+  fs, io,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+  path::Path,
+  thread,
+  time::{Duration as StdDuration, Instant},
+};
+
+use bytes::Bytes;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+pub use crate::network::mock_transport::SentDatagram;
+use crate::{
+  dds::{
+    participant::{DomainParticipant, DomainParticipantBuilder, DomainParticipantStatusListener},
+    result::CreateResult,
+    statusevents::{DomainParticipantStatusEvent, StatusEvented},
+  },
+  network::mock_transport::{mock_transport, MockNetwork},
+  structure::{clock::SimulatedClock, duration::Duration, guid::GuidPrefix},
+};
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+const RTPS_HEADER_SIZE: usize = 20;
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// A UDP datagram found in a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedDatagram {
+  pub source: SocketAddr,
+  pub destination: SocketAddr,
+  pub payload: Bytes,
+}
+
+impl CapturedDatagram {
+  /// Does the payload start with an RTPS message header?
+  pub fn is_rtps(&self) -> bool {
+    self.payload.len() >= RTPS_HEADER_SIZE && self.payload.starts_with(b"RTPS")
+  }
+
+  /// GuidPrefix of the sending participant, from the RTPS message header
+  pub fn guid_prefix(&self) -> Option<GuidPrefix> {
+    if self.is_rtps() {
+      Some(GuidPrefix::new(&self.payload[8..RTPS_HEADER_SIZE]))
+    } else {
+      None
+    }
+  }
+}
+
+/// Selects which RTPS messages of a [`Capture`] are replayed.
+///
+/// The default filter passes all RTPS messages. Each added port or GuidPrefix
+/// widens the set of accepted values for that criterion.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureFilter {
+  ports: Vec<u16>,
+  guid_prefixes: Vec<GuidPrefix>,
+}
+
+impl CaptureFilter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Accept datagrams having this source or destination port.
+  #[must_use]
+  pub fn port(mut self, port: u16) -> Self {
+    self.ports.push(port);
+    self
+  }
+
+  /// Accept RTPS messages sent by the participant with this GuidPrefix.
+  #[must_use]
+  pub fn guid_prefix(mut self, guid_prefix: GuidPrefix) -> Self {
+    self.guid_prefixes.push(guid_prefix);
+    self
+  }
+
+  pub fn accepts(&self, datagram: &CapturedDatagram) -> bool {
+    datagram.is_rtps()
+      && (self.ports.is_empty()
+        || self.ports.contains(&datagram.source.port())
+        || self.ports.contains(&datagram.destination.port()))
+      && (self.guid_prefixes.is_empty()
+        || matches!(datagram.guid_prefix(), Some(p) if self.guid_prefixes.contains(&p)))
+  }
+}
+
+/// UDP datagrams loaded from a capture file, in capture order.
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+  datagrams: Vec<CapturedDatagram>,
+}
+
+impl Capture {
+  /// Loads a pcapng or raw frames file. The format is detected from the
+  /// contents.
+  pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+    Self::from_bytes(&fs::read(path)?)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+    if bytes.len() >= 4 && LittleEndian::read_u32(bytes) == PCAPNG_SECTION_HEADER {
+      Self::from_pcapng(bytes)
+    } else {
+      Self::from_raw_frames(bytes)
+    }
+  }
+
+  pub fn from_pcapng(bytes: &[u8]) -> io::Result<Self> {
+    let mut datagrams = Vec::new();
+    // Link type and snap length of each interface in the current section
+    let mut interfaces: Vec<(u16, usize)> = Vec::new();
+    let mut big_endian = false;
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+      if rest.len() < 12 {
+        return Err(invalid_data("pcapng: truncated block header"));
+      }
+      // The section header block type is a palindrome, so it can be read
+      // before the byte order is known.
+      if LittleEndian::read_u32(rest) == PCAPNG_SECTION_HEADER {
+        big_endian = match LittleEndian::read_u32(&rest[8..]) {
+          PCAPNG_BYTE_ORDER_MAGIC => false,
+          m if m.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+          _ => return Err(invalid_data("pcapng: bad byte order magic")),
+        };
+        interfaces.clear();
+      }
+      let read_u16 = |b: &[u8]| {
+        if big_endian {
+          BigEndian::read_u16(b)
+        } else {
+          LittleEndian::read_u16(b)
+        }
+      };
+      let read_u32 = |b: &[u8]| {
+        if big_endian {
+          BigEndian::read_u32(b)
+        } else {
+          LittleEndian::read_u32(b)
+        }
+      };
+
+      let block_type = read_u32(rest);
+      let total_length = read_u32(&rest[4..]) as usize;
+      if total_length < 12 || total_length % 4 != 0 || total_length > rest.len() {
+        return Err(invalid_data(format!(
+          "pcapng: bad block length {total_length}"
+        )));
+      }
+      let body = &rest[8..total_length - 4];
+      rest = &rest[total_length..];
+
+      match block_type {
+        PCAPNG_INTERFACE_DESCRIPTION if body.len() >= 8 => {
+          let snap_length = match read_u32(&body[4..]) as usize {
+            0 => usize::MAX,
+            s => s,
+          };
+          interfaces.push((read_u16(body), snap_length));
+        }
+        PCAPNG_ENHANCED_PACKET if body.len() >= 20 => {
+          let interface_id = read_u32(body) as usize;
+          let captured_length = read_u32(&body[12..]) as usize;
+          let (link_type, _) = interfaces
+            .get(interface_id)
+            .ok_or_else(|| invalid_data("pcapng: packet from undeclared interface"))?;
+          let frame = body
+            .get(20..20 + captured_length)
+            .ok_or_else(|| invalid_data("pcapng: truncated packet"))?;
+          datagrams.extend(udp_from_link_layer(*link_type, frame));
+        }
+        PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+          let original_length = read_u32(body) as usize;
+          let (link_type, snap_length) = interfaces
+            .first()
+            .ok_or_else(|| invalid_data("pcapng: packet from undeclared interface"))?;
+          let captured_length = original_length.min(*snap_length).min(body.len() - 4);
+          datagrams.extend(udp_from_link_layer(
+            *link_type,
+            &body[4..4 + captured_length],
+          ));
+        }
+        _ => (), // Other block types carry nothing we need
+      }
+    }
+    Ok(Capture { datagrams })
+  }
+
+  pub fn from_raw_frames(bytes: &[u8]) -> io::Result<Self> {
+    let mut datagrams = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+      if rest.len() < 4 {
+        return Err(invalid_data("raw frames: truncated length"));
+      }
+      let length = BigEndian::read_u32(rest) as usize;
+      let frame = rest
+        .get(4..4 + length)
+        .ok_or_else(|| invalid_data("raw frames: truncated frame"))?;
+      datagrams.extend(udp_from_link_layer(LINKTYPE_ETHERNET, frame));
+      rest = &rest[4 + length..];
+    }
+    Ok(Capture { datagrams })
+  }
+
+  /// All UDP datagrams in the capture
+  pub fn datagrams(&self) -> &[CapturedDatagram] {
+    &self.datagrams
+  }
+
+  /// RTPS messages accepted by `filter`
+  pub fn rtps_messages<'a>(
+    &'a self,
+    filter: &'a CaptureFilter,
+  ) -> impl Iterator<Item = &'a CapturedDatagram> + 'a {
+    self.datagrams.iter().filter(|d| filter.accepts(d))
+  }
+}
+
+fn udp_from_link_layer(link_type: u16, frame: &[u8]) -> Option<CapturedDatagram> {
+  let (ethertype, packet) = match link_type {
+    LINKTYPE_ETHERNET => {
+      let mut ethertype = BigEndian::read_u16(frame.get(12..14)?);
+      let mut offset = 14;
+      while ethertype == ETHERTYPE_VLAN {
+        ethertype = BigEndian::read_u16(frame.get(offset + 2..offset + 4)?);
+        offset += 4;
+      }
+      (ethertype, frame.get(offset..)?)
+    }
+    // Address family in host byte order. IPv4 is 2, IPv6 varies by OS.
+    LINKTYPE_NULL => {
+      let family = frame.get(0..4)?;
+      let ethertype = if family == [2, 0, 0, 0] || family == [0, 0, 0, 2] {
+        ETHERTYPE_IPV4
+      } else {
+        ETHERTYPE_IPV6
+      };
+      (ethertype, frame.get(4..)?)
+    }
+    LINKTYPE_RAW => match frame.first()? >> 4 {
+      4 => (ETHERTYPE_IPV4, frame),
+      6 => (ETHERTYPE_IPV6, frame),
+      _ => return None,
+    },
+    LINKTYPE_LINUX_SLL => (BigEndian::read_u16(frame.get(14..16)?), frame.get(16..)?),
+    LINKTYPE_LINUX_SLL2 => (BigEndian::read_u16(frame.get(0..2)?), frame.get(20..)?),
+    _ => return None,
+  };
+
+  match ethertype {
+    ETHERTYPE_IPV4 => udp_from_ipv4(packet),
+    ETHERTYPE_IPV6 => udp_from_ipv6(packet),
+    _ => None,
+  }
+}
+
+fn udp_from_ipv4(packet: &[u8]) -> Option<CapturedDatagram> {
+  let header_length = usize::from(packet.first()? & 0x0F) * 4;
+  let total_length = usize::from(BigEndian::read_u16(packet.get(2..4)?));
+  let fragment = BigEndian::read_u16(packet.get(6..8)?);
+  let more_fragments = fragment & 0x2000 != 0;
+  let fragment_offset = fragment & 0x1FFF;
+  if more_fragments || fragment_offset != 0 || *packet.get(9)? != IP_PROTOCOL_UDP {
+    return None;
+  }
+  let source = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(12..16)?).ok()?);
+  let destination = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(16..20)?).ok()?);
+  udp_datagram(
+    source.into(),
+    destination.into(),
+    packet.get(header_length..total_length)?,
+  )
+}
+
+fn udp_from_ipv6(packet: &[u8]) -> Option<CapturedDatagram> {
+  // Extension headers are not supported.
+  if *packet.get(6)? != IP_PROTOCOL_UDP {
+    return None;
+  }
+  let payload_length = usize::from(BigEndian::read_u16(packet.get(4..6)?));
+  let source = Ipv6Addr::from(<[u8; 16]>::try_from(packet.get(8..24)?).ok()?);
+  let destination = Ipv6Addr::from(<[u8; 16]>::try_from(packet.get(24..40)?).ok()?);
+  udp_datagram(
+    source.into(),
+    destination.into(),
+    packet.get(40..40 + payload_length)?,
+  )
+}
+
+fn udp_datagram(source: IpAddr, destination: IpAddr, segment: &[u8]) -> Option<CapturedDatagram> {
+  let length = usize::from(BigEndian::read_u16(segment.get(4..6)?));
+  Some(CapturedDatagram {
+    source: SocketAddr::new(source, BigEndian::read_u16(segment.get(0..2)?)),
+    destination: SocketAddr::new(destination, BigEndian::read_u16(segment.get(2..4)?)),
+    payload: Bytes::copy_from_slice(segment.get(8..length)?),
+  })
+}
+
+/// Replays captured RTPS messages into a [`DomainParticipant`] of its own.
+///
+/// The clocks of the thread that creates the Replayer are simulated too, until
+/// the Replayer is dropped.
+pub struct Replayer {
+  participant: DomainParticipant,
+  network: MockNetwork,
+  clock: SimulatedClock,
+  status_listener: DomainParticipantStatusListener,
+}
+
+impl Replayer {
+  /// Builds the participant from `builder`, replacing its UDP sockets and
+  /// clock.
+  pub fn new(builder: DomainParticipantBuilder) -> CreateResult<Self> {
+    let (transport, network) = mock_transport();
+    let clock = SimulatedClock::start();
+    let participant = builder.mock_transport(transport).build()?;
+    let status_listener = participant.status_listener();
+    Ok(Replayer {
+      participant,
+      network,
+      clock,
+      status_listener,
+    })
+  }
+
+  /// The participant that receives the replayed messages
+  pub fn participant(&self) -> &DomainParticipant {
+    &self.participant
+  }
+
+  /// Passes one datagram to the participant.
+  pub fn inject(&self, datagram: &CapturedDatagram) -> io::Result<()> {
+    self.network.deliver(datagram.payload.clone())
+  }
+
+  /// Passes the RTPS messages of `capture` accepted by `filter` to the
+  /// participant, in capture order. Returns the number of messages passed.
+  pub fn replay(&self, capture: &Capture, filter: &CaptureFilter) -> io::Result<usize> {
+    let mut count = 0;
+    for datagram in capture.rtps_messages(filter) {
+      self.inject(datagram)?;
+      count += 1;
+    }
+    Ok(count)
+  }
+
+  /// Lets simulated time pass for the participant.
+  pub fn advance_clock(&self, duration: StdDuration) {
+    self.clock.advance(Duration::from_std(duration));
+  }
+
+  /// Removes and returns the messages that the participant has sent, in the
+  /// order they were sent.
+  pub fn take_sent(&self) -> Vec<SentDatagram> {
+    self.network.take_sent()
+  }
+
+  /// Waits until the participant sends a message for which `predicate` is
+  /// true, and returns the first such message. Returns `None` on timeout. The
+  /// messages sent until then are consumed.
+  pub fn wait_for_sent<F>(&self, timeout: StdDuration, mut predicate: F) -> Option<SentDatagram>
+  where
+    F: FnMut(&SentDatagram) -> bool,
+  {
+    let deadline = Instant::now() + timeout;
+    loop {
+      if let Some(datagram) = self.take_sent().into_iter().find(&mut predicate) {
+        return Some(datagram);
+      }
+      if Instant::now() >= deadline {
+        return None;
+      }
+      thread::sleep(StdDuration::from_millis(10));
+    }
+  }
+
+  /// Waits for a participant status event for which `predicate` is true.
+  /// Events not matching the predicate are consumed. Returns `None` on
+  /// timeout.
+  pub fn wait_for_status<F>(
+    &self,
+    timeout: StdDuration,
+    mut predicate: F,
+  ) -> Option<DomainParticipantStatusEvent>
+  where
+    F: FnMut(&DomainParticipantStatusEvent) -> bool,
+  {
+    let deadline = Instant::now() + timeout;
+    loop {
+      while let Some(event) = self.status_listener.try_recv_status() {
+        if predicate(&event) {
+          return Some(event);
+        }
+      }
+      if Instant::now() >= deadline {
+        return None;
+      }
+      thread::sleep(StdDuration::from_millis(10));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::structure::{entity::RTPSEntity, locator::Locator};
+
+  // A DATA(p) from Fast RTPS, captured with Wireshark from shapes demo, sent to
+  // the discovery multicast port. Preceded by a datagram that is not RTPS.
+  const SPDP_DATA_P_CAPTURE: &[u8] = include_bytes!("../test/captures/spdp_data_p.pcapng");
+  // SEC_PREFIX, SEC_BODY and SEC_POSTFIX, for which the participant has no
+  // keys, followed by a message with a SEC_PREFIX too short for a CryptoHeader.
+  const SECURE_SUBMESSAGES_CAPTURE: &[u8] =
+    include_bytes!("../test/captures/secure_submessages.pcapng");
+
+  fn fast_rtps_prefix() -> GuidPrefix {
+    GuidPrefix::new(&[
+      0x01, 0x0f, 0x99, 0x06, 0x78, 0x34, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ])
+  }
+
+  fn participant_discovered(
+    prefix: GuidPrefix,
+  ) -> impl FnMut(&DomainParticipantStatusEvent) -> bool {
+    move |event| {
+      matches!(event,
+        DomainParticipantStatusEvent::ParticipantDiscovered { dpd } if dpd.guid.prefix == prefix)
+    }
+  }
+
+  fn participant_lost(prefix: GuidPrefix) -> impl FnMut(&DomainParticipantStatusEvent) -> bool {
+    move |event| {
+      matches!(event,
+        DomainParticipantStatusEvent::ParticipantLost { id, .. } if *id == prefix)
+    }
+  }
+
+  // A freshly created participant reports matching its own builtin endpoints.
+  // Wait until it has done so, because the status channel is bounded and
+  // drops events when full.
+  fn wait_until_settled(replayer: &Replayer) {
+    while replayer
+      .wait_for_status(StdDuration::from_millis(300), |_| true)
+      .is_some()
+    {}
+  }
+
+  #[test]
+  fn pcapng_ethernet() {
+    let capture = Capture::from_bytes(SPDP_DATA_P_CAPTURE).unwrap();
+    assert_eq!(capture.datagrams().len(), 2);
+    assert!(!capture.datagrams()[0].is_rtps());
+
+    let filter = CaptureFilter::new();
+    let all: Vec<_> = capture.rtps_messages(&filter).collect();
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].guid_prefix(), Some(fast_rtps_prefix()));
+    assert_eq!(
+      all[0].destination,
+      SocketAddr::new(Ipv4Addr::new(239, 255, 0, 1).into(), 7400)
+    );
+    assert_eq!(all[0].payload.len(), 204);
+  }
+
+  #[test]
+  fn raw_frames_match_pcapng() {
+    // Cut the Ethernet frames out of the pcapng file: each enhanced packet
+    // block has a 28 byte header.
+    let pcapng = SPDP_DATA_P_CAPTURE;
+    let mut raw = Vec::new();
+    let mut offset = 0;
+    while offset < pcapng.len() {
+      let block_type = LittleEndian::read_u32(&pcapng[offset..]);
+      let total_length = LittleEndian::read_u32(&pcapng[offset + 4..]) as usize;
+      if block_type == PCAPNG_ENHANCED_PACKET {
+        let captured_length = LittleEndian::read_u32(&pcapng[offset + 20..]) as usize;
+        raw.extend_from_slice(&(captured_length as u32).to_be_bytes());
+        raw.extend_from_slice(&pcapng[offset + 28..offset + 28 + captured_length]);
+      }
+      offset += total_length;
+    }
+    let from_raw = Capture::from_bytes(&raw).unwrap();
+    let from_pcapng = Capture::from_bytes(pcapng).unwrap();
+    assert_eq!(from_raw.datagrams(), from_pcapng.datagrams());
+  }
+
+  #[test]
+  fn capture_filter() {
+    let capture = Capture::from_bytes(SECURE_SUBMESSAGES_CAPTURE).unwrap();
+    let secure_prefix = capture.datagrams()[0].guid_prefix().unwrap();
+
+    let count = |filter: CaptureFilter| capture.rtps_messages(&filter).count();
+    assert_eq!(count(CaptureFilter::new()), 2);
+    assert_eq!(count(CaptureFilter::new().port(7411)), 2);
+    assert_eq!(count(CaptureFilter::new().port(43211)), 2);
+    assert_eq!(count(CaptureFilter::new().port(7410)), 0);
+    assert_eq!(count(CaptureFilter::new().guid_prefix(secure_prefix)), 2);
+    assert_eq!(
+      count(CaptureFilter::new().guid_prefix(fast_rtps_prefix())),
+      0
+    );
+    assert_eq!(
+      count(
+        CaptureFilter::new()
+          .guid_prefix(fast_rtps_prefix())
+          .guid_prefix(secure_prefix)
+      ),
+      2
+    );
+  }
+
+  #[test]
+  fn truncated_pcapng_is_an_error() {
+    let capture = SPDP_DATA_P_CAPTURE;
+    assert!(Capture::from_pcapng(&capture[..capture.len() - 5]).is_err());
+  }
+
+  #[test]
+  fn replay_spdp_data_p() {
+    let replayer = Replayer::new(DomainParticipantBuilder::new(0)).unwrap();
+    let capture = Capture::from_bytes(SPDP_DATA_P_CAPTURE).unwrap();
+
+    wait_until_settled(&replayer);
+    assert_eq!(replayer.replay(&capture, &CaptureFilter::new()).unwrap(), 1);
+    assert!(replayer
+      .wait_for_status(
+        StdDuration::from_secs(5),
+        participant_discovered(fast_rtps_prefix())
+      )
+      .is_some());
+
+    // The participant announces itself to the metatraffic unicast locator of
+    // the discovered participant.
+    let fast_rtps_metatraffic = Locator::from(SocketAddr::new(
+      Ipv4Addr::new(10, 80, 142, 104).into(),
+      7412,
+    ));
+    let own_prefix = replayer.participant().guid().prefix;
+    assert!(replayer
+      .wait_for_sent(StdDuration::from_secs(5), |datagram| {
+        datagram.locator == fast_rtps_metatraffic
+          && GuidPrefix::new(&datagram.payload[8..RTPS_HEADER_SIZE]) == own_prefix
+      })
+      .is_some());
+  }
+
+  #[test]
+  fn replayed_participant_expires_only_as_simulated_time_passes() {
+    let replayer = Replayer::new(DomainParticipantBuilder::new(0)).unwrap();
+    let capture = Capture::from_bytes(SPDP_DATA_P_CAPTURE).unwrap();
+
+    wait_until_settled(&replayer);
+    replayer.replay(&capture, &CaptureFilter::new()).unwrap();
+    assert!(replayer
+      .wait_for_status(
+        StdDuration::from_secs(5),
+        participant_discovered(fast_rtps_prefix())
+      )
+      .is_some());
+
+    // Lease checks run every 2 seconds, but the clock stands still.
+    assert!(replayer
+      .wait_for_status(
+        StdDuration::from_secs(3),
+        participant_lost(fast_rtps_prefix())
+      )
+      .is_none());
+
+    replayer.advance_clock(StdDuration::from_secs(3600));
+    assert!(replayer
+      .wait_for_status(
+        StdDuration::from_secs(5),
+        participant_lost(fast_rtps_prefix())
+      )
+      .is_some());
+  }
+
+  #[test]
+  fn replay_undecodable_secure_submessages() {
+    let replayer = Replayer::new(DomainParticipantBuilder::new(0)).unwrap();
+
+    let capture = Capture::from_bytes(SECURE_SUBMESSAGES_CAPTURE).unwrap();
+    wait_until_settled(&replayer);
+    assert_eq!(replayer.replay(&capture, &CaptureFilter::new()).unwrap(), 2);
+
+    // The participant must survive the above and still process discovery.
+    let capture = Capture::from_bytes(SPDP_DATA_P_CAPTURE).unwrap();
+    replayer.replay(&capture, &CaptureFilter::new()).unwrap();
+    assert!(replayer
+      .wait_for_status(
+        StdDuration::from_secs(5),
+        participant_discovered(fast_rtps_prefix())
+      )
+      .is_some());
+  }
+}
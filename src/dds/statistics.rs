@@ -0,0 +1,913 @@
+//! Publisher for a subset of the eProsima Fast DDS statistics topics.
+//!
+//! Fast DDS can publish protocol statistics on a set of well-known topics
+//! (`_fastdds_statistics_...`), which are consumed e.g. by Fast DDS Monitor.
+//! This module maps the counters collected by RustDDS onto the same topic
+//! names, type names and QoS, so that such tooling can observe RustDDS
+//! participants, too.
+//!
+//! The published subset is
+//! * [`HISTORY_LATENCY_TOPIC`]: [`WriterReaderData`], latency from the
+//!   writer's source timestamp to reception at the reader, in nanoseconds
+//! * [`PUBLICATION_THROUGHPUT_TOPIC`] and [`SUBSCRIPTION_THROUGHPUT_TOPIC`]:
+//!   [`EntityData`], serialized payload bytes per second
+//! * [`HEARTBEAT_COUNT_TOPIC`], [`ACKNACK_COUNT_TOPIC`], [`GAP_COUNT_TOPIC`]
+//!   and [`DATA_COUNT_TOPIC`]: [`EntityCount`], cumulative submessage counts
+//! * [`NETWORK_LATENCY_TOPIC`]: [`Locator2LocatorData`], half of the round
+//!   trip time from a DataWriter's HEARTBEAT to the ACKNACK answering it, in
+//!   nanoseconds. `dst_locator` is the first unicast locator of the remote
+//!   DataReader. `src_locator` is invalid, because RustDDS does not know which
+//!   of its own locators the ACKNACK arrived to.
+//! * [`RTPS_LOST_TOPIC`]: [`Entity2LocatorTraffic`], cumulative count of
+//!   samples lost by a DataReader, i.e. the count reported by
+//!   [`SampleLost`](crate::dds::statusevents::DataReaderStatus::SampleLost).
+//!   Fast DDS reports this per DataWriter and destination locator. RustDDS
+//!   reports it per DataReader in `src_guid`, with an invalid `dst_locator`,
+//!   and `byte_count` zero.
+//!
+//! Only user-defined DataReaders and DataWriters are reported. The
+//! statistics DataWriters themselves are excluded.
+
+use std::{
+  collections::BTreeMap,
+  sync::{
+    mpsc::{self, RecvTimeoutError},
+    Mutex,
+  },
+  thread::{self, JoinHandle},
+  time::{Duration as StdDuration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use cdr_encoding_size::CdrEncodingSize;
+use log::{debug, error, warn};
+
+use crate::{
+  dds::{
+    key::{Key, Keyed},
+    participant::DomainParticipant,
+    qos::{
+      policy::{Durability, History, Reliability},
+      QosPolicies, QosPolicyBuilder,
+    },
+    result::{CreateError, CreateResult},
+    with_key::datawriter::DataWriterCdr,
+  },
+  structure::{
+    duration::Duration, entity::RTPSEntity, guid::GUID, locator::Locator, time::Timestamp,
+    topic_kind::TopicKind,
+  },
+};
+
+pub const HISTORY_LATENCY_TOPIC: &str = "_fastdds_statistics_history2history_latency";
+pub const NETWORK_LATENCY_TOPIC: &str = "_fastdds_statistics_network_latency";
+pub const PUBLICATION_THROUGHPUT_TOPIC: &str = "_fastdds_statistics_publication_throughput";
+pub const SUBSCRIPTION_THROUGHPUT_TOPIC: &str = "_fastdds_statistics_subscription_throughput";
+pub const HEARTBEAT_COUNT_TOPIC: &str = "_fastdds_statistics_heartbeat_count";
+pub const ACKNACK_COUNT_TOPIC: &str = "_fastdds_statistics_acknack_count";
+pub const GAP_COUNT_TOPIC: &str = "_fastdds_statistics_gap_count";
+pub const DATA_COUNT_TOPIC: &str = "_fastdds_statistics_data_count";
+pub const RTPS_LOST_TOPIC: &str = "_fastdds_statistics_rtps_lost";
+
+const ENTITY_DATA_TYPE_NAME: &str = "eprosima::fastdds::statistics::EntityData";
+const WRITER_READER_DATA_TYPE_NAME: &str = "eprosima::fastdds::statistics::WriterReaderData";
+const ENTITY_COUNT_TYPE_NAME: &str = "eprosima::fastdds::statistics::EntityCount";
+const LOCATOR2LOCATOR_DATA_TYPE_NAME: &str = "eprosima::fastdds::statistics::Locator2LocatorData";
+const ENTITY2LOCATOR_TRAFFIC_TYPE_NAME: &str =
+  "eprosima::fastdds::statistics::Entity2LocatorTraffic";
+
+/// QoS used by Fast DDS for its statistics DataWriters.
+pub fn statistics_qos() -> QosPolicies {
+  QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::ZERO,
+    })
+    .durability(Durability::TransientLocal)
+    .history(History::KeepLast { depth: 100 })
+    .build()
+}
+
+// ---------------------------------------------------------------
+// IDL types. These follow module eprosima::fastdds::statistics in the Fast DDS
+// file types.idl. Field order and widths must not be changed.
+
+/// `detail::EntityId_s`
+#[allow(non_camel_case_types)]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct EntityId_s {
+  pub value: [u8; 4],
+}
+
+/// `detail::GuidPrefix_s`
+#[allow(non_camel_case_types)]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct GuidPrefix_s {
+  pub value: [u8; 12],
+}
+
+/// `detail::GUID_s`
+#[allow(non_camel_case_types)]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct GUID_s {
+  pub guid_prefix: GuidPrefix_s,
+  pub entity_id: EntityId_s,
+}
+
+impl Key for GUID_s {}
+
+impl From<GUID> for GUID_s {
+  fn from(guid: GUID) -> Self {
+    Self {
+      guid_prefix: GuidPrefix_s {
+        value: guid.prefix.bytes,
+      },
+      entity_id: EntityId_s {
+        value: guid.entity_id.to_slice(),
+      },
+    }
+  }
+}
+
+/// `detail::Locator_s`
+#[allow(non_camel_case_types)]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct Locator_s {
+  pub kind: i32,
+  pub port: u32,
+  pub address: [u8; 16],
+}
+
+impl From<Locator> for Locator_s {
+  fn from(locator: Locator) -> Self {
+    let (kind, port, address) = match locator {
+      Locator::Invalid => (-1, 0, [0; 16]),
+      Locator::Reserved => (0, 0, [0; 16]),
+      Locator::UdpV4(sa) => {
+        let mut address = [0; 16];
+        address[12..].copy_from_slice(&sa.ip().octets());
+        (1, u32::from(sa.port()), address)
+      }
+      Locator::UdpV6(sa) => (2, u32::from(sa.port()), sa.ip().octets()),
+      Locator::Other {
+        kind,
+        port,
+        address,
+      } => (kind, port, address),
+    };
+    Self {
+      kind,
+      port,
+      address,
+    }
+  }
+}
+
+/// Sample type of the publication and subscription throughput topics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityData {
+  pub guid: GUID_s,
+  pub data: f32,
+}
+
+impl Keyed for EntityData {
+  type K = GUID_s;
+  fn key(&self) -> GUID_s {
+    self.guid
+  }
+}
+
+/// Sample type of the history latency topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WriterReaderData {
+  pub writer_guid: GUID_s,
+  pub reader_guid: GUID_s,
+  pub data: f32,
+}
+
+/// Key of [`WriterReaderData`]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct WriterReaderKey {
+  pub writer_guid: GUID_s,
+  pub reader_guid: GUID_s,
+}
+
+impl Key for WriterReaderKey {}
+
+impl Keyed for WriterReaderData {
+  type K = WriterReaderKey;
+  fn key(&self) -> WriterReaderKey {
+    WriterReaderKey {
+      writer_guid: self.writer_guid,
+      reader_guid: self.reader_guid,
+    }
+  }
+}
+
+/// Sample type of the network latency topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Locator2LocatorData {
+  pub src_locator: Locator_s,
+  pub dst_locator: Locator_s,
+  pub data: f32,
+}
+
+/// Key of [`Locator2LocatorData`]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct Locator2LocatorKey {
+  pub src_locator: Locator_s,
+  pub dst_locator: Locator_s,
+}
+
+impl Key for Locator2LocatorKey {}
+
+impl Keyed for Locator2LocatorData {
+  type K = Locator2LocatorKey;
+  fn key(&self) -> Locator2LocatorKey {
+    Locator2LocatorKey {
+      src_locator: self.src_locator,
+      dst_locator: self.dst_locator,
+    }
+  }
+}
+
+/// Sample type of the RTPS lost topic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entity2LocatorTraffic {
+  pub src_guid: GUID_s,
+  pub dst_locator: Locator_s,
+  pub packet_count: u64,
+  pub byte_count: u64,
+  pub byte_magnitude_order: i16,
+}
+
+/// Key of [`Entity2LocatorTraffic`]
+#[derive(
+  Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CdrEncodingSize,
+)]
+pub struct Entity2LocatorKey {
+  pub src_guid: GUID_s,
+  pub dst_locator: Locator_s,
+}
+
+impl Key for Entity2LocatorKey {}
+
+impl Keyed for Entity2LocatorTraffic {
+  type K = Entity2LocatorKey;
+  fn key(&self) -> Entity2LocatorKey {
+    Entity2LocatorKey {
+      src_guid: self.src_guid,
+      dst_locator: self.dst_locator,
+    }
+  }
+}
+
+/// Sample type of the submessage count topics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityCount {
+  pub guid: GUID_s,
+  pub count: u64,
+}
+
+impl Keyed for EntityCount {
+  type K = GUID_s;
+  fn key(&self) -> GUID_s {
+    self.guid
+  }
+}
+
+// ---------------------------------------------------------------
+// Counters. These are updated by the RTPS Readers and Writers from the event
+// loop thread, and read by the StatisticsPublisher thread.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct EntityCounters {
+  pub heartbeat_count: u64,
+  pub acknack_count: u64,
+  pub gap_count: u64,
+  pub data_count: u64,
+  pub bytes_sent: u64,
+  pub bytes_received: u64,
+  pub samples_lost: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct LatencyAccumulator {
+  total_nanos: f64,
+  samples: u64,
+}
+
+#[derive(Debug, Default)]
+struct StatisticsInner {
+  counters: BTreeMap<GUID, EntityCounters>,
+  // (writer, reader) -> latency since last snapshot
+  latencies: BTreeMap<(GUID, GUID), LatencyAccumulator>,
+  // writer -> when it last sent a HEARTBEAT
+  heartbeats_sent: BTreeMap<GUID, Instant>,
+  // (writer, reader) -> the HEARTBEAT the reader has already answered
+  heartbeats_answered: BTreeMap<(GUID, GUID), Instant>,
+  // remote reader locator -> one-way latency since last snapshot
+  network_latencies: BTreeMap<Locator, LatencyAccumulator>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StatisticsSnapshot {
+  pub counters: BTreeMap<GUID, EntityCounters>,
+  // (writer, reader) -> mean latency in nanoseconds since previous snapshot
+  pub latencies: BTreeMap<(GUID, GUID), f64>,
+  // remote reader locator -> mean one-way latency in nanoseconds since
+  // previous snapshot
+  pub network_latencies: BTreeMap<Locator, f64>,
+}
+
+impl LatencyAccumulator {
+  fn add(&mut self, nanos: f64) {
+    self.total_nanos += nanos;
+    self.samples += 1;
+  }
+}
+
+fn drain_means<K: Ord>(accumulators: &mut BTreeMap<K, LatencyAccumulator>) -> BTreeMap<K, f64> {
+  std::mem::take(accumulators)
+    .into_iter()
+    .filter(|(_, acc)| acc.samples > 0)
+    .map(|(k, acc)| (k, acc.total_nanos / acc.samples as f64))
+    .collect()
+}
+
+/// Protocol counters of all local user-defined DataReaders and DataWriters of
+/// one DomainParticipant.
+#[derive(Debug, Default)]
+pub(crate) struct ProtocolStatistics {
+  inner: Mutex<StatisticsInner>,
+}
+
+impl ProtocolStatistics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn update(&self, guid: GUID, f: impl FnOnce(&mut EntityCounters)) {
+    match self.inner.lock() {
+      Ok(mut inner) => f(inner.counters.entry(guid).or_default()),
+      Err(e) => error!("ProtocolStatistics lock poisoned: {e:?}"),
+    }
+  }
+
+  pub fn heartbeat_sent(&self, writer: GUID) {
+    match self.inner.lock() {
+      Ok(mut inner) => {
+        inner.counters.entry(writer).or_default().heartbeat_count += 1;
+        inner.heartbeats_sent.insert(writer, Instant::now());
+      }
+      Err(e) => error!("ProtocolStatistics lock poisoned: {e:?}"),
+    }
+  }
+
+  // The first ACKNACK from a reader after a HEARTBEAT gives an estimate of
+  // the round trip time to the reader.
+  pub fn acknack_received(&self, writer: GUID, reader: GUID, reader_locator: Locator) {
+    match self.inner.lock() {
+      Ok(mut inner) => {
+        let Some(&heartbeat_sent) = inner.heartbeats_sent.get(&writer) else {
+          return; // An ACKNACK nobody asked for
+        };
+        let previous = inner
+          .heartbeats_answered
+          .insert((writer, reader), heartbeat_sent);
+        if previous != Some(heartbeat_sent) {
+          let round_trip = heartbeat_sent.elapsed();
+          inner
+            .network_latencies
+            .entry(reader_locator)
+            .or_default()
+            .add(round_trip.as_nanos() as f64 / 2.0);
+        }
+      }
+      Err(e) => error!("ProtocolStatistics lock poisoned: {e:?}"),
+    }
+  }
+
+  pub fn samples_lost(&self, reader: GUID, count: u64) {
+    self.update(reader, |c| c.samples_lost += count);
+  }
+
+  /// Forgets a local DataReader or DataWriter that has been deleted.
+  pub fn remove_entity(&self, guid: GUID) {
+    match self.inner.lock() {
+      Ok(mut inner) => {
+        inner.counters.remove(&guid);
+        inner.heartbeats_sent.remove(&guid);
+        inner
+          .latencies
+          .retain(|(writer, reader), _| *writer != guid && *reader != guid);
+        inner
+          .heartbeats_answered
+          .retain(|(writer, _reader), _| *writer != guid);
+      }
+      Err(e) => error!("ProtocolStatistics lock poisoned: {e:?}"),
+    }
+  }
+
+  pub fn acknack_sent(&self, reader: GUID) {
+    self.update(reader, |c| c.acknack_count += 1);
+  }
+
+  pub fn gap_sent(&self, writer: GUID) {
+    self.update(writer, |c| c.gap_count += 1);
+  }
+
+  pub fn data_sent(&self, writer: GUID, payload_bytes: usize) {
+    self.update(writer, |c| {
+      c.data_count += 1;
+      c.bytes_sent += payload_bytes as u64;
+    });
+  }
+
+  pub fn data_received(
+    &self,
+    reader: GUID,
+    writer: GUID,
+    payload_bytes: usize,
+    source_timestamp: Option<Timestamp>,
+    receive_timestamp: Timestamp,
+  ) {
+    match self.inner.lock() {
+      Ok(mut inner) => {
+        inner.counters.entry(reader).or_default().bytes_received += payload_bytes as u64;
+        if let Some(source_timestamp) = source_timestamp {
+          let latency = receive_timestamp.duration_since(source_timestamp);
+          // Clocks of remote hosts are not synchronized to ours. Negative
+          // latency is clock skew, not information.
+          if latency >= Duration::ZERO {
+            inner
+              .latencies
+              .entry((writer, reader))
+              .or_default()
+              .add(latency.to_nanoseconds() as f64);
+          }
+        }
+      }
+      Err(e) => error!("ProtocolStatistics lock poisoned: {e:?}"),
+    }
+  }
+
+  /// Returns cumulative counters and the mean latencies accumulated since the
+  /// previous call.
+  pub fn snapshot(&self) -> StatisticsSnapshot {
+    match self.inner.lock() {
+      Ok(mut inner) => StatisticsSnapshot {
+        counters: inner.counters.clone(),
+        latencies: drain_means(&mut inner.latencies),
+        network_latencies: drain_means(&mut inner.network_latencies),
+      },
+      Err(e) => {
+        error!("ProtocolStatistics lock poisoned: {e:?}");
+        StatisticsSnapshot::default()
+      }
+    }
+  }
+}
+
+// ---------------------------------------------------------------
+
+struct StatisticsWriters {
+  history_latency: DataWriterCdr<WriterReaderData>,
+  network_latency: DataWriterCdr<Locator2LocatorData>,
+  publication_throughput: DataWriterCdr<EntityData>,
+  subscription_throughput: DataWriterCdr<EntityData>,
+  heartbeat_count: DataWriterCdr<EntityCount>,
+  acknack_count: DataWriterCdr<EntityCount>,
+  gap_count: DataWriterCdr<EntityCount>,
+  data_count: DataWriterCdr<EntityCount>,
+  rtps_lost: DataWriterCdr<Entity2LocatorTraffic>,
+}
+
+impl StatisticsWriters {
+  fn own_guids(&self) -> Vec<GUID> {
+    vec![
+      self.history_latency.guid(),
+      self.network_latency.guid(),
+      self.publication_throughput.guid(),
+      self.subscription_throughput.guid(),
+      self.heartbeat_count.guid(),
+      self.acknack_count.guid(),
+      self.gap_count.guid(),
+      self.data_count.guid(),
+      self.rtps_lost.guid(),
+    ]
+  }
+}
+
+/// Periodically publishes the protocol statistics of a [`DomainParticipant`]
+/// on the Fast DDS statistics topics.
+///
+/// Publishing runs in a background thread, which is stopped when this object
+/// is dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use rustdds::{DomainParticipant, statistics::StatisticsPublisher};
+/// # use std::time::Duration;
+/// let domain_participant = DomainParticipant::new(0).unwrap();
+/// let statistics = StatisticsPublisher::new(&domain_participant, Duration::from_secs(1)).unwrap();
+/// ```
+pub struct StatisticsPublisher {
+  stop_sender: mpsc::Sender<()>,
+  join_handle: Option<JoinHandle<()>>,
+}
+
+impl StatisticsPublisher {
+  /// Creates the statistics topics and DataWriters, and starts publishing a
+  /// sample per entity and topic every `period`.
+  pub fn new(participant: &DomainParticipant, period: StdDuration) -> CreateResult<Self> {
+    if period.is_zero() {
+      return Err(CreateError::BadParameter {
+        reason: "Statistics publication period must be nonzero".to_string(),
+      });
+    }
+    let qos = statistics_qos();
+    let publisher = participant.create_publisher(&qos)?;
+
+    let topic = |name: &str, type_name: &str| {
+      participant.create_topic(
+        name.to_string(),
+        type_name.to_string(),
+        &qos,
+        TopicKind::WithKey,
+      )
+    };
+    let entity_data_writer = |name: &str| {
+      let t = topic(name, ENTITY_DATA_TYPE_NAME)?;
+      publisher.create_datawriter_cdr::<EntityData>(&t, None)
+    };
+    let entity_count_writer = |name: &str| {
+      let t = topic(name, ENTITY_COUNT_TYPE_NAME)?;
+      publisher.create_datawriter_cdr::<EntityCount>(&t, None)
+    };
+
+    let history_latency_topic = topic(HISTORY_LATENCY_TOPIC, WRITER_READER_DATA_TYPE_NAME)?;
+    let network_latency_topic = topic(NETWORK_LATENCY_TOPIC, LOCATOR2LOCATOR_DATA_TYPE_NAME)?;
+    let rtps_lost_topic = topic(RTPS_LOST_TOPIC, ENTITY2LOCATOR_TRAFFIC_TYPE_NAME)?;
+    let writers = StatisticsWriters {
+      history_latency: publisher
+        .create_datawriter_cdr::<WriterReaderData>(&history_latency_topic, None)?,
+      network_latency: publisher
+        .create_datawriter_cdr::<Locator2LocatorData>(&network_latency_topic, None)?,
+      publication_throughput: entity_data_writer(PUBLICATION_THROUGHPUT_TOPIC)?,
+      subscription_throughput: entity_data_writer(SUBSCRIPTION_THROUGHPUT_TOPIC)?,
+      heartbeat_count: entity_count_writer(HEARTBEAT_COUNT_TOPIC)?,
+      acknack_count: entity_count_writer(ACKNACK_COUNT_TOPIC)?,
+      gap_count: entity_count_writer(GAP_COUNT_TOPIC)?,
+      data_count: entity_count_writer(DATA_COUNT_TOPIC)?,
+      rtps_lost: publisher
+        .create_datawriter_cdr::<Entity2LocatorTraffic>(&rtps_lost_topic, None)?,
+    };
+
+    let statistics = participant.protocol_statistics()?;
+    let (stop_sender, stop_receiver) = mpsc::channel();
+
+    let join_handle = thread::Builder::new()
+      .name("RustDDS statistics publisher".to_string())
+      .spawn(move || {
+        let own_guids = writers.own_guids();
+        let mut previous = StatisticsSnapshot::default();
+        let mut previous_instant = Instant::now();
+        // Stop when requested, or when the StatisticsPublisher is gone.
+        while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(period) {
+          let mut snapshot = statistics.snapshot();
+          snapshot
+            .counters
+            .retain(|guid, _| !own_guids.contains(guid));
+          let now = Instant::now();
+          publish_snapshot(
+            &writers,
+            &snapshot,
+            &previous,
+            now.duration_since(previous_instant),
+          );
+          previous = snapshot;
+          previous_instant = now;
+        }
+        debug!("Statistics publisher thread stopped.");
+      })
+      .map_err(|e| CreateError::OutOfResources {
+        reason: format!("Cannot start statistics publisher thread: {e:?}"),
+      })?;
+
+    Ok(Self {
+      stop_sender,
+      join_handle: Some(join_handle),
+    })
+  }
+}
+
+impl Drop for StatisticsPublisher {
+  fn drop(&mut self) {
+    let _ = self.stop_sender.send(());
+    if let Some(handle) = self.join_handle.take() {
+      handle
+        .join()
+        .unwrap_or_else(|e| error!("Statistics publisher thread panicked: {e:?}"));
+    }
+  }
+}
+
+fn publish_snapshot(
+  writers: &StatisticsWriters,
+  snapshot: &StatisticsSnapshot,
+  previous: &StatisticsSnapshot,
+  elapsed: StdDuration,
+) {
+  let seconds = elapsed.as_secs_f64();
+  for (guid, counters) in &snapshot.counters {
+    let before = previous.counters.get(guid).cloned().unwrap_or_default();
+    if *counters == before {
+      continue; // nothing new to report
+    }
+    let guid_s = GUID_s::from(*guid);
+    let throughput = |now: u64, then: u64| ((now - then) as f64 / seconds) as f32;
+
+    if guid.entity_id.entity_kind.is_writer() {
+      write_sample(
+        &writers.publication_throughput,
+        EntityData {
+          guid: guid_s,
+          data: throughput(counters.bytes_sent, before.bytes_sent),
+        },
+      );
+      write_sample(
+        &writers.heartbeat_count,
+        EntityCount {
+          guid: guid_s,
+          count: counters.heartbeat_count,
+        },
+      );
+      write_sample(
+        &writers.gap_count,
+        EntityCount {
+          guid: guid_s,
+          count: counters.gap_count,
+        },
+      );
+      write_sample(
+        &writers.data_count,
+        EntityCount {
+          guid: guid_s,
+          count: counters.data_count,
+        },
+      );
+    } else {
+      write_sample(
+        &writers.subscription_throughput,
+        EntityData {
+          guid: guid_s,
+          data: throughput(counters.bytes_received, before.bytes_received),
+        },
+      );
+      write_sample(
+        &writers.acknack_count,
+        EntityCount {
+          guid: guid_s,
+          count: counters.acknack_count,
+        },
+      );
+      if counters.samples_lost != before.samples_lost {
+        write_sample(
+          &writers.rtps_lost,
+          Entity2LocatorTraffic {
+            src_guid: guid_s,
+            dst_locator: Locator::Invalid.into(),
+            packet_count: counters.samples_lost,
+            byte_count: 0,
+            byte_magnitude_order: 0,
+          },
+        );
+      }
+    }
+  }
+
+  for (reader_locator, latency) in &snapshot.network_latencies {
+    write_sample(
+      &writers.network_latency,
+      Locator2LocatorData {
+        src_locator: Locator::Invalid.into(),
+        dst_locator: (*reader_locator).into(),
+        data: *latency as f32,
+      },
+    );
+  }
+
+  for ((writer, reader), latency) in &snapshot.latencies {
+    write_sample(
+      &writers.history_latency,
+      WriterReaderData {
+        writer_guid: GUID_s::from(*writer),
+        reader_guid: GUID_s::from(*reader),
+        data: *latency as f32,
+      },
+    );
+  }
+}
+
+fn write_sample<D>(writer: &DataWriterCdr<D>, sample: D)
+where
+  D: Keyed + Serialize + std::fmt::Debug,
+  <D as Keyed>::K: Key,
+{
+  writer
+    .write(sample, None)
+    .unwrap_or_else(|e| warn!("Cannot publish statistics sample: {e:?}"));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+  use cdr_encoding::to_vec;
+  use byteorder::LittleEndian;
+
+  use super::*;
+  use crate::structure::guid::{EntityId, EntityKind, GuidPrefix};
+
+  fn test_guid() -> GUID {
+    GUID::new(
+      GuidPrefix::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+      EntityId::new([0x13, 0x14, 0x15], EntityKind::WRITER_WITH_KEY_USER_DEFINED),
+    )
+  }
+
+  fn test_guid_bytes() -> Vec<u8> {
+    vec![
+      1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 0x13, 0x14, 0x15, 0x02,
+    ]
+  }
+
+  #[test]
+  fn entity_data_cdr() {
+    let sample = EntityData {
+      guid: test_guid().into(),
+      data: 1.5,
+    };
+    let mut expected = test_guid_bytes();
+    expected.extend_from_slice(&1.5f32.to_le_bytes());
+    assert_eq!(to_vec::<_, LittleEndian>(&sample).unwrap(), expected);
+    assert_eq!(expected.len(), 20);
+  }
+
+  #[test]
+  fn writer_reader_data_cdr() {
+    let reader = GUID::new(
+      GuidPrefix::new(&[0xA0; 12]),
+      EntityId::new([0, 0, 1], EntityKind::READER_WITH_KEY_USER_DEFINED),
+    );
+    let sample = WriterReaderData {
+      writer_guid: test_guid().into(),
+      reader_guid: reader.into(),
+      data: 250.0,
+    };
+    let mut expected = test_guid_bytes();
+    expected.extend_from_slice(&[0xA0; 12]);
+    expected.extend_from_slice(&[0, 0, 1, 0x07]);
+    expected.extend_from_slice(&250.0f32.to_le_bytes());
+    assert_eq!(to_vec::<_, LittleEndian>(&sample).unwrap(), expected);
+    assert_eq!(expected.len(), 36);
+  }
+
+  #[test]
+  fn locator2locator_data_cdr() {
+    let src = Locator::from(SocketAddr::V4(SocketAddrV4::new(
+      Ipv4Addr::new(192, 168, 1, 2),
+      7411,
+    )));
+    let sample = Locator2LocatorData {
+      src_locator: src.into(),
+      dst_locator: Locator::Invalid.into(),
+      data: -2.0,
+    };
+    let mut expected = vec![];
+    expected.extend_from_slice(&1i32.to_le_bytes());
+    expected.extend_from_slice(&7411u32.to_le_bytes());
+    expected.extend_from_slice(&[0; 12]);
+    expected.extend_from_slice(&[192, 168, 1, 2]);
+    expected.extend_from_slice(&(-1i32).to_le_bytes());
+    expected.extend_from_slice(&0u32.to_le_bytes());
+    expected.extend_from_slice(&[0; 16]);
+    expected.extend_from_slice(&(-2.0f32).to_le_bytes());
+    assert_eq!(to_vec::<_, LittleEndian>(&sample).unwrap(), expected);
+    assert_eq!(expected.len(), 52);
+  }
+
+  #[test]
+  fn entity_count_cdr() {
+    let sample = EntityCount {
+      guid: test_guid().into(),
+      count: 0x0102_0304_0506_0708,
+    };
+    let mut expected = test_guid_bytes();
+    // unsigned long long is aligned to 8 bytes
+    expected.extend_from_slice(&0x0102_0304_0506_0708u64.to_le_bytes());
+    assert_eq!(to_vec::<_, LittleEndian>(&sample).unwrap(), expected);
+    assert_eq!(expected.len(), 24);
+  }
+
+  #[test]
+  fn entity2locator_traffic_cdr() {
+    let sample = Entity2LocatorTraffic {
+      src_guid: test_guid().into(),
+      dst_locator: Locator::Invalid.into(),
+      packet_count: 3,
+      byte_count: 0,
+      byte_magnitude_order: 0,
+    };
+    let mut expected = test_guid_bytes();
+    expected.extend_from_slice(&(-1i32).to_le_bytes());
+    expected.extend_from_slice(&0u32.to_le_bytes());
+    expected.extend_from_slice(&[0; 16]);
+    expected.extend_from_slice(&3u64.to_le_bytes());
+    expected.extend_from_slice(&0u64.to_le_bytes());
+    expected.extend_from_slice(&0i16.to_le_bytes());
+    assert_eq!(to_vec::<_, LittleEndian>(&sample).unwrap(), expected);
+    assert_eq!(expected.len(), 58);
+  }
+
+  #[test]
+  fn network_latency_from_first_acknack_per_heartbeat() {
+    let stats = ProtocolStatistics::new();
+    let writer = test_guid();
+    let reader = GUID::dummy_test_guid(EntityKind::READER_WITH_KEY_USER_DEFINED);
+    let locator = Locator::from(SocketAddr::V4(SocketAddrV4::new(
+      Ipv4Addr::new(10, 0, 0, 1),
+      7411,
+    )));
+
+    // No HEARTBEAT sent yet: nothing to measure
+    stats.acknack_received(writer, reader, locator);
+    assert!(stats.snapshot().network_latencies.is_empty());
+
+    stats.heartbeat_sent(writer);
+    stats.acknack_received(writer, reader, locator);
+    // A second ACKNACK to the same HEARTBEAT is not a round trip
+    thread::sleep(StdDuration::from_millis(20));
+    stats.acknack_received(writer, reader, locator);
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.network_latencies.len(), 1);
+    assert!(snapshot.network_latencies[&locator] < 10_000_000.0);
+  }
+
+  #[test]
+  fn removed_entity_is_forgotten() {
+    let stats = ProtocolStatistics::new();
+    let writer = test_guid();
+    let reader = GUID::dummy_test_guid(EntityKind::READER_WITH_KEY_USER_DEFINED);
+    let now = Timestamp::now();
+    stats.data_received(reader, writer, 100, Some(now), now);
+    stats.samples_lost(reader, 2);
+    stats.heartbeat_sent(writer);
+    assert_eq!(stats.snapshot().counters[&reader].samples_lost, 2);
+
+    stats.data_received(reader, writer, 100, Some(now), now);
+    stats.remove_entity(reader);
+    let snapshot = stats.snapshot();
+    assert!(!snapshot.counters.contains_key(&reader));
+    assert!(snapshot.latencies.is_empty());
+    assert!(snapshot.counters.contains_key(&writer));
+
+    stats.remove_entity(writer);
+    assert!(stats.snapshot().counters.is_empty());
+  }
+
+  #[test]
+  fn snapshot_latency_is_drained() {
+    let stats = ProtocolStatistics::new();
+    let writer = test_guid();
+    let reader = GUID::dummy_test_guid(EntityKind::READER_WITH_KEY_USER_DEFINED);
+    let now = Timestamp::now();
+    stats.data_received(reader, writer, 100, Some(now), now);
+    stats.data_sent(writer, 40);
+    stats.heartbeat_sent(writer);
+
+    let first = stats.snapshot();
+    assert_eq!(first.latencies.len(), 1);
+    assert_eq!(first.counters[&reader].bytes_received, 100);
+    assert_eq!(first.counters[&writer].bytes_sent, 40);
+    assert_eq!(first.counters[&writer].data_count, 1);
+    assert_eq!(first.counters[&writer].heartbeat_count, 1);
+
+    let second = stats.snapshot();
+    assert!(second.latencies.is_empty());
+    assert_eq!(second.counters, first.counters);
+  }
+}
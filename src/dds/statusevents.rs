@@ -321,6 +321,15 @@ pub enum DomainParticipantStatusEvent {
     participant: GuidPrefix,
     status: AuthenticationStatus,
   },
+  /// Authentication of a remote participant succeeded, failed, or its access
+  /// was withdrawn afterwards. `subject_name` is the subject of the remote's
+  /// identity certificate, if it is known. Only with the "security" feature.
+  #[cfg(feature = "security")]
+  ParticipantAuthenticationStatus {
+    participant: GuidPrefix,
+    subject_name: Option<String>,
+    status: ParticipantAuthenticationStatus,
+  },
   /// The CA has revoked the identity of some Participant.
   /// We may be currently communicating with the Participant, or it may be
   /// unknown to us.
@@ -340,6 +349,43 @@ pub enum DomainParticipantStatusEvent {
     participant: GuidPrefix,
     // TODO: How to get more details on what was revoked, or was something added?
   },
+  /// A Discovery announcement from a remote participant exceeded the
+  /// [`DiscoveryLimits`](crate::discovery::DiscoveryLimits). If it was not
+  /// `rejected`, the excess entries were dropped.
+  DiscoveryLimitExceeded {
+    participant: GuidPrefix,
+    limit: DiscoveryLimit,
+    rejected: bool,
+  },
+}
+
+/// Which of the [`DiscoveryLimits`](crate::discovery::DiscoveryLimits) was
+/// exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryLimit {
+  Locators,
+  ListEntries,
+  StringLength,
+  DataLength,
+  RemoteParticipants,
+  RemoteEndpoints,
+}
+
+/// Result of authenticating a remote participant, reported in
+/// [`DomainParticipantStatusEvent::ParticipantAuthenticationStatus`]. Only
+/// with the "security" feature.
+#[cfg(feature = "security")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParticipantAuthenticationStatus {
+  /// The handshake succeeded and the participant passed the access control
+  /// checks
+  Authenticated,
+  /// The participant could not be authenticated, or it is not allowed to join
+  /// the domain
+  Rejected { reason: String },
+  /// An authenticated participant is no longer allowed to communicate with
+  /// us, because its permissions have expired
+  Revoked,
 }
 
 /// Why some remote entity is considered to be no longer with us.
@@ -352,6 +398,12 @@ pub enum LostReason {
     lease: Duration,   // What was the discovered lease duration
     elapsed: Duration, // How much time has actually elapsed from last contact
   },
+  /// The access control permissions of the participant expired. Only with
+  /// the "security" feature.
+  PermissionsExpired,
+  /// The participant did not answer the authentication handshake, although
+  /// the handshake messages were resent. Only with the "security" feature.
+  AuthenticationFailed,
 }
 
 /// This is a rewrite/summary of SpdpDiscoveredParticipantData from discovery.
@@ -429,14 +481,11 @@ pub enum DataReaderStatus {
   // DataAvailable variant is not implemented, as it seems to bring little additional value,
   // because the normal data waiting mechanism already uses the same mio::poll structure.
   /// A sample has been lost (never received).
-  /// TODO: Implement this.
-  /// * Check that the following interpretation is correct:
   /// * For a BEST_EFFORT reader: Whenever we skip ahead in SequenceNumber,
   ///   possibly because a message is lost, or messages arrive out of order.
-  /// * For a RELIABLE reader: Whenever we skip ahead in SequenceNumbers that
-  ///   are delivered via DataReader. The reason may be that we receive a
-  ///   HEARTBEAT or GAP submessage indicating that some samples we are
-  ///   expecting are not available.
+  /// * For a RELIABLE reader: Whenever a HEARTBEAT indicates that some samples
+  ///   we are still expecting are no longer available. Samples that the writer
+  ///   reports as irrelevant with a GAP are not lost.
   SampleLost { count: CountWithChange },
 
   /// The DataReader has found a DataWriter that matches the Topic and has
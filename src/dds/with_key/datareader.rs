@@ -17,6 +17,7 @@ use crate::{
     qos::*,
     readcondition::*,
     result::ReadResult,
+    sampleinfo::SampleInfo,
     statusevents::*,
     with_key::{datasample::*, simpledatareader::*},
     ReadError,
@@ -87,6 +88,44 @@ where
       datasample_cache: dsc,
     }
   }
+
+  // Same as fill_and_lock_local_datasample_cache, but with the given decoder
+  #[allow(clippy::needless_pass_by_value)]
+  fn fill_and_lock_local_datasample_cache_with<S>(&mut self, decoder: S) -> ReadResult<()>
+  where
+    S: Decode<DA::Decoded, DA::DecodedKey> + Clone,
+  {
+    while let Some(dcc) = self.simple_data_reader.try_take_one_with(decoder.clone())? {
+      self
+        .datasample_cache
+        .fill_from_deserialized_cache_change(dcc);
+    }
+    Ok(())
+  }
+
+  /// Same as [`take`](Self::take), but decodes the samples with the given
+  /// decoder instead of the default one.
+  ///
+  /// If a sample cannot be decoded, the error is returned and the sample is
+  /// skipped. Samples decoded before it remain available for the next call.
+  pub fn take_with<S>(
+    &mut self,
+    max_samples: usize,
+    read_condition: ReadCondition,
+    decoder: S,
+  ) -> ReadResult<Vec<DataSample<D>>>
+  where
+    S: Decode<DA::Decoded, DA::DecodedKey> + Clone,
+  {
+    // Clear notification buffer. This must be done first to avoid race conditions.
+    self.simple_data_reader.drain_read_notifications();
+
+    self.fill_and_lock_local_datasample_cache_with(decoder)?;
+    let mut selected = self.datasample_cache.select_keys_for_access(read_condition);
+    selected.truncate(max_samples);
+
+    Ok(self.datasample_cache.take_by_keys(&selected))
+  }
 }
 
 impl<D: 'static, DA> DataReader<D, DA>
@@ -98,12 +137,7 @@ where
   // the serialized payload and stores the DataSamples (the actual data and the
   // samplestate) to local container, datasample_cache.
   fn fill_and_lock_local_datasample_cache(&mut self) -> ReadResult<()> {
-    while let Some(dcc) = self.simple_data_reader.try_take_one()? {
-      self
-        .datasample_cache
-        .fill_from_deserialized_cache_change(dcc);
-    }
-    Ok(())
+    self.fill_and_lock_local_datasample_cache_with(DA::DECODER)
   }
 
   fn drain_read_notifications(&self) {
@@ -710,6 +744,49 @@ where
     Ok(result)
   }
 
+  /// Returns a copy of the newest alive sample of an instance, or None if
+  /// the instance is unknown or has been disposed since.
+  ///
+  /// "Newest" follows the [`DestinationOrder`](crate::policy::DestinationOrder)
+  /// policy. This does not mark any samples as read or instances as viewed,
+  /// so it can be mixed freely with read() and take().
+  ///
+  /// The sample is returned even if it has been taken, or dropped from the
+  /// cache by the History policy, but only if that happened after the first
+  /// call to latest() or latest_all() on this DataReader. Before the first
+  /// call, no copies of the samples are retained.
+  pub fn latest(&mut self, instance_key: &D::K) -> ReadResult<Option<(D, SampleInfo)>>
+  where
+    D: Clone,
+  {
+    self.fill_and_lock_local_datasample_cache()?;
+    self.datasample_cache.keep_latest_copies(D::clone);
+
+    Ok(
+      self
+        .datasample_cache
+        .latest(instance_key)
+        .map(|(value, sample_info)| (value.clone(), sample_info)),
+    )
+  }
+
+  /// Like [`latest`](Self::latest), but for every instance with an alive
+  /// sample. The samples are in instance key order.
+  pub fn latest_all(&mut self) -> ReadResult<impl Iterator<Item = (D, SampleInfo)>>
+  where
+    D: Clone,
+  {
+    self.fill_and_lock_local_datasample_cache()?;
+    self.datasample_cache.keep_latest_copies(D::clone);
+
+    let latest: Vec<_> = self
+      .datasample_cache
+      .latest_all()
+      .map(|(value, sample_info)| (value.clone(), sample_info))
+      .collect();
+    Ok(latest.into_iter())
+  }
+
   /// Return values:
   /// true - got all historical data
   /// false - timeout before all historical data was received
@@ -730,6 +807,35 @@ where
     vec![].into_iter()
   }
 
+  /// The QoS contract with a matched DataWriter: for each policy, the value
+  /// requested by this DataReader, the value offered by the DataWriter, and
+  /// the value in effect. Useful for finding out why the behavior differs
+  /// from what one side expects.
+  ///
+  /// Returns `None` if the DataWriter is not known from Discovery, writes
+  /// another Topic, or offers incompatible QoS.
+  pub fn matched_contract(&self, writer: GUID) -> Option<MatchedContract> {
+    self.simple_data_reader.matched_contract(writer)
+  }
+
+  /// Number of samples dropped so far, because the
+  /// [`StaleSampleFilter`](crate::policy::StaleSampleFilter) policy found them
+  /// too old compared to the newest sample of the same instance.
+  pub fn stale_sample_count(&self) -> u64 {
+    self.datasample_cache.stale_sample_count()
+  }
+
+  /// Checks the internal consistency of the cache of samples in this
+  /// DataReader, and returns a description of each violation found. An empty
+  /// result means the cache is consistent.
+  ///
+  /// This is meant for debugging and support. With the feature
+  /// "verify_invariants", the check is also done after every change to the
+  /// cache, and a violation panics.
+  pub fn verify_invariants(&self) -> Vec<String> {
+    self.datasample_cache.verify_invariants()
+  }
+
   /// An async stream for reading the (bare) data samples.
   /// The resulting Stream can be used to get another stream of status events.
   pub fn async_sample_stream(self) -> DataReaderStream<D, DA> {
@@ -14,7 +14,10 @@ use crate::{
     sampleinfo::*,
     with_key::datasample::{DataSample, DeserializedCacheChange, Sample},
   },
-  structure::{guid::GUID, sequence_number::SequenceNumber, time::Timestamp},
+  structure::{
+    cache_change::ChangeKind, clock::ClockJumpDetector, duration::Duration, guid::GUID, invariants,
+    sequence_number::SequenceNumber, time::Timestamp,
+  },
   with_key::WriteOptions,
 };
 
@@ -32,6 +35,16 @@ pub struct DataSampleCache<D: Keyed> {
   datasamples: BTreeMap<Timestamp, SampleWithMetaData<D>>, /* ordered storage for deserialized
                                                             * samples */
   pub(crate) instance_map: BTreeMap<D::K, InstanceMetaData>, // ordered storage for instances
+  stale_sample_count: u64, // samples dropped by the StaleSampleFilter policy
+  // Present if the ClockSkewTolerance policy asks to rebaseline after clock jumps
+  clock_jump_detector: Option<ClockJumpDetector>,
+
+  // Copies of latest alive samples that have been taken or dropped from
+  // datasamples, so that latest() can still return them
+  retained_latest: BTreeMap<D::K, SampleWithMetaData<D>>,
+  // Makes the copies for retained_latest. Copies are made only after this has
+  // been set by the first call to latest(), as D may not be Clone.
+  copy_latest: Option<fn(&D) -> D>,
 }
 
 pub(crate) struct InstanceMetaData {
@@ -39,6 +52,13 @@ pub(crate) struct InstanceMetaData {
   instance_state: InstanceState,         // latest known alive/not_alive state for this instance
   latest_generation_available: NotAliveGenerationCounts, // in this instance
   last_generation_accessed: NotAliveGenerationCounts, // in this instance
+  latest_source_timestamp: Option<Timestamp>, // newest source timestamp delivered in this instance
+  // Writers that have written and not unregistered this instance
+  registered_writers: BTreeSet<GUID>,
+  // The newest alive sample in this instance, in destination order. The sample
+  // is in datasamples, or in retained_latest if it has left datasamples. None
+  // if the instance has been disposed after it.
+  latest_alive: Option<Timestamp>,
 }
 
 struct SampleWithMetaData<D: Keyed> {
@@ -71,10 +91,124 @@ where
   D: Keyed,
 {
   pub fn new(qos: QosPolicies) -> Self {
+    let clock_jump_detector = qos
+      .clock_skew_tolerance()
+      .filter(|tolerance| tolerance.rebaseline_after_clock_jump)
+      .map(|_| ClockJumpDetector::new());
     Self {
       qos,
       datasamples: BTreeMap::new(),
       instance_map: BTreeMap::new(),
+      stale_sample_count: 0,
+      clock_jump_detector,
+      retained_latest: BTreeMap::new(),
+      copy_latest: None,
+    }
+  }
+
+  pub fn stale_sample_count(&self) -> u64 {
+    self.stale_sample_count
+  }
+
+  // Checks the sample against the StaleSampleFilter policy, if there is one.
+  fn is_stale(&self, instance_key: &D::K, write_options: &WriteOptions) -> bool {
+    let (Some(filter), Some(source_timestamp)) = (
+      self.qos.stale_sample_filter(),
+      write_options.source_timestamp(),
+    ) else {
+      return false;
+    };
+    if source_timestamp == Timestamp::INVALID {
+      return false;
+    }
+    self
+      .instance_map
+      .get(instance_key)
+      .and_then(|imd| imd.latest_source_timestamp)
+      .is_some_and(|latest| source_timestamp + filter.max_lag < latest)
+  }
+
+  // Tells if a new sample of the instance supersedes the samples delivered
+  // before it. With the BY_SOURCE_TIMESTAMP destination order, an older source
+  // timestamp does not. Otherwise the newest received sample is the latest.
+  fn supersedes_latest(&self, instance_key: &D::K, write_options: &WriteOptions) -> bool {
+    if self.qos.destination_order() != Some(policy::DestinationOrder::BySourceTimeStamp) {
+      return true;
+    }
+    let latest_source_timestamp = self
+      .instance_map
+      .get(instance_key)
+      .and_then(|imd| imd.latest_source_timestamp);
+    let max_skew = self
+      .qos
+      .clock_skew_tolerance()
+      .map_or(Duration::ZERO, |tolerance| tolerance.max_skew);
+    match (write_options.source_timestamp(), latest_source_timestamp) {
+      (Some(new), Some(latest)) if new != Timestamp::INVALID => new + max_skew >= latest,
+      _ => true,
+    }
+  }
+
+  // The newest source timestamps of the instances are from before a step of the
+  // wall clock. If the samples come from this host, they would make the samples
+  // stamped after a backwards step look stale, so forget them.
+  fn rebaseline_after_clock_jump(&mut self) {
+    let Some(detector) = &mut self.clock_jump_detector else {
+      return;
+    };
+    if let Some(step) = detector.check() {
+      info!(
+        "Clock jumped by {:?}. Forgetting the newest source timestamps of instances.",
+        step
+      );
+      for imd in self.instance_map.values_mut() {
+        imd.latest_source_timestamp = None;
+      }
+    }
+  }
+
+  fn latest_alive_sample(
+    &self,
+    instance_key: &D::K,
+  ) -> Option<(&InstanceMetaData, &SampleWithMetaData<D>)> {
+    let imd = self.instance_map.get(instance_key)?;
+    let timestamp = imd.latest_alive?;
+    self
+      .datasamples
+      .get(&timestamp)
+      .or_else(|| self.retained_latest.get(instance_key))
+      .map(|dswm| (imd, dswm))
+  }
+
+  // To be called when a sample leaves datasamples. If it is the latest alive
+  // sample of its instance, a copy of it is retained.
+  fn retain_if_latest(
+    &mut self,
+    instance_key: &D::K,
+    timestamp: Timestamp,
+    dswm: &SampleWithMetaData<D>,
+  ) {
+    let Some(imd) = self.instance_map.get_mut(instance_key) else {
+      return;
+    };
+    if imd.latest_alive != Some(timestamp) {
+      return;
+    }
+    match (self.copy_latest, &dswm.sample) {
+      (Some(copy), Sample::Value(value)) => {
+        self.retained_latest.insert(
+          instance_key.clone(),
+          SampleWithMetaData {
+            generation_counts: dswm.generation_counts,
+            writer_guid: dswm.writer_guid,
+            sequence_number: dswm.sequence_number,
+            write_options: dswm.write_options.clone(),
+            sample_has_been_read: dswm.sample_has_been_read,
+            sample: Sample::Value(copy(value)),
+          },
+        );
+      }
+      _ => imd.latest_alive = None,
     }
   }
 
@@ -86,6 +220,7 @@ where
 
     self.add_sample(
       deserialized_cc.sample,
+      deserialized_cc.change_kind,
       deserialized_cc.writer_guid,
       deserialized_cc.sequence_number,
       deserialized_cc.receive_instant,
@@ -96,6 +231,7 @@ where
   fn add_sample(
     &mut self,
     new_sample: Sample<D, D::K>,
+    change_kind: ChangeKind,
     writer_guid: GUID,
     sequence_number: SequenceNumber,
     receive_timestamp: Timestamp,
@@ -106,22 +242,37 @@ where
       Sample::Dispose(k) => k.clone(),
     };
 
-    let new_instance_state = match new_sample {
-      Sample::Value(_) => InstanceState::Alive,
-      Sample::Dispose(_) => InstanceState::NotAliveDisposed,
-    };
+    self.rebaseline_after_clock_jump();
+    if self.is_stale(&instance_key, &write_options) {
+      debug!(
+        "Dropping stale sample {:?} from {:?}, source timestamp {:?}",
+        sequence_number,
+        writer_guid,
+        write_options.source_timestamp()
+      );
+      self.stale_sample_count += 1;
+      return;
+    }
+
+    let supersedes_latest = self.supersedes_latest(&instance_key, &write_options);
+    let instance_keep_count = self.instance_keep_count();
 
     // find or create metadata record
     let instance_metadata = if let Some(imd) = self.instance_map.get_mut(&instance_key) {
       imd
     } else {
       // not found, create new one.
+      // A dispose or unregister may be the first thing received about an
+      // instance. Its state is set below.
       let imd = InstanceMetaData {
         instance_samples: BTreeSet::new(),
-        instance_state: new_instance_state,
+        instance_state: InstanceState::Alive,
         latest_generation_available: NotAliveGenerationCounts::zero(), /* this is new instance,
                                                                         * so start from zero */
         last_generation_accessed: NotAliveGenerationCounts::sub_zero(), // never accessed
+        latest_source_timestamp: None,
+        registered_writers: BTreeSet::new(),
+        latest_alive: None,
       };
       self.instance_map.insert(instance_key.clone(), imd);
       self
@@ -131,8 +282,55 @@ where
         .unwrap()
     };
 
+    // Writing registers the writer to the instance, and unregistering removes
+    // the registration. Dispose wins over unregister, and an instance has no
+    // writers only after the last registered writer has unregistered it.
+    // DDS Spec v1.4 Section "2.2.2.5.1.3 Interpretation of the SampleInfo"
+    let old_instance_state = instance_metadata.instance_state;
+    let new_instance_state = match (&new_sample, change_kind) {
+      (Sample::Value(_), _) => {
+        instance_metadata.registered_writers.insert(writer_guid);
+        InstanceState::Alive
+      }
+      (Sample::Dispose(_), ChangeKind::NotAliveUnregistered) => {
+        instance_metadata.registered_writers.remove(&writer_guid);
+        if old_instance_state == InstanceState::Alive
+          && instance_metadata.registered_writers.is_empty()
+        {
+          InstanceState::NotAliveNoWriters
+        } else {
+          old_instance_state
+        }
+      }
+      (Sample::Dispose(_), _) => {
+        if change_kind.is_unregistered() {
+          instance_metadata.registered_writers.remove(&writer_guid);
+        }
+        InstanceState::NotAliveDisposed
+      }
+    };
+    if matches!(new_sample, Sample::Dispose(_))
+      && !change_kind.is_disposed()
+      && new_instance_state == old_instance_state
+    {
+      // Other writers still have the instance registered, or it was not alive
+      // anyway. There is nothing to tell the application.
+      debug!(
+        "Unregister {:?} from {:?} does not change instance state {:?}",
+        sequence_number, writer_guid, old_instance_state
+      );
+      return;
+    }
+
     // update instance metadata
     instance_metadata.instance_samples.insert(receive_timestamp);
+    if let Some(source_timestamp) = write_options.source_timestamp() {
+      if source_timestamp != Timestamp::INVALID {
+        instance_metadata.latest_source_timestamp = instance_metadata
+          .latest_source_timestamp
+          .max(Some(source_timestamp));
+      }
+    }
 
     match (instance_metadata.instance_state, new_instance_state) {
       (InstanceState::Alive, _) => (), // was Alive, does not change counts
@@ -159,6 +357,14 @@ where
     }
     instance_metadata.instance_state = new_instance_state;
 
+    if supersedes_latest {
+      instance_metadata.latest_alive = match new_instance_state {
+        InstanceState::Alive => Some(receive_timestamp),
+        _ => None,
+      };
+      self.retained_latest.remove(&instance_key);
+    }
+
     // insert new_sample to main table
     self
       .datasamples
@@ -189,6 +395,36 @@ where
       );
 
     // garbage collect
+    if let Some(instance_keep_count) = instance_keep_count {
+      let remove_count = instance_metadata.instance_samples.len() as i32 - instance_keep_count;
+      if remove_count > 0 {
+        let keys_to_remove: Vec<_> = instance_metadata
+          .instance_samples
+          .iter()
+          .take(remove_count as usize)
+          .copied()
+          .collect();
+        let mut removed = Vec::with_capacity(keys_to_remove.len());
+        for k in keys_to_remove {
+          instance_metadata.instance_samples.remove(&k);
+          if let Some(dswm) = self.datasamples.remove(&k) {
+            removed.push((k, dswm));
+          }
+        }
+        for (k, dswm) in removed {
+          self.retain_if_latest(&instance_key, k, &dswm);
+        }
+      }
+    }
+
+    // TODO: Implement other resource_limit settings than max_instances_per
+    // sample, i.e.
+
+    self.check_invariants();
+  }
+
+  // How many samples are kept per instance, if limited
+  fn instance_keep_count(&self) -> Option<i32> {
     let sample_keep_history_limit: Option<i32> = match self.qos.history() {
       Some(policy::History::KeepAll) => None, // no limit
       Some(policy::History::KeepLast { depth }) => Some(depth),
@@ -204,25 +440,104 @@ where
     } else {
       None
     };
+    sample_keep_history_limit.or(sample_keep_resource_limit)
+  }
 
-    if let Some(instance_keep_count) = sample_keep_history_limit.or(sample_keep_resource_limit) {
-      let remove_count = instance_metadata.instance_samples.len() as i32 - instance_keep_count;
-      if remove_count > 0 {
-        let keys_to_remove: Vec<_> = instance_metadata
-          .instance_samples
-          .iter()
-          .take(remove_count as usize)
-          .copied()
-          .collect();
-        for k in keys_to_remove {
-          instance_metadata.instance_samples.remove(&k);
-          self.datasamples.remove(&k);
+  /// Checks that the instance index, the sample store and the instance states
+  /// agree with each other. Returns a description of each violation found.
+  ///
+  /// The invariants are:
+  /// * Every sample is indexed under exactly the instance of its key.
+  /// * An instance has no more samples than the History (or ResourceLimits)
+  ///   policy allows.
+  /// * The generation counts of samples and of the last access of an instance
+  ///   do not exceed the latest generation counts of the instance.
+  /// * The latest alive sample of an instance is stored, either among the
+  ///   samples or as a retained copy, and copies are retained only for it.
+  pub fn verify_invariants(&self) -> Vec<String> {
+    let mut violations = Vec::new();
+    let not_after = |a: NotAliveGenerationCounts, b: NotAliveGenerationCounts| {
+      a.disposed_generation_count <= b.disposed_generation_count
+        && a.no_writers_generation_count <= b.no_writers_generation_count
+    };
+    let instance_keep_count = self.instance_keep_count();
+
+    let mut indexed_sample_count = 0;
+    for (instance_key, imd) in &self.instance_map {
+      indexed_sample_count += imd.instance_samples.len();
+      for timestamp in &imd.instance_samples {
+        match self.datasamples.get(timestamp) {
+          None => violations.push(format!(
+            "Sample {timestamp:?} is in the instance index, but not in the sample store"
+          )),
+          Some(dswm) => {
+            if dswm.key() != *instance_key {
+              violations.push(format!(
+                "Sample {timestamp:?} is indexed under another instance than that of its key"
+              ));
+            }
+            if !not_after(dswm.generation_counts, imd.latest_generation_available) {
+              violations.push(format!(
+                "Sample {timestamp:?} has generation counts {:?}, which are ahead of its instance \
+                 {:?}",
+                dswm.generation_counts, imd.latest_generation_available
+              ));
+            }
+          }
+        }
+      }
+      if let Some(keep_count) = instance_keep_count {
+        if imd.instance_samples.len() > keep_count.max(0) as usize {
+          violations.push(format!(
+            "An instance has {} samples, but at most {keep_count} should be kept",
+            imd.instance_samples.len()
+          ));
+        }
+      }
+      if !not_after(
+        imd.last_generation_accessed,
+        imd.latest_generation_available,
+      ) {
+        violations.push(format!(
+          "An instance was last accessed at generation {:?}, which is ahead of its latest \
+           generation {:?}",
+          imd.last_generation_accessed, imd.latest_generation_available
+        ));
+      }
+      if let Some(latest_alive) = imd.latest_alive {
+        if !self.datasamples.contains_key(&latest_alive)
+          && !self.retained_latest.contains_key(instance_key)
+        {
+          violations.push(format!(
+            "The latest alive sample {latest_alive:?} of an instance is not stored"
+          ));
         }
       }
     }
+    if indexed_sample_count != self.datasamples.len() {
+      violations.push(format!(
+        "The instance index has {indexed_sample_count} samples, but the sample store has {}",
+        self.datasamples.len()
+      ));
+    }
+    for instance_key in self.retained_latest.keys() {
+      if self
+        .instance_map
+        .get(instance_key)
+        .and_then(|imd| imd.latest_alive)
+        .is_none()
+      {
+        violations.push(
+          "A copy of a sample is retained for an instance without a latest alive sample"
+            .to_string(),
+        );
+      }
+    }
+    violations
+  }
 
-    // TODO: Implement other resource_limit settings than max_instances_per
-    // sample, i.e.
+  fn check_invariants(&self) {
+    invariants::check_after_mutation("DataSampleCache", || self.verify_invariants());
   }
 
   // Helper for select_keys and select_instance_keys
@@ -440,6 +755,7 @@ where
 
     // mark instances viewed
     self.mark_instances_viewed(&instance_generations);
+    self.check_invariants();
 
     // We need to do SampleInfo construction and final result construction as
     // separate passes. This is because SampleInfo construction needs to mark
@@ -494,7 +810,9 @@ where
     // collect result
     for (index, (ts, key)) in keys.iter().enumerate() {
       let dswm = self.datasamples.remove(ts).unwrap();
-      let imd = self.instance_map.get(key).unwrap();
+      self.retain_if_latest(key, *ts, &dswm);
+      let imd = self.instance_map.get_mut(key).unwrap();
+      imd.instance_samples.remove(ts);
       let sample_info = Self::make_sample_info(&dswm, imd, len - index - 1, mrs_total, mrsic_total);
       // dwsm.sample_has_been_read = true; // no need to mark read, as the dswm is
       // about to be destroyed
@@ -507,6 +825,7 @@ where
     }
 
     self.mark_instances_viewed(&instance_generations);
+    self.check_invariants();
     result
   }
 
@@ -539,6 +858,7 @@ where
     }
 
     self.mark_instances_viewed(&instance_generations);
+    self.check_invariants();
 
     // We need to do SampleInfo construction and final result construction as
     // separate passes. See reason in read function above.
@@ -572,6 +892,10 @@ where
 
     for (ts, key) in keys.iter() {
       let dswm = self.datasamples.remove(ts).unwrap();
+      self.retain_if_latest(key, *ts, &dswm);
+      if let Some(imd) = self.instance_map.get_mut(key) {
+        imd.instance_samples.remove(ts);
+      }
       // dwsm.sample_has_been_read = true; // no need to mark read, as the dswm is
       // about to be destroyed
       Self::record_instance_generation_viewed(
@@ -583,9 +907,46 @@ where
     }
 
     self.mark_instances_viewed(&instance_generations);
+    self.check_invariants();
     result
   }
 
+  // Enables retaining copies of latest alive samples
+  pub(in crate::dds::with_key) fn keep_latest_copies(&mut self, copy: fn(&D) -> D) {
+    self.copy_latest = Some(copy);
+  }
+
+  // The latest alive sample of the instance. This does not mark anything read
+  // or viewed. In the SampleInfo, the sample is the last of its instance.
+  pub(in crate::dds::with_key) fn latest(&self, instance_key: &D::K) -> Option<(&D, SampleInfo)> {
+    let (imd, dswm) = self.latest_alive_sample(instance_key)?;
+    let Sample::Value(value) = &dswm.sample else {
+      return None;
+    };
+    let mrs_total = self
+      .datasamples
+      .values()
+      .next_back()
+      .map_or(dswm.generation_counts, |newest| newest.generation_counts)
+      .total();
+    let sample_info = Self::make_sample_info(
+      dswm,
+      imd,
+      0,
+      mrs_total,
+      imd.latest_generation_available.total(),
+    );
+    Some((value, sample_info))
+  }
+
+  // The latest alive samples of all instances, in instance key order
+  pub(in crate::dds::with_key) fn latest_all(&self) -> impl Iterator<Item = (&D, SampleInfo)> {
+    self
+      .instance_map
+      .keys()
+      .filter_map(|instance_key| self.latest(instance_key))
+  }
+
   pub(in crate::dds::with_key) fn next_key(&self, key: &D::K) -> Option<D::K> {
     self
       .instance_map
@@ -606,6 +967,75 @@ pub(crate) fn result_ok_as_ref_err_clone<T, E: Clone>(r: &Sample<T, E>) -> Sampl
 
 #[cfg(test)]
 mod tests {
+  use super::*;
+  use crate::{
+    structure::clock::{self, SimulatedClock},
+    test::random_data::RandomData,
+    QosPolicyBuilder,
+  };
+
+  #[test]
+  fn dsc_drops_stale_samples() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .stale_sample_filter(policy::StaleSampleFilter {
+        max_lag: Duration::from_secs(10),
+      })
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let now = Timestamp::now();
+    let mut sn = 0;
+    let mut add = |dsc: &mut DataSampleCache<RandomData>, a, source_timestamp| {
+      sn += 1;
+      dsc.add_sample(
+        Sample::Value(RandomData {
+          a,
+          b: String::new(),
+        }),
+        ChangeKind::Alive,
+        GUID::GUID_UNKNOWN,
+        SequenceNumber::new(sn),
+        now + Duration::from_millis(sn),
+        WriteOptions::from(Some(source_timestamp)),
+      );
+    };
+
+    add(&mut datasample_cache, 1, now);
+    // within max_lag of the newest sample of instance 1
+    add(&mut datasample_cache, 1, now - Duration::from_secs(5));
+    // too old for instance 1
+    add(&mut datasample_cache, 1, now - Duration::from_secs(60));
+    // instance 2 has not delivered anything newer
+    add(&mut datasample_cache, 2, now - Duration::from_secs(60));
+
+    assert_eq!(datasample_cache.stale_sample_count(), 1);
+    assert_eq!(datasample_cache.datasamples.len(), 3);
+  }
+
+  #[test]
+  fn dsc_delivers_stale_samples_without_filter() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .build();
+    let mut datasample_cache = DataSampleCache::<RandomData>::new(qos);
+    let now = Timestamp::now();
+    for (sn, source_timestamp) in [now, now - Duration::from_secs(60)].into_iter().enumerate() {
+      datasample_cache.add_sample(
+        Sample::Value(RandomData {
+          a: 1,
+          b: String::new(),
+        }),
+        ChangeKind::Alive,
+        GUID::GUID_UNKNOWN,
+        SequenceNumber::new(sn as i64 + 1),
+        now + Duration::from_millis(sn as i64 + 1),
+        WriteOptions::from(Some(source_timestamp)),
+      );
+    }
+    assert_eq!(datasample_cache.stale_sample_count(), 0);
+    assert_eq!(datasample_cache.datasamples.len(), 2);
+  }
+
   // use super::*;
   // use crate::{
   //   structure::{time::Timestamp},
@@ -643,4 +1073,365 @@ mod tests {
     }
     */
   }
+
+  #[test]
+  fn dsc_rebaselines_stale_sample_filter_after_clock_jump() {
+    let clock = SimulatedClock::start();
+    let receive_after_backwards_step = |rebaseline_after_clock_jump| {
+      let qos = QosPolicyBuilder::new()
+        .history(policy::History::KeepAll)
+        .stale_sample_filter(policy::StaleSampleFilter {
+          max_lag: Duration::from_secs(10),
+        })
+        .clock_skew_tolerance(policy::ClockSkewTolerance {
+          max_skew: Duration::ZERO,
+          rebaseline_after_clock_jump,
+        })
+        .build();
+      let mut dsc = DataSampleCache::<RandomData>::new(qos);
+      for sn in 1..=2 {
+        dsc.add_sample(
+          Sample::Value(RandomData {
+            a: 1,
+            b: String::new(),
+          }),
+          ChangeKind::Alive,
+          GUID::GUID_UNKNOWN,
+          SequenceNumber::new(sn),
+          clock::local_timestamp(),
+          WriteOptions::from(Some(clock::wall_now())),
+        );
+        clock.advance(Duration::from_secs(1));
+        clock.step_wall_clock(Duration::from_secs(-10 * 60));
+      }
+      dsc.stale_sample_count()
+    };
+
+    assert_eq!(receive_after_backwards_step(true), 0);
+    assert_eq!(receive_after_backwards_step(false), 1);
+  }
+
+  fn add_value(dsc: &mut DataSampleCache<RandomData>, sn: i64, a: i64, b: &str, source: Timestamp) {
+    dsc.add_sample(
+      Sample::Value(RandomData {
+        a,
+        b: b.to_string(),
+      }),
+      ChangeKind::Alive,
+      GUID::GUID_UNKNOWN,
+      SequenceNumber::new(sn),
+      Timestamp::now() + Duration::from_millis(sn),
+      WriteOptions::from(Some(source)),
+    );
+  }
+
+  fn latest_b(dsc: &DataSampleCache<RandomData>, a: i64) -> Option<String> {
+    dsc.latest(&a).map(|(d, _)| d.b.clone())
+  }
+
+  #[test]
+  fn dsc_latest_interleaved_with_take() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .build();
+    let mut dsc = DataSampleCache::<RandomData>::new(qos);
+    dsc.keep_latest_copies(RandomData::clone);
+    let now = Timestamp::now();
+
+    assert_eq!(latest_b(&dsc, 1), None);
+    add_value(&mut dsc, 1, 1, "first", now);
+    add_value(&mut dsc, 2, 2, "other", now);
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("first"));
+    add_value(&mut dsc, 3, 1, "second", now);
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("second"));
+
+    // latest() does not hide anything from take()
+    let keys = dsc.select_keys_for_access(ReadCondition::any());
+    let taken = dsc.take_by_keys(&keys);
+    assert_eq!(taken.len(), 3);
+    assert!(taken
+      .iter()
+      .all(|s| s.sample_info().sample_state == SampleState::NotRead));
+
+    // the latest samples survive take()
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("second"));
+    assert_eq!(latest_b(&dsc, 2).as_deref(), Some("other"));
+    assert!(dsc.datasamples.is_empty());
+
+    add_value(&mut dsc, 4, 1, "third", now);
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("third"));
+    let keys = dsc.select_keys_for_access(ReadCondition::not_read());
+    assert_eq!(keys.len(), 1);
+    let latest: Vec<_> = dsc.latest_all().map(|(d, _)| d.b.clone()).collect();
+    assert_eq!(latest, vec!["third", "other"]);
+  }
+
+  #[test]
+  fn dsc_latest_follows_source_timestamp_order() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepLast { depth: 1 })
+      .destination_order(policy::DestinationOrder::BySourceTimeStamp)
+      .build();
+    let mut dsc = DataSampleCache::<RandomData>::new(qos);
+    dsc.keep_latest_copies(RandomData::clone);
+    let now = Timestamp::now();
+
+    add_value(&mut dsc, 1, 1, "new", now);
+    // received later, but written earlier
+    add_value(&mut dsc, 2, 1, "old", now - Duration::from_secs(1));
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("new"));
+    add_value(&mut dsc, 3, 1, "newer", now + Duration::from_secs(1));
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("newer"));
+  }
+
+  #[test]
+  fn dsc_latest_is_cleared_by_dispose() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepLast { depth: 1 })
+      .build();
+    let mut dsc = DataSampleCache::<RandomData>::new(qos);
+    dsc.keep_latest_copies(RandomData::clone);
+    let now = Timestamp::now();
+
+    add_value(&mut dsc, 1, 1, "alive", now);
+    dsc.add_sample(
+      Sample::Dispose(1),
+      ChangeKind::NotAliveDisposed,
+      GUID::GUID_UNKNOWN,
+      SequenceNumber::new(2),
+      Timestamp::now() + Duration::from_millis(2),
+      WriteOptions::from(Some(now)),
+    );
+    assert_eq!(latest_b(&dsc, 1), None);
+    add_value(&mut dsc, 3, 1, "reborn", now);
+    assert_eq!(latest_b(&dsc, 1).as_deref(), Some("reborn"));
+  }
+
+  // A cache with instances 1 and 2, with one sample each
+  fn cache_for_corruption() -> DataSampleCache<RandomData> {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepLast { depth: 1 })
+      .build();
+    let mut dsc = DataSampleCache::<RandomData>::new(qos);
+    dsc.keep_latest_copies(RandomData::clone);
+    let now = Timestamp::now();
+    add_value(&mut dsc, 1, 1, "one", now);
+    add_value(&mut dsc, 2, 2, "two", now);
+    assert_eq!(dsc.verify_invariants(), Vec::<String>::new());
+    dsc
+  }
+
+  fn assert_violation(dsc: &DataSampleCache<RandomData>, expected: &str) {
+    let violations = dsc.verify_invariants();
+    assert!(
+      violations.iter().any(|v| v.contains(expected)),
+      "expected a violation containing {expected:?}, got {violations:?}"
+    );
+  }
+
+  #[test]
+  fn dsc_verify_invariants_detects_corruption() {
+    // A sample missing from the store
+    let mut dsc = cache_for_corruption();
+    let (&timestamp, _) = dsc.datasamples.iter().next().unwrap();
+    dsc.datasamples.remove(&timestamp);
+    assert_violation(&dsc, "not in the sample store");
+    assert_violation(&dsc, "the sample store has 1");
+
+    // A sample indexed under the wrong instance
+    let mut dsc = cache_for_corruption();
+    let moved: Vec<_> = std::mem::take(&mut dsc.instance_map.get_mut(&1).unwrap().instance_samples)
+      .into_iter()
+      .collect();
+    dsc
+      .instance_map
+      .get_mut(&2)
+      .unwrap()
+      .instance_samples
+      .extend(moved);
+    assert_violation(&dsc, "indexed under another instance");
+    assert_violation(&dsc, "at most 1 should be kept");
+
+    // Generation counts of a sample ahead of its instance
+    let mut dsc = cache_for_corruption();
+    dsc
+      .datasamples
+      .values_mut()
+      .next()
+      .unwrap()
+      .generation_counts
+      .disposed_generation_count = 1;
+    assert_violation(&dsc, "ahead of its instance");
+
+    // An instance accessed at a generation it has not reached
+    let mut dsc = cache_for_corruption();
+    dsc
+      .instance_map
+      .get_mut(&1)
+      .unwrap()
+      .last_generation_accessed
+      .no_writers_generation_count = 1;
+    assert_violation(&dsc, "ahead of its latest generation");
+
+    // The latest alive sample lost
+    let mut dsc = cache_for_corruption();
+    dsc.instance_map.get_mut(&1).unwrap().latest_alive = Some(Timestamp::ZERO);
+    assert_violation(&dsc, "of an instance is not stored");
+
+    // A retained copy without a latest alive sample
+    let mut dsc = cache_for_corruption();
+    let keys = dsc.select_keys_for_access(ReadCondition::any());
+    dsc.take_by_keys(&keys);
+    assert_eq!(dsc.verify_invariants(), Vec::<String>::new());
+    dsc.instance_map.get_mut(&2).unwrap().latest_alive = None;
+    assert_violation(&dsc, "without a latest alive sample");
+  }
+
+  #[test]
+  fn dsc_take_removes_samples_from_instance_index() {
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .build();
+    let mut dsc = DataSampleCache::<RandomData>::new(qos);
+    let now = Timestamp::now();
+    for sn in 1..=3 {
+      add_value(&mut dsc, sn, 1, "value", now);
+    }
+    let keys = dsc.select_keys_for_access(ReadCondition::any());
+    dsc.take_bare_by_keys(&keys[..2]);
+    assert_eq!(dsc.instance_map[&1].instance_samples.len(), 1);
+    let keys = dsc.select_keys_for_access(ReadCondition::any());
+    dsc.take_by_keys(&keys);
+    assert!(dsc.instance_map[&1].instance_samples.is_empty());
+    assert_eq!(dsc.verify_invariants(), Vec::<String>::new());
+  }
+
+  // Adds a change of instance 1 from the writer. Returns the instance state
+  // and generation counts of the newest sample, as seen by take().
+  fn add_change(
+    dsc: &mut DataSampleCache<RandomData>,
+    writer: u8,
+    sn: i64,
+    change_kind: ChangeKind,
+  ) -> Option<(InstanceState, i32, i32)> {
+    let sample = match change_kind {
+      ChangeKind::Alive => Sample::Value(RandomData {
+        a: 1,
+        b: String::new(),
+      }),
+      _ => Sample::Dispose(1),
+    };
+    dsc.add_sample(
+      sample,
+      change_kind,
+      GUID::from_bytes([writer; 16]),
+      SequenceNumber::new(sn),
+      Timestamp::now() + Duration::from_millis(sn),
+      WriteOptions::from(None),
+    );
+    let keys = dsc.select_keys_for_access(ReadCondition::any());
+    dsc.take_by_keys(&keys).pop().map(|sample| {
+      let info = sample.sample_info();
+      (
+        info.instance_state(),
+        info.disposed_generation_count(),
+        info.no_writers_generation_count(),
+      )
+    })
+  }
+
+  #[test]
+  fn dsc_instance_state_transitions() {
+    use InstanceState::*;
+
+    let qos = QosPolicyBuilder::new()
+      .history(policy::History::KeepAll)
+      .build();
+    let mut dsc = DataSampleCache::<RandomData>::new(qos);
+    let (w1, w2) = (1, 2);
+
+    // Both writers register the instance by writing it
+    assert_eq!(
+      add_change(&mut dsc, w1, 1, ChangeKind::Alive),
+      Some((Alive, 0, 0))
+    );
+    assert_eq!(
+      add_change(&mut dsc, w2, 2, ChangeKind::Alive),
+      Some((Alive, 0, 0))
+    );
+    // Unregistering by one writer does not change anything
+    assert_eq!(
+      add_change(&mut dsc, w1, 3, ChangeKind::NotAliveUnregistered),
+      None
+    );
+    // ... but by the last one it does
+    assert_eq!(
+      add_change(&mut dsc, w2, 4, ChangeKind::NotAliveUnregistered),
+      Some((NotAliveNoWriters, 0, 0))
+    );
+    // Alive again
+    assert_eq!(
+      add_change(&mut dsc, w1, 5, ChangeKind::Alive),
+      Some((Alive, 0, 1))
+    );
+    assert_eq!(
+      add_change(&mut dsc, w1, 6, ChangeKind::NotAliveDisposed),
+      Some((NotAliveDisposed, 0, 1))
+    );
+    assert_eq!(
+      add_change(&mut dsc, w1, 7, ChangeKind::Alive),
+      Some((Alive, 1, 1))
+    );
+    // Both bits: disposed wins, and the writer is no longer registered
+    assert_eq!(
+      add_change(&mut dsc, w1, 8, ChangeKind::NotAliveDisposedUnregistered),
+      Some((NotAliveDisposed, 1, 1))
+    );
+    assert!(dsc.instance_map[&1].registered_writers.is_empty());
+    // Unregistering a disposed instance does not make it have no writers
+    assert_eq!(
+      add_change(&mut dsc, w2, 9, ChangeKind::NotAliveUnregistered),
+      None
+    );
+    assert_eq!(dsc.instance_map[&1].instance_state, NotAliveDisposed);
+    assert_eq!(
+      add_change(&mut dsc, w2, 10, ChangeKind::Alive),
+      Some((Alive, 2, 1))
+    );
+    assert_eq!(dsc.verify_invariants(), Vec::<String>::new());
+  }
+
+  #[test]
+  fn dsc_dispose_or_unregister_before_data_creates_instance() {
+    for (change_kind, instance_state) in [
+      (
+        ChangeKind::NotAliveDisposed,
+        InstanceState::NotAliveDisposed,
+      ),
+      (
+        ChangeKind::NotAliveDisposedUnregistered,
+        InstanceState::NotAliveDisposed,
+      ),
+      (
+        ChangeKind::NotAliveUnregistered,
+        InstanceState::NotAliveNoWriters,
+      ),
+    ] {
+      let mut dsc = DataSampleCache::<RandomData>::new(QosPolicies::qos_none());
+      assert_eq!(
+        add_change(&mut dsc, 1, 1, change_kind),
+        Some((instance_state, 0, 0))
+      );
+      assert_eq!(dsc.instance_map[&1].instance_state, instance_state);
+      // Born again
+      let counts = match instance_state {
+        InstanceState::NotAliveDisposed => (1, 0),
+        _ => (0, 1),
+      };
+      assert_eq!(
+        add_change(&mut dsc, 1, 2, ChangeKind::Alive),
+        Some((InstanceState::Alive, counts.0, counts.1))
+      );
+    }
+  }
 }
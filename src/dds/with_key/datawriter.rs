@@ -23,13 +23,16 @@ use crate::{
     pubsub::Publisher,
     qos::{
       policy::{Liveliness, Reliability},
-      HasQoSPolicy, QosPolicies,
+      HasQoSPolicy, MatchedContract, QosPolicies,
     },
     result::{CreateResult, WriteError, WriteResult},
     statusevents::*,
     topic::Topic,
   },
-  discovery::{discovery::DiscoveryCommand, sedp_messages::SubscriptionBuiltinTopicData},
+  discovery::{
+    discovery::DiscoveryCommand, discovery_db::discovery_db_read,
+    sedp_messages::SubscriptionBuiltinTopicData,
+  },
   messages::submessages::elements::serialized_payload::SerializedPayload,
   rtps::writer::WriterCommand,
   serialization::CDRSerializerAdapter,
@@ -37,7 +40,7 @@ use crate::{
     cache_change::ChangeKind, duration, entity::RTPSEntity, guid::GUID, rpc::SampleIdentity,
     sequence_number::SequenceNumber, time::Timestamp,
   },
-  Keyed, TopicDescription,
+  Key, Keyed, TopicDescription,
 };
 
 // TODO: Move the write options and the builder type to some lower-level module
@@ -484,6 +487,25 @@ where
     } // match
   }
 
+  /// Sends the samples that are waiting to be packed into the same RTPS
+  /// message with later ones, without waiting for the flush delay. See
+  /// [`DomainParticipantBuilder::message_flush_delay`](crate::DomainParticipantBuilder::message_flush_delay).
+  ///
+  /// The samples are sent in the background, so they may not have been sent
+  /// yet when this returns.
+  pub fn flush(&self) -> WriteResult<(), ()> {
+    let timeout = self.qos().reliable_max_blocking_time();
+    match try_send_timeout(&self.cc_upload, WriterCommand::Flush, timeout) {
+      Ok(_) => Ok(()),
+      Err(TrySendError::Full(_)) => Err(WriteError::WouldBlock { data: () }),
+      Err(TrySendError::Disconnected(_)) => Err(WriteError::Poisoned {
+        reason: "Cannot send to Writer".to_string(),
+        data: (),
+      }),
+      Err(TrySendError::Io(e)) => Err(e.into()),
+    }
+  }
+
   /*
 
   /// Unimplemented. <b>Do not use</b>.
@@ -818,6 +840,26 @@ where
     todo!()
   }
 
+  /// The QoS contract with a matched DataReader: for each policy, the value
+  /// offered by this DataWriter, the value requested by the DataReader, and
+  /// the value in effect. Useful for finding out why the behavior differs
+  /// from what one side expects.
+  ///
+  /// Returns `None` if the DataReader is not known from Discovery, reads
+  /// another Topic, or requests incompatible QoS.
+  pub fn matched_contract(&self, reader: GUID) -> Option<MatchedContract> {
+    let discovery_db = self.my_publisher.discovery_db();
+    let db = discovery_db_read(&discovery_db);
+    let reader_data = db.get_topic_reader(&reader)?;
+    if *reader_data.subscription_topic_data.topic_name() != self.my_topic.name() {
+      return None;
+    }
+    self
+      .qos_policy
+      .matched_contract(&reader_data.subscription_topic_data.qos(), true)
+      .ok()
+  }
+
   /// Disposes data instance with specified key
   ///
   /// # Arguments
@@ -870,15 +912,45 @@ where
     &self,
     key: &<D as Keyed>::K,
     source_timestamp: Option<Timestamp>,
+  ) -> WriteResult<(), ()> {
+    self.change_instance_state(key, ChangeKind::NotAliveDisposed, source_timestamp)
+  }
+
+  /// Unregisters the instance with specified key, i.e. tells that this
+  /// DataWriter no longer updates it. Unlike [`dispose`](Self::dispose), this
+  /// does not tell that the instance was deleted. A DataReader reports the
+  /// instance as `NotAliveNoWriters`, when no DataWriter has it registered.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - Key of the instance
+  /// * `source_timestamp` - DDS source timestamp (None uses now as time as
+  ///   specified in DDS spec)
+  pub fn unregister_instance(
+    &self,
+    key: &<D as Keyed>::K,
+    source_timestamp: Option<Timestamp>,
+  ) -> WriteResult<(), ()> {
+    self.change_instance_state(key, ChangeKind::NotAliveUnregistered, source_timestamp)
+  }
+
+  // Sends the key of the instance, with its key hash, marked with the change
+  // kind
+  fn change_instance_state(
+    &self,
+    key: &<D as Keyed>::K,
+    change_kind: ChangeKind,
+    source_timestamp: Option<Timestamp>,
   ) -> WriteResult<(), ()> {
     let send_buffer = SA::key_to_bytes(key).map_err(|e| WriteError::Serialization {
       reason: format!("{e}"),
       data: (),
     })?; // serialize key
 
-    let ddsdata = DDSData::new_disposed_by_key(
-      ChangeKind::NotAliveDisposed,
+    let ddsdata = DDSData::new_disposed_by_key_and_hash(
+      change_kind,
       SerializedPayload::new_from_bytes(SA::output_encoding(), send_buffer),
+      key.hash_key(false),
     );
     self
       .cc_upload
@@ -26,11 +26,12 @@ use crate::{
     topic::{Topic, TopicDescription},
     with_key::datasample::{DeserializedCacheChange, Sample},
   },
-  discovery::discovery::DiscoveryCommand,
+  discovery::{discovery::DiscoveryCommand, discovery_db::discovery_db_read},
   mio_source::PollEventSource,
   serialization::CDRDeserializerAdapter,
   structure::{
     cache_change::CacheChange,
+    clock,
     dds_cache::TopicCache,
     entity::RTPSEntity,
     guid::{EntityId, GUID},
@@ -51,6 +52,8 @@ pub(crate) struct ReadState<K: Key> {
   latest_instant: Timestamp, /* This is used as a read pointer from dds_cache for BEST_EFFORT
                               * reading */
   last_read_sn: BTreeMap<GUID, SequenceNumber>, // collection of read pointers for RELIABLE reading
+  forget_count: u64,                            /* TopicCache::forget_count, up to which
+                                                 * last_read_sn has been reset */
   /// hash_to_key_map is used for decoding received key hashes back to original
   /// key values. This is needed when we receive a dispose message via hash
   /// only.
@@ -62,10 +65,23 @@ impl<K: Key> ReadState<K> {
     ReadState {
       latest_instant: Timestamp::ZERO,
       last_read_sn: BTreeMap::new(),
+      forget_count: 0,
       hash_to_key_map: BTreeMap::<KeyHash, K>::new(),
     }
   }
 
+  // Reset the read pointers of the Writers whose sequence numbers the topic
+  // cache has forgotten, since they start numbering from the beginning again
+  fn reset_forgotten_writers(&mut self, topic_cache: &TopicCache) {
+    if topic_cache.forget_count() == self.forget_count {
+      return;
+    }
+    for writer in topic_cache.writers_forgotten_after(self.forget_count) {
+      self.last_read_sn.remove(writer);
+    }
+    self.forget_count = topic_cache.forget_count();
+  }
+
   // This is a helper function so that borrow checker understands
   // that we are splitting one mutable borrow into two _disjoint_ mutable
   // borrows.
@@ -218,7 +234,7 @@ where
     if is_reliable {
       topic_cache.get_changes_in_range_reliable(last_read_sn)
     } else {
-      topic_cache.get_changes_in_range_best_effort(latest_instant, Timestamp::now())
+      topic_cache.get_changes_in_range_best_effort(latest_instant, clock::local_timestamp())
     }
   }
 
@@ -359,6 +375,7 @@ where
     let topic_cache = self.acquire_the_topic_cache_guard();
 
     let mut read_state_ref = self.read_state.lock().unwrap();
+    read_state_ref.reset_forgotten_writers(&topic_cache);
     let latest_instant = read_state_ref.latest_instant;
     let (last_read_sn, hash_to_key_map) = read_state_ref.get_sn_map_and_hash_map();
 
@@ -414,6 +431,25 @@ where
     &self.my_topic
   }
 
+  /// The QoS contract with a matched DataWriter: for each policy, the value
+  /// requested by this reader, the value offered by the writer, and the
+  /// value in effect.
+  ///
+  /// Returns `None` if the writer is not known from Discovery, writes
+  /// another Topic, or offers incompatible QoS.
+  pub fn matched_contract(&self, writer: GUID) -> Option<MatchedContract> {
+    let discovery_db = self.my_subscriber.discovery_db();
+    let db = discovery_db_read(&discovery_db);
+    let writer_data = db.get_topic_writer(&writer)?;
+    if *writer_data.publication_topic_data.topic_name() != self.my_topic.name() {
+      return None;
+    }
+    self
+      .qos_policy
+      .matched_contract(&writer_data.publication_topic_data.qos(), false)
+      .ok()
+  }
+
   pub fn as_async_stream<S>(&self) -> SimpleDataReaderStream<D, S, DA>
   where
     DA: DefaultDecoder<D, Decoder = S>,
@@ -3,6 +3,7 @@ pub(crate) mod content_filter_property;
 #[allow(clippy::module_inception)]
 pub(crate) mod discovery;
 pub(crate) mod discovery_db;
+pub(crate) mod discovery_limits;
 
 #[cfg(feature = "security")]
 pub(crate) mod secure_discovery;
@@ -10,5 +11,6 @@ pub(crate) mod secure_discovery;
 pub(crate) mod sedp_messages;
 pub(crate) mod spdp_participant_data;
 
+pub use discovery_limits::{DiscoveryLimits, OversizedAnnouncement};
 pub use sedp_messages::*;
 pub use spdp_participant_data::*;
@@ -1,4 +1,5 @@
 use std::{
+  cell::RefCell,
   sync::{Arc, RwLock},
   time::Duration as StdDuration,
 };
@@ -20,11 +21,12 @@ use crate::{
       QosPolicies, QosPolicyBuilder,
     },
     readcondition::ReadCondition,
-    result::{CreateError, CreateResult},
+    result::{CreateError, CreateResult, ReadError, ReadResult},
     statusevents::{DomainParticipantStatusEvent, LostReason, StatusChannelSender},
   },
   discovery::{
     discovery_db::{discovery_db_read, discovery_db_write, DiscoveredVia, DiscoveryDB},
+    discovery_limits::{DiscoveryLimitDecoder, LimitViolation},
     sedp_messages::{
       DiscoveredReaderData, DiscoveredTopicData, DiscoveredWriterData, Endpoint_GUID,
       ParticipantMessageData, ParticipantMessageDataKind,
@@ -35,18 +37,20 @@ use crate::{
   rtps::constant::*,
   serialization::{pl_cdr_adapters::*, CDRDeserializerAdapter, CDRSerializerAdapter},
   structure::{
+    clock,
     duration::Duration,
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, GUID},
     time::Timestamp,
   },
-  with_key::{DataReader, DataWriter, Sample},
-  DomainParticipant,
+  with_key::{DataReader, DataSample, DataWriter, Sample},
+  DomainParticipant, Keyed,
 };
 #[cfg(feature = "security")]
 use crate::{
-  discovery::secure_discovery::SecureDiscovery,
+  discovery::{discovery_limits::TokenLimitDecoder, secure_discovery::SecureDiscovery},
   security::{security_plugins::SecurityPluginsHandle, types::*},
+  security_warn,
 };
 #[cfg(not(feature = "security"))]
 use crate::no_security::*;
@@ -95,7 +99,7 @@ pub struct LivelinessState {
 impl LivelinessState {
   pub fn new() -> Self {
     Self {
-      last_auto_update: Timestamp::now(),
+      last_auto_update: clock::local_timestamp(),
       manual_participant_liveness_refresh_requested: false,
     }
   }
@@ -257,8 +261,6 @@ impl Discovery {
   const TOPIC_CLEANUP_PERIOD: StdDuration = StdDuration::from_secs(60); // timer for cleaning up inactive topics
   const SEND_PARTICIPANT_INFO_PERIOD: StdDuration = StdDuration::from_secs(2);
   const CHECK_PARTICIPANT_MESSAGES: StdDuration = StdDuration::from_secs(1);
-  #[cfg(feature = "security")]
-  const CACHED_SECURE_DISCOVERY_MESSAGE_RESEND_PERIOD: StdDuration = StdDuration::from_secs(1);
 
   pub(crate) const PARTICIPANT_MESSAGE_QOS: QosPolicies = QosPolicies {
     durability: Some(Durability::TransientLocal),
@@ -275,6 +277,11 @@ impl Discovery {
     history: Some(History::KeepLast { depth: 1 }),
     resource_limits: None,
     lifespan: None,
+    partition: None,
+    stale_sample_filter: None,
+    clock_skew_tolerance: None,
+    #[cfg(feature = "history_spill")]
+    history_spill: None,
     #[cfg(feature = "security")]
     property: None,
   };
@@ -615,12 +622,11 @@ impl Discovery {
     );
 
     // Create a timer to periodically check whether to resend any cached security
-    // (authentication, key exchange) messages
+    // (authentication, key exchange) messages. It is started once SecureDiscovery
+    // knows the configured resend period.
     #[cfg(feature = "security")]
-    let secure_message_resend_timer = {
-      let mut secure_message_resend_timer: Timer<()> = new_simple_timer();
-      secure_message_resend_timer
-        .set_timeout(Self::CACHED_SECURE_DISCOVERY_MESSAGE_RESEND_PERIOD, ());
+    let mut secure_message_resend_timer = {
+      let secure_message_resend_timer: Timer<()> = new_simple_timer();
       try_construct!(
         poll.register(
           &secure_message_resend_timer,
@@ -640,9 +646,15 @@ impl Discovery {
     let security_opt = if let Some(plugins_handle) = security_plugins_opt {
       // Plugins is Some so security is enabled. Initialize SecureDiscovery
       let security = try_construct!(
-        SecureDiscovery::new(&domain_participant, &discovery_db, plugins_handle),
+        SecureDiscovery::new(
+          &domain_participant,
+          &discovery_db,
+          plugins_handle,
+          participant_status_sender.clone(),
+        ),
         "Could not initialize Secure Discovery. {:?}"
       );
+      secure_message_resend_timer.set_timeout(security.handshake_resend_period, ());
       Some(security)
     } else {
       None // no security configured
@@ -918,63 +930,64 @@ impl Discovery {
   }
 
   pub fn handle_participant_reader(&mut self) {
-    loop {
-      let s = self.dcps_participant.reader.take_next_sample();
-      debug!("handle_participant_reader read {:?}", &s);
-      match s {
-        Ok(Some(ds)) => {
-          #[cfg(not(feature = "security"))]
-          let permission = NormalDiscoveryPermission::Allow;
-
-          #[cfg(feature = "security")]
-          let permission = if let Some(security) = self.security_opt.as_mut() {
-            // Security is enabled. Do a secure read, potentially starting the
-            // authentication protocol. The return value tells if normal Discovery is
-            // allowed to process the message.
-            security.participant_read(
-              &ds,
-              &self.discovery_db,
-              &self.discovery_updated_sender,
-              &self.dcps_participant_stateless_message.writer,
-            )
-          } else {
-            // No security configured, always allowed
-            NormalDiscoveryPermission::Allow
-          };
-
-          if permission == NormalDiscoveryPermission::Allow {
-            match ds.value {
-              Sample::Value(participant_data) => {
-                debug!(
-                  "handle_participant_reader discovered {:?}",
-                  &participant_data
-                );
-                self.process_discovered_participant_data(&participant_data);
-              }
-              // Sample::Dispose means that DomainParticipant was disposed
-              Sample::Dispose(participant_guid) => {
-                self.process_participant_dispose(participant_guid.0.prefix);
-              }
-            }
+    let samples = match take_within_limits(
+      &mut self.dcps_participant.reader,
+      &self.discovery_db,
+      self.domain_participant.guid().prefix,
+    ) {
+      Ok(samples) => samples,
+      Err(e) => {
+        error!(" !!! handle_participant_reader: {e:?}");
+        return;
+      }
+    };
+    for ds in samples {
+      debug!("handle_participant_reader read {:?}", &ds);
+      #[cfg(not(feature = "security"))]
+      let permission = NormalDiscoveryPermission::Allow;
+
+      #[cfg(feature = "security")]
+      let permission = if let Some(security) = self.security_opt.as_mut() {
+        // Security is enabled. Do a secure read, potentially starting the
+        // authentication protocol. The return value tells if normal Discovery is
+        // allowed to process the message.
+        security.participant_read(
+          &ds,
+          &self.discovery_db,
+          &self.discovery_updated_sender,
+          &self.dcps_participant_stateless_message.writer,
+        )
+      } else {
+        // No security configured, always allowed
+        NormalDiscoveryPermission::Allow
+      };
+
+      if permission == NormalDiscoveryPermission::Allow {
+        match ds.value {
+          Sample::Value(participant_data) => {
+            debug!(
+              "handle_participant_reader discovered {:?}",
+              &participant_data
+            );
+            self.process_discovered_participant_data(&participant_data);
+          }
+          // Sample::Dispose means that DomainParticipant was disposed
+          Sample::Dispose(participant_guid) => {
+            self.process_participant_dispose(participant_guid.0.prefix);
           }
-        }
-        Ok(None) => {
-          trace!("handle_participant_reader: no more data");
-          return;
-        } // no more data
-        Err(e) => {
-          error!(" !!! handle_participant_reader: {e:?}");
-          return;
         }
       }
-    } // loop
+    }
   }
 
   fn process_discovered_participant_data(
     &mut self,
     participant_data: &SpdpDiscoveredParticipantData,
   ) {
-    let was_new = discovery_db_write(&self.discovery_db).update_participant(participant_data);
+    let Some(was_new) = discovery_db_write(&self.discovery_db).update_participant(participant_data)
+    else {
+      return; // rejected due to the Discovery limits
+    };
     let guid_prefix = participant_data.participant_guid.prefix;
     self.send_discovery_notification(DiscoveryNotificationType::ParticipantUpdated { guid_prefix });
     if was_new {
@@ -1042,6 +1055,7 @@ impl Discovery {
     for writer in db.get_all_local_topic_writers() {
       self.send_endpoint_dispose_message(writer.writer_proxy.remote_writer_guid);
     }
+    drop(db);
 
     self
       .dcps_participant
@@ -1054,15 +1068,28 @@ impl Discovery {
       .writer
       .dispose(&Participant_GUID(self.domain_participant.guid()), None)
       .unwrap_or(());
+    // The goodbye goes last, since the remotes drop our keys when they get it
+    #[cfg(feature = "security")]
+    if let Some(security) = self.security_opt.as_mut() {
+      security.send_goodbye_messages(
+        &self.discovery_db,
+        &self.dcps_participant_volatile_message_secure.writer,
+      );
+    }
   }
 
   // Check if there are messages about new Readers
   pub fn handle_subscription_reader(&mut self, read_history: Option<GuidPrefix>) {
-    let drds: Vec<Sample<DiscoveredReaderData, GUID>> =
-      match self.dcps_subscription.reader.into_iterator() {
-        Ok(ds) => ds
-          .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
-          .filter(|d|
+    let drds: Vec<Sample<DiscoveredReaderData, GUID>> = match take_within_limits(
+      &mut self.dcps_subscription.reader,
+      &self.discovery_db,
+      self.domain_participant.guid().prefix,
+    ) {
+      Ok(ds) => ds
+        .into_iter()
+        .map(DataSample::into_value)
+        .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
+        .filter(|d|
               // If a participant was specified, we must match its GUID prefix.
               match (read_history, d) {
                 (None, _) => true, // Not asked to filter by participant
@@ -1071,12 +1098,12 @@ impl Discovery {
                 (Some(participant_to_update), Sample::Dispose(guid)) =>
                   guid.prefix == participant_to_update,
               })
-          .collect(),
-        Err(e) => {
-          error!("handle_subscription_reader: {e:?}");
-          return;
-        }
-      };
+        .collect(),
+      Err(e) => {
+        error!("handle_subscription_reader: {e:?}");
+        return;
+      }
+    };
 
     for d in drds {
       #[cfg(not(feature = "security"))]
@@ -1094,7 +1121,9 @@ impl Discovery {
       if permission == NormalDiscoveryPermission::Allow {
         match d {
           Sample::Value(d) => {
-            let drd = discovery_db_write(&self.discovery_db).update_subscription(&d);
+            let Some(drd) = discovery_db_write(&self.discovery_db).update_subscription(&d) else {
+              continue; // rejected due to the Discovery limits
+            };
             debug!(
               "handle_subscription_reader - send_discovery_notification ReaderUpdated  {:?}",
               &drd
@@ -1127,29 +1156,34 @@ impl Discovery {
   }
 
   pub fn handle_publication_reader(&mut self, read_history: Option<GuidPrefix>) {
-    let dwds: Vec<Sample<DiscoveredWriterData, GUID>> =
-      match self.dcps_publication.reader.into_iterator() {
-        // a lot of cloning here, but we must copy the data out of the
-        // reader before we can use self again, as .read() returns references to within
-        // a reader and thus self
-        Ok(ds) => ds
-          .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
-          // If a participant was specified, we must match its GUID prefix.
-          .filter(|d| match (read_history, d) {
-            (None, _) => true, // Not asked to filter by participant
-            (Some(participant_to_update), Sample::Value(dwd)) => {
-              dwd.writer_proxy.remote_writer_guid.prefix == participant_to_update
-            }
-            (Some(participant_to_update), Sample::Dispose(guid)) => {
-              guid.prefix == participant_to_update
-            }
-          })
-          .collect(),
-        Err(e) => {
-          error!("handle_publication_reader: {e:?}");
-          return;
-        }
-      };
+    let dwds: Vec<Sample<DiscoveredWriterData, GUID>> = match take_within_limits(
+      &mut self.dcps_publication.reader,
+      &self.discovery_db,
+      self.domain_participant.guid().prefix,
+    ) {
+      // a lot of cloning here, but we must copy the data out of the
+      // reader before we can use self again, as .read() returns references to within
+      // a reader and thus self
+      Ok(ds) => ds
+        .into_iter()
+        .map(DataSample::into_value)
+        .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
+        // If a participant was specified, we must match its GUID prefix.
+        .filter(|d| match (read_history, d) {
+          (None, _) => true, // Not asked to filter by participant
+          (Some(participant_to_update), Sample::Value(dwd)) => {
+            dwd.writer_proxy.remote_writer_guid.prefix == participant_to_update
+          }
+          (Some(participant_to_update), Sample::Dispose(guid)) => {
+            guid.prefix == participant_to_update
+          }
+        })
+        .collect(),
+      Err(e) => {
+        error!("handle_publication_reader: {e:?}");
+        return;
+      }
+    };
 
     for d in dwds {
       #[cfg(not(feature = "security"))]
@@ -1168,8 +1202,11 @@ impl Discovery {
         match d {
           Sample::Value(dwd) => {
             trace!("handle_publication_reader discovered {:?}", &dwd);
-            let discovered_writer_data =
-              discovery_db_write(&self.discovery_db).update_publication(&dwd);
+            let Some(discovered_writer_data) =
+              discovery_db_write(&self.discovery_db).update_publication(&dwd)
+            else {
+              continue; // rejected due to the Discovery limits
+            };
             self.send_discovery_notification(DiscoveryNotificationType::WriterUpdated {
               discovered_writer_data,
             });
@@ -1361,7 +1398,7 @@ impl Discovery {
       })
       .min();
 
-    let timenow = Timestamp::now();
+    let timenow = clock::local_timestamp();
 
     let mut messages_to_be_sent: Vec<ParticipantMessageData> = vec![];
 
@@ -1451,61 +1488,94 @@ impl Discovery {
     if let Some(security) = self.security_opt.as_mut() {
       // Security enabled. Get messages from the stateless data reader & feed to
       // Secure Discovery.
-      match self
-        .dcps_participant_stateless_message
-        .reader
-        .into_iterator()
-      {
-        Ok(dr_iter) => {
-          for msg in dr_iter {
-            security.participant_stateless_message_read(
-              &msg,
-              &self.discovery_db,
-              &self.discovery_updated_sender,
-              &self.dcps_participant_stateless_message.writer,
-            );
+      let decoder = TokenLimitDecoder::new(discovery_db_read(&self.discovery_db).limits());
+      loop {
+        match self.dcps_participant_stateless_message.reader.take_with(
+          usize::MAX,
+          ReadCondition::not_read(),
+          decoder,
+        ) {
+          Ok(samples) => {
+            for ds in samples {
+              security.participant_stateless_message_read(
+                &ds.value,
+                &self.discovery_db,
+                &self.discovery_updated_sender,
+                &self.dcps_participant_stateless_message.writer,
+              );
+            }
+            break;
+          }
+          // The offending sample has been skipped, so take the rest.
+          Err(ReadError::Deserialization { reason }) => {
+            security_warn!("Dropped a ParticipantStatelessMessage: {reason}");
+          }
+          Err(e) => {
+            error!("handle_participant_stateless_message_reader: {e:?}");
+            break;
           }
         }
-        Err(e) => {
-          error!("handle_participant_stateless_message_reader: {e:?}");
-        }
-      };
+      }
     }
   }
 
   #[cfg(feature = "security")]
   fn handle_volatile_message_secure_reader(&mut self) {
+    let mut departed_participants = Vec::new();
     if let Some(security) = self.security_opt.as_mut() {
       // Security enabled. Get messages from the volatile message reader & feed to
       // Secure Discovery.
-      match self
-        .dcps_participant_volatile_message_secure
-        .reader
-        .into_iterator()
-      {
-        Ok(dr_iter) => {
-          for msg in dr_iter {
-            security.volatile_message_secure_read(&msg);
+      let decoder = TokenLimitDecoder::new(discovery_db_read(&self.discovery_db).limits());
+      loop {
+        match self
+          .dcps_participant_volatile_message_secure
+          .reader
+          .take_with(usize::MAX, ReadCondition::not_read(), decoder)
+        {
+          Ok(samples) => {
+            for ds in samples {
+              let sender_guid_prefix = ds.sample_info().writer_guid().prefix;
+              if let Some(guid_prefix) = security.volatile_message_secure_read(
+                ds.value(),
+                sender_guid_prefix,
+                &self.discovery_db,
+              ) {
+                departed_participants.push(guid_prefix);
+              }
+            }
+            break;
+          }
+          // The offending sample has been skipped, so take the rest.
+          Err(ReadError::Deserialization { reason }) => {
+            security_warn!("Dropped a ParticipantVolatileMessageSecure: {reason}");
+          }
+          Err(e) => {
+            error!("handle_volatile_message_secure_reader: {e:?}");
+            break;
           }
         }
-        Err(e) => {
-          error!("handle_volatile_message_secure_reader: {e:?}");
-        }
-      };
+      }
+    }
+    for guid_prefix in departed_participants {
+      self.process_participant_dispose(guid_prefix);
     }
   }
 
   #[cfg(feature = "security")]
   pub fn handle_secure_participant_reader(&mut self) {
-    let sample_iter = match self.dcps_participant_secure.reader.into_iterator() {
-      Ok(iter) => iter,
+    let samples = match take_within_limits(
+      &mut self.dcps_participant_secure.reader,
+      &self.discovery_db,
+      self.domain_participant.guid().prefix,
+    ) {
+      Ok(samples) => samples,
       Err(e) => {
         error!("handle_secure_participant_reader: {e:?}");
         return;
       }
     };
 
-    for sample in sample_iter {
+    for sample in samples.into_iter().map(DataSample::into_value) {
       let permission = if let Some(security) = self.security_opt.as_mut() {
         security.secure_participant_read(
           &sample,
@@ -1535,8 +1605,14 @@ impl Discovery {
   #[cfg(feature = "security")]
   pub fn handle_secure_subscription_reader(&mut self, read_history: Option<GuidPrefix>) {
     let sec_subs: Vec<Sample<SubscriptionBuiltinTopicDataSecure, GUID>> =
-      match self.dcps_subscriptions_secure.reader.into_iterator() {
+      match take_within_limits(
+        &mut self.dcps_subscriptions_secure.reader,
+        &self.discovery_db,
+        self.domain_participant.guid().prefix,
+      ) {
         Ok(ds) => ds
+          .into_iter()
+          .map(DataSample::into_value)
           .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
           .filter(|d|
               // If a participant was specified, we must match its GUID prefix.
@@ -1567,10 +1643,13 @@ impl Discovery {
           Sample::Value(sec_sub) => {
             // Currently we use only the DiscoveredReaderData field, no DataTag
             let drd_from_topic = sec_sub.discovered_reader_data;
-            let drd = discovery_db_write(&self.discovery_db).update_subscription(&drd_from_topic);
-            self.send_discovery_notification(DiscoveryNotificationType::ReaderUpdated {
-              discovered_reader_data: drd,
-            });
+            if let Some(drd) =
+              discovery_db_write(&self.discovery_db).update_subscription(&drd_from_topic)
+            {
+              self.send_discovery_notification(DiscoveryNotificationType::ReaderUpdated {
+                discovered_reader_data: drd,
+              });
+            }
           }
           Sample::Dispose(reader_guid) => {
             info!("Secure Dispose Reader {:?}", reader_guid);
@@ -1588,31 +1667,36 @@ impl Discovery {
 
   #[cfg(feature = "security")]
   pub fn handle_secure_publication_reader(&mut self, read_history: Option<GuidPrefix>) {
-    let sec_pubs: Vec<Sample<PublicationBuiltinTopicDataSecure, GUID>> =
-      match self.dcps_publications_secure.reader.into_iterator() {
-        Ok(ds) => ds
-          .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
-          // If a participant was specified, we must match its GUID prefix.
-          .filter(|d| match (read_history, d) {
-            (None, _) => true, // Not asked to filter by participant
-            (Some(participant_to_update), Sample::Value(sec_pub)) => {
-              sec_pub
-                .discovered_writer_data
-                .writer_proxy
-                .remote_writer_guid
-                .prefix
-                == participant_to_update
-            }
-            (Some(participant_to_update), Sample::Dispose(guid)) => {
-              guid.prefix == participant_to_update
-            }
-          })
-          .collect(),
-        Err(e) => {
-          error!("handle_secure_publication_reader: {e:?}");
-          return;
-        }
-      };
+    let sec_pubs: Vec<Sample<PublicationBuiltinTopicDataSecure, GUID>> = match take_within_limits(
+      &mut self.dcps_publications_secure.reader,
+      &self.discovery_db,
+      self.domain_participant.guid().prefix,
+    ) {
+      Ok(ds) => ds
+        .into_iter()
+        .map(DataSample::into_value)
+        .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
+        // If a participant was specified, we must match its GUID prefix.
+        .filter(|d| match (read_history, d) {
+          (None, _) => true, // Not asked to filter by participant
+          (Some(participant_to_update), Sample::Value(sec_pub)) => {
+            sec_pub
+              .discovered_writer_data
+              .writer_proxy
+              .remote_writer_guid
+              .prefix
+              == participant_to_update
+          }
+          (Some(participant_to_update), Sample::Dispose(guid)) => {
+            guid.prefix == participant_to_update
+          }
+        })
+        .collect(),
+      Err(e) => {
+        error!("handle_secure_publication_reader: {e:?}");
+        return;
+      }
+    };
 
     for sec_pub_sample in sec_pubs {
       let permission = if let Some(security) = self.security_opt.as_mut() {
@@ -1627,10 +1711,13 @@ impl Discovery {
           Sample::Value(se_pub) => {
             // Currently we use only the DiscoveredWriterData field, no DataTag
             let dwd_from_topic = se_pub.discovered_writer_data;
-            let dwd = discovery_db_write(&self.discovery_db).update_publication(&dwd_from_topic);
-            self.send_discovery_notification(DiscoveryNotificationType::WriterUpdated {
-              discovered_writer_data: dwd,
-            });
+            if let Some(dwd) =
+              discovery_db_write(&self.discovery_db).update_publication(&dwd_from_topic)
+            {
+              self.send_discovery_notification(DiscoveryNotificationType::WriterUpdated {
+                discovered_writer_data: dwd,
+              });
+            }
           }
           Sample::Dispose(writer_guid) => {
             info!("Secure Dispose Writer {:?}", writer_guid);
@@ -1650,20 +1737,46 @@ impl Discovery {
   fn on_secure_discovery_message_resend_triggered(&mut self) {
     if let Some(security) = self.security_opt.as_mut() {
       // Security is enabled
-      security.resend_cached_secure_discovery_messages(
+      let failed_handshakes = security.resend_cached_secure_discovery_messages(
+        &self.discovery_db,
         &self.dcps_participant_stateless_message.writer,
         &self.dcps_participant_volatile_message_secure.writer,
       );
+      security.send_crypto_tokens_of_rekeyed_endpoints(
+        &self.dcps_participant_volatile_message_secure.writer,
+        &self.discovery_db,
+      );
 
       // Reset timer for resending security messages
       self
         .cached_secure_discovery_messages_resend_timer
-        .set_timeout(Self::CACHED_SECURE_DISCOVERY_MESSAGE_RESEND_PERIOD, ());
+        .set_timeout(security.handshake_resend_period, ());
+
+      // Remove the participants whose handshake was given up
+      for guid_prefix in failed_handshakes {
+        discovery_db_write(&self.discovery_db).remove_participant(guid_prefix, true); // true = actively removed
+        self
+          .send_discovery_notification(DiscoveryNotificationType::ParticipantLost { guid_prefix });
+        self.send_participant_status(DomainParticipantStatusEvent::ParticipantLost {
+          id: guid_prefix,
+          reason: LostReason::AuthenticationFailed,
+        });
+      }
     }
   }
 
-  pub fn participant_cleanup(&self) {
-    let removed = discovery_db_write(&self.discovery_db).participant_cleanup();
+  pub fn participant_cleanup(&mut self) {
+    #[allow(unused_mut)] // mutated only with security
+    let mut removed = discovery_db_write(&self.discovery_db).participant_cleanup();
+
+    #[cfg(feature = "security")]
+    if let Some(security) = self.security_opt.as_mut() {
+      for guid_prefix in security.participants_with_expired_permissions(&self.discovery_db) {
+        discovery_db_write(&self.discovery_db).remove_participant(guid_prefix, true); // true = actively removed
+        removed.push((guid_prefix, LostReason::PermissionsExpired));
+      }
+    }
+
     for (guid_prefix, reason) in removed {
       debug!("participant cleanup - timeout for {:?}", guid_prefix);
       self.send_discovery_notification(DiscoveryNotificationType::ParticipantLost { guid_prefix });
@@ -1963,6 +2076,46 @@ impl Discovery {
   }
 }
 
+// Takes the unread samples from a Discovery reader. The samples are checked
+// against the Discovery limits while they are decoded, so oversized
+// announcements are truncated or skipped. The exceeded limits are reported to
+// the DiscoveryDB.
+fn take_within_limits<D>(
+  reader: &mut DataReaderPlCdr<D>,
+  discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  local_participant: GuidPrefix,
+) -> ReadResult<Vec<DataSample<D>>>
+where
+  D: Keyed + PlCdrDeserialize + 'static,
+  D::K: PlCdrDeserialize,
+{
+  let limits = discovery_db_read(discovery_db).limits().clone();
+  let violations = RefCell::new(Vec::new());
+  let decoder = DiscoveryLimitDecoder::new(&limits, local_participant, &violations);
+  let result = loop {
+    match reader.take_with(usize::MAX, ReadCondition::not_read(), decoder) {
+      // The offending sample has been skipped, so take the rest.
+      Err(ReadError::Deserialization { reason }) => {
+        warn!("Dropped Discovery data: {reason}");
+      }
+      result => break result,
+    }
+  };
+  let violations = violations.into_inner();
+  if !violations.is_empty() {
+    let mut db = discovery_db_write(discovery_db);
+    for LimitViolation {
+      participant,
+      limit,
+      rejected,
+    } in violations
+    {
+      db.report_limit_exceeded(participant, limit, rejected);
+    }
+  }
+  result
+}
+
 // -----------------------------------------------------------------------
 // -----------------------------------------------------------------------
 // -----------------------------------------------------------------------
@@ -12,7 +12,7 @@ use crate::{
   dds::{
     participant::DomainParticipant,
     qos::HasQoSPolicy,
-    statusevents::{DomainParticipantStatusEvent, LostReason, StatusChannelSender},
+    statusevents::{DiscoveryLimit, DomainParticipantStatusEvent, LostReason, StatusChannelSender},
     topic::{Topic, TopicDescription},
   },
   rtps::{
@@ -20,12 +20,14 @@ use crate::{
     rtps_writer_proxy::RtpsWriterProxy,
   },
   structure::{
+    clock,
     duration::Duration,
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, GUID},
   },
 };
 use super::{
+  discovery_limits::DiscoveryLimits,
   sedp_messages::{
     topics_inconsistent, DiscoveredReaderData, DiscoveredTopicData, DiscoveredWriterData,
     ParticipantMessageData, ReaderProxy, SubscriptionBuiltinTopicData, TopicBuiltinTopicData,
@@ -79,6 +81,10 @@ pub(crate) struct DiscoveryDB {
   topic_updated_sender: mio_extras::channel::SyncSender<()>,
 
   participant_status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
+
+  limits: DiscoveryLimits,
+  // How many times each remote participant has exceeded the limits
+  limit_violations: BTreeMap<GuidPrefix, u64>,
 }
 
 // How did we discover this topic
@@ -140,6 +146,58 @@ impl DiscoveryDB {
       topics: BTreeMap::new(),
       topic_updated_sender,
       participant_status_sender,
+      limits: DiscoveryLimits::default(),
+      limit_violations: BTreeMap::new(),
+    }
+  }
+
+  pub fn set_limits(&mut self, limits: DiscoveryLimits) {
+    self.limits = limits;
+  }
+
+  pub fn limits(&self) -> &DiscoveryLimits {
+    &self.limits
+  }
+
+  pub fn limit_violations(&self) -> BTreeMap<GuidPrefix, u64> {
+    self.limit_violations.clone()
+  }
+
+  pub fn report_limit_exceeded(
+    &mut self,
+    participant: GuidPrefix,
+    limit: DiscoveryLimit,
+    rejected: bool,
+  ) {
+    warn!(
+      "Discovery data from {:?} exceeds limit {:?}. Rejected={}",
+      participant, limit, rejected
+    );
+    // The counters are bounded like the participants themselves.
+    if self.limit_violations.contains_key(&participant)
+      || self.limit_violations.len() < self.limits.max_remote_participants
+    {
+      *self.limit_violations.entry(participant).or_insert(0) += 1;
+    }
+    self.send_participant_status(DomainParticipantStatusEvent::DiscoveryLimitExceeded {
+      participant,
+      limit,
+      rejected,
+    });
+  }
+
+  // Returns false, if a new remote endpoint would exceed the limit
+  fn has_room_for_endpoint(&mut self, guid: GUID) -> bool {
+    if guid.prefix == self.my_guid.prefix
+      || self.external_topic_readers.contains_key(&guid)
+      || self.external_topic_writers.contains_key(&guid)
+      || self.external_topic_readers.len() + self.external_topic_writers.len()
+        < self.limits.max_remote_endpoints
+    {
+      true
+    } else {
+      self.report_limit_exceeded(guid.prefix, DiscoveryLimit::RemoteEndpoints, true);
+      false
     }
   }
 
@@ -150,8 +208,9 @@ impl DiscoveryDB {
       .unwrap_or_else(|e| error!("Cannot report participant status: {e:?}"));
   }
 
-  // Returns if participant was previously unknown
-  pub fn update_participant(&mut self, data: &SpdpDiscoveredParticipantData) -> bool {
+  // Returns if participant was previously unknown, or None if the data was
+  // rejected due to the limits.
+  pub fn update_participant(&mut self, data: &SpdpDiscoveredParticipantData) -> Option<bool> {
     debug!("update_participant: {:?}", &data);
     let guid = data.participant_guid;
 
@@ -162,7 +221,17 @@ impl DiscoveryDB {
         guid
       );
       // Maybe we should discard the participant here?
-      return false;
+      return Some(false);
+    }
+
+    let remote_participants = self.participant_proxies.len()
+      - usize::from(self.participant_proxies.contains_key(&self.my_guid.prefix));
+    if guid.prefix != self.my_guid.prefix
+      && !self.participant_proxies.contains_key(&guid.prefix)
+      && remote_participants >= self.limits.max_remote_participants
+    {
+      self.report_limit_exceeded(guid.prefix, DiscoveryLimit::RemoteParticipants, true);
+      return None;
     }
 
     // We allow discovery to discover self, since our discovery readers
@@ -202,14 +271,14 @@ impl DiscoveryDB {
     self.participant_proxies.insert(guid.prefix, data.clone());
     self
       .participant_last_life_signs
-      .insert(guid.prefix, Instant::now());
+      .insert(guid.prefix, clock::monotonic_now());
 
-    new_participant
+    Some(new_participant)
   }
 
   pub fn participant_is_alive(&mut self, guid_prefix: GuidPrefix) {
     if let Some(ts) = self.participant_last_life_signs.get_mut(&guid_prefix) {
-      let now = Instant::now();
+      let now = clock::monotonic_now();
       if now.duration_since(*ts) > std::time::Duration::from_secs(1) {
         debug!(
           "Participant alive update for {:?}, but no full update.",
@@ -251,6 +320,14 @@ impl DiscoveryDB {
         &mut self.external_topic_writers,
         &mut self.external_topic_writers_attic,
       );
+      // Forget endpoints beyond the limit, to keep the attic bounded
+      while self.external_topic_readers_attic.len() + self.external_topic_writers_attic.len()
+        > self.limits.max_remote_endpoints
+      {
+        if self.external_topic_readers_attic.pop_last().is_none() {
+          self.external_topic_writers_attic.pop_last();
+        }
+      }
     }
   }
 
@@ -279,12 +356,10 @@ impl DiscoveryDB {
     self.external_topic_readers.remove(&guid);
   }
 
-  #[cfg(feature = "security")]
   pub fn get_topic_reader(&self, guid: &GUID) -> Option<&DiscoveredReaderData> {
     self.external_topic_readers.get(guid)
   }
 
-  #[cfg(feature = "security")]
   pub fn get_topic_writer(&self, guid: &GUID) -> Option<&DiscoveredWriterData> {
     self.external_topic_writers.get(guid)
   }
@@ -309,7 +384,7 @@ impl DiscoveryDB {
   // Delete participant proxies, if we have not heard of them within
   // lease_duration
   pub fn participant_cleanup(&mut self) -> Vec<(GuidPrefix, LostReason)> {
-    let inow = Instant::now();
+    let inow = clock::monotonic_now();
 
     let mut to_remove = Vec::new();
     // TODO: We are not cleaning up liast_life_signs table, but that should not be a
@@ -426,9 +501,18 @@ impl DiscoveryDB {
   // them from the remote participant.
   //
   // The topic is updated to the topics table.
-  pub fn update_subscription(&mut self, data: &DiscoveredReaderData) -> DiscoveredReaderData {
+  //
+  // Returns None if the data was rejected due to the limits.
+  pub fn update_subscription(
+    &mut self,
+    data: &DiscoveredReaderData,
+  ) -> Option<DiscoveredReaderData> {
     let guid = data.reader_proxy.remote_reader_guid;
 
+    if !self.has_room_for_endpoint(guid) {
+      return None;
+    }
+
     self.external_topic_readers.insert(guid, data.clone());
 
     // fill in the default locators from participant, in case DRD did not provide
@@ -469,20 +553,27 @@ impl DiscoveryDB {
     // from that record and modify by QoS given in the DRD.
 
     // Return DiscoveredReaderData with possibly updated locators.
-    DiscoveredReaderData {
+    Some(DiscoveredReaderData {
       reader_proxy: ReaderProxy::from(RtpsReaderProxy::from_discovered_reader_data(
         data,
         &default_locator_lists.0,
         &default_locator_lists.1,
       )),
       ..data.clone()
-    }
+    })
   }
 
   // TODO: This is silly. Returns one of the parameters cloned, or None
-  pub fn update_publication(&mut self, data: &DiscoveredWriterData) -> DiscoveredWriterData {
+  pub fn update_publication(
+    &mut self,
+    data: &DiscoveredWriterData,
+  ) -> Option<DiscoveredWriterData> {
     let guid = data.writer_proxy.remote_writer_guid;
 
+    if !self.has_room_for_endpoint(guid) {
+      return None;
+    }
+
     self
       .external_topic_writers
       .insert(data.writer_proxy.remote_writer_guid, data.clone());
@@ -521,14 +612,14 @@ impl DiscoveryDB {
       DiscoveredVia::Publication,
     );
 
-    DiscoveredWriterData {
+    Some(DiscoveredWriterData {
       writer_proxy: WriterProxy::from(RtpsWriterProxy::from_discovered_writer_data(
         data,
         &default_locator_lists.0,
         &default_locator_lists.1,
       )),
       ..data.clone()
-    }
+    })
   }
 
   // This is for local participant updating the topic table
@@ -763,7 +854,7 @@ impl DiscoveryDB {
   }
 
   pub fn update_lease_duration(&mut self, data: &ParticipantMessageData) {
-    let now = Instant::now();
+    let now = clock::monotonic_now();
     let prefix = data.guid;
     self
       .external_topic_writers
@@ -784,6 +875,15 @@ impl DiscoveryDB {
   ) {
     self.authentication_statuses.insert(guid_prefix, status);
   }
+
+  #[cfg(feature = "security")]
+  pub fn authenticated_participants(&self) -> impl Iterator<Item = GuidPrefix> + '_ {
+    self
+      .authentication_statuses
+      .iter()
+      .filter(|(_, status)| **status == AuthenticationStatus::Authenticated)
+      .map(|(guid_prefix, _)| *guid_prefix)
+  }
 }
 
 #[cfg(test)]
@@ -806,7 +906,10 @@ mod tests {
     structure::guid::*,
     test::{
       random_data::RandomData,
-      test_data::{reader_proxy_data, spdp_participant_data, subscription_builtin_topic_data},
+      test_data::{
+        publication_builtin_topic_data, reader_proxy_data, spdp_participant_data,
+        subscription_builtin_topic_data, writer_proxy_data,
+      },
     },
   };
 
@@ -837,6 +940,101 @@ mod tests {
     // TODO: more operations tests
   }
 
+  #[test]
+  fn discdb_participant_lease_survives_wall_clock_steps() {
+    let clock = clock::SimulatedClock::start();
+    let (discovery_db_event_sender, _discovery_db_event_receiver) =
+      mio_channel::sync_channel::<()>(4);
+    let (status_sender, _status_receiver) = sync_status_channel(16).unwrap();
+    let mut discoverydb = DiscoveryDB::new(
+      GUID::new_participant_guid(),
+      discovery_db_event_sender,
+      status_sender,
+    );
+    let mut data = spdp_participant_data().unwrap();
+    data.lease_duration = Some(Duration::from_secs(10));
+    discoverydb.update_participant(&data);
+
+    for step in [Duration::from_secs(10 * 60), Duration::from_secs(-10 * 60)] {
+      clock.step_wall_clock(step);
+      clock.advance(Duration::from_secs(5));
+      assert!(discoverydb.participant_cleanup().is_empty());
+      discoverydb.participant_is_alive(data.participant_guid.prefix);
+    }
+
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(discoverydb.participant_cleanup().len(), 1);
+    assert!(discoverydb.participant_proxies.is_empty());
+  }
+
+  fn remote_participant_data() -> SpdpDiscoveredParticipantData {
+    let mut data = spdp_participant_data().unwrap();
+    data.participant_guid = GUID::new_participant_guid();
+    data
+  }
+
+  #[test]
+  fn discdb_caps_remote_participants_and_endpoints() {
+    let (discovery_db_event_sender, _discovery_db_event_receiver) =
+      mio_channel::sync_channel::<()>(4);
+    let (status_sender, _status_receiver) = sync_status_channel(16).unwrap();
+    let my_guid = GUID::new_participant_guid();
+    let mut discoverydb = DiscoveryDB::new(my_guid, discovery_db_event_sender, status_sender);
+    discoverydb.set_limits(DiscoveryLimits {
+      max_remote_participants: 2,
+      max_remote_endpoints: 1,
+      ..DiscoveryLimits::default()
+    });
+
+    // Self does not count
+    let mut own = spdp_participant_data().unwrap();
+    own.participant_guid = my_guid;
+    assert!(discoverydb.update_participant(&own).is_some());
+
+    let first = remote_participant_data();
+    let second = remote_participant_data();
+    let third = remote_participant_data();
+    assert_eq!(discoverydb.update_participant(&first), Some(true));
+    assert_eq!(discoverydb.update_participant(&second), Some(true));
+    assert_eq!(discoverydb.update_participant(&third), None);
+    // Known participants are still updated
+    assert_eq!(discoverydb.update_participant(&first), Some(false));
+    assert_eq!(
+      discoverydb.limit_violations()[&third.participant_guid.prefix],
+      1
+    );
+
+    let mut reader = DiscoveredReaderData::default("topic".to_string(), "Type".to_string());
+    reader.reader_proxy.remote_reader_guid = GUID::new_with_prefix_and_id(
+      first.participant_guid.prefix,
+      EntityId::new([1, 2, 3], EntityKind::READER_WITH_KEY_USER_DEFINED),
+    );
+    assert!(discoverydb.update_subscription(&reader).is_some());
+    assert!(discoverydb.update_subscription(&reader).is_some());
+
+    let mut writer = DiscoveredWriterData {
+      last_updated: clock::monotonic_now(),
+      writer_proxy: writer_proxy_data().unwrap(),
+      publication_topic_data: publication_builtin_topic_data().unwrap(),
+    };
+    writer.writer_proxy.remote_writer_guid = GUID::new_with_prefix_and_id(
+      first.participant_guid.prefix,
+      EntityId::new([1, 2, 4], EntityKind::WRITER_WITH_KEY_USER_DEFINED),
+    );
+    assert!(discoverydb.update_publication(&writer).is_none());
+    assert!(discoverydb
+      .get_topic_writer(&writer.writer_proxy.remote_writer_guid)
+      .is_none());
+    assert_eq!(
+      discoverydb.limit_violations()[&first.participant_guid.prefix],
+      1
+    );
+
+    // Room is freed when the reader is removed
+    discoverydb.remove_topic_reader(reader.reader_proxy.remote_reader_guid);
+    assert!(discoverydb.update_publication(&writer).is_some());
+  }
+
   #[test]
   fn discdb_writer_proxies() {
     let (discovery_db_event_sender, _discovery_db_event_receiver) =
@@ -0,0 +1,879 @@
+use std::{borrow::Cow, cell::RefCell, collections::BTreeMap, ops::Range};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+#[cfg(feature = "security")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "security")]
+use speedy::Writable;
+use speedy::{Endianness, Readable};
+
+use crate::{
+  dds::{
+    adapters::{no_key, with_key},
+    statusevents::DiscoveryLimit,
+  },
+  serialization::{self, pl_cdr_adapters::PlCdrDeserialize, RepresentationIdentifier},
+  structure::{guid::GuidPrefix, parameter_id::ParameterId},
+};
+#[cfg(feature = "security")]
+use crate::messages::submessages::elements::parameter::Parameter;
+
+/// What to do with a Discovery announcement that has too many locators or
+/// list entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OversizedAnnouncement {
+  /// Drop the excess entries and accept the rest of the announcement
+  Truncate,
+  /// Ignore the whole announcement
+  Reject,
+}
+
+/// Bounds on the Discovery (SPDP and SEDP) data accepted from remote
+/// participants.
+///
+/// Lists that are too long are truncated or cause the announcement to be
+/// rejected, as selected by `oversized`. Strings and binary data that are too
+/// long always cause the announcement to be rejected, because truncating them
+/// would change their meaning. The same applies to lists whose entries cannot
+/// be dropped, such as content filter parameters and partition names. User
+/// data, group data and topic data are not stored from Discovery
+/// announcements, so they need no limits.
+///
+/// Exceeded limits are reported with
+/// [`DomainParticipantStatusEvent::DiscoveryLimitExceeded`](crate::DomainParticipantStatusEvent::DiscoveryLimitExceeded)
+/// and counted per remote participant, see
+/// [`DomainParticipant::discovery_limit_violations`](crate::DomainParticipant::discovery_limit_violations).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveryLimits {
+  /// Locators in each locator list of an announcement
+  pub max_locators: usize,
+  /// Entries in property lists, topic alias lists, partition name lists and
+  /// content filter parameter lists
+  pub max_list_entries: usize,
+  /// Length in bytes of names, property values and other strings
+  pub max_string_length: usize,
+  /// Length in bytes of binary property values, which are used by security
+  pub max_data_length: usize,
+  /// Length in bytes of each binary property in the handshake tokens and
+  /// CryptoTokens received from remote participants
+  pub max_token_property_length: usize,
+  /// Serialized length in bytes of each received handshake token or
+  /// CryptoToken
+  pub max_token_length: usize,
+  pub oversized: OversizedAnnouncement,
+  /// Remote participants tracked at the same time
+  pub max_remote_participants: usize,
+  /// Remote Readers and Writers tracked at the same time. Endpoints of lost
+  /// participants are remembered up to the same number, in case the
+  /// participants return.
+  pub max_remote_endpoints: usize,
+}
+
+impl Default for DiscoveryLimits {
+  fn default() -> Self {
+    Self {
+      max_locators: 16,
+      max_list_entries: 64,
+      max_string_length: 4096,
+      max_data_length: 16384,
+      max_token_property_length: 64 * 1024,
+      max_token_length: 256 * 1024,
+      oversized: OversizedAnnouncement::Truncate,
+      max_remote_participants: 1024,
+      max_remote_endpoints: 16384,
+    }
+  }
+}
+
+/// A Discovery limit exceeded by an announcement from a remote participant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct LimitViolation {
+  pub participant: GuidPrefix,
+  pub limit: DiscoveryLimit,
+  pub rejected: bool,
+}
+
+// Decodes SPDP and SEDP data, but first checks it against DiscoveryLimits.
+// The check walks the parameter list and reads only the parameter headers and
+// the length prefixes inside the parameters, so an oversized announcement is
+// rejected before it is decoded. When truncating is allowed, the excess
+// locators, topic aliases and properties are dropped from the parameter list
+// before decoding. Exceeded limits are collected to `violations`. Data from
+// the local participant is not checked.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DiscoveryLimitDecoder<'a> {
+  limits: &'a DiscoveryLimits,
+  local_participant: GuidPrefix,
+  violations: &'a RefCell<Vec<LimitViolation>>,
+}
+
+impl<'a> DiscoveryLimitDecoder<'a> {
+  pub fn new(
+    limits: &'a DiscoveryLimits,
+    local_participant: GuidPrefix,
+    violations: &'a RefCell<Vec<LimitViolation>>,
+  ) -> Self {
+    Self {
+      limits,
+      local_participant,
+      violations,
+    }
+  }
+
+  // Returns the parameter list to decode: either the given one, or a copy
+  // without the excess entries.
+  fn check<'b>(
+    &self,
+    bytes: &'b [u8],
+    encoding: RepresentationIdentifier,
+  ) -> serialization::Result<Cow<'b, [u8]>> {
+    let endianness = match encoding {
+      RepresentationIdentifier::PL_CDR_LE => Endianness::LittleEndian,
+      RepresentationIdentifier::PL_CDR_BE => Endianness::BigEndian,
+      // Decoding reports the unsupported encoding
+      _ => return Ok(Cow::Borrowed(bytes)),
+    };
+    let mut check = LimitCheck::new(self.limits);
+    let mut sender = None;
+    let mut locator_counts = BTreeMap::<ParameterId, usize>::new();
+    let mut topic_aliases = 0;
+    // Parameters to replace and their replacements. Dropped parameters are
+    // replaced with nothing.
+    let mut edits = Vec::<(Range<usize>, Vec<u8>)>::new();
+
+    let mut pos = 0;
+    loop {
+      let header = bytes.get(pos..pos + 4).ok_or(serialization::Error::Eof)?;
+      let parameter_id = ParameterId::read_from_buffer_with_ctx(endianness, &header[..2])
+        .map_err(|e| serialization::Error::Message(e.to_string()))?;
+      if parameter_id == ParameterId::PID_SENTINEL {
+        break;
+      }
+      let length = match endianness {
+        Endianness::LittleEndian => LittleEndian::read_u16(&header[2..]),
+        Endianness::BigEndian => BigEndian::read_u16(&header[2..]),
+      };
+      let parameter = pos..pos + 4 + usize::from(length);
+      let mut cdr = CdrLengths {
+        bytes: bytes
+          .get(pos + 4..parameter.end)
+          .ok_or(serialization::Error::Eof)?,
+        pos: 0,
+        little_endian: endianness == Endianness::LittleEndian,
+      };
+
+      match parameter_id {
+        ParameterId::PID_PARTICIPANT_GUID | ParameterId::PID_ENDPOINT_GUID => {
+          if let Some(prefix) = cdr.bytes.get(..12) {
+            sender.get_or_insert(GuidPrefix::new(prefix));
+          }
+        }
+        ParameterId::PID_METATRAFFIC_UNICAST_LOCATOR
+        | ParameterId::PID_METATRAFFIC_MULTICAST_LOCATOR
+        | ParameterId::PID_DEFAULT_UNICAST_LOCATOR
+        | ParameterId::PID_DEFAULT_MULTICAST_LOCATOR
+        | ParameterId::PID_UNICAST_LOCATOR
+        | ParameterId::PID_MULTICAST_LOCATOR => {
+          let count = locator_counts.entry(parameter_id).or_default();
+          *count += 1;
+          if !check.locator(*count) {
+            edits.push((parameter.clone(), Vec::new()));
+          }
+        }
+        ParameterId::PID_ENTITY_NAME
+        | ParameterId::PID_TOPIC_NAME
+        | ParameterId::PID_TYPE_NAME
+        | ParameterId::PID_SERVICE_INSTANCE_NAME => check.string(cdr.string()?),
+        // Each alias is a parameter of its own
+        ParameterId::PID_TOPIC_ALIASES => {
+          topic_aliases += 1;
+          if check.entry(topic_aliases) {
+            check.string(cdr.string()?);
+          } else {
+            edits.push((parameter.clone(), Vec::new()));
+          }
+        }
+        // Dropping partition names would change the access control decision
+        ParameterId::PID_PARTITION => {
+          let count = cdr.length()?;
+          if check.fixed_entries(count) {
+            for _ in 0..count {
+              check.string(cdr.string()?);
+            }
+          }
+        }
+        ParameterId::PID_CONTENT_FILTER_PROPERTY => {
+          // Topic names, filter class name and filter expression
+          for _ in 0..4 {
+            check.string(cdr.string()?);
+          }
+          let parameter_count = cdr.length()?;
+          if check.fixed_entries(parameter_count) {
+            for _ in 0..parameter_count {
+              check.string(cdr.string()?);
+            }
+          }
+        }
+        #[cfg(feature = "security")]
+        ParameterId::PID_PROPERTY_LIST => {
+          if let Some(truncated) = check.properties(&mut cdr)? {
+            let truncated = Parameter::new(parameter_id, truncated)
+              .write_to_vec_with_ctx(endianness)
+              .map_err(|e| serialization::Error::Message(e.to_string()))?;
+            edits.push((parameter.clone(), truncated));
+          }
+        }
+        _ => {}
+      }
+      pos = parameter.end;
+    }
+
+    if sender == Some(self.local_participant) {
+      return Ok(Cow::Borrowed(bytes));
+    }
+    if let Some((limit, rejected)) = check.finish() {
+      self.violations.borrow_mut().push(LimitViolation {
+        participant: sender.unwrap_or(GuidPrefix::UNKNOWN),
+        limit,
+        rejected,
+      });
+      if rejected {
+        return Err(serialization::Error::Message(format!(
+          "Discovery data exceeds the {limit:?} limit"
+        )));
+      }
+    }
+    if edits.is_empty() {
+      return Ok(Cow::Borrowed(bytes));
+    }
+    let mut edited = Vec::with_capacity(bytes.len());
+    let mut copied = 0;
+    for (parameter, replacement) in edits {
+      edited.extend_from_slice(&bytes[copied..parameter.start]);
+      edited.extend_from_slice(&replacement);
+      copied = parameter.end;
+    }
+    edited.extend_from_slice(&bytes[copied..]);
+    Ok(Cow::Owned(edited))
+  }
+}
+
+impl<D: PlCdrDeserialize> no_key::Decode<D> for DiscoveryLimitDecoder<'_> {
+  type Error = serialization::Error;
+
+  fn decode_bytes(
+    self,
+    input_bytes: &[u8],
+    encoding: RepresentationIdentifier,
+  ) -> serialization::Result<D> {
+    let bytes = self.check(input_bytes, encoding)?;
+    D::from_pl_cdr_bytes(&bytes, encoding).map_err(|e| serialization::Error::Message(e.to_string()))
+  }
+}
+
+// Keys are GUIDs, which need no limits
+impl<D: PlCdrDeserialize, K: PlCdrDeserialize> with_key::Decode<D, K>
+  for DiscoveryLimitDecoder<'_>
+{
+  fn decode_key_bytes(
+    self,
+    input_key_bytes: &[u8],
+    encoding: RepresentationIdentifier,
+  ) -> serialization::Result<K> {
+    K::from_pl_cdr_bytes(input_key_bytes, encoding)
+      .map_err(|e| serialization::Error::Message(e.to_string()))
+  }
+}
+
+// Counts what the DiscoveryLimitDecoder finds against the limits
+struct LimitCheck<'a> {
+  limits: &'a DiscoveryLimits,
+  exceeded: Option<DiscoveryLimit>,
+  reject: bool,
+}
+
+impl<'a> LimitCheck<'a> {
+  fn new(limits: &'a DiscoveryLimits) -> Self {
+    Self {
+      limits,
+      exceeded: None,
+      reject: false,
+    }
+  }
+
+  // Returns false, if the locator at the given (1-based) position of its list
+  // must be dropped
+  fn locator(&mut self, position: usize) -> bool {
+    self.droppable(position, self.limits.max_locators, DiscoveryLimit::Locators)
+  }
+
+  // Same for an entry of a list whose excess entries can be dropped
+  fn entry(&mut self, position: usize) -> bool {
+    self.droppable(
+      position,
+      self.limits.max_list_entries,
+      DiscoveryLimit::ListEntries,
+    )
+  }
+
+  // A list that is meaningful only as a whole. Returns false, if it is too
+  // long.
+  fn fixed_entries(&mut self, count: usize) -> bool {
+    let within = count <= self.limits.max_list_entries;
+    if !within {
+      self.exceed(DiscoveryLimit::ListEntries, true);
+    }
+    within
+  }
+
+  fn string(&mut self, length: usize) {
+    if length > self.limits.max_string_length {
+      self.exceed(DiscoveryLimit::StringLength, true);
+    }
+  }
+
+  #[cfg(feature = "security")]
+  fn data(&mut self, length: usize) {
+    if length > self.limits.max_data_length {
+      self.exceed(DiscoveryLimit::DataLength, true);
+    }
+  }
+
+  // Checks the value of a PID_PROPERTY_LIST parameter. Returns a replacement
+  // for the value, if it has too many entries.
+  #[cfg(feature = "security")]
+  fn properties(&mut self, cdr: &mut CdrLengths) -> serialization::Result<Option<Vec<u8>>> {
+    let count = cdr.length()?;
+    let values = cdr.pos..cdr.pos;
+    let mut kept_values = values.clone();
+    for position in 1..=count {
+      let name = cdr.string()?;
+      let value = cdr.string()?;
+      if self.entry(position) {
+        self.string(name);
+        self.string(value);
+        kept_values.end = cdr.pos;
+      }
+    }
+    // Binary properties are missing from property lists of some RTPS versions
+    let mut binary = None;
+    if cdr.pos < cdr.bytes.len() {
+      let count = cdr.length()?;
+      let start = cdr.pos;
+      let mut kept = start..start;
+      for position in 1..=count {
+        let name = cdr.string()?;
+        let length = cdr.length()?;
+        cdr.skip(length)?;
+        if self.entry(position) {
+          self.string(name);
+          self.data(length);
+          kept.end = cdr.pos;
+        }
+      }
+      binary = Some((count, kept));
+    }
+
+    let limit = self.limits.max_list_entries;
+    if count <= limit && binary.as_ref().map_or(true, |(count, _)| *count <= limit) {
+      return Ok(None);
+    }
+    let mut truncated = Vec::new();
+    let write_u32 = |truncated: &mut Vec<u8>, value: usize| {
+      let mut buf = [0; 4];
+      if cdr.little_endian {
+        LittleEndian::write_u32(&mut buf, value.min(limit) as u32);
+      } else {
+        BigEndian::write_u32(&mut buf, value.min(limit) as u32);
+      }
+      truncated.extend_from_slice(&buf);
+    };
+    write_u32(&mut truncated, count);
+    truncated.extend_from_slice(&cdr.bytes[kept_values]);
+    if let Some((count, kept)) = binary {
+      truncated.resize(serialization::round_up_to_4(truncated.len()), 0);
+      write_u32(&mut truncated, count);
+      truncated.extend_from_slice(&cdr.bytes[kept]);
+    }
+    Ok(Some(truncated))
+  }
+
+  fn droppable(&mut self, position: usize, max: usize, limit: DiscoveryLimit) -> bool {
+    let within = position <= max;
+    if !within {
+      self.exceed(limit, false);
+    }
+    within
+  }
+
+  fn exceed(&mut self, limit: DiscoveryLimit, always_reject: bool) {
+    self.exceeded.get_or_insert(limit);
+    self.reject |= always_reject || self.limits.oversized == OversizedAnnouncement::Reject;
+  }
+
+  // The first exceeded limit, if any, and whether the announcement must be
+  // rejected
+  fn finish(self) -> Option<(DiscoveryLimit, bool)> {
+    self.exceeded.map(|limit| (limit, self.reject))
+  }
+}
+
+// Decodes ParticipantStatelessMessages and ParticipantVolatileMessageSecures,
+// but first checks the lengths of their tokens against DiscoveryLimits. The
+// check walks the CDR encoding and reads only the length prefixes, so an
+// oversized token is rejected before anything is allocated for it.
+#[cfg(feature = "security")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TokenLimitDecoder {
+  max_property_length: usize,
+  max_token_length: usize,
+}
+
+#[cfg(feature = "security")]
+impl TokenLimitDecoder {
+  pub fn new(limits: &DiscoveryLimits) -> Self {
+    Self {
+      max_property_length: limits.max_token_property_length,
+      max_token_length: limits.max_token_length,
+    }
+  }
+
+  fn check(&self, bytes: &[u8], encoding: RepresentationIdentifier) -> serialization::Result<()> {
+    let mut cdr = CdrLengths {
+      bytes,
+      pos: 0,
+      little_endian: matches!(
+        encoding,
+        RepresentationIdentifier::CDR_LE | RepresentationIdentifier::PL_CDR_LE
+      ),
+    };
+    // message_identity and related_message_identity are SampleIdentities (GUID
+    // and SequenceNumber), followed by three GUIDs.
+    cdr.skip(2 * 24 + 3 * 16)?;
+    cdr.string()?; // message_class_id
+    let token_count = cdr.length()?;
+    for _ in 0..token_count {
+      let start = cdr.pos;
+      cdr.string()?; // class_id
+      let property_count = cdr.length()?;
+      for _ in 0..property_count {
+        cdr.string()?; // name
+        cdr.string()?; // value
+        cdr.skip(1)?; // propagate
+      }
+      let binary_property_count = cdr.length()?;
+      for _ in 0..binary_property_count {
+        cdr.string()?; // name
+        let length = cdr.length()?;
+        if length > self.max_property_length {
+          return Err(serialization::Error::Message(format!(
+            "Token binary property length {length} exceeds limit {}",
+            self.max_property_length
+          )));
+        }
+        if cdr.pos - start + length > self.max_token_length {
+          return Err(serialization::Error::Message(format!(
+            "Token length exceeds limit {}",
+            self.max_token_length
+          )));
+        }
+        cdr.skip(length)?;
+      }
+      if cdr.pos - start > self.max_token_length {
+        return Err(serialization::Error::Message(format!(
+          "Token length {} exceeds limit {}",
+          cdr.pos - start,
+          self.max_token_length
+        )));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(feature = "security")]
+impl<D: DeserializeOwned> no_key::Decode<D> for TokenLimitDecoder {
+  type Error = serialization::Error;
+
+  fn decode_bytes(
+    self,
+    input_bytes: &[u8],
+    encoding: RepresentationIdentifier,
+  ) -> serialization::Result<D> {
+    self.check(input_bytes, encoding)?;
+    serialization::deserialize_from_cdr_with_rep_id(input_bytes, encoding).map(|r| r.0)
+  }
+}
+
+// Reads lengths from CDR data, skipping everything else. Alignment is relative
+// to the start of the data, as in the CDR encoding.
+struct CdrLengths<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+  little_endian: bool,
+}
+
+impl CdrLengths<'_> {
+  fn skip(&mut self, count: usize) -> serialization::Result<()> {
+    if count > self.bytes.len() - self.pos {
+      return Err(serialization::Error::Eof);
+    }
+    self.pos += count;
+    Ok(())
+  }
+
+  fn length(&mut self) -> serialization::Result<usize> {
+    self.skip(serialization::padding_needed_for_alignment_4(self.pos))?;
+    let start = self.pos;
+    self.skip(4)?;
+    let bytes = &self.bytes[start..self.pos];
+    let length = if self.little_endian {
+      LittleEndian::read_u32(bytes)
+    } else {
+      BigEndian::read_u32(bytes)
+    };
+    Ok(length as usize)
+  }
+
+  // Skips a string and returns its length without the terminating NUL
+  fn string(&mut self) -> serialization::Result<usize> {
+    let length = self.length()?;
+    self.skip(length)?;
+    Ok(length.saturating_sub(1))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{Ipv4Addr, SocketAddr};
+  #[cfg(feature = "security")]
+  use std::time::{Duration, Instant};
+
+  #[cfg(feature = "security")]
+  use bytes::Bytes;
+
+  use super::*;
+  use crate::{
+    dds::{adapters::no_key::Decode, qos::policy::Partition},
+    discovery::{
+      sedp_messages::{DiscoveredReaderData, DiscoveredWriterData},
+      spdp_participant_data::SpdpDiscoveredParticipantData,
+    },
+    serialization::pl_cdr_adapters::PlCdrSerialize,
+    structure::{
+      clock,
+      guid::{EntityKind, GUID},
+      locator::Locator,
+    },
+    test::test_data::{publication_builtin_topic_data, spdp_participant_data, writer_proxy_data},
+    QosPolicyBuilder,
+  };
+  #[cfg(feature = "security")]
+  use crate::{
+    dds::qos::policy,
+    security::{
+      types::{BinaryProperty, Property},
+      DataHolder, DataHolderBuilder, ParticipantGenericMessage,
+    },
+    structure::{rpc::SampleIdentity, sequence_number::SequenceNumber},
+  };
+
+  fn locators(count: u16) -> Vec<Locator> {
+    (0..count)
+      .map(|port| Locator::from(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 7400 + port)))
+      .collect()
+  }
+
+  fn remote_participant_data() -> SpdpDiscoveredParticipantData {
+    let mut data = spdp_participant_data().unwrap();
+    data.participant_guid = GUID::new_participant_guid();
+    data
+  }
+
+  // Decodes with the limits as a remote participant, and returns the
+  // violations
+  fn decode_within<D: PlCdrSerialize + PlCdrDeserialize>(
+    data: &D,
+    limits: &DiscoveryLimits,
+  ) -> (serialization::Result<D>, Vec<LimitViolation>) {
+    let encoding = RepresentationIdentifier::PL_CDR_LE;
+    let bytes = data.to_pl_cdr_bytes(encoding).unwrap();
+    let violations = RefCell::new(Vec::new());
+    let decoder = DiscoveryLimitDecoder::new(limits, GuidPrefix::UNKNOWN, &violations);
+    let result = decoder.decode_bytes(&bytes, encoding);
+    (result, violations.into_inner())
+  }
+
+  fn violation(participant: GuidPrefix, limit: DiscoveryLimit, rejected: bool) -> LimitViolation {
+    LimitViolation {
+      participant,
+      limit,
+      rejected,
+    }
+  }
+
+  #[test]
+  fn oversized_participant_data_is_truncated_or_rejected() {
+    let limits = DiscoveryLimits::default();
+    let data = remote_participant_data();
+    let (decoded, violations) = decode_within(&data, &limits);
+    assert_eq!(decoded.unwrap().participant_guid, data.participant_guid);
+    assert!(violations.is_empty());
+
+    // Too many locators are truncated by default
+    let mut data = remote_participant_data();
+    let prefix = data.participant_guid.prefix;
+    data.default_unicast_locators = locators(20);
+    let (decoded, violations) = decode_within(&data, &limits);
+    let decoded = decoded.unwrap();
+    assert_eq!(decoded.default_unicast_locators, locators(16));
+    assert_eq!(
+      decoded.metatraffic_unicast_locators,
+      data.metatraffic_unicast_locators
+    );
+    assert_eq!(
+      violations,
+      [violation(prefix, DiscoveryLimit::Locators, false)]
+    );
+
+    // Over-long names cannot be truncated
+    let mut data = remote_participant_data();
+    data.entity_name = Some("x".repeat(5000));
+    let (decoded, violations) = decode_within(&data, &limits);
+    assert!(decoded.is_err());
+    assert_eq!(
+      violations,
+      [violation(
+        data.participant_guid.prefix,
+        DiscoveryLimit::StringLength,
+        true
+      )]
+    );
+
+    // Rejecting instead of truncating
+    let reject = DiscoveryLimits {
+      oversized: OversizedAnnouncement::Reject,
+      ..DiscoveryLimits::default()
+    };
+    let mut rejected = remote_participant_data();
+    rejected.metatraffic_multicast_locators = locators(17);
+    let (decoded, violations) = decode_within(&rejected, &reject);
+    assert!(decoded.is_err());
+    assert_eq!(
+      violations,
+      [violation(
+        rejected.participant_guid.prefix,
+        DiscoveryLimit::Locators,
+        true
+      )]
+    );
+  }
+
+  #[test]
+  fn own_participant_data_is_not_limited() {
+    let limits = DiscoveryLimits::default();
+    let mut data = remote_participant_data();
+    data.default_unicast_locators = locators(20);
+    let bytes = data
+      .to_pl_cdr_bytes(RepresentationIdentifier::PL_CDR_BE)
+      .unwrap();
+    let violations = RefCell::new(Vec::new());
+    let decoder = DiscoveryLimitDecoder::new(&limits, data.participant_guid.prefix, &violations);
+    let decoded: SpdpDiscoveredParticipantData = decoder
+      .decode_bytes(&bytes, RepresentationIdentifier::PL_CDR_BE)
+      .unwrap();
+    assert_eq!(decoded.default_unicast_locators, locators(20));
+    assert!(violations.into_inner().is_empty());
+  }
+
+  #[test]
+  fn oversized_endpoint_data_is_truncated_or_rejected() {
+    let limits = DiscoveryLimits::default();
+    let mut writer = DiscoveredWriterData {
+      last_updated: clock::monotonic_now(),
+      writer_proxy: writer_proxy_data().unwrap(),
+      publication_topic_data: publication_builtin_topic_data().unwrap(),
+    };
+    let prefix = writer.writer_proxy.remote_writer_guid.prefix;
+    writer.writer_proxy.unicast_locator_list = locators(20);
+    let (decoded, violations) = decode_within(&writer, &limits);
+    assert_eq!(
+      decoded.unwrap().writer_proxy.unicast_locator_list,
+      locators(16)
+    );
+    assert_eq!(
+      violations,
+      [violation(prefix, DiscoveryLimit::Locators, false)]
+    );
+
+    // Each topic alias is a parameter of its own
+    writer.writer_proxy.unicast_locator_list = locators(1);
+    writer.publication_topic_data.topic_aliases =
+      Some((0..70).map(|i| format!("alias{i}")).collect());
+    let (decoded, violations) = decode_within(&writer, &limits);
+    assert!(decoded.is_ok());
+    assert_eq!(
+      violations,
+      [violation(prefix, DiscoveryLimit::ListEntries, false)]
+    );
+    let reject = DiscoveryLimits {
+      oversized: OversizedAnnouncement::Reject,
+      ..DiscoveryLimits::default()
+    };
+    assert!(decode_within(&writer, &reject).0.is_err());
+
+    // Partition names cannot be dropped
+    let mut reader = DiscoveredReaderData::default("topic".to_string(), "Type".to_string());
+    reader.reader_proxy.remote_reader_guid =
+      GUID::dummy_test_guid(EntityKind::READER_WITH_KEY_USER_DEFINED);
+    reader.subscription_topic_data.set_qos(
+      &QosPolicyBuilder::new()
+        .partition(Partition::new((0..65).map(|i| format!("p{i}"))))
+        .build(),
+    );
+    let (decoded, violations) = decode_within(&reader, &limits);
+    assert!(decoded.is_err());
+    assert_eq!(
+      violations,
+      [violation(
+        reader.reader_proxy.remote_reader_guid.prefix,
+        DiscoveryLimit::ListEntries,
+        true
+      )]
+    );
+  }
+
+  #[cfg(feature = "security")]
+  #[test]
+  fn oversized_property_lists_are_truncated_or_rejected() {
+    let limits = DiscoveryLimits::default();
+    let property = |name: String| Property {
+      name,
+      value: "value".to_string(),
+      propagate: true,
+    };
+    let binary_property = |name: String, length| BinaryProperty {
+      name,
+      value: Bytes::from(vec![0xAB; length]),
+      propagate: true,
+    };
+
+    let mut data = remote_participant_data();
+    let prefix = data.participant_guid.prefix;
+    data.property = Some(policy::Property {
+      value: (0..70).map(|i| property(format!("p.{i}"))).collect(),
+      binary_value: (0..65)
+        .map(|i| binary_property(format!("b.{i}"), 3))
+        .collect(),
+    });
+    let (decoded, violations) = decode_within(&data, &limits);
+    let decoded = decoded.unwrap().property.unwrap();
+    let expected = data.property.as_ref().unwrap();
+    assert_eq!(decoded.value, expected.value[..64]);
+    assert_eq!(decoded.binary_value, expected.binary_value[..64]);
+    assert_eq!(
+      violations,
+      [violation(prefix, DiscoveryLimit::ListEntries, false)]
+    );
+
+    data.property = Some(policy::Property {
+      value: vec![property("p".to_string())],
+      binary_value: vec![binary_property("b".to_string(), 20000)],
+    });
+    let (decoded, violations) = decode_within(&data, &limits);
+    assert!(decoded.is_err());
+    assert_eq!(
+      violations,
+      [violation(prefix, DiscoveryLimit::DataLength, true)]
+    );
+  }
+
+  #[cfg(feature = "security")]
+  fn message(tokens: Vec<DataHolder>) -> ParticipantGenericMessage {
+    let identity = SampleIdentity {
+      writer_guid: GUID::GUID_UNKNOWN,
+      sequence_number: SequenceNumber::new(1),
+    };
+    ParticipantGenericMessage {
+      message_identity: identity,
+      related_message_identity: identity,
+      destination_participant_guid: GUID::GUID_UNKNOWN,
+      destination_endpoint_guid: GUID::GUID_UNKNOWN,
+      source_endpoint_guid: GUID::GUID_UNKNOWN,
+      message_class_id: "dds.sec.auth".to_string(),
+      message_data: tokens,
+    }
+  }
+
+  #[cfg(feature = "security")]
+  fn token(binary_property_lengths: &[usize]) -> DataHolder {
+    let mut builder = DataHolderBuilder::with_class_id("DDS:Auth:PKI-DH:1.2+Req".to_string())
+      .add_property_opt("dds.sec.x", Some("odd".to_string()), true);
+    for (i, length) in binary_property_lengths.iter().enumerate() {
+      builder =
+        builder.add_binary_property(&format!("c.{i}"), Bytes::from(vec![0xAB; *length]), true);
+    }
+    builder.build()
+  }
+
+  #[cfg(feature = "security")]
+  fn encode(msg: &ParticipantGenericMessage, encoding: RepresentationIdentifier) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    serialization::to_writer_with_rep_id(&mut bytes, msg, encoding).unwrap();
+    bytes
+  }
+
+  #[cfg(feature = "security")]
+  fn decode(
+    bytes: &[u8],
+    encoding: RepresentationIdentifier,
+  ) -> serialization::Result<ParticipantGenericMessage> {
+    TokenLimitDecoder::new(&DiscoveryLimits::default()).decode_bytes(bytes, encoding)
+  }
+
+  #[cfg(feature = "security")]
+  #[test]
+  fn tokens_within_limits_are_decoded() {
+    let msg = message(vec![token(&[3, 1000]), token(&[64 * 1024])]);
+    for encoding in [
+      RepresentationIdentifier::CDR_LE,
+      RepresentationIdentifier::CDR_BE,
+    ] {
+      let decoded = decode(&encode(&msg, encoding), encoding).unwrap();
+      assert!(decoded == msg);
+    }
+  }
+
+  #[cfg(feature = "security")]
+  #[test]
+  fn oversized_tokens_are_rejected() {
+    let encoding = RepresentationIdentifier::CDR_LE;
+    let long_property = message(vec![token(&[64 * 1024 + 1])]);
+    assert!(decode(&encode(&long_property, encoding), encoding).is_err());
+
+    let long_token = message(vec![token(&[60 * 1024; 5])]);
+    assert!(decode(&encode(&long_token, encoding), encoding).is_err());
+  }
+
+  #[cfg(feature = "security")]
+  #[test]
+  fn huge_claimed_length_is_rejected_before_reading_the_payload() {
+    let encoding = RepresentationIdentifier::CDR_LE;
+    let mut bytes = encode(&message(vec![token(&[16])]), encoding);
+    // Claim 100 MB for the binary property and drop the actual value. The
+    // length prefix is right before the value, which ends the message.
+    let value_start = bytes.len() - 16;
+    bytes.truncate(value_start);
+    bytes[value_start - 4..].copy_from_slice(&100_000_000u32.to_le_bytes());
+
+    let started = Instant::now();
+    let result = decode(&bytes, encoding);
+    assert!(started.elapsed() < Duration::from_millis(100));
+    // Rejected by the limit, not by running out of data while reading the value
+    match result {
+      Err(serialization::Error::Message(msg)) => assert!(msg.contains("exceeds limit"), "{msg}"),
+      Err(e) => panic!("Expected a limit error, got {e:?}"),
+      Ok(_) => panic!("Expected a limit error"),
+    }
+  }
+}
@@ -1,6 +1,7 @@
 use std::{
   collections::{HashMap, HashSet},
   sync::{Arc, RwLock},
+  time::Duration,
 };
 
 #[allow(unused_imports)]
@@ -12,6 +13,9 @@ use crate::{
   dds::{
     no_key,
     participant::DomainParticipantWeak,
+    statusevents::{
+      DomainParticipantStatusEvent, ParticipantAuthenticationStatus, StatusChannelSender,
+    },
     with_key::{DataSample, Sample, WriteOptionsBuilder},
     WriteError,
   },
@@ -20,10 +24,14 @@ use crate::{
     DiscoveryNotificationType, SECURE_BUILTIN_READERS_INIT_LIST, SECURE_BUILTIN_WRITERS_INIT_LIST,
   },
   security::{
-    access_control::{EndpointSecurityAttributes, ParticipantSecurityAttributes, PermissionsToken},
+    access_control::{
+      EndpointSecurityAttributes, ParticipantSecurityAttributes, PermissionsToken,
+      RemoteParticipantPermission,
+    },
     authentication::{
-      authentication_builtin::DiscHandshakeState, HandshakeMessageToken, IdentityToken,
-      ValidationOutcome, GMCLASSID_SECURITY_AUTH_HANDSHAKE,
+      authentication_builtin::{identity_token_certificate_subject, DiscHandshakeState},
+      AuthRequestMessageToken, HandshakeMessageToken, IdentityToken, ValidationOutcome,
+      GMCLASSID_SECURITY_AUTH_HANDSHAKE, GMCLASSID_SECURITY_AUTH_REQUEST,
     },
     cryptographic::{
       CryptoToken, GMCLASSID_SECURITY_DATAREADER_CRYPTO_TOKENS,
@@ -33,12 +41,13 @@ use crate::{
     security_plugins::SecurityPluginsHandle,
     DataHolder, ParticipantBuiltinTopicDataSecure, ParticipantGenericMessage,
     ParticipantSecurityInfo, ParticipantStatelessMessage, ParticipantVolatileMessageSecure,
-    PublicationBuiltinTopicDataSecure, SecurityError, SecurityResult,
+    PublicationBuiltinTopicDataSecure, SecurityError, SecurityErrorKind, SecurityResult,
     SubscriptionBuiltinTopicDataSecure,
   },
   security_info, security_warn,
   serialization::pl_cdr_adapters::PlCdrSerialize,
   structure::{
+    clock,
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix},
   },
@@ -62,23 +71,81 @@ pub enum AuthenticationStatus {
   Rejected, // Could not authenticate & should not communicate to
 }
 
-// How many times an authentication message is resent if we don't get an answer
-const STORED_AUTH_MESSAGE_MAX_RESEND_COUNT: u8 = 10;
+// How often unanswered authentication messages are resent, unless configured
+// otherwise with the participant property below. The unsent key exchange
+// messages are retried at the same pace.
+const DEFAULT_HANDSHAKE_RESEND_PERIOD: Duration = Duration::from_secs(1);
+
+// How many times an authentication message is resent if we don't get an
+// answer, unless configured otherwise with the participant property below.
+// When the resends are exhausted, the handshake is given up.
+const DEFAULT_HANDSHAKE_MAX_ATTEMPTS: u32 = 10;
+
+// Participant properties for tuning the handshake resends on lossy networks.
+// The period is in milliseconds.
+pub(crate) const QOS_HANDSHAKE_RESEND_PERIOD_PROPERTY_NAME: &str =
+  "dds.sec.auth.handshake_resend_period";
+pub(crate) const QOS_HANDSHAKE_MAX_ATTEMPTS_PROPERTY_NAME: &str =
+  "dds.sec.auth.handshake_max_attempts";
+
+// Vendor-specific message class id of the secure goodbye. It is sent on the
+// DCPSParticipantVolatileMessageSecure topic to every authenticated remote
+// participant when the local participant shuts down, so that the remotes can
+// drop our keys and discovery data immediately instead of waiting for the
+// lease duration to expire. Implementations that do not know this class id
+// ignore the message, as required by section 7.4.4 of the Security spec.
+pub(crate) const GMCLASSID_RUSTDDS_PARTICIPANT_GOODBYE: &str = "rustdds.sec.participant_goodbye";
+
+// Participant property that enables sending the secure goodbye. Receiving it
+// is always enabled.
+pub(crate) const QOS_SECURE_GOODBYE_PROPERTY_NAME: &str = "rustdds.sec.secure_goodbye";
+
+// How long shutdown waits for the remotes to acknowledge the goodbye
+const GOODBYE_ACKNOWLEDGMENT_TIMEOUT: Duration = Duration::from_secs(2);
 
 struct StoredAuthenticationMessage {
   message: ParticipantStatelessMessage,
-  remaining_resend_counter: u8,
+  remaining_resend_counter: u32,
 }
 
 impl StoredAuthenticationMessage {
-  pub fn new(message: ParticipantStatelessMessage) -> Self {
+  pub fn new(message: ParticipantStatelessMessage, max_resends: u32) -> Self {
     Self {
       message,
-      remaining_resend_counter: STORED_AUTH_MESSAGE_MAX_RESEND_COUNT,
+      remaining_resend_counter: max_resends,
     }
   }
 }
 
+// The authentication with a remote participant that is in force while the
+// remote is authenticated again
+struct PreviousAuthentication {
+  handshake_state: Option<DiscHandshakeState>,
+  answered_handshake_request: Option<HandshakeMessageToken>,
+  stored_authentication_message: Option<StoredAuthenticationMessage>,
+  // The participant data to use once the new handshake succeeds
+  participant_data: SpdpDiscoveredParticipantData,
+}
+
+// Value of a numeric participant property, or the default if the property is
+// missing or invalid
+fn numeric_property<T: std::str::FromStr>(
+  property_qos: &qos::policy::Property,
+  name: &str,
+  default: T,
+) -> T {
+  match property_qos.value.iter().find(|p| p.name == name) {
+    Some(property) => property.value.parse().unwrap_or_else(|_| {
+      warn!(
+        "Invalid value {:?} for the property {name}. Using the default.",
+        property.value
+      );
+      default
+    }),
+    None => default,
+  }
+}
+
 // This struct is an appendix to Discovery that handles Security-related
 // functionality. The intention is that Discovery calls the methods of this
 // struct when Security matters needs to be handled.
@@ -102,6 +169,22 @@ pub(crate) struct SecureDiscovery {
   // Here we store the latest authentication message that we've sent to each remote,
   // in case they need to be sent again
   stored_authentication_messages: HashMap<GuidPrefix, StoredAuthenticationMessage>,
+  // The latest AuthRequestMessageToken received from each remote, and the
+  // handshake request that we have replied to. A different one from an
+  // authenticated remote means that it has restarted with the same GUID prefix.
+  received_auth_request_tokens: HashMap<GuidPrefix, AuthRequestMessageToken>,
+  answered_handshake_requests: HashMap<GuidPrefix, HandshakeMessageToken>,
+  // Remotes whose handshake messages have failed validation, and which have
+  // been reported to the application as rejected. The handshake itself goes
+  // on, so that a forged message cannot end it.
+  reported_handshake_failures: HashSet<GuidPrefix>,
+  // Authenticated remotes with which a new handshake is going on. Their
+  // current authentication and keys stay in use until the new handshake
+  // succeeds, so that a forged authentication message cannot end them.
+  reauthentications: HashMap<GuidPrefix, PreviousAuthentication>,
+
+  // For reporting the authentication results to the application
+  participant_status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
 
   cached_key_exchange_messages_for_resend: HashSet<ParticipantVolatileMessageSecure>,
 
@@ -111,6 +194,14 @@ pub(crate) struct SecureDiscovery {
 
   // A set for keeping track which remote readers are relay-only
   relay_only_remote_readers: HashSet<GUID>,
+
+  // Should we send GMCLASSID_RUSTDDS_PARTICIPANT_GOODBYE on shutdown
+  send_goodbye: bool,
+
+  // How often unanswered authentication messages are resent, and how many
+  // times at most
+  pub handshake_resend_period: Duration,
+  handshake_max_attempts: u32,
 }
 
 impl SecureDiscovery {
@@ -118,12 +209,13 @@ impl SecureDiscovery {
     domain_participant: &DomainParticipantWeak,
     discovery_db: &Arc<RwLock<DiscoveryDB>>,
     security_plugins: SecurityPluginsHandle,
+    participant_status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
   ) -> SecurityResult<Self> {
     // Run the Discovery-related initialization steps of DDS Security spec v1.1
     // Section "8.8.1 Authentication and AccessControl behavior with local
     // DomainParticipant"
 
-    let mut plugins = security_plugins.lock().unwrap();
+    let mut plugins = security_plugins.write().unwrap();
 
     let participant_guid_prefix = domain_participant.guid().prefix;
 
@@ -242,6 +334,22 @@ impl SecureDiscovery {
 
     drop(plugins); // Drop plugins so that they can be moved to self
 
+    let send_goodbye = property_qos
+      .value
+      .iter()
+      .any(|p| p.name == QOS_SECURE_GOODBYE_PROPERTY_NAME && p.value == "true");
+
+    let handshake_resend_period = Duration::from_millis(numeric_property(
+      &property_qos,
+      QOS_HANDSHAKE_RESEND_PERIOD_PROPERTY_NAME,
+      DEFAULT_HANDSHAKE_RESEND_PERIOD.as_millis() as u64,
+    ));
+    let handshake_max_attempts = numeric_property(
+      &property_qos,
+      QOS_HANDSHAKE_MAX_ATTEMPTS_PROPERTY_NAME,
+      DEFAULT_HANDSHAKE_MAX_ATTEMPTS,
+    );
+
     Ok(Self {
       security_plugins,
       domain_id: domain_participant.domain_id(),
@@ -254,9 +362,17 @@ impl SecureDiscovery {
       handshake_states: HashMap::new(),
       cached_key_exchange_messages_for_resend: HashSet::new(),
       stored_authentication_messages: HashMap::new(),
+      received_auth_request_tokens: HashMap::new(),
+      answered_handshake_requests: HashMap::new(),
+      reported_handshake_failures: HashSet::new(),
+      reauthentications: HashMap::new(),
+      participant_status_sender,
       cached_received_key_exchange_messages: HashMap::new(),
       user_data_endpoints_with_keys_already_sent_to: HashSet::new(),
       relay_only_remote_readers: HashSet::new(),
+      send_goodbye,
+      handshake_resend_period,
+      handshake_max_attempts,
     })
   }
 
@@ -335,6 +451,13 @@ impl SecureDiscovery {
                {:?}",
               participant_data.participant_guid
             );
+            self.send_authentication_status(
+              guid_prefix,
+              participant_data.identity_token.as_ref(),
+              ParticipantAuthenticationStatus::Rejected {
+                reason: "Incompatible security configuration".to_string(),
+              },
+            );
             AuthenticationStatus::Rejected
           }
         }
@@ -355,6 +478,13 @@ impl SecureDiscovery {
         // Otherwise keep the same authentication status
         AuthenticationStatus::Authenticating
       }
+      Some(AuthenticationStatus::Authenticated)
+        if self.identity_token_changed(participant_data, discovery_db) =>
+      {
+        // The remote may have restarted with a new identity
+        self.reauthenticate_remote(participant_data, discovery_db, auth_msg_writer);
+        AuthenticationStatus::Authenticated
+      }
       Some(other_status) => {
         // Do nothing, just keep the same status
         other_status
@@ -434,10 +564,12 @@ impl SecureDiscovery {
       }
     };
 
+    // The topic attributes come from our own governance. An Unauthenticated
+    // remote does not even have permissions of its own.
     let topic_sec_attributes = match self
       .security_plugins
       .get_plugins()
-      .get_topic_sec_attributes(participant_guidp, &topic_name)
+      .get_topic_sec_attributes(self.local_participant_guid.prefix, &topic_name)
     {
       Ok(attr) => attr,
       Err(e) => {
@@ -606,10 +738,12 @@ impl SecureDiscovery {
       }
     };
 
+    // The topic attributes come from our own governance. An Unauthenticated
+    // remote does not even have permissions of its own.
     let topic_sec_attributes = match self
       .security_plugins
       .get_plugins()
-      .get_topic_sec_attributes(participant_guidp, &topic_name)
+      .get_topic_sec_attributes(self.local_participant_guid.prefix, &topic_name)
     {
       Ok(attr) => attr,
       Err(e) => {
@@ -1314,24 +1448,35 @@ impl SecureDiscovery {
         create_security_error_and_log!(
           "SpdpDiscoveredParticipantData is missing the Identity token"
         );
+        self.send_authentication_status(
+          remote_guid.prefix,
+          None,
+          ParticipantAuthenticationStatus::Rejected {
+            reason: "The participant data has no IdentityToken".to_string(),
+          },
+        );
         return AuthenticationStatus::Rejected;
       }
     };
 
+    let remote_auth_request_token = self
+      .received_auth_request_tokens
+      .get(&remote_guid.prefix)
+      .cloned();
+
     // First validate the remote identity
-    let outcome: ValidationOutcome = match self
+    let (outcome, local_auth_request_token) = match self
       .security_plugins
       .get_plugins()
       .validate_remote_identity(
         my_guid.prefix,
         remote_identity_token,
         remote_guid.prefix,
-        None,
+        remote_auth_request_token,
       ) {
       Ok(res) => {
-        // Validation passed. Getting only the validation outcome, ignoring
-        // authentication request token which is not used
-        res.0
+        // Validation passed
+        res
       }
       Err(e) => {
         // Validation failed
@@ -1353,6 +1498,13 @@ impl SecureDiscovery {
           return AuthenticationStatus::Unauthenticated;
         } else {
           // Reject the damn thing
+          self.send_authentication_status(
+            remote_guid.prefix,
+            participant_data.identity_token.as_ref(),
+            ParticipantAuthenticationStatus::Rejected {
+              reason: format!("Identity validation failed: {}", e.msg),
+            },
+          );
           return AuthenticationStatus::Rejected;
         }
       }
@@ -1367,7 +1519,20 @@ impl SecureDiscovery {
     // DP event loop. This will result in matching the builtin
     // ParticipantStatelessMessage endpoints, which are used for exchanging
     // authentication messages.
-    discovery_db_write(discovery_db).update_participant(participant_data);
+    if discovery_db_write(discovery_db)
+      .update_participant(participant_data)
+      .is_none()
+    {
+      // Rejected due to the Discovery limits
+      self.send_authentication_status(
+        remote_guid.prefix,
+        participant_data.identity_token.as_ref(),
+        ParticipantAuthenticationStatus::Rejected {
+          reason: "Discovery limits exceeded".to_string(),
+        },
+      );
+      return AuthenticationStatus::Rejected;
+    }
     self.update_participant_authentication_status_and_notify_dp(
       remote_guid.prefix,
       AuthenticationStatus::Authenticating,
@@ -1375,42 +1540,74 @@ impl SecureDiscovery {
       discovery_updated_sender,
     );
 
-    // What is the exact validation outcome?
-    // The returned authentication status is from this match statement
+    if self.begin_handshake(
+      remote_guid.prefix,
+      outcome.clone(),
+      local_auth_request_token,
+      discovery_db,
+      auth_msg_writer,
+    ) {
+      AuthenticationStatus::Authenticating
+    } else {
+      self.send_authentication_status(
+        remote_guid.prefix,
+        participant_data.identity_token.as_ref(),
+        ParticipantAuthenticationStatus::Rejected {
+          reason: format!("Unexpected identity validation outcome {outcome:?}"),
+        },
+      );
+      AuthenticationStatus::Rejected
+    }
+  }
+
+  // Begin the handshake as the validation outcome of the remote identity tells.
+  // Returns false if the outcome is not one to begin a handshake with.
+  fn begin_handshake(
+    &mut self,
+    remote_guid_prefix: GuidPrefix,
+    outcome: ValidationOutcome,
+    local_auth_request_token: Option<AuthRequestMessageToken>,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+    auth_msg_writer: &no_key::DataWriter<ParticipantStatelessMessage>,
+  ) -> bool {
     match outcome {
       ValidationOutcome::PendingHandshakeRequest => {
         // We should send the handshake request
-        self.update_handshake_state(remote_guid.prefix, DiscHandshakeState::PendingRequestSend);
+        self.update_handshake_state(remote_guid_prefix, DiscHandshakeState::PendingRequestSend);
         self.try_sending_new_handshake_request_message(
-          remote_guid.prefix,
+          remote_guid_prefix,
           discovery_db,
           auth_msg_writer,
         );
-
-        AuthenticationStatus::Authenticating // return value
+        true
       }
       ValidationOutcome::PendingHandshakeMessage => {
         // We should wait for the handshake request
         self.update_handshake_state(
-          remote_guid.prefix,
+          remote_guid_prefix,
           DiscHandshakeState::PendingRequestMessage,
         );
+        // Ask the remote to begin the handshake with our future challenge. This
+        // also tells a remote that has authenticated us before that we have
+        // restarted.
+        if let Some(token) = local_auth_request_token {
+          self.send_auth_request_message(remote_guid_prefix, token, auth_msg_writer);
+        }
 
         debug!(
           "Waiting for a handshake request from remote participant {:?}",
-          remote_guid.prefix
+          remote_guid_prefix
         );
-
-        AuthenticationStatus::Authenticating // return value
+        true
       }
       outcome => {
         // Other outcomes should not be possible
         error!(
           "Got an unexpected outcome when validating remote identity. Validation outcome: {:?}. \
-           Remote guid: {:?}",
-          outcome, remote_guid
+           Remote guid prefix: {:?}",
+          outcome, remote_guid_prefix
         );
-        AuthenticationStatus::Rejected // return value
+        false
       }
     }
   }
@@ -1433,6 +1630,55 @@ impl SecureDiscovery {
     );
   }
 
+  // Tell the application how authenticating a remote participant turned out.
+  // The subject name comes from the IdentityToken of the remote.
+  fn send_authentication_status(
+    &self,
+    participant: GuidPrefix,
+    identity_token: Option<&IdentityToken>,
+    status: ParticipantAuthenticationStatus,
+  ) {
+    let event = DomainParticipantStatusEvent::ParticipantAuthenticationStatus {
+      participant,
+      subject_name: identity_token.and_then(identity_token_certificate_subject),
+      status,
+    };
+    self
+      .participant_status_sender
+      .try_send(event)
+      .unwrap_or_else(|e| error!("Cannot report participant status: {e:?}"));
+  }
+
+  // Same as above, for a remote participant whose data is in DiscoveryDB
+  fn send_authentication_status_of_known_remote(
+    &self,
+    participant: GuidPrefix,
+    status: ParticipantAuthenticationStatus,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) {
+    let identity_token = discovery_db_read(discovery_db)
+      .find_participant_proxy(participant)
+      .and_then(|participant_data| participant_data.identity_token.clone());
+    self.send_authentication_status(participant, identity_token.as_ref(), status);
+  }
+
+  // Report the remote as rejected, unless that has been done already during
+  // the current handshake
+  fn report_handshake_failure(
+    &mut self,
+    remote_guid_prefix: GuidPrefix,
+    reason: String,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) {
+    if self.reported_handshake_failures.insert(remote_guid_prefix) {
+      self.send_authentication_status_of_known_remote(
+        remote_guid_prefix,
+        ParticipantAuthenticationStatus::Rejected { reason },
+        discovery_db,
+      );
+    }
+  }
+
   fn create_handshake_request_message(
     &mut self,
     discovery_db: &Arc<RwLock<DiscoveryDB>>,
@@ -1464,7 +1710,7 @@ impl SecureDiscovery {
       GMCLASSID_SECURITY_AUTH_HANDSHAKE,
       remote_guid_prefix,
       None,
-      request_token,
+      request_token.data_holder,
     );
     Ok(request_message)
   }
@@ -1498,7 +1744,7 @@ impl SecureDiscovery {
     // resending it later if needed
     self.stored_authentication_messages.insert(
       remote_guid_prefix,
-      StoredAuthenticationMessage::new(request_message.clone()),
+      StoredAuthenticationMessage::new(request_message.clone(), self.handshake_max_attempts),
     );
 
     // Try to send the message
@@ -1514,41 +1760,66 @@ impl SecureDiscovery {
     self.update_handshake_state(remote_guid_prefix, DiscHandshakeState::PendingReplyMessage);
   }
 
+  // Resend the unanswered authentication messages and the key exchange messages
+  // that could not be sent. Handshakes whose resends are exhausted are given
+  // up, and the remote participants are returned so that they can be removed.
+  // A later announcement of such a participant starts a new handshake.
   pub fn resend_cached_secure_discovery_messages(
     &mut self,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
     auth_msg_writer: &no_key::DataWriter<ParticipantStatelessMessage>,
     key_exchange_writer: &no_key::DataWriter<ParticipantVolatileMessageSecure>,
-  ) {
+  ) -> Vec<GuidPrefix> {
     // First resend authentication messages
+    let mut failed_handshakes = Vec::new();
     for (guid_prefix, stored_message) in self.stored_authentication_messages.iter_mut() {
       // Resend the message unless it's a final message (which needs to be requested
       // from us)
       if self.handshake_states.get(guid_prefix)
-        != Some(&DiscHandshakeState::CompletedWithFinalMessageSent)
+        == Some(&DiscHandshakeState::CompletedWithFinalMessageSent)
       {
-        match auth_msg_writer.write(stored_message.message.clone(), None) {
-          Ok(()) => {
-            stored_message.remaining_resend_counter -= 1;
-            debug!(
-              "Resent an unanswered authentication message to remote participant {:?}. Resending \
-               at most {} more times.",
-              guid_prefix, stored_message.remaining_resend_counter,
-            );
-          }
-          Err(err) => {
-            debug!(
-              "Failed to resend an unanswered authentication message to remote participant {:?}. \
-               Error: {}. Retrying later.",
-              guid_prefix, err
-            );
-          }
+        continue;
+      }
+      if stored_message.remaining_resend_counter == 0 {
+        failed_handshakes.push(*guid_prefix);
+        continue;
+      }
+      match auth_msg_writer.write(stored_message.message.clone(), None) {
+        Ok(()) => {
+          stored_message.remaining_resend_counter -= 1;
+          debug!(
+            "Resent an unanswered authentication message to remote participant {:?}. Resending at \
+             most {} more times.",
+            guid_prefix, stored_message.remaining_resend_counter,
+          );
+        }
+        Err(err) => {
+          debug!(
+            "Failed to resend an unanswered authentication message to remote participant {:?}. \
+             Error: {}. Retrying later.",
+            guid_prefix, err
+          );
         }
       }
     }
-    // Remove authentication messages with no more resends
-    self
-      .stored_authentication_messages
-      .retain(|_guid_prefix, message| message.remaining_resend_counter > 0);
+    // A new handshake with an authenticated remote is just dropped. Tear down
+    // the other handshakes with no more resends.
+    failed_handshakes.retain(|guid_prefix| !self.discard_reauthentication(*guid_prefix));
+    for guid_prefix in &failed_handshakes {
+      security_warn!(
+        "Handshake with the remote participant {guid_prefix:?} failed: no answer after {} resends",
+        self.handshake_max_attempts
+      );
+      self.report_handshake_failure(
+        *guid_prefix,
+        format!(
+          "No answer to the handshake after {} resends",
+          self.handshake_max_attempts
+        ),
+        discovery_db,
+      );
+      self.forget_remote_participant(*guid_prefix);
+    }
 
     // Then try to send those key exchange messages that we haven't been able to
     // send yet
@@ -1572,6 +1843,8 @@ impl SecureDiscovery {
       }
     }
     self.cached_key_exchange_messages_for_resend = msgs_still_to_cache;
+
+    failed_handshakes
   }
 
   fn reset_stored_message_resend_counter(&mut self, remote_guid_prefix: &GuidPrefix) {
@@ -1579,7 +1852,7 @@ impl SecureDiscovery {
       .stored_authentication_messages
       .get_mut(remote_guid_prefix)
     {
-      msg.remaining_resend_counter = STORED_AUTH_MESSAGE_MAX_RESEND_COUNT;
+      msg.remaining_resend_counter = self.handshake_max_attempts;
     } else {
       debug!(
         "Did not find a stored authentication message for remote participant {:?}",
@@ -1601,6 +1874,10 @@ impl SecureDiscovery {
     }
 
     // Check that GenericMessageClassID is what we expect
+    if message.generic.message_class_id == GMCLASSID_SECURITY_AUTH_REQUEST {
+      self.auth_request_message_read(message, discovery_db, auth_msg_writer);
+      return;
+    }
     if message.generic.message_class_id != GMCLASSID_SECURITY_AUTH_HANDSHAKE {
       debug!(
         "Received a ParticipantStatelessMessage with an unknown GenericMessageClassID: {}",
@@ -1610,6 +1887,10 @@ impl SecureDiscovery {
     }
 
     let remote_guid_prefix = message.generic.source_guid_prefix();
+    if self.is_handshake_request_from_restarted_remote(message) {
+      // Begin a new handshake. We are then waiting for this very request.
+      self.reauthenticate_known_remote(remote_guid_prefix, discovery_db, auth_msg_writer);
+    }
     // What to do depends on the handshake state with the remote participant
     match self.get_handshake_state(&remote_guid_prefix) {
       None => {
@@ -1662,6 +1943,308 @@ impl SecureDiscovery {
     }
   }
 
+  fn auth_request_message_read(
+    &mut self,
+    message: &ParticipantStatelessMessage,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+    auth_msg_writer: &no_key::DataWriter<ParticipantStatelessMessage>,
+  ) {
+    let remote_guid_prefix = message.generic.source_guid_prefix();
+    let token = match message.generic.message_data.first() {
+      Some(data_holder) => AuthRequestMessageToken::from(data_holder.clone()),
+      None => {
+        error!(
+          "A ParticipantStatelessMessage does not contain an authentication request token. Remote \
+           guid prefix: {:?}",
+          remote_guid_prefix
+        );
+        return;
+      }
+    };
+    if self.received_auth_request_tokens.get(&remote_guid_prefix) == Some(&token) {
+      trace!(
+        "Received a resent authentication request from remote participant {:?}. Ignoring.",
+        remote_guid_prefix
+      );
+      return;
+    }
+    self
+      .received_auth_request_tokens
+      .insert(remote_guid_prefix, token);
+
+    match self.get_handshake_state(&remote_guid_prefix) {
+      Some(
+        DiscHandshakeState::CompletedWithFinalMessageSent
+        | DiscHandshakeState::CompletedWithFinalMessageReceived,
+      ) if self.begins_handshake_with(remote_guid_prefix) => {
+        // A new authentication request from an authenticated remote means that it
+        // may have restarted and waits for us to begin a new handshake
+        self.reauthenticate_known_remote(remote_guid_prefix, discovery_db, auth_msg_writer);
+      }
+      _ => {
+        // The token is used when we begin the handshake
+        debug!(
+          "Received an authentication request from remote participant {:?}",
+          remote_guid_prefix
+        );
+      }
+    }
+  }
+
+  fn send_auth_request_message(
+    &mut self,
+    remote_guid_prefix: GuidPrefix,
+    token: AuthRequestMessageToken,
+    auth_msg_writer: &no_key::DataWriter<ParticipantStatelessMessage>,
+  ) {
+    let request_message = self.new_stateless_message(
+      GMCLASSID_SECURITY_AUTH_REQUEST,
+      remote_guid_prefix,
+      None,
+      token.data_holder,
+    );
+    // Resend the message until the remote begins the handshake
+    self.stored_authentication_messages.insert(
+      remote_guid_prefix,
+      StoredAuthenticationMessage::new(request_message.clone(), self.handshake_max_attempts),
+    );
+    let _ = auth_msg_writer.write(request_message, None).map_err(|err| {
+      warn!(
+        "Failed to send an authentication request message. Remote GUID prefix: {:?}. Info: {}. \
+         Trying to resend the message later.",
+        remote_guid_prefix, err
+      );
+    });
+  }
+
+  // A handshake request from an authenticated remote, which is not the one that
+  // we have replied to, means that the remote has restarted with the same GUID
+  // prefix
+  fn is_handshake_request_from_restarted_remote(
+    &self,
+    message: &ParticipantStatelessMessage,
+  ) -> bool {
+    let remote_guid_prefix = message.generic.source_guid_prefix();
+    let authenticated = matches!(
+      self.get_handshake_state(&remote_guid_prefix),
+      Some(
+        DiscHandshakeState::CompletedWithFinalMessageSent
+          | DiscHandshakeState::CompletedWithFinalMessageReceived
+      )
+    );
+    let is_request = message.generic.related_message_identity.writer_guid == GUID::GUID_UNKNOWN;
+    authenticated
+      && is_request
+      && !self.begins_handshake_with(remote_guid_prefix)
+      && get_handshake_token_from_stateless_message(message).as_ref()
+        != self.answered_handshake_requests.get(&remote_guid_prefix)
+  }
+
+  // With the built-in authentication plugin, the participant with the lower GUID
+  // prefix begins the handshake by sending the request. The other one may ask
+  // for it with an authentication request.
+  fn begins_handshake_with(&self, remote_guid_prefix: GuidPrefix) -> bool {
+    self.local_participant_guid.prefix < remote_guid_prefix
+  }
+
+  fn identity_token_changed(
+    &self,
+    participant_data: &SpdpDiscoveredParticipantData,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) -> bool {
+    discovery_db_read(discovery_db)
+      .find_participant_proxy(participant_data.participant_guid.prefix)
+      .is_some_and(|known_data| known_data.identity_token != participant_data.identity_token)
+  }
+
+  fn reauthenticate_known_remote(
+    &mut self,
+    remote_guid_prefix: GuidPrefix,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+    auth_msg_writer: &no_key::DataWriter<ParticipantStatelessMessage>,
+  ) {
+    let participant_data_opt = discovery_db_read(discovery_db)
+      .find_participant_proxy(remote_guid_prefix)
+      .cloned();
+    match participant_data_opt {
+      Some(participant_data) => {
+        self.reauthenticate_remote(&participant_data, discovery_db, auth_msg_writer);
+      }
+      None => {
+        // The participant has been removed already, but not forgotten
+        debug!(
+          "Remote participant {:?} has restarted, but its participant data is not known. Waiting \
+           for it to be discovered again.",
+          remote_guid_prefix
+        );
+        self.forget_remote_participant_but_auth_request(remote_guid_prefix);
+      }
+    }
+  }
+
+  // Begin a new handshake with an authenticated remote participant, which
+  // seems to have restarted with the same GUID prefix. The messages telling
+  // this are not protected, so the current authentication and keys stay in
+  // use until the new handshake succeeds.
+  fn reauthenticate_remote(
+    &mut self,
+    participant_data: &SpdpDiscoveredParticipantData,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+    auth_msg_writer: &no_key::DataWriter<ParticipantStatelessMessage>,
+  ) {
+    let guid_prefix = participant_data.participant_guid.prefix;
+    if self.reauthentications.contains_key(&guid_prefix) {
+      debug!("A new handshake with remote participant {guid_prefix:?} is going on already");
+      return;
+    }
+    let Some(remote_identity_token) = participant_data.identity_token.clone() else {
+      debug!("Participant data of {guid_prefix:?} has no IdentityToken. Not authenticating again.");
+      return;
+    };
+    security_info!(
+      "Remote participant {guid_prefix:?} may have restarted. Authenticating it again."
+    );
+
+    let remote_auth_request_token = self.received_auth_request_tokens.get(&guid_prefix).cloned();
+    let mut plugins = self.security_plugins.get_plugins();
+    plugins.begin_remote_reauthentication(guid_prefix);
+    let validation_result = plugins.validate_remote_identity(
+      self.local_participant_guid.prefix,
+      remote_identity_token,
+      guid_prefix,
+      remote_auth_request_token,
+    );
+    drop(plugins);
+    let (outcome, local_auth_request_token) = match validation_result {
+      Ok(res) => res,
+      Err(e) => {
+        self
+          .security_plugins
+          .get_plugins()
+          .discard_remote_reauthentication(&guid_prefix);
+        security_info!(
+          "Failed to validate the new identity of remote participant {guid_prefix:?}: {}. Keeping \
+           the current authentication.",
+          e.msg
+        );
+        return;
+      }
+    };
+
+    self.reauthentications.insert(
+      guid_prefix,
+      PreviousAuthentication {
+        handshake_state: self.handshake_states.remove(&guid_prefix),
+        answered_handshake_request: self.answered_handshake_requests.remove(&guid_prefix),
+        stored_authentication_message: self.stored_authentication_messages.remove(&guid_prefix),
+        participant_data: participant_data.clone(),
+      },
+    );
+    let begun = self.begin_handshake(
+      guid_prefix,
+      outcome,
+      local_auth_request_token,
+      discovery_db,
+      auth_msg_writer,
+    );
+    if !begun
+      || self.get_handshake_state(&guid_prefix) == Some(DiscHandshakeState::PendingRequestSend)
+    {
+      self.discard_reauthentication(guid_prefix);
+    }
+  }
+
+  // The new handshake with a remote participant that was authenticated already
+  // has succeeded. The participant is removed, so that its old endpoints and
+  // keys are dropped, and then added back with the new authentication. Does
+  // nothing if the remote was not authenticated before.
+  fn complete_reauthentication(
+    &mut self,
+    remote_guid_prefix: GuidPrefix,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+    discovery_updated_sender: &mio_channel::SyncSender<DiscoveryNotificationType>,
+  ) {
+    let Some(previous) = self.reauthentications.remove(&remote_guid_prefix) else {
+      return;
+    };
+    security_info!(
+      "Authenticated remote participant {remote_guid_prefix:?} again. Dropping its old endpoints \
+       and keys."
+    );
+
+    if let Err(e) = self
+      .security_plugins
+      .get_plugins()
+      .complete_remote_reauthentication(remote_guid_prefix)
+    {
+      error!("Failed to unregister remote participant {remote_guid_prefix:?}: {e}");
+    }
+    self.forget_key_exchange_with_remote_participant(remote_guid_prefix);
+
+    // The participant is back in DiscoveryDB before the DP event loop gets the
+    // notification
+    {
+      let mut db = discovery_db_write(discovery_db);
+      db.remove_participant(remote_guid_prefix, true);
+      if db.update_participant(&previous.participant_data).is_none() {
+        error!("Could not add remote participant {remote_guid_prefix:?} back to DiscoveryDB");
+      }
+      db.update_authentication_status(remote_guid_prefix, AuthenticationStatus::Authenticating);
+    }
+    send_discovery_notification(
+      discovery_updated_sender,
+      DiscoveryNotificationType::ParticipantLost {
+        guid_prefix: remote_guid_prefix,
+      },
+    );
+  }
+
+  // The new handshake with an authenticated remote participant has failed. Go
+  // back to the current authentication. Returns false if the remote was not
+  // authenticated before.
+  fn discard_reauthentication(&mut self, remote_guid_prefix: GuidPrefix) -> bool {
+    let Some(previous) = self.reauthentications.remove(&remote_guid_prefix) else {
+      return false;
+    };
+    security_info!(
+      "A new handshake with remote participant {remote_guid_prefix:?} failed. Keeping the current \
+       authentication."
+    );
+    self
+      .security_plugins
+      .get_plugins()
+      .discard_remote_reauthentication(&remote_guid_prefix);
+    match previous.handshake_state {
+      Some(state) => self.update_handshake_state(remote_guid_prefix, state),
+      None => {
+        self.handshake_states.remove(&remote_guid_prefix);
+      }
+    }
+    match previous.answered_handshake_request {
+      Some(token) => {
+        self
+          .answered_handshake_requests
+          .insert(remote_guid_prefix, token);
+      }
+      None => {
+        self.answered_handshake_requests.remove(&remote_guid_prefix);
+      }
+    }
+    match previous.stored_authentication_message {
+      Some(message) => {
+        self
+          .stored_authentication_messages
+          .insert(remote_guid_prefix, message);
+      }
+      None => {
+        self
+          .stored_authentication_messages
+          .remove(&remote_guid_prefix);
+      }
+    }
+    true
+  }
+
   fn handshake_on_pending_request_message(
     &mut self,
     received_message: &ParticipantStatelessMessage,
@@ -1702,18 +2285,22 @@ impl SecureDiscovery {
     let result = self.security_plugins.get_plugins().begin_handshake_reply(
       local_guid_prefix,
       remote_guid_prefix,
-      handshake_token,
+      handshake_token.clone(),
       my_serialized_data,
     );
     match result {
       Ok((ValidationOutcome::PendingHandshakeMessage, reply_token)) => {
+        self
+          .answered_handshake_requests
+          .insert(remote_guid_prefix, handshake_token);
+
         // Request token was OK and we got a reply token to send back
         // Create a ParticipantStatelessMessage with the token
         let reply_message = self.new_stateless_message(
           GMCLASSID_SECURITY_AUTH_HANDSHAKE,
           remote_guid_prefix,
           Some(received_message),
-          reply_token,
+          reply_token.data_holder,
         );
 
         debug!(
@@ -1736,7 +2323,7 @@ impl SecureDiscovery {
         // resending it later if needed
         self.stored_authentication_messages.insert(
           remote_guid_prefix,
-          StoredAuthenticationMessage::new(reply_message),
+          StoredAuthenticationMessage::new(reply_message, self.handshake_max_attempts),
         );
 
         // Set handshake state as pending final message
@@ -1755,6 +2342,13 @@ impl SecureDiscovery {
           "Replying to a handshake request failed: {}. Remote guid prefix: {:?}",
           e, remote_guid_prefix
         );
+        if !self.discard_reauthentication(remote_guid_prefix) {
+          self.report_handshake_failure(
+            remote_guid_prefix,
+            format!("Handshake request failed validation: {}", e.msg),
+            discovery_db,
+          );
+        }
       }
     }
   }
@@ -1810,7 +2404,7 @@ impl SecureDiscovery {
           GMCLASSID_SECURITY_AUTH_HANDSHAKE,
           remote_guid_prefix,
           Some(received_message),
-          final_message_token,
+          final_message_token.data_holder,
         );
 
         debug!(
@@ -1833,7 +2427,7 @@ impl SecureDiscovery {
         // resending it later if needed
         self.stored_authentication_messages.insert(
           remote_guid_prefix,
-          StoredAuthenticationMessage::new(final_message),
+          StoredAuthenticationMessage::new(final_message, self.handshake_max_attempts),
         );
 
         // Set handshake state as completed with final message
@@ -1842,6 +2436,7 @@ impl SecureDiscovery {
           DiscHandshakeState::CompletedWithFinalMessageSent,
         );
 
+        self.complete_reauthentication(remote_guid_prefix, discovery_db, discovery_updated_sender);
         self.on_remote_participant_authenticated(
           remote_guid_prefix,
           discovery_db,
@@ -1861,9 +2456,16 @@ impl SecureDiscovery {
           "Validating handshake reply message failed. Error: {}. Remote guid prefix: {:?}",
           e, remote_guid_prefix
         );
-        // Reset stored message resend counter, so our resends can't be depleted by
-        // sending us incorrect messages
-        self.reset_stored_message_resend_counter(&remote_guid_prefix);
+        if !self.discard_reauthentication(remote_guid_prefix) {
+          self.report_handshake_failure(
+            remote_guid_prefix,
+            format!("Handshake reply failed validation: {}", e.msg),
+            discovery_db,
+          );
+          // Reset stored message resend counter, so our resends can't be depleted by
+          // sending us incorrect messages
+          self.reset_stored_message_resend_counter(&remote_guid_prefix);
+        }
       }
     }
   }
@@ -1925,6 +2527,7 @@ impl SecureDiscovery {
           .stored_authentication_messages
           .remove(&remote_guid_prefix);
 
+        self.complete_reauthentication(remote_guid_prefix, discovery_db, discovery_updated_sender);
         self.on_remote_participant_authenticated(
           remote_guid_prefix,
           discovery_db,
@@ -1944,14 +2547,32 @@ impl SecureDiscovery {
           "Validating final handshake message failed. Error: {}. Remote guid prefix: {:?}",
           e, remote_guid_prefix
         );
-        // Reset stored message resend counter, so our resends can't be depleted by
-        // sending us incorrect messages
-        self.reset_stored_message_resend_counter(&remote_guid_prefix);
+        if !self.discard_reauthentication(remote_guid_prefix) {
+          self.report_handshake_failure(
+            remote_guid_prefix,
+            format!("Final handshake message failed validation: {}", e.msg),
+            discovery_db,
+          );
+          // Reset stored message resend counter, so our resends can't be depleted by
+          // sending us incorrect messages
+          self.reset_stored_message_resend_counter(&remote_guid_prefix);
+        }
       }
     }
   }
 
-  pub fn volatile_message_secure_read(&mut self, msg: &ParticipantVolatileMessageSecure) {
+  // Process a message from the DCPSParticipantVolatileMessageSecure topic.
+  // `sender_guid_prefix` is the prefix of the RTPS writer the message came
+  // from. The volatile topic is protected with keys shared only with that
+  // participant, so the prefix is authenticated.
+  // Returns the prefix of a remote participant that said goodbye, if any.
+  // Discovery must then remove the participant.
+  pub fn volatile_message_secure_read(
+    &mut self,
+    msg: &ParticipantVolatileMessageSecure,
+    sender_guid_prefix: GuidPrefix,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) -> Option<GuidPrefix> {
     // Check is the message meant to us (see 7.4.4.4 Destination of the
     // ParticipantVolatileMessageSecure of the spec)
     let dest_guid = msg.generic.destination_participant_guid;
@@ -1961,7 +2582,7 @@ impl SecureDiscovery {
         "Ignoring ParticipantVolatileMessageSecure message since it's not for us. dest_guid: {:?}",
         dest_guid
       );
-      return;
+      return None;
     }
 
     // Get crypto tokens from message
@@ -1980,7 +2601,7 @@ impl SecureDiscovery {
         // Make sure destination_participant_guid is correct
         if dest_guid != self.local_participant_guid {
           debug!("Invalid destination participant guid, ignoring participant crypto tokens");
-          return;
+          return None;
         }
 
         let remote_participant_guidp = msg.generic.message_identity.writer_guid.prefix;
@@ -2060,10 +2681,178 @@ impl SecureDiscovery {
           );
         }
       }
+      GMCLASSID_RUSTDDS_PARTICIPANT_GOODBYE => {
+        return self.participant_goodbye_read(msg, sender_guid_prefix, discovery_db);
+      }
       other => {
         debug!("Unknown message_class_id in a volatile message: {}", other);
       }
     }
+    None
+  }
+
+  fn participant_goodbye_read(
+    &mut self,
+    msg: &ParticipantVolatileMessageSecure,
+    sender_guid_prefix: GuidPrefix,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) -> Option<GuidPrefix> {
+    // A goodbye is always addressed to a single participant, and must come
+    // from the participant that is leaving.
+    let claimed_guid_prefix = msg.generic.message_identity.writer_guid.prefix;
+    if msg.generic.destination_participant_guid != self.local_participant_guid
+      || claimed_guid_prefix != sender_guid_prefix
+      || sender_guid_prefix == self.local_participant_guid.prefix
+    {
+      security_warn!(
+        "Ignoring an invalid participant goodbye. Sender: {:?}, claimed sender: {:?}, \
+         destination: {:?}",
+        sender_guid_prefix,
+        claimed_guid_prefix,
+        msg.generic.destination_participant_guid
+      );
+      return None;
+    }
+
+    let auth_status = discovery_db_read(discovery_db).get_authentication_status(sender_guid_prefix);
+    if auth_status != Some(AuthenticationStatus::Authenticated) {
+      security_warn!(
+        "Ignoring a participant goodbye from a non-authenticated participant {:?}. Auth status: \
+         {:?}",
+        sender_guid_prefix,
+        auth_status
+      );
+      return None;
+    }
+
+    security_info!("Participant {sender_guid_prefix:?} said goodbye");
+    self.forget_remote_participant(sender_guid_prefix);
+    Some(sender_guid_prefix)
+  }
+
+  // Send GMCLASSID_RUSTDDS_PARTICIPANT_GOODBYE to all authenticated remote
+  // participants, if enabled by the participant properties.
+  pub fn send_goodbye_messages(
+    &mut self,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+    key_exchange_writer: &no_key::DataWriter<ParticipantVolatileMessageSecure>,
+  ) {
+    if !self.send_goodbye {
+      return;
+    }
+
+    let local_guid_prefix = self.local_participant_guid.prefix;
+    let remotes: Vec<GuidPrefix> = discovery_db_read(discovery_db)
+      .authenticated_participants()
+      .filter(|guid_prefix| *guid_prefix != local_guid_prefix)
+      .collect();
+
+    if remotes.is_empty() {
+      return;
+    }
+    for remote_guid_prefix in remotes {
+      let message = self.new_volatile_message(
+        GMCLASSID_RUSTDDS_PARTICIPANT_GOODBYE,
+        key_exchange_writer.guid(),
+        GUID::GUID_UNKNOWN, // No source endpoint, just the participant
+        remote_guid_prefix,
+        GUID::GUID_UNKNOWN, // No destination endpoint, just the participant
+        &[],
+      );
+      match self.send_key_exchange_message(key_exchange_writer, &message) {
+        Ok(()) => debug!("Sent participant goodbye to {remote_guid_prefix:?}"),
+        Err(e) => warn!("Failed to send participant goodbye to {remote_guid_prefix:?}: {e}"),
+      }
+    }
+
+    // We are about to shut down, so there will be no chance to resend the
+    // goodbyes later. Give the remotes a moment to get them.
+    match key_exchange_writer.wait_for_acknowledgments(GOODBYE_ACKNOWLEDGMENT_TIMEOUT) {
+      Ok(true) => debug!("Participant goodbyes were acknowledged"),
+      Ok(false) => debug!("Timed out waiting for participant goodbyes to be acknowledged"),
+      Err(e) => warn!("Failed to wait for participant goodbyes to be acknowledged: {e:?}"),
+    }
+  }
+
+  // Drop everything SecureDiscovery remembers about a remote participant
+  // Forget authenticated remote participants whose permissions have expired,
+  // and return them so that they can be removed.
+  pub fn participants_with_expired_permissions(
+    &mut self,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) -> Vec<GuidPrefix> {
+    let now = clock::wall_now().to_utc();
+    let authenticated: Vec<GuidPrefix> = discovery_db_read(discovery_db)
+      .authenticated_participants()
+      .collect();
+    let expired: Vec<GuidPrefix> = authenticated
+      .into_iter()
+      .filter(|guid_prefix| {
+        let expiration = self
+          .security_plugins
+          .get_plugins()
+          .get_permissions_expiration(*guid_prefix);
+        match expiration {
+          Ok(expiration) => expiration <= now,
+          // Permissions not validated (yet), so there is nothing to expire
+          Err(e) if e.kind() == SecurityErrorKind::NotRegistered => false,
+          // No currently valid grant
+          Err(_) => true,
+        }
+      })
+      .collect();
+    for guid_prefix in &expired {
+      security_info!("Permissions of the participant {guid_prefix:?} have expired");
+      self.send_authentication_status_of_known_remote(
+        *guid_prefix,
+        ParticipantAuthenticationStatus::Revoked,
+        discovery_db,
+      );
+      self.forget_remote_participant(*guid_prefix);
+    }
+    expired
+  }
+
+  fn forget_remote_participant(&mut self, remote_guid_prefix: GuidPrefix) {
+    self.handshake_states.remove(&remote_guid_prefix);
+    self
+      .stored_authentication_messages
+      .remove(&remote_guid_prefix);
+    self
+      .received_auth_request_tokens
+      .remove(&remote_guid_prefix);
+    self.answered_handshake_requests.remove(&remote_guid_prefix);
+    self.reported_handshake_failures.remove(&remote_guid_prefix);
+    self.reauthentications.remove(&remote_guid_prefix);
+    self.forget_key_exchange_with_remote_participant(remote_guid_prefix);
+  }
+
+  fn forget_key_exchange_with_remote_participant(&mut self, remote_guid_prefix: GuidPrefix) {
+    self
+      .cached_key_exchange_messages_for_resend
+      .retain(|msg| msg.generic.destination_participant_guid.prefix != remote_guid_prefix);
+    self
+      .cached_received_key_exchange_messages
+      .retain(|(_local, remote), _msg| remote.prefix != remote_guid_prefix);
+    self
+      .user_data_endpoints_with_keys_already_sent_to
+      .retain(|guid| guid.prefix != remote_guid_prefix);
+    self
+      .relay_only_remote_readers
+      .retain(|guid| guid.prefix != remote_guid_prefix);
+  }
+
+  // Keep the authentication request that a restarted remote may have sent
+  fn forget_remote_participant_but_auth_request(&mut self, remote_guid_prefix: GuidPrefix) {
+    let auth_request_token_opt = self
+      .received_auth_request_tokens
+      .remove(&remote_guid_prefix);
+    self.forget_remote_participant(remote_guid_prefix);
+    if let Some(token) = auth_request_token_opt {
+      self
+        .received_auth_request_tokens
+        .insert(remote_guid_prefix, token);
+    }
   }
 
   fn store_received_volatile_message(&mut self, msg: ParticipantVolatileMessageSecure) {
@@ -2081,62 +2870,57 @@ impl SecureDiscovery {
     discovery_updated_sender: &mio_channel::SyncSender<DiscoveryNotificationType>,
   ) {
     security_info!("Authenticated successfully Participant {remote_guid_prefix:?}");
+    self.reported_handshake_failures.remove(&remote_guid_prefix);
 
     // Call the required access control methods
     // (see Security spec. section "8.8.6 AccessControl behavior with remote
     // participant discovery")
-    match self.validate_remote_participant_permissions(remote_guid_prefix, discovery_db) {
-      Ok(()) => {
-        debug!(
-          "Validated permissions for remote participant {:?}",
-          remote_guid_prefix
-        );
-      }
-      Err(e) => {
-        security_info!(
-          "Validating permissions for remote failed: {}. Rejecting the remote. Guid prefix: {:?}",
-          e,
-          remote_guid_prefix
-        );
-        self.update_participant_authentication_status_and_notify_dp(
-          remote_guid_prefix,
-          AuthenticationStatus::Rejected,
-          discovery_db,
-          discovery_updated_sender,
-        );
-        return;
-      }
-    }
+    let mut permission =
+      match self.validate_remote_participant_permissions(remote_guid_prefix, discovery_db) {
+        Ok(permission) => {
+          debug!(
+            "Validated permissions for remote participant {:?}: {:?}",
+            remote_guid_prefix, permission
+          );
+          permission
+        }
+        Err(e) => {
+          security_info!(
+            "Validating permissions for remote failed: {}. Rejecting the remote. Guid prefix: {:?}",
+            e,
+            remote_guid_prefix
+          );
+          self.send_authentication_status_of_known_remote(
+            remote_guid_prefix,
+            ParticipantAuthenticationStatus::Rejected {
+              reason: format!("Permissions validation failed: {e}"),
+            },
+            discovery_db,
+          );
+          self.update_participant_authentication_status_and_notify_dp(
+            remote_guid_prefix,
+            AuthenticationStatus::Rejected,
+            discovery_db,
+            discovery_updated_sender,
+          );
+          return;
+        }
+      };
 
     // If needed, check is remote allowed to join the domain
-    if self.local_dp_sec_attributes.is_access_protected {
+    if permission == RemoteParticipantPermission::Allowed
+      && self.local_dp_sec_attributes.is_access_protected
+    {
       let check_result = self
         .security_plugins
         .get_plugins()
-        .check_remote_participant(self.domain_id, remote_guid_prefix);
+        .check_remote_participant(
+          self.domain_id,
+          self.local_participant_guid.prefix,
+          remote_guid_prefix,
+        );
       match check_result {
-        Ok(check_passed) => {
-          if check_passed {
-            // All good
-            security_info!(
-              "Allowing remote participant {:?} to join the domain.",
-              remote_guid_prefix
-            );
-          } else {
-            // Not allowed
-            security_info!(
-              "Remote participant {:?} is not allowed to join the domain. Rejecting the remote.",
-              remote_guid_prefix
-            );
-            self.update_participant_authentication_status_and_notify_dp(
-              remote_guid_prefix,
-              AuthenticationStatus::Rejected,
-              discovery_db,
-              discovery_updated_sender,
-            );
-            return;
-          }
-        }
+        Ok(check_permission) => permission = check_permission,
         Err(e) => {
           // Something went wrong in checking permissions
           create_security_error_and_log!(
@@ -2145,6 +2929,13 @@ impl SecureDiscovery {
             e,
             remote_guid_prefix
           );
+          self.send_authentication_status_of_known_remote(
+            remote_guid_prefix,
+            ParticipantAuthenticationStatus::Rejected {
+              reason: format!("Checking the participant permissions failed: {e}"),
+            },
+            discovery_db,
+          );
           self.update_participant_authentication_status_and_notify_dp(
             remote_guid_prefix,
             AuthenticationStatus::Rejected,
@@ -2155,6 +2946,53 @@ impl SecureDiscovery {
         }
       }
     }
+
+    match permission {
+      RemoteParticipantPermission::Allowed => {
+        // All good
+        security_info!(
+          "Allowing remote participant {:?} to join the domain.",
+          remote_guid_prefix
+        );
+      }
+      RemoteParticipantPermission::AllowedUnauthenticated => {
+        // Communicate with the remote only on the unprotected topics, without
+        // exchanging keys (see Security spec section 8.8.2.1)
+        security_info!(
+          "Remote participant {:?} did not pass the access control checks. Treating it as \
+           Unauthenticated, since configuration allows this.",
+          remote_guid_prefix
+        );
+        self.update_participant_authentication_status_and_notify_dp(
+          remote_guid_prefix,
+          AuthenticationStatus::Unauthenticated,
+          discovery_db,
+          discovery_updated_sender,
+        );
+        return;
+      }
+      RemoteParticipantPermission::Denied => {
+        // Not allowed
+        security_info!(
+          "Remote participant {:?} is not allowed to join the domain. Rejecting the remote.",
+          remote_guid_prefix
+        );
+        self.send_authentication_status_of_known_remote(
+          remote_guid_prefix,
+          ParticipantAuthenticationStatus::Rejected {
+            reason: "Not allowed to join the domain".to_string(),
+          },
+          discovery_db,
+        );
+        self.update_participant_authentication_status_and_notify_dp(
+          remote_guid_prefix,
+          AuthenticationStatus::Rejected,
+          discovery_db,
+          discovery_updated_sender,
+        );
+        return;
+      }
+    }
     // Permission checks OK
 
     if let Err(e) = register_remote_to_crypto(
@@ -2167,6 +3005,13 @@ impl SecureDiscovery {
         remote_guid_prefix,
         e,
       );
+      self.send_authentication_status_of_known_remote(
+        remote_guid_prefix,
+        ParticipantAuthenticationStatus::Rejected {
+          reason: format!("Registering to the cryptographic plugin failed: {e}"),
+        },
+        discovery_db,
+      );
       self.update_participant_authentication_status_and_notify_dp(
         remote_guid_prefix,
         AuthenticationStatus::Rejected,
@@ -2183,6 +3028,11 @@ impl SecureDiscovery {
       discovery_db,
       discovery_updated_sender,
     );
+    self.send_authentication_status_of_known_remote(
+      remote_guid_prefix,
+      ParticipantAuthenticationStatus::Authenticated,
+      discovery_db,
+    );
   }
 
   // Initiates the exchange of cryptographic keys with the remote participant.
@@ -2210,6 +3060,18 @@ impl SecureDiscovery {
         }
       };
 
+    // When a remote has been authenticated again, the DP event loop may drop
+    // its old built-in endpoints only after the new keys have been registered.
+    // It has done so by now, so register the endpoints that are missing.
+    if let Err(e) = register_remote_builtin_endpoints_to_crypto(
+      self.local_participant_guid.prefix,
+      remote_guid_prefix,
+      &self.security_plugins,
+    ) {
+      error!("Cannot start the key exchange with {remote_guid_prefix:?}: {e}");
+      return;
+    }
+
     // Send local participant crypto tokens to remote
     // TODO: do this only if needed?
     let crypto_tokens_res = self
@@ -2579,11 +3441,36 @@ impl SecureDiscovery {
       .insert(remote_endpoint_guid);
   }
 
+  // Sends the new crypto tokens of local endpoints whose keys have been
+  // regenerated to their matched remote endpoints
+  pub fn send_crypto_tokens_of_rekeyed_endpoints(
+    &mut self,
+    key_exchange_writer: &no_key::DataWriter<ParticipantVolatileMessageSecure>,
+    discovery_db: &Arc<RwLock<DiscoveryDB>>,
+  ) {
+    let rekeyed_endpoints = self.security_plugins.get_plugins().take_rekeyed_endpoints();
+    for (local_endpoint_guid, remote_endpoint_guid) in rekeyed_endpoints {
+      debug!(
+        "Keys of local endpoint {:?} were regenerated, sending new crypto tokens to {:?}",
+        local_endpoint_guid.entity_id, remote_endpoint_guid
+      );
+      self
+        .user_data_endpoints_with_keys_already_sent_to
+        .remove(&remote_endpoint_guid);
+      self.start_key_exchange_with_remote_endpoint(
+        local_endpoint_guid,
+        remote_endpoint_guid,
+        key_exchange_writer,
+        discovery_db,
+      );
+    }
+  }
+
   fn validate_remote_participant_permissions(
     &mut self,
     remote_guid_prefix: GuidPrefix,
     discovery_db: &Arc<RwLock<DiscoveryDB>>,
-  ) -> SecurityResult<()> {
+  ) -> SecurityResult<RemoteParticipantPermission> {
     let mut sec_plugins = self.security_plugins.get_plugins();
 
     // Get PermissionsToken
@@ -2707,7 +3594,7 @@ impl SecureDiscovery {
     message_class_id: &str,
     destination_guid_prefix: GuidPrefix,
     related_message_opt: Option<&ParticipantStatelessMessage>,
-    handshake_token: HandshakeMessageToken,
+    token_data: DataHolder,
   ) -> ParticipantStatelessMessage {
     let generic_message = self.generic_message_helper.new_message(
       message_class_id,
@@ -2716,7 +3603,7 @@ impl SecureDiscovery {
       related_message_opt.map(|msg| &msg.generic),
       destination_guid_prefix,
       GUID::GUID_UNKNOWN, // Do not specify destination endpoint guid
-      vec![handshake_token.data_holder],
+      vec![token_data],
     );
 
     ParticipantStatelessMessage::from(generic_message)
@@ -2806,6 +3693,16 @@ fn register_remote_to_crypto(
     remote_guidp
   );
 
+  register_remote_builtin_endpoints_to_crypto(local_guidp, remote_guidp, security_plugins_handle)
+}
+
+// Register the secure built-in endpoints of a remote participant, unless they
+// are registered already
+fn register_remote_builtin_endpoints_to_crypto(
+  local_guidp: GuidPrefix,
+  remote_guidp: GuidPrefix,
+  security_plugins_handle: &SecurityPluginsHandle,
+) -> SecurityResult<()> {
   // Register remote's secure built-in readers
   for (writer_eid, reader_eid, _reader_endpoint) in SECURE_BUILTIN_READERS_INIT_LIST {
     let remote_reader_guid = GUID::new(remote_guidp, *reader_eid);
@@ -15,7 +15,7 @@ use crate::{
     qos::{
       policy::{
         Deadline, DestinationOrder, Durability, History, LatencyBudget, Lifespan, Liveliness,
-        Ownership, Presentation, Reliability, ResourceLimits, TimeBasedFilter,
+        Ownership, Partition, Presentation, Reliability, ResourceLimits, TimeBasedFilter,
       },
       HasQoSPolicy, QosPolicies,
     },
@@ -37,6 +37,7 @@ use crate::{
     RepresentationIdentifier,
   },
   structure::{
+    clock,
     entity::RTPSEntity,
     guid::{GuidPrefix, GUID},
     locator,
@@ -165,7 +166,7 @@ pub struct SubscriptionBuiltinTopicData {
   // pub user_data: Option<UserData>,
   time_based_filter: Option<TimeBasedFilter>,
   presentation: Option<Presentation>,
-  // pub partition: Option<Partition>,
+  partition: Option<Partition>,
   // pub topic_data: Option<TopicData>,
   // pub group_data: Option<GroupData>,
   // pub durability_service: Option<DurabilityService>,
@@ -208,6 +209,7 @@ impl SubscriptionBuiltinTopicData {
       destination_order: None,
       time_based_filter: None,
       presentation: None,
+      partition: None,
       lifespan: None,
       // DDS-RPC
       // TODO: these are not implemented
@@ -245,6 +247,10 @@ impl SubscriptionBuiltinTopicData {
     &self.security_info
   }
 
+  pub fn partition(&self) -> &Option<Partition> {
+    &self.partition
+  }
+
   pub fn set_qos(&mut self, qos: &QosPolicies) {
     self.durability = qos.durability;
     self.deadline = qos.deadline;
@@ -255,6 +261,7 @@ impl SubscriptionBuiltinTopicData {
     self.destination_order = qos.destination_order;
     self.time_based_filter = qos.time_based_filter;
     self.presentation = qos.presentation;
+    self.partition = qos.partition.clone();
     self.lifespan = qos.lifespan;
     // history does not exist
     // resource_limits does not exist
@@ -274,6 +281,14 @@ impl SubscriptionBuiltinTopicData {
       history: None, // SubscriptionBuiltinTopicData does not contain History QoS
       resource_limits: None, // nor Resource Limits, see Figure 8.30 in RTPS spec 2.5
       lifespan: self.lifespan,
+      partition: self.partition.clone(),
+
+      stale_sample_filter: None,
+
+      clock_skew_tolerance: None,
+
+      #[cfg(feature = "history_spill")]
+      history_spill: None,
 
       #[cfg(feature = "security")]
       property: None, // TODO: no property QoS?
@@ -466,6 +481,7 @@ impl ParameterListable for DiscoveredReaderData {
           destination_order: _,
           time_based_filter: _,
           presentation: _,
+          partition: _,
           lifespan: _,
 
           service_instance_name,
@@ -621,6 +637,7 @@ pub struct PublicationBuiltinTopicData {
   pub ownership: Option<Ownership>,
   pub destination_order: Option<DestinationOrder>,
   pub presentation: Option<Presentation>,
+  pub partition: Option<Partition>,
 
   // From Remote Procedure Call over DDS:
   pub service_instance_name: Option<String>,
@@ -659,6 +676,7 @@ impl PublicationBuiltinTopicData {
       ownership: None,
       destination_order: None,
       presentation: None,
+      partition: None,
 
       service_instance_name: None,  // TODO: These are not supported/used
       related_datareader_key: None, // TODO
@@ -693,6 +711,7 @@ impl PublicationBuiltinTopicData {
     self.ownership = qos.ownership;
     self.destination_order = qos.destination_order;
     self.presentation = qos.presentation;
+    self.partition = qos.partition.clone();
   }
 
   pub fn qos(&self) -> QosPolicies {
@@ -709,6 +728,11 @@ impl PublicationBuiltinTopicData {
       history: None,         // PublicationBuiltinTopicData does not contain History QoS
       resource_limits: None, // nor Resource Limits, see Figure 8.30 in RTPS spec 2.5
       lifespan: self.lifespan,
+      partition: self.partition.clone(),
+      stale_sample_filter: None,
+      clock_skew_tolerance: None,
+      #[cfg(feature = "history_spill")]
+      history_spill: None,
       #[cfg(feature = "security")]
       property: None, // TODO: no property Qos?
     }
@@ -778,7 +802,21 @@ impl DiscoveredWriterData {
     );
 
     Self {
-      last_updated: Instant::now(),
+      last_updated: clock::monotonic_now(),
+      writer_proxy,
+      publication_topic_data,
+    }
+  }
+
+  // This is for generating test data only
+  #[cfg(test)]
+  pub fn default(topic_name: String, type_name: String) -> Self {
+    let wguid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_BUILT_IN);
+    let writer_proxy = WriterProxy::new(wguid, vec![], vec![]);
+    let publication_topic_data =
+      PublicationBuiltinTopicData::new(wguid, None, topic_name, type_name, None);
+    Self {
+      last_updated: clock::monotonic_now(),
       writer_proxy,
       publication_topic_data,
     }
@@ -848,7 +886,7 @@ impl PlCdrDeserialize for DiscoveredWriterData {
     let qos = QosPolicies::from_parameter_list(ctx, &pl_map)?;
 
     Ok(DiscoveredWriterData {
-      last_updated: Instant::now(),
+      last_updated: clock::monotonic_now(),
       writer_proxy: WriterProxy {
         remote_writer_guid: guid,
         unicast_locator_list,
@@ -910,6 +948,7 @@ impl ParameterListable for DiscoveredWriterData {
           destination_order: _,
           time_based_filter: _,
           presentation: _,
+          partition: _,
           lifespan: _,
 
           service_instance_name,
@@ -1057,6 +1096,11 @@ impl HasQoSPolicy for TopicBuiltinTopicData {
       history: self.history,
       resource_limits: self.resource_limits,
       lifespan: self.lifespan,
+      partition: None,
+      stale_sample_filter: None,
+      clock_skew_tolerance: None,
+      #[cfg(feature = "history_spill")]
+      history_spill: None,
       #[cfg(feature = "security")]
       property: None, // TODO: no property Qos?
     }
@@ -1405,7 +1449,7 @@ mod tests {
     writer_proxy.remote_writer_guid = pub_topic_data.key;
 
     let dwd = DiscoveredWriterData {
-      last_updated: Instant::now(),
+      last_updated: clock::monotonic_now(),
       writer_proxy,
       publication_topic_data: pub_topic_data,
     };
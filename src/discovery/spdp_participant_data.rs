@@ -91,6 +91,7 @@ impl SpdpDiscoveredParticipantData {
     &self,
     is_metatraffic: bool,
     entity_id: Option<EntityId>,
+    qos: QosPolicies,
   ) -> RtpsReaderProxy {
     let remote_reader_guid = GUID::new_with_prefix_and_id(
       self.participant_guid.prefix,
@@ -100,11 +101,7 @@ impl SpdpDiscoveredParticipantData {
       },
     );
 
-    let mut proxy = RtpsReaderProxy::new(
-      remote_reader_guid,
-      QosPolicies::qos_none(), // TODO: What is the correct QoS value here?
-      self.expects_inline_qos,
-    );
+    let mut proxy = RtpsReaderProxy::new(remote_reader_guid, qos, self.expects_inline_qos);
 
     if !is_metatraffic {
       proxy
@@ -598,6 +595,7 @@ mod tests {
     dds::adapters::no_key::DeserializerAdapter,
     messages::submessages::submessages::WriterSubmessage,
     rtps::{submessage::*, Message},
+    structure::guid::GuidPrefix,
     test::test_data::*,
   };
 
@@ -770,4 +768,97 @@ mod tests {
       }
     }
   }
+
+  // The serialized payload of the only DATA submessage in the message
+  fn data_payload(message: &Bytes) -> Bytes {
+    let rtpsmsg = Message::read_from_buffer(message).unwrap();
+    rtpsmsg
+      .submessages()
+      .iter()
+      .find_map(|submsg| match &submsg.body {
+        SubmessageBody::Writer(WriterSubmessage::Data(d, _)) => {
+          Some(d.unwrap_serialized_payload_value().clone())
+        }
+        _ => None,
+      })
+      .unwrap()
+  }
+
+  #[test]
+  fn pdata_with_unknown_parameters_skips_them() {
+    let payload = data_payload(&spdp_participant_data_with_unknown_parameters_raw());
+    let pl =
+      ParameterList::read_from_buffer_with_ctx(speedy::Endianness::LittleEndian, &payload).unwrap();
+    // 16 parameters, of which PID_DOMAIN_ID and the vendor-specific one are skipped
+    assert_eq!(pl.parameters.len(), 14);
+
+    let participant_data: SpdpDiscoveredParticipantData =
+      PlCdrDeserializerAdapter::from_bytes(&payload, RepresentationIdentifier::PL_CDR_LE).unwrap();
+    assert_eq!(
+      participant_data.participant_guid.prefix,
+      GuidPrefix::new(&[0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d, 0x31, 0xa2, 0x28, 0x20, 0x02, 0x08])
+    );
+    assert_eq!(participant_data.metatraffic_unicast_locators.len(), 3);
+    assert_eq!(participant_data.metatraffic_multicast_locators.len(), 1);
+    assert_eq!(
+      participant_data.lease_duration,
+      Some(Duration::from_secs(300))
+    );
+  }
+
+  #[test]
+  fn pdata_with_unknown_must_understand_parameter_is_rejected() {
+    let mut payload = data_payload(&spdp_participant_data_with_unknown_parameters_raw()).to_vec();
+    // Set the must-understand bit of PID_DOMAIN_ID (0x000f)
+    let domain_id_at = 8;
+    assert_eq!(payload[domain_id_at..domain_id_at + 2], [0x0f, 0x00]);
+    payload[domain_id_at + 1] = 0x40;
+    let result: Result<SpdpDiscoveredParticipantData, _> =
+      PlCdrDeserializerAdapter::from_bytes(&payload, RepresentationIdentifier::PL_CDR_LE);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn pdata_with_repeated_parameter_uses_first() {
+    let payload = data_payload(&spdp_participant_data_with_unknown_parameters_raw());
+    let mut pl =
+      ParameterList::read_from_buffer_with_ctx(speedy::Endianness::LittleEndian, &payload).unwrap();
+    // Another lease duration of 10 seconds after the original 300
+    pl.push(Parameter::new(
+      ParameterId::PID_PARTICIPANT_LEASE_DURATION,
+      vec![10, 0, 0, 0, 0, 0, 0, 0],
+    ));
+    let payload = pl
+      .serialize_to_bytes(speedy::Endianness::LittleEndian)
+      .unwrap();
+    let participant_data: SpdpDiscoveredParticipantData =
+      PlCdrDeserializerAdapter::from_bytes(&payload, RepresentationIdentifier::PL_CDR_LE).unwrap();
+    assert_eq!(
+      participant_data.lease_duration,
+      Some(Duration::from_secs(300))
+    );
+  }
+
+  #[test]
+  fn pdata_mutations_do_not_panic() {
+    let payload = data_payload(&spdp_participant_data_with_unknown_parameters_raw());
+    // xorshift, so that failures are reproducible
+    let mut state = 0x9e37_79b9_u32;
+    let mut random = move || {
+      state ^= state << 13;
+      state ^= state >> 17;
+      state ^= state << 5;
+      state as usize
+    };
+    for _ in 0..5_000 {
+      let mut bytes = payload.to_vec();
+      for _ in 0..1 + random() % 4 {
+        let i = random() % bytes.len();
+        bytes[i] = random() as u8;
+      }
+      bytes.truncate(random() % (bytes.len() + 1));
+      let _result: Result<SpdpDiscoveredParticipantData, _> =
+        PlCdrDeserializerAdapter::from_bytes(&bytes, RepresentationIdentifier::PL_CDR_LE);
+    }
+  }
 }
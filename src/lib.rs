@@ -176,6 +176,15 @@ mod rtps;
 mod security;
 #[cfg(feature = "security")]
 pub use security::config::DomainParticipantSecurityConfigFiles;
+#[cfg(feature = "security")]
+pub use security::entropy::{EntropySource, SystemEntropySource};
+#[cfg(feature = "security")]
+pub use dds::statusevents::ParticipantAuthenticationStatus;
+#[cfg(feature = "security")]
+pub use security::cryptographic::{
+  CryptoOperation, CryptoSecurityEvent, CryptoSecurityEventKind, CryptoStatistics,
+  SecurityEventListener,
+};
 
 #[cfg(not(feature = "security"))]
 mod no_security;
@@ -206,8 +215,8 @@ pub use dds::{
   readcondition::ReadCondition,
   sampleinfo::{InstanceState, NotAliveGenerationCounts, SampleInfo, SampleState, ViewState},
   statusevents::{
-    DataReaderStatus, DataWriterStatus, DomainParticipantStatusEvent, EndpointDescription,
-    LostReason, ParticipantDescription, StatusEvented,
+    DataReaderStatus, DataWriterStatus, DiscoveryLimit, DomainParticipantStatusEvent,
+    EndpointDescription, LostReason, ParticipantDescription, StatusEvented,
   },
   topic::{Topic, TopicDescription, TopicKind},
   typedesc::TypeDesc,
@@ -241,3 +250,9 @@ pub mod with_key {
 pub mod rpc {
   pub use crate::structure::rpc::*;
 }
+
+#[cfg(feature = "fastdds_statistics")]
+pub use dds::statistics;
+
+#[cfg(feature = "replay")]
+pub use dds::replay;
@@ -1,7 +1,7 @@
 use std::io;
 
 use bytes::Bytes;
-use speedy::{Context, Error, Readable, Writable, Writer};
+use speedy::{Context, Endianness, Error, Readable, Writable, Writer};
 use enumflags2::BitFlags;
 
 use crate::{
@@ -13,6 +13,12 @@ use crate::{
 #[cfg(test)]
 use super::elements::serialized_payload::SerializedPayload;
 
+// Size of DATA-specific header is
+// extraFlags (2) + octetsToInlineQos (2) + readerId (4) + writerId (4) +
+// writerSN (8) = 20 bytes
+// of which 16 bytes is after octetsToInlineQos field.
+const RTPS_V23_DATA_HEADER_SIZE: u16 = 16;
+
 /// This Submessage is sent from an RTPS Writer (NO_KEY or WITH_KEY)
 /// to an RTPS Reader (NO_KEY or WITH_KEY)
 ///
@@ -56,71 +62,53 @@ impl Data {
   /// and expect_payload, which are told on submessage header flags.
 
   pub fn deserialize_data(buffer: &Bytes, flags: BitFlags<DATA_Flags>) -> io::Result<Self> {
-    let mut cursor = io::Cursor::new(&buffer);
+    let mut cursor = io::Cursor::new(&buffer[..]);
     let endianness = endianness_flag(flags.bits());
-    let map_speedy_err = |p: Error| io::Error::new(io::ErrorKind::Other, p);
-
-    let _extra_flags =
-      u16::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor).map_err(map_speedy_err)?;
-    let octets_to_inline_qos =
-      u16::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor).map_err(map_speedy_err)?;
-    let reader_id = EntityId::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
-      .map_err(map_speedy_err)?;
-    let writer_id = EntityId::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
-      .map_err(map_speedy_err)?;
-    let sequence_number =
-      SequenceNumber::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
-        .map_err(map_speedy_err)?;
+
+    let _extra_flags: u16 = read_field(&mut cursor, endianness, "extraFlags")?;
+    let octets_to_inline_qos: u16 = read_field(&mut cursor, endianness, "octetsToInlineQos")?;
+    let reader_id: EntityId = read_field(&mut cursor, endianness, "readerId")?;
+    let writer_id: EntityId = read_field(&mut cursor, endianness, "writerId")?;
+    let sequence_number: SequenceNumber = read_field(&mut cursor, endianness, "writerSN")?;
 
     let expect_qos = flags.contains(DATA_Flags::InlineQos);
     let expect_data = flags.contains(DATA_Flags::Data) || flags.contains(DATA_Flags::Key);
 
-    // size of DATA-specific header above is
-    // extraFlags (2) + octetsToInlineQos (2) + readerId (4) + writerId (4) +
-    // writerSN (8) = 20 bytes
-    // of which 16 bytes is after octetsToInlineQos field.
-    let rtps_v23_data_header_size: u16 = 16;
-    // ... and octets_to_inline_qos must be at least this much, or otherwise inline
-    // Qos (or in case it is absent, the following SerializedPayload) would
-    // overlap with the rtps_v23_data_header fields (readerId, writerId, and
-    // writerSN).
-    if octets_to_inline_qos < rtps_v23_data_header_size {
+    // octets_to_inline_qos must be at least RTPS_V23_DATA_HEADER_SIZE, or
+    // otherwise inline Qos (or in case it is absent, the following
+    // SerializedPayload) would overlap with the rtps_v23_data_header fields
+    // (readerId, writerId, and writerSN).
+    if octets_to_inline_qos < RTPS_V23_DATA_HEADER_SIZE {
       return Err(io::Error::new(
         io::ErrorKind::InvalidData,
         format!("DATA submessage has invalid octets_to_inline_qos={octets_to_inline_qos}."),
       ));
     }
 
-    // We need to check to avoid subtract overflow
-    // https://github.com/jhelovuo/RustDDS/issues/277
-    if octets_to_inline_qos > rtps_v23_data_header_size {
-      let extra_octets = octets_to_inline_qos - rtps_v23_data_header_size;
-      // There may be some extra data between writerSN and inlineQos, if the header is
-      // extended in future versions. But as of RTPS v2.3 , extra_octets should be
-      // always zero.
-
-      // Nevertheless, skip over that extra data, if we are told such exists.
-      cursor.set_position(cursor.position() + u64::from(extra_octets));
-
-      if cursor.position() > buffer.len().try_into().unwrap() {
-        // octets_to_inline_qos told us to skip past the end of the message.
-        // This is a malformed message.
-        return Err(io::Error::new(
-          io::ErrorKind::InvalidData,
-          format!(
-            "DATA submessage octets_to_inline_qos points to byte {}, but message len={}.",
-            cursor.position(),
-            buffer.len()
-          ),
-        ));
-      }
+    // There may be some extra data between writerSN and inlineQos, if the header
+    // is extended in future versions. But as of RTPS v2.3 , extra_octets should
+    // be always zero. Nevertheless, skip over that extra data, if we are told
+    // such exists. The offset is counted from the end of octetsToInlineQos.
+    let inline_qos_start = 4 + usize::from(octets_to_inline_qos);
+    if inline_qos_start > buffer.len() {
+      // octets_to_inline_qos told us to skip past the end of the message.
+      // This is a malformed message.
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+          "DATA submessage octets_to_inline_qos points to byte {}, but message len={}.",
+          inline_qos_start,
+          buffer.len()
+        ),
+      ));
     }
+    cursor.set_position(inline_qos_start as u64);
 
     // read the inline Qos
     let parameter_list = if expect_qos {
       Some(
-        ParameterList::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
-          .map_err(map_speedy_err)?,
+        ParameterList::read_from_cursor(endianness, &mut cursor)
+          .map_err(|e| field_error("inlineQos", buffer.len(), &e))?,
       )
     } else {
       None
@@ -130,7 +118,7 @@ impl Data {
     // alignment padding, but at least a CDR decoder should be able to cope with
     // that.
     let serialized_payload = if expect_data {
-      Some(buffer.clone().split_off(cursor.position() as usize))
+      Some(buffer.slice(cursor.position() as usize..))
     } else {
       None
     };
@@ -183,6 +171,35 @@ impl Data {
   }
 }
 
+// Reads a fixed-size field of a DATA submessage. A message too short for the
+// field is an error naming the field.
+fn read_field<'a, T>(
+  cursor: &mut io::Cursor<&[u8]>,
+  endianness: Endianness,
+  field: &str,
+) -> io::Result<T>
+where
+  T: Readable<'a, Endianness>,
+{
+  let len = cursor.get_ref().len();
+  let remaining = len.saturating_sub(cursor.position() as usize);
+  if remaining < T::minimum_bytes_needed() {
+    return Err(io::Error::new(
+      io::ErrorKind::UnexpectedEof,
+      format!("DATA submessage is too short for {field}. len={len}"),
+    ));
+  }
+  T::read_from_stream_unbuffered_with_ctx(endianness, cursor)
+    .map_err(|e| field_error(field, len, &e))
+}
+
+fn field_error(field: &str, len: usize, e: &Error) -> io::Error {
+  io::Error::new(
+    io::ErrorKind::InvalidData,
+    format!("DATA submessage has invalid {field}: {e}. len={len}"),
+  )
+}
+
 impl<C: Context> Writable<C> for Data {
   fn write_to<T: ?Sized + Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
     // This version of the protocol (2.3) should set all the bits in the extraFlags
@@ -193,7 +210,9 @@ impl<C: Context> Writable<C> for Data {
     // inlineQos SubmessageElement. If the inlineQos SubmessageElement is not
     // present (i.e., the InlineQosFlag is not set), then octetsToInlineQos contains
     // the offset to the next field after the inlineQos.
-    writer.write_u16(16)?;
+    // We write nothing between writerSN and inlineQos, so this is the standard
+    // header size regardless of what follows.
+    writer.write_u16(RTPS_V23_DATA_HEADER_SIZE)?;
 
     writer.write_value(&self.reader_id)?;
     writer.write_value(&self.writer_id)?;
@@ -221,3 +240,309 @@ impl HasEntityIds for Data {
     self.writer_id
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+  use hex_literal::hex;
+
+  use super::*;
+  use crate::{
+    messages::submessages::elements::{inline_qos::InlineQos, parameter::Parameter},
+    structure::{cache_change::ChangeKind, parameter_id::ParameterId},
+  };
+
+  fn data_with_inline_qos() -> Data {
+    let mut inline_qos = ParameterList::new();
+    inline_qos.push(Parameter::new(
+      ParameterId::PID_STATUS_INFO,
+      vec![0, 0, 0, 1],
+    ));
+    Data {
+      reader_id: EntityId::SEDP_BUILTIN_PUBLICATIONS_READER,
+      writer_id: EntityId::SEDP_BUILTIN_PUBLICATIONS_WRITER,
+      writer_sn: SequenceNumber::new(42),
+      inline_qos: Some(inline_qos),
+      serialized_payload: Some(Bytes::from_static(&[0x00, 0x01, 0x00, 0x00, 1, 2, 3, 4])),
+    }
+  }
+
+  fn all_flags() -> BitFlags<DATA_Flags> {
+    DATA_Flags::Endianness | DATA_Flags::InlineQos | DATA_Flags::Data
+  }
+
+  #[test]
+  fn data_round_trip() {
+    let data = data_with_inline_qos();
+    let bytes = Bytes::from(
+      data
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+    );
+    assert_eq!(Data::deserialize_data(&bytes, all_flags()).unwrap(), data);
+  }
+
+  #[test]
+  fn octets_to_inline_qos_is_written_before_inline_qos() {
+    let data = data_with_inline_qos();
+    let inline_qos = data.inline_qos.as_ref().unwrap();
+    for endianness in [Endianness::LittleEndian, Endianness::BigEndian] {
+      let bytes = data.write_to_vec_with_ctx(endianness).unwrap();
+      let octets_to_inline_qos = u16::read_from_buffer_with_ctx(endianness, &bytes[2..4]).unwrap();
+      assert_eq!(octets_to_inline_qos, 16);
+      let inline_qos_start = 4 + usize::from(octets_to_inline_qos);
+      assert!(bytes[inline_qos_start..]
+        .starts_with(&inline_qos.write_to_vec_with_ctx(endianness).unwrap()));
+    }
+  }
+
+  #[test]
+  fn data_without_inline_qos_round_trip() {
+    let data = Data {
+      inline_qos: None,
+      ..data_with_inline_qos()
+    };
+    let flags = DATA_Flags::Endianness | DATA_Flags::Data;
+    let bytes = Bytes::from(
+      data
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+    );
+    assert_eq!(&bytes[2..4], &[16, 0]);
+    assert_eq!(Data::deserialize_data(&bytes, flags).unwrap(), data);
+  }
+
+  #[test]
+  fn non_standard_payload_round_trip() {
+    // Does not start with an encapsulation header
+    let data = Data {
+      serialized_payload: Some(Bytes::from_static(&[0xff, 0xff, 9, 8, 7, 6, 5, 4])),
+      ..data_with_inline_qos()
+    };
+    let flags = all_flags() | DATA_Flags::NonStandardPayload;
+    let bytes = Bytes::from(
+      data
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+    );
+    assert_eq!(Data::deserialize_data(&bytes, flags).unwrap(), data);
+  }
+
+  #[test]
+  fn data_serializes_like_the_capture() {
+    // DATA submessage body from a Wireshark capture of the shapes demo
+    let captured = Bytes::from_static(&hex!(
+      "00 00 10 00 00 00 00 07 00 00 01 02 00 00 00 00
+       5b 00 00 00 00 01 00 00 04 00 00 00 52 45 44 00
+       69 00 00 00 17 00 00 00 1e 00 00 00"
+    ));
+    let flags = DATA_Flags::Endianness | DATA_Flags::Data;
+    let data = Data::deserialize_data(&captured, flags).unwrap();
+    assert_eq!(
+      data
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+      captured
+    );
+
+    let flags = DATA_Flags::Endianness | DATA_Flags::InlineQos | DATA_Flags::Key;
+    let dispose = dispose_body(&hex!("00 01 00 00 07 00 00 00"));
+    let data = Data::deserialize_data(&dispose, flags).unwrap();
+    assert_eq!(
+      data
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+      dispose
+    );
+  }
+
+  #[test]
+  fn truncated_data_is_an_error_naming_the_field() {
+    let data = data_with_inline_qos();
+    let bytes = Bytes::from(
+      data
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+    );
+    let inline_qos_end = 20 + data.inline_qos.as_ref().unwrap().len_serialized();
+
+    for len in 0..inline_qos_end {
+      let field = match len {
+        0..=1 => "extraFlags",
+        2..=3 => "octetsToInlineQos",
+        4..=7 => "readerId",
+        8..=11 => "writerId",
+        12..=19 => "writerSN",
+        _ => "inlineQos",
+      };
+      let err = Data::deserialize_data(&bytes.slice(..len), all_flags())
+        .expect_err("Truncated DATA was accepted");
+      assert!(
+        err.to_string().contains(field),
+        "len={len}: expected {field} in \"{err}\""
+      );
+    }
+  }
+
+  #[test]
+  fn octets_to_inline_qos_past_the_end_is_an_error() {
+    let data = data_with_inline_qos();
+    let mut bytes = data
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+    let past_the_end = u16::try_from(bytes.len()).unwrap();
+    bytes[2..4].copy_from_slice(&past_the_end.to_le_bytes());
+    assert!(Data::deserialize_data(&Bytes::from(bytes), all_flags()).is_err());
+  }
+
+  // The body of data_with_inline_qos() with octetsToInlineQos = 24, so that 8
+  // unknown octets follow writerSN, as a future protocol version may have them.
+  fn body_with_gap(data: &Data, flags: BitFlags<DATA_Flags>) -> Bytes {
+    let standard = Data {
+      inline_qos: data
+        .inline_qos
+        .clone()
+        .filter(|_| flags.contains(DATA_Flags::InlineQos)),
+      ..data.clone()
+    }
+    .write_to_vec_with_ctx(Endianness::LittleEndian)
+    .unwrap();
+    let gap = [0xEE; 8];
+    let mut bytes = [&standard[..20], &gap, &standard[20..]].concat();
+    bytes[2..4].copy_from_slice(&24u16.to_le_bytes());
+    Bytes::from(bytes)
+  }
+
+  #[test]
+  fn extra_octets_before_inline_qos_are_skipped() {
+    let data = data_with_inline_qos();
+    for flags in [
+      DATA_Flags::Endianness | DATA_Flags::InlineQos | DATA_Flags::Data,
+      DATA_Flags::Endianness | DATA_Flags::InlineQos | DATA_Flags::Key,
+      DATA_Flags::Endianness | DATA_Flags::Data,
+      DATA_Flags::Endianness | DATA_Flags::Key,
+    ] {
+      let decoded = Data::deserialize_data(&body_with_gap(&data, flags), flags).unwrap();
+      assert_eq!(decoded.writer_sn, data.writer_sn);
+      if flags.contains(DATA_Flags::InlineQos) {
+        assert_eq!(decoded.inline_qos, data.inline_qos);
+      } else {
+        assert_eq!(decoded.inline_qos, None);
+      }
+      assert_eq!(decoded.serialized_payload, data.serialized_payload);
+    }
+
+    // Inline QoS only, no payload
+    let flags = DATA_Flags::Endianness | DATA_Flags::InlineQos;
+    let decoded = Data::deserialize_data(&body_with_gap(&data, flags), flags).unwrap();
+    assert_eq!(decoded.inline_qos, data.inline_qos);
+    assert_eq!(decoded.serialized_payload, None);
+  }
+
+  #[test]
+  fn octets_to_inline_qos_may_end_the_submessage() {
+    let flags = DATA_Flags::Endianness.into();
+    let data = Data {
+      inline_qos: None,
+      serialized_payload: None,
+      ..data_with_inline_qos()
+    };
+    let mut bytes = body_with_gap(&data, flags).to_vec();
+    assert_eq!(bytes.len(), 28);
+    assert!(Data::deserialize_data(&Bytes::from(bytes.clone()), flags).is_ok());
+
+    bytes[2..4].copy_from_slice(&25u16.to_le_bytes());
+    assert!(Data::deserialize_data(&Bytes::from(bytes), flags).is_err());
+  }
+
+  #[test]
+  fn random_bytes_do_not_panic() {
+    let mut rng = StdRng::seed_from_u64(0x0da7a);
+    let valid = data_with_inline_qos()
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+
+    for _ in 0..20_000 {
+      let flags = BitFlags::<DATA_Flags>::from_bits_truncate(rng.gen());
+      // Either completely random bytes, or a valid message with some bytes
+      // changed, which gets further into the parsing.
+      let bytes = if rng.gen() {
+        let len = rng.gen_range(0..64);
+        (0..len).map(|_| rng.gen()).collect::<Vec<u8>>()
+      } else {
+        let mut bytes = valid.clone();
+        for _ in 0..rng.gen_range(1..4) {
+          let i = rng.gen_range(0..bytes.len());
+          bytes[i] = rng.gen();
+        }
+        bytes.truncate(rng.gen_range(0..=bytes.len()));
+        bytes
+      };
+      let _ = Data::deserialize_data(&Bytes::from(bytes), flags);
+    }
+  }
+
+  // Submessage bodies of a dispose in the form Fast DDS sends them: inline QoS
+  // with PID_KEY_HASH and PID_STATUS_INFO, and either the serialized key
+  // (Key flag) or no payload at all. The instance key is an i32 with value 7.
+  const DISPOSE_INLINE_QOS: [u8; 32] = hex!(
+    "70 00 10 00 00 00 00 07 00 00 00 00 00 00 00 00 00 00 00 00
+     71 00 04 00 00 00 00 01
+     01 00 00 00"
+  );
+
+  fn dispose_body(payload: &[u8]) -> Bytes {
+    let header = hex!(
+      "00 00 10 00
+       00 00 00 00
+       00 00 01 02
+       00 00 00 00 03 00 00 00"
+    );
+    Bytes::from([&header[..], &DISPOSE_INLINE_QOS, payload].concat())
+  }
+
+  fn check_dispose(data: &Data, flags: BitFlags<DATA_Flags>) {
+    assert_eq!(data.writer_sn, SequenceNumber::new(3));
+    let inline_qos = data.inline_qos.as_ref().unwrap();
+    assert_eq!(
+      InlineQos::key_hash(inline_qos).unwrap().unwrap().to_vec(),
+      DISPOSE_INLINE_QOS[4..20].to_vec()
+    );
+    let status_info =
+      InlineQos::status_info(inline_qos, DATA_Flags::cdr_representation_identifier(flags)).unwrap();
+    assert_eq!(status_info.change_kind(), ChangeKind::NotAliveDisposed);
+  }
+
+  #[test]
+  fn dispose_with_serialized_key() {
+    let flags = DATA_Flags::Endianness | DATA_Flags::InlineQos | DATA_Flags::Key;
+    let key_payload = hex!("00 01 00 00 07 00 00 00");
+    let data = Data::deserialize_data(&dispose_body(&key_payload), flags).unwrap();
+    check_dispose(&data, flags);
+    // The key has the same encapsulation as data
+    assert_eq!(
+      data.unwrap_serialized_payload().representation_identifier,
+      RepresentationIdentifier::CDR_LE
+    );
+    assert_eq!(data.unwrap_serialized_payload_value(), &key_payload[4..]);
+  }
+
+  #[test]
+  fn dispose_with_inline_qos_only() {
+    let flags = DATA_Flags::Endianness | DATA_Flags::InlineQos;
+    let data = Data::deserialize_data(&dispose_body(&[]), flags).unwrap();
+    check_dispose(&data, flags);
+    assert_eq!(data.serialized_payload, None);
+  }
+
+  #[test]
+  fn inline_qos_must_end_within_submessage() {
+    let flags = DATA_Flags::Endianness | DATA_Flags::InlineQos;
+    let body = dispose_body(&[]);
+    // Cut the submessage in the middle of PID_STATUS_INFO, and just before
+    // PID_SENTINEL
+    for end in [body.len() - 8, body.len() - 4] {
+      assert!(Data::deserialize_data(&body.slice(..end), flags).is_err());
+    }
+  }
+}
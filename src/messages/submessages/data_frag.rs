@@ -8,6 +8,7 @@ use bytes::Bytes;
 
 use crate::{
   messages::submessages::{elements::parameter_list::ParameterList, submessages::*},
+  serialization::{padding_needed_for_alignment_4, round_up_to_4},
   structure::{
     guid::EntityId,
     sequence_number::{FragmentNumber, SequenceNumber},
@@ -78,17 +79,19 @@ impl DataFrag {
   // "octetsToNextHeader" field in RTPS spec v2.5 Section "9.4.5.1 Submessage
   // Header".
   pub fn len_serialized(&self) -> usize {
-    2 + // extraFlags (unused in RTPS v2.5)
-    2 + // octetsToInlineSos
-    4 + // readerId
-    4 + // writerId
-    8 + // writerSN
-    4 + // fragmentStartingNum
-    2 + // fragmentsInSubmessage
-    2 + // fragmentSize
-    4 + // sampleSize
-    self.inline_qos.as_ref().map(|q| q.len_serialized() ).unwrap_or(0) + // QoS ParameterList
-    self.serialized_payload.len()
+    round_up_to_4(
+      2 + // extraFlags (unused in RTPS v2.5)
+      2 + // octetsToInlineSos
+      4 + // readerId
+      4 + // writerId
+      8 + // writerSN
+      4 + // fragmentStartingNum
+      2 + // fragmentsInSubmessage
+      2 + // fragmentSize
+      4 + // sampleSize
+      self.inline_qos.as_ref().map(|q| q.len_serialized() ).unwrap_or(0) + // QoS ParameterList
+      self.serialized_payload.len(),
+    )
   }
 
   /// Spec talks about (expected) total number of fragments.
@@ -188,10 +191,7 @@ impl DataFrag {
     }
 
     let inline_qos = if expect_qos {
-      Some(
-        ParameterList::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
-          .map_err(map_speedy_err)?,
-      )
+      Some(ParameterList::read_from_cursor(endianness, &mut cursor).map_err(map_speedy_err)?)
     } else {
       None
     };
@@ -272,10 +272,18 @@ impl<C: Context> Writable<C> for DataFrag {
     writer.write_value(&self.fragments_in_submessage)?;
     writer.write_value(&self.fragment_size)?;
     writer.write_value(&self.data_size)?;
-    if self.inline_qos.is_some() && !self.inline_qos.as_ref().unwrap().parameters.is_empty() {
-      writer.write_value(&self.inline_qos)?;
+    // Write the ParameterList itself, not the Option around it: Speedy would
+    // otherwise prefix a presence tag byte, which is not part of the wire format.
+    if let Some(inline_qos) = self.inline_qos.as_ref() {
+      writer.write_value(inline_qos)?;
     }
     writer.write_bytes(&self.serialized_payload)?;
+    // The last fragment, or any fragment of an odd fragment_size, may end
+    // unaligned. Pad so that the next submessage starts at a 4-octet boundary.
+    // The receiver does not copy more than fragment_size or data_size bytes.
+    for _ in 0..padding_needed_for_alignment_4(self.serialized_payload.len()) {
+      writer.write_u8(0)?;
+    }
     Ok(())
   }
 }
@@ -158,15 +158,15 @@ impl StatusInfo {
   }
 
   pub fn change_kind(&self) -> ChangeKind {
-    if self.contains(StatusInfoEnum::Disposed) {
-      // DISPOSED is strongest
-      ChangeKind::NotAliveDisposed
-    } else if self.contains(StatusInfoEnum::Unregistered) {
-      // Checking unregistered second
-      ChangeKind::NotAliveUnregistered
-    } else {
+    match (
+      self.contains(StatusInfoEnum::Disposed),
+      self.contains(StatusInfoEnum::Unregistered),
+    ) {
+      (true, true) => ChangeKind::NotAliveDisposedUnregistered,
+      (true, false) => ChangeKind::NotAliveDisposed,
+      (false, true) => ChangeKind::NotAliveUnregistered,
       // Even if filtered is set it is still alive
-      ChangeKind::Alive
+      (false, false) => ChangeKind::Alive,
     }
   }
 
@@ -232,4 +232,20 @@ mod tests {
       }
     );
   }
+
+  #[test]
+  fn status_info_change_kind() {
+    for (bits, change_kind) in [
+      (0b000, ChangeKind::Alive),
+      (0b100, ChangeKind::Alive), // filtered
+      (0b001, ChangeKind::NotAliveDisposed),
+      (0b010, ChangeKind::NotAliveUnregistered),
+      (0b011, ChangeKind::NotAliveDisposedUnregistered),
+      (0b111, ChangeKind::NotAliveDisposedUnregistered),
+    ] {
+      let status_info =
+        StatusInfo::from_cdr_bytes(&[0, 0, 0, bits], RepresentationIdentifier::CDR_LE).unwrap();
+      assert_eq!(status_info.change_kind(), change_kind, "bits {bits:#05b}");
+    }
+  }
 }
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, io};
 
 use bytes::Bytes;
 use speedy::{Context, Readable, Writable, Writer};
@@ -13,6 +13,11 @@ use crate::{
 /// QoS parameters that may affect the interpretation of the message.
 /// The encapsulation of the parameters follows a mechanism that allows
 /// extensions to the QoS without breaking backwards compatibility.
+///
+/// When reading, parameters that we do not recognize are skipped, unless they
+/// must be understood. A repeated parameter is kept as many times as it
+/// appears, in order. Parameters that have a single value are taken from
+/// their first occurrence.
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct ParameterList {
   pub parameters: Vec<Parameter>,
@@ -45,6 +50,20 @@ impl ParameterList {
     self.parameters = [self.parameters.clone(), other_parameter_list.parameters].concat();
   }
 
+  // Reads a ParameterList from the position of the cursor, without reading
+  // past the end of the cursor buffer, and moves the cursor past the list.
+  pub fn read_from_cursor<T: AsRef<[u8]>>(
+    endianness: speedy::Endianness,
+    cursor: &mut io::Cursor<T>,
+  ) -> Result<Self, speedy::Error> {
+    let buffer = cursor.get_ref().as_ref();
+    let start = buffer.len().min(cursor.position() as usize);
+    let (result, length) =
+      Self::read_with_length_from_buffer_with_ctx(endianness, &buffer[start..]);
+    cursor.set_position((start + length) as u64);
+    result
+  }
+
   pub fn serialize_to_bytes(&self, endianness: speedy::Endianness) -> Result<Bytes, speedy::Error> {
     let b = self.write_to_vec_with_ctx(endianness)?;
     Ok(Bytes::from(b))
@@ -83,6 +102,11 @@ impl<'a, C: Context> Readable<'a, C> for ParameterList {
 
     // loop ends in failure to read something or catching sentinel
     loop {
+      // A reader of a buffer knows if the list was cut short, but a stream
+      // reader runs into its end
+      if reader.can_read_at_least(4) == Some(false) {
+        return Err(speedy::Error::custom("ParameterList ends without PID_SENTINEL").into());
+      }
       let parameter_id = ParameterId::read_from(reader)?;
       let length = u16::read_from(reader)?;
 
@@ -93,9 +117,31 @@ impl<'a, C: Context> Readable<'a, C> for ParameterList {
         return Ok(parameters);
       }
 
+      if reader.can_read_at_least(length.into()) == Some(false) {
+        return Err(
+          speedy::Error::custom(format!(
+            "ParameterList: {parameter_id:?} of length {length} does not fit in the list"
+          ))
+          .into(),
+        );
+      }
+
+      if parameter_id == ParameterId::PID_PAD || !parameter_id.is_known() {
+        if parameter_id.must_understand() {
+          return Err(
+            speedy::Error::custom(format!(
+              "ParameterList: Unknown {parameter_id:?} must be understood. Rejecting the list."
+            ))
+            .into(),
+          );
+        }
+        reader.skip_bytes(length.into())?;
+        continue;
+      }
+
       parameters.parameters.push(Parameter {
         parameter_id,
-        value: reader.read_vec(length as usize)?,
+        value: reader.read_vec(length.into())?,
       });
     }
   }
@@ -108,3 +154,142 @@ pub trait ParameterListable {
     encoding: RepresentationIdentifier,
   ) -> Result<ParameterList, PlCdrSerializeError>;
 }
+
+#[cfg(test)]
+mod tests {
+  use hex_literal::hex;
+  use speedy::Endianness;
+
+  use super::*;
+
+  fn read(bytes: &[u8]) -> Result<ParameterList, speedy::Error> {
+    ParameterList::read_from_buffer_with_ctx(Endianness::LittleEndian, bytes)
+  }
+
+  #[test]
+  fn parameter_list_stops_at_sentinel() {
+    let (list, length) = ParameterList::read_with_length_from_buffer_with_ctx(
+      Endianness::LittleEndian,
+      &hex!(
+        "71 00 04 00 00 00 00 01
+         01 00 00 00
+         ff ff ff ff"
+      ),
+    );
+    assert_eq!(
+      list.unwrap().parameters,
+      [Parameter::create_pid_status_info_parameter(
+        true, false, false
+      )]
+    );
+    assert_eq!(length, 12);
+  }
+
+  #[test]
+  fn parameter_list_skips_unknown_parameters() {
+    let list = read(&hex!(
+      "0f 00 04 00 00 00 00 00
+       00 00 04 00 00 00 00 00
+       71 00 04 00 00 00 00 01
+       05 b0 04 00 01 00 00 00
+       01 00 00 00"
+    ))
+    .unwrap();
+    // PID_DOMAIN_ID, PID_PAD and a vendor-specific parameter are skipped
+    assert_eq!(
+      list.parameters,
+      [Parameter::create_pid_status_info_parameter(
+        true, false, false
+      )]
+    );
+  }
+
+  #[test]
+  fn parameter_list_rejects_unknown_must_understand_parameter() {
+    let error = read(&hex!(
+      "71 00 04 00 00 00 00 01
+       0f 40 04 00 00 00 00 00
+       01 00 00 00"
+    ))
+    .unwrap_err();
+    assert!(error.to_string().contains("must be understood"), "{error}");
+  }
+
+  #[test]
+  fn parameter_list_keeps_repeated_parameters_in_order() {
+    let list = read(&hex!(
+      "71 00 04 00 00 00 00 01
+       71 00 04 00 00 00 00 02
+       01 00 00 00"
+    ))
+    .unwrap();
+    assert_eq!(
+      list.parameters,
+      [
+        Parameter::create_pid_status_info_parameter(true, false, false),
+        Parameter::create_pid_status_info_parameter(false, true, false),
+      ]
+    );
+    assert_eq!(list.to_map()[&ParameterId::PID_STATUS_INFO].len(), 2);
+  }
+
+  #[test]
+  fn parameter_list_requires_sentinel() {
+    for bytes in [
+      &[][..],
+      &hex!("71 00 04 00 00 00 00 01")[..],
+      &hex!("71 00 04 00 00 00 00 01 01 00")[..],
+    ] {
+      let error = read(bytes).unwrap_err();
+      assert!(
+        error.to_string().contains("without PID_SENTINEL"),
+        "{error}"
+      );
+    }
+  }
+
+  #[test]
+  fn parameter_list_does_not_read_past_its_buffer() {
+    let error = read(&hex!("71 00 10 00 00 00 00 01 01 00 00 00")).unwrap_err();
+    assert!(error.to_string().contains("does not fit"), "{error}");
+    // Also when reading from a cursor
+    let bytes = hex!("71 00 08 00 00 00 00 01");
+    let mut cursor = io::Cursor::new(&bytes[..]);
+    assert!(ParameterList::read_from_cursor(Endianness::LittleEndian, &mut cursor).is_err());
+  }
+
+  #[test]
+  fn parameter_list_survives_mutations() {
+    let list = hex!(
+      "71 00 04 00 00 00 00 01
+       70 00 10 00 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f
+       0f 00 04 00 00 00 00 00
+       0f 80 04 00 00 00 00 00
+       01 00 00 00"
+    );
+    // xorshift, so that failures are reproducible
+    let mut state = 0x2545_f491_u32;
+    let mut random = move || {
+      state ^= state << 13;
+      state ^= state >> 17;
+      state ^= state << 5;
+      state as usize
+    };
+    for _ in 0..10_000 {
+      let mut bytes = list.to_vec();
+      for _ in 0..1 + random() % 3 {
+        let i = random() % bytes.len();
+        bytes[i] = random() as u8;
+      }
+      bytes.truncate(random() % (bytes.len() + 1));
+      let mut cursor = io::Cursor::new(&bytes[..]);
+      if let Ok(list) = ParameterList::read_from_cursor(Endianness::LittleEndian, &mut cursor) {
+        assert!(cursor.position() as usize <= bytes.len());
+        assert!(list
+          .parameters
+          .iter()
+          .all(|p| p.parameter_id.is_known() && p.len_serialized() <= bytes.len()));
+      }
+    }
+  }
+}
@@ -1,11 +1,20 @@
-use speedy::{Readable, Writable};
+use std::io;
 
-use crate::structure::locator::Locator;
+use bytes::Bytes;
+use enumflags2::BitFlags;
+use speedy::{Error, Readable};
+
+use crate::{
+  messages::submessages::submessage_flag::{endianness_flag, INFOREPLY_Flags},
+  structure::locator::Locator,
+};
 
 /// This message is sent from an RTPS Reader to an RTPS Writer.
 /// It contains explicit information on where to send a reply
 /// to the Submessages that follow it within the same message.
-#[derive(Debug, PartialEq, Eq, Clone, Readable, Writable)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+// We cannot use the Speedy-derived Writable/Readable impls, because
+// the multicast list is either serialized or not, depending on flags.
 pub struct InfoReply {
   /// Indicates an alternative set of unicast addresses that
   /// the Writer should use to reach the Readers when
@@ -19,3 +28,78 @@ pub struct InfoReply {
   /// Only present when the MulticastFlag is set.
   pub multicast_locator_list: Option<Vec<Locator>>,
 }
+
+impl InfoReply {
+  pub fn deserialize(buffer: &Bytes, flags: BitFlags<INFOREPLY_Flags>) -> io::Result<Self> {
+    let mut cursor = io::Cursor::new(&buffer);
+    let endianness = endianness_flag(flags.bits());
+    let map_speedy_err = |p: Error| io::Error::new(io::ErrorKind::Other, p);
+
+    let unicast_locator_list =
+      Vec::<Locator>::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
+        .map_err(map_speedy_err)?;
+    let multicast_locator_list = if flags.contains(INFOREPLY_Flags::Multicast) {
+      Some(
+        Vec::<Locator>::read_from_stream_unbuffered_with_ctx(endianness, &mut cursor)
+          .map_err(map_speedy_err)?,
+      )
+    } else {
+      None
+    };
+
+    Ok(Self {
+      unicast_locator_list,
+      multicast_locator_list,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{Ipv4Addr, SocketAddrV4};
+
+  use speedy::{Endianness, Writable};
+
+  use super::*;
+  use crate::messages::submessages::{
+    submessage::InterpreterSubmessage, submessage_flag::FromEndianness,
+  };
+
+  fn info_reply(multicast: bool) -> InfoReply {
+    InfoReply {
+      unicast_locator_list: vec![Locator::UdpV4(SocketAddrV4::new(
+        Ipv4Addr::new(192, 168, 1, 10),
+        7411,
+      ))],
+      multicast_locator_list: multicast.then(|| {
+        vec![Locator::UdpV4(SocketAddrV4::new(
+          Ipv4Addr::new(239, 255, 0, 1),
+          7401,
+        ))]
+      }),
+    }
+  }
+
+  #[test]
+  fn info_reply_round_trip() {
+    for endianness in [Endianness::LittleEndian, Endianness::BigEndian] {
+      for multicast in [false, true] {
+        let reply = info_reply(multicast);
+        let mut flags = BitFlags::<INFOREPLY_Flags>::from_endianness(endianness);
+        if multicast {
+          flags.insert(INFOREPLY_Flags::Multicast);
+        }
+        let bytes = InterpreterSubmessage::InfoReply(reply.clone(), flags)
+          .write_to_vec_with_ctx(endianness)
+          .unwrap();
+        // Each LocatorList is a 4-byte count followed by 24-byte locators.
+        // There is no presence tag for the optional multicast list.
+        assert_eq!(bytes.len(), if multicast { 56 } else { 28 });
+        assert_eq!(
+          InfoReply::deserialize(&Bytes::from(bytes), flags).unwrap(),
+          reply
+        );
+      }
+    }
+  }
+}
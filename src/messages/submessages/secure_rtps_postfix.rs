@@ -25,7 +25,7 @@ impl SecureRTPSPostfix {
   pub fn create_submessage(self, endianness: speedy::Endianness) -> SecurityResult<Submessage> {
     let flags: BitFlags<SECURERTPSPOSTFIX_Flags> = BitFlags::from_endianness(endianness);
     self
-      .write_to_vec()
+      .write_to_vec_with_ctx(endianness)
       .map(|bytes| Submessage {
         header: SubmessageHeader {
           kind: SubmessageKind::SRTPS_POSTFIX,
@@ -25,7 +25,7 @@ impl SecureRTPSPrefix {
   pub fn create_submessage(self, endianness: speedy::Endianness) -> SecurityResult<Submessage> {
     let flags: BitFlags<SECURERTPSPREFIX_Flags> = BitFlags::from_endianness(endianness);
     self
-      .write_to_vec()
+      .write_to_vec_with_ctx(endianness)
       .map(|bytes| Submessage {
         header: SubmessageHeader {
           kind: SubmessageKind::SRTPS_PREFIX,
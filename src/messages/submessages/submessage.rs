@@ -109,7 +109,15 @@ impl<C: Context> Writable<C> for InterpreterSubmessage {
     match self {
       InterpreterSubmessage::InfoSource(s, _f) => writer.write_value(s),
       InterpreterSubmessage::InfoDestination(s, _f) => writer.write_value(s),
-      InterpreterSubmessage::InfoReply(s, _f) => writer.write_value(s),
+      InterpreterSubmessage::InfoReply(s, _f) => {
+        // The multicast list is present only if the MulticastFlag is set,
+        // so no Speedy Option tag is written for it.
+        writer.write_value(&s.unicast_locator_list)?;
+        match &s.multicast_locator_list {
+          None => Ok(()),
+          Some(multicast_locator_list) => writer.write_value(multicast_locator_list),
+        }
+      }
       InterpreterSubmessage::InfoTimestamp(s, _f) => match s {
         InfoTimestamp { timestamp: None } => Ok(()), // serialization is empty string
         InfoTimestamp {
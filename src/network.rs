@@ -1,4 +1,6 @@
 pub mod constant;
+#[cfg(feature = "replay")]
+pub mod mock_transport;
 pub mod udp_listener;
 pub mod udp_sender;
 pub mod util;
@@ -0,0 +1,124 @@
+//! In-process replacement for the UDP sockets of a DomainParticipant.
+//!
+//! Used for replaying captured traffic: datagrams are passed to the event loop
+//! of the participant over a channel, and the datagrams that the participant
+//! sends are recorded instead of sent.
+
+use std::{
+  collections::HashMap,
+  io,
+  net::{Ipv4Addr, SocketAddr},
+  sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use mio_06::Token;
+use mio_extras::channel as mio_channel;
+
+use crate::{
+  network::constant::*,
+  rtps::constant::{
+    DISCOVERY_LISTENER_TOKEN, DISCOVERY_MUL_LISTENER_TOKEN, USER_TRAFFIC_LISTENER_TOKEN,
+    USER_TRAFFIC_MUL_LISTENER_TOKEN,
+  },
+  structure::locator::Locator,
+};
+
+/// A datagram that a DomainParticipant sent over a mock transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentDatagram {
+  pub locator: Locator,
+  pub payload: Bytes,
+}
+
+type SentDatagrams = Arc<Mutex<Vec<SentDatagram>>>;
+
+/// The end of a mock transport that is given to the DomainParticipant.
+pub(crate) struct MockTransport {
+  pub received: mio_channel::Receiver<Bytes>,
+  pub sent: MockSender,
+}
+
+/// Records the datagrams sent by the participant.
+#[derive(Debug)]
+pub(crate) struct MockSender {
+  sent: SentDatagrams,
+}
+
+impl MockSender {
+  pub fn send_to_locator(&self, buffer: &[u8], locator: &Locator) {
+    self.sent.lock().unwrap().push(SentDatagram {
+      locator: *locator,
+      payload: Bytes::copy_from_slice(buffer),
+    });
+  }
+}
+
+/// The end of a mock transport that plays the role of the network.
+pub(crate) struct MockNetwork {
+  received: mio_channel::Sender<Bytes>,
+  sent: SentDatagrams,
+}
+
+impl MockNetwork {
+  /// Passes a datagram to the participant
+  pub fn deliver(&self, payload: Bytes) -> io::Result<()> {
+    self.received.send(payload).map_err(|e| {
+      io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        format!("DomainParticipant is not receiving: {e:?}"),
+      )
+    })
+  }
+
+  /// Removes and returns the datagrams sent by the participant so far
+  pub fn take_sent(&self) -> Vec<SentDatagram> {
+    std::mem::take(&mut self.sent.lock().unwrap())
+  }
+}
+
+pub(crate) fn mock_transport() -> (MockTransport, MockNetwork) {
+  let (sender, receiver) = mio_channel::channel();
+  let sent = SentDatagrams::default();
+  (
+    MockTransport {
+      received: receiver,
+      sent: MockSender {
+        sent: Arc::clone(&sent),
+      },
+    },
+    MockNetwork {
+      received: sender,
+      sent,
+    },
+  )
+}
+
+/// Locators that a participant using a mock transport advertises: the default
+/// ports of participant 0 on the loopback interface. Nothing listens on them.
+pub(crate) fn mock_self_locators(domain_id: u16) -> HashMap<Token, Vec<Locator>> {
+  let locator =
+    |address: Ipv4Addr, port| vec![Locator::from(SocketAddr::new(address.into(), port))];
+  let multicast_group = Ipv4Addr::new(239, 255, 0, 1);
+  HashMap::from([
+    (
+      DISCOVERY_MUL_LISTENER_TOKEN,
+      locator(multicast_group, spdp_well_known_multicast_port(domain_id)),
+    ),
+    (
+      DISCOVERY_LISTENER_TOKEN,
+      locator(
+        Ipv4Addr::LOCALHOST,
+        spdp_well_known_unicast_port(domain_id, 0),
+      ),
+    ),
+    (
+      USER_TRAFFIC_MUL_LISTENER_TOKEN,
+      locator(multicast_group, user_traffic_multicast_port(domain_id)),
+    ),
+    (
+      USER_TRAFFIC_LISTENER_TOKEN,
+      locator(Ipv4Addr::LOCALHOST, user_traffic_unicast_port(domain_id, 0)),
+    ),
+  ])
+}
@@ -18,6 +18,10 @@ use crate::{
 const MAX_MESSAGE_SIZE: usize = 64 * 1024; // This is max we can get from UDP.
 const MESSAGE_BUFFER_ALLOCATION_CHUNK: usize = 256 * 1024; // must be >= MAX_MESSAGE_SIZE
 static_assertions::const_assert!(MESSAGE_BUFFER_ALLOCATION_CHUNK > MAX_MESSAGE_SIZE);
+// Socket receive buffer size requested from the OS. A large sample arrives as a
+// burst of DATA_FRAGs, which overflows the usual default (~200 KiB) and is
+// then lost. The OS may limit this, e.g. Linux to net.core.rmem_max.
+const SOCKET_RECEIVE_BUFFER_SIZE: usize = 4 * 1024 * 1024;
 
 /// Listens to messages coming to specified host port combination.
 /// Only messages from added listen addressed are read when get_all_messages is
@@ -66,6 +70,10 @@ impl UDPListener {
       }
     }
 
+    if let Err(e) = raw_socket.set_recv_buffer_size(SOCKET_RECEIVE_BUFFER_SIZE) {
+      warn!("new_socket - cannot set receive buffer size: {e:?}");
+    }
+
     let address = SocketAddr::new(
       host
         .parse()
@@ -12,6 +12,8 @@ use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use local_ip_address::list_afinet_netifas;
 
 use crate::{network::util::get_local_multicast_ip_addrs, structure::locator::Locator};
+#[cfg(feature = "replay")]
+use crate::network::mock_transport::MockSender;
 
 // We need one multicast sender socket per interface
 
@@ -19,6 +21,9 @@ use crate::{network::util::get_local_multicast_ip_addrs, structure::locator::Loc
 pub struct UDPSender {
   unicast_socket: mio_08::net::UdpSocket,
   multicast_sockets: Vec<mio_08::net::UdpSocket>,
+  // If set, datagrams are recorded here instead of sent
+  #[cfg(feature = "replay")]
+  mock_sender: Option<MockSender>,
 }
 
 impl UDPSender {
@@ -65,11 +70,27 @@ impl UDPSender {
     let sender = Self {
       unicast_socket,
       multicast_sockets,
+      #[cfg(feature = "replay")]
+      mock_sender: None,
     };
     info!("UDPSender::new() --> {:?}", sender);
     Ok(sender)
   }
 
+  /// A sender that records the datagrams in `mock_sender` instead of sending
+  /// them.
+  #[cfg(feature = "replay")]
+  pub(crate) fn new_mock(mock_sender: MockSender) -> io::Result<Self> {
+    // The socket is never sent from, but the field needs one.
+    let unicast_socket =
+      mio_08::net::UdpSocket::bind(SocketAddr::new(std::net::Ipv4Addr::LOCALHOST.into(), 0))?;
+    Ok(Self {
+      unicast_socket,
+      multicast_sockets: Vec::new(),
+      mock_sender: Some(mock_sender),
+    })
+  }
+
   #[cfg(test)]
   pub fn new_with_random_port() -> io::Result<Self> {
     Self::new(0)
@@ -105,6 +126,11 @@ impl UDPSender {
   }
 
   pub fn send_to_locator(&self, buffer: &[u8], locator: &Locator) {
+    #[cfg(feature = "replay")]
+    if let Some(mock_sender) = &self.mock_sender {
+      mock_sender.send_to_locator(buffer, locator);
+      return;
+    }
     if buffer.len() > 1500 {
       warn!("send_to_locator: Message size = {}", buffer.len());
     }
@@ -33,6 +33,11 @@ impl ROSDiscoveryTopic {
     lifespan: Some(Lifespan {
       duration: Duration::INFINITE,
     }),
+    partition: None,
+    stale_sample_filter: None,
+    clock_skew_tolerance: None,
+    #[cfg(feature = "history_spill")]
+    history_spill: None,
     #[cfg(feature = "security")]
     property: None,
   };
@@ -71,6 +76,11 @@ impl ParameterEventsTopic {
     history: Some(History::KeepLast { depth: 1 }),
     resource_limits: None,
     lifespan: None,
+    partition: None,
+    stale_sample_filter: None,
+    clock_skew_tolerance: None,
+    #[cfg(feature = "history_spill")]
+    history_spill: None,
     #[cfg(feature = "security")]
     property: None,
   };
@@ -115,6 +125,11 @@ impl RosOutTopic {
     lifespan: Some(Lifespan {
       duration: Duration::from_secs(10),
     }),
+    partition: None,
+    stale_sample_filter: None,
+    clock_skew_tolerance: None,
+    #[cfg(feature = "history_spill")]
+    history_spill: None,
     #[cfg(feature = "security")]
     property: None,
   };
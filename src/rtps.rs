@@ -3,7 +3,11 @@ pub(crate) mod constant;
 
 pub(crate) mod dp_event_loop;
 pub(crate) mod fragment_assembler;
+#[cfg(feature = "history_spill")]
+pub(crate) mod history_spill;
 pub(crate) mod message_receiver;
+#[cfg(feature = "security")]
+pub(crate) mod parked_secure_submessages;
 pub(crate) mod reader;
 pub(crate) mod rtps_reader_proxy;
 pub(crate) mod rtps_writer_proxy;
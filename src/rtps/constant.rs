@@ -15,6 +15,27 @@ pub const PREEMPTIVE_ACKNACK_PERIOD: Duration = Duration::from_secs(5);
 
 pub const CACHE_CLEAN_PERIOD: Duration = Duration::from_secs(4);
 
+// Bounds for the secure submessages waiting for the key material of their
+// sender, see ParkedSecureSubmessages
+pub const MAX_PARKED_SECURE_SUBMESSAGES_PER_KEY_ID: usize = 64;
+pub const MAX_PARKED_SECURE_SUBMESSAGES: usize = 256;
+pub const MAX_SECURE_SUBMESSAGE_PARKING_TIME: Duration = Duration::from_secs(5);
+
+// Size of the fragments of large samples, unless configured otherwise in
+// DomainParticipantBuilder
+pub const DEFAULT_FRAGMENT_SIZE: u16 = 1024;
+
+// Largest fragment size that DomainParticipantBuilder accepts. A fragment must
+// fit in one UDP datagram, at most 65507 bytes over IPv4, together with the
+// RTPS header, INFO_TS, INFO_DST, the DATA_FRAG header with its inline QoS, and
+// any security protection. 1024 bytes are left for those.
+pub const MAX_FRAGMENT_SIZE: u16 = 65507 - 1024;
+
+// Size limit of the RTPS messages into which Writers pack their submessages,
+// unless configured otherwise in DomainParticipantBuilder. This is the
+// Ethernet MTU less the IPv6 and UDP headers.
+pub const DEFAULT_MAX_MESSAGE_SIZE: u16 = 1452;
+
 // RTPS spec Section 8.4.7.1.1  "Default Timing-Related Values"
 pub const NACK_RESPONSE_DELAY: Duration = Duration::from_millis(200);
 pub const NACK_SUPPRESSION_DURATION: Duration = Duration::from_millis(0);
@@ -203,6 +224,9 @@ pub const DISCOVERY_LISTENER_TOKEN: Token = Token(6 + PTB);
 pub const DISCOVERY_MUL_LISTENER_TOKEN: Token = Token(7 + PTB);
 pub const USER_TRAFFIC_LISTENER_TOKEN: Token = Token(8 + PTB);
 pub const USER_TRAFFIC_MUL_LISTENER_TOKEN: Token = Token(9 + PTB);
+// Replaces the listeners above when the DomainParticipant uses a mock transport
+#[cfg(feature = "replay")]
+pub const MOCK_TRANSPORT_TOKEN: Token = Token(3 + PTB);
 
 pub const ADD_READER_TOKEN: Token = Token(10 + PTB);
 pub const REMOVE_READER_TOKEN: Token = Token(11 + PTB);
@@ -32,6 +32,7 @@ use crate::{
     writer::{Writer, WriterIngredients},
   },
   structure::{
+    clock::ClockJumpDetector,
     dds_cache::DDSCache,
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, TokenDecode, GUID},
@@ -45,12 +46,18 @@ use crate::{
 };
 #[cfg(not(feature = "security"))]
 use crate::no_security::security_plugins::SecurityPluginsHandle;
+#[cfg(feature = "fastdds_statistics")]
+use crate::dds::statistics::ProtocolStatistics;
+#[cfg(feature = "replay")]
+use crate::network::mock_transport::MockTransport;
 
 #[derive(Clone, Debug)]
 pub struct DomainInfo {
   pub domain_participant_guid: GUID,
   pub domain_id: u16,
   pub participant_id: u16,
+  #[cfg(feature = "fastdds_statistics")]
+  pub statistics: Arc<ProtocolStatistics>,
 }
 
 pub(crate) enum EventLoopCommand {
@@ -64,6 +71,9 @@ pub struct DPEventLoop {
   dds_cache: Arc<RwLock<DDSCache>>,
   discovery_db: Arc<RwLock<DiscoveryDB>>,
   udp_listeners: HashMap<Token, UDPListener>,
+  // Datagrams from a mock transport, which replaces the listeners
+  #[cfg(feature = "replay")]
+  mock_transport_receiver: Option<mio_channel::Receiver<bytes::Bytes>>,
   message_receiver: MessageReceiver, // This contains our Readers
 
   // If security is enabled, this contains the security plugins
@@ -111,6 +121,7 @@ impl DPEventLoop {
     spdp_liveness_sender: mio_channel::SyncSender<GuidPrefix>,
     participant_status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
     security_plugins_opt: Option<SecurityPluginsHandle>,
+    #[cfg(feature = "replay")] mock_transport: Option<MockTransport>,
   ) -> Self {
     #[cfg(not(feature = "security"))]
     let _dummy = _discovery_command_sender;
@@ -192,6 +203,30 @@ impl DPEventLoop {
       )
       .expect("Failed to register reader update notification.");
 
+    #[cfg(feature = "replay")]
+    let (mock_transport_receiver, mock_sender) = match mock_transport {
+      Some(MockTransport { received, sent }) => {
+        poll
+          .register(
+            &received,
+            MOCK_TRANSPORT_TOKEN,
+            Ready::readable(),
+            PollOpt::edge(),
+          )
+          .expect("Failed to register mock transport.");
+        (Some(received), Some(sent))
+      }
+      None => (None, None),
+    };
+
+    #[cfg(feature = "replay")]
+    let udp_sender = match mock_sender {
+      Some(mock_sender) => UDPSender::new_mock(mock_sender),
+      // port number 0 means OS chooses an available port number.
+      None => UDPSender::new(0),
+    }
+    .expect("UDPSender construction fail");
+    #[cfg(not(feature = "replay"))]
     // port number 0 means OS chooses an available port number.
     let udp_sender = UDPSender::new(0).expect("UDPSender construction fail"); // TODO
 
@@ -204,6 +239,8 @@ impl DPEventLoop {
       dds_cache,
       discovery_db,
       udp_listeners,
+      #[cfg(feature = "replay")]
+      mock_transport_receiver,
       udp_sender: Rc::new(udp_sender),
       message_receiver: MessageReceiver::new(
         participant_guid_prefix,
@@ -255,6 +292,7 @@ impl DPEventLoop {
       )
       .unwrap();
     let mut poll_alive = Instant::now();
+    let mut clock_jump_detector = ClockJumpDetector::new();
     let mut ev_wrapper = self;
     let mut preparing_to_stop = false;
 
@@ -271,6 +309,13 @@ impl DPEventLoop {
         debug!("Poll loop alive");
         poll_alive = now;
       }
+      if let Some(step) = clock_jump_detector.check() {
+        warn!(
+          "The system clock was stepped by {:?}. Timers use the monotonic clock and are not \
+           affected, but source timestamps are.",
+          step
+        );
+      }
 
       if events.is_empty() {
         debug!("dp_event_loop idling.");
@@ -328,6 +373,16 @@ impl DPEventLoop {
                   ev_wrapper.message_receiver.handle_received_packet(&packet);
                 }
               }
+              #[cfg(feature = "replay")]
+              MOCK_TRANSPORT_TOKEN => {
+                while let Some(Ok(packet)) = ev_wrapper
+                  .mock_transport_receiver
+                  .as_ref()
+                  .map(mio_channel::Receiver::try_recv)
+                {
+                  ev_wrapper.message_receiver.handle_received_packet(&packet);
+                }
+              }
               ADD_READER_TOKEN | REMOVE_READER_TOKEN => {
                 ev_wrapper.handle_reader_action(&event);
               }
@@ -613,8 +668,6 @@ impl DPEventLoop {
           .available_builtin_endpoints
           .contains(*endpoint)
         {
-          let reader_proxy = discovered_participant.as_reader_proxy(true, Some(*reader_eid));
-
           // Get the QoS for the built-in topic from the local writer
           let mut qos = writer.qos();
           // special case by RTPS 2.3 spec Section
@@ -628,6 +681,9 @@ impl DPEventLoop {
             qos.reliability = Some(policy::Reliability::BestEffort);
           };
 
+          // The proxy needs the QoS too, e.g. for waiting for acknowledgments
+          let reader_proxy =
+            discovered_participant.as_reader_proxy(true, Some(*reader_eid), qos.clone());
           writer.update_reader_proxy(&reader_proxy, &qos);
           debug!(
             "update_discovery writer - endpoint {:?} - {:?}",
@@ -684,12 +740,25 @@ impl DPEventLoop {
       reader.participant_lost(participant_guid_prefix);
     }
 
+    self
+      .dds_cache
+      .write()
+      .unwrap()
+      .forget_participant_sequence_numbers(participant_guid_prefix);
+
+    // If the participant is already back in Discovery DB, it has restarted, and
+    // the security plugins hold the state of the new authentication instead.
     #[cfg(feature = "security")]
     if let Some(security_plugins_handle) = &self.security_plugins_opt {
-      security_plugins_handle
-        .get_plugins()
-        .unregister_remote_participant(&participant_guid_prefix)
-        .unwrap_or_else(|e| error!("{e}"));
+      if discovery_db_read(&self.discovery_db)
+        .find_participant_proxy(participant_guid_prefix)
+        .is_none()
+      {
+        security_plugins_handle
+          .get_plugins()
+          .unregister_remote_participant(&participant_guid_prefix)
+          .unwrap_or_else(|e| error!("{e}"));
+      }
     }
   }
 
@@ -714,6 +783,7 @@ impl DPEventLoop {
             .subscription_topic_data
             .security_info()
             .clone();
+          let remote_has_security = remote_reader_sec_info_opt.is_some();
 
           let compatible = check_are_endpoints_securities_compatible(
             local_writer_sec_info_opt,
@@ -727,6 +797,9 @@ impl DPEventLoop {
               remote_reader_guid
             );
             false // match_to_reader
+          } else if !remote_has_security {
+            // An unprotected local writer needs no keys
+            true // match_to_reader
           } else {
             // Signal Secure discovery to exchange keys with the remote
             // TODO: do this only at first encounter with the remote / before keys have been
@@ -788,6 +861,7 @@ impl DPEventLoop {
             .ok();
           let remote_writer_sec_info_opt =
             remote_writer.publication_topic_data.security_info.clone();
+          let remote_has_security = remote_writer_sec_info_opt.is_some();
 
           let compatible = check_are_endpoints_securities_compatible(
             local_reader_sec_info_opt,
@@ -802,6 +876,9 @@ impl DPEventLoop {
               remote_writer_guid
             );
             false // match_to_writer
+          } else if !remote_has_security {
+            // An unprotected local reader needs no keys
+            true // match_to_writer
           } else {
             // Signal Secure discovery to exchange keys with the remote
             // TODO: do this only at first encounter with the remote / before keys have been
@@ -861,6 +938,10 @@ impl DPEventLoop {
       timer,
       self.participant_status_sender.clone(),
     );
+    #[cfg(feature = "fastdds_statistics")]
+    if new_reader.guid().entity_id.entity_kind.is_user_defined() {
+      new_reader.set_statistics(self.domain_info.statistics.clone());
+    }
 
     // Non-timed action polling
     self
@@ -891,6 +972,9 @@ impl DPEventLoop {
           error!("Cannot deregister data_reader_command_receiver: {e:?}");
         });
 
+      #[cfg(feature = "fastdds_statistics")]
+      self.domain_info.statistics.remove_entity(reader_guid);
+
       #[cfg(feature = "security")]
       if let Some(plugins_handle) = self.security_plugins_opt.as_ref() {
         // Security is enabled. Unregister the reader with the crypto plugin.
@@ -918,12 +1002,17 @@ impl DPEventLoop {
       )
       .expect("Writer heartbeat timer channel registration failed!!");
 
-    let new_writer = Writer::new(
+    #[allow(unused_mut)] // mutable only with statistics
+    let mut new_writer = Writer::new(
       writer_ing,
       self.udp_sender.clone(),
       timer,
       self.participant_status_sender.clone(),
     );
+    #[cfg(feature = "fastdds_statistics")]
+    if new_writer.guid().entity_id.entity_kind.is_user_defined() {
+      new_writer.set_statistics(self.domain_info.statistics.clone());
+    }
 
     self
       .poll
@@ -949,6 +1038,9 @@ impl DPEventLoop {
         .deregister(&w.timed_event_timer)
         .unwrap_or_else(|e| error!("Deregister fail (writer timer) {e:?}"));
 
+      #[cfg(feature = "fastdds_statistics")]
+      self.domain_info.statistics.remove_entity(*writer_guid);
+
       #[cfg(feature = "security")]
       if let Some(plugins_handle) = self.security_plugins_opt.as_ref() {
         // Security is enabled. Unregister the writer with the crypto plugin.
@@ -1022,8 +1114,16 @@ fn check_are_endpoints_securities_compatible(
       // Neither has security info. Pass?
       return true;
     }
-    (Some(_info), None) | (None, Some(_info)) => {
-      // Only one of the endpoints has security info. Reject.
+    (Some(local_info), None) => {
+      // The remote does not use security, e.g. it belongs to an Unauthenticated
+      // participant. It can communicate only with a local endpoint that does
+      // not need keys. Discovery has already checked access control.
+      return !local_info
+        .endpoint_security_attributes
+        .is_crypto_protected();
+    }
+    (None, Some(_info)) => {
+      // Only the remote has security info. Reject.
       return false;
     }
     (Some(local_info), Some(remote_info)) => (local_info, remote_info),
@@ -1111,6 +1211,8 @@ mod tests {
       domain_participant_guid: GUID::default(),
       domain_id: 0,
       participant_id: 0,
+      #[cfg(feature = "fastdds_statistics")]
+      statistics: Arc::new(ProtocolStatistics::new()),
     };
 
     let (sender_stop, receiver_stop) = mio_channel::channel::<i32>();
@@ -1145,6 +1247,8 @@ mod tests {
         spdp_liveness_sender,
         participant_status_sender,
         None,
+        #[cfg(feature = "replay")]
+        None,
       );
       dp_event_loop
         .poll
@@ -14,6 +14,7 @@ use crate::{
   },
   structure::{
     cache_change::ChangeKind,
+    clock,
     sequence_number::{FragmentNumber, SequenceNumber},
     time::Timestamp,
   },
@@ -51,7 +52,7 @@ impl AssemblyBuffer {
 
     let fragment_count = usize::from(datafrag.total_number_of_fragments());
 
-    let now = Timestamp::now();
+    let now = clock::local_timestamp();
 
     Self {
       buffer_bytes,
@@ -136,7 +137,7 @@ impl AssemblyBuffer {
     for f in 0..frags_in_submessage {
       self.received_bitmap.set(start_frag_from_0 + f, true);
     }
-    self.modified_time = Timestamp::now();
+    self.modified_time = clock::local_timestamp();
   }
 
   pub fn is_complete(&self) -> bool {
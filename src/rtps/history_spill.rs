@@ -0,0 +1,705 @@
+// Disk-backed storage for the oldest part of a Writer history, see the
+// HistorySpill QoS policy.
+//
+// The store is a single append-only file per Writer. Each record is
+//
+//   body length (u32, little endian) | MD5 of the body (16 bytes) | body
+//
+// where the body is a speedy-serialized SpilledChange. An index in memory maps
+// the history timestamps of the stored changes to their records. Records of
+// changes removed from the history are only marked dead, and compaction
+// rewrites the file without them. Compaction runs in a worker thread, so that
+// the Writer is not held up by it. The Writer keeps using the old file
+// meanwhile, and the compacted file replaces it when the worker has finished.
+//
+// The file does not outlive the Writer: it is created empty and removed on
+// drop. Crash safety is limited to the torn tail: a record that was not
+// completely written, or does not match its checksum, is never returned.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  fs::{self, File, OpenOptions},
+  io::{self, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+  thread::{self, JoinHandle},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use speedy::{Endianness, Readable, Writable};
+use bytes::Bytes;
+
+use crate::{
+  dds::{
+    ddsdata::DDSData,
+    key::KeyHash,
+    with_key::datawriter::{WriteOptions, WriteOptionsBuilder},
+  },
+  messages::submessages::elements::serialized_payload::SerializedPayload,
+  serialization::RepresentationIdentifier,
+  structure::{
+    cache_change::{CacheChange, ChangeKind},
+    guid::GUID,
+    rpc::SampleIdentity,
+    sequence_number::SequenceNumber,
+    time::Timestamp,
+  },
+};
+
+const RECORD_HEADER_LENGTH: u64 = 4 + 16;
+
+// Dead bytes are not worth a rewrite of the file below this
+const MIN_COMPACTION_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone, Copy)]
+struct RecordLocation {
+  offset: u64,
+  length: u64, // including the header
+}
+
+pub(crate) struct SpillStore {
+  path: PathBuf,
+  file: File,
+  end: u64,
+  index: BTreeMap<Timestamp, RecordLocation>,
+  live_bytes: u64,
+  dead_bytes: u64,
+  compaction: Option<JoinHandle<io::Result<CompactedFile>>>,
+}
+
+// The result of the compaction worker: a file with the records that were live
+// when the compaction started
+struct CompactedFile {
+  file: File,
+  end: u64,
+  // New locations of the records, by their offset in the old file
+  relocated: HashMap<u64, RecordLocation>,
+}
+
+impl SpillStore {
+  // Creates an empty store for the given Writer in the directory
+  pub fn create(directory: &Path, writer_guid: GUID) -> io::Result<Self> {
+    fs::create_dir_all(directory)?;
+    let file_name: String = writer_guid
+      .to_bytes()
+      .iter()
+      .map(|byte| format!("{byte:02x}"))
+      .collect();
+    let path = directory.join(file_name).with_extension("spill");
+    let file = Self::open_file(&path, true)?;
+    Ok(Self {
+      path,
+      file,
+      end: 0,
+      index: BTreeMap::new(),
+      live_bytes: 0,
+      dead_bytes: 0,
+      compaction: None,
+    })
+  }
+
+  fn open_file(path: &Path, truncate: bool) -> io::Result<File> {
+    OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(truncate)
+      .open(path)
+  }
+
+  pub fn contains(&self, timestamp: &Timestamp) -> bool {
+    self.index.contains_key(timestamp)
+  }
+
+  // Size of the file in bytes, including dead records
+  #[cfg(test)]
+  fn file_size(&self) -> u64 {
+    self.end
+  }
+
+  // Appends a change to the end of the file. If writing fails, the file is
+  // truncated back, so that no torn record is left behind.
+  pub fn append(&mut self, timestamp: Timestamp, cache_change: &CacheChange) -> io::Result<()> {
+    let body = SpilledChange::from(cache_change)
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let body_length = u32::try_from(body.len())
+      .map_err(|_| io::Error::new(io::ErrorKind::Other, "change is too large to spill"))?;
+
+    let mut record = Vec::with_capacity(RECORD_HEADER_LENGTH as usize + body.len());
+    record.extend_from_slice(&body_length.to_le_bytes());
+    record.extend_from_slice(&md5::compute(&body).0);
+    record.extend_from_slice(&body);
+
+    let location = RecordLocation {
+      offset: self.end,
+      length: record.len() as u64,
+    };
+    let write_result = self
+      .file
+      .seek(SeekFrom::Start(location.offset))
+      .and_then(|_| self.file.write_all(&record));
+    if let Err(e) = write_result {
+      if let Err(truncate_error) = self.file.set_len(self.end) {
+        error!(
+          "Cannot truncate a failed record from {:?}: {truncate_error}",
+          self.path
+        );
+      }
+      return Err(e);
+    }
+
+    self.end += location.length;
+    self.live_bytes += location.length;
+    if let Some(old) = self.index.insert(timestamp, location) {
+      self.live_bytes -= old.length;
+      self.dead_bytes += old.length;
+    }
+    Ok(())
+  }
+
+  // Reads a change back. Returns None if the change is not in the store, or
+  // its record is torn or corrupted.
+  pub fn read(&self, timestamp: &Timestamp) -> Option<CacheChange> {
+    let location = self.index.get(timestamp)?;
+    match Self::read_record(&self.file, *location) {
+      Ok(cache_change) => Some(cache_change),
+      Err(e) => {
+        error!(
+          "Cannot read a spilled change from {:?} at offset {}: {e}",
+          self.path, location.offset
+        );
+        None
+      }
+    }
+  }
+
+  fn read_record(mut file: &File, location: RecordLocation) -> io::Result<CacheChange> {
+    let mut record = vec![0; location.length as usize];
+    file.seek(SeekFrom::Start(location.offset))?;
+    file.read_exact(&mut record)?;
+
+    let (header, body) = record.split_at(RECORD_HEADER_LENGTH as usize);
+    let body_length = u32::from_le_bytes(header[..4].try_into().unwrap());
+    if body_length as usize != body.len() || md5::compute(body).0 != header[4..] {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "torn or corrupted record",
+      ));
+    }
+    SpilledChange::read_from_buffer_with_ctx(Endianness::LittleEndian, body)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+      .and_then(CacheChange::try_from)
+  }
+
+  // Marks the records of all changes before the timestamp dead
+  pub fn remove_before(&mut self, timestamp: &Timestamp) {
+    let kept = self.index.split_off(timestamp);
+    let removed = std::mem::replace(&mut self.index, kept);
+    for location in removed.values() {
+      self.live_bytes -= location.length;
+      self.dead_bytes += location.length;
+    }
+  }
+
+  pub fn needs_compaction(&self) -> bool {
+    self.dead_bytes >= MIN_COMPACTION_BYTES && self.dead_bytes > self.live_bytes
+  }
+
+  fn compacted_path(&self) -> PathBuf {
+    self.path.with_extension("spill.compact")
+  }
+
+  // To be called periodically. Takes the result of a finished compaction into
+  // use, or starts a compaction if one is needed.
+  pub fn compact_in_background(&mut self) -> io::Result<()> {
+    if self.compaction.is_some() {
+      self.finish_compaction(false)
+    } else if self.needs_compaction() {
+      self.start_compaction()
+    } else {
+      Ok(())
+    }
+  }
+
+  // Starts copying the live records to a new file in a worker thread. The
+  // worker reads the old file through a handle of its own, so the store can be
+  // used meanwhile.
+  fn start_compaction(&mut self) -> io::Result<()> {
+    let path = self.path.clone();
+    let compacted_path = self.compacted_path();
+    let live_records: Vec<RecordLocation> = self.index.values().copied().collect();
+    let worker = thread::Builder::new()
+      .name("RustDDS history spill compaction".to_string())
+      .spawn(move || Self::write_compacted_file(&path, &compacted_path, &live_records))?;
+    self.compaction = Some(worker);
+    Ok(())
+  }
+
+  fn write_compacted_file(
+    path: &Path,
+    compacted_path: &Path,
+    live_records: &[RecordLocation],
+  ) -> io::Result<CompactedFile> {
+    let source = File::open(path)?;
+    let mut compacted = CompactedFile {
+      file: Self::open_file(compacted_path, true)?,
+      end: 0,
+      relocated: HashMap::with_capacity(live_records.len()),
+    };
+    let mut buffer = Vec::new();
+    for location in live_records {
+      compacted.copy_record(&source, *location, &mut buffer)?;
+    }
+    Ok(compacted)
+  }
+
+  // Replaces the file with the compacted one, if the worker has finished or
+  // `wait` is set. Records appended since the compaction started are copied
+  // to the compacted file first, and records removed since then are left
+  // there as dead.
+  fn finish_compaction(&mut self, wait: bool) -> io::Result<()> {
+    match &self.compaction {
+      Some(worker) if wait || worker.is_finished() => (),
+      _ => return Ok(()),
+    }
+    let worker = self.compaction.take().unwrap();
+    let compacted_path = self.compacted_path();
+
+    let result = worker
+      .join()
+      .unwrap_or_else(|_| {
+        Err(io::Error::new(
+          io::ErrorKind::Other,
+          "compaction worker panicked",
+        ))
+      })
+      .and_then(|mut compacted| {
+        let mut index = BTreeMap::new();
+        let mut buffer = Vec::new();
+        for (timestamp, location) in &self.index {
+          let new_location = match compacted.relocated.get(&location.offset) {
+            Some(new_location) => *new_location,
+            None => compacted.copy_record(&self.file, *location, &mut buffer)?,
+          };
+          index.insert(*timestamp, new_location);
+        }
+        fs::rename(&compacted_path, &self.path)?;
+        Ok((compacted, index))
+      });
+    let (compacted, index) = match result {
+      Ok(result) => result,
+      Err(e) => {
+        let _ = fs::remove_file(&compacted_path);
+        return Err(e);
+      }
+    };
+
+    debug!(
+      "Compacted {:?} from {} to {} bytes",
+      self.path, self.end, compacted.end
+    );
+    self.live_bytes = index.values().map(|location| location.length).sum();
+    self.dead_bytes = compacted.end - self.live_bytes;
+    self.file = compacted.file;
+    self.end = compacted.end;
+    self.index = index;
+    Ok(())
+  }
+
+  // Compacts the file and waits until done
+  #[cfg(test)]
+  fn compact(&mut self) -> io::Result<()> {
+    self.start_compaction()?;
+    self.finish_compaction(true)
+  }
+}
+
+impl CompactedFile {
+  // Appends a record from the old file
+  fn copy_record(
+    &mut self,
+    mut source: &File,
+    location: RecordLocation,
+    buffer: &mut Vec<u8>,
+  ) -> io::Result<RecordLocation> {
+    buffer.resize(location.length as usize, 0);
+    source.seek(SeekFrom::Start(location.offset))?;
+    source.read_exact(buffer)?;
+    self.file.seek(SeekFrom::Start(self.end))?;
+    self.file.write_all(buffer)?;
+    let new_location = RecordLocation {
+      offset: self.end,
+      length: location.length,
+    };
+    self.end += location.length;
+    self.relocated.insert(location.offset, new_location);
+    Ok(new_location)
+  }
+}
+
+impl Drop for SpillStore {
+  fn drop(&mut self) {
+    if let Some(worker) = self.compaction.take() {
+      let _ = worker.join();
+      let _ = fs::remove_file(self.compacted_path());
+    }
+    if let Err(e) = fs::remove_file(&self.path) {
+      warn!("Cannot remove the history spill file {:?}: {e}", self.path);
+    }
+  }
+}
+
+// On-disk form of a CacheChange
+
+#[derive(Readable, Writable)]
+struct SpilledPayload {
+  representation_identifier: RepresentationIdentifier,
+  representation_options: [u8; 2],
+  value: Vec<u8>,
+}
+
+#[derive(Readable, Writable)]
+#[speedy(tag_type = u8)]
+enum SpilledData {
+  Data {
+    serialized_payload: SpilledPayload,
+  },
+  DisposeByKey {
+    change_kind: u8,
+    key: SpilledPayload,
+    key_hash: Option<Vec<u8>>,
+  },
+  DisposeByKeyHash {
+    change_kind: u8,
+    key_hash: Vec<u8>,
+  },
+}
+
+#[derive(Readable, Writable)]
+struct SpilledChange {
+  writer_guid: GUID,
+  sequence_number: i64,
+  related_sample_identity: Option<SampleIdentity>,
+  source_timestamp: Option<Timestamp>,
+  to_single_reader: Option<GUID>,
+  data_value: SpilledData,
+}
+
+impl From<&SerializedPayload> for SpilledPayload {
+  fn from(serialized_payload: &SerializedPayload) -> Self {
+    Self {
+      representation_identifier: serialized_payload.representation_identifier,
+      representation_options: serialized_payload.representation_options,
+      value: serialized_payload.value.to_vec(),
+    }
+  }
+}
+
+impl From<SpilledPayload> for SerializedPayload {
+  fn from(spilled: SpilledPayload) -> Self {
+    Self {
+      representation_identifier: spilled.representation_identifier,
+      representation_options: spilled.representation_options,
+      value: Bytes::from(spilled.value),
+    }
+  }
+}
+
+fn change_kind_to_u8(change_kind: ChangeKind) -> u8 {
+  match change_kind {
+    ChangeKind::Alive => 0,
+    ChangeKind::NotAliveDisposed => 1,
+    ChangeKind::NotAliveUnregistered => 2,
+    ChangeKind::NotAliveDisposedUnregistered => 3,
+  }
+}
+
+fn change_kind_from_u8(change_kind: u8) -> io::Result<ChangeKind> {
+  match change_kind {
+    0 => Ok(ChangeKind::Alive),
+    1 => Ok(ChangeKind::NotAliveDisposed),
+    2 => Ok(ChangeKind::NotAliveUnregistered),
+    3 => Ok(ChangeKind::NotAliveDisposedUnregistered),
+    _ => Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "invalid change kind",
+    )),
+  }
+}
+
+impl From<&CacheChange> for SpilledChange {
+  fn from(cache_change: &CacheChange) -> Self {
+    let write_options = &cache_change.write_options;
+    Self {
+      writer_guid: cache_change.writer_guid,
+      sequence_number: i64::from(cache_change.sequence_number),
+      related_sample_identity: write_options.related_sample_identity(),
+      source_timestamp: write_options.source_timestamp(),
+      to_single_reader: write_options.to_single_reader(),
+      data_value: match &cache_change.data_value {
+        DDSData::Data { serialized_payload } => SpilledData::Data {
+          serialized_payload: serialized_payload.into(),
+        },
+        DDSData::DisposeByKey {
+          change_kind,
+          key,
+          key_hash,
+        } => SpilledData::DisposeByKey {
+          change_kind: change_kind_to_u8(*change_kind),
+          key: key.into(),
+          key_hash: key_hash.map(KeyHash::to_vec),
+        },
+        DDSData::DisposeByKeyHash {
+          change_kind,
+          key_hash,
+        } => SpilledData::DisposeByKeyHash {
+          change_kind: change_kind_to_u8(*change_kind),
+          key_hash: key_hash.to_vec(),
+        },
+      },
+    }
+  }
+}
+
+impl TryFrom<SpilledChange> for CacheChange {
+  type Error = io::Error;
+
+  fn try_from(spilled: SpilledChange) -> io::Result<Self> {
+    let mut write_options =
+      WriteOptionsBuilder::new().related_sample_identity_opt(spilled.related_sample_identity);
+    if let Some(source_timestamp) = spilled.source_timestamp {
+      write_options = write_options.source_timestamp(source_timestamp);
+    }
+    if let Some(reader) = spilled.to_single_reader {
+      write_options = write_options.to_single_reader(reader);
+    }
+    let write_options: WriteOptions = write_options.build();
+
+    let data_value = match spilled.data_value {
+      SpilledData::Data { serialized_payload } => DDSData::new(serialized_payload.into()),
+      SpilledData::DisposeByKey {
+        change_kind,
+        key,
+        key_hash,
+      } => {
+        let change_kind = change_kind_from_u8(change_kind)?;
+        match key_hash {
+          None => DDSData::new_disposed_by_key(change_kind, key.into()),
+          Some(key_hash) => DDSData::new_disposed_by_key_and_hash(
+            change_kind,
+            key.into(),
+            KeyHash::from_pl_cdr_bytes(key_hash)
+              .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+          ),
+        }
+      }
+      SpilledData::DisposeByKeyHash {
+        change_kind,
+        key_hash,
+      } => DDSData::new_disposed_by_key_hash(
+        change_kind_from_u8(change_kind)?,
+        KeyHash::from_pl_cdr_bytes(key_hash)
+          .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+      ),
+    };
+
+    Ok(CacheChange::new(
+      spilled.writer_guid,
+      SequenceNumber::from(spilled.sequence_number),
+      write_options,
+      data_value,
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::structure::guid::EntityKind;
+
+  fn test_directory() -> PathBuf {
+    std::env::temp_dir().join("rustdds_history_spill_test")
+  }
+
+  fn writer_guid() -> GUID {
+    GUID::new_participant_guid().from_prefix(crate::structure::guid::EntityId {
+      entity_key: [0, 0, 1],
+      entity_kind: EntityKind::WRITER_WITH_KEY_USER_DEFINED,
+    })
+  }
+
+  fn data_change(writer_guid: GUID, sequence_number: i64, payload: Vec<u8>) -> CacheChange {
+    CacheChange::new(
+      writer_guid,
+      SequenceNumber::new(sequence_number),
+      WriteOptionsBuilder::new()
+        .source_timestamp(Timestamp::now())
+        .build(),
+      DDSData::new(SerializedPayload::new(
+        RepresentationIdentifier::CDR_LE,
+        payload,
+      )),
+    )
+  }
+
+  #[test]
+  fn changes_are_read_back_unchanged() {
+    let guid = writer_guid();
+    let mut store = SpillStore::create(&test_directory(), guid).unwrap();
+
+    let changes = vec![
+      data_change(guid, 1, vec![1, 2, 3, 4]),
+      CacheChange::new(
+        guid,
+        SequenceNumber::new(2),
+        WriteOptionsBuilder::new().to_single_reader(guid).build(),
+        DDSData::new_disposed_by_key_and_hash(
+          ChangeKind::NotAliveDisposed,
+          SerializedPayload::new(RepresentationIdentifier::PL_CDR_LE, vec![5, 6, 7, 8]),
+          KeyHash::from_pl_cdr_bytes((5..21).collect()).unwrap(),
+        ),
+      ),
+      CacheChange::new(
+        guid,
+        SequenceNumber::new(3),
+        WriteOptions::default(),
+        DDSData::new_disposed_by_key_hash(
+          ChangeKind::NotAliveUnregistered,
+          KeyHash::from_pl_cdr_bytes((0..16).collect()).unwrap(),
+        ),
+      ),
+    ];
+
+    let timestamps: Vec<Timestamp> = changes.iter().map(|_| Timestamp::now()).collect();
+    for (timestamp, change) in timestamps.iter().zip(&changes) {
+      store.append(*timestamp, change).unwrap();
+    }
+    for (timestamp, change) in timestamps.iter().zip(&changes) {
+      assert_eq!(store.read(timestamp).as_ref(), Some(change));
+    }
+  }
+
+  #[test]
+  fn torn_and_corrupted_records_are_ignored() {
+    let guid = writer_guid();
+    let mut store = SpillStore::create(&test_directory(), guid).unwrap();
+    let (first, second, third) = (Timestamp::now(), Timestamp::now(), Timestamp::now());
+    let first_change = data_change(guid, 1, vec![1; 100]);
+    store.append(first, &first_change).unwrap();
+    store
+      .append(second, &data_change(guid, 2, vec![2; 100]))
+      .unwrap();
+    let second_end = store.file_size();
+    store
+      .append(third, &data_change(guid, 3, vec![3; 100]))
+      .unwrap();
+
+    // Tear the last record, as if writing it had been interrupted
+    store.file.set_len(store.file_size() - 10).unwrap();
+    assert!(store.read(&third).is_none());
+
+    // Flip a byte in the middle of the second record
+    let mut byte = [0];
+    (&store.file)
+      .seek(SeekFrom::Start(second_end - 50))
+      .unwrap();
+    (&store.file).read_exact(&mut byte).unwrap();
+    (&store.file)
+      .seek(SeekFrom::Start(second_end - 50))
+      .unwrap();
+    (&store.file).write_all(&[!byte[0]]).unwrap();
+    assert!(store.read(&second).is_none());
+
+    assert_eq!(store.read(&first), Some(first_change));
+  }
+
+  #[test]
+  fn compaction_keeps_the_live_records() {
+    let guid = writer_guid();
+    let mut store = SpillStore::create(&test_directory(), guid).unwrap();
+    let payload_size = 64 * 1024;
+    let changes: Vec<(Timestamp, CacheChange)> = (1..=40)
+      .map(|sequence_number| {
+        let timestamp = Timestamp::now();
+        let change = data_change(
+          guid,
+          sequence_number,
+          vec![sequence_number as u8; payload_size],
+        );
+        store.append(timestamp, &change).unwrap();
+        (timestamp, change)
+      })
+      .collect();
+    assert!(!store.needs_compaction());
+
+    store.remove_before(&changes[30].0);
+    assert!(store.needs_compaction());
+    let size_before = store.file_size();
+    store.compact().unwrap();
+    assert!(store.file_size() < size_before / 3);
+    assert!(!store.needs_compaction());
+
+    assert!(!store.contains(&changes[29].0));
+    for (timestamp, change) in &changes[30..] {
+      assert_eq!(store.read(timestamp).as_ref(), Some(change));
+    }
+    // Records appended after compaction land after the live ones
+    let timestamp = Timestamp::now();
+    store
+      .append(timestamp, &data_change(guid, 41, vec![41; 10]))
+      .unwrap();
+    assert_eq!(
+      store.read(&timestamp).unwrap().sequence_number,
+      SequenceNumber::new(41)
+    );
+  }
+
+  #[test]
+  fn changes_during_compaction_are_kept() {
+    let guid = writer_guid();
+    let mut store = SpillStore::create(&test_directory(), guid).unwrap();
+    let payload_size = 64 * 1024;
+    let append = |store: &mut SpillStore, sequence_number: i64| {
+      let timestamp = Timestamp::now();
+      let change = data_change(
+        guid,
+        sequence_number,
+        vec![sequence_number as u8; payload_size],
+      );
+      store.append(timestamp, &change).unwrap();
+      (timestamp, change)
+    };
+    let mut changes: Vec<(Timestamp, CacheChange)> =
+      (1..=40).map(|sn| append(&mut store, sn)).collect();
+    let record_length = store.file_size() / 40;
+    store.remove_before(&changes[30].0);
+    store.compact_in_background().unwrap();
+    assert!(store.compaction.is_some());
+
+    // The store is used as usual while the worker copies the records
+    changes.extend((41..=45).map(|sn| append(&mut store, sn)));
+    store.remove_before(&changes[32].0);
+    assert_eq!(store.read(&changes[35].0).as_ref(), Some(&changes[35].1));
+
+    store.finish_compaction(true).unwrap();
+    assert!(store.compaction.is_none());
+    assert!(!store.compacted_path().exists());
+    assert!(!store.contains(&changes[31].0));
+    for (timestamp, change) in &changes[32..] {
+      assert_eq!(store.read(timestamp).as_ref(), Some(change));
+    }
+    // The two records removed during the compaction are left dead
+    assert_eq!(store.file_size(), 15 * record_length);
+    assert_eq!(store.dead_bytes, 2 * record_length);
+  }
+
+  #[test]
+  fn file_is_removed_on_drop() {
+    let store = SpillStore::create(&test_directory(), writer_guid()).unwrap();
+    let path = store.path.clone();
+    assert!(path.exists());
+    drop(store);
+    assert!(!path.exists());
+  }
+}
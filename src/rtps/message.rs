@@ -21,7 +21,7 @@ use crate::{
   },
   rtps::{Submessage, SubmessageBody},
   structure::{
-    cache_change::CacheChange,
+    cache_change::{CacheChange, ChangeKind},
     guid::{EntityId, GuidPrefix, GUID},
     parameter_id::ParameterId,
     sequence_number::{FragmentNumber, SequenceNumber, SequenceNumberSet},
@@ -62,6 +62,14 @@ impl Message {
   // top level to fix that. And there seems to be no reasonable way to change
   // endianness. TODO: The error type should be something better
   pub fn read_from_buffer(buffer: &Bytes) -> io::Result<Self> {
+    Self::read_from_buffer_counting_skipped(buffer).map(|(message, _skipped)| message)
+  }
+
+  // As above, but also returns the number of skipped submessages, i.e. those of
+  // unknown or vendor-specific kind. RTPS spec v2.5 Section "8.3.4.1 Rules
+  // Followed by the Message Receiver" says to skip them and go on with the
+  // next submessage.
+  pub fn read_from_buffer_counting_skipped(buffer: &Bytes) -> io::Result<(Self, usize)> {
     // The Header deserializes the same
     let rtps_header =
       Header::read_from_buffer(buffer).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
@@ -69,15 +77,20 @@ impl Message {
       return Err(io::Error::new(io::ErrorKind::Other, "Invalid RTPS header"));
     }
     let mut message = Self::new(rtps_header);
+    let mut skipped = 0;
     let mut submessages_left: Bytes = buffer.slice(20..); // header is 20 bytes
                                                           // submessage loop
     while !submessages_left.is_empty() {
-      if let Some(submessage) = Submessage::read_from_buffer(&mut submessages_left)? {
-        message.submessages.push(submessage);
+      let kind = submessages_left[0]; // the first octet of the submessage header
+      match Submessage::read_from_buffer(&mut submessages_left)? {
+        Some(submessage) => message.submessages.push(submessage),
+        // PAD is skipped, too, but is not unknown
+        None if kind != u8::from(SubmessageKind::PAD) => skipped += 1,
+        None => (),
       }
     } // loop
 
-    Ok(message)
+    Ok((message, skipped))
   }
 }
 
@@ -112,6 +125,9 @@ impl<C: Context> Writable<C> for Message {
 #[derive(Default, Clone)]
 pub(crate) struct MessageBuilder {
   submessages: Vec<Submessage>,
+  // The source timestamp in effect for the next submessage, as set by the
+  // last INFO_TS. There is none at the start of a message.
+  source_timestamp: Option<Timestamp>,
 }
 
 impl MessageBuilder {
@@ -144,6 +160,7 @@ impl MessageBuilder {
   /// Argument None means "invalidate", i.e. the previously sent
   /// [`InfoTimestamp`] submessage no longer applies.
   pub fn ts_msg(mut self, endianness: Endianness, timestamp: Option<Timestamp>) -> Self {
+    self.source_timestamp = timestamp;
     let mut flags = BitFlags::<INFOTIMESTAMP_Flags>::from_endianness(endianness);
     if timestamp.is_none() {
       flags |= INFOTIMESTAMP_Flags::Invalidate;
@@ -173,6 +190,50 @@ impl MessageBuilder {
     self
   }
 
+  /// Like [`Self::ts_msg`], but adds the [`InfoTimestamp`] submessage only
+  /// if `timestamp` differs from the one in effect. Consecutive submessages
+  /// with the same source timestamp then share a single INFO_TS.
+  pub fn ts_msg_if_changed(self, endianness: Endianness, timestamp: Option<Timestamp>) -> Self {
+    if timestamp == self.source_timestamp {
+      self
+    } else {
+      self.ts_msg(endianness, timestamp)
+    }
+  }
+
+  // Inline QoS that tells about a dispose or unregister: the key hash, if
+  // known, so that Readers can identify the instance without deserializing the
+  // key, and the status info.
+  // The key hash of a small key is the key itself, so it is left out when the
+  // key is sent in a protected payload. Readers then take the instance from the
+  // decoded key. See DDS Security v1.1 Section "7.3.4 Mechanisms and
+  // Safeguards for Confidentiality".
+  // RTPS Spec v2.5 Sections "8.7.4 Changes in the Instance State" and "9.6.4.9
+  // StatusInfo_t (PID_STATUS_INFO)"
+  fn instance_state_inline_qos(
+    data_value: &DDSData,
+    payload_protected: bool,
+    param_list: &mut ParameterList,
+  ) {
+    let change_kind = data_value.change_kind();
+    if change_kind == ChangeKind::Alive {
+      return;
+    }
+    let key_in_protected_payload =
+      payload_protected && matches!(data_value, DDSData::DisposeByKey { .. });
+    if let Some(key_hash) = data_value.key_hash().filter(|_| !key_in_protected_payload) {
+      param_list.push(Parameter {
+        parameter_id: ParameterId::PID_KEY_HASH,
+        value: key_hash.to_vec(),
+      });
+    }
+    param_list.push(Parameter::create_pid_status_info_parameter(
+      /* disposed */ change_kind.is_disposed(),
+      /* unregistered */ change_kind.is_unregistered(),
+      /* filtered */ false,
+    ));
+  }
+
   pub fn data_msg(
     mut self,
     cache_change: &CacheChange,
@@ -189,36 +250,15 @@ impl MessageBuilder {
 
     let mut param_list = ParameterList::new(); // inline QoS goes here
 
-    // Check if we are disposing (by key or by key hash).
-    // If yes, then Indicate Dispose by PID_STATUS_INFO in Inline QoS
-    // RTPS Spec v2.5 Section "9.6.4.9 StatusInfo_t (PID_STATUS_INFO)"
-    // Dispose must be indicated in Inline QoS:
-    // RTPS Spec v2.5 Section "8.7.4 Changes in the Instance State"
-    //
-    // When disposing, we also indicate "unregistered", although we do not
-    // support whole register/unregister mechanism at all. TODO: Does this
-    // make sense?
-    match cache_change.data_value {
-      DDSData::Data { .. } => (), // data sample, not dispose
+    // Whether the payloads of the writer are protected
+    #[cfg(feature = "security")]
+    let payload_protected =
+      security_plugins.is_some_and(|sp| !sp.read_plugins().payload_not_protected(&writer_guid));
+    #[cfg(not(feature = "security"))]
+    let payload_protected = false;
 
-      DDSData::DisposeByKey { .. } => {
-        param_list.push(Parameter::create_pid_status_info_parameter(
-          /* disposed */ true, /* unregistered */ true, /* filtered */ false,
-        ));
-      }
-      DDSData::DisposeByKeyHash { key_hash, .. } => {
-        // yes, insert key hash to inline QoS
-        param_list.push(Parameter {
-          parameter_id: ParameterId::PID_KEY_HASH,
-          value: key_hash.to_vec(),
-        });
-        // ... and tell what the key_hash means
-        let status_info = Parameter::create_pid_status_info_parameter(
-          /* disposed */ true, /* unregistered */ true, /* filtered */ false,
-        );
-        param_list.push(status_info);
-      }
-    }
+    // Dispose and unregister are indicated in inline QoS.
+    Self::instance_state_inline_qos(&cache_change.data_value, payload_protected, &mut param_list);
 
     // If we are sending related sample identity, then insert that.
     if let Some(si) = cache_change.write_options.related_sample_identity() {
@@ -238,7 +278,11 @@ impl MessageBuilder {
     };
 
     #[cfg(not(feature = "security"))]
-    let encoded_payload = serialized_payload;
+    let (encoded_payload, payload_transformed) = (serialized_payload, false);
+
+    // Whether encode_serialized_payload turns the payload into CryptoContent
+    #[cfg(feature = "security")]
+    let payload_transformed = serialized_payload.is_some() && payload_protected;
 
     #[cfg(feature = "security")]
     let encoded_payload = match serialized_payload
@@ -249,7 +293,7 @@ impl MessageBuilder {
           .write_to_vec()
           .map_err(|e| create_security_error_and_log!("{e:?}"))
           .and_then(|serialized_payload| {
-            match security_plugins.map(SecurityPluginsHandle::get_plugins) {
+            match security_plugins.map(SecurityPluginsHandle::read_plugins) {
               Some(security_plugins) => {
                 security_plugins
                   .encode_serialized_payload(serialized_payload, &writer_guid)
@@ -299,6 +343,13 @@ impl MessageBuilder {
         BitFlags::<DATA_Flags>::from_flag(DATA_Flags::InlineQos)
       } else {
         BitFlags::<DATA_Flags>::empty()
+      })
+      // RTPS spec v2.5 Section "9.4.5.3.1 Flags in the Submessage Header":
+      // set when the payload is transformed as in DDS Security
+      | (if payload_transformed {
+        BitFlags::<DATA_Flags>::from_flag(DATA_Flags::NonStandardPayload)
+      } else {
+        BitFlags::<DATA_Flags>::empty()
       });
 
     self.submessages.push(Submessage {
@@ -335,9 +386,23 @@ impl MessageBuilder {
 
     let mut param_list = ParameterList::new(); // inline QoS goes here
 
+    // Whether encode_serialized_payload turns the payload into CryptoContent
+    #[cfg(feature = "security")]
+    let payload_transformed =
+      security_plugins.is_some_and(|sp| !sp.read_plugins().payload_not_protected(&writer_guid));
+    #[cfg(not(feature = "security"))]
+    let payload_transformed = false;
+
     // Check if we are disposing by key hash
     match cache_change.data_value {
-      DDSData::Data { .. } | DDSData::DisposeByKey { .. } => (), // no => ok
+      DDSData::Data { .. } => (),
+      DDSData::DisposeByKey { .. } => {
+        Self::instance_state_inline_qos(
+          &cache_change.data_value,
+          payload_transformed,
+          &mut param_list,
+        );
+      }
       DDSData::DisposeByKeyHash { .. } => {
         error!(
           "data_frag_msg: Called with DDSData::DisposeByKeyHash. This is not legit! Discarding."
@@ -379,7 +444,7 @@ impl MessageBuilder {
 
     #[cfg(feature = "security")]
     let encoded_payload = {
-      let encode_result = match security_plugins.map(SecurityPluginsHandle::get_plugins) {
+      let encode_result = match security_plugins.map(SecurityPluginsHandle::read_plugins) {
         Some(security_plugins) => {
           security_plugins
             .encode_serialized_payload(serialized_payload, &writer_guid)
@@ -435,6 +500,12 @@ impl MessageBuilder {
         BitFlags::<DATAFRAG_Flags>::from_flag(DATAFRAG_Flags::InlineQos)
       } else {
         BitFlags::<DATAFRAG_Flags>::empty()
+      })
+      // non-standard payload flag, as in DATA
+      | (if payload_transformed {
+        BitFlags::<DATAFRAG_Flags>::from_flag(DATAFRAG_Flags::NonStandardPayload)
+      } else {
+        BitFlags::<DATAFRAG_Flags>::empty()
       });
 
     self.submessages.push(Submessage {
@@ -456,33 +527,39 @@ impl MessageBuilder {
     writer_endianness: Endianness,
     reader_guid: GUID,
   ) -> Self {
-    match (irrelevant_sns.first(), irrelevant_sns.last()) {
-      (Some(&gap_start), Some(&_last_sn)) => {
-        // Determine the contiguous range of irrelevant seqnums starting from gap_start.
-        // Do this by finding the first seqnum which is larger than gap_start but is not
-        // included in irrelevant_sns. That is, find the
-        // exclusive endpoint of the contiguous range.
-        let mut range_endpoint_excl = gap_start.plus_1();
-        while irrelevant_sns.contains(&range_endpoint_excl) {
-          range_endpoint_excl = range_endpoint_excl.plus_1();
-        }
-        // Set gapList.base as this exclusive endpoint of the range
-        let list_base = range_endpoint_excl;
-        let list_set = irrelevant_sns.clone().split_off(&list_base);
-        let gap_list = SequenceNumberSet::from_base_and_set(list_base, &list_set);
-
-        let gap = Gap {
-          reader_id: reader_guid.entity_id,
-          writer_id: writer_entity_id,
-          gap_start,
-          gap_list,
-        };
-        let gap_flags = BitFlags::<GAP_Flags>::from_endianness(writer_endianness);
-        gap
-          .create_submessage(gap_flags)
-          .map(|s| self.submessages.push(s));
+    if irrelevant_sns.is_empty() {
+      error!("gap_msg called with empty SN set. Skipping GAP submessage");
+      return self;
+    }
+    // A single GAP can list at most 256 sequence numbers after the contiguous
+    // range starting at gapStart. Emit as many GAPs as needed to cover all.
+    let mut remaining = irrelevant_sns.clone();
+    while let Some(&gap_start) = remaining.first() {
+      // Determine the contiguous range of irrelevant seqnums starting from gap_start.
+      // Do this by finding the first seqnum which is larger than gap_start but is not
+      // included in irrelevant_sns. That is, find the
+      // exclusive endpoint of the contiguous range.
+      let mut range_endpoint_excl = gap_start.plus_1();
+      while remaining.contains(&range_endpoint_excl) {
+        range_endpoint_excl = range_endpoint_excl.plus_1();
       }
-      (_, _) => error!("gap_msg called with empty SN set. Skipping GAP submessage"),
+      // Set gapList.base as this exclusive endpoint of the range
+      let list_base = range_endpoint_excl;
+      let mut list_set = remaining.split_off(&list_base);
+      remaining = list_set
+        .split_off(&(list_base + SequenceNumber::from(i64::from(SequenceNumberSet::MAX_BITS))));
+      let gap_list = SequenceNumberSet::from_base_and_set(list_base, &list_set);
+
+      let gap = Gap {
+        reader_id: reader_guid.entity_id,
+        writer_id: writer_entity_id,
+        gap_start,
+        gap_list,
+      };
+      let gap_flags = BitFlags::<GAP_Flags>::from_endianness(writer_endianness);
+      gap
+        .create_submessage(gap_flags)
+        .map(|s| self.submessages.push(s));
     }
     self
   }
@@ -563,9 +640,15 @@ impl MessageBuilder {
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
+  use std::net::SocketAddr;
+
   use log::info;
 
   use super::*;
+  use crate::{
+    messages::submessages::{info_source::InfoSource, submessage_flag::FromEndianness},
+    structure::{locator::Locator, sequence_number::FragmentNumberSet},
+  };
 
   #[test]
 
@@ -779,6 +862,864 @@ mod tests {
     assert_eq!(bits1, serialized);
   }
 
+  // One submessage of every kind that we can write, with all its multi-byte
+  // fields set to values that read differently when the byte order is swapped.
+  fn every_kind_of_submessage(endianness: Endianness) -> Vec<Submessage> {
+    fn submessage(kind: SubmessageKind, flags: u8, body: SubmessageBody) -> Submessage {
+      let content_length = body
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap()
+        .len() as u16;
+      Submessage {
+        header: SubmessageHeader {
+          kind,
+          flags,
+          content_length,
+        },
+        body,
+        original_bytes: None,
+      }
+    }
+
+    let reader_id = EntityId::SPDP_BUILTIN_PARTICIPANT_READER;
+    let writer_id = EntityId::SPDP_BUILTIN_PARTICIPANT_WRITER;
+    let inline_qos = ParameterList {
+      parameters: vec![Parameter {
+        parameter_id: ParameterId::PID_STATUS_INFO,
+        value: vec![0, 0, 0, 1],
+      }],
+    };
+    let sn_set = SequenceNumberSet::from_base_and_set(
+      SequenceNumber::new(0x1_0000_0002),
+      &BTreeSet::from([
+        SequenceNumber::new(0x1_0000_0002),
+        SequenceNumber::new(0x1_0000_0005),
+      ]),
+    );
+
+    let data_flags = BitFlags::<DATA_Flags>::from_endianness(endianness)
+      | DATA_Flags::InlineQos
+      | DATA_Flags::Data;
+    let datafrag_flags =
+      BitFlags::<DATAFRAG_Flags>::from_endianness(endianness) | DATAFRAG_Flags::InlineQos;
+    let gap_flags = BitFlags::<GAP_Flags>::from_endianness(endianness);
+    let heartbeat_flags =
+      BitFlags::<HEARTBEAT_Flags>::from_endianness(endianness) | HEARTBEAT_Flags::Final;
+    let heartbeatfrag_flags = BitFlags::<HEARTBEATFRAG_Flags>::from_endianness(endianness);
+    let acknack_flags = BitFlags::<ACKNACK_Flags>::from_endianness(endianness);
+    let nackfrag_flags = BitFlags::<NACKFRAG_Flags>::from_endianness(endianness);
+    let infosource_flags = BitFlags::<INFOSOURCE_Flags>::from_endianness(endianness);
+    let infodst_flags = BitFlags::<INFODESTINATION_Flags>::from_endianness(endianness);
+    let infots_flags = BitFlags::<INFOTIMESTAMP_Flags>::from_endianness(endianness);
+    let inforeply_flags =
+      BitFlags::<INFOREPLY_Flags>::from_endianness(endianness) | INFOREPLY_Flags::Multicast;
+
+    vec![
+      submessage(
+        SubmessageKind::INFO_SRC,
+        infosource_flags.bits(),
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoSource(
+          InfoSource {
+            unused: 0x0102_0304,
+            protocol_version: ProtocolVersion::THIS_IMPLEMENTATION,
+            vendor_id: VendorId::THIS_IMPLEMENTATION,
+            guid_prefix: GuidPrefix::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+          },
+          infosource_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::INFO_DST,
+        infodst_flags.bits(),
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoDestination(
+          InfoDestination {
+            guid_prefix: GuidPrefix::new(&[12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1]),
+          },
+          infodst_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::INFO_TS,
+        infots_flags.bits(),
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(
+          InfoTimestamp {
+            timestamp: Some(Timestamp::from_ticks(0x0123_4567_89ab_cdef)),
+          },
+          infots_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::INFO_REPLY,
+        inforeply_flags.bits(),
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoReply(
+          InfoReply {
+            unicast_locator_list: vec![Locator::from(
+              "192.168.1.10:7411".parse::<SocketAddr>().unwrap(),
+            )],
+            multicast_locator_list: Some(vec![Locator::from(
+              "239.255.0.1:7401".parse::<SocketAddr>().unwrap(),
+            )]),
+          },
+          inforeply_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::DATA,
+        data_flags.bits(),
+        SubmessageBody::Writer(WriterSubmessage::Data(
+          Data {
+            reader_id,
+            writer_id,
+            writer_sn: SequenceNumber::new(0x1_0000_0002),
+            inline_qos: Some(inline_qos.clone()),
+            serialized_payload: Some(Bytes::from_static(&[0, 1, 0, 0, 1, 2, 3, 4])),
+          },
+          data_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::DATA_FRAG,
+        datafrag_flags.bits(),
+        SubmessageBody::Writer(WriterSubmessage::DataFrag(
+          DataFrag {
+            reader_id,
+            writer_id,
+            writer_sn: SequenceNumber::new(0x1_0000_0003),
+            fragment_starting_num: FragmentNumber::new(2),
+            fragments_in_submessage: 1,
+            data_size: 0x0001_0010,
+            fragment_size: 0x0100,
+            inline_qos: Some(inline_qos),
+            serialized_payload: Bytes::from_static(&[1, 2, 3, 4, 5, 6, 7, 8]),
+          },
+          datafrag_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::GAP,
+        gap_flags.bits(),
+        SubmessageBody::Writer(WriterSubmessage::Gap(
+          Gap {
+            reader_id,
+            writer_id,
+            gap_start: SequenceNumber::new(0x1_0000_0001),
+            gap_list: sn_set.clone(),
+          },
+          gap_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::HEARTBEAT,
+        heartbeat_flags.bits(),
+        SubmessageBody::Writer(WriterSubmessage::Heartbeat(
+          Heartbeat {
+            reader_id,
+            writer_id,
+            first_sn: SequenceNumber::new(0x1_0000_0001),
+            last_sn: SequenceNumber::new(0x1_0000_0007),
+            count: 0x0102_0304,
+          },
+          heartbeat_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::HEARTBEAT_FRAG,
+        heartbeatfrag_flags.bits(),
+        SubmessageBody::Writer(WriterSubmessage::HeartbeatFrag(
+          HeartbeatFrag {
+            reader_id,
+            writer_id,
+            writer_sn: SequenceNumber::new(0x1_0000_0003),
+            last_fragment_num: FragmentNumber::new(0x0102),
+            count: 0x0102_0305,
+          },
+          heartbeatfrag_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::ACKNACK,
+        acknack_flags.bits(),
+        SubmessageBody::Reader(ReaderSubmessage::AckNack(
+          AckNack {
+            reader_id,
+            writer_id,
+            reader_sn_state: sn_set,
+            count: 0x0102_0306,
+          },
+          acknack_flags,
+        )),
+      ),
+      submessage(
+        SubmessageKind::NACK_FRAG,
+        nackfrag_flags.bits(),
+        SubmessageBody::Reader(ReaderSubmessage::NackFrag(
+          NackFrag {
+            reader_id,
+            writer_id,
+            writer_sn: SequenceNumber::new(0x1_0000_0003),
+            fragment_number_state: FragmentNumberSet::from_base_and_set(
+              FragmentNumber::new(0x0101),
+              &BTreeSet::from([FragmentNumber::new(0x0101), FragmentNumber::new(0x0103)]),
+            ),
+            count: 0x0102_0307,
+          },
+          nackfrag_flags,
+        )),
+      ),
+    ]
+  }
+
+  fn message_with(submessages: Vec<Submessage>) -> Message {
+    let mut message = Message::new(Header {
+      protocol_id: ProtocolId::default(),
+      protocol_version: ProtocolVersion::THIS_IMPLEMENTATION,
+      vendor_id: VendorId::THIS_IMPLEMENTATION,
+      guid_prefix: GuidPrefix::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+    });
+    submessages
+      .into_iter()
+      .for_each(|s| message.add_submessage(s));
+    message
+  }
+
+  fn assert_same_submessages(parsed: &Message, expected: &[Submessage]) {
+    assert_eq!(parsed.submessages.len(), expected.len());
+    for (p, e) in parsed.submessages.iter().zip(expected) {
+      assert_eq!(p.header, e.header);
+      assert_eq!(p.body, e.body);
+    }
+  }
+
+  #[test]
+  fn every_submessage_kind_round_trips_in_both_byte_orders() {
+    let little = every_kind_of_submessage(Endianness::LittleEndian);
+    let big = every_kind_of_submessage(Endianness::BigEndian);
+
+    for submessages in [&little, &big] {
+      // The Message-level context must not matter: each submessage body is
+      // written in the byte order given by its own Endianness flag.
+      for ctx in [Endianness::LittleEndian, Endianness::BigEndian] {
+        let bytes = message_with(submessages.clone())
+          .write_to_vec_with_ctx(ctx)
+          .unwrap();
+        let parsed = Message::read_from_buffer(&Bytes::from(bytes)).unwrap();
+        assert_same_submessages(&parsed, submessages);
+      }
+    }
+
+    // The two byte orders must actually produce different bodies, but of
+    // equal length. INFO_DST is the exception, as a GuidPrefix is just octets.
+    for (l, b) in little.iter().zip(&big) {
+      let l_bytes = l.write_to_vec_with_ctx(Endianness::LittleEndian).unwrap();
+      let b_bytes = b.write_to_vec_with_ctx(Endianness::LittleEndian).unwrap();
+      assert_eq!(l_bytes.len(), b_bytes.len(), "{:?}", l.header.kind);
+      if l.header.kind != SubmessageKind::INFO_DST {
+        assert_ne!(l_bytes[4..], b_bytes[4..], "{:?}", l.header.kind);
+      }
+    }
+  }
+
+  #[test]
+  fn submessages_of_mixed_byte_order_in_one_message() {
+    // Each submessage declares its own byte order, so a message may mix them.
+    let mixed: Vec<Submessage> = every_kind_of_submessage(Endianness::LittleEndian)
+      .into_iter()
+      .zip(every_kind_of_submessage(Endianness::BigEndian))
+      .enumerate()
+      .map(|(i, (l, b))| if i % 2 == 0 { l } else { b })
+      .collect();
+
+    let bytes = message_with(mixed.clone())
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+    let parsed = Message::read_from_buffer(&Bytes::from(bytes)).unwrap();
+    assert_same_submessages(&parsed, &mixed);
+  }
+
+  #[test]
+  fn big_endian_shapes_demo_message() {
+    // The INFO_DST, INFO_TS, DATA, HEARTBEAT packet from
+    // rtps_message_test_shapes_demo_message_deserialization above, as a
+    // big-endian implementation would send it: Endianness flags cleared and all
+    // multi-byte submessage fields byte-swapped. The serialized payload carries
+    // its own CDR encapsulation, so it is the same in both.
+    let little_endian = Bytes::from_static(&[
+      0x52, 0x54, 0x50, 0x53, 0x02, 0x03, 0x01, 0x0f, 0x01, 0x0f, 0x99, 0x06, 0x78, 0x34, 0x00,
+      0x00, 0x01, 0x00, 0x00, 0x00, 0x0e, 0x01, 0x0c, 0x00, 0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d,
+      0x31, 0xa2, 0x28, 0x20, 0x02, 0x08, 0x09, 0x01, 0x08, 0x00, 0x1a, 0x15, 0xf3, 0x5e, 0x00,
+      0xcc, 0xfb, 0x13, 0x15, 0x05, 0x2c, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x07,
+      0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+      0x00, 0x04, 0x00, 0x00, 0x00, 0x52, 0x45, 0x44, 0x00, 0x69, 0x00, 0x00, 0x00, 0x17, 0x00,
+      0x00, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x07, 0x01, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00,
+      0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+      0x5b, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00,
+    ]);
+    let big_endian = Bytes::copy_from_slice(&hex_literal::hex!(
+      "
+      52 54 50 53 02 03 01 0f 01 0f 99 06 78 34 00 00 01 00 00 00
+      0e 00 00 0c 01 03 00 0c 29 2d 31 a2 28 20 02 08
+      09 00 00 08 5e f3 15 1a 13 fb cc 00
+      15 04 00 2c 00 00 00 10 00 00 00 07 00 00 01 02 00 00 00 00 00 00 00 5b
+        00 01 00 00 04 00 00 00 52 45 44 00 69 00 00 00 17 00 00 00 1e 00 00 00
+      07 00 00 1c 00 00 00 07 00 00 01 02 00 00 00 00 00 00 00 5b
+        00 00 00 00 00 00 00 5b 00 00 00 1f
+    "
+    ));
+
+    let from_little = Message::read_from_buffer(&little_endian).unwrap();
+    let from_big = Message::read_from_buffer(&big_endian).unwrap();
+
+    assert_eq!(from_little.header, from_big.header);
+    assert_eq!(from_little.submessages.len(), 4);
+    assert_eq!(from_big.submessages.len(), 4);
+    for (l, b) in from_little.submessages.iter().zip(&from_big.submessages) {
+      assert_eq!(l.header.kind, b.header.kind);
+      assert_eq!(l.header.content_length, b.header.content_length);
+      assert_eq!(l.header.flags & !0x01, b.header.flags);
+      // The bodies carry the flags too, so compare their encodings in a common
+      // byte order instead.
+      assert_eq!(
+        l.body
+          .write_to_vec_with_ctx(Endianness::LittleEndian)
+          .unwrap(),
+        b.body
+          .write_to_vec_with_ctx(Endianness::LittleEndian)
+          .unwrap()
+      );
+    }
+
+    // Writing the parsed message must reproduce the big-endian packet.
+    let serialized = Bytes::from(
+      from_big
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+    );
+    assert_eq!(big_endian, serialized);
+  }
+
+  #[test]
+  fn odd_length_string_payload_is_padded() {
+    // INFO_TS, DATA and HEARTBEAT as Fast DDS sends a HelloWorld sample
+    // { index: 1, message: "HelloWorld" }. The SerializedPayload is 23 octets
+    // long, so the DATA ends with one octet of padding, which octetsToNextHeader
+    // includes, and the HEARTBEAT starts at a 4-octet boundary.
+    let bits = Bytes::copy_from_slice(&hex_literal::hex!(
+      "
+      52 54 50 53 02 03 01 0f 01 0f 45 d2 b3 f5 58 b9 01 00 00 00
+      09 01 08 00 5c 2a 9b 65 00 00 40 1f
+      15 05 2c 00 00 00 10 00 00 00 00 00 00 00 01 03 00 00 00 00 01 00 00 00
+        00 01 00 00 01 00 00 00 0b 00 00 00 48 65 6c 6c 6f 57 6f 72 6c 64 00
+        00
+      07 01 1c 00 00 00 00 00 00 00 01 03 00 00 00 00 01 00 00 00
+        00 00 00 00 01 00 00 00 01 00 00 00
+    "
+    ));
+
+    let rtps = Message::read_from_buffer(&bits).unwrap();
+    assert_eq!(rtps.submessages.len(), 3);
+    let data = match &rtps.submessages[1].body {
+      SubmessageBody::Writer(WriterSubmessage::Data(d, _flags)) => d,
+      other => panic!("Expected DATA, got {other:?}"),
+    };
+    // The padding is received as part of the payload, and ignored when decoding.
+    let payload = data.unwrap_serialized_payload();
+    let (index, message): (u32, String) = crate::serialization::deserialize_from_cdr_with_rep_id(
+      &payload.value,
+      payload.representation_identifier,
+    )
+    .map(|(sample, _bytes_consumed)| sample)
+    .unwrap();
+    assert_eq!((index, message.as_str()), (1, "HelloWorld"));
+    assert!(matches!(
+      rtps.submessages[2].body,
+      SubmessageBody::Writer(WriterSubmessage::Heartbeat(Heartbeat { count: 1, .. }, _))
+    ));
+
+    // Writing the same sample from its unpadded payload gives the same octets.
+    let unpadded = Data {
+      serialized_payload: Some(data.serialized_payload.as_ref().unwrap().slice(..23)),
+      ..data.clone()
+    };
+    assert_eq!(unpadded.len_serialized(), 44);
+    let data_start = 20 + 12 + 4;
+    assert_eq!(
+      unpadded
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+      bits[data_start..data_start + 44]
+    );
+
+    let serialized = Bytes::from(
+      rtps
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap(),
+    );
+    assert_eq!(bits, serialized);
+  }
+
+  #[test]
+  fn odd_length_payloads_keep_submessages_aligned() {
+    let reader_id = EntityId::UNKNOWN;
+    let writer_id = EntityId::SPDP_BUILTIN_PARTICIPANT_WRITER;
+    // A 5-octet parameter value, e.g. a CDR string of length 1
+    let inline_qos = ParameterList {
+      parameters: vec![Parameter {
+        parameter_id: ParameterId::PID_TOPIC_NAME,
+        value: vec![2, 0, 0, 0, b'x'],
+      }],
+    };
+    let data = Data {
+      reader_id,
+      writer_id,
+      writer_sn: SequenceNumber::new(1),
+      inline_qos: Some(inline_qos.clone()),
+      serialized_payload: Some(Bytes::from_static(&[0, 1, 0, 0, 7])),
+    };
+    // Last fragment of a 1029-octet sample
+    let data_frag = DataFrag {
+      reader_id,
+      writer_id,
+      writer_sn: SequenceNumber::new(2),
+      fragment_starting_num: FragmentNumber::new(2),
+      fragments_in_submessage: 1,
+      data_size: 1029,
+      fragment_size: 1024,
+      inline_qos: Some(inline_qos),
+      serialized_payload: Bytes::from_static(&[1, 2, 3, 4, 5]),
+    };
+    let heartbeat = Heartbeat {
+      reader_id,
+      writer_id,
+      first_sn: SequenceNumber::new(1),
+      last_sn: SequenceNumber::new(2),
+      count: 1,
+    };
+
+    let data_flags = DATA_Flags::Endianness | DATA_Flags::InlineQos | DATA_Flags::Data;
+    let datafrag_flags = DATAFRAG_Flags::Endianness | DATAFRAG_Flags::InlineQos;
+    let heartbeat_flags = BitFlags::<HEARTBEAT_Flags>::from(HEARTBEAT_Flags::Endianness);
+    let submessages = vec![
+      Submessage {
+        header: SubmessageHeader {
+          kind: SubmessageKind::DATA,
+          flags: data_flags.bits(),
+          content_length: data.len_serialized() as u16,
+        },
+        body: SubmessageBody::Writer(WriterSubmessage::Data(data, data_flags)),
+        original_bytes: None,
+      },
+      Submessage {
+        header: SubmessageHeader {
+          kind: SubmessageKind::DATA_FRAG,
+          flags: datafrag_flags.bits(),
+          content_length: data_frag.len_serialized() as u16,
+        },
+        body: SubmessageBody::Writer(WriterSubmessage::DataFrag(data_frag, datafrag_flags)),
+        original_bytes: None,
+      },
+      heartbeat
+        .clone()
+        .create_submessage(heartbeat_flags)
+        .unwrap(),
+    ];
+
+    for s in &submessages {
+      let body = s
+        .body
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap();
+      assert_eq!(usize::from(s.header.content_length), body.len());
+      assert_eq!(body.len() % 4, 0, "{:?} is not padded", s.header.kind);
+    }
+    // The parameter header gives the padded length
+    let data_body = submessages[0]
+      .body
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+    assert_eq!(data_body[20..24], [0x05, 0x00, 0x08, 0x00]);
+
+    let bytes = message_with(submessages)
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+    let parsed = Message::read_from_buffer(&Bytes::from(bytes)).unwrap();
+    assert_eq!(parsed.submessages.len(), 3);
+    assert_eq!(
+      parsed.submessages[2].body,
+      SubmessageBody::Writer(WriterSubmessage::Heartbeat(heartbeat, heartbeat_flags))
+    );
+  }
+
+  #[test]
+  fn consecutive_samples_share_info_ts() {
+    use crate::{
+      dds::with_key::datawriter::WriteOptionsBuilder,
+      messages::submessages::elements::serialized_payload::SerializedPayload,
+      structure::guid::EntityKind, RepresentationIdentifier,
+    };
+
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let t1 = Timestamp::from_ticks(1 << 32);
+    let t2 = Timestamp::from_ticks(2 << 32);
+    let mut builder = MessageBuilder::new();
+    for (sn, source_timestamp) in [(1, Some(t1)), (2, Some(t1)), (3, None), (4, Some(t2))] {
+      let mut write_options = WriteOptionsBuilder::new();
+      if let Some(ts) = source_timestamp {
+        write_options = write_options.source_timestamp(ts);
+      }
+      let cache_change = CacheChange::new(
+        writer_guid,
+        SequenceNumber::new(sn),
+        write_options.build(),
+        DDSData::new(SerializedPayload::new(
+          RepresentationIdentifier::CDR_LE,
+          vec![1, 2, 3, 4],
+        )),
+      );
+      builder = builder
+        .ts_msg_if_changed(Endianness::LittleEndian, source_timestamp)
+        .data_msg(
+          &cache_change,
+          EntityId::UNKNOWN,
+          writer_guid,
+          Endianness::LittleEndian,
+          None,
+        );
+    }
+    let message = builder.add_header_and_build(writer_guid.prefix);
+
+    // Each DATA is labelled with the INFO_TS in effect, or with none after
+    // an INFO_TS with the Invalidate flag.
+    let sequence: Vec<String> = message
+      .submessages
+      .iter()
+      .map(|s| match &s.body {
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(ts, flags)) => {
+          assert_eq!(
+            flags.contains(INFOTIMESTAMP_Flags::Invalidate),
+            ts.timestamp.is_none()
+          );
+          format!("INFO_TS {:?}", ts.timestamp.map(|t| t.to_ticks() >> 32))
+        }
+        SubmessageBody::Writer(WriterSubmessage::Data(d, _)) => {
+          format!("DATA {}", i64::from(d.writer_sn))
+        }
+        other => panic!("Unexpected submessage {other:?}"),
+      })
+      .collect();
+    assert_eq!(
+      sequence,
+      [
+        "INFO_TS Some(1)",
+        "DATA 1",
+        "DATA 2",
+        "INFO_TS None",
+        "DATA 3",
+        "INFO_TS Some(2)",
+        "DATA 4"
+      ]
+    );
+  }
+
+  #[test]
+  fn dispose_and_unregister_carry_key_hash_and_status_info() {
+    use crate::{
+      dds::{key::KeyHash, with_key::datawriter::WriteOptions},
+      messages::submessages::elements::{
+        inline_qos::{InlineQos, StatusInfoEnum},
+        serialized_payload::SerializedPayload,
+      },
+      structure::guid::EntityKind,
+      RepresentationIdentifier,
+    };
+
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let key = SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![7, 0, 0, 0]);
+    let key_hash = KeyHash::from_pl_cdr_bytes((1..=16).collect()).unwrap();
+    let inline_qos_of = |dds_data: DDSData| {
+      let cache_change = CacheChange::new(
+        writer_guid,
+        SequenceNumber::new(1),
+        WriteOptions::default(),
+        dds_data,
+      );
+      let message = MessageBuilder::new()
+        .data_msg(
+          &cache_change,
+          EntityId::UNKNOWN,
+          writer_guid,
+          Endianness::LittleEndian,
+          None,
+        )
+        .add_header_and_build(writer_guid.prefix);
+      match &message.submessages[0].body {
+        SubmessageBody::Writer(WriterSubmessage::Data(data, _)) => data.inline_qos.clone(),
+        other => panic!("Expected DATA, got {other:?}"),
+      }
+    };
+    let status_and_hash = |params: &ParameterList| {
+      let status_info =
+        InlineQos::status_info(params, RepresentationIdentifier::PL_CDR_LE).unwrap();
+      (
+        status_info.contains(StatusInfoEnum::Disposed),
+        status_info.contains(StatusInfoEnum::Unregistered),
+        InlineQos::key_hash(params).unwrap(),
+      )
+    };
+
+    // Alive samples need neither
+    assert_eq!(
+      inline_qos_of(DDSData::new(key.clone())).map(|p| p.parameters.len()),
+      None
+    );
+
+    let disposed = inline_qos_of(DDSData::new_disposed_by_key_and_hash(
+      ChangeKind::NotAliveDisposed,
+      key.clone(),
+      key_hash,
+    ))
+    .unwrap();
+    assert_eq!(status_and_hash(&disposed), (true, false, Some(key_hash)));
+
+    let unregistered = inline_qos_of(DDSData::new_disposed_by_key_and_hash(
+      ChangeKind::NotAliveUnregistered,
+      key.clone(),
+      key_hash,
+    ))
+    .unwrap();
+    assert_eq!(
+      status_and_hash(&unregistered),
+      (false, true, Some(key_hash))
+    );
+
+    // Without a known key hash, only the status info is sent
+    let disposed = inline_qos_of(DDSData::new_disposed_by_key(
+      ChangeKind::NotAliveDisposed,
+      key,
+    ))
+    .unwrap();
+    assert_eq!(status_and_hash(&disposed), (true, false, None));
+
+    let disposed = inline_qos_of(DDSData::new_disposed_by_key_hash(
+      ChangeKind::NotAliveDisposed,
+      key_hash,
+    ))
+    .unwrap();
+    assert_eq!(status_and_hash(&disposed), (true, false, Some(key_hash)));
+  }
+
+  #[test]
+  fn key_hash_is_not_sent_with_a_protected_key() {
+    use crate::{
+      dds::key::KeyHash,
+      messages::submessages::elements::{
+        inline_qos::{InlineQos, StatusInfoEnum},
+        serialized_payload::SerializedPayload,
+      },
+      RepresentationIdentifier,
+    };
+
+    // The key hash of a small key is the key itself
+    let key = SerializedPayload::new(RepresentationIdentifier::CDR_BE, vec![0, 0, 0, 7]);
+    let key_hash =
+      KeyHash::from_pl_cdr_bytes([[0, 0, 0, 7], [0; 4], [0; 4], [0; 4]].concat()).unwrap();
+    let inline_qos_of = |dds_data: &DDSData, payload_protected: bool| {
+      let mut param_list = ParameterList::new();
+      MessageBuilder::instance_state_inline_qos(dds_data, payload_protected, &mut param_list);
+      param_list
+    };
+
+    let disposed =
+      DDSData::new_disposed_by_key_and_hash(ChangeKind::NotAliveDisposed, key, key_hash);
+    let plain = inline_qos_of(&disposed, false);
+    assert_eq!(InlineQos::key_hash(&plain).unwrap(), Some(key_hash));
+
+    // The key goes in the protected payload, so the key hash must not reveal it
+    let protected = inline_qos_of(&disposed, true);
+    assert_eq!(InlineQos::key_hash(&protected).unwrap(), None);
+    assert!(
+      InlineQos::status_info(&protected, RepresentationIdentifier::PL_CDR_LE)
+        .unwrap()
+        .contains(StatusInfoEnum::Disposed)
+    );
+
+    // Without a payload, the key hash is all there is to identify the instance
+    let disposed_by_hash =
+      DDSData::new_disposed_by_key_hash(ChangeKind::NotAliveDisposed, key_hash);
+    assert_eq!(
+      InlineQos::key_hash(&inline_qos_of(&disposed_by_hash, true)).unwrap(),
+      Some(key_hash)
+    );
+  }
+
+  // SEC_PREFIX, SEC_BODY and SEC_POSTFIX of an encrypted submessage, with the
+  // given flags, laid out as Fast DDS sends them with AES128_GCM: CryptoHeader
+  // of transformation kind, key id, session id and IV suffix, CryptoContent
+  // with a big-endian length, and CryptoFooter of common MAC and an empty
+  // sequence of receiver-specific MACs.
+  #[cfg(feature = "security")]
+  fn secure_submessage_triple(flags: u8) -> Vec<u8> {
+    // octetsToNextHeader follows the Endianness flag
+    let header = |kind: u8, length: u16| {
+      let length = if flags & 0x01 == 0 {
+        length.to_be_bytes()
+      } else {
+        length.to_le_bytes()
+      };
+      [kind, flags, length[0], length[1]]
+    };
+    [
+      &header(0x31, 20)[..],
+      &hex_literal::hex!("00000002 00000103 00000007 1122334455667788"),
+      &header(0x30, 24),
+      &hex_literal::hex!("00000014 0102030405060708090a0b0c0d0e0f1011121314"),
+      &header(0x32, 20),
+      &hex_literal::hex!("a0a1a2a3a4a5a6a7a8a9aaabacadaeaf 00000000"),
+    ]
+    .concat()
+  }
+
+  #[cfg(feature = "security")]
+  #[test]
+  fn secure_submessages_round_trip() {
+    use crate::{
+      messages::submessages::{
+        secure_postfix::SecurePostfix, secure_prefix::SecurePrefix, submessage::SecuritySubmessage,
+      },
+      security::cryptographic::types::CryptoTransformKeyId,
+    };
+
+    let header = hex_literal::hex!("52545053 0203 010f 010f45d2b3f558b901000000");
+    // Both the big-endian (E=0) and the little-endian (E=1) flags. The
+    // contents of these submessages do not depend on the flag.
+    for flags in [0x00, 0x01] {
+      let bits = Bytes::from([&header[..], &secure_submessage_triple(flags)].concat());
+      let rtps = Message::read_from_buffer(&bits).unwrap();
+      let [prefix, body, postfix] = &rtps.submessages[..] else {
+        panic!("Expected 3 submessages, got {:?}", rtps.submessages);
+      };
+      let SubmessageBody::Security(SecuritySubmessage::SecurePrefix(sec_prefix, prefix_flags)) =
+        &prefix.body
+      else {
+        panic!("Expected SEC_PREFIX, got {prefix:?}");
+      };
+      let crypto_header = &sec_prefix.crypto_header;
+      assert_eq!(
+        crypto_header.transformation_id.transformation_kind,
+        [0, 0, 0, 2]
+      );
+      assert_eq!(
+        crypto_header.transformation_id.transformation_key_id,
+        CryptoTransformKeyId::from([0, 0, 1, 3])
+      );
+      assert_eq!(
+        crypto_header.plugin_crypto_header_extra.data,
+        hex_literal::hex!("00000007 1122334455667788")
+      );
+      let SubmessageBody::Security(SecuritySubmessage::SecureBody(sec_body, _)) = &body.body else {
+        panic!("Expected SEC_BODY, got {body:?}");
+      };
+      assert_eq!(sec_body.crypto_content.data, (1..=20).collect::<Vec<u8>>());
+      let SubmessageBody::Security(SecuritySubmessage::SecurePostfix(sec_postfix, _)) =
+        &postfix.body
+      else {
+        panic!("Expected SEC_POSTFIX, got {postfix:?}");
+      };
+      assert_eq!(sec_postfix.crypto_footer.data.len(), 20);
+
+      let endianness = if prefix_flags.contains(SECUREPREFIX_Flags::Endianness) {
+        Endianness::LittleEndian
+      } else {
+        Endianness::BigEndian
+      };
+      assert_eq!(flags == 0x01, endianness == Endianness::LittleEndian);
+
+      // Writing the parsed message reproduces the octets
+      let serialized = rtps.write_to_vec_with_ctx(endianness).unwrap();
+      assert_eq!(bits, serialized);
+
+      // So does building the submessages from their contents
+      let rebuilt = [
+        SecurePrefix {
+          crypto_header: crypto_header.clone(),
+        }
+        .create_submessage(endianness)
+        .unwrap(),
+        sec_body.clone().create_submessage(endianness).unwrap(),
+        SecurePostfix {
+          crypto_footer: sec_postfix.crypto_footer.clone(),
+        }
+        .create_submessage(endianness)
+        .unwrap(),
+      ];
+      assert_eq!(
+        message_with(rebuilt.to_vec())
+          .write_to_vec_with_ctx(endianness)
+          .unwrap()[20..],
+        bits[20..]
+      );
+    }
+  }
+
+  #[cfg(feature = "security")]
+  #[test]
+  fn secure_rtps_message_round_trip() {
+    use crate::messages::submessages::submessage::SecuritySubmessage;
+
+    // SRTPS_PREFIX, the encrypted submessages of the original message, and
+    // SRTPS_POSTFIX
+    let triple = secure_submessage_triple(0x00);
+    let mut bits = hex_literal::hex!("52545053 0203 010f 010f45d2b3f558b901000000").to_vec();
+    bits.extend([0x33, 0x00, 0x00, 0x14]);
+    bits.extend(&triple[4..24]);
+    bits.extend(&triple[24..52]);
+    bits.extend([0x34, 0x00, 0x00, 0x14]);
+    bits.extend(&triple[56..]);
+    let bits = Bytes::from(bits);
+
+    let rtps = Message::read_from_buffer(&bits).unwrap();
+    let kinds: Vec<SubmessageKind> = rtps.submessages.iter().map(|s| s.header.kind).collect();
+    assert_eq!(
+      kinds,
+      [
+        SubmessageKind::SRTPS_PREFIX,
+        SubmessageKind::SEC_BODY,
+        SubmessageKind::SRTPS_POSTFIX
+      ]
+    );
+    let SubmessageBody::Security(SecuritySubmessage::SecureRTPSPrefix(srtps_prefix, _)) =
+      &rtps.submessages[0].body
+    else {
+      panic!("Expected SRTPS_PREFIX");
+    };
+    let SubmessageBody::Security(SecuritySubmessage::SecureRTPSPostfix(srtps_postfix, _)) =
+      &rtps.submessages[2].body
+    else {
+      panic!("Expected SRTPS_POSTFIX");
+    };
+
+    assert_eq!(
+      rtps.write_to_vec_with_ctx(Endianness::BigEndian).unwrap(),
+      bits
+    );
+    let rebuilt = [
+      srtps_prefix
+        .clone()
+        .create_submessage(Endianness::BigEndian)
+        .unwrap(),
+      rtps.submessages[1].clone(),
+      srtps_postfix
+        .clone()
+        .create_submessage(Endianness::BigEndian)
+        .unwrap(),
+    ];
+    assert_eq!(
+      message_with(rebuilt.to_vec())
+        .write_to_vec_with_ctx(Endianness::BigEndian)
+        .unwrap()[20..],
+      bits[20..]
+    );
+  }
+
   #[test]
   fn fuzz_rtps() {
     // https://github.com/jhelovuo/RustDDS/issues/280
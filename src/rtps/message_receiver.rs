@@ -17,9 +17,19 @@ use crate::{
   },
 };
 #[cfg(feature = "security")]
-use crate::security::{
-  cryptographic::{DecodeOutcome, DecodedSubmessage},
-  security_plugins::SecurityPluginsHandle,
+use crate::{
+  rtps::{
+    constant::{
+      MAX_PARKED_SECURE_SUBMESSAGES, MAX_PARKED_SECURE_SUBMESSAGES_PER_KEY_ID,
+      MAX_SECURE_SUBMESSAGE_PARKING_TIME,
+    },
+    parked_secure_submessages::{Parked, ParkedSecureSubmessages},
+  },
+  security::{
+    cryptographic::{DecodeOutcome, DecodedSubmessage},
+    security_plugins::SecurityPluginsHandle,
+    SecurityError, SecurityErrorKind,
+  },
 };
 #[cfg(feature = "security")]
 use crate::messages::submessages::{secure_postfix::SecurePostfix, secure_prefix::SecurePrefix};
@@ -65,6 +75,24 @@ pub struct SecureWrapping {
   // TODO
 }
 
+// A secure submessage waiting for the key material of its sender, with the
+// receiver state it arrived in
+#[cfg(feature = "security")]
+struct ParkedSecureSubmessage {
+  source_version: ProtocolVersion,
+  source_vendor_id: VendorId,
+  source_guid_prefix: GuidPrefix,
+  dest_guid_prefix: GuidPrefix,
+  unicast_reply_locator_list: Vec<Locator>,
+  multicast_reply_locator_list: Vec<Locator>,
+  source_timestamp: Option<Timestamp>,
+  secure_rtps_wrapped: Option<SecureWrapping>,
+  must_be_rtps_protection_special_case: bool,
+  sec_prefix: SecurePrefix,
+  encoded_submessage: Submessage,
+  sec_postfix: SecurePostfix,
+}
+
 // This is partial receiver state to be sent to a Reader or a Writer with a
 // Submessage
 #[derive(Debug, Clone)]
@@ -124,6 +152,8 @@ pub(crate) struct MessageReceiver {
   pub source_timestamp: Option<Timestamp>,
 
   submessage_count: usize, // Used in tests and error messages only?
+  // Submessages of unknown or vendor-specific kind, in all messages so far
+  skipped_submessage_count: u64,
   secure_receiver_state: Option<SecureReceiverState>,
   #[cfg(feature = "security")]
   secure_rtps_wrapped: Option<SecureWrapping>,
@@ -131,6 +161,12 @@ pub(crate) struct MessageReceiver {
   // For certain topics we have to allow unprotected rtps messages even if the domain is
   // rtps-protected
   must_be_rtps_protection_special_case: bool,
+  #[cfg(feature = "security")]
+  parked_secure_submessages: ParkedSecureSubmessages<ParkedSecureSubmessage>,
+  // SecurityPlugins::remote_endpoint_crypto_tokens_set at the last retry of the
+  // parked submessages
+  #[cfg(feature = "security")]
+  remote_endpoint_crypto_tokens_seen: u64,
 }
 
 impl MessageReceiver {
@@ -156,12 +192,21 @@ impl MessageReceiver {
       source_timestamp: None,
 
       submessage_count: 0,
+      skipped_submessage_count: 0,
       secure_receiver_state: None,
       #[cfg(feature = "security")]
       secure_rtps_wrapped: None,
       #[cfg(feature = "security")]
       // Protection on by default
       must_be_rtps_protection_special_case: true,
+      #[cfg(feature = "security")]
+      parked_secure_submessages: ParkedSecureSubmessages::new(
+        MAX_PARKED_SECURE_SUBMESSAGES_PER_KEY_ID,
+        MAX_PARKED_SECURE_SUBMESSAGES,
+        MAX_SECURE_SUBMESSAGE_PARKING_TIME,
+      ),
+      #[cfg(feature = "security")]
+      remote_endpoint_crypto_tokens_seen: 0,
     }
   }
 
@@ -253,8 +298,16 @@ impl MessageReceiver {
 
     // call Speedy reader
     // Bytes .clone() is cheap, so no worries
-    let rtps_message = match Message::read_from_buffer(msg_bytes) {
-      Ok(m) => m,
+    let rtps_message = match Message::read_from_buffer_counting_skipped(msg_bytes) {
+      Ok((m, 0)) => m,
+      Ok((m, skipped)) => {
+        self.skipped_submessage_count += skipped as u64;
+        debug!(
+          "Skipped {skipped} submessages of unknown kind, {} so far",
+          self.skipped_submessage_count
+        );
+        m
+      }
       Err(speedy_err) => {
         warn!("RTPS deserialize error {:?}", speedy_err);
         debug!("Data was {:?}", msg_bytes);
@@ -268,6 +321,9 @@ impl MessageReceiver {
 
   // This is also called directly from dp_event_loop in case of loopback messages.
   pub fn handle_parsed_message(&mut self, rtps_message: Message) {
+    #[cfg(feature = "security")]
+    self.retry_parked_secure_submessages();
+
     self.reset();
     self.dest_guid_prefix = self.own_guid_prefix;
     self.source_guid_prefix = rtps_message.header.guid_prefix;
@@ -285,7 +341,7 @@ impl MessageReceiver {
       }
 
       Some(security_plugins_handle) => {
-        let security_plugins = security_plugins_handle.get_plugins();
+        let security_plugins = security_plugins_handle.read_plugins();
 
         // If the first submessage is SecureRTPSPrefix, the message has to be decoded
         // using the cryptographic plugin
@@ -317,7 +373,7 @@ impl MessageReceiver {
                 guid_prefix
               )
             }
-            Err(e) => return error!("{e:?}"),
+            Err(e) => return Self::log_decode_error("RTPS message", &e),
           }
         } else {
           if security_plugins.rtps_not_protected(&self.dest_guid_prefix) {
@@ -399,8 +455,8 @@ impl MessageReceiver {
                       entity_id: target_entity_id,
                     };
                     if plugins_handle
-                      .get_plugins()
-                      .submessage_not_protected(&destination_guid)
+                      .read_plugins()
+                      .accept_unprotected_submessage(&destination_guid, self.source_guid_prefix)
                     {
                       self.handle_writer_submessage(target_entity_id, submessage.clone());
                     }
@@ -421,8 +477,8 @@ impl MessageReceiver {
                     entity_id: receiver_entity_id,
                   };
                   if plugins_handle
-                    .get_plugins()
-                    .submessage_not_protected(&destination_guid)
+                    .read_plugins()
+                    .accept_unprotected_submessage(&destination_guid, self.source_guid_prefix)
                   {
                     self.handle_writer_submessage(receiver_entity_id, submessage);
                   } else {
@@ -442,20 +498,18 @@ impl MessageReceiver {
               self.handle_reader_submessage(submessage);
             }
             #[cfg(feature = "security")]
-            match self
-              .security_plugins
-              .as_ref()
-              .map(SecurityPluginsHandle::get_plugins)
-            {
+            match self.security_plugins.as_ref() {
               None => self.handle_reader_submessage(submessage),
-              Some(plugins) => {
+              Some(plugins_handle) => {
                 let destination_guid = GUID {
                   prefix: self.dest_guid_prefix,
                   entity_id: submessage.receiver_entity_id(),
                 };
-                #[cfg(feature = "security")]
-                // This match branch can only be taken with security feature
-                if plugins.submessage_not_protected(&destination_guid) {
+                // Release the plugins before handing the submessage on
+                let accepted = plugins_handle
+                  .read_plugins()
+                  .accept_unprotected_submessage(&destination_guid, self.source_guid_prefix);
+                if accepted {
                   self.handle_reader_submessage(submessage);
                 } else {
                   error!(
@@ -528,7 +582,7 @@ impl MessageReceiver {
         // Now expecting postfix, and only that.
         match submessage.body {
           SubmessageBody::Security(SecuritySubmessage::SecurePostfix(sec_postfix, _)) => {
-            self.handle_secure_submessage(&sec_prefix, &sec_submessage, &sec_postfix);
+            self.handle_secure_submessage(&sec_prefix, &sec_submessage, &sec_postfix, None);
           }
           other => {
             warn!(
@@ -618,11 +672,7 @@ impl MessageReceiver {
       }
 
       WriterSubmessage::Heartbeat(heartbeat, flags) => {
-        target_reader.handle_heartbeat_msg(
-          &heartbeat,
-          flags.contains(HEARTBEAT_Flags::Final),
-          &mr_state,
-        );
+        target_reader.handle_heartbeat_msg(&heartbeat, flags, &mr_state);
       }
 
       WriterSubmessage::Gap(gap, _flags) => {
@@ -659,6 +709,28 @@ impl MessageReceiver {
     reader.handle_data_msg(data, data_flags, mr_state);
   }
 
+  // The decoded message is dropped in every case. How loudly depends on the
+  // kind of the error: a sender whose keys we do not have yet is expected
+  // during discovery, and reliable writers resend once the keys have been
+  // exchanged. Data that fails verification or cannot be parsed comes from the
+  // remote end, and is not our error.
+  #[cfg(feature = "security")]
+  fn log_decode_error(what: &str, e: &SecurityError) {
+    match e.kind() {
+      SecurityErrorKind::NotRegistered => {
+        debug!("{what} decoding failed, sender not registered yet: {e}");
+      }
+      SecurityErrorKind::VerificationFailed
+      | SecurityErrorKind::MalformedToken
+      | SecurityErrorKind::UnsupportedTransformation => {
+        warn!("{what} decoding failed, dropping it: {e}");
+      }
+      SecurityErrorKind::Internal | SecurityErrorKind::Other => {
+        error!("{what} decoding failed: {e}");
+      }
+    }
+  }
+
   #[cfg(feature = "security")]
   fn decode_and_handle_data(
     security_plugins: Option<&SecurityPluginsHandle>,
@@ -674,10 +746,16 @@ impl MessageReceiver {
       ..
     } = data.clone();
 
+    // Once decoded, a protected payload is a standard SerializedPayload again
+    let mut data_flags = data_flags;
+    if security_plugins.is_some_and(|sp| !sp.read_plugins().payload_not_protected(&reader.guid())) {
+      data_flags.remove(DATA_Flags::NonStandardPayload);
+    }
+
     serialized_payload
       // If there is an encoded_payload, decode it
       .map(
-        |encoded_payload| match security_plugins.map(SecurityPluginsHandle::get_plugins) {
+        |encoded_payload| match security_plugins.map(SecurityPluginsHandle::read_plugins) {
           Some(security_plugins) => security_plugins
             .decode_serialized_payload(
               encoded_payload,
@@ -685,7 +763,7 @@ impl MessageReceiver {
               source_guid,
               &reader.guid(),
             )
-            .map_err(|e| error!("{e:?}")),
+            .map_err(|e| Self::log_decode_error("Serialized payload", &e)),
           None => Ok(encoded_payload),
         },
       )
@@ -753,7 +831,13 @@ impl MessageReceiver {
       ..
     } = datafrag.clone();
 
-    match security_plugins.map(SecurityPluginsHandle::get_plugins) {
+    // see decode_and_handle_data above
+    let mut datafrag_flags = datafrag_flags;
+    if security_plugins.is_some_and(|sp| !sp.read_plugins().payload_not_protected(&reader.guid())) {
+      datafrag_flags.remove(DATAFRAG_Flags::NonStandardPayload);
+    }
+
+    match security_plugins.map(SecurityPluginsHandle::read_plugins) {
       Some(security_plugins) => {
         // Decode
         security_plugins
@@ -763,7 +847,7 @@ impl MessageReceiver {
             source_guid,
             &reader.guid(),
           )
-          .map_err(|e| error!("{e:?}"))
+          .map_err(|e| Self::log_decode_error("Serialized payload", &e))
       }
       None => Ok(encoded_payload),
     }
@@ -849,18 +933,31 @@ impl MessageReceiver {
         }
       }
 
-      ReaderSubmessage::NackFrag(_, _) => {
-        // TODO: Implement NackFrag handling
+      ReaderSubmessage::NackFrag(nackfrag, _) => {
+        // Goes to the Writer the same way as AckNack, see above.
+        match self
+          .acknack_sender
+          .try_send((self.source_guid_prefix, AckSubmessage::NackFrag(nackfrag)))
+        {
+          Ok(_) => (),
+          Err(TrySendError::Full(_)) => {
+            info!("AckNack pipe full. Looks like I am very busy. Discarding NackFrag.");
+          }
+          Err(e) => warn!("AckNack pipe fail: {:?}", e),
+        }
       }
     }
   }
 
   #[cfg(feature = "security")]
+  // parked_at is the time the submessage was first parked, if it is being
+  // retried
   fn handle_secure_submessage(
     &mut self,
     sec_prefix: &SecurePrefix,
     encoded_submessage: &Submessage,
     sec_postfix: &SecurePostfix,
+    parked_at: Option<std::time::Instant>,
   ) {
     let security_plugins = self.security_plugins.clone();
     match security_plugins {
@@ -871,7 +968,7 @@ impl MessageReceiver {
         // Call 8.5.1.9.6 Operation: preprocess_secure_submsg to determine what
         // the submessage contains and then proceed to decode and process accordingly.
 
-        let decode_result = security_plugins_handle.get_plugins().decode_submessage(
+        let decode_result = security_plugins_handle.read_plugins().decode_submessage(
           (
             sec_prefix.clone(),
             encoded_submessage.clone(),
@@ -880,9 +977,7 @@ impl MessageReceiver {
           &self.source_guid_prefix,
         );
         match decode_result {
-          Err(e) => {
-            error!("Submessage decoding failed: {e:?}");
-          }
+          Err(e) => Self::log_decode_error("Submessage", &e),
           Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
             decoded_writer_submessage,
             approved_receiving_datareader_crypto_handles,
@@ -909,7 +1004,7 @@ impl MessageReceiver {
                       && target_reader.entity_id() == EntityId::P2P_BUILTIN_PARTICIPANT_STATELESS_READER)
                     )
                     &&
-                    security_plugins_handle.get_plugins()
+                    security_plugins_handle.read_plugins()
                     .confirm_local_endpoint_guid(&approved_receiving_datareader_crypto_handles,
                       &GUID { prefix: self.dest_guid_prefix,entity_id: target_reader.entity_id() })
               }){
@@ -923,7 +1018,7 @@ impl MessageReceiver {
                 entity_id: receiver_entity_id,
               };
               if security_plugins_handle
-                .get_plugins()
+                .read_plugins()
                 .confirm_local_endpoint_guid(
                   &approved_receiving_datareader_crypto_handles,
                   &receiver_guid,
@@ -945,7 +1040,7 @@ impl MessageReceiver {
               entity_id: receiver_entity_id,
             };
             if security_plugins_handle
-              .get_plugins()
+              .read_plugins()
               .confirm_local_endpoint_guid(
                 &approved_receiving_datawriter_crypto_handles,
                 &receiver_guid,
@@ -964,10 +1059,27 @@ impl MessageReceiver {
           Ok(DecodeOutcome::KeysNotFound(header_key_id)) => {
             trace!(
               "No matching submessage decode keys found for the key id {:?} for the remote \
-               participant {:?}",
+               participant {:?}. Waiting for the crypto tokens.",
               header_key_id,
               self.source_guid_prefix
             );
+            let parked = ParkedSecureSubmessage {
+              source_version: self.source_version,
+              source_vendor_id: self.source_vendor_id,
+              source_guid_prefix: self.source_guid_prefix,
+              dest_guid_prefix: self.dest_guid_prefix,
+              unicast_reply_locator_list: self.unicast_reply_locator_list.clone(),
+              multicast_reply_locator_list: self.multicast_reply_locator_list.clone(),
+              source_timestamp: self.source_timestamp,
+              secure_rtps_wrapped: self.secure_rtps_wrapped.clone(),
+              must_be_rtps_protection_special_case: self.must_be_rtps_protection_special_case,
+              sec_prefix: sec_prefix.clone(),
+              encoded_submessage: encoded_submessage.clone(),
+              sec_postfix: sec_postfix.clone(),
+            };
+            self
+              .parked_secure_submessages
+              .park(header_key_id, parked, parked_at);
           }
           Ok(DecodeOutcome::ValidatingReceiverSpecificMACFailed) => {
             trace!("No endpoints passed the receiver-specific MAC validation for the submessage.");
@@ -984,6 +1096,48 @@ impl MessageReceiver {
     };
   }
 
+  // Decodes the parked secure submessages again, if crypto tokens of remote
+  // endpoints have been set since the last try. Those still missing their key
+  // are parked again.
+  #[cfg(feature = "security")]
+  fn retry_parked_secure_submessages(&mut self) {
+    if self.parked_secure_submessages.is_empty() {
+      return;
+    }
+    let tokens_set = match &self.security_plugins {
+      Some(security_plugins) => security_plugins
+        .read_plugins()
+        .remote_endpoint_crypto_tokens_set(),
+      None => return,
+    };
+    if tokens_set == self.remote_endpoint_crypto_tokens_seen {
+      return;
+    }
+    self.remote_endpoint_crypto_tokens_seen = tokens_set;
+
+    for Parked {
+      parked_at, item, ..
+    } in self.parked_secure_submessages.take_all()
+    {
+      self.reset();
+      self.source_version = item.source_version;
+      self.source_vendor_id = item.source_vendor_id;
+      self.source_guid_prefix = item.source_guid_prefix;
+      self.dest_guid_prefix = item.dest_guid_prefix;
+      self.unicast_reply_locator_list = item.unicast_reply_locator_list;
+      self.multicast_reply_locator_list = item.multicast_reply_locator_list;
+      self.source_timestamp = item.source_timestamp;
+      self.secure_rtps_wrapped = item.secure_rtps_wrapped;
+      self.must_be_rtps_protection_special_case = item.must_be_rtps_protection_special_case;
+      self.handle_secure_submessage(
+        &item.sec_prefix,
+        &item.encoded_submessage,
+        &item.sec_postfix,
+        Some(parked_at),
+      );
+    }
+  }
+
   fn handle_interpreter_submessage(&mut self, interpreter_submessage: InterpreterSubmessage)
   // no return value, just change state of self.
   {
@@ -1092,7 +1246,7 @@ mod tests {
     sync::{Arc, Mutex, RwLock},
   };
 
-  use speedy::{Readable, Writable};
+  use speedy::{Endianness, Readable, Writable};
   use log::info;
   use serde::{Deserialize, Serialize};
   use mio_extras::channel as mio_channel;
@@ -1100,7 +1254,7 @@ mod tests {
 
   use crate::{
     dds::{
-      qos::QosPolicies,
+      qos::{policy, QosPolicies, QosPolicyBuilder},
       statusevents::{sync_status_channel, DataReaderStatus},
       typedesc::TypeDesc,
       with_key::simpledatareader::ReaderCommand,
@@ -1114,29 +1268,42 @@ mod tests {
   };
   use super::*;
 
-  #[test]
-
-  fn test_shapes_demo_message_deserialization() {
-    // The following message bytes contain serialized INFO_DST, INFO_TS, DATA &
-    // HEARTBEAT submessages. The DATA submessage contains a ShapeType value.
-    // The bytes have been captured from WireShark.
-    let udp_bits1 = Bytes::from_static(&[
-      0x52, 0x54, 0x50, 0x53, 0x02, 0x03, 0x01, 0x0f, 0x01, 0x0f, 0x99, 0x06, 0x78, 0x34, 0x00,
-      0x00, 0x01, 0x00, 0x00, 0x00, 0x0e, 0x01, 0x0c, 0x00, 0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d,
-      0x31, 0xa2, 0x28, 0x20, 0x02, 0x08, 0x09, 0x01, 0x08, 0x00, 0x1a, 0x15, 0xf3, 0x5e, 0x00,
-      0xcc, 0xfb, 0x13, 0x15, 0x05, 0x2c, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x07,
-      0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
-      0x00, 0x04, 0x00, 0x00, 0x00, 0x52, 0x45, 0x44, 0x00, 0x69, 0x00, 0x00, 0x00, 0x17, 0x00,
-      0x00, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x07, 0x01, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00,
-      0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-      0x5b, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00,
-    ]);
-
-    // The message bytes contain the following guid prefix as the message target.
-    let target_gui_prefix = GuidPrefix::new(&[
-      0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d, 0x31, 0xa2, 0x28, 0x20, 0x02, 0x08,
-    ]);
+  // The following message bytes contain serialized INFO_DST, INFO_TS, DATA &
+  // HEARTBEAT submessages. The DATA submessage contains a ShapeType value.
+  // The bytes have been captured from WireShark.
+  const SHAPES_DEMO_MESSAGE: [u8; 128] = [
+    0x52, 0x54, 0x50, 0x53, 0x02, 0x03, 0x01, 0x0f, 0x01, 0x0f, 0x99, 0x06, 0x78, 0x34, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x0e, 0x01, 0x0c, 0x00, 0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d, 0x31, 0xa2,
+    0x28, 0x20, 0x02, 0x08, 0x09, 0x01, 0x08, 0x00, 0x1a, 0x15, 0xf3, 0x5e, 0x00, 0xcc, 0xfb, 0x13,
+    0x15, 0x05, 0x2c, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x01, 0x02,
+    0x00, 0x00, 0x00, 0x00, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+    0x52, 0x45, 0x44, 0x00, 0x69, 0x00, 0x00, 0x00, 0x17, 0x00, 0x00, 0x00, 0x1e, 0x00, 0x00, 0x00,
+    0x07, 0x01, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00,
+    0x5b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5b, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00,
+  ];
+
+  // The message bytes contain the following guid prefix as the message target.
+  const SHAPES_DEMO_TARGET_PREFIX: [u8; 12] = [
+    0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d, 0x31, 0xa2, 0x28, 0x20, 0x02, 0x08,
+  ];
+
+  // A message receiver with a reader for the ShapeType DATA of
+  // SHAPES_DEMO_MESSAGE
+  fn shapes_demo_receiver() -> (MessageReceiver, GUID) {
+    shapes_demo_receiver_of(
+      GuidPrefix::new(&SHAPES_DEMO_TARGET_PREFIX),
+      &QosPolicies::qos_none(),
+      vec![],
+    )
+  }
 
+  // As above, but for a participant with the given prefix, a reader with the
+  // given QoS, and the writer reachable (for ACKNACKs) at the given locators.
+  fn shapes_demo_receiver_of(
+    target_gui_prefix: GuidPrefix,
+    qos_policy: &QosPolicies,
+    writer_unicast_locators: Vec<Locator>,
+  ) -> (MessageReceiver, GUID) {
     // The message bytes contain the following guid as the message source
     let remote_writer_guid = GUID::new(
       GuidPrefix::new(&[
@@ -1173,14 +1340,12 @@ mod tests {
     let (_reader_command_sender, reader_command_receiver) =
       mio_channel::sync_channel::<ReaderCommand>(10);
 
-    let qos_policy = QosPolicies::qos_none();
-
     let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
 
     let topic_cache_handle = dds_cache.write().unwrap().add_new_topic(
       "test".to_string(),
       TypeDesc::new("test".to_string()),
-      &qos_policy,
+      qos_policy,
     );
     let reader_ing = ReaderIngredients {
       guid: reader_guid,
@@ -1189,7 +1354,7 @@ mod tests {
       topic_name: "test".to_string(),
       topic_cache_handle: topic_cache_handle.clone(),
       like_stateless: false,
-      qos_policy,
+      qos_policy: qos_policy.clone(),
       data_reader_command_receiver: reader_command_receiver,
       data_reader_waker: data_reader_waker.clone(),
       poll_event_sender: notification_event_sender,
@@ -1207,19 +1372,26 @@ mod tests {
     new_reader.matched_writer_add(
       remote_writer_guid,
       EntityId::UNKNOWN,
+      writer_unicast_locators,
       vec![],
-      vec![],
-      &QosPolicies::qos_none(),
+      qos_policy,
     );
 
-    // Add reader to message reader and process the bytes message
+    // Add reader to message reader
     message_receiver.add_reader(new_reader);
+    (message_receiver, reader_guid)
+  }
 
-    message_receiver.handle_received_packet(&udp_bits1);
-
-    // Verify the message reader has recorded the right amount of submessages
-    assert_eq!(message_receiver.submessage_count, 4);
+  #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+  struct ShapeType {
+    color: String,
+    x: i32,
+    y: i32,
+    size: i32,
+  }
 
+  // The first ShapeType in the reader's history cache
+  fn received_shape(message_receiver: &MessageReceiver, reader_guid: GUID) -> ShapeType {
     // This is not correct way to read history cache values but it serves as a test
     let sequence_numbers =
       message_receiver.get_reader_history_cache_start_and_end_seq_num(reader_guid.entity_id);
@@ -1238,18 +1410,180 @@ mod tests {
     info!("reader history cache DATA: {:?}", a.data());
 
     // Deserialize the ShapesType value from the data
-    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
-    struct ShapeType {
-      color: String,
-      x: i32,
-      y: i32,
-      size: i32,
-    }
     let (deserialized_shape_type, _) = from_bytes::<ShapeType, LittleEndian>(&a.data()).unwrap();
     info!("deserialized shapeType: {:?}", deserialized_shape_type);
+    deserialized_shape_type
+  }
+
+  #[test]
+  fn test_shapes_demo_message_deserialization() {
+    let (mut message_receiver, reader_guid) = shapes_demo_receiver();
+    message_receiver.handle_received_packet(&Bytes::from_static(&SHAPES_DEMO_MESSAGE));
+
+    // Verify the message reader has recorded the right amount of submessages
+    assert_eq!(message_receiver.submessage_count, 4);
+    assert_eq!(message_receiver.skipped_submessage_count, 0);
 
     // Verify the color in the deserialized value is correct
-    assert_eq!(deserialized_shape_type.color, "RED");
+    assert_eq!(received_shape(&message_receiver, reader_guid).color, "RED");
+  }
+
+  #[test]
+  fn unknown_submessages_are_skipped() {
+    let m = &SHAPES_DEMO_MESSAGE;
+    // Header and INFO_DST, INFO_TS, then a vendor-specific submessage (as
+    // Fast DDS sends), DATA, an unknown standard kind, HEARTBEAT, and last a
+    // vendor-specific submessage with length 0, i.e. extending to the end.
+    let message = Bytes::from(
+      [
+        &m[..48],
+        &[0x80, 0x01, 0x08, 0x00, 1, 2, 3, 4, 5, 6, 7, 8][..],
+        &m[48..96],
+        &[0x7e, 0x01, 0x04, 0x00, 0xff, 0xff, 0xff, 0xff][..],
+        &m[96..],
+        &[0xff, 0x01, 0x00, 0x00, 9, 9, 9, 9, 9, 9][..],
+      ]
+      .concat(),
+    );
+
+    let (parsed, skipped) = Message::read_from_buffer_counting_skipped(&message).unwrap();
+    assert_eq!(skipped, 3);
+    let kinds: Vec<SubmessageKind> = parsed.submessages.iter().map(|s| s.header.kind).collect();
+    assert_eq!(
+      kinds,
+      [
+        SubmessageKind::INFO_DST,
+        SubmessageKind::INFO_TS,
+        SubmessageKind::DATA,
+        SubmessageKind::HEARTBEAT
+      ]
+    );
+
+    let (mut message_receiver, reader_guid) = shapes_demo_receiver();
+    message_receiver.handle_received_packet(&message);
+    assert_eq!(message_receiver.submessage_count, 4);
+    assert_eq!(message_receiver.skipped_submessage_count, 3);
+    assert_eq!(received_shape(&message_receiver, reader_guid).color, "RED");
+  }
+
+  #[test]
+  fn samples_get_the_source_timestamp_in_effect() {
+    let m = &SHAPES_DEMO_MESSAGE;
+    // The DATA of SHAPES_DEMO_MESSAGE with the given sequence number
+    let data = |sn: u8| {
+      let mut data = m[48..96].to_vec();
+      data[20] = sn;
+      data
+    };
+    let invalidate = [0x09, 0x03, 0x00, 0x00];
+    let info_ts = [0x09, 0x01, 0x08, 0x00, 1, 0, 0, 0, 0, 0, 0, 0];
+
+    // Header, INFO_DST and INFO_TS, then DATAs in timestamped and invalidated
+    // sections
+    let message = Bytes::from(
+      [
+        &m[..48],
+        &data(91),
+        &data(92),
+        &invalidate[..],
+        &data(93),
+        &info_ts[..],
+        &data(94),
+      ]
+      .concat(),
+    );
+    // The next message starts with no timestamp
+    let next_message = Bytes::from([&m[..36], &data(95)].concat());
+
+    let (mut message_receiver, reader_guid) = shapes_demo_receiver();
+    message_receiver.handle_received_packet(&message);
+    message_receiver.handle_received_packet(&next_message);
+
+    let shapes_demo_timestamp =
+      Timestamp::read_from_buffer_with_ctx(Endianness::LittleEndian, &m[40..48]).unwrap();
+    let reader = &message_receiver.available_readers[&reader_guid.entity_id];
+    let source_timestamps: Vec<Option<Timestamp>> = (91..=95)
+      .map(|sn| {
+        reader
+          .history_cache_change_source_timestamp(SequenceNumber::new(sn))
+          .expect("sample not received")
+      })
+      .collect();
+    assert_eq!(
+      source_timestamps,
+      [
+        Some(shapes_demo_timestamp),
+        Some(shapes_demo_timestamp),
+        None,
+        Some(Timestamp::from_ticks(1 << 32)),
+        None
+      ]
+    );
+  }
+
+  // Two participants receive the same (e.g. multicast) SHAPES_DEMO_MESSAGE,
+  // with its INFO_DST replaced by `info_dst`. Returns whether each reader got
+  // the DATA, and the guid prefixes of the participants that sent an ACKNACK
+  // in response to the HEARTBEAT.
+  fn two_receivers_of_shapes_demo(info_dst: GuidPrefix) -> ([bool; 2], Vec<GuidPrefix>) {
+    let writer_socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    writer_socket
+      .set_read_timeout(Some(std::time::Duration::from_millis(200)))
+      .unwrap();
+    let writer_locator = Locator::from(writer_socket.local_addr().unwrap());
+    let reliable = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable {
+        max_blocking_time: crate::Duration::ZERO,
+      })
+      .build();
+
+    let mut message = SHAPES_DEMO_MESSAGE;
+    message[24..36].copy_from_slice(&info_dst.bytes);
+    let message = Bytes::copy_from_slice(&message);
+
+    let prefixes = [
+      GuidPrefix::new(&SHAPES_DEMO_TARGET_PREFIX),
+      GuidPrefix::new(&[
+        0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d, 0x31, 0xa2, 0, 0, 0, 0x99,
+      ]),
+    ];
+    let received = prefixes.map(|prefix| {
+      let (mut message_receiver, reader_guid) =
+        shapes_demo_receiver_of(prefix, &reliable, vec![writer_locator]);
+      message_receiver.handle_received_packet(&message);
+      !message_receiver
+        .get_reader_history_cache_start_and_end_seq_num(reader_guid.entity_id)
+        .is_empty()
+    });
+
+    let mut responders = Vec::new();
+    let mut buf = [0; 1500];
+    while let Ok(len) = writer_socket.recv(&mut buf) {
+      let reply = Message::read_from_buffer(&Bytes::copy_from_slice(&buf[..len])).unwrap();
+      assert!(reply
+        .submessages
+        .iter()
+        .any(|s| s.header.kind == SubmessageKind::ACKNACK));
+      responders.push(reply.header.guid_prefix);
+    }
+    responders.sort();
+    (received, responders)
+  }
+
+  #[test]
+  fn only_the_addressed_participant_responds() {
+    let addressed = GuidPrefix::new(&SHAPES_DEMO_TARGET_PREFIX);
+    assert_eq!(
+      two_receivers_of_shapes_demo(addressed),
+      ([true, false], vec![addressed])
+    );
+  }
+
+  #[test]
+  fn unknown_info_dst_addresses_everyone() {
+    let (received, responders) = two_receivers_of_shapes_demo(GuidPrefix::UNKNOWN);
+    assert_eq!(received, [true, true]);
+    assert_eq!(responders.len(), 2);
   }
 
   #[test]
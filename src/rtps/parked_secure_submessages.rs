@@ -0,0 +1,154 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{security::cryptographic::CryptoTransformKeyId, structure::clock};
+
+// Secure submessages that could not be decoded, because the key material of the
+// remote sender has not been received yet. They wait here until the crypto
+// tokens of the sender have been set, so that e.g. the first samples of a
+// reliable writer are not lost. The waiting is bounded by the number of
+// submessages per key id and in total, and by their age.
+pub(crate) struct ParkedSecureSubmessages<T> {
+  // In the order of parking
+  parked: VecDeque<Parked<T>>,
+  max_per_key_id: usize,
+  max_count: usize,
+  max_age: Duration,
+}
+
+pub(crate) struct Parked<T> {
+  pub key_id: CryptoTransformKeyId,
+  // When the submessage was first parked
+  pub parked_at: Instant,
+  pub item: T,
+}
+
+impl<T> ParkedSecureSubmessages<T> {
+  pub fn new(max_per_key_id: usize, max_count: usize, max_age: Duration) -> Self {
+    Self {
+      parked: VecDeque::new(),
+      max_per_key_id,
+      max_count,
+      max_age,
+    }
+  }
+
+  // Parks a submessage waiting for the key id. A submessage that is parked
+  // again after a retry keeps the time it was first parked, so that retries do
+  // not extend its wait.
+  pub fn park(&mut self, key_id: CryptoTransformKeyId, item: T, parked_at: Option<Instant>) {
+    self.drop_expired();
+    let parked_at = parked_at.unwrap_or_else(clock::monotonic_now);
+    if clock::monotonic_now().saturating_duration_since(parked_at) > self.max_age {
+      return info!("Dropping a secure submessage that waited too long for the key {key_id}");
+    }
+
+    let parked_for_key_id = self
+      .parked
+      .iter()
+      .filter(|parked| parked.key_id == key_id)
+      .count();
+    let oldest = if parked_for_key_id >= self.max_per_key_id {
+      self
+        .parked
+        .iter()
+        .position(|parked| parked.key_id == key_id)
+    } else if self.parked.len() >= self.max_count {
+      Some(0)
+    } else {
+      None
+    };
+    if let Some(oldest) = oldest.and_then(|oldest| self.parked.remove(oldest)) {
+      info!(
+        "Too many secure submessages waiting for keys. Dropping one waiting for the key {}",
+        oldest.key_id
+      );
+    }
+
+    self.parked.push_back(Parked {
+      key_id,
+      parked_at,
+      item,
+    });
+  }
+
+  // Takes the parked submessages for a retry, in the order they were parked
+  pub fn take_all(&mut self) -> Vec<Parked<T>> {
+    self.drop_expired();
+    self.parked.drain(..).collect()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.parked.is_empty()
+  }
+
+  fn drop_expired(&mut self) {
+    let now = clock::monotonic_now();
+    while let Some(oldest) = self.parked.front() {
+      if now.saturating_duration_since(oldest.parked_at) <= self.max_age {
+        break;
+      }
+      info!(
+        "Dropping a secure submessage that waited too long for the key {}",
+        oldest.key_id
+      );
+      self.parked.pop_front();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::structure::{clock::SimulatedClock, duration};
+
+  fn key_id(id: u8) -> CryptoTransformKeyId {
+    CryptoTransformKeyId::from([0, 0, 0, id])
+  }
+
+  fn items(parked: Vec<Parked<u32>>) -> Vec<u32> {
+    parked.into_iter().map(|parked| parked.item).collect()
+  }
+
+  #[test]
+  fn parked_submessages_are_bounded_per_key_id_and_in_total() {
+    let mut parked = ParkedSecureSubmessages::new(2, 3, Duration::from_secs(5));
+    for item in 0..3 {
+      parked.park(key_id(1), item, None);
+    }
+    parked.park(key_id(2), 10, None);
+    parked.park(key_id(3), 20, None);
+
+    // The oldest one of the key id goes first, then the oldest in total
+    assert_eq!(items(parked.take_all()), vec![2, 10, 20]);
+    assert!(parked.is_empty());
+  }
+
+  #[test]
+  fn parked_submessages_expire() {
+    let clock = SimulatedClock::start();
+    let mut parked = ParkedSecureSubmessages::new(10, 10, Duration::from_secs(5));
+    parked.park(key_id(1), 0, None);
+    clock.advance(duration::Duration::from_secs(3));
+    parked.park(key_id(1), 1, None);
+    clock.advance(duration::Duration::from_secs(3));
+
+    let retried = parked.take_all();
+    assert_eq!(
+      retried.iter().map(|parked| parked.item).collect::<Vec<_>>(),
+      vec![1]
+    );
+
+    // Parking again after a failed retry does not extend the wait
+    for retry in retried {
+      parked.park(retry.key_id, retry.item, Some(retry.parked_at));
+    }
+    clock.advance(duration::Duration::from_secs(3));
+    assert!(parked.take_all().is_empty());
+  }
+}
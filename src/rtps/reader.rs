@@ -1,5 +1,5 @@
 use std::{
-  collections::BTreeMap,
+  collections::{BTreeMap, BTreeSet},
   fmt, iter,
   rc::Rc,
   sync::{Arc, Mutex, MutexGuard},
@@ -16,6 +16,7 @@ use speedy::{Endianness, Writable};
 use crate::{
   dds::{
     ddsdata::DDSData,
+    key::KeyHash,
     qos::{policy, HasQoSPolicy, QosPolicies},
     statusevents::{
       CountWithChange, DataReaderStatus, DomainParticipantStatusEvent, StatusChannelSender,
@@ -44,11 +45,13 @@ use crate::{
     rtps_writer_proxy::RtpsWriterProxy, Message,
   },
   structure::{
+    clock,
     cache_change::{CacheChange, ChangeKind},
     dds_cache::TopicCache,
     duration::Duration,
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, GUID},
+    invariants,
     locator::Locator,
     sequence_number::{FragmentNumber, FragmentNumberSet, SequenceNumber, SequenceNumberSet},
     time::Timestamp,
@@ -60,6 +63,8 @@ use super::Submessage;
 use crate::security::{security_plugins::SecurityPluginsHandle, SecurityResult};
 #[cfg(not(feature = "security"))]
 use crate::no_security::SecurityPluginsHandle;
+#[cfg(feature = "fastdds_statistics")]
+use crate::dds::statistics::ProtocolStatistics;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum TimedEvent {
@@ -141,6 +146,7 @@ pub(crate) struct Reader {
 
   requested_deadline_missed_count: i32,
   offered_incompatible_qos_count: i32,
+  sample_lost_count: i32,
 
   pub(crate) timed_event_timer: Timer<TimedEvent>,
   pub(crate) data_reader_command_receiver: mio_channel::Receiver<ReaderCommand>,
@@ -151,6 +157,9 @@ pub(crate) struct Reader {
 
   #[allow(dead_code)] // to avoid warning if no security feature
   security_plugins: Option<SecurityPluginsHandle>,
+
+  #[cfg(feature = "fastdds_statistics")]
+  statistics: Option<Arc<ProtocolStatistics>>,
 }
 
 // If we are assembling a fragment, but it does not receive any updates
@@ -201,11 +210,12 @@ impl Reader {
       heartbeat_suppression_duration: StdDuration::new(0, 0),
       received_heartbeat_count: 0,
       fragment_assemblers: BTreeMap::new(),
-      last_fragment_garbage_collect: Timestamp::now(),
+      last_fragment_garbage_collect: clock::local_timestamp(),
       matched_writers: BTreeMap::new(),
       writer_match_count_total: 0,
       requested_deadline_missed_count: 0,
       offered_incompatible_qos_count: 0,
+      sample_lost_count: 0,
       timed_event_timer,
       data_reader_command_receiver: i.data_reader_command_receiver,
       data_reader_waker: i.data_reader_waker,
@@ -213,8 +223,17 @@ impl Reader {
       participant_status_sender,
 
       security_plugins: i.security_plugins,
+
+      #[cfg(feature = "fastdds_statistics")]
+      statistics: None,
     }
   }
+
+  #[cfg(feature = "fastdds_statistics")]
+  pub(crate) fn set_statistics(&mut self, statistics: Arc<ProtocolStatistics>) {
+    self.statistics = Some(statistics);
+  }
+
   // TODO: check if it's necessary to implement different handlers for discovery
   // and user messages
 
@@ -283,7 +302,7 @@ impl Reader {
     };
 
     let mut changes: Vec<DataReaderStatus> = vec![];
-    let now = Timestamp::now();
+    let now = clock::local_timestamp();
     for writer_proxy in self.matched_writers.values_mut() {
       if let Some(last_change) = writer_proxy.last_change_timestamp() {
         let since_last = now.duration_since(last_change);
@@ -370,6 +389,20 @@ impl Reader {
     cc.map(|cc| cc.data_value.clone())
   }
 
+  // TODO Used for test/debugging purposes
+  #[cfg(test)]
+  pub fn history_cache_change_source_timestamp(
+    &self,
+    sequence_number: SequenceNumber,
+  ) -> Option<Option<Timestamp>> {
+    let topic_cache = self.acquire_the_topic_cache_guard();
+    self
+      .seqnum_instant_map
+      .get(&sequence_number)
+      .and_then(|i| topic_cache.get_change(i))
+      .map(|cc| cc.write_options.source_timestamp())
+  }
+
   // TODO Used for test/debugging purposes
   #[cfg(test)]
   pub fn history_cache_sequence_start_and_end_numbers(&self) -> Vec<SequenceNumber> {
@@ -383,7 +416,7 @@ impl Reader {
   }
 
   // updates or adds a new writer proxy, doesn't touch changes
-  pub fn update_writer_proxy(&mut self, proxy: RtpsWriterProxy, offered_qos: &QosPolicies) {
+  pub fn update_writer_proxy(&mut self, mut proxy: RtpsWriterProxy, offered_qos: &QosPolicies) {
     if self.like_stateless {
       debug!(
         "Attempted to update writer proxy for stateless reader. Ignoring. topic={:?}",
@@ -395,9 +428,10 @@ impl Reader {
     debug!("update_writer_proxy topic={:?}", self.topic_name);
     let writer = proxy.remote_writer_guid;
 
-    match offered_qos.compliance_failure_wrt(&self.qos_policy) {
-      None => {
+    match self.qos_policy.matched_contract(offered_qos, false) {
+      Ok(contract) => {
         // success, update or insert
+        proxy.contract = Some(contract);
         let count_change = self.matched_writer_update(proxy);
         if count_change > 0 {
           self.writer_match_count_total += count_change;
@@ -417,7 +451,7 @@ impl Reader {
           );
         }
       }
-      Some(bad_policy_id) => {
+      Err(bad_policy_id) => {
         // no QoS match.
         self.offered_incompatible_qos_count += 1;
         self.send_status_change(DataReaderStatus::RequestedIncompatibleQos {
@@ -441,6 +475,7 @@ impl Reader {
         );
       }
     }
+    self.check_invariants();
   }
 
   // return value counts how many new proxies were added
@@ -470,6 +505,38 @@ impl Reader {
         writer: writer_guid,
       });
     }
+    self.check_invariants();
+  }
+
+  /// Checks that the proxies of the matched writers are consistent. Returns a
+  /// description of each violation found.
+  pub fn verify_invariants(&self) -> Vec<String> {
+    let mut violations = Vec::new();
+    if self.like_stateless && !self.matched_writers.is_empty() {
+      violations.push("A stateless reader has writer proxies".to_string());
+    }
+    for (guid, writer_proxy) in &self.matched_writers {
+      if writer_proxy.remote_writer_guid != *guid {
+        violations.push(format!(
+          "The proxy of writer {:?} is stored as {guid:?}",
+          writer_proxy.remote_writer_guid
+        ));
+      }
+      if writer_proxy.contract.is_none() {
+        violations.push(format!("Matched writer {guid:?} has no QoS contract"));
+      }
+      violations.extend(
+        writer_proxy
+          .verify_invariants()
+          .into_iter()
+          .map(|violation| format!("Writer {guid:?}: {violation}")),
+      );
+    }
+    violations
+  }
+
+  fn check_invariants(&self) {
+    invariants::check_after_mutation("Reader", || self.verify_invariants());
   }
 
   // Entire remote participant was lost.
@@ -531,7 +598,7 @@ impl Reader {
     mr_state: &MessageReceiverState,
   ) {
     // trace!("handle_data_msg entry");
-    let receive_timestamp = Timestamp::now();
+    let receive_timestamp = clock::local_timestamp();
 
     // parse write_options out of the message
     let mut write_options_b = WriteOptionsBuilder::new();
@@ -556,6 +623,14 @@ impl Reader {
     let writer_guid = GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, data.writer_id);
     let writer_seq_num = data.writer_sn; // for borrow checker
 
+    if self.lifespan_exceeded(mr_state.source_timestamp) {
+      info!(
+        "Data {:?} from {:?} lifespan exceeded. topic={:?}",
+        writer_seq_num, writer_guid, self.topic_name
+      );
+      return;
+    }
+
     match self.data_to_dds_data(data, data_flags) {
       Ok(dds_data) => self.process_received_data(
         dds_data,
@@ -576,23 +651,31 @@ impl Reader {
   ) {
     let writer_guid = GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, datafrag.writer_id);
     let seq_num = datafrag.writer_sn;
-    let receive_timestamp = Timestamp::now();
+    let receive_timestamp = clock::local_timestamp();
     //trace!("DATAFRAG received topic={:?}", self.topic_name);
 
+    #[cfg(test)]
+    if crate::test::fragment_loss::lose_fragment(&self.topic_name) {
+      return;
+    }
+
+    if datafrag_flags.contains(DATAFRAG_Flags::NonStandardPayload) {
+      info!(
+        "DataFrag {:?} from {:?} has a non-standard payload, which we cannot assemble. topic={:?}",
+        seq_num, writer_guid, self.topic_name
+      );
+      return;
+    }
+
     // check if this submessage is expired already
     // TODO: Maybe this check is in the wrong place altogether? It should be
     // done when Datareader fetches data for the application.
-    if let (Some(source_timestamp), Some(lifespan)) =
-      (mr_state.source_timestamp, self.qos().lifespan)
-    {
-      let elapsed = receive_timestamp.duration_since(source_timestamp);
-      if lifespan.duration < elapsed {
-        info!(
-          "DataFrag {:?} from {:?} lifespan exceeded. duration={:?} elapsed={:?}",
-          seq_num, writer_guid, lifespan.duration, elapsed
-        );
-        return;
-      }
+    if self.lifespan_exceeded(mr_state.source_timestamp) {
+      info!(
+        "DataFrag {:?} from {:?} lifespan exceeded. topic={:?}",
+        seq_num, writer_guid, self.topic_name
+      );
+      return;
     }
 
     // parse write_options out of the message
@@ -627,6 +710,18 @@ impl Reader {
 
     // ... and continue processing, if data was completed.
     if let Some(dds_data) = completed_dds_data {
+      // A fragmented key tells in inline QoS what happened to the instance
+      let dds_data = match dds_data {
+        DDSData::DisposeByKey { key, .. } => {
+          let change_kind =
+            Self::deduce_change_kind(&datafrag.inline_qos, false, representation_identifier);
+          match Self::inline_qos_key_hash(&datafrag.inline_qos) {
+            Some(key_hash) => DDSData::new_disposed_by_key_and_hash(change_kind, key, key_hash),
+            None => DDSData::new_disposed_by_key(change_kind, key),
+          }
+        }
+        data => data,
+      };
       // Source timestamp (if any) will be the timestamp of the last fragment (that
       // completes the sample).
       self.process_received_data(
@@ -641,6 +736,31 @@ impl Reader {
     }
   }
 
+  // The source timestamp is from the wall clock of the Writer, so it is
+  // compared to our wall clock, allowing for the clock skew tolerance. Local
+  // timestamps would not do, as they do not follow steps of the wall clock.
+  fn lifespan_exceeded(&self, source_timestamp: Option<Timestamp>) -> bool {
+    let (Some(source_timestamp), Some(lifespan)) = (source_timestamp, self.qos().lifespan) else {
+      return false;
+    };
+    if source_timestamp == Timestamp::INVALID || lifespan.duration == Duration::INFINITE {
+      return false;
+    }
+    let max_skew = self
+      .qos()
+      .clock_skew_tolerance()
+      .map_or(Duration::ZERO, |tolerance| tolerance.max_skew);
+    let elapsed = clock::wall_now().duration_since(source_timestamp);
+    let exceeded = lifespan.duration.saturating_add(max_skew) < elapsed;
+    if exceeded {
+      debug!(
+        "Lifespan exceeded: duration={:?} max_skew={:?} elapsed={:?}",
+        lifespan.duration, max_skew, elapsed
+      );
+    }
+    exceeded
+  }
+
   fn fragment_assembler_mutable(
     &mut self,
     writer_guid: GUID,
@@ -657,7 +777,7 @@ impl Reader {
     // fragment assemblers and discard those assembly buffers whose
     // creation / modification timestamps look like it is no longer receiving
     // data and can therefore be discarded.
-    let now = Timestamp::now();
+    let now = clock::local_timestamp();
     if now - self.last_fragment_garbage_collect > MIN_FRAGMENT_GC_INTERVAL {
       self.last_fragment_garbage_collect = now;
 
@@ -713,6 +833,8 @@ impl Reader {
     );
     if !self.like_stateless {
       let my_entity_id = self.my_guid.entity_id; // to please borrow checker
+      let reliability = self.reliability;
+      let filter_stale = self.qos_policy.stale_sample_filter().is_some();
       if let Some(writer_proxy) = self.matched_writer_mut(writer_guid) {
         if writer_proxy.should_ignore_change(writer_sn) {
           // change already present
@@ -726,8 +848,27 @@ impl Reader {
             return;
           }
         }
+        // A BestEffort Reader never gets the changes it skips over
+        let lost = if reliability == policy::Reliability::BestEffort {
+          writer_proxy.changes_skipped_before(writer_sn)
+        } else {
+          0
+        };
+        // A Reliable Reader already ignores changes declared lost or irrelevant
+        // above, but a BestEffort Reader only declares them lost when skipping over.
+        let stale = filter_stale
+          && reliability == policy::Reliability::BestEffort
+          && writer_proxy.is_skipped_change(writer_sn);
         // Add the change and get the instant
         writer_proxy.received_changes_add(writer_sn, receive_timestamp);
+        self.report_samples_lost(lost);
+        if stale {
+          debug!(
+            "Dropping stale change seq={:?} from {:?}, it was already reported lost. topic={:?}",
+            writer_sn, writer_guid, self.topic_name
+          );
+          return;
+        }
       } else {
         // no writer proxy found
         debug!(
@@ -745,6 +886,18 @@ impl Reader {
       // stateless reader: nothing to do before making cache change
     }
 
+    #[cfg(feature = "fastdds_statistics")]
+    if let Some(statistics) = &self.statistics {
+      statistics.data_received(
+        self.my_guid,
+        writer_guid,
+        dds_data.payload_size(),
+        write_options.source_timestamp(),
+        // Latency is measured against the source timestamp, so use the wall clock
+        clock::wall_now(),
+      );
+    }
+
     self.make_cache_change(
       dds_data,
       receive_timestamp,
@@ -758,6 +911,7 @@ impl Reader {
     self.seqnum_instant_map.insert(writer_sn, receive_timestamp);
 
     self.notify_cache_change();
+    self.check_invariants();
   }
 
   fn data_to_dds_data(
@@ -767,6 +921,12 @@ impl Reader {
   ) -> Result<DDSData, String> {
     let representation_identifier = DATA_Flags::cdr_representation_identifier(data_flags);
 
+    // The payload is not a SerializedPayload, but e.g. vendor-specific or
+    // protected by DDS Security and not decoded. We do not know how to parse it.
+    if data.serialized_payload.is_some() && data_flags.contains(DATA_Flags::NonStandardPayload) {
+      return Err("DATA has a non-standard payload".to_string());
+    }
+
     match (
       data.serialized_payload,
       data_flags.contains(DATA_Flags::Data),
@@ -780,23 +940,22 @@ impl Reader {
       }
 
       (Some(serialized_payload), false, true) => {
-        // key
-        Ok(DDSData::new_disposed_by_key(
-          Self::deduce_change_kind(&data.inline_qos, false, representation_identifier),
-          SerializedPayload::from_bytes(&serialized_payload).map_err(|e| format!("{e:?}"))?,
-        ))
+        // key, possibly with its key hash
+        let change_kind =
+          Self::deduce_change_kind(&data.inline_qos, false, representation_identifier);
+        let key =
+          SerializedPayload::from_bytes(&serialized_payload).map_err(|e| format!("{e:?}"))?;
+        Ok(match Self::inline_qos_key_hash(&data.inline_qos) {
+          Some(key_hash) => DDSData::new_disposed_by_key_and_hash(change_kind, key, key_hash),
+          None => DDSData::new_disposed_by_key(change_kind, key),
+        })
       }
 
       (None, false, false) => {
         // no data, no key. Maybe there is inline QoS?
         // At least we should find key hash, or we do not know WTF the writer is talking
         // about
-        let key_hash = if let Some(h) = data.inline_qos.as_ref().and_then(|inline_qos_parameters| {
-          InlineQos::key_hash(inline_qos_parameters).unwrap_or_else(|e| {
-            error!("Deserializing key_hash: {:?}", &e);
-            None
-          })
-        }) {
+        let key_hash = if let Some(h) = Self::inline_qos_key_hash(&data.inline_qos) {
           Ok(h)
         } else {
           info!("Received DATA that has no payload and no key_hash inline QoS - discarding");
@@ -851,22 +1010,54 @@ impl Reader {
         if x.is_some() {
           panic!("with_mutable_writer_proxy: Worker inserted writer proxy behind my back!")
         }
+        self.check_invariants();
         Some(res)
       }
     }
   }
 
+  fn report_samples_lost(&mut self, lost: i64) {
+    if lost <= 0 {
+      return;
+    }
+    let lost = i32::try_from(lost).unwrap_or(i32::MAX);
+    self.sample_lost_count = self.sample_lost_count.saturating_add(lost);
+    self.send_status_change(DataReaderStatus::SampleLost {
+      count: CountWithChange::new(self.sample_lost_count, lost),
+    });
+    #[cfg(feature = "fastdds_statistics")]
+    if let Some(statistics) = &self.statistics {
+      statistics.samples_lost(self.my_guid, lost as u64);
+    }
+  }
+
   // Returns if responding with ACKNACK?
   // TODO: Return value seems to go unused in callers.
   // ...except in test cases, but not sure if this is strictly necessary to have.
   pub fn handle_heartbeat_msg(
     &mut self,
     heartbeat: &Heartbeat,
-    final_flag_set: bool,
+    heartbeat_flags: BitFlags<HEARTBEAT_Flags>,
     mr_state: &MessageReceiverState,
   ) -> bool {
     let writer_guid =
       GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, heartbeat.writer_id);
+    let final_flag_set = heartbeat_flags.contains(HEARTBEAT_Flags::Final);
+
+    // RTPS spec v2.5 Section 8.3.7.5: The Liveliness flag means that the Writer
+    // manually asserts its liveliness with this HEARTBEAT. This concerns
+    // BestEffort Readers as well, so record it before anything else.
+    if heartbeat_flags.contains(HEARTBEAT_Flags::Liveliness) {
+      if let Some(writer_proxy) = self.matched_writers.get_mut(&writer_guid) {
+        trace!(
+          "Writer {:?} asserted liveliness. Previous assertion at {:?}. topic={:?}",
+          writer_guid,
+          writer_proxy.last_liveliness_assertion,
+          self.topic_name
+        );
+        writer_proxy.last_liveliness_assertion = Some(clock::local_timestamp());
+      }
+    }
 
     if self.reliability == policy::Reliability::BestEffort || self.like_stateless {
       debug!(
@@ -912,8 +1103,15 @@ impl Reader {
           // This heartbeat was already seen an processed.
           return false;
         }
+        let first_heartbeat = writer_proxy.received_heartbeat_count == 0;
         writer_proxy.received_heartbeat_count = heartbeat.count;
 
+        // Changes below first_sn that we are still missing are lost. The first
+        // HEARTBEAT only tells where a late-joining Reader starts from.
+        if !first_heartbeat {
+          this.report_samples_lost(writer_proxy.missing_changes_before(heartbeat.first_sn));
+        }
+
         // remove changes until first_sn.
         writer_proxy.irrelevant_changes_up_to(heartbeat.first_sn);
 
@@ -1101,7 +1299,8 @@ impl Reader {
         );
         return;
       }
-      // TODO: check that maximum(gap_list) - minimum(gap_list) < 256 ?
+      // maximum(gap_list) - minimum(gap_list) < 256 is already checked when
+      // decoding the SequenceNumberSet.
 
       // Irrelevant sequence numbers communicated in the Gap message are
       // composed of two groups:
@@ -1118,6 +1317,7 @@ impl Reader {
       }
       all_ackable_before = writer_proxy.all_ackable_before();
     }
+    self.check_invariants();
 
     // Get the topic cache and mark progress
     let marker_moved = self
@@ -1142,15 +1342,74 @@ impl Reader {
     // as "lost" but "filtered".
   }
 
+  // The Writer announces that fragments up to last_fragment_num of writer_sn
+  // are available. Request those we are missing with a NackFrag.
   pub fn handle_heartbeatfrag_msg(
     &mut self,
     heartbeatfrag: &HeartbeatFrag,
-    _mr_state: &MessageReceiverState,
+    mr_state: &MessageReceiverState,
   ) {
-    info!(
-      "HeartbeatFrag handling not implemented. topic={:?}   {:?}",
-      self.topic_name, heartbeatfrag
-    );
+    let writer_guid =
+      GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, heartbeatfrag.writer_id);
+
+    if self.reliability == policy::Reliability::BestEffort || self.like_stateless {
+      debug!(
+        "HEARTBEAT_FRAG from {:?}, but this Reader is BestEffort or stateless. Ignoring. \
+         topic={:?}",
+        writer_guid, self.topic_name
+      );
+      return;
+    }
+
+    if !self.matched_writers.contains_key(&writer_guid) {
+      debug!(
+        "HEARTBEAT_FRAG from {:?}, but no writer proxy available. topic={:?}",
+        writer_guid, self.topic_name
+      );
+      return;
+    }
+
+    let sn = heartbeatfrag.writer_sn;
+    // A sample not yet partially received has no assembly buffer, and its
+    // fragments are requested by AckNack as a whole.
+    if !self.is_frag_partially_received(writer_guid, sn) {
+      return;
+    }
+    let missing_frags: BTreeSet<FragmentNumber> = self
+      .missing_frags_for(writer_guid, sn)
+      .filter(|f| *f <= heartbeatfrag.last_fragment_num)
+      .collect();
+
+    self.with_mutable_writer_proxy(writer_guid, |this, writer_proxy| {
+      if heartbeatfrag.count <= writer_proxy.received_heartbeatfrag_count {
+        return; // already seen
+      }
+      writer_proxy.received_heartbeatfrag_count = heartbeatfrag.count;
+
+      let Some(&first) = missing_frags.iter().next() else {
+        return; // Nothing available is missing
+      };
+      let reply_locators = match mr_state.unicast_reply_locator_list.as_slice() {
+        [] | [Locator::Invalid] => writer_proxy.unicast_locator_list.clone(),
+        others => others.to_vec(),
+      };
+      let nackfrag = NackFrag {
+        reader_id: this.entity_id(),
+        writer_id: heartbeatfrag.writer_id,
+        writer_sn: sn,
+        fragment_number_state: FragmentNumberSet::from_base_and_set(first, &missing_frags),
+        count: writer_proxy.next_ack_nack_sequence_number(),
+      };
+      this.send_nackfrags_to(
+        BitFlags::<NACKFRAG_Flags>::from_flag(NACKFRAG_Flags::Endianness),
+        vec![nackfrag],
+        InfoDestination {
+          guid_prefix: mr_state.source_guid_prefix,
+        },
+        &reply_locators,
+        writer_guid,
+      );
+    });
   }
 
   // This is used to determine exact change kind in case we do not get a data
@@ -1181,6 +1440,15 @@ impl Reader {
     }
   }
 
+  fn inline_qos_key_hash(inline_qos: &Option<ParameterList>) -> Option<KeyHash> {
+    inline_qos.as_ref().and_then(|inline_qos_parameters| {
+      InlineQos::key_hash(inline_qos_parameters).unwrap_or_else(|e| {
+        error!("Deserializing key_hash: {:?}", &e);
+        None
+      })
+    })
+  }
+
   // Convert DATA submessage into a CacheChange and update history cache
   fn make_cache_change(
     &mut self,
@@ -1286,7 +1554,7 @@ impl Reader {
       // Encode submessages
       SecurityResult::<Vec<Vec<Submessage>>>::from_iter(submessages.iter().map(|submessage| {
         security_plugins_handle
-          .get_plugins()
+          .read_plugins()
           .encode_datareader_submessage(submessage.clone(), &source_guid, &[destination_guid])
           // Convert each encoding output to a Vec of 1 or 3 submessages
           .map(Vec::from)
@@ -1302,7 +1570,7 @@ impl Reader {
         let source_guid_prefix = source_guid.prefix;
         let destination_guid_prefix = destination_guid.prefix;
         // Encode message
-        security_plugins_handle.get_plugins().encode_message(
+        security_plugins_handle.read_plugins().encode_message(
           message,
           &source_guid_prefix,
           &[destination_guid_prefix],
@@ -1335,6 +1603,11 @@ impl Reader {
 
     message.add_submessage(acknack.create_submessage(flags));
 
+    #[cfg(feature = "fastdds_statistics")]
+    if let Some(statistics) = &self.statistics {
+      statistics.acknack_sent(self.my_guid);
+    }
+
     self.encode_and_send(message, destination_guid, dst_locator_list);
   }
 
@@ -1454,7 +1727,11 @@ mod tests {
   use std::sync::RwLock;
 
   use crate::{
-    dds::{qos::policy::Reliability, statusevents::sync_status_channel, typedesc::TypeDesc},
+    dds::{
+      qos::{policy::Reliability, PolicyContract},
+      statusevents::sync_status_channel,
+      typedesc::TypeDesc,
+    },
     structure::{dds_cache::DDSCache, guid::EntityKind},
     QosPolicyBuilder,
   };
@@ -1730,7 +2007,7 @@ mod tests {
       last_sn: SequenceNumber::new(0),
       count: 1,
     };
-    assert!(!reader.handle_heartbeat_msg(&hb_new, true, &mr_state)); // should be false, no ack
+    assert!(!reader.handle_heartbeat_msg(&hb_new, HEARTBEAT_Flags::Final.into(), &mr_state)); // should be false, no ack
 
     // 4. Send the first proper heartbeat, reader should respond with acknack
     let hb_one = Heartbeat {
@@ -1740,12 +2017,12 @@ mod tests {
       last_sn: SequenceNumber::new(1),
       count: 2,
     };
-    assert!(reader.handle_heartbeat_msg(&hb_one, false, &mr_state)); // Should send an ack_nack
+    assert!(reader.handle_heartbeat_msg(&hb_one, BitFlags::empty(), &mr_state)); // Should send an ack_nack
 
     // 5. Send a duplicate of the first heartbeat, reader should not respond with
     // acknack
     let hb_one2 = hb_one.clone();
-    assert!(!reader.handle_heartbeat_msg(&hb_one2, false, &mr_state)); // No acknack
+    assert!(!reader.handle_heartbeat_msg(&hb_one2, BitFlags::empty(), &mr_state)); // No acknack
 
     // 6. Send a second proper heartbeat, reader should respond with acknack
     let hb_2 = Heartbeat {
@@ -1755,7 +2032,7 @@ mod tests {
       last_sn: SequenceNumber::new(3),  // writer has written 3 samples
       count: 3,
     };
-    assert!(reader.handle_heartbeat_msg(&hb_2, false, &mr_state)); // Should send an ack_nack
+    assert!(reader.handle_heartbeat_msg(&hb_2, BitFlags::empty(), &mr_state)); // Should send an ack_nack
 
     // 7. Count of acknack sent should be 2
     // The count is verified from the writer proxy
@@ -1972,4 +2249,399 @@ mod tests {
     // we attempted to add
     assert!(reader.matched_writer(writer_guid).is_none());
   }
+
+  // Creates a Reader with a matched writer. Returns the reader, the writer guid
+  // and the MessageReceiverState for messages from the writer.
+  fn reader_with_matched_writer(qos_policy: &QosPolicies) -> (Reader, GUID, MessageReceiverState) {
+    reader_with_writer_offering(qos_policy, qos_policy)
+  }
+
+  // As above, with the writer offering the given QoS
+  fn reader_with_writer_offering(
+    qos_policy: &QosPolicies,
+    offered_qos: &QosPolicies,
+  ) -> (Reader, GUID, MessageReceiverState) {
+    let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
+    let topic_name = "test_name";
+    let topic_cache_handle = dds_cache.write().unwrap().add_new_topic(
+      topic_name.to_string(),
+      TypeDesc::new("test_type".to_string()),
+      qos_policy,
+    );
+
+    let (notification_sender, _notification_receiver) = mio_channel::sync_channel::<()>(100);
+    let (_notification_event_source, notification_event_sender) =
+      mio_source::make_poll_channel().unwrap();
+    let (status_sender, _status_receiver) = sync_status_channel::<DataReaderStatus>(4).unwrap();
+    let (participant_status_sender, _participant_status_receiver) =
+      sync_status_channel(16).unwrap();
+    let (_reader_command_sender, reader_command_receiver) =
+      mio_channel::sync_channel::<ReaderCommand>(10);
+
+    let reader_ing = ReaderIngredients {
+      guid: GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED),
+      notification_sender,
+      status_sender,
+      topic_name: topic_name.to_string(),
+      topic_cache_handle,
+      like_stateless: false,
+      qos_policy: qos_policy.clone(),
+      data_reader_command_receiver: reader_command_receiver,
+      data_reader_waker: Arc::new(Mutex::new(None)),
+      poll_event_sender: notification_event_sender,
+      security_plugins: None,
+    };
+    let mut reader = Reader::new(
+      reader_ing,
+      Rc::new(UDPSender::new(0).unwrap()),
+      mio_extras::timer::Builder::default().build(),
+      participant_status_sender,
+    );
+
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let mr_state = MessageReceiverState {
+      source_guid_prefix: writer_guid.prefix,
+      ..Default::default()
+    };
+    reader.matched_writer_add(
+      writer_guid,
+      EntityId::UNKNOWN,
+      mr_state.unicast_reply_locator_list.clone(),
+      mr_state.multicast_reply_locator_list.clone(),
+      offered_qos,
+    );
+    (reader, writer_guid, mr_state)
+  }
+
+  fn data_with_sn(reader: &Reader, writer_guid: GUID, sn: i64) -> Data {
+    Data {
+      reader_id: reader.entity_id(),
+      writer_id: writer_guid.entity_id,
+      writer_sn: SequenceNumber::new(sn),
+      ..Data::default()
+    }
+  }
+
+  const STALE_SAMPLE_FILTER: policy::StaleSampleFilter = policy::StaleSampleFilter {
+    max_lag: Duration::from_secs(10),
+  };
+
+  #[test]
+  fn reliable_reader_acks_but_drops_retransmission_of_lost_change() {
+    let qos = QosPolicyBuilder::new()
+      .reliable(Duration::from_millis(100))
+      .stale_sample_filter(STALE_SAMPLE_FILTER)
+      .build();
+    let (mut reader, writer_guid, mr_state) = reader_with_matched_writer(&qos);
+    let data_flags = BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Data);
+    let heartbeat = |first_sn, last_sn, count| Heartbeat {
+      reader_id: reader.entity_id(),
+      writer_id: writer_guid.entity_id,
+      first_sn: SequenceNumber::new(first_sn),
+      last_sn: SequenceNumber::new(last_sn),
+      count,
+    };
+    let (hb_1_3, hb_4_4_a, hb_4_4_b) = (heartbeat(1, 3, 1), heartbeat(4, 4, 2), heartbeat(4, 4, 3));
+
+    reader.handle_data_msg(data_with_sn(&reader, writer_guid, 1), data_flags, &mr_state);
+    assert!(reader.handle_heartbeat_msg(&hb_1_3, BitFlags::empty(), &mr_state));
+    // The writer no longer has 2 and 3, so they are lost
+    assert!(reader.handle_heartbeat_msg(&hb_4_4_a, BitFlags::empty(), &mr_state));
+    assert_eq!(reader.sample_lost_count, 2);
+
+    // A delayed retransmission of 2 arrives
+    reader.handle_data_msg(data_with_sn(&reader, writer_guid, 2), data_flags, &mr_state);
+    assert!(!reader
+      .seqnum_instant_map
+      .contains_key(&SequenceNumber::new(2)));
+
+    // ... and it is covered by the next ACKNACK
+    assert!(reader.handle_heartbeat_msg(&hb_4_4_b, BitFlags::empty(), &mr_state));
+    let writer_proxy = reader.matched_writer(writer_guid).unwrap();
+    assert_eq!(writer_proxy.sent_ack_nack_count, 3);
+    assert_eq!(writer_proxy.all_ackable_before(), SequenceNumber::new(4));
+  }
+
+  #[test]
+  fn final_flag_suppresses_acknack_only_when_nothing_is_missing() {
+    let qos = QosPolicyBuilder::new()
+      .reliable(Duration::from_millis(100))
+      .build();
+    let (mut reader, writer_guid, mr_state) = reader_with_matched_writer(&qos);
+    let data_flags = BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Data);
+    let reader_id = reader.entity_id();
+    let heartbeat = |last_sn, count| Heartbeat {
+      reader_id,
+      writer_id: writer_guid.entity_id,
+      first_sn: SequenceNumber::new(1),
+      last_sn: SequenceNumber::new(last_sn),
+      count,
+    };
+    let final_flag = BitFlags::<HEARTBEAT_Flags>::from_flag(HEARTBEAT_Flags::Final);
+    let no_flags = BitFlags::<HEARTBEAT_Flags>::empty();
+    let acknacks_sent = |reader: &Reader| {
+      reader
+        .matched_writer(writer_guid)
+        .unwrap()
+        .sent_ack_nack_count
+    };
+
+    // Nothing available, nothing missing
+    assert!(!reader.handle_heartbeat_msg(&heartbeat(0, 1), final_flag, &mr_state));
+    assert_eq!(acknacks_sent(&reader), 0);
+    assert!(reader.handle_heartbeat_msg(&heartbeat(0, 2), no_flags, &mr_state));
+    assert_eq!(acknacks_sent(&reader), 1);
+
+    // 1 is missing, so the Reader requests it regardless of the final flag
+    assert!(reader.handle_heartbeat_msg(&heartbeat(1, 3), final_flag, &mr_state));
+    assert_eq!(acknacks_sent(&reader), 2);
+
+    // Everything received
+    reader.handle_data_msg(data_with_sn(&reader, writer_guid, 1), data_flags, &mr_state);
+    assert!(!reader.handle_heartbeat_msg(&heartbeat(1, 4), final_flag, &mr_state));
+    assert_eq!(acknacks_sent(&reader), 2);
+    assert!(reader.handle_heartbeat_msg(&heartbeat(1, 5), no_flags, &mr_state));
+    assert_eq!(acknacks_sent(&reader), 3);
+  }
+
+  #[test]
+  fn liveliness_flag_asserts_writer_liveliness() {
+    // BestEffort Readers do not otherwise react to HEARTBEATs
+    let (mut reader, writer_guid, mr_state) = reader_with_matched_writer(&QosPolicies::qos_none());
+    let reader_id = reader.entity_id();
+    let heartbeat = |count| Heartbeat {
+      reader_id,
+      writer_id: writer_guid.entity_id,
+      first_sn: SequenceNumber::new(1),
+      last_sn: SequenceNumber::new(0),
+      count,
+    };
+    let last_assertion = |reader: &Reader| {
+      reader
+        .matched_writer(writer_guid)
+        .unwrap()
+        .last_liveliness_assertion
+    };
+
+    reader.handle_heartbeat_msg(&heartbeat(1), BitFlags::empty(), &mr_state);
+    assert_eq!(last_assertion(&reader), None);
+
+    reader.handle_heartbeat_msg(
+      &heartbeat(2),
+      HEARTBEAT_Flags::Final | HEARTBEAT_Flags::Liveliness,
+      &mr_state,
+    );
+    let asserted = last_assertion(&reader).expect("Liveliness assertion not recorded");
+
+    reader.handle_heartbeat_msg(&heartbeat(3), BitFlags::empty(), &mr_state);
+    assert_eq!(last_assertion(&reader), Some(asserted));
+  }
+
+  #[test]
+  fn reader_does_not_parse_non_standard_payload() {
+    let (mut reader, writer_guid, mr_state) = reader_with_matched_writer(&QosPolicies::qos_none());
+    reader.handle_data_msg(
+      data_with_sn(&reader, writer_guid, 1),
+      DATA_Flags::Data | DATA_Flags::NonStandardPayload,
+      &mr_state,
+    );
+    assert!(!reader
+      .seqnum_instant_map
+      .contains_key(&SequenceNumber::new(1)));
+
+    // As after decoding, which clears the flag
+    reader.handle_data_msg(
+      data_with_sn(&reader, writer_guid, 2),
+      DATA_Flags::Data.into(),
+      &mr_state,
+    );
+    assert!(reader
+      .seqnum_instant_map
+      .contains_key(&SequenceNumber::new(2)));
+  }
+
+  #[test]
+  fn reader_keeps_instance_state_and_key_hash_from_inline_qos() {
+    use crate::{
+      dds::with_key::datawriter::WriteOptions,
+      rtps::{message::MessageBuilder, SubmessageBody},
+    };
+
+    let (mut reader, writer_guid, mr_state) = reader_with_matched_writer(&QosPolicies::qos_none());
+    let key = SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![7, 0, 0, 0]);
+    let key_hash = KeyHash::from_pl_cdr_bytes((1..=16).collect()).unwrap();
+    let sent = [
+      DDSData::new_disposed_by_key_and_hash(ChangeKind::NotAliveDisposed, key.clone(), key_hash),
+      DDSData::new_disposed_by_key_and_hash(ChangeKind::NotAliveUnregistered, key, key_hash),
+      // No serialized key at all: the key hash identifies the instance
+      DDSData::new_disposed_by_key_hash(ChangeKind::NotAliveDisposed, key_hash),
+    ];
+
+    for (sn, dds_data) in (1..).zip(sent.iter()) {
+      let cache_change = CacheChange::new(
+        writer_guid,
+        SequenceNumber::new(sn),
+        WriteOptions::default(),
+        dds_data.clone(),
+      );
+      let message = MessageBuilder::new()
+        .data_msg(
+          &cache_change,
+          reader.entity_id(),
+          writer_guid,
+          Endianness::LittleEndian,
+          None,
+        )
+        .add_header_and_build(writer_guid.prefix);
+      match &message.submessages[0].body {
+        SubmessageBody::Writer(WriterSubmessage::Data(data, flags)) => {
+          reader.handle_data_msg(data.clone(), *flags, &mr_state);
+        }
+        other => panic!("Expected DATA, got {other:?}"),
+      }
+      assert_eq!(
+        reader
+          .history_cache_change_data(SequenceNumber::new(sn))
+          .as_ref(),
+        Some(dds_data)
+      );
+    }
+  }
+
+  #[test]
+  fn best_effort_reader_drops_late_change_already_reported_lost() {
+    let data_flags = BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Data);
+    let receive_out_of_order = |qos| {
+      let (mut reader, writer_guid, mr_state) = reader_with_matched_writer(&qos);
+      for sn in [1, 3, 2] {
+        reader.handle_data_msg(
+          data_with_sn(&reader, writer_guid, sn),
+          data_flags,
+          &mr_state,
+        );
+      }
+      assert_eq!(reader.sample_lost_count, 1);
+      reader
+        .seqnum_instant_map
+        .contains_key(&SequenceNumber::new(2))
+    };
+
+    let filtered = QosPolicyBuilder::new()
+      .best_effort()
+      .stale_sample_filter(STALE_SAMPLE_FILTER)
+      .build();
+    assert!(!receive_out_of_order(filtered));
+    // Without the filter the late change is delivered
+    assert!(receive_out_of_order(
+      QosPolicyBuilder::new().best_effort().build()
+    ));
+  }
+
+  #[test]
+  fn reader_timing_survives_wall_clock_steps() {
+    let clock = clock::SimulatedClock::start();
+    let qos = QosPolicyBuilder::new()
+      .best_effort()
+      .deadline(policy::Deadline(Duration::from_secs(1)))
+      .lifespan(policy::Lifespan {
+        duration: Duration::from_secs(60),
+      })
+      .clock_skew_tolerance(policy::ClockSkewTolerance {
+        max_skew: Duration::from_secs(15 * 60),
+        rebaseline_after_clock_jump: false,
+      })
+      .build();
+    let (mut reader, writer_guid, mut mr_state) = reader_with_matched_writer(&qos);
+    let data_flags = BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Data);
+    let ten_minutes = Duration::from_secs(10 * 60);
+
+    let mut sn = 0;
+    for step in [ten_minutes, Duration::from_secs(-10 * 60)] {
+      // Stamped by the Writer before our clock is stepped
+      mr_state.source_timestamp = Some(clock::wall_now());
+      clock.advance(Duration::from_millis(100));
+      clock.step_wall_clock(step);
+
+      sn += 1;
+      reader.handle_data_msg(
+        data_with_sn(&reader, writer_guid, sn),
+        data_flags,
+        &mr_state,
+      );
+      assert!(reader
+        .seqnum_instant_map
+        .contains_key(&SequenceNumber::new(sn)));
+
+      clock.advance(Duration::from_millis(100));
+      assert!(reader
+        .calculate_if_requested_deadline_is_missed()
+        .is_empty());
+    }
+
+    // Samples older than the Lifespan and the tolerance still expire
+    mr_state.source_timestamp = Some(clock::wall_now() - Duration::from_secs(20 * 60));
+    clock.advance(Duration::from_millis(100));
+    reader.handle_data_msg(data_with_sn(&reader, writer_guid, 3), data_flags, &mr_state);
+    assert!(!reader
+      .seqnum_instant_map
+      .contains_key(&SequenceNumber::new(3)));
+
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(reader.calculate_if_requested_deadline_is_missed().len(), 1);
+  }
+
+  #[test]
+  fn matched_contract_follows_runtime_behavior() {
+    let clock = clock::SimulatedClock::start();
+    let requested = QosPolicyBuilder::new()
+      .best_effort()
+      .history(policy::History::KeepLast { depth: 2 })
+      .deadline(policy::Deadline(Duration::from_secs(1)))
+      .build();
+    let offered = QosPolicyBuilder::new()
+      .reliable(Duration::from_millis(100))
+      .history(policy::History::KeepLast { depth: 5 })
+      .deadline(policy::Deadline(Duration::from_millis(500)))
+      .build();
+    let (mut reader, writer_guid, mr_state) = reader_with_writer_offering(&requested, &offered);
+
+    let writer_proxy = reader.matched_writer(writer_guid).unwrap();
+    let contract = writer_proxy.contract.clone().unwrap();
+    assert!(format!("{writer_proxy:?}").contains("MatchedContract"));
+    assert_eq!(
+      contract.history,
+      PolicyContract {
+        local: Some(policy::History::KeepLast { depth: 2 }),
+        remote: Some(policy::History::KeepLast { depth: 5 }),
+        effective: Some(policy::History::KeepLast { depth: 2 }),
+      }
+    );
+    assert_eq!(
+      contract.reliability.effective,
+      Some(policy::Reliability::BestEffort)
+    );
+    let Some(policy::Deadline(effective_deadline)) = contract.deadline.effective else {
+      panic!("no effective deadline");
+    };
+    assert_eq!(effective_deadline, Duration::from_secs(1));
+
+    // The deadline is missed only after the effective period, not the offered one
+    let data_flags = BitFlags::<DATA_Flags>::from_flag(DATA_Flags::Data);
+    reader.handle_data_msg(data_with_sn(&reader, writer_guid, 1), data_flags, &mr_state);
+    clock.advance(Duration::from_millis(700));
+    assert!(reader
+      .calculate_if_requested_deadline_is_missed()
+      .is_empty());
+    clock.advance(Duration::from_millis(400));
+    assert_eq!(reader.calculate_if_requested_deadline_is_missed().len(), 1);
+
+    // The reader acts best effort: a gap is reported lost, not waited for
+    reader.handle_data_msg(data_with_sn(&reader, writer_guid, 3), data_flags, &mr_state);
+    assert_eq!(reader.sample_lost_count, 1);
+
+    // An incompatible offer is not matched
+    let (reader, writer_guid, _) =
+      reader_with_writer_offering(&offered, &QosPolicyBuilder::new().best_effort().build());
+    assert!(reader.matched_writer(writer_guid).is_none());
+  }
 }
@@ -8,7 +8,10 @@ use bit_vec::BitVec;
 use log::{debug, error, info, trace, warn};
 
 use crate::{
-  dds::{participant::DomainParticipant, qos::QosPolicies},
+  dds::{
+    participant::DomainParticipant,
+    qos::{MatchedContract, QosPolicies},
+  },
   discovery::sedp_messages::DiscoveredReaderData,
   messages::submessages::submessage::AckSubmessage,
   rtps::constant::*,
@@ -60,6 +63,8 @@ pub(crate) struct RtpsReaderProxy {
   // false = send data messages directly from DataWriter
   pub repair_mode: bool,
   qos: QosPolicies,
+  // QoS contract with the local Writer. Set by the Writer on matching.
+  pub contract: Option<MatchedContract>,
   frags_requested: BTreeMap<SequenceNumber, BitVec>,
 }
 
@@ -77,6 +82,7 @@ impl RtpsReaderProxy {
       pending_gap: BTreeSet::new(),
       repair_mode: false,
       qos,
+      contract: None,
       frags_requested: BTreeMap::new(),
     }
   }
@@ -170,6 +176,7 @@ impl RtpsReaderProxy {
       pending_gap: BTreeSet::new(),
       repair_mode: false,
       qos: reader.qos_policy.clone(),
+      contract: None,
       frags_requested: BTreeMap::new(),
     }
   }
@@ -221,6 +228,7 @@ impl RtpsReaderProxy {
       pending_gap: BTreeSet::new(),
       repair_mode: false,
       qos: discovered_reader_data.subscription_topic_data.qos(),
+      contract: None,
       frags_requested: BTreeMap::new(),
     }
   }
@@ -317,6 +325,32 @@ impl RtpsReaderProxy {
     self.all_acked_before
   }
 
+  // Checks the sequence numbers tracked for the Reader against the last one
+  // available in the Writer. Returns a description of each violation found.
+  pub fn verify_invariants(&self, last_available: SequenceNumber) -> Vec<String> {
+    let mut violations = Vec::new();
+    if self.all_acked_before < SequenceNumber::zero() {
+      violations.push(format!(
+        "Acked before {:?}, which is negative",
+        self.all_acked_before
+      ));
+    }
+    for (set_name, set) in [
+      ("unsent changes", &self.unsent_changes),
+      ("pending GAP", &self.pending_gap),
+    ] {
+      if let (Some(first), Some(last)) = (set.first(), set.last()) {
+        if *first < SequenceNumber::new(1) || *last > last_available {
+          violations.push(format!(
+            "The {set_name} {first:?}..={last:?} are not within the available changes 1..={:?}",
+            last_available
+          ));
+        }
+      }
+    }
+    violations
+  }
+
   // Fragment handling
 
   pub fn mark_all_frags_requested(&mut self, seq_num: SequenceNumber, frag_count: u32) {
@@ -330,28 +364,36 @@ impl RtpsReaderProxy {
       );
   }
 
-  pub fn mark_frags_requested(&mut self, seq_num: SequenceNumber, frag_nums: &FragmentNumberSet) {
-    let req_set = self
-      .frags_requested
-      .entry(seq_num)
-      .or_insert_with(|| BitVec::with_capacity(64)); // default capacity out of hat
-
-    if let Some(max_fn_requested) = req_set.iter().next_back() {
-      // allocate more space if needed
-      let max_fn_requested = usize::from(max_fn_requested);
-      if max_fn_requested > req_set.len() {
-        let growth_need = max_fn_requested - req_set.len();
-        req_set.grow(growth_need, false);
-      }
-      for f in frag_nums.iter() {
-        // -1 because FragmentNumbers start at 1
-        req_set.set(usize::from(f) - 1, true);
-      }
-    } else {
+  // Marks the fragments in frag_nums as requested. Fragment numbers beyond
+  // frag_count, the number of fragments in the sample, are ignored.
+  pub fn mark_frags_requested(
+    &mut self,
+    seq_num: SequenceNumber,
+    frag_nums: &FragmentNumberSet,
+    frag_count: u32,
+  ) {
+    let frag_count = usize::try_from(frag_count).unwrap_or(usize::MAX);
+    let requested: Vec<usize> = frag_nums
+      .iter()
+      .map(usize::from)
+      .filter(|f| (1..=frag_count).contains(f))
+      .collect();
+
+    if requested.is_empty() {
       warn!(
-        "mark_frags_requested: Empty set in NackFrag??? reader={:?} SN={:?}",
+        "mark_frags_requested: No valid fragments in NackFrag??? reader={:?} SN={:?}",
         self.remote_reader_guid, seq_num
       );
+      return;
+    }
+
+    let req_set = self
+      .frags_requested
+      .entry(seq_num)
+      .or_insert_with(|| BitVec::from_elem(frag_count, false));
+    for f in requested {
+      // -1 because FragmentNumbers start at 1
+      req_set.set(f - 1, true);
     }
   }
 
@@ -5,6 +5,7 @@ use std::{cmp::max, collections::BTreeMap};
 use log::{debug, error, info, trace, warn};
 
 use crate::{
+  dds::qos::MatchedContract,
   discovery::sedp_messages::DiscoveredWriterData,
   structure::{
     guid::{EntityId, GUID},
@@ -30,6 +31,9 @@ pub(crate) struct RtpsWriterProxy {
   /// Identifies the group to which the matched Reader belongs
   pub remote_group_entity_id: EntityId,
 
+  /// QoS contract with the local Reader. Set by the Reader on matching.
+  pub contract: Option<MatchedContract>,
+
   // See RTPS Spec v2.5 Section 8.4.10.4 on how the WriterProxy is supposed to
   // operate.
   // And 8.4.10.5 on statuses of the (cache) changes received from a writer.
@@ -63,9 +67,14 @@ pub(crate) struct RtpsWriterProxy {
 
   // The changes map is cleaned on heartbeat messages. The changes no longer available are dropped.
   pub received_heartbeat_count: i32,
+  pub received_heartbeatfrag_count: i32,
 
   pub sent_ack_nack_count: i32,
 
+  // When the Writer last asserted its liveliness by a HEARTBEAT with the
+  // Liveliness flag, i.e. manually as in the MANUAL_BY_* Liveliness QoS kinds.
+  pub last_liveliness_assertion: Option<Timestamp>,
+
   ack_base: SequenceNumber, // We can ACK everything before this number.
   // ack_base can be increased from N-1 to N, if we receive DATA with SequenceNumber N-1
   // heartbeat(first,last) => ack_base can be increased to first.
@@ -88,9 +97,12 @@ impl RtpsWriterProxy {
       unicast_locator_list,
       multicast_locator_list,
       remote_group_entity_id,
+      contract: None,
       changes: BTreeMap::new(),
       received_heartbeat_count: 0,
+      received_heartbeatfrag_count: 0,
       sent_ack_nack_count: 0,
+      last_liveliness_assertion: None,
       // Sequence numbering must start at 1.
       // Therefore, we can ACK all sequence numbers below 1 even before receiving anything.
       ack_base: SequenceNumber::new(1),
@@ -115,6 +127,7 @@ impl RtpsWriterProxy {
     self.unicast_locator_list = other.unicast_locator_list;
     self.multicast_locator_list = other.multicast_locator_list;
     self.remote_group_entity_id = other.remote_group_entity_id;
+    self.contract = other.contract;
   }
 
   // This is used to check for DEADLINE policy
@@ -203,6 +216,58 @@ impl RtpsWriterProxy {
     seqnum < self.ack_base || self.changes.contains_key(&seqnum)
   }
 
+  // Number of changes skipped over, if seq_num is received next. This is used
+  // to detect lost samples in a BestEffort Reader.
+  pub fn changes_skipped_before(&self, seq_num: SequenceNumber) -> i64 {
+    if self.last_received_sequence_number > SequenceNumber::new(0)
+      && seq_num > self.last_received_sequence_number
+    {
+      i64::from(seq_num - self.last_received_sequence_number) - 1
+    } else {
+      0
+    }
+  }
+
+  // Tells if seq_num is one of the changes a BestEffort Reader skipped over,
+  // i.e. already counted as lost, and is now arriving late.
+  pub fn is_skipped_change(&self, seq_num: SequenceNumber) -> bool {
+    seq_num < self.last_received_sequence_number && !self.changes.contains_key(&seq_num)
+  }
+
+  // Number of changes before seq_num that are still missing, i.e. neither
+  // received nor not_available. This is used to detect lost samples in a
+  // Reliable Reader, when a HEARTBEAT says the writer no longer has them.
+  pub fn missing_changes_before(&self, seq_num: SequenceNumber) -> i64 {
+    if seq_num <= self.ack_base {
+      return 0;
+    }
+    let known = self.changes.range(self.ack_base..seq_num).count() as i64;
+    i64::from(seq_num - self.ack_base) - known
+  }
+
+  // Checks that ack_base has advanced as far as the known changes allow, and
+  // that the latest received change is still accounted for. Returns a
+  // description of each violation found.
+  pub fn verify_invariants(&self) -> Vec<String> {
+    let mut violations = Vec::new();
+    if self.ack_base < SequenceNumber::new(1) {
+      violations.push(format!("Ack base {:?} is below 1", self.ack_base));
+    }
+    if self.changes.contains_key(&self.ack_base) {
+      violations.push(format!(
+        "Ack base {:?} is a known change, so it should have advanced",
+        self.ack_base
+      ));
+    }
+    let last_received = self.last_received_sequence_number;
+    if last_received >= self.ack_base && !self.changes.contains_key(&last_received) {
+      violations.push(format!(
+        "The latest received change {last_received:?} is neither acked nor known"
+      ));
+    }
+    violations
+  }
+
   // This is used to mark DATA as received.
   pub fn received_changes_add(&mut self, seq_num: SequenceNumber, receive_timestamp: Timestamp) {
     self.changes.insert(seq_num, Some(receive_timestamp));
@@ -322,9 +387,12 @@ impl RtpsWriterProxy {
       remote_group_entity_id: EntityId::UNKNOWN,
       unicast_locator_list,
       multicast_locator_list,
+      contract: None,
       changes: BTreeMap::new(),
       received_heartbeat_count: 0,
+      received_heartbeatfrag_count: 0,
       sent_ack_nack_count: 0,
+      last_liveliness_assertion: None,
       ack_base: SequenceNumber::default(),
       last_received_sequence_number: SequenceNumber::new(0),
       last_received_timestamp: Timestamp::INVALID,
@@ -354,3 +422,40 @@ impl RtpsWriterProxy {
     }
   }
 } // impl
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn writer_proxy_verify_invariants_detects_corruption() {
+    let mut writer_proxy = RtpsWriterProxy::new(
+      GUID::GUID_UNKNOWN,
+      Vec::new(),
+      Vec::new(),
+      EntityId::UNKNOWN,
+    );
+    writer_proxy.received_changes_add(SequenceNumber::new(1), Timestamp::now());
+    writer_proxy.received_changes_add(SequenceNumber::new(3), Timestamp::now());
+    assert_eq!(writer_proxy.verify_invariants(), Vec::<String>::new());
+
+    // ack_base was not advanced over a received change
+    writer_proxy.changes.insert(SequenceNumber::new(2), None);
+    let violations = writer_proxy.verify_invariants();
+    assert!(
+      violations[0].contains("should have advanced"),
+      "{violations:?}"
+    );
+
+    // The latest received change was forgotten
+    writer_proxy.advance_ack_base();
+    writer_proxy.last_received_sequence_number = SequenceNumber::new(5);
+    let violations = writer_proxy.verify_invariants();
+    assert_eq!(violations.len(), 1, "{violations:?}");
+    assert!(violations[0].contains("neither acked nor known"));
+
+    writer_proxy.ack_base = SequenceNumber::new(0);
+    let violations = writer_proxy.verify_invariants();
+    assert!(violations[0].contains("below 1"), "{violations:?}");
+  }
+}
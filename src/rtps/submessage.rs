@@ -2,7 +2,7 @@ use std::io;
 
 use bytes::Bytes;
 use enumflags2::BitFlags;
-use log::{debug, error, trace};
+use log::{debug, info, trace};
 use speedy::{Context, Readable, Writable, Writer};
 
 use crate::{
@@ -226,7 +226,7 @@ impl Submessage {
       SubmessageKind::INFO_REPLY => {
         let f = BitFlags::<INFOREPLY_Flags>::from_bits_truncate(sub_header.flags);
         mk_i_subm(InterpreterSubmessage::InfoReply(
-          InfoReply::read_from_buffer_with_ctx(e, &sub_content_buffer)?,
+          InfoReply::deserialize(&sub_content_buffer, f)?,
           f,
         ))
       }
@@ -285,8 +285,9 @@ impl Submessage {
           );
           trace!("Submessage was {:?}", &sub_buffer);
         } else {
-          // Kind is 0x00 - 0x7F, it should be in the standard.
-          error!("Received unknown submessage kind {:?}", unknown_kind);
+          // Kind is 0x00 - 0x7F, it should be in the standard. Maybe a newer
+          // version than ours.
+          info!("Received unknown submessage kind {:?}", unknown_kind);
           debug!("Submessage was {:?}", &sub_buffer);
         }
         Ok(None)
@@ -1,4 +1,6 @@
 use std::{
+  borrow::Cow,
+  cell::RefCell,
   cmp::{max, min},
   collections::{BTreeMap, BTreeSet},
   ops::Bound::Included,
@@ -6,6 +8,7 @@ use std::{
   sync::{atomic, Arc, Mutex},
 };
 use core::task::Waker;
+use bytes::Bytes;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
@@ -22,37 +25,40 @@ use crate::{
     qos::{
       policy,
       policy::{History, Reliability},
-      HasQoSPolicy, QosPolicies,
+      HasQoSPolicy, MatchedContract, QosPolicies,
     },
     statusevents::{
       CountWithChange, DataWriterStatus, DomainParticipantStatusEvent, StatusChannelSender,
     },
     with_key::datawriter::WriteOptions,
   },
-  messages::submessages::submessages::AckSubmessage,
+  messages::submessages::submessages::{AckSubmessage, InterpreterSubmessage, WriterSubmessage},
   network::udp_sender::UDPSender,
   rtps::{
-    constant::{NACK_RESPONSE_DELAY, NACK_SUPPRESSION_DURATION},
+    constant::{DEFAULT_MAX_MESSAGE_SIZE, NACK_RESPONSE_DELAY, NACK_SUPPRESSION_DURATION},
     rtps_reader_proxy::RtpsReaderProxy,
-    Message, MessageBuilder,
+    Message, MessageBuilder, Submessage, SubmessageBody,
   },
   structure::{
     cache_change::CacheChange,
+    clock,
     duration::Duration,
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, GUID},
+    invariants,
     locator::Locator,
     sequence_number::{FragmentNumber, SequenceNumber},
     time::Timestamp,
   },
 };
 #[cfg(feature = "security")]
-use crate::{
-  rtps::Submessage,
-  security::{security_plugins::SecurityPluginsHandle, SecurityResult},
-};
+use crate::security::{security_plugins::SecurityPluginsHandle, SecurityResult};
 #[cfg(not(feature = "security"))]
 use crate::no_security::SecurityPluginsHandle;
+#[cfg(feature = "fastdds_statistics")]
+use crate::dds::statistics::ProtocolStatistics;
+#[cfg(feature = "history_spill")]
+use crate::rtps::history_spill::SpillStore;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum DeliveryMode {
@@ -66,6 +72,133 @@ pub(crate) enum TimedEvent {
   CacheCleaning,
   SendRepairData { to_reader: GUID },
   SendRepairFrags { to_reader: GUID },
+  FlushMessage,
+}
+
+// How the Writer packs submessages into RTPS messages, see
+// Writer::queue_message_to_readers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct MessageAggregation {
+  // Messages are not grown beyond this many bytes. Zero disables aggregation.
+  pub max_message_size: usize,
+  // How long a message may wait for more submessages after the DataWriter
+  // queue has been emptied. Zero sends it right away.
+  pub flush_delay: std::time::Duration,
+}
+
+impl Default for MessageAggregation {
+  fn default() -> Self {
+    Self {
+      max_message_size: usize::from(DEFAULT_MAX_MESSAGE_SIZE),
+      flush_delay: std::time::Duration::ZERO,
+    }
+  }
+}
+
+// RTPS spec v2.5 Section "9.4.4 RTPS Header"
+const RTPS_HEADER_SIZE: usize = 20;
+// RTPS spec v2.5 Section "9.4.5.1 SubmessageHeader"
+const SUBMESSAGE_HEADER_SIZE: usize = 4;
+// An INFO_TS without a timestamp and an INFO_DST, which may be needed to reset
+// what earlier submessages left in effect
+const RESET_SUBMESSAGES_SIZE: usize = 2 * SUBMESSAGE_HEADER_SIZE + 12;
+
+fn submessages_size(submessages: &[Submessage]) -> usize {
+  submessages
+    .iter()
+    .map(|s| SUBMESSAGE_HEADER_SIZE + usize::from(s.header.content_length))
+    .sum()
+}
+
+fn is_heartbeat(submessage: &Submessage) -> bool {
+  matches!(
+    submessage.body,
+    SubmessageBody::Writer(WriterSubmessage::Heartbeat(..))
+  )
+}
+
+// Submessages waiting to be sent together in one RTPS message to the same
+// Readers and locators
+struct PendingMessage {
+  reader_guids: Vec<GUID>,
+  locators: Vec<Locator>,
+  submessages: Vec<Submessage>,
+  // The source timestamp and the destination in effect after the last
+  // submessage, as set by INFO_TS and INFO_DST
+  source_timestamp: Option<Timestamp>,
+  destination: GuidPrefix,
+}
+
+impl PendingMessage {
+  fn new(reader_guids: Vec<GUID>, locators: Vec<Locator>) -> Self {
+    Self {
+      reader_guids,
+      locators,
+      submessages: vec![],
+      source_timestamp: None,
+      destination: GuidPrefix::UNKNOWN,
+    }
+  }
+
+  // Serialized size, including the RTPS header
+  fn size(&self) -> usize {
+    RTPS_HEADER_SIZE + submessages_size(&self.submessages)
+  }
+
+  // Appends the submessages of a message that was built to be sent alone, i.e.
+  // expects no source timestamp or destination in effect at its start.
+  fn append(&mut self, mut submessages: Vec<Submessage>, endianness: Endianness) {
+    // The interpreter submessages before the first Entity submessage
+    let preamble = || {
+      submessages
+        .iter()
+        .take_while(|s| matches!(s.body, SubmessageBody::Interpreter(_)))
+    };
+    let sets_timestamp = preamble().any(|s| {
+      matches!(
+        s.body,
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(..))
+      )
+    });
+    let sets_destination = preamble().any(|s| {
+      matches!(
+        s.body,
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoDestination(..))
+      )
+    });
+
+    // Reset what the earlier submessages left in effect
+    let mut builder = MessageBuilder::new();
+    if self.source_timestamp.is_some() && !sets_timestamp {
+      builder = builder.ts_msg(endianness, None);
+    }
+    if self.destination != GuidPrefix::UNKNOWN && !sets_destination {
+      builder = builder.dst_submessage(endianness, GuidPrefix::UNKNOWN);
+    }
+    let reset = builder
+      .add_header_and_build(GuidPrefix::UNKNOWN)
+      .submessages;
+    submessages.splice(0..0, reset);
+
+    // A HEARTBEAT supersedes the earlier ones, so they are piggybacked only on
+    // the last DATA
+    if submessages.iter().any(is_heartbeat) {
+      self.submessages.retain(|s| !is_heartbeat(s));
+    }
+
+    for submessage in &submessages {
+      match &submessage.body {
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(ts, _)) => {
+          self.source_timestamp = ts.timestamp;
+        }
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoDestination(dst, _)) => {
+          self.destination = dst.guid_prefix;
+        }
+        _ => (),
+      }
+    }
+    self.submessages.extend(submessages);
+  }
 }
 
 // This is used to construct an actual Writer.
@@ -80,6 +213,8 @@ pub(crate) struct WriterIngredients {
   pub status_sender: StatusChannelSender<DataWriterStatus>,
 
   pub(crate) security_plugins: Option<SecurityPluginsHandle>,
+  pub fragment_size: u16, // Payloads larger than this are sent as DATA_FRAGs
+  pub(crate) message_aggregation: MessageAggregation,
 }
 
 impl WriterIngredients {
@@ -132,21 +267,86 @@ struct HistoryBuffer {
   /// specifiy.
   history_buffer: BTreeMap<Timestamp, CacheChange>,
 
+  /// Sum of the payload sizes in history_buffer
+  #[cfg(feature = "history_spill")]
+  memory_bytes: usize,
+
+  /// Holds the oldest changes, which do not fit in the memory budget of the
+  /// HistorySpill QoS policy.
+  #[cfg(feature = "history_spill")]
+  spill: Option<HistorySpill>,
+
+  /// The dispose and unregister changes in the history, with the instances
+  /// they change
+  instance_state_changes: BTreeMap<SequenceNumber, Bytes>,
+  /// For durable writers, how many instances keep their latest dispose or
+  /// unregister change when older changes are removed. Late-joining readers
+  /// then learn the final state of the instance, like a reader that has been
+  /// matched all along. None for volatile writers.
+  max_retained_instance_states: Option<usize>,
+  /// All changes from here on are in the history. Before this, only the
+  /// retained instance state changes are.
+  kept_from: SequenceNumber,
+
   // topic name is just for debugging
   topic_name: String,
 }
 
+#[cfg(feature = "history_spill")]
+struct HistorySpill {
+  store: SpillStore,
+  memory_budget: usize,
+}
+
 impl HistoryBuffer {
-  fn new(topic_name: String) -> Self {
+  fn new(topic_name: String, max_retained_instance_states: Option<usize>) -> Self {
     HistoryBuffer {
       first_seq: SequenceNumber::new(1),
       last_seq: SequenceNumber::new(0), // Indicates that we have nothing yet
       sequence_number_to_instant: BTreeMap::new(),
       history_buffer: BTreeMap::new(),
+      #[cfg(feature = "history_spill")]
+      memory_bytes: 0,
+      #[cfg(feature = "history_spill")]
+      spill: None,
+      instance_state_changes: BTreeMap::new(),
+      max_retained_instance_states,
+      kept_from: SequenceNumber::new(1),
       topic_name,
     }
   }
 
+  #[cfg(feature = "history_spill")]
+  fn with_spill(
+    topic_name: String,
+    max_retained_instance_states: Option<usize>,
+    store: SpillStore,
+    memory_budget: usize,
+  ) -> Self {
+    HistoryBuffer {
+      spill: Some(HistorySpill {
+        store,
+        memory_budget,
+      }),
+      ..Self::new(topic_name, max_retained_instance_states)
+    }
+  }
+
+  // The instance that a dispose or unregister change applies to
+  fn changed_instance(data: &DDSData) -> Option<Bytes> {
+    match data {
+      DDSData::Data { .. } => None,
+      DDSData::DisposeByKey { key, .. } => Some(key.value.clone()),
+      DDSData::DisposeByKeyHash { key_hash, .. } => Some(Bytes::from(key_hash.to_vec())),
+    }
+  }
+
+  // Sequence numbers below kept_from that are not in the history have been
+  // removed on purpose
+  fn was_removed(&self, sn: SequenceNumber) -> bool {
+    sn < self.kept_from && !self.sequence_number_to_instant.contains_key(&sn)
+  }
+
   /// Internal counter used to assign
   /// increasing sequence number to
   /// each change made by the Writer
@@ -162,19 +362,37 @@ impl HistoryBuffer {
     self.first_seq
   }
 
-  fn get_change(&self, ts: Timestamp) -> Option<&CacheChange> {
-    self.history_buffer.get(&ts)
+  fn get_change(&self, ts: Timestamp) -> Option<Cow<'_, CacheChange>> {
+    if let Some(cc) = self.history_buffer.get(&ts) {
+      return Some(Cow::Borrowed(cc));
+    }
+    #[cfg(feature = "history_spill")]
+    if let Some(spill) = &self.spill {
+      return spill.store.read(&ts).map(Cow::Owned);
+    }
+    None
   }
 
-  fn get_by_sn(&self, sn: SequenceNumber) -> Option<&CacheChange> {
+  fn get_by_sn(&self, sn: SequenceNumber) -> Option<Cow<'_, CacheChange>> {
     self
       .sequence_number_to_instant
       .get(&sn)
       .and_then(|ts| self.get_change(*ts))
   }
 
+  fn contains_sn(&self, sn: SequenceNumber) -> bool {
+    self.sequence_number_to_instant.contains_key(&sn)
+  }
+
   fn add_change(&mut self, timestamp: Timestamp, new_cache_change: CacheChange) {
     let new_seq = new_cache_change.sequence_number;
+    if let Some(instance) = Self::changed_instance(&new_cache_change.data_value) {
+      self.instance_state_changes.insert(new_seq, instance);
+    }
+    #[cfg(feature = "history_spill")]
+    {
+      self.memory_bytes += new_cache_change.data_value.payload_size();
+    }
 
     // actual insert
     let had_already_same = self.history_buffer.insert(timestamp, new_cache_change);
@@ -192,11 +410,27 @@ impl HistoryBuffer {
     } else {
       error!("HistoryBuffer: Tried to add changes out of SequenceNumber order.");
     }
+    self.check_invariants();
   }
 
   fn remove_changes_before(&mut self, remove_before_seq: SequenceNumber) {
+    if remove_before_seq <= self.kept_from {
+      // Already removed
+      return;
+    }
     if let Some(remove_before) = self.sequence_number_to_instant.get(&remove_before_seq) {
-      self.history_buffer = self.history_buffer.split_off(remove_before);
+      let retained = self.instance_states_to_retain(remove_before_seq);
+      let kept = self.history_buffer.split_off(remove_before);
+      #[cfg(feature = "history_spill")]
+      {
+        for removed in self.history_buffer.values() {
+          self.memory_bytes -= removed.data_value.payload_size();
+        }
+        if let Some(spill) = &mut self.spill {
+          spill.store.remove_before(remove_before);
+        }
+      }
+      self.history_buffer = kept;
       self.sequence_number_to_instant = self
         .sequence_number_to_instant
         .split_off(&remove_before_seq);
@@ -209,6 +443,25 @@ impl HistoryBuffer {
            it in the sequence_number_to_instant map? Looks like a bug."
         );
       }
+      self.kept_from = remove_before_seq;
+
+      let mut kept_instance_state_changes =
+        self.instance_state_changes.split_off(&remove_before_seq);
+      for (timestamp, cache_change) in retained {
+        let sn = cache_change.sequence_number;
+        if let Some(instance) = self.instance_state_changes.remove(&sn) {
+          kept_instance_state_changes.insert(sn, instance);
+        }
+        self.first_seq = min(self.first_seq, sn);
+        self.sequence_number_to_instant.insert(sn, timestamp);
+        #[cfg(feature = "history_spill")]
+        {
+          self.memory_bytes += cache_change.data_value.payload_size();
+        }
+        self.history_buffer.insert(timestamp, cache_change);
+      }
+      self.instance_state_changes = kept_instance_state_changes;
+      self.check_invariants();
     } else {
       // Number 1 is a special case. If the buffer is empty, then it is not found.
       if remove_before_seq != SequenceNumber::new(1) {
@@ -219,6 +472,167 @@ impl HistoryBuffer {
       }
     }
   }
+
+  // Checks that the sequence number index, the stored changes and the sequence
+  // number bounds agree with each other. Returns a description of each
+  // violation found.
+  fn verify_invariants(&self) -> Vec<String> {
+    let mut violations = Vec::new();
+    for (sn, timestamp) in &self.sequence_number_to_instant {
+      match self.history_buffer.get(timestamp) {
+        Some(cache_change) if cache_change.sequence_number != *sn => violations.push(format!(
+          "{sn:?} is indexed to the change at {timestamp:?}, which has {:?}",
+          cache_change.sequence_number
+        )),
+        Some(_) => (),
+        None if self.is_spilled(timestamp) => (),
+        None => violations.push(format!(
+          "{sn:?} is indexed to {timestamp:?}, but there is no change stored there"
+        )),
+      }
+      if *sn < self.first_seq || *sn > self.last_seq {
+        violations.push(format!(
+          "{sn:?} is outside of the history bounds {:?}..={:?}",
+          self.first_seq, self.last_seq
+        ));
+      }
+      if *sn < self.kept_from && !self.instance_state_changes.contains_key(sn) {
+        violations.push(format!(
+          "{sn:?} is before {:?}, but is not a retained dispose or unregister",
+          self.kept_from
+        ));
+      }
+    }
+    if self.history_buffer.len() > self.sequence_number_to_instant.len() {
+      violations.push(format!(
+        "{} changes are stored, but only {} are indexed",
+        self.history_buffer.len(),
+        self.sequence_number_to_instant.len()
+      ));
+    }
+    for sn in self.instance_state_changes.keys() {
+      if !self.sequence_number_to_instant.contains_key(sn) {
+        violations.push(format!(
+          "Dispose or unregister {sn:?} is tracked, but not in the history"
+        ));
+      }
+    }
+    #[cfg(feature = "history_spill")]
+    {
+      let memory_bytes: usize = self
+        .history_buffer
+        .values()
+        .map(|cache_change| cache_change.data_value.payload_size())
+        .sum();
+      if memory_bytes != self.memory_bytes {
+        violations.push(format!(
+          "The changes in memory take {memory_bytes} bytes, but {} bytes are accounted",
+          self.memory_bytes
+        ));
+      }
+    }
+    violations
+  }
+
+  fn is_spilled(&self, _timestamp: &Timestamp) -> bool {
+    #[cfg(feature = "history_spill")]
+    if let Some(spill) = &self.spill {
+      return spill.store.contains(_timestamp);
+    }
+    false
+  }
+
+  fn check_invariants(&self) {
+    invariants::check_after_mutation("HistoryBuffer", || self.verify_invariants());
+  }
+
+  // The changes before the sequence number that are the latest dispose or
+  // unregister change of their instance, at most max_retained_instance_states
+  // of the newest ones
+  fn instance_states_to_retain(
+    &self,
+    remove_before_seq: SequenceNumber,
+  ) -> Vec<(Timestamp, CacheChange)> {
+    let Some(max_retained) = self.max_retained_instance_states else {
+      return Vec::new();
+    };
+    let mut latest_changes = BTreeMap::new();
+    for (sn, instance) in &self.instance_state_changes {
+      latest_changes.insert(instance, *sn);
+    }
+    let latest_changes: BTreeSet<SequenceNumber> = latest_changes.into_values().collect();
+
+    let mut retained: Vec<(Timestamp, CacheChange)> = latest_changes
+      .range(..remove_before_seq)
+      .rev()
+      .take(max_retained)
+      .filter_map(|sn| {
+        let timestamp = *self.sequence_number_to_instant.get(sn)?;
+        let cache_change = self.get_change(timestamp)?.into_owned();
+        Some((timestamp, cache_change))
+      })
+      .collect();
+    retained.reverse();
+    retained
+  }
+
+  #[cfg(feature = "history_spill")]
+  fn is_spilling(&self) -> bool {
+    self.spill.is_some()
+  }
+
+  // Removes the changes that were written before the timestamp, but always
+  // keeps the latest change
+  #[cfg(feature = "history_spill")]
+  fn remove_changes_written_before(&mut self, timestamp: Timestamp) {
+    let first_keeper = self
+      .sequence_number_to_instant
+      .iter()
+      .find(|(_sn, ts)| **ts >= timestamp)
+      .map_or(self.last_seq, |(sn, _ts)| *sn);
+    if first_keeper > self.first_seq {
+      self.remove_changes_before(first_keeper);
+    }
+  }
+
+  // Moves the oldest changes before the sequence number from memory to disk,
+  // until the rest fit in the memory budget
+  #[cfg(feature = "history_spill")]
+  fn spill_changes_before(&mut self, spill_before_seq: SequenceNumber) {
+    let Some(spill) = &mut self.spill else {
+      return;
+    };
+    while self.memory_bytes > spill.memory_budget {
+      let Some(oldest) = self.history_buffer.first_entry() else {
+        break;
+      };
+      if oldest.get().sequence_number >= spill_before_seq {
+        break;
+      }
+      if let Err(e) = spill.store.append(*oldest.key(), oldest.get()) {
+        // Keep the change in memory
+        error!(
+          "HistoryBuffer: Cannot spill to disk: {e} topic={}",
+          self.topic_name
+        );
+        break;
+      }
+      self.memory_bytes -= oldest.remove().data_value.payload_size();
+    }
+    self.check_invariants();
+  }
+
+  #[cfg(feature = "history_spill")]
+  fn compact_spill(&mut self) {
+    if let Some(spill) = &mut self.spill {
+      if let Err(e) = spill.store.compact_in_background() {
+        error!(
+          "HistoryBuffer: Cannot compact the spill file: {e} topic={}",
+          self.topic_name
+        );
+      }
+    }
+  }
 }
 
 pub(crate) struct Writer {
@@ -281,6 +695,9 @@ pub(crate) struct Writer {
 
   // Sending mechanism
   udp_sender: Rc<UDPSender>,
+  message_aggregation: MessageAggregation,
+  pending_message: RefCell<Option<PendingMessage>>,
+  flush_timer_set: bool,
 
   // By default, this writer is a StatefulWriter (see RTPS spec section 8.4.9)
   // If like_stateless is true, then the writer mimics the behavior of a Best-Effort
@@ -314,6 +731,9 @@ pub(crate) struct Writer {
   participant_status_sender: StatusChannelSender<DomainParticipantStatusEvent>,
 
   security_plugins: Option<SecurityPluginsHandle>,
+
+  #[cfg(feature = "fastdds_statistics")]
+  statistics: Option<Arc<ProtocolStatistics>>,
 }
 
 pub enum WriterCommand {
@@ -326,9 +746,17 @@ pub enum WriterCommand {
   WaitForAcknowledgments {
     all_acked: StatusChannelSender<()>,
   },
+  Flush,
   // ResetOfferedDeadlineMissedStatus { writer_guid: GUID },
 }
 
+impl Drop for Writer {
+  fn drop(&mut self) {
+    // Do not lose samples that wait for aggregation
+    self.flush_pending_message();
+  }
+}
+
 impl Writer {
   pub fn new(
     i: WriterIngredients,
@@ -387,8 +815,8 @@ impl Writer {
       nackfrag_response_delay: NACK_RESPONSE_DELAY, // default value from dp_event_loop
       repairfrags_continue_delay: std::time::Duration::from_millis(1),
       nack_suppression_duration: NACK_SUPPRESSION_DURATION,
-      data_max_size_serialized: 1024,
-      // ^^ TODO: Maybe a smarter selection would be in order.
+      data_max_size_serialized: usize::from(i.fragment_size),
+      // ^^ TODO: Maybe a smarter default would be in order.
       // We should get the minimum over all outgoing interfaces.
       my_guid: i.guid,
       writer_command_receiver: i.writer_command_receiver,
@@ -397,8 +825,11 @@ impl Writer {
       matched_readers_count_total: 0,
       requested_incompatible_qos_count: 0,
       udp_sender,
+      message_aggregation: i.message_aggregation,
+      pending_message: RefCell::new(None),
+      flush_timer_set: false,
       my_topic_name: i.topic_name.clone(),
-      history_buffer: HistoryBuffer::new(i.topic_name),
+      history_buffer: Self::new_history_buffer(i.topic_name, &i.qos_policies, i.guid),
       timed_event_timer,
       like_stateless: i.like_stateless,
       qos_policies: i.qos_policies,
@@ -407,9 +838,17 @@ impl Writer {
       ack_waiter: None,
 
       security_plugins: i.security_plugins,
+
+      #[cfg(feature = "fastdds_statistics")]
+      statistics: None,
     }
   }
 
+  #[cfg(feature = "fastdds_statistics")]
+  pub(crate) fn set_statistics(&mut self, statistics: Arc<ProtocolStatistics>) {
+    self.statistics = Some(statistics);
+  }
+
   /// To know when token represents a writer we should look entity attribute
   /// kind this entity token can be used in DataWriter -> Writer mio::channel.
   pub fn entity_token(&self) -> Token {
@@ -469,9 +908,12 @@ impl Writer {
           self.handle_repair_data_send(reader_guid);
           if let Some(rp) = self.lookup_reader_proxy_mut(reader_guid) {
             if rp.repair_mode {
+              // An infinite deadline, as in the builtin endpoints, would
+              // stall repair until the next ACKNACK.
               let delay_to_next_repair = self
                 .qos_policies
                 .deadline()
+                .filter(|dl| dl.0 != Duration::INFINITE)
                 .map_or_else(|| Duration::from_millis(100), |dl| dl.0)
                 / 5;
               self.timed_event_timer.set_timeout(
@@ -499,12 +941,70 @@ impl Writer {
             } // if
           } // if let
         } // SendRepairFrags
+        TimedEvent::FlushMessage => {
+          self.flush_timer_set = false;
+          self.flush_pending_message();
+        }
       } // match
     } // while
+    self.check_invariants();
   } // fn
 
+  // Durable writers keep the latest dispose or unregister change of as many
+  // instances as the ResourceLimits policy allows
+  fn max_retained_instance_states(qos: &QosPolicies) -> Option<usize> {
+    if qos.is_volatile() {
+      return None;
+    }
+    Some(
+      qos
+        .resource_limits
+        .and_then(|limits| usize::try_from(limits.max_instances).ok())
+        .filter(|max_instances| *max_instances > 0)
+        .unwrap_or(usize::MAX),
+    )
+  }
+
+  #[cfg(not(feature = "history_spill"))]
+  fn new_history_buffer(topic_name: String, qos: &QosPolicies, _guid: GUID) -> HistoryBuffer {
+    HistoryBuffer::new(topic_name, Self::max_retained_instance_states(qos))
+  }
+
+  #[cfg(feature = "history_spill")]
+  fn new_history_buffer(topic_name: String, qos: &QosPolicies, guid: GUID) -> HistoryBuffer {
+    let max_retained_instance_states = Self::max_retained_instance_states(qos);
+    let Some(policy::HistorySpill {
+      memory_budget,
+      directory,
+    }) = qos.history_spill()
+    else {
+      return HistoryBuffer::new(topic_name, max_retained_instance_states);
+    };
+    match SpillStore::create(&directory, guid) {
+      Ok(store) => HistoryBuffer::with_spill(
+        topic_name,
+        max_retained_instance_states,
+        store,
+        memory_budget,
+      ),
+      Err(e) => {
+        error!(
+          "Cannot create a history spill file in {directory:?}: {e}. Keeping the whole history \
+           in memory. topic={topic_name}"
+        );
+        HistoryBuffer::new(topic_name, max_retained_instance_states)
+      }
+    }
+  }
+
   /// This is called by dp_wrapper every time cacheCleaning message is received.
   fn handle_cache_cleaning(&mut self) {
+    #[cfg(feature = "history_spill")]
+    if self.history_buffer.is_spilling() {
+      self.clean_spilling_history();
+      return;
+    }
+
     let resource_limit = 32;
     // TODO: This limit should be obtained
     // from Topic and Writer QoS. There should be some reasonable default limit
@@ -525,6 +1025,53 @@ impl Writer {
     }
   }
 
+  // With the HistorySpill policy the history is not limited by memory, so it
+  // keeps as many changes as the History and ResourceLimits policies allow.
+  // Changes past their Lifespan are removed.
+  #[cfg(feature = "history_spill")]
+  fn clean_spilling_history(&mut self) {
+    let depth = match self.qos_policies.history {
+      None => 1,
+      Some(History::KeepAll) => self
+        .qos_policies
+        .resource_limits
+        .and_then(|limits| usize::try_from(limits.max_samples).ok())
+        .unwrap_or(usize::MAX),
+      Some(History::KeepLast { depth: d }) => d as usize,
+    };
+    // Do not overflow the SequenceNumber arithmetic
+    self.remove_all_acked_changes_but_keep_depth(min(depth, i32::MAX as usize));
+
+    if let Some(policy::Lifespan { duration }) = self.qos_policies.lifespan {
+      if duration != Duration::INFINITE {
+        self
+          .history_buffer
+          .remove_changes_written_before(clock::local_timestamp() - duration);
+      }
+    }
+
+    self.spill_acked_changes();
+    self.history_buffer.compact_spill();
+  }
+
+  // Moves changes that need no more sending to disk, if the memory budget is
+  // exceeded. Changes are needed until all reliable readers have acknowledged
+  // them. With no such readers, all but the latest change can be spilled.
+  #[cfg(feature = "history_spill")]
+  fn spill_acked_changes(&mut self) {
+    let last_seq = self.history_buffer.last_change_sequence_number();
+    let acked_by_all_readers = self
+      .readers
+      .values()
+      .filter(|reader| reader.qos().is_reliable())
+      .map(RtpsReaderProxy::acked_up_to_before)
+      .min()
+      .map_or(last_seq, |acked| min(acked, last_seq));
+    self
+      .history_buffer
+      .spill_changes_before(acked_by_all_readers);
+  }
+
   // --------------------------------------------------------------
   // --------------------------------------------------------------
   // --------------------------------------------------------------
@@ -586,7 +1133,7 @@ impl Writer {
               };
 
               let send_also_heartbeat = true;
-              self.send_cache_change(cc, send_also_heartbeat, target_reader_opt);
+              self.send_cache_change(&cc, send_also_heartbeat, target_reader_opt);
             } else {
               error!("Lost the cache change that was just added?!");
             }
@@ -607,7 +1154,7 @@ impl Writer {
                 liveliness_flag,
               )
               .add_header_and_build(self.my_guid.prefix);
-            self.send_message_to_readers(
+            self.queue_message_to_readers(
               DeliveryMode::Multicast,
               hb_message,
               &mut self.readers.values(),
@@ -619,6 +1166,8 @@ impl Writer {
         //   self.reset_offered_deadline_missed_status();
         // }
         WriterCommand::WaitForAcknowledgments { all_acked } => {
+          // The application waits, so there is no point in holding back data
+          self.flush_pending_message();
           if self.like_stateless {
             error!(
               "Attempted to wait for acknowledgements in a stateless Writer, which currently only \
@@ -656,8 +1205,23 @@ impl Writer {
             })
           };
         }
+
+        WriterCommand::Flush => self.flush_pending_message(),
       }
     }
+
+    // The DataWriter queue is empty. Send what was aggregated, unless it is
+    // configured to wait for more.
+    if self.message_aggregation.flush_delay.is_zero() {
+      self.flush_pending_message();
+    } else if !self.flush_timer_set && self.pending_message.borrow().is_some() {
+      self.timed_event_timer.set_timeout(
+        self.message_aggregation.flush_delay,
+        TimedEvent::FlushMessage,
+      );
+      self.flush_timer_set = true;
+    }
+    self.check_invariants();
   }
 
   // Returns a boolean telling if the data had to be fragmented
@@ -705,15 +1269,19 @@ impl Writer {
     let data_size = cc.data_value.payload_size();
     let fragmentation_needed = data_size > self.data_max_size_serialized;
 
+    #[cfg(feature = "fastdds_statistics")]
+    if let Some(statistics) = &self.statistics {
+      statistics.data_sent(self.my_guid, data_size);
+    }
+
     if !fragmentation_needed {
       // We can send DATA
       let mut message_builder = MessageBuilder::new();
 
       // If DataWriter sent us a source timestamp, then add that.
       // Timestamp has to go before Data to have effect on Data.
-      if let Some(src_ts) = cc.write_options.source_timestamp() {
-        message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
-      }
+      message_builder =
+        message_builder.ts_msg_if_changed(self.endianness, cc.write_options.source_timestamp());
 
       if let Some(reader) = target_reader_opt {
         // Add info_destination
@@ -786,10 +1354,9 @@ impl Writer {
       {
         let mut message_builder = MessageBuilder::new(); // fresh builder
 
-        if let Some(src_ts) = cc.write_options.source_timestamp() {
-          // Add timestamp
-          message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
-        }
+        // Add timestamp
+        message_builder =
+          message_builder.ts_msg_if_changed(self.endianness, cc.write_options.source_timestamp());
 
         if let Some(reader) = target_reader_opt {
           // Add info_destination
@@ -817,7 +1384,12 @@ impl Writer {
         let final_flag = false; // false = request that readers acknowledge with ACKNACK.
         let liveliness_flag = false; // This is not a manual liveliness assertion (DDS API call), but side-effect of
                                      // writing new data.
-        let hb_msg = MessageBuilder::new()
+        let mut message_builder = MessageBuilder::new();
+        if let Some(reader) = target_reader_opt {
+          message_builder =
+            message_builder.dst_submessage(self.endianness, reader.remote_reader_guid.prefix);
+        }
+        let hb_msg = message_builder
           .heartbeat_msg(
             self.entity_id(), // from Writer
             self.history_buffer.first_change_sequence_number(),
@@ -833,16 +1405,17 @@ impl Writer {
       }
     }
 
-    // Send the messages, either to all readers or just one
+    // Queue the messages, either to all readers or just one. They are sent
+    // when the caller flushes, or earlier when the queue fills up.
     for msg in messages_to_send {
       match target_reader_opt {
         None => {
           // To all
-          self.send_message_to_readers(DeliveryMode::Multicast, msg, &mut self.readers.values());
+          self.queue_message_to_readers(DeliveryMode::Multicast, msg, &mut self.readers.values());
         }
         Some(reader_proxy) => {
           // To one
-          self.send_message_to_readers(
+          self.queue_message_to_readers(
             DeliveryMode::Unicast,
             msg,
             &mut std::iter::once(reader_proxy),
@@ -866,9 +1439,11 @@ impl Writer {
     // Create a new CacheChange from DDSData & insert to history buffer
     // The timestamp taken here is used as a unique(!) key in the cache.
     let new_cache_change = CacheChange::new(self.guid(), new_sequence_number, write_options, data);
-    let timestamp = Timestamp::now();
+    let timestamp = clock::local_timestamp();
 
     self.history_buffer.add_change(timestamp, new_cache_change);
+    #[cfg(feature = "history_spill")]
+    self.spill_acked_changes();
 
     // Do not add to Global DDS cache, as we do not necessarily have a local Reader.
     // If we do, then we will receive the ATA packet from network.
@@ -890,9 +1465,6 @@ impl Writer {
       );
       return;
     }
-    // Reliable Stateful Writer (that tracks Readers by ReaderProxy) will not set
-    // the final flag.
-    let final_flag = false;
     let liveliness_flag = is_manual_assertion; // RTPS spec "8.3.7.5 Heartbeat"
 
     trace!(
@@ -904,17 +1476,22 @@ impl Writer {
     let first_change = self.history_buffer.first_change_sequence_number();
     let last_change = self.history_buffer.last_change_sequence_number();
 
-    if self
+    let all_acked = self
       .readers
       .values()
-      .all(|rp| last_change < rp.all_acked_before)
-    {
+      .all(|rp| last_change < rp.all_acked_before);
+
+    if all_acked && !is_manual_assertion {
       trace!("heartbeat tick: all readers have all available data.");
     } else {
+      // Reliable Stateful Writer (that tracks Readers by ReaderProxy) sets the
+      // final flag only when it does not need an acknowledgement, i.e. when
+      // the HEARTBEAT is sent only to assert liveliness.
+      let final_flag = all_acked;
       // the interface to .heartbeat_msg is silly: we give ref to ourself
       // and that function then queries us.
       let hb_message = MessageBuilder::new()
-        .ts_msg(self.endianness, Some(Timestamp::now()))
+        .ts_msg(self.endianness, Some(clock::wall_now()))
         .heartbeat_msg(
           self.entity_id(), // from Writer
           self.history_buffer.first_change_sequence_number(),
@@ -1009,6 +1586,16 @@ impl Writer {
           );
         }
 
+        #[cfg(feature = "fastdds_statistics")]
+        if let Some(statistics) = &self.statistics {
+          let reader_locator = self
+            .readers
+            .get(&reader_guid)
+            .and_then(|rp| rp.unicast_locator_list.first().copied())
+            .unwrap_or(Locator::Invalid);
+          statistics.acknack_received(self.my_guid, reader_guid, reader_locator);
+        }
+
         let my_topic = self.my_topic_name.clone(); // for debugging
         self.update_ack_waiters(reader_guid, Some(an.reader_sn_state.base()));
 
@@ -1074,6 +1661,7 @@ impl Writer {
         if let Some(reader_proxy) = self.readers.get(&reader_guid) {
           if !reader_proxy.get_pending_gap().is_empty() {
             let gap_message = MessageBuilder::new()
+              .dst_submessage(self.endianness, reader_guid.prefix)
               .gap_msg(
                 reader_proxy.get_pending_gap(),
                 self.my_guid.entity_id,
@@ -1088,22 +1676,70 @@ impl Writer {
             );
           }
         }
+
+        // A preemptive ACKNACK, which neither acknowledges nor requests anything,
+        // comes from a Reader that does not yet know what we have. Tell it with a
+        // HEARTBEAT that has the final flag clear, so that the Reader responds
+        // with the changes it is missing.
+        if an.reader_sn_state.is_empty()
+          && an.reader_sn_state.base() <= SequenceNumber::new(1)
+          && last_seq >= SequenceNumber::new(1)
+        {
+          if let Some(reader_proxy) = self.readers.get(&reader_guid) {
+            let final_flag = false;
+            let liveliness_flag = false;
+            let hb_message = MessageBuilder::new()
+              .dst_submessage(self.endianness, reader_guid.prefix)
+              .heartbeat_msg(
+                self.entity_id(), // from Writer
+                self.history_buffer.first_change_sequence_number(),
+                last_seq,
+                self.next_heartbeat_count(),
+                self.endianness,
+                reader_guid.entity_id, // to Reader
+                final_flag,
+                liveliness_flag,
+              )
+              .add_header_and_build(self.my_guid.prefix);
+            self.send_message_to_readers(
+              DeliveryMode::Unicast,
+              hb_message,
+              &mut std::iter::once(reader_proxy),
+            );
+          }
+        }
       } // AckNack
       AckSubmessage::NackFrag(ref nackfrag) => {
         // NackFrag is negative acknowledgement only, i.e. requesting missing fragments.
 
         let reader_guid = GUID::new(reader_guid_prefix, nackfrag.reader_id);
-        if let Some(reader_proxy) = self.lookup_reader_proxy_mut(reader_guid) {
-          reader_proxy.mark_frags_requested(nackfrag.writer_sn, &nackfrag.fragment_number_state);
+        let num_frags = self
+          .history_buffer
+          .get_by_sn(nackfrag.writer_sn)
+          .map(|cc| self.num_frags_and_frag_size(cc.data_value.payload_size()).0);
+        match (num_frags, self.lookup_reader_proxy_mut(reader_guid)) {
+          (Some(num_frags), Some(reader_proxy)) => {
+            reader_proxy.mark_frags_requested(
+              nackfrag.writer_sn,
+              &nackfrag.fragment_number_state,
+              num_frags,
+            );
+            self.timed_event_timer.set_timeout(
+              self.nackfrag_response_delay,
+              TimedEvent::SendRepairFrags {
+                to_reader: reader_guid,
+              },
+            );
+          }
+          (None, _) => debug!(
+            "NackFrag for {:?}, which is not in the history buffer. topic={:?}",
+            nackfrag.writer_sn, self.my_topic_name
+          ),
+          (Some(_), None) => (),
         }
-        self.timed_event_timer.set_timeout(
-          self.nackfrag_response_delay,
-          TimedEvent::SendRepairFrags {
-            to_reader: reader_guid,
-          },
-        );
       }
     }
+    self.check_invariants();
   }
 
   // Application may be waiting that remote Readers ACK what we are sending.
@@ -1141,6 +1777,8 @@ impl Writer {
       // reader_proxy back. This technique ensures that all return paths lead to
       // re-insertion.
       self.handle_repair_data_send_worker(&mut reader_proxy);
+      // Repair data is not held back, since the Reader is waiting for it
+      self.flush_pending_message();
       // insert reader back
       if let Some(rp) = self
         .readers
@@ -1218,10 +1856,11 @@ impl Writer {
           // // DEBUG
 
           // The cache change was found. Send it to the reader
-          let data_was_fragmented = self.send_cache_change(cc, false, Some(reader_proxy));
-
-          if data_was_fragmented {
-            // Mark the reader as having requested all frags
+          if cc.data_value.payload_size() <= self.data_max_size_serialized {
+            self.send_cache_change(&cc, false, Some(reader_proxy));
+          } else {
+            // Fragmented data is resent by the repair frags timer, a few
+            // fragments at a time. Mark the reader as having requested all frags.
             let (num_frags, _frag_size) =
               self.num_frags_and_frag_size(cc.data_value.payload_size());
             reader_proxy.mark_all_frags_requested(unsent_sn, num_frags);
@@ -1245,6 +1884,11 @@ impl Writer {
               "Reader {:?} requested too old data {:?}. I have only from {:?}. Topic {:?}",
               &reader_proxy, unsent_sn, first_available, &self.my_topic_name
             );
+          } else if self.history_buffer.was_removed(unsent_sn) {
+            debug!(
+              "Reader {:?} requested {:?}, which was removed from the history. Topic {:?}",
+              reader_guid, unsent_sn, &self.my_topic_name
+            );
           } else {
             // we are running out of excuses
             error!(
@@ -1255,6 +1899,18 @@ impl Writer {
         }
       }
 
+      // Collect also the rest of the unsent changes that will never be sent as
+      // DATA, so that a single GAP covers them instead of one GAP per repair
+      // round. The reader would otherwise keep NACKing them in the meantime.
+      let irrelevant_after_first: Vec<SequenceNumber> = reader_proxy
+        .unsent_changes_iter()
+        .filter(|sn| *sn > unsent_sn && *sn >= first_available)
+        .filter(|sn| {
+          reader_proxy.get_pending_gap().contains(sn) || !self.history_buffer.contains_sn(*sn)
+        })
+        .collect();
+      no_longer_relevant.extend(irrelevant_after_first);
+
       // Send a GAP if we marked a sequence number as no longer relevant
       if !no_longer_relevant.is_empty() || all_irrelevant_before.is_some() {
         let mut gap_msg = MessageBuilder::new().dst_submessage(self.endianness, reader_guid.prefix);
@@ -1280,6 +1936,11 @@ impl Writer {
         }
         let gap_msg = gap_msg.add_header_and_build(self.my_guid.prefix);
 
+        #[cfg(feature = "fastdds_statistics")]
+        if let Some(statistics) = &self.statistics {
+          statistics.gap_sent(self.my_guid);
+        }
+
         self.send_message_to_readers(
           DeliveryMode::Unicast,
           gap_msg,
@@ -1323,16 +1984,18 @@ impl Writer {
         }
 
         // Generate datafrag message
-        let mut message_builder = MessageBuilder::new();
-        if let Some(src_ts) = cache_change.write_options.source_timestamp() {
-          message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
-        }
+        let mut message_builder =
+          MessageBuilder::new().dst_submessage(self.endianness, reader_guid.prefix);
+        message_builder = message_builder.ts_msg_if_changed(
+          self.endianness,
+          cache_change.write_options.source_timestamp(),
+        );
 
         let fragment_size: u32 = self.data_max_size_serialized as u32; // TODO: overflow check
         let data_size: u32 = cache_change.data_value.payload_size() as u32; // TODO: overflow check
 
         message_builder = message_builder.data_frag_msg(
-          cache_change,
+          &cache_change,
           reader_guid.entity_id, // reader
           self.my_guid,          // writer
           frag_num,
@@ -1392,25 +2055,22 @@ impl Writer {
   }
 
   pub(crate) fn next_heartbeat_count(&self) -> i32 {
+    #[cfg(feature = "fastdds_statistics")]
+    if let Some(statistics) = &self.statistics {
+      statistics.heartbeat_sent(self.my_guid);
+    }
     self
       .heartbeat_message_counter
       .fetch_add(1, atomic::Ordering::SeqCst)
   }
 
   #[cfg(feature = "security")]
-  fn security_encode(
-    &self,
-    message: Message,
-    readers: &[&RtpsReaderProxy],
-  ) -> SecurityResult<Message> {
+  fn security_encode(&self, message: Message, reader_guids: &[GUID]) -> SecurityResult<Message> {
     // If we have security plugins, use them, otherwise pass through
     if let Some(security_plugins_handle) = &self.security_plugins {
       // Get the source and destination GUIDs
       let source_guid = self.guid();
-      let destination_guid_list: Vec<GUID> = readers
-        .iter()
-        .map(|reader_proxy| reader_proxy.remote_reader_guid)
-        .collect();
+      let destination_guid_list: Vec<GUID> = reader_guids.to_vec();
       // Destructure
       let Message {
         header,
@@ -1420,7 +2080,7 @@ impl Writer {
       // Encode submessages
       SecurityResult::<Vec<Vec<Submessage>>>::from_iter(submessages.iter().map(|submessage| {
         security_plugins_handle
-          .get_plugins()
+          .read_plugins()
           .encode_datawriter_submessage(submessage.clone(), &source_guid, &destination_guid_list)
           // Convert each encoding output to a Vec of 1 or 3 submessages
           .map(Vec::from)
@@ -1439,7 +2099,7 @@ impl Writer {
           .map(|guid| guid.prefix)
           .collect();
         // Encode message
-        security_plugins_handle.get_plugins().encode_message(
+        security_plugins_handle.read_plugins().encode_message(
           message,
           &source_guid_prefix,
           &destination_guid_prefix_list,
@@ -1456,6 +2116,76 @@ impl Writer {
     message: Message,
     readers: &mut dyn Iterator<Item = &RtpsReaderProxy>,
   ) {
+    let readers = readers.collect::<Vec<_>>(); // clone iterator
+    let reader_guids: Vec<GUID> = readers.iter().map(|r| r.remote_reader_guid).collect();
+    let locators = Self::reader_locators(preferred_mode, &readers);
+
+    // What was queued earlier must go out first
+    self.flush_pending_message();
+    self.send_message_to_locators(message, &reader_guids, &locators);
+  }
+
+  // Like send_message_to_readers, but the message may wait to be sent in one
+  // RTPS message together with the following ones to the same Readers, as long
+  // as the combined message fits in MessageAggregation::max_message_size. This
+  // saves datagrams when small samples are written at a high rate. The waiting
+  // submessages are sent when the next message does not fit or goes elsewhere,
+  // when anything is sent with send_message_to_readers, and by
+  // flush_pending_message.
+  fn queue_message_to_readers(
+    &self,
+    preferred_mode: DeliveryMode,
+    message: Message,
+    readers: &mut dyn Iterator<Item = &RtpsReaderProxy>,
+  ) {
+    let readers = readers.collect::<Vec<_>>();
+    let reader_guids: Vec<GUID> = readers.iter().map(|r| r.remote_reader_guid).collect();
+    let locators = Self::reader_locators(preferred_mode, &readers);
+    let max_size = self.message_aggregation.max_message_size;
+
+    let size_if_appended = |pending: &PendingMessage| {
+      pending.size() + RESET_SUBMESSAGES_SIZE + submessages_size(&message.submessages)
+    };
+    let pending = self.pending_message.take();
+    let mut pending = match pending {
+      Some(pending)
+        if pending.reader_guids == reader_guids
+          && pending.locators == locators
+          && size_if_appended(&pending) <= max_size =>
+      {
+        pending
+      }
+      other => {
+        if let Some(other) = other {
+          self.send_pending_message(other);
+        }
+        PendingMessage::new(reader_guids, locators)
+      }
+    };
+    pending.append(message.submessages, self.endianness);
+
+    if pending.size() >= max_size {
+      self.send_pending_message(pending);
+    } else {
+      *self.pending_message.borrow_mut() = Some(pending);
+    }
+  }
+
+  // Sends the submessages queued by queue_message_to_readers, if any
+  fn flush_pending_message(&self) {
+    if let Some(pending) = self.pending_message.take() {
+      self.send_pending_message(pending);
+    }
+  }
+
+  fn send_pending_message(&self, pending: PendingMessage) {
+    let mut message = MessageBuilder::new().add_header_and_build(self.my_guid.prefix);
+    message.submessages = pending.submessages;
+    self.send_message_to_locators(message, &pending.reader_guids, &pending.locators);
+  }
+
+  // The locators to send to in order to reach the readers
+  fn reader_locators(preferred_mode: DeliveryMode, readers: &[&RtpsReaderProxy]) -> Vec<Locator> {
     // TODO: This is a stupid transmit algorithm. We should compute a preferred
     // unicast and multicast locators for each reader only on every reader update,
     // and not find it dynamically on every message.
@@ -1463,60 +2193,71 @@ impl Writer {
     // TODO: In addition to Locators found in Readers, we should observe
     // the Locators given in MEssageReceiverState, i.e. if there was an
     // applicable InfoReply submessage, and we are sending a reply.
+    let mut locators: Vec<Locator> = Vec::new();
 
-    let readers = readers.collect::<Vec<_>>(); // clone iterator
+    macro_rules! add_unless_added {
+      ($locs:expr) => {
+        for loc in $locs.iter() {
+          if locators.contains(loc) {
+            trace!("Already sending to {:?}", loc);
+          } else {
+            locators.push(loc.clone());
+          }
+        }
+      };
+    }
 
+    for reader in readers {
+      match (
+        preferred_mode,
+        reader
+          .unicast_locator_list
+          .iter()
+          .find(|l| Locator::is_udp(l)),
+        reader
+          .multicast_locator_list
+          .iter()
+          .find(|l| Locator::is_udp(l)),
+      ) {
+        (DeliveryMode::Multicast, _, Some(_mc_locator)) => {
+          add_unless_added!(reader.multicast_locator_list);
+        }
+        (DeliveryMode::Unicast, Some(_uc_locator), _) => {
+          add_unless_added!(reader.unicast_locator_list)
+        }
+        (_delivery_mode, _, Some(_mc_locator)) => {
+          add_unless_added!(reader.multicast_locator_list);
+        }
+        (_delivery_mode, Some(_uc_locator), _) => {
+          add_unless_added!(reader.unicast_locator_list)
+        }
+        (_delivery_mode, None, None) => {
+          warn!("send_message_to_readers: No locators for {:?}", reader);
+        }
+      } // match
+    }
+    locators
+  }
+
+  fn send_message_to_locators(
+    &self,
+    message: Message,
+    reader_guids: &[GUID],
+    locators: &[Locator],
+  ) {
     #[cfg(feature = "security")]
-    let encoded = self.security_encode(message, &readers);
+    let encoded = self.security_encode(message, reader_guids);
+    #[cfg(not(feature = "security"))]
+    // Parameter not used
+    let _ = reader_guids;
     #[cfg(not(feature = "security"))]
     let encoded: Result<Message, ()> = Ok(message);
 
     match encoded {
       Ok(message) => {
         let buffer = message.write_to_vec_with_ctx(self.endianness).unwrap();
-        let mut already_sent_to = BTreeSet::new();
-
-        macro_rules! send_unless_sent_and_mark {
-          ($locs:expr) => {
-            for loc in $locs.iter() {
-              if already_sent_to.contains(loc) {
-                trace!("Already sent to {:?}", loc);
-              } else {
-                self.udp_sender.send_to_locator(&buffer, loc);
-                already_sent_to.insert(loc.clone());
-              }
-            }
-          };
-        }
-
-        for reader in readers {
-          match (
-            preferred_mode,
-            reader
-              .unicast_locator_list
-              .iter()
-              .find(|l| Locator::is_udp(l)),
-            reader
-              .multicast_locator_list
-              .iter()
-              .find(|l| Locator::is_udp(l)),
-          ) {
-            (DeliveryMode::Multicast, _, Some(_mc_locator)) => {
-              send_unless_sent_and_mark!(reader.multicast_locator_list);
-            }
-            (DeliveryMode::Unicast, Some(_uc_locator), _) => {
-              send_unless_sent_and_mark!(reader.unicast_locator_list)
-            }
-            (_delivery_mode, _, Some(_mc_locator)) => {
-              send_unless_sent_and_mark!(reader.multicast_locator_list);
-            }
-            (_delivery_mode, Some(_uc_locator), _) => {
-              send_unless_sent_and_mark!(reader.unicast_locator_list)
-            }
-            (_delivery_mode, None, None) => {
-              warn!("send_message_to_readers: No locators for {:?}", reader);
-            }
-          } // match
+        for loc in locators {
+          self.udp_sender.send_to_locator(&buffer, loc);
         }
       }
       Err(e) => error!("Failed to send message to readers. Encoding failed: {e:?}"),
@@ -1545,10 +2286,10 @@ impl Writer {
     requested_qos: &QosPolicies,
   ) {
     debug!("update_reader_proxy topic={:?}", self.my_topic_name);
-    match self.qos_policies.compliance_failure_wrt(requested_qos) {
+    match self.qos_policies.matched_contract(requested_qos, true) {
       // matched QoS
-      None => {
-        let change = self.matched_reader_update(reader_proxy);
+      Ok(contract) => {
+        let change = self.matched_reader_update(reader_proxy, contract);
         if change > 0 {
           self.matched_readers_count_total += change;
           self.send_status(DataWriterStatus::PublicationMatched {
@@ -1570,7 +2311,7 @@ impl Writer {
           debug!("Reader details: {:?}", &reader_proxy);
         }
       }
-      Some(bad_policy_id) => {
+      Err(bad_policy_id) => {
         // QoS not compliant :(
         warn!(
           "update_reader_proxy - QoS mismatch {:?} topic={:?}",
@@ -1598,15 +2339,20 @@ impl Writer {
         });
       }
     } // match
+    self.check_invariants();
   }
 
   // Update the given reader proxy. Preserve data we are tracking.
   // return 0 if the reader already existed
   // return 1 if it was new ( = count of added reader proxies)
-  fn matched_reader_update(&mut self, updated_reader_proxy: &RtpsReaderProxy) -> i32 {
+  fn matched_reader_update(
+    &mut self,
+    updated_reader_proxy: &RtpsReaderProxy,
+    contract: MatchedContract,
+  ) -> i32 {
     let mut new = 0;
     let is_volatile = self.qos().is_volatile(); // Get this in advance to work with the borrow checker
-    self
+    let reader_proxy = self
       .readers
       .entry(updated_reader_proxy.remote_reader_guid)
       .and_modify(|rp| rp.update(updated_reader_proxy))
@@ -1621,6 +2367,7 @@ impl Writer {
         }
         new_proxy
       });
+    reader_proxy.contract = Some(contract);
     new
   }
 
@@ -1661,6 +2408,36 @@ impl Writer {
     }
     // also remember to remove reader from ack_waiter
     self.update_ack_waiters(guid, None);
+    self.check_invariants();
+  }
+
+  /// Checks that the history and the proxies of the matched readers are
+  /// consistent. Returns a description of each violation found.
+  pub fn verify_invariants(&self) -> Vec<String> {
+    let mut violations = self.history_buffer.verify_invariants();
+    let last_seq = self.history_buffer.last_change_sequence_number();
+    for (guid, reader_proxy) in &self.readers {
+      if reader_proxy.remote_reader_guid != *guid {
+        violations.push(format!(
+          "The proxy of reader {:?} is stored as {guid:?}",
+          reader_proxy.remote_reader_guid
+        ));
+      }
+      if reader_proxy.contract.is_none() {
+        violations.push(format!("Matched reader {guid:?} has no QoS contract"));
+      }
+      violations.extend(
+        reader_proxy
+          .verify_invariants(last_seq)
+          .into_iter()
+          .map(|violation| format!("Reader {guid:?}: {violation}")),
+      );
+    }
+    violations
+  }
+
+  fn check_invariants(&self) {
+    invariants::check_after_mutation("Writer", || self.verify_invariants());
   }
 
   // Entire remote participant was lost.
@@ -1720,16 +2497,28 @@ mod tests {
   use std::thread;
 
   use byteorder::LittleEndian;
+  use enumflags2::BitFlags;
   use log::info;
 
   use crate::{
     dds::{
-      participant::DomainParticipant, qos::QosPolicies, topic::TopicKind,
-      with_key::datawriter::DataWriter,
+      participant::DomainParticipant,
+      qos::QosPolicies,
+      statusevents::sync_status_channel,
+      topic::TopicKind,
+      with_key::datawriter::{DataWriter, WriteOptionsBuilder},
+    },
+    messages::submessages::{
+      elements::serialized_payload::SerializedPayload,
+      submessages::{AckNack, HEARTBEAT_Flags, InterpreterSubmessage, WriterSubmessage},
     },
+    rtps::SubmessageBody,
     serialization::CDRSerializerAdapter,
+    structure::{cache_change::ChangeKind, guid::EntityKind, sequence_number::SequenceNumberSet},
     test::random_data::*,
+    QosPolicyBuilder, RepresentationIdentifier,
   };
+  use super::*;
 
   #[test]
   fn test_writer_receives_datawriter_cache_change_notifications() {
@@ -1781,4 +2570,576 @@ mod tests {
     thread::sleep(std::time::Duration::from_millis(100));
     info!("writerResult:  {:?}", write_result);
   }
+
+  #[test]
+  fn history_buffer_retains_latest_dispose_of_each_instance() {
+    let guid = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let payload = |key: u8| SerializedPayload::new(RepresentationIdentifier::CDR_LE, vec![key]);
+    let changes = [
+      DDSData::new(payload(1)),
+      DDSData::new_disposed_by_key(ChangeKind::NotAliveDisposed, payload(1)),
+      DDSData::new(payload(2)),
+      DDSData::new_disposed_by_key(ChangeKind::NotAliveDisposed, payload(2)),
+      DDSData::new_disposed_by_key(ChangeKind::NotAliveUnregistered, payload(2)),
+      DDSData::new(payload(3)),
+    ];
+
+    let mut history = HistoryBuffer::new("test".to_string(), Some(usize::MAX));
+    for (i, data) in changes.into_iter().enumerate() {
+      let sn = SequenceNumber::new(i as i64 + 1);
+      history.add_change(
+        Timestamp::from_ticks(i as u64 + 1),
+        CacheChange::new(guid, sn, WriteOptions::default(), data),
+      );
+    }
+    history.remove_changes_before(SequenceNumber::new(6));
+
+    let retained: Vec<SequenceNumber> =
+      history.sequence_number_to_instant.keys().copied().collect();
+    assert_eq!(
+      retained,
+      vec![
+        SequenceNumber::new(2),
+        SequenceNumber::new(5),
+        SequenceNumber::new(6)
+      ]
+    );
+    assert_eq!(
+      history.first_change_sequence_number(),
+      SequenceNumber::new(2)
+    );
+    assert!(history.was_removed(SequenceNumber::new(3)));
+    assert!(!history.was_removed(SequenceNumber::new(5)));
+
+    // Removing again does not lose the retained changes
+    history.remove_changes_before(SequenceNumber::new(6));
+    assert_eq!(history.history_buffer.len(), 3);
+
+    let mut volatile_history = HistoryBuffer::new("test".to_string(), None);
+    volatile_history.add_change(
+      Timestamp::from_ticks(1),
+      CacheChange::new(
+        guid,
+        SequenceNumber::new(1),
+        WriteOptions::default(),
+        DDSData::new_disposed_by_key(ChangeKind::NotAliveDisposed, payload(1)),
+      ),
+    );
+    volatile_history.add_change(
+      Timestamp::from_ticks(2),
+      CacheChange::new(
+        guid,
+        SequenceNumber::new(2),
+        WriteOptions::default(),
+        DDSData::new(payload(2)),
+      ),
+    );
+    volatile_history.remove_changes_before(SequenceNumber::new(2));
+    assert_eq!(volatile_history.history_buffer.len(), 1);
+  }
+
+  #[test]
+  fn history_buffer_verify_invariants_detects_corruption() {
+    let guid = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let history_with_changes = || {
+      let mut history = HistoryBuffer::new("test".to_string(), None);
+      for i in 1..=3 {
+        history.add_change(
+          Timestamp::from_ticks(i),
+          CacheChange::new(
+            guid,
+            SequenceNumber::new(i as i64),
+            WriteOptions::default(),
+            DDSData::new(SerializedPayload::new(
+              RepresentationIdentifier::CDR_LE,
+              vec![0; 4],
+            )),
+          ),
+        );
+      }
+      assert_eq!(history.verify_invariants(), Vec::<String>::new());
+      history
+    };
+    let assert_violation = |history: &HistoryBuffer, expected: &str| {
+      let violations = history.verify_invariants();
+      assert!(
+        violations.iter().any(|v| v.contains(expected)),
+        "expected a violation containing {expected:?}, got {violations:?}"
+      );
+    };
+
+    let mut history = history_with_changes();
+    history.history_buffer.remove(&Timestamp::from_ticks(2));
+    assert_violation(&history, "no change stored there");
+
+    let mut history = history_with_changes();
+    history
+      .sequence_number_to_instant
+      .insert(SequenceNumber::new(2), Timestamp::from_ticks(3));
+    assert_violation(&history, "which has SequenceNumber");
+
+    let mut history = history_with_changes();
+    history
+      .sequence_number_to_instant
+      .remove(&SequenceNumber::new(3));
+    assert_violation(&history, "but only 2 are indexed");
+
+    let mut history = history_with_changes();
+    history.last_seq = SequenceNumber::new(2);
+    assert_violation(&history, "outside of the history bounds");
+
+    let mut history = history_with_changes();
+    history.kept_from = SequenceNumber::new(2);
+    assert_violation(&history, "not a retained dispose or unregister");
+
+    let mut history = history_with_changes();
+    history
+      .instance_state_changes
+      .insert(SequenceNumber::new(4), Bytes::new());
+    assert_violation(&history, "tracked, but not in the history");
+  }
+
+  #[test]
+  fn reader_proxy_verify_invariants_detects_corruption() {
+    let mut reader_proxy = RtpsReaderProxy::new(
+      GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED),
+      QosPolicies::qos_none(),
+      false,
+    );
+    reader_proxy.notify_new_cache_change(SequenceNumber::new(1));
+    assert_eq!(
+      reader_proxy.verify_invariants(SequenceNumber::new(1)),
+      Vec::<String>::new()
+    );
+    // A change that the Writer does not have
+    reader_proxy.notify_new_cache_change(SequenceNumber::new(2));
+    let violations = reader_proxy.verify_invariants(SequenceNumber::new(1));
+    assert_eq!(violations.len(), 1, "{violations:?}");
+    assert!(violations[0].contains("unsent changes"));
+
+    reader_proxy.mark_change_sent(SequenceNumber::new(2));
+    reader_proxy.insert_pending_gap(SequenceNumber::new(0));
+    let violations = reader_proxy.verify_invariants(SequenceNumber::new(1));
+    assert!(violations[0].contains("pending GAP"), "{violations:?}");
+
+    reader_proxy.all_acked_before = SequenceNumber::new(-1);
+    let violations = reader_proxy.verify_invariants(SequenceNumber::new(1));
+    assert!(violations[0].contains("negative"), "{violations:?}");
+  }
+
+  // A reliable Writer with one matched remote Reader, which is played by the
+  // returned socket.
+  fn reliable_writer_with_reader() -> (
+    Writer,
+    mio_channel::SyncSender<WriterCommand>,
+    GUID,
+    std::net::UdpSocket,
+  ) {
+    let qos = QosPolicyBuilder::new()
+      .reliable(Duration::from_millis(100))
+      .build();
+    let (writer_command_sender, writer_command_receiver) = mio_channel::sync_channel(4);
+    let (status_sender, _status_receiver) = sync_status_channel(4).unwrap();
+    let (participant_status_sender, _participant_status_receiver) =
+      sync_status_channel(16).unwrap();
+    let mut writer = Writer::new(
+      WriterIngredients {
+        guid: GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED),
+        writer_command_receiver,
+        writer_command_receiver_waker: Arc::new(Mutex::new(None)),
+        topic_name: "test".to_string(),
+        like_stateless: false,
+        qos_policies: qos.clone(),
+        status_sender,
+        security_plugins: None,
+        fragment_size: 1024,
+        message_aggregation: MessageAggregation::default(),
+      },
+      Rc::new(UDPSender::new_with_random_port().unwrap()),
+      mio_extras::timer::Builder::default().build(),
+      participant_status_sender,
+    );
+
+    let reader_socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    reader_socket
+      .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+      .unwrap();
+    let reader_guid = GUID::new(
+      GuidPrefix::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+      EntityId::create_custom_entity_id([0, 0, 1], EntityKind::READER_NO_KEY_USER_DEFINED),
+    );
+    let mut reader_proxy = RtpsReaderProxy::new(reader_guid, qos.clone(), false);
+    reader_proxy.unicast_locator_list = vec![Locator::from(reader_socket.local_addr().unwrap())];
+    writer.update_reader_proxy(&reader_proxy, &qos);
+
+    (writer, writer_command_sender, reader_guid, reader_socket)
+  }
+
+  fn write_sample(
+    writer: &mut Writer,
+    writer_command_sender: &mio_channel::SyncSender<WriterCommand>,
+    sn: i64,
+  ) {
+    write_sample_with_options(writer, writer_command_sender, sn, WriteOptions::default());
+  }
+
+  fn write_sample_with_options(
+    writer: &mut Writer,
+    writer_command_sender: &mio_channel::SyncSender<WriterCommand>,
+    sn: i64,
+    write_options: WriteOptions,
+  ) {
+    writer_command_sender
+      .send(WriterCommand::DDSData {
+        ddsdata: DDSData::new(SerializedPayload::new(
+          RepresentationIdentifier::CDR_LE,
+          vec![1, 2, 3, 4],
+        )),
+        write_options,
+        sequence_number: SequenceNumber::new(sn),
+      })
+      .unwrap();
+    writer.process_writer_command();
+  }
+
+  // The HEARTBEATs that reached the Reader socket, with the destination given
+  // by a preceding INFO_DST, if any, and the flags other than Endianness
+  fn received_heartbeats(
+    reader_socket: &std::net::UdpSocket,
+  ) -> Vec<(Option<GuidPrefix>, BitFlags<HEARTBEAT_Flags>)> {
+    let mut heartbeats = Vec::new();
+    let mut buf = [0; 1500];
+    while let Ok(len) = reader_socket.recv(&mut buf) {
+      let message = Message::read_from_buffer(&Bytes::copy_from_slice(&buf[..len])).unwrap();
+      let mut info_dst = None;
+      for submessage in message.submessages {
+        match submessage.body {
+          SubmessageBody::Interpreter(InterpreterSubmessage::InfoDestination(dst, _)) => {
+            info_dst = Some(dst.guid_prefix);
+          }
+          SubmessageBody::Writer(WriterSubmessage::Heartbeat(_, flags)) => {
+            heartbeats.push((info_dst, flags & !HEARTBEAT_Flags::Endianness));
+          }
+          _ => (),
+        }
+      }
+    }
+    heartbeats
+  }
+
+  // The sequence numbers that reached the Reader socket as DATA, and those
+  // declared irrelevant by GAPs
+  fn received_data_and_gaps(
+    reader_socket: &std::net::UdpSocket,
+  ) -> (Vec<SequenceNumber>, BTreeSet<SequenceNumber>) {
+    let mut data = Vec::new();
+    let mut irrelevant = BTreeSet::new();
+    let mut buf = [0; 1500];
+    while let Ok(len) = reader_socket.recv(&mut buf) {
+      let message = Message::read_from_buffer(&Bytes::copy_from_slice(&buf[..len])).unwrap();
+      for submessage in message.submessages {
+        match submessage.body {
+          SubmessageBody::Writer(WriterSubmessage::Data(d, _)) => data.push(d.writer_sn),
+          SubmessageBody::Writer(WriterSubmessage::Gap(gap, _)) => {
+            let mut sn = gap.gap_start;
+            while sn < gap.gap_list.base() {
+              irrelevant.insert(sn);
+              sn = sn.plus_1();
+            }
+            irrelevant.extend(gap.gap_list.iter());
+          }
+          _ => (),
+        }
+      }
+    }
+    (data, irrelevant)
+  }
+
+  // The messages that reached the Reader socket, with their sizes in bytes
+  fn received_messages(reader_socket: &std::net::UdpSocket) -> Vec<(usize, Message)> {
+    let mut messages = Vec::new();
+    let mut buf = [0; 65536];
+    while let Ok(len) = reader_socket.recv(&mut buf) {
+      let message = Message::read_from_buffer(&Bytes::copy_from_slice(&buf[..len])).unwrap();
+      messages.push((len, message));
+    }
+    messages
+  }
+
+  // The sequence numbers of the DATA submessages in a message, with the source
+  // timestamps in effect for them
+  fn data_with_timestamps(message: &Message) -> Vec<(SequenceNumber, Option<Timestamp>)> {
+    let mut source_timestamp = None;
+    let mut data = Vec::new();
+    for submessage in &message.submessages {
+      match &submessage.body {
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(ts, _)) => {
+          source_timestamp = ts.timestamp;
+        }
+        SubmessageBody::Writer(WriterSubmessage::Data(d, _)) => {
+          data.push((d.writer_sn, source_timestamp));
+        }
+        _ => (),
+      }
+    }
+    data
+  }
+
+  fn acknack(writer: &Writer, reader_guid: GUID, base: i64, count: i32) -> AckSubmessage {
+    AckSubmessage::AckNack(AckNack {
+      reader_id: reader_guid.entity_id,
+      writer_id: writer.entity_id(),
+      reader_sn_state: SequenceNumberSet::new_empty(SequenceNumber::new(base)),
+      count,
+    })
+  }
+
+  #[test]
+  fn heartbeat_tick_sets_final_only_for_liveliness_assertions() {
+    let (mut writer, writer_command_sender, reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    let liveliness: BitFlags<HEARTBEAT_Flags> = HEARTBEAT_Flags::Liveliness.into();
+    let final_and_liveliness = HEARTBEAT_Flags::Final | HEARTBEAT_Flags::Liveliness;
+
+    write_sample(&mut writer, &writer_command_sender, 1);
+    received_heartbeats(&reader_socket);
+
+    // Not acknowledged yet, so the Reader must respond
+    writer.handle_heartbeat_tick(false);
+    writer.handle_heartbeat_tick(true);
+    assert_eq!(
+      received_heartbeats(&reader_socket),
+      [(None, BitFlags::empty()), (None, liveliness)]
+    );
+
+    // After acknowledgement there is nothing to tell, unless asserting liveliness
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 2, 1));
+    writer.handle_heartbeat_tick(false);
+    assert!(received_heartbeats(&reader_socket).is_empty());
+    writer.handle_heartbeat_tick(true);
+    assert_eq!(
+      received_heartbeats(&reader_socket),
+      [(None, final_and_liveliness)]
+    );
+  }
+
+  #[test]
+  fn preemptive_acknack_is_answered_with_heartbeat() {
+    let (mut writer, writer_command_sender, reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+
+    // Nothing written, so nothing to announce
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 1, 1));
+    assert!(received_heartbeats(&reader_socket).is_empty());
+
+    write_sample(&mut writer, &writer_command_sender, 1);
+    received_heartbeats(&reader_socket);
+
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 1, 2));
+    assert_eq!(
+      received_heartbeats(&reader_socket),
+      [(Some(reader_guid.prefix), BitFlags::empty())]
+    );
+
+    // An ACKNACK that acknowledges the sample needs no answer
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 2, 3));
+    assert!(received_heartbeats(&reader_socket).is_empty());
+  }
+
+  #[test]
+  fn repair_gaps_all_irrelevant_changes_at_once() {
+    let (mut writer, writer_command_sender, reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    let other_reader = GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED);
+
+    // Every other sample is meant for another Reader only. The irrelevant
+    // sequence numbers span more than one GAP can list.
+    write_sample(&mut writer, &writer_command_sender, 1);
+    for sn in 2..=600 {
+      let write_options = if sn % 2 == 0 {
+        WriteOptionsBuilder::new()
+          .to_single_reader(other_reader)
+          .build()
+      } else {
+        WriteOptions::default()
+      };
+      write_sample_with_options(&mut writer, &writer_command_sender, sn, write_options);
+    }
+    received_data_and_gaps(&reader_socket);
+
+    // The Reader has received nothing
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 1, 1));
+    writer.handle_repair_data_send(reader_guid);
+
+    let (data, irrelevant) = received_data_and_gaps(&reader_socket);
+    assert_eq!(data, [SequenceNumber::new(1)]);
+    let expected: BTreeSet<SequenceNumber> =
+      (2..=600).step_by(2).map(SequenceNumber::new).collect();
+    assert_eq!(irrelevant, expected);
+    let still_unsent: Vec<SequenceNumber> = (3..600).step_by(2).map(SequenceNumber::new).collect();
+    assert_eq!(
+      writer.readers[&reader_guid].unsent_changes_debug(),
+      still_unsent
+    );
+  }
+
+  #[test]
+  fn repair_gaps_changes_removed_from_history() {
+    let (mut writer, writer_command_sender, reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    for sn in 1..=5 {
+      write_sample(&mut writer, &writer_command_sender, sn);
+    }
+    writer
+      .history_buffer
+      .remove_changes_before(SequenceNumber::new(4));
+    received_data_and_gaps(&reader_socket);
+
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 1, 1));
+    writer.handle_repair_data_send(reader_guid);
+
+    let (data, irrelevant) = received_data_and_gaps(&reader_socket);
+    assert!(data.is_empty(), "{data:?}");
+    assert_eq!(
+      irrelevant,
+      (1..=3).map(SequenceNumber::new).collect::<BTreeSet<_>>()
+    );
+    assert_eq!(
+      writer.readers[&reader_guid].unsent_changes_debug(),
+      [SequenceNumber::new(4), SequenceNumber::new(5)]
+    );
+  }
+
+  #[test]
+  fn repair_continues_with_infinite_deadline() {
+    let (mut writer, writer_command_sender, reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    // As in the builtin endpoints
+    writer.qos_policies = writer.qos_policies.modify_by(
+      &QosPolicyBuilder::new()
+        .deadline(policy::Deadline(Duration::INFINITE))
+        .build(),
+    );
+    for sn in 1..=3 {
+      write_sample(&mut writer, &writer_command_sender, sn);
+    }
+    received_data_and_gaps(&reader_socket);
+
+    // One change is repaired at a time, so the rest need the repair timer
+    writer.handle_ack_nack(reader_guid.prefix, &acknack(&writer, reader_guid, 1, 1));
+    let mut repaired = Vec::new();
+    for _ in 0..3 {
+      thread::sleep(std::time::Duration::from_millis(300));
+      writer.handle_timed_event();
+      repaired.extend(received_data_and_gaps(&reader_socket).0);
+    }
+    assert_eq!(
+      repaired,
+      (1..=3).map(SequenceNumber::new).collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn small_samples_are_aggregated_up_to_max_message_size() {
+    let (mut writer, writer_command_sender, _reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    // Without aggregation every sample needs a datagram
+    writer.message_aggregation.max_message_size = 0;
+    for sn in 1..=100 {
+      write_sample(&mut writer, &writer_command_sender, sn);
+    }
+    assert_eq!(received_messages(&reader_socket).len(), 100);
+
+    // Hold the messages back until they are full
+    writer.message_aggregation = MessageAggregation {
+      flush_delay: std::time::Duration::from_secs(60),
+      ..MessageAggregation::default()
+    };
+    let mut messages = Vec::new();
+    for sn in 101..=10_100 {
+      write_sample(&mut writer, &writer_command_sender, sn);
+      if sn % 2000 == 0 {
+        messages.extend(received_messages(&reader_socket));
+      }
+    }
+    writer_command_sender.send(WriterCommand::Flush).unwrap();
+    writer.process_writer_command();
+    messages.extend(received_messages(&reader_socket));
+
+    let data: Vec<SequenceNumber> = messages
+      .iter()
+      .flat_map(|(_len, message)| data_with_timestamps(message))
+      .map(|(sn, _ts)| sn)
+      .collect();
+    let expected: Vec<SequenceNumber> = (101..=10_100).map(SequenceNumber::new).collect();
+    assert_eq!(data, expected);
+    assert!(messages.len() < 400, "{} messages", messages.len());
+
+    // Each message has the HEARTBEAT of its last DATA only
+    for (len, message) in &messages {
+      assert!(*len <= usize::from(DEFAULT_MAX_MESSAGE_SIZE), "{len}");
+      let heartbeats = message.submessages.iter().filter(|s| is_heartbeat(s));
+      assert_eq!(heartbeats.count(), 1);
+      assert!(is_heartbeat(message.submessages.last().unwrap()));
+    }
+  }
+
+  #[test]
+  fn aggregated_samples_keep_their_source_timestamps() {
+    let (mut writer, writer_command_sender, _reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    writer.message_aggregation.flush_delay = std::time::Duration::from_secs(60);
+    let timestamp = Timestamp::now();
+    let with_timestamp = || {
+      WriteOptionsBuilder::new()
+        .source_timestamp(timestamp)
+        .build()
+    };
+
+    write_sample_with_options(&mut writer, &writer_command_sender, 1, with_timestamp());
+    write_sample(&mut writer, &writer_command_sender, 2);
+    write_sample_with_options(&mut writer, &writer_command_sender, 3, with_timestamp());
+    write_sample_with_options(&mut writer, &writer_command_sender, 4, with_timestamp());
+    writer.flush_pending_message();
+
+    let messages = received_messages(&reader_socket);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+      data_with_timestamps(&messages[0].1),
+      [
+        (SequenceNumber::new(1), Some(timestamp)),
+        (SequenceNumber::new(2), None),
+        (SequenceNumber::new(3), Some(timestamp)),
+        (SequenceNumber::new(4), Some(timestamp)),
+      ]
+    );
+  }
+
+  #[test]
+  fn pending_message_is_sent_by_timers() {
+    let (mut writer, writer_command_sender, _reader_guid, reader_socket) =
+      reliable_writer_with_reader();
+    writer.message_aggregation.flush_delay = std::time::Duration::from_millis(10);
+
+    write_sample(&mut writer, &writer_command_sender, 1);
+    write_sample(&mut writer, &writer_command_sender, 2);
+    assert!(received_messages(&reader_socket).is_empty());
+    thread::sleep(std::time::Duration::from_millis(300));
+    writer.handle_timed_event();
+    let messages = received_messages(&reader_socket);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(data_with_timestamps(&messages[0].1).len(), 2);
+
+    // The heartbeat goes after the pending DATA
+    write_sample(&mut writer, &writer_command_sender, 3);
+    writer.handle_heartbeat_tick(false);
+    let messages = received_messages(&reader_socket);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(
+      data_with_timestamps(&messages[0].1),
+      [(SequenceNumber::new(3), None)]
+    );
+    assert!(data_with_timestamps(&messages[1].1).is_empty());
+    assert!(messages[1].1.submessages.iter().any(is_heartbeat));
+  }
 }
@@ -3,6 +3,7 @@ pub mod authentication;
 mod certificate;
 pub mod config;
 pub mod cryptographic;
+pub mod entropy;
 pub mod logging;
 mod private_key;
 pub mod security_plugins;
@@ -1,18 +1,21 @@
-use std::{collections::HashMap, ops::Not};
+use std::{collections::HashMap, ops::Not, sync::Arc};
 
 use bytes::Bytes;
-use chrono::Utc;
+use chrono::Duration;
 
 use crate::{
   create_security_error_and_log,
+  dds::qos::policy::Partition,
   rtps::constant::builtin_topic_names,
   security::{
     authentication::IdentityHandle,
     certificate::{Certificate, DistinguishedName},
     SecurityError, SecurityResult,
   },
+  structure::clock,
 };
 use self::{
+  document_cache::DocumentCache,
   domain_governance_document::{DomainRule, TopicRule},
   domain_participant_permissions_document::{Action, DomainParticipantPermissions, Grant},
   types::Entity,
@@ -20,8 +23,10 @@ use self::{
 use super::{AccessControl, PermissionsHandle};
 
 //mod config_error; --> crate::security::config
+pub mod document_cache;
 mod domain_governance_document;
 mod domain_participant_permissions_document;
+mod fnmatch;
 //mod permissions_ca_certificate; --> crate::security::certificate
 pub mod s_mime_config_parser;
 
@@ -30,16 +35,32 @@ mod participant_access_control;
 mod remote_entity_access_control;
 pub(in crate::security) mod types;
 
+// The partition names of a DataWriter or DataReader for matching against the
+// partitions criteria of its grant. No Partition QoS means the default
+// partition, and so does an empty list.
+fn partition_names(partition: &Option<Partition>) -> Vec<&str> {
+  partition
+    .iter()
+    .flat_map(|partition| partition.names.iter())
+    .map(String::as_str)
+    .collect()
+}
+
 // A struct implementing the builtin Access control plugin
 // See sections 8.4 and 9.4 of the Security specification (v. 1.1)
 pub struct AccessControlBuiltin {
   domain_participant_permissions:
-    HashMap<PermissionsHandle, (DistinguishedName, DomainParticipantPermissions)>,
+    HashMap<PermissionsHandle, (DistinguishedName, Arc<DomainParticipantPermissions>)>,
   signed_permissions_documents: HashMap<PermissionsHandle, Bytes>,
   domain_rules: HashMap<PermissionsHandle, DomainRule>,
   permissions_ca_certificates: HashMap<PermissionsHandle, Certificate>,
   identity_to_permissions: HashMap<IdentityHandle, PermissionsHandle>,
   permissions_handle_counter: u32,
+  // How far outside of its validity period a grant is still accepted
+  clock_skew_tolerance: Duration,
+  document_cache: Arc<DocumentCache>,
+  // Whether verified and parsed documents are taken from document_cache
+  use_document_cache: bool,
 }
 
 impl AccessControl for AccessControlBuiltin {}
@@ -53,9 +74,19 @@ impl AccessControlBuiltin {
       permissions_ca_certificates: HashMap::new(),
       identity_to_permissions: HashMap::new(),
       permissions_handle_counter: 0,
+      clock_skew_tolerance: Duration::zero(),
+      document_cache: DocumentCache::global(),
+      use_document_cache: true,
     }
   }
 
+  // Use the given cache instead of the process-wide one
+  #[cfg(test)]
+  pub fn with_document_cache(mut self, document_cache: Arc<DocumentCache>) -> Self {
+    self.document_cache = document_cache;
+    self
+  }
+
   fn generate_permissions_handle(&mut self) -> PermissionsHandle {
     self.permissions_handle_counter += 1;
     self.permissions_handle_counter
@@ -73,7 +104,7 @@ impl AccessControlBuiltin {
   fn get_permissions_document(
     &self,
     permissions_handle: &PermissionsHandle,
-  ) -> SecurityResult<&(DistinguishedName, DomainParticipantPermissions)> {
+  ) -> SecurityResult<&(DistinguishedName, Arc<DomainParticipantPermissions>)> {
     self
       .domain_participant_permissions
       .get(permissions_handle)
@@ -89,7 +120,11 @@ impl AccessControlBuiltin {
     self.get_permissions_document(permissions_handle).and_then(
       |(subject_name, permissions_document)| {
         permissions_document
-          .find_grant(subject_name, &Utc::now())
+          .find_grant(
+            subject_name,
+            &clock::wall_now().to_utc(),
+            self.clock_skew_tolerance,
+          )
           .ok_or_else(|| {
             create_security_error_and_log!(
               "Could not find a valid grant for the PermissionsHandle {}",
@@ -145,13 +180,14 @@ impl AccessControlBuiltin {
       })
   }
 
-  // check_create_ and check_remote_ methods are very similar
+  // check_create_ and check_remote_ methods are very similar. partitions is
+  // None for Topics.
   fn check_entity(
     &self,
     permissions_handle: PermissionsHandle,
     domain_id: u16,
     topic_name: &str,
-    partitions: &[&str],
+    partitions: Option<&[&str]>,
     data_tags: &[(&str, &str)],
     entity_kind: &Entity,
   ) -> SecurityResult<bool> {
@@ -221,3 +257,44 @@ impl AccessControlBuiltin {
     }
   }
 }
+
+// The example permissions document with its grants expiring at the given
+// instant, signed with the example permissions CA as
+// examples/security_configuration_files/sign-test-configurations.sh signs it
+#[cfg(test)]
+pub(crate) fn example_permissions_expiring_at(not_after: chrono::DateTime<chrono::Utc>) -> Vec<u8> {
+  use openssl::{
+    pkcs7::{Pkcs7, Pkcs7Flags},
+    pkey::PKey,
+    stack::Stack,
+    x509::X509,
+  };
+
+  let read =
+    |name: &str| std::fs::read(format!("examples/security_configuration_files/{name}")).unwrap();
+  let document = String::from_utf8(read("permissions_unsigned.xml"))
+    .unwrap()
+    .replace(
+      "<not_after>9999-01-01T00:00:00</not_after>",
+      &format!(
+        "<not_after>{}</not_after>",
+        not_after.format("%Y-%m-%dT%H:%M:%S")
+      ),
+    );
+  let certificate = X509::from_pem(&read("permissions_ca.cert.pem")).unwrap();
+  let private_key = PKey::private_key_from_pem_passphrase(
+    &read("permissions_ca_private_key.pem"),
+    &read("password"),
+  )
+  .unwrap();
+  let flags = Pkcs7Flags::TEXT | Pkcs7Flags::DETACHED;
+  Pkcs7::sign(
+    &certificate,
+    &private_key,
+    &Stack::new().unwrap(),
+    document.as_bytes(),
+    flags,
+  )
+  .and_then(|signed| signed.to_smime(document.as_bytes(), flags))
+  .unwrap()
+}
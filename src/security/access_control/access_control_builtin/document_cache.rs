@@ -0,0 +1,313 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, OnceLock,
+  },
+};
+
+use crate::{
+  create_security_error_and_log,
+  security::{authentication::Sha256, certificate::Certificate, SecurityError, SecurityResult},
+};
+use super::{
+  domain_governance_document::DomainGovernanceDocument,
+  domain_participant_permissions_document::DomainParticipantPermissions,
+};
+
+// How many documents of each kind the process-wide cache holds
+const GLOBAL_CAPACITY: usize = 16;
+
+// Whether a verified and parsed document can be reused depends on both the
+// signed document and the permissions CA that verified it
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+  document: Sha256,
+  permissions_ca: Sha256,
+}
+
+impl CacheKey {
+  fn new(signed_document: &[u8], permissions_ca_certificate: &Certificate) -> Self {
+    Self {
+      document: Sha256::hash(signed_document),
+      permissions_ca: Sha256::hash(permissions_ca_certificate.to_pem().as_bytes()),
+    }
+  }
+}
+
+// Holds at most `capacity` entries, evicting the oldest first
+struct CacheMap<T> {
+  entries: HashMap<CacheKey, Arc<T>>,
+  insertion_order: VecDeque<CacheKey>,
+}
+
+impl<T> CacheMap<T> {
+  fn new() -> Self {
+    Self {
+      entries: HashMap::new(),
+      insertion_order: VecDeque::new(),
+    }
+  }
+
+  fn insert(&mut self, key: CacheKey, value: Arc<T>, capacity: usize) {
+    while self.entries.len() >= capacity {
+      match self.insertion_order.pop_front() {
+        Some(oldest) => self.entries.remove(&oldest),
+        None => break,
+      };
+    }
+    if capacity > 0 {
+      self.insertion_order.push_back(key.clone());
+      self.entries.insert(key, value);
+    }
+  }
+}
+
+// Verified and parsed domain governance and permissions documents, shared
+// between the access control plugins of all participants that use the same
+// documents, so that each document is verified and parsed only once.
+pub struct DocumentCache {
+  capacity: usize,
+  governance_documents: Mutex<CacheMap<DomainGovernanceDocument>>,
+  permissions_documents: Mutex<CacheMap<DomainParticipantPermissions>>,
+  // Number of times a document has been verified and parsed
+  parse_count: AtomicUsize,
+}
+
+impl DocumentCache {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      governance_documents: Mutex::new(CacheMap::new()),
+      permissions_documents: Mutex::new(CacheMap::new()),
+      parse_count: AtomicUsize::new(0),
+    }
+  }
+
+  // The cache shared by all AccessControlBuiltin instances in the process,
+  // unless they were given their own
+  pub fn global() -> Arc<Self> {
+    static GLOBAL: OnceLock<Arc<DocumentCache>> = OnceLock::new();
+    GLOBAL
+      .get_or_init(|| Arc::new(DocumentCache::new(GLOBAL_CAPACITY)))
+      .clone()
+  }
+
+  #[cfg(test)]
+  pub fn parse_count(&self) -> usize {
+    self.parse_count.load(Ordering::Relaxed)
+  }
+
+  // Returns the governance document cached for this signed document and
+  // permissions CA, or calls `verify_and_parse` to produce it. Errors are not
+  // cached. If `use_cache` is false, the cache is neither read nor updated.
+  pub(super) fn governance_document(
+    &self,
+    signed_document: &[u8],
+    permissions_ca_certificate: &Certificate,
+    use_cache: bool,
+    verify_and_parse: impl FnOnce() -> SecurityResult<DomainGovernanceDocument>,
+  ) -> SecurityResult<Arc<DomainGovernanceDocument>> {
+    self.get_or_parse(
+      &self.governance_documents,
+      signed_document,
+      permissions_ca_certificate,
+      use_cache,
+      verify_and_parse,
+    )
+  }
+
+  // As governance_document, but for permissions documents
+  pub(super) fn permissions_document(
+    &self,
+    signed_document: &[u8],
+    permissions_ca_certificate: &Certificate,
+    use_cache: bool,
+    verify_and_parse: impl FnOnce() -> SecurityResult<DomainParticipantPermissions>,
+  ) -> SecurityResult<Arc<DomainParticipantPermissions>> {
+    self.get_or_parse(
+      &self.permissions_documents,
+      signed_document,
+      permissions_ca_certificate,
+      use_cache,
+      verify_and_parse,
+    )
+  }
+
+  fn get_or_parse<T>(
+    &self,
+    cache_map: &Mutex<CacheMap<T>>,
+    signed_document: &[u8],
+    permissions_ca_certificate: &Certificate,
+    use_cache: bool,
+    verify_and_parse: impl FnOnce() -> SecurityResult<T>,
+  ) -> SecurityResult<Arc<T>> {
+    if !use_cache {
+      self.parse_count.fetch_add(1, Ordering::Relaxed);
+      return verify_and_parse().map(Arc::new);
+    }
+
+    let key = CacheKey::new(signed_document, permissions_ca_certificate);
+    // The lock is held while parsing, so that participants created
+    // concurrently do not parse the same document more than once
+    let mut cache_map = cache_map
+      .lock()
+      .map_err(|e| create_security_error_and_log!("Document cache lock poisoned: {e}"))?;
+    if let Some(document) = cache_map.entries.get(&key) {
+      return Ok(document.clone());
+    }
+    self.parse_count.fetch_add(1, Ordering::Relaxed);
+    let document = Arc::new(verify_and_parse()?);
+    cache_map.insert(key, document.clone(), self.capacity);
+    Ok(document)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{super::types::QOS_DOCUMENT_CACHE_PROPERTY_NAME, *};
+  use crate::{
+    dds::qos::QosPolicies,
+    security::{
+      access_control::{access_control_builtin::AccessControlBuiltin, ParticipantAccessControl},
+      config::DomainParticipantSecurityConfigFiles,
+      security_error, AuthenticationBuiltin, Property,
+    },
+    QosPolicyBuilder,
+  };
+
+  const CONFIGURATION_FILES: &str = "examples/security_configuration_files";
+
+  fn participant_qos(use_document_cache: Option<&str>) -> QosPolicies {
+    let mut property = DomainParticipantSecurityConfigFiles::with_ros_default_names(
+      CONFIGURATION_FILES,
+      "no_pwd".to_string(),
+    )
+    .into_property_policy();
+    if let Some(value) = use_document_cache {
+      property.value.push(Property {
+        name: QOS_DOCUMENT_CACHE_PROPERTY_NAME.to_string(),
+        value: value.to_string(),
+        propagate: false,
+      });
+    }
+    QosPolicyBuilder::new().property(property).build()
+  }
+
+  fn read(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{CONFIGURATION_FILES}/{name}")).unwrap()
+  }
+
+  fn certificate(name: &str) -> Certificate {
+    Certificate::from_pem(read(name)).unwrap()
+  }
+
+  fn permissions() -> SecurityResult<DomainParticipantPermissions> {
+    DomainParticipantPermissions::from_xml(
+      &String::from_utf8(read("permissions_unsigned.xml")).unwrap(),
+    )
+    .map_err(|e| security_error(&format!("{e:?}")))
+  }
+
+  #[test]
+  pub fn documents_are_parsed_once_for_many_participants() {
+    let cache = Arc::new(DocumentCache::new(GLOBAL_CAPACITY));
+    let qos = participant_qos(None);
+
+    let participants: Vec<_> = (0..50)
+      .map(|_| {
+        let mut access = AccessControlBuiltin::new().with_document_cache(cache.clone());
+        let handle = access
+          .validate_local_permissions(&AuthenticationBuiltin::new(), 1, 0, &qos)
+          .unwrap();
+        (access, handle)
+      })
+      .collect();
+
+    // One governance and one permissions document
+    assert_eq!(cache.parse_count(), 2);
+
+    // The parsed permissions are shared, not copied
+    let (first, first_handle) = &participants[0];
+    let (last, last_handle) = &participants[49];
+    assert!(Arc::ptr_eq(
+      &first.get_permissions_document(first_handle).unwrap().1,
+      &last.get_permissions_document(last_handle).unwrap().1,
+    ));
+  }
+
+  #[test]
+  pub fn cache_can_be_bypassed() {
+    let cache = Arc::new(DocumentCache::new(GLOBAL_CAPACITY));
+    let qos = participant_qos(Some("false"));
+
+    for _ in 0..3 {
+      AccessControlBuiltin::new()
+        .with_document_cache(cache.clone())
+        .validate_local_permissions(&AuthenticationBuiltin::new(), 1, 0, &qos)
+        .unwrap();
+    }
+    assert_eq!(cache.parse_count(), 6);
+
+    let result = AccessControlBuiltin::new()
+      .with_document_cache(cache)
+      .validate_local_permissions(
+        &AuthenticationBuiltin::new(),
+        1,
+        0,
+        &participant_qos(Some("sometimes")),
+      );
+    assert!(result.is_err());
+  }
+
+  #[test]
+  pub fn oldest_documents_are_evicted() {
+    let cache = DocumentCache::new(1);
+    let permissions_ca = certificate("permissions_ca.cert.pem");
+    // The same permissions, signed in two different formats
+    let detached = read("permissions.p7s");
+    let wrapped = read("permissions_wrapped.p7s");
+
+    for document in [&detached, &wrapped, &detached] {
+      cache
+        .permissions_document(document, &permissions_ca, true, permissions)
+        .unwrap();
+    }
+    assert_eq!(cache.parse_count(), 3);
+
+    cache
+      .permissions_document(&detached, &permissions_ca, true, permissions)
+      .unwrap();
+    assert_eq!(cache.parse_count(), 3);
+  }
+
+  #[test]
+  pub fn entries_depend_on_the_permissions_ca() {
+    let cache = DocumentCache::new(GLOBAL_CAPACITY);
+    let document = read("permissions.p7s");
+
+    for ca in ["permissions_ca.cert.pem", "identity_ca.cert.pem"] {
+      cache
+        .permissions_document(&document, &certificate(ca), true, permissions)
+        .unwrap();
+    }
+    assert_eq!(cache.parse_count(), 2);
+  }
+
+  #[test]
+  pub fn errors_are_not_cached() {
+    let cache = DocumentCache::new(GLOBAL_CAPACITY);
+    let permissions_ca = certificate("permissions_ca.cert.pem");
+    let document = read("permissions.p7s");
+
+    assert!(cache
+      .permissions_document(&document, &permissions_ca, true, || {
+        Err(security_error("Signature verification failed"))
+      })
+      .is_err());
+    assert!(cache
+      .permissions_document(&document, &permissions_ca, true, permissions)
+      .is_ok());
+    assert_eq!(cache.parse_count(), 2);
+  }
+}
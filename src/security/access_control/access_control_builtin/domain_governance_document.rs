@@ -1,9 +1,22 @@
 use serde_xml_rs::from_str;
-use glob::*;
 pub use xml::{BasicProtectionKind, ProtectionKind};
 
-use crate::security::config::ConfigError;
-use super::domain_participant_permissions_document::DomainIds;
+use crate::{
+  create_security_error_and_log,
+  rtps::constant::builtin_topic_names,
+  security::{
+    access_control::{
+      EndpointSecurityAttributes, ParticipantSecurityAttributes, TopicSecurityAttributes,
+    },
+    config::{parse_config_error, ConfigError},
+    SecurityError, SecurityResult,
+  },
+};
+use super::{
+  domain_participant_permissions_document::DomainIds,
+  fnmatch::Pattern,
+  types::{BuiltinPluginEndpointSecurityAttributes, BuiltinPluginParticipantSecurityAttributes},
+};
 
 // This module provides access (parsing and query) to Domain Governance
 // Document as specified in Section "9.4.1.2 Domain Governance Document" of
@@ -16,24 +29,49 @@ pub struct DomainGovernanceDocument {
 
 impl DomainGovernanceDocument {
   // Find an applicable rule for domain according to
-  // Section "9.4.1.2.7 Application of Domain and Topic Rules"
+  // Section "9.4.1.2.7 Application of Domain and Topic Rules". The first rule
+  // whose domains contain domain_id applies, even if later rules would too.
   //
   // If no rule is found (None), then the operation being attempted must fail with
   // a permissions error.
   pub fn find_rule(&self, domain_id: u16) -> Option<&DomainRule> {
-    self.domain_access_rules.iter().find(|dr| {
-      dr.domains
-        .iter()
-        .any(|domain_ids| domain_ids.matches(domain_id))
+    self
+      .domain_access_rules
+      .iter()
+      .find(|dr| dr.applies_to(domain_id))
+  }
+
+  // As find_rule, but with the permissions error if no rule applies
+  pub fn domain_rule(&self, domain_id: u16) -> SecurityResult<&DomainRule> {
+    self.find_rule(domain_id).ok_or_else(|| {
+      create_security_error_and_log!("Domain rule not found for the domain_id {}", domain_id)
     })
   }
 
+  // Derive the security attributes of a participant in domain_id and of its
+  // endpoints on topic_name, as specified in Sections "9.4.1.2.5 Domain Rules"
+  // and "9.4.1.2.6 Topic Rules". The plugin itself keeps only the matched
+  // DomainRule per participant and queries that directly.
+  #[allow(dead_code)]
+  pub fn security_attributes(
+    &self,
+    domain_id: u16,
+    topic_name: &str,
+  ) -> SecurityResult<(ParticipantSecurityAttributes, EndpointSecurityAttributes)> {
+    let domain_rule = self.domain_rule(domain_id)?;
+    Ok((
+      domain_rule.participant_security_attributes(),
+      domain_rule.endpoint_security_attributes(topic_name)?,
+    ))
+  }
+
   pub fn from_xml(xml: &str) -> Result<Self, ConfigError> {
     let dgd: xml::DomainGovernanceDocument = from_str(
       xml
         .trim_start_matches("Content-Type: text/plain")
         .trim_start_matches(char::is_whitespace),
-    )?;
+    )
+    .map_err(|e| parse_config_error(format!("Malformed domain governance document: {e}")))?;
 
     let domain_access_rules = dgd
       .domain_access_rules
@@ -60,6 +98,13 @@ pub struct DomainRule {
 }
 
 impl DomainRule {
+  pub fn applies_to(&self, domain_id: u16) -> bool {
+    self
+      .domains
+      .iter()
+      .any(|domain_ids| domain_ids.matches(domain_id))
+  }
+
   pub fn find_topic_rule(&self, topic_name: &str) -> Option<&TopicRule> {
     self
       .topic_access_rules
@@ -67,6 +112,97 @@ impl DomainRule {
       .find(|tar| tar.topic_expression.matches(topic_name))
   }
 
+  pub fn participant_security_attributes(&self) -> ParticipantSecurityAttributes {
+    let (is_rtps_protected, is_rtps_encrypted, is_rtps_origin_authenticated) =
+      self.rtps_protection_kind.to_security_attributes_format();
+    let (is_discovery_protected, is_discovery_encrypted, is_discovery_origin_authenticated) = self
+      .discovery_protection_kind
+      .to_security_attributes_format();
+    let (is_liveliness_protected, is_liveliness_encrypted, is_liveliness_origin_authenticated) =
+      self
+        .liveliness_protection_kind
+        .to_security_attributes_format();
+
+    ParticipantSecurityAttributes {
+      allow_unauthenticated_participants: self.allow_unauthenticated_participants,
+      is_access_protected: self.enable_join_access_control,
+      is_discovery_protected,
+      is_liveliness_protected,
+      is_rtps_protected,
+      plugin_participant_attributes: BuiltinPluginParticipantSecurityAttributes {
+        is_discovery_encrypted,
+        is_discovery_origin_authenticated,
+        is_liveliness_encrypted,
+        is_liveliness_origin_authenticated,
+        is_rtps_encrypted,
+        is_rtps_origin_authenticated,
+      }
+      .into(),
+      ac_participant_properties: Vec::new(),
+    }
+  }
+
+  pub fn endpoint_security_attributes(
+    &self,
+    topic_name: &str,
+  ) -> SecurityResult<EndpointSecurityAttributes> {
+    // Special handling for builtin topics
+    match topic_name {
+      // 7.4.8: is_submessage_protected shall match is_discovery_protected of the participant
+      // security attributes
+      builtin_topic_names::DCPS_PARTICIPANT_SECURE
+      | builtin_topic_names::DCPS_PUBLICATIONS_SECURE
+      | builtin_topic_names::DCPS_SUBSCRIPTIONS_SECURE => {
+        let (is_submessage_protected, is_submessage_encrypted, is_submessage_origin_authenticated) =
+          self
+            .discovery_protection_kind
+            .to_security_attributes_format();
+        Ok(EndpointSecurityAttributes::for_builtin_topic(
+          is_submessage_protected,
+          is_submessage_encrypted,
+          is_submessage_origin_authenticated,
+        ))
+      }
+      // 7.4.8: is_submessage_protected shall match is_liveliness_protected of the participant
+      // security attributes
+      builtin_topic_names::DCPS_PARTICIPANT_MESSAGE_SECURE => {
+        let (is_submessage_protected, is_submessage_encrypted, is_submessage_origin_authenticated) =
+          self
+            .liveliness_protection_kind
+            .to_security_attributes_format();
+        Ok(EndpointSecurityAttributes::for_builtin_topic(
+          is_submessage_protected,
+          is_submessage_encrypted,
+          is_submessage_origin_authenticated,
+        ))
+      }
+
+      // This topic is for sharing keys. A unique encryption key is used for each receiver, so no
+      // additional origin authentication is needed.
+      builtin_topic_names::DCPS_PARTICIPANT_VOLATILE_MESSAGE_SECURE => Ok(
+        EndpointSecurityAttributes::for_builtin_topic(true, true, false),
+      ),
+
+      // 7.4.8 for stateless, the others are used for normal unprotected discovery
+      builtin_topic_names::DCPS_PARTICIPANT_STATELESS_MESSAGE
+      | builtin_topic_names::DCPS_PARTICIPANT
+      | builtin_topic_names::DCPS_PARTICIPANT_MESSAGE
+      | builtin_topic_names::DCPS_PUBLICATION
+      | builtin_topic_names::DCPS_SUBSCRIPTION
+      | builtin_topic_names::DCPS_TOPIC => Ok(EndpointSecurityAttributes::empty()),
+
+      // General case
+      topic_name => self
+        .find_topic_rule(topic_name)
+        .map(TopicRule::endpoint_security_attributes)
+        .ok_or_else(|| {
+          create_security_error_and_log!(
+            "Could not find a topic rule for the topic_name {topic_name}"
+          )
+        }),
+    }
+  }
+
   fn from_xml(xr: &xml::DomainRule) -> Result<Self, ConfigError> {
     let domains: Result<Vec<DomainIds>, ConfigError> =
       xr.domains.members.iter().map(DomainIds::from_xml).collect();
@@ -103,9 +239,36 @@ pub struct TopicRule {
 }
 
 impl TopicRule {
+  fn endpoint_security_attributes(&self) -> EndpointSecurityAttributes {
+    let (is_submessage_protected, is_submessage_encrypted, is_submessage_origin_authenticated) =
+      self
+        .metadata_protection_kind
+        .to_security_attributes_format();
+    let (is_payload_protected, is_payload_encrypted, is_key_protected) =
+      self.data_protection_kind.to_security_attributes_format();
+
+    EndpointSecurityAttributes {
+      topic_security_attributes: TopicSecurityAttributes {
+        is_read_protected: self.enable_read_access_control,
+        is_write_protected: self.enable_write_access_control,
+        is_discovery_protected: self.enable_discovery_protection,
+        is_liveliness_protected: self.enable_liveliness_protection,
+      },
+      is_submessage_protected,
+      is_payload_protected,
+      is_key_protected,
+      plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted,
+        is_submessage_origin_authenticated,
+        is_payload_encrypted,
+      }
+      .into(),
+      ac_endpoint_properties: Vec::new(),
+    }
+  }
+
   fn from_xml(xtr: &xml::TopicRule) -> Result<Self, ConfigError> {
-    let topic_expression =
-      Pattern::new(&xtr.topic_expression.expression).map_err(ConfigError::from)?;
+    let topic_expression = Pattern::new(&xtr.topic_expression.expression);
 
     Ok(TopicRule {
       topic_expression,
@@ -250,15 +413,13 @@ mod xml {
 mod tests {
   use super::*;
 
-  #[test]
-  pub fn parse_spec_example() {
-    // Modifications to example in spec:
-    // * insert missing "/" in closing id_range
-    // * Boolean literals true/false in all lowercase
-    // * field `enable_liveliness_protection` is systematically missing from
-    //   `topic_rule`s
+  // Modifications to example in spec:
+  // * insert missing "/" in closing id_range
+  // * Boolean literals true/false in all lowercase
+  // * field `enable_liveliness_protection` is systematically missing from
+  //   `topic_rule`s
 
-    let domain_governance_document = r#"<?xml version="1.0" encoding="utf-8"?>
+  const SPEC_EXAMPLE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
 <dds xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
 xsi:noNamespaceSchemaLocation="http://www.omg.org/spec/DDS-Security/20170801/omg_shared_ca_domain_governance.xsd">
   <domain_access_rules>
@@ -340,6 +501,10 @@ xsi:noNamespaceSchemaLocation="http://www.omg.org/spec/DDS-Security/20170801/omg
 </dds>
 "#;
 
+  #[test]
+  pub fn parse_spec_example() {
+    let domain_governance_document = SPEC_EXAMPLE;
+
     from_str::<xml::DomainGovernanceDocument>(domain_governance_document).unwrap();
 
     println!(
@@ -393,4 +558,229 @@ xsi:noNamespaceSchemaLocation="http://www.omg.org/spec/DDS-Security/20170801/omg
       DomainGovernanceDocument::from_xml(domain_governance_document).unwrap()
     );
   }
+
+  #[test]
+  pub fn spec_example_participant_attributes() {
+    let document = DomainGovernanceDocument::from_xml(SPEC_EXAMPLE).unwrap();
+
+    // Domain 15 is covered by the id_range 10..=20
+    for domain_id in [0, 10, 15, 20] {
+      let (participant, _) = document.security_attributes(domain_id, "Square").unwrap();
+      assert!(!participant.allow_unauthenticated_participants);
+      assert!(participant.is_access_protected);
+      assert!(participant.is_rtps_protected);
+      assert!(participant.is_discovery_protected);
+      assert!(participant.is_liveliness_protected);
+
+      let plugin_attributes = BuiltinPluginParticipantSecurityAttributes::try_from(
+        participant.plugin_participant_attributes,
+      )
+      .unwrap();
+      assert!(!plugin_attributes.is_rtps_encrypted);
+      assert!(plugin_attributes.is_discovery_encrypted);
+      assert!(!plugin_attributes.is_liveliness_encrypted);
+      assert!(!plugin_attributes.is_rtps_origin_authenticated);
+      assert!(!plugin_attributes.is_discovery_origin_authenticated);
+      assert!(!plugin_attributes.is_liveliness_origin_authenticated);
+    }
+
+    for domain_id in [1, 9, 21] {
+      assert!(document.security_attributes(domain_id, "Square").is_err());
+    }
+  }
+
+  #[test]
+  pub fn spec_example_endpoint_attributes() {
+    let document = DomainGovernanceDocument::from_xml(SPEC_EXAMPLE).unwrap();
+    let endpoint = |topic_name| document.security_attributes(0, topic_name).unwrap().1;
+
+    // Matches "Square*"
+    let square = endpoint("SquareWithFilter");
+    assert!(square.topic_security_attributes.is_read_protected);
+    assert!(square.topic_security_attributes.is_write_protected);
+    assert!(square.topic_security_attributes.is_discovery_protected);
+    assert!(!square.topic_security_attributes.is_liveliness_protected);
+    assert!(square.is_submessage_protected);
+    assert!(square.is_payload_protected);
+    assert!(square.is_key_protected);
+    let plugin_attributes =
+      BuiltinPluginEndpointSecurityAttributes::try_from(square.plugin_endpoint_attributes).unwrap();
+    assert!(plugin_attributes.is_submessage_encrypted);
+    assert!(!plugin_attributes.is_submessage_origin_authenticated);
+    assert!(plugin_attributes.is_payload_encrypted);
+
+    let circle = endpoint("Circle");
+    assert!(!circle.topic_security_attributes.is_read_protected);
+    assert!(circle.topic_security_attributes.is_write_protected);
+    assert!(circle.is_submessage_protected);
+    assert!(circle.is_payload_protected);
+
+    let triangle = endpoint("Triangle");
+    assert!(!triangle.topic_security_attributes.is_read_protected);
+    assert!(triangle.topic_security_attributes.is_write_protected);
+    assert!(!triangle.topic_security_attributes.is_discovery_protected);
+    assert!(!triangle.is_submessage_protected);
+    assert!(!triangle.is_payload_protected);
+    assert!(!triangle.is_key_protected);
+
+    // Matches only the catch-all "*"
+    let other = endpoint("Hexagon");
+    assert!(other.topic_security_attributes.is_read_protected);
+    assert!(other.is_submessage_protected);
+    assert!(other.is_payload_protected);
+
+    // Builtin secure discovery endpoints follow discovery_protection_kind (ENCRYPT)
+    let publications = endpoint(builtin_topic_names::DCPS_PUBLICATIONS_SECURE);
+    assert!(publications.is_submessage_protected);
+    assert!(!publications.is_payload_protected);
+    let plugin_attributes =
+      BuiltinPluginEndpointSecurityAttributes::try_from(publications.plugin_endpoint_attributes)
+        .unwrap();
+    assert!(plugin_attributes.is_submessage_encrypted);
+  }
+
+  #[test]
+  pub fn malformed_documents_are_rejected() {
+    let error_message = |xml: &str| match DomainGovernanceDocument::from_xml(xml) {
+      Err(ConfigError::Parse(message)) => message,
+      other => panic!("Expected a parse error, got {other:?}"),
+    };
+
+    let bad_protection_kind = SPEC_EXAMPLE.replacen(
+      "<rtps_protection_kind>SIGN</rtps_protection_kind>",
+      "<rtps_protection_kind>SCRAMBLE</rtps_protection_kind>",
+      1,
+    );
+    assert!(error_message(&bad_protection_kind).contains("Malformed domain governance document"));
+
+    let bad_bool = SPEC_EXAMPLE.replacen(
+      "<enable_join_access_control>true</enable_join_access_control>",
+      "<enable_join_access_control>maybe</enable_join_access_control>",
+      1,
+    );
+    assert!(error_message(&bad_bool).contains("maybe"));
+
+    let inverted_range = SPEC_EXAMPLE.replacen("<min>10</min>", "<min>30</min>", 1);
+    assert!(error_message(&inverted_range).contains("must not exceed"));
+  }
+
+  fn governance_with_domain_rules(rules: &[(&str, &str)]) -> String {
+    let rules: String = rules
+      .iter()
+      .map(|(domains, rtps_protection_kind)| {
+        format!(
+          r#"
+    <domain_rule>
+      <domains>{domains}</domains>
+      <allow_unauthenticated_participants>false</allow_unauthenticated_participants>
+      <enable_join_access_control>true</enable_join_access_control>
+      <rtps_protection_kind>{rtps_protection_kind}</rtps_protection_kind>
+      <discovery_protection_kind>NONE</discovery_protection_kind>
+      <liveliness_protection_kind>NONE</liveliness_protection_kind>
+      <topic_access_rules>
+        <topic_rule>
+          <topic_expression>*</topic_expression>
+          <enable_discovery_protection>false</enable_discovery_protection>
+          <enable_liveliness_protection>false</enable_liveliness_protection>
+          <enable_read_access_control>false</enable_read_access_control>
+          <enable_write_access_control>false</enable_write_access_control>
+          <metadata_protection_kind>NONE</metadata_protection_kind>
+          <data_protection_kind>NONE</data_protection_kind>
+        </topic_rule>
+      </topic_access_rules>
+    </domain_rule>"#
+        )
+      })
+      .collect();
+    format!(
+      r#"<?xml version="1.0" encoding="utf-8"?>
+<dds>
+  <domain_access_rules>{rules}
+  </domain_access_rules>
+</dds>
+"#
+    )
+  }
+
+  fn single_rule(domains: &str) -> DomainRule {
+    DomainGovernanceDocument::from_xml(&governance_with_domain_rules(&[(domains, "NONE")]))
+      .unwrap()
+      .domain_access_rules
+      .remove(0)
+  }
+
+  #[test]
+  pub fn domain_id_set_boundaries() {
+    let range = single_rule("<id_range><min>10</min><max>20</max></id_range>");
+    assert!(!range.applies_to(9));
+    assert!(range.applies_to(10));
+    assert!(range.applies_to(20));
+    assert!(!range.applies_to(21));
+
+    let at_least = single_rule("<id_range><min>15</min></id_range>");
+    assert!(!at_least.applies_to(14));
+    assert!(at_least.applies_to(15));
+    assert!(at_least.applies_to(u16::MAX));
+
+    let at_most = single_rule("<id_range><max>3</max></id_range>");
+    assert!(at_most.applies_to(0));
+    assert!(at_most.applies_to(3));
+    assert!(!at_most.applies_to(4));
+
+    let mixed = single_rule("<id>5</id><id_range><min>7</min><max>7</max></id_range><id>9</id>");
+    for (domain_id, applies) in [
+      (4, false),
+      (5, true),
+      (6, false),
+      (7, true),
+      (8, false),
+      (9, true),
+    ] {
+      assert_eq!(mixed.applies_to(domain_id), applies, "domain {domain_id}");
+    }
+  }
+
+  #[test]
+  pub fn first_matching_domain_rule_applies() {
+    let document = DomainGovernanceDocument::from_xml(&governance_with_domain_rules(&[
+      (
+        "<id>5</id><id_range><min>10</min><max>20</max></id_range>",
+        "SIGN",
+      ),
+      ("<id_range><min>15</min></id_range>", "ENCRYPT"),
+      ("<id_range><max>3</max></id_range>", "NONE"),
+    ]))
+    .unwrap();
+    let rtps_protection_kind = |domain_id| {
+      document
+        .domain_rule(domain_id)
+        .unwrap()
+        .rtps_protection_kind
+    };
+
+    assert_eq!(rtps_protection_kind(5), ProtectionKind::Sign);
+    // 15..=20 is covered by both of the first two rules
+    assert_eq!(rtps_protection_kind(15), ProtectionKind::Sign);
+    assert_eq!(rtps_protection_kind(20), ProtectionKind::Sign);
+    assert_eq!(rtps_protection_kind(21), ProtectionKind::Encrypt);
+    assert_eq!(rtps_protection_kind(0), ProtectionKind::None);
+    assert_eq!(rtps_protection_kind(3), ProtectionKind::None);
+
+    for domain_id in [4, 6, 9] {
+      assert!(document.find_rule(domain_id).is_none());
+      assert!(document.domain_rule(domain_id).is_err());
+    }
+  }
+
+  #[test]
+  pub fn domain_rule_without_domains_is_rejected() {
+    let error_message = |domains| match DomainGovernanceDocument::from_xml(
+      &governance_with_domain_rules(&[(domains, "NONE")]),
+    ) {
+      Err(ConfigError::Parse(message)) => message,
+      other => panic!("Expected a parse error, got {other:?}"),
+    };
+    assert!(error_message("").contains("Malformed domain governance document"));
+    assert!(error_message("<id_range></id_range>").contains("at least one bound"));
+  }
 }
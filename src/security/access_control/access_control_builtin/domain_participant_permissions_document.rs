@@ -1,13 +1,13 @@
 use std::fmt::Debug;
 
 use log::warn;
-use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
-use glob::Pattern;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Utc};
 
 use crate::security::{
   certificate::DistinguishedName,
   config::{parse_config_error, to_config_error_parse, ConfigError},
 };
+use super::fnmatch::Pattern;
 
 // A list of Grants
 #[derive(Debug, Clone)]
@@ -22,16 +22,18 @@ pub struct DomainParticipantPermissions {
 }
 
 impl DomainParticipantPermissions {
+  // A grant is also accepted if its validity period begins or ended at most
+  // clock_skew_tolerance away from current_datetime.
   pub fn find_grant(
     &self,
     subject_name: &DistinguishedName,
     current_datetime: &DateTime<Utc>,
+    clock_skew_tolerance: Duration,
   ) -> Option<&Grant> {
     // TODO: How to match subject names?
-    self
-      .grants
-      .iter()
-      .find(|g| g.subject_name.matches(subject_name) && g.validity.contains(current_datetime))
+    self.grants.iter().find(|g| {
+      g.subject_name.matches(subject_name) && g.is_valid_at(current_datetime, clock_skew_tolerance)
+    })
   }
 
   pub fn from_xml(domain_participant_permissions_xml: &str) -> Result<Self, ConfigError> {
@@ -75,12 +77,24 @@ pub struct Grant {
 }
 
 impl Grant {
+  pub fn is_valid_at(&self, datetime: &DateTime<Utc>, clock_skew_tolerance: Duration) -> bool {
+    self.validity.start - clock_skew_tolerance <= *datetime
+      && *datetime < self.validity.end + clock_skew_tolerance
+  }
+
+  // The instant at which this grant stops being valid
+  pub fn expiration(&self, clock_skew_tolerance: Duration) -> DateTime<Utc> {
+    self.validity.end + clock_skew_tolerance
+  }
+
+  // partitions is None for checks that concern the Topic itself rather than
+  // a DataWriter or DataReader
   pub fn check_action<'a>(
     &self,
     action: Action,
     domain_id: u16,
     topic_name: &'a str,
-    partitions: &'a [&str],
+    partitions: Option<&'a [&str]>,
     data_tags: &'a [(&str, &str)],
   ) -> AllowOrDeny {
     self
@@ -207,7 +221,7 @@ impl Rule {
     action: Action,
     domain_id: u16,
     topic_name: &'a str,
-    partitions: &'a [&str],
+    partitions: Option<&'a [&str]>,
     data_tags: &'a [(&str, &str)],
   ) -> bool {
     debug_assert!(!self.domains.is_empty());
@@ -223,7 +237,7 @@ impl Rule {
       // Check for matching criteria
       criteria
         .iter()
-        .any(|c| c.is_applicable(topic_name, partitions.iter(), data_tags.iter()))
+        .any(|c| c.is_applicable(topic_name, partitions, data_tags.iter()))
     } else {
       false // Did not apply to this domain, no result
     }
@@ -319,14 +333,14 @@ impl From<AllowOrDeny> for bool {
 #[derive(Debug, Clone)]
 pub struct Criterion {
   topics: Vec<Pattern>,
-  // This Vec must not be empty. Match occurs when any Glob matches the topic
+  // This Vec must not be empty. Match occurs when any pattern matches the topic
   // name.
-  partitions: Vec<Pattern>, // Match occurs when any Glob matches the partition name.
+  partitions: Vec<Pattern>, // Match occurs when any pattern matches the partition name.
   // If the Vec is empty, then the default "empty string" partition is assumed. This means that
   // only the "empty string" partition will match.
   // DDS Security spec defines two matching behaviors in case of publishing (subscribing)
   // to multiple partitions. "Default" behaviors requires all partitions to match, and
-  // the "legacy" behaviors requires only some partitions to match.
+  // the "legacy" behaviors requires only some partitions to match. We implement the default.
   data_tags: Vec<DataTag>, /* Match condition: All the data tags associated with a a DDS Entity
                             * must match an element of this vector. (But other, unmatched,
                             * DataTags in the Vec are ok.)
@@ -339,16 +353,37 @@ impl Criterion {
   pub fn is_applicable<'a>(
     &self,
     topic_name: &'a str,
-    mut partitions: impl Iterator<Item = &'a &'a str>,
+    partitions: Option<&'a [&str]>,
     mut data_tags: impl Iterator<Item = &'a (&'a str, &'a str)>,
   ) -> bool {
     debug_assert!(!self.topics.is_empty());
 
-    self.topics.iter().any(|glob| glob.matches(topic_name))
-      && partitions.all(|p| self.partitions.iter().any(|glob| glob.matches(p)))
+    let partitions_match = match partitions {
+      None => true, // Partitions are not considered
+      // An entity without partitions is in the default partition
+      Some([]) => self.matches_partition(""),
+      Some(partitions) => partitions.iter().all(|p| self.matches_partition(p)),
+    };
+
+    self
+      .topics
+      .iter()
+      .any(|pattern| pattern.matches(topic_name))
+      && partitions_match
       && data_tags.all(|(name, value)| self.data_tags.iter().any(|dt| dt.check(name, value)))
   }
 
+  fn matches_partition(&self, partition: &str) -> bool {
+    if self.partitions.is_empty() {
+      partition.is_empty()
+    } else {
+      self
+        .partitions
+        .iter()
+        .any(|pattern| pattern.matches(partition))
+    }
+  }
+
   fn from_xml(xc: &xml::Criteria) -> Result<Self, ConfigError> {
     let contents: (Vec<String>, Vec<String>, Vec<DataTag>) = xc.members.iter().fold(
       (Vec::new(), Vec::new(), Vec::new()),
@@ -381,15 +416,8 @@ impl Criterion {
       ));
     }
 
-    let topics = topics
-      .iter()
-      .map(|s| Pattern::new(s).map_err(ConfigError::from))
-      .collect::<Result<Vec<Pattern>, ConfigError>>()?;
-
-    let partitions = partitions
-      .iter()
-      .map(|s| Pattern::new(s).map_err(ConfigError::from))
-      .collect::<Result<Vec<Pattern>, ConfigError>>()?;
+    let topics = topics.iter().map(|s| Pattern::new(s)).collect();
+    let partitions = partitions.iter().map(|s| Pattern::new(s)).collect();
 
     Ok(Criterion {
       topics,
@@ -440,6 +468,10 @@ impl DomainIds {
     match xd {
       xml::DomainIdSetMember::DomainId(xml::DomainId { id }) => Ok(DomainIds::Value(*id)),
       xml::DomainIdSetMember::DomainIdRange(xml::DomainIdRange { min, max }) => match (min, max) {
+        (Some(min), Some(max)) if min.id > max.id => Err(parse_config_error(format!(
+          "Domain id range min {} must not exceed max {}",
+          min.id, max.id
+        ))),
         (Some(min), Some(max)) => Ok(DomainIds::Range(min.id, max.id)),
         (None, Some(max)) => Ok(DomainIds::Max(max.id)),
         (Some(min), None) => Ok(DomainIds::Min(min.id)),
@@ -786,10 +818,209 @@ mod tests {
     let grant = dpd.find_grant(
       &DistinguishedName::parse("CN=some_subject").unwrap(),
       &chrono::Utc.with_ymd_and_hms(2014, 11, 28, 0, 0, 0).unwrap(),
+      Duration::zero(),
     );
 
     assert!(grant.is_some());
 
     println!("{:?}", grant);
   }
+
+  const OVERLAPPING_RULES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<dds>
+  <permissions>
+    <grant name="DenyFirst">
+      <subject_name>CN=deny_first</subject_name>
+      <validity>
+        <not_before>2013-10-26T00:00:00Z</not_before>
+        <not_after>2018-10-26T22:45:30Z</not_after>
+      </validity>
+      <deny_rule>
+        <domains><id>0</id></domains>
+        <publish>
+          <topics><topic>Secret*</topic></topics>
+        </publish>
+      </deny_rule>
+      <allow_rule>
+        <domains><id_range><min>0</min><max>5</max></id_range></domains>
+        <publish>
+          <topics><topic>*</topic></topics>
+        </publish>
+        <subscribe>
+          <topics><topic>Sq*</topic></topics>
+          <partitions><partition>P[0-9]</partition></partitions>
+        </subscribe>
+      </allow_rule>
+      <default>DENY</default>
+    </grant>
+    <grant name="AllowFirst">
+      <subject_name>CN=allow_first</subject_name>
+      <validity>
+        <not_before>2013-10-26T00:00:00Z</not_before>
+        <not_after>2018-10-26T22:45:30Z</not_after>
+      </validity>
+      <allow_rule>
+        <domains><id>0</id></domains>
+        <publish>
+          <topics><topic>*</topic></topics>
+        </publish>
+      </allow_rule>
+      <deny_rule>
+        <domains><id>0</id></domains>
+        <publish>
+          <topics><topic>Secret*</topic></topics>
+        </publish>
+      </deny_rule>
+      <default>DENY</default>
+    </grant>
+  </permissions>
+</dds>
+"#;
+
+  fn overlapping_rules_grant(subject_name: &str) -> Grant {
+    DomainParticipantPermissions::from_xml(OVERLAPPING_RULES)
+      .unwrap()
+      .find_grant(
+        &DistinguishedName::parse(subject_name).unwrap(),
+        &chrono::Utc.with_ymd_and_hms(2014, 11, 28, 0, 0, 0).unwrap(),
+        Duration::zero(),
+      )
+      .unwrap()
+      .clone()
+  }
+
+  fn allowed(
+    grant: &Grant,
+    action: Action,
+    domain_id: u16,
+    topic: &str,
+    partitions: &[&str],
+  ) -> bool {
+    grant
+      .check_action(action, domain_id, topic, Some(partitions), &[])
+      .into()
+  }
+
+  #[test]
+  pub fn first_applicable_rule_wins() {
+    let deny_first = overlapping_rules_grant("CN=deny_first");
+    assert!(!allowed(&deny_first, Action::Publish, 0, "SecretPlan", &[]));
+    assert!(allowed(&deny_first, Action::Publish, 0, "Square", &[]));
+    // The deny rule only covers domain 0
+    assert!(allowed(&deny_first, Action::Publish, 3, "SecretPlan", &[]));
+    // No rule covers domain 6, so the default applies
+    assert!(!allowed(&deny_first, Action::Publish, 6, "Square", &[]));
+
+    let allow_first = overlapping_rules_grant("CN=allow_first");
+    assert!(allowed(&allow_first, Action::Publish, 0, "SecretPlan", &[]));
+  }
+
+  #[test]
+  pub fn wildcard_topics_and_partitions() {
+    let grant = overlapping_rules_grant("CN=deny_first");
+    assert!(allowed(&grant, Action::Subscribe, 0, "Square", &["P1"]));
+    assert!(allowed(&grant, Action::Subscribe, 0, "Sq", &["P1", "P2"]));
+    // Every partition must match
+    assert!(!allowed(
+      &grant,
+      Action::Subscribe,
+      0,
+      "Square",
+      &["P1", "PX"]
+    ));
+    assert!(!allowed(&grant, Action::Subscribe, 0, "Square", &["P10"]));
+    assert!(!allowed(&grant, Action::Subscribe, 0, "Circle", &["P1"]));
+    // Subscribe rules do not grant relaying
+    assert!(!allowed(&grant, Action::Relay, 0, "Square", &["P1"]));
+  }
+
+  #[test]
+  pub fn default_partition() {
+    let grant = overlapping_rules_grant("CN=deny_first");
+    // Publish criteria list no partitions, so they cover only the default
+    // partition
+    assert!(allowed(&grant, Action::Publish, 0, "Square", &[]));
+    assert!(allowed(&grant, Action::Publish, 0, "Square", &[""]));
+    assert!(!allowed(&grant, Action::Publish, 0, "Square", &["P1"]));
+    // Subscribe criteria list partitions, none of which is the default one
+    assert!(!allowed(&grant, Action::Subscribe, 0, "Square", &[]));
+    // Topic checks do not consider partitions
+    assert!(bool::from(grant.check_action(
+      Action::Subscribe,
+      0,
+      "Square",
+      None,
+      &[]
+    )));
+  }
+
+  #[test]
+  pub fn grants_outside_validity_are_not_found() {
+    let permissions = DomainParticipantPermissions::from_xml(OVERLAPPING_RULES).unwrap();
+    let subject_name = DistinguishedName::parse("CN=deny_first").unwrap();
+    let find_at = |datetime| {
+      permissions
+        .find_grant(&subject_name, &datetime, Duration::zero())
+        .is_some()
+    };
+
+    assert!(!find_at(
+      chrono::Utc
+        .with_ymd_and_hms(2013, 10, 25, 23, 59, 59)
+        .unwrap()
+    ));
+    assert!(find_at(
+      chrono::Utc.with_ymd_and_hms(2013, 10, 26, 0, 0, 0).unwrap()
+    ));
+    assert!(find_at(
+      chrono::Utc
+        .with_ymd_and_hms(2018, 10, 26, 22, 45, 29)
+        .unwrap()
+    ));
+    assert!(!find_at(
+      chrono::Utc
+        .with_ymd_and_hms(2018, 10, 26, 22, 45, 30)
+        .unwrap()
+    ));
+    assert!(!find_at(
+      chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+    ));
+
+    // A grant that becomes valid in the future, or expired in the past, is
+    // accepted within the clock skew tolerance
+    let find_with_tolerance = |datetime| {
+      permissions
+        .find_grant(&subject_name, &datetime, Duration::seconds(60))
+        .is_some()
+    };
+    assert!(find_with_tolerance(
+      chrono::Utc
+        .with_ymd_and_hms(2013, 10, 25, 23, 59, 0)
+        .unwrap()
+    ));
+    assert!(!find_with_tolerance(
+      chrono::Utc
+        .with_ymd_and_hms(2013, 10, 25, 23, 58, 59)
+        .unwrap()
+    ));
+    assert!(find_with_tolerance(
+      chrono::Utc
+        .with_ymd_and_hms(2018, 10, 26, 22, 46, 29)
+        .unwrap()
+    ));
+    assert!(!find_with_tolerance(
+      chrono::Utc
+        .with_ymd_and_hms(2018, 10, 26, 22, 46, 30)
+        .unwrap()
+    ));
+
+    // Unknown subjects have no grant at any time
+    assert!(permissions
+      .find_grant(
+        &DistinguishedName::parse("CN=someone_else").unwrap(),
+        &chrono::Utc.with_ymd_and_hms(2014, 11, 28, 0, 0, 0).unwrap(),
+        Duration::zero(),
+      )
+      .is_none());
+  }
 }
@@ -0,0 +1,211 @@
+// Topic and partition expressions in both the Domain Governance Document and
+// the DomainParticipant permissions document are matched "using the syntax and
+// rules of the POSIX fnmatch() function as specified in POSIX 1003.2-1992,
+// Section B.6" (DDS Security Spec v1.1, sections 9.4.1.2.6 and 9.4.1.3.2.3).
+//
+// This is fnmatch() with no flags: `*` and `?` also match '/' and a leading
+// '.', and a backslash quotes the next character. An unterminated bracket
+// expression matches a literal '[', so every string is a valid pattern.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+  Literal(char),
+  AnyChar,     // ?
+  AnySequence, // *
+  Bracket {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+  },
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+  tokens: Vec<Token>,
+}
+
+impl Pattern {
+  pub fn new(pattern: &str) -> Self {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+      match chars[i] {
+        '*' => {
+          // Consecutive stars are equivalent to one
+          if tokens.last() != Some(&Token::AnySequence) {
+            tokens.push(Token::AnySequence);
+          }
+          i += 1;
+        }
+        '?' => {
+          tokens.push(Token::AnyChar);
+          i += 1;
+        }
+        '[' => match Self::parse_bracket(&chars, i + 1) {
+          Some((token, next)) => {
+            tokens.push(token);
+            i = next;
+          }
+          None => {
+            tokens.push(Token::Literal('['));
+            i += 1;
+          }
+        },
+        '\\' if i + 1 < chars.len() => {
+          tokens.push(Token::Literal(chars[i + 1]));
+          i += 2;
+        }
+        c => {
+          tokens.push(Token::Literal(c));
+          i += 1;
+        }
+      }
+    }
+    Pattern { tokens }
+  }
+
+  // Parses a bracket expression starting just after the opening '['. Returns
+  // the token and the index after the closing ']', or None if there is no
+  // closing ']'.
+  fn parse_bracket(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+      i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+      let mut low = *chars.get(i)?;
+      if low == ']' && !first {
+        return Some((Token::Bracket { negated, ranges }, i + 1));
+      }
+      first = false;
+      if low == '\\' {
+        i += 1;
+        low = *chars.get(i)?;
+      }
+      i += 1;
+
+      // A '-' forms a range unless it is the last character before ']'
+      let high = match (chars.get(i), chars.get(i + 1)) {
+        (Some('-'), Some(&next)) if next != ']' => {
+          i += 1;
+          let high = if next == '\\' {
+            i += 1;
+            *chars.get(i)?
+          } else {
+            next
+          };
+          i += 1;
+          high
+        }
+        _ => low,
+      };
+      ranges.push((low, high));
+    }
+  }
+
+  pub fn matches(&self, name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut t, mut n) = (0, 0);
+    // Position of the most recent '*' and the name position it is currently
+    // assumed to extend to. On mismatch, let that '*' swallow one more char.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+      match self.tokens.get(t) {
+        Some(Token::AnySequence) => {
+          backtrack = Some((t, n));
+          t += 1;
+          continue;
+        }
+        Some(token) if token.matches_char(name[n]) => {
+          t += 1;
+          n += 1;
+          continue;
+        }
+        _ => {}
+      }
+      match backtrack {
+        Some((star, star_n)) => {
+          t = star + 1;
+          n = star_n + 1;
+          backtrack = Some((star, n));
+        }
+        None => return false,
+      }
+    }
+
+    // Name is exhausted, so only trailing stars may remain
+    self.tokens[t..]
+      .iter()
+      .all(|token| *token == Token::AnySequence)
+  }
+}
+
+impl Token {
+  fn matches_char(&self, c: char) -> bool {
+    match self {
+      Token::Literal(l) => *l == c,
+      Token::AnyChar => true,
+      Token::AnySequence => false, // handled by Pattern::matches
+      Token::Bracket { negated, ranges } => {
+        ranges.iter().any(|(low, high)| *low <= c && c <= *high) != *negated
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn check(pattern: &str, name: &str) -> bool {
+    Pattern::new(pattern).matches(name)
+  }
+
+  #[test]
+  pub fn wildcards() {
+    assert!(check("Square", "Square"));
+    assert!(!check("Square", "Square1"));
+    assert!(check("Sq*", "Sq"));
+    assert!(check("Sq*", "Square"));
+    assert!(check("*", ""));
+    assert!(check("*are", "Square"));
+    assert!(check("S*u*e", "Square"));
+    assert!(!check("S*u*e", "Squares"));
+    assert!(check("Square?", "Square1"));
+    assert!(!check("Square?", "Square"));
+    assert!(check("a**b", "a/x/b"));
+    // No FNM_PATHNAME or FNM_PERIOD
+    assert!(check("rt/*", "rt/ns/topic"));
+    assert!(check("?hidden", ".hidden"));
+  }
+
+  #[test]
+  pub fn brackets() {
+    assert!(check("P[0-9]", "P7"));
+    assert!(!check("P[0-9]", "Px"));
+    assert!(check("P[!0-9]", "Px"));
+    assert!(check("P[^0-9]", "Px"));
+    assert!(!check("P[^0-9]", "P7"));
+    assert!(check("[abc]", "b"));
+    assert!(check("[]a]", "]"));
+    assert!(check("[a-]", "-"));
+    assert!(check("[!]]", "a"));
+    assert!(!check("[!]]", "]"));
+    // Unterminated bracket is a literal '['
+    assert!(check("Circle[", "Circle["));
+    assert!(!check("Circle[", "Circle"));
+  }
+
+  #[test]
+  pub fn escapes() {
+    assert!(check(r"Sq\*", "Sq*"));
+    assert!(!check(r"Sq\*", "Square"));
+    assert!(check(r"[\]]", "]"));
+    assert!(check(r"a\", r"a\"));
+  }
+}
@@ -1,13 +1,8 @@
 use crate::{
-  create_security_error_and_log,
   dds::qos::QosPolicies,
-  rtps::constant::builtin_topic_names,
   security::{access_control::*, *},
 };
-use super::{
-  domain_governance_document::{DomainRule, TopicRule},
-  types::{BuiltinPluginEndpointSecurityAttributes, Entity},
-};
+use super::{partition_names, types::Entity};
 
 impl AccessControlBuiltin {
   fn get_endpoint_security_attributes(
@@ -15,117 +10,9 @@ impl AccessControlBuiltin {
     permissions_handle: PermissionsHandle,
     topic_name: &str,
   ) -> SecurityResult<EndpointSecurityAttributes> {
-    // Special handling for builtin topics
-    match topic_name {
-      // 7.4.8: is_submessage_protected shall match is_discovery_protected of the participant
-      // security attributes
-      builtin_topic_names::DCPS_PARTICIPANT_SECURE
-      | builtin_topic_names::DCPS_PUBLICATIONS_SECURE
-      | builtin_topic_names::DCPS_SUBSCRIPTIONS_SECURE => {
-        self.get_domain_rule(&permissions_handle).map(
-          |DomainRule {
-             discovery_protection_kind,
-             ..
-           }| {
-            let (
-              is_submessage_protected,
-              is_submessage_encrypted,
-              is_submessage_origin_authenticated,
-            ) = discovery_protection_kind.to_security_attributes_format();
-
-            EndpointSecurityAttributes::for_builtin_topic(
-              is_submessage_protected,
-              is_submessage_encrypted,
-              is_submessage_origin_authenticated,
-            )
-          },
-        )
-      }
-      // 7.4.8: is_submessage_protected shall match is_liveliness_protected of the participant
-      // security attributes
-      builtin_topic_names::DCPS_PARTICIPANT_MESSAGE_SECURE => {
-        self.get_domain_rule(&permissions_handle).map(
-          |DomainRule {
-             liveliness_protection_kind,
-             ..
-           }| {
-            let (
-              is_submessage_protected,
-              is_submessage_encrypted,
-              is_submessage_origin_authenticated,
-            ) = liveliness_protection_kind.to_security_attributes_format();
-
-            EndpointSecurityAttributes::for_builtin_topic(
-              is_submessage_protected,
-              is_submessage_encrypted,
-              is_submessage_origin_authenticated,
-            )
-          },
-        )
-      }
-
-      // This topic is for sharing keys. A unique encryption key is used for each receiver, so no
-      // additional origin authentication is needed.
-      builtin_topic_names::DCPS_PARTICIPANT_VOLATILE_MESSAGE_SECURE => Ok(
-        EndpointSecurityAttributes::for_builtin_topic(true, true, false),
-      ),
-
-      // 7.4.8 for stateless, the others are used for normal unprotected discovery
-      builtin_topic_names::DCPS_PARTICIPANT_STATELESS_MESSAGE
-      | builtin_topic_names::DCPS_PARTICIPANT
-      | builtin_topic_names::DCPS_PARTICIPANT_MESSAGE
-      | builtin_topic_names::DCPS_PUBLICATION
-      | builtin_topic_names::DCPS_SUBSCRIPTION
-      | builtin_topic_names::DCPS_TOPIC => Ok(EndpointSecurityAttributes::empty()),
-
-      // General case
-      topic_name => self
-        .get_domain_rule(&permissions_handle)
-        .and_then(|domain_rule| {
-          domain_rule.find_topic_rule(topic_name).ok_or_else(|| {
-            create_security_error_and_log!(
-              "Could not find a topic rule for the topic_name {topic_name}"
-            )
-          })
-        })
-        .map(
-          |TopicRule {
-             enable_discovery_protection,
-             enable_liveliness_protection,
-             enable_read_access_control,
-             enable_write_access_control,
-             metadata_protection_kind,
-             data_protection_kind,
-             ..
-           }| {
-            let (
-              is_submessage_protected,
-              is_submessage_encrypted,
-              is_submessage_origin_authenticated,
-            ) = metadata_protection_kind.to_security_attributes_format();
-            let (is_payload_protected, is_payload_encrypted, is_key_protected) =
-              data_protection_kind.to_security_attributes_format();
-            EndpointSecurityAttributes {
-              topic_security_attributes: TopicSecurityAttributes {
-                is_read_protected: *enable_read_access_control,
-                is_write_protected: *enable_write_access_control,
-                is_discovery_protected: *enable_discovery_protection,
-                is_liveliness_protected: *enable_liveliness_protection,
-              },
-              is_submessage_protected,
-              is_payload_protected,
-              is_key_protected,
-              plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
-                is_submessage_encrypted,
-                is_submessage_origin_authenticated,
-                is_payload_encrypted,
-              }
-              .into(),
-              ac_endpoint_properties: Vec::new(),
-            }
-          },
-        ),
-    }
+    self
+      .get_domain_rule(&permissions_handle)
+      .and_then(|domain_rule| domain_rule.endpoint_security_attributes(topic_name))
   }
 }
 
@@ -135,15 +22,16 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
     permissions_handle: PermissionsHandle,
     domain_id: u16,
     topic_name: String,
-    _qos: &QosPolicies,
+    qos: &QosPolicies,
   ) -> SecurityResult<bool> {
-    let partitions = &[]; // Partitions currently unsupported. TODO: get from PartitionQosPolicy
+    let partition = qos.partition();
+    let partitions = &partition_names(&partition);
     let data_tags = &[]; // Data tagging currently unsupported. TODO: get from DataTagQosPolicy
     self.check_entity(
       permissions_handle,
       domain_id,
       &topic_name,
-      partitions,
+      Some(partitions),
       data_tags,
       &Entity::Datawriter,
     )
@@ -154,15 +42,16 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
     permissions_handle: PermissionsHandle,
     domain_id: u16,
     topic_name: String,
-    _qos: &QosPolicies,
+    qos: &QosPolicies,
   ) -> SecurityResult<bool> {
-    let partitions = &[]; // Partitions currently unsupported. TODO: get from PartitionQosPolicy
+    let partition = qos.partition();
+    let partitions = &partition_names(&partition);
     let data_tags = &[]; // Data tagging currently unsupported. TODO: get from DataTagQosPolicy
     self.check_entity(
       permissions_handle,
       domain_id,
       &topic_name,
-      partitions,
+      Some(partitions),
       data_tags,
       &Entity::Datareader,
     )
@@ -175,13 +64,12 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
     topic_name: String,
     _qos: &QosPolicies,
   ) -> SecurityResult<bool> {
-    let partitions = &[]; // Partitions currently unsupported. TODO: get from PartitionQosPolicy
     let data_tags = &[]; // Data tagging currently unsupported. TODO: get from DataTagQosPolicy
     self.check_entity(
       permissions_handle,
       domain_id,
       &topic_name,
-      partitions,
+      None,
       data_tags,
       &Entity::Topic,
     )
@@ -216,7 +104,6 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
   }
   */
 
-  // Currently only mocked, but ready after removing the last line
   fn get_topic_sec_attributes(
     &self,
     permissions_handle: PermissionsHandle,
@@ -232,7 +119,6 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
       )
   }
 
-  // Currently only mocked, but ready after removing the last line
   fn get_datawriter_sec_attributes(
     &self,
     permissions_handle: PermissionsHandle,
@@ -241,7 +127,6 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
     self.get_endpoint_security_attributes(permissions_handle, &topic_name)
   }
 
-  // Currently only mocked, but ready after removing the last line
   fn get_datareader_sec_attributes(
     &self,
     permissions_handle: PermissionsHandle,
@@ -250,3 +135,218 @@ impl LocalEntityAccessControl for AccessControlBuiltin {
     self.get_endpoint_security_attributes(permissions_handle, &topic_name)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    super::{
+      domain_governance_document::DomainGovernanceDocument,
+      domain_participant_permissions_document::DomainParticipantPermissions, types::*,
+    },
+    *,
+  };
+  use std::sync::Arc;
+
+  use crate::{
+    dds::qos::{policy::Partition, QosPolicyBuilder},
+    security::certificate::DistinguishedName,
+  };
+
+  const GOVERNANCE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<dds>
+  <domain_access_rules>
+    <domain_rule>
+      <domains><id>0</id></domains>
+      <allow_unauthenticated_participants>false</allow_unauthenticated_participants>
+      <enable_join_access_control>true</enable_join_access_control>
+      <rtps_protection_kind>NONE</rtps_protection_kind>
+      <discovery_protection_kind>NONE</discovery_protection_kind>
+      <liveliness_protection_kind>NONE</liveliness_protection_kind>
+      <topic_access_rules>
+        <topic_rule>
+          <topic_expression>Protected*</topic_expression>
+          <enable_discovery_protection>true</enable_discovery_protection>
+          <enable_liveliness_protection>false</enable_liveliness_protection>
+          <enable_read_access_control>true</enable_read_access_control>
+          <enable_write_access_control>true</enable_write_access_control>
+          <metadata_protection_kind>ENCRYPT</metadata_protection_kind>
+          <data_protection_kind>ENCRYPT</data_protection_kind>
+        </topic_rule>
+        <topic_rule>
+          <topic_expression>*</topic_expression>
+          <enable_discovery_protection>false</enable_discovery_protection>
+          <enable_liveliness_protection>false</enable_liveliness_protection>
+          <enable_read_access_control>false</enable_read_access_control>
+          <enable_write_access_control>false</enable_write_access_control>
+          <metadata_protection_kind>NONE</metadata_protection_kind>
+          <data_protection_kind>NONE</data_protection_kind>
+        </topic_rule>
+      </topic_access_rules>
+    </domain_rule>
+  </domain_access_rules>
+</dds>
+"#;
+
+  // Readers and writers of ProtectedSensors are allowed only in the sensors.*
+  // partitions
+  const PERMISSIONS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<dds>
+  <permissions>
+    <grant name="Sensors">
+      <subject_name>CN=local</subject_name>
+      <validity>
+        <not_before>2013-10-26T00:00:00Z</not_before>
+        <not_after>9999-01-01T00:00:00Z</not_after>
+      </validity>
+      <allow_rule>
+        <domains><id>0</id></domains>
+        <publish>
+          <topics><topic>ProtectedSensors</topic></topics>
+          <partitions><partition>sensors.*</partition></partitions>
+        </publish>
+        <subscribe>
+          <topics><topic>ProtectedSensors</topic></topics>
+          <partitions><partition>sensors.*</partition></partitions>
+        </subscribe>
+      </allow_rule>
+      <default>DENY</default>
+    </grant>
+  </permissions>
+</dds>
+"#;
+
+  fn plugin_with_governance() -> (AccessControlBuiltin, PermissionsHandle) {
+    let domain_rule = DomainGovernanceDocument::from_xml(GOVERNANCE)
+      .unwrap()
+      .find_rule(0)
+      .unwrap()
+      .clone();
+    let mut access = AccessControlBuiltin::new();
+    let handle = access.generate_permissions_handle();
+    access.domain_rules.insert(handle, domain_rule);
+    (access, handle)
+  }
+
+  fn plugin_with_permissions() -> (AccessControlBuiltin, PermissionsHandle) {
+    let (mut access, handle) = plugin_with_governance();
+    let permissions = DomainParticipantPermissions::from_xml(PERMISSIONS).unwrap();
+    access.domain_participant_permissions.insert(
+      handle,
+      (
+        DistinguishedName::parse("CN=local").unwrap(),
+        Arc::new(permissions),
+      ),
+    );
+    (access, handle)
+  }
+
+  fn qos_with_partitions(names: &[&str]) -> QosPolicies {
+    QosPolicyBuilder::new()
+      .partition(Partition::new(names.iter().copied()))
+      .build()
+  }
+
+  fn decode_plugin_attributes(
+    attributes: &EndpointSecurityAttributes,
+  ) -> BuiltinPluginEndpointSecurityAttributes {
+    BuiltinPluginEndpointSecurityAttributes::try_from(attributes.plugin_endpoint_attributes)
+      .unwrap()
+  }
+
+  #[test]
+  pub fn protected_topics_are_encrypted() {
+    let (access, handle) = plugin_with_governance();
+
+    for attributes in [
+      access.get_datawriter_sec_attributes(handle, "ProtectedSquare".to_string()),
+      access.get_datareader_sec_attributes(handle, "ProtectedSquare".to_string()),
+    ] {
+      let attributes = attributes.unwrap();
+      assert!(attributes.is_payload_protected);
+      assert!(attributes.is_submessage_protected);
+      assert!(attributes.is_key_protected);
+      let plugin_attributes = decode_plugin_attributes(&attributes);
+      assert!(plugin_attributes.is_submessage_encrypted);
+      assert!(!plugin_attributes.is_submessage_origin_authenticated);
+      assert!(plugin_attributes.is_payload_encrypted);
+    }
+
+    let topic_attributes = access
+      .get_topic_sec_attributes(handle, "ProtectedSquare")
+      .unwrap();
+    assert!(topic_attributes.is_read_protected);
+    assert!(topic_attributes.is_write_protected);
+    assert!(topic_attributes.is_discovery_protected);
+    assert!(!topic_attributes.is_liveliness_protected);
+  }
+
+  #[test]
+  pub fn other_topics_are_unprotected() {
+    let (access, handle) = plugin_with_governance();
+
+    // Matching is by fnmatch, so a prefix in another case does not match
+    for topic_name in ["Square", "protectedSquare", "Protecte"] {
+      for attributes in [
+        access.get_datawriter_sec_attributes(handle, topic_name.to_string()),
+        access.get_datareader_sec_attributes(handle, topic_name.to_string()),
+      ] {
+        let attributes = attributes.unwrap();
+        assert!(!attributes.is_payload_protected);
+        assert!(!attributes.is_submessage_protected);
+        assert!(!attributes.is_key_protected);
+        let plugin_attributes = decode_plugin_attributes(&attributes);
+        assert!(!plugin_attributes.is_submessage_encrypted);
+        assert!(!plugin_attributes.is_submessage_origin_authenticated);
+        assert!(!plugin_attributes.is_payload_encrypted);
+      }
+      let topic_attributes = access.get_topic_sec_attributes(handle, topic_name).unwrap();
+      assert!(!topic_attributes.is_read_protected);
+      assert!(!topic_attributes.is_write_protected);
+    }
+  }
+
+  #[test]
+  pub fn unknown_permissions_handle_is_an_error() {
+    let (access, handle) = plugin_with_governance();
+    assert!(access
+      .get_datawriter_sec_attributes(handle + 1, "ProtectedSquare".to_string())
+      .is_err());
+  }
+
+  #[test]
+  pub fn partitions_are_checked() {
+    let (access, handle) = plugin_with_permissions();
+    let topic_name = || "ProtectedSensors".to_string();
+
+    for partitions in [&["sensors.temp"][..], &["sensors.temp", "sensors.wind"]] {
+      let qos = qos_with_partitions(partitions);
+      assert!(access
+        .check_create_datareader(handle, 0, topic_name(), &qos)
+        .unwrap());
+      assert!(access
+        .check_create_datawriter(handle, 0, topic_name(), &qos)
+        .unwrap());
+    }
+
+    // Every requested partition must be allowed. The default partition is not
+    // in the grant.
+    for partitions in [&["control"][..], &["sensors.temp", "control"], &[]] {
+      let qos = qos_with_partitions(partitions);
+      assert!(!access
+        .check_create_datareader(handle, 0, topic_name(), &qos)
+        .unwrap());
+      assert!(!access
+        .check_create_datawriter(handle, 0, topic_name(), &qos)
+        .unwrap());
+    }
+    let no_partition_qos = QosPolicies::qos_none();
+    assert!(!access
+      .check_create_datareader(handle, 0, topic_name(), &no_partition_qos)
+      .unwrap());
+
+    // Creating the Topic does not involve partitions
+    assert!(access
+      .check_create_topic(handle, 0, topic_name(), &no_partition_qos)
+      .unwrap());
+  }
+}
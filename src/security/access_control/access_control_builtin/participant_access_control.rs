@@ -1,4 +1,4 @@
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 
 use crate::{
   create_security_error_and_log,
@@ -24,14 +24,16 @@ use crate::{
     config::*,
     *,
   },
+  structure::clock,
 };
 use super::{
   domain_governance_document::{DomainRule, TopicRule},
-  s_mime_config_parser::SignedDocument,
+  s_mime_config_parser::verified_content,
   types::{
     BuiltinPermissionsCredentialToken, BuiltinPermissionsToken,
-    BuiltinPluginParticipantSecurityAttributes, QOS_GOVERNANCE_DOCUMENT_PROPERTY_NAME,
-    QOS_PERMISSIONS_CERTIFICATE_PROPERTY_NAME, QOS_PERMISSIONS_DOCUMENT_PROPERTY_NAME,
+    QOS_CLOCK_SKEW_TOLERANCE_PROPERTY_NAME, QOS_DOCUMENT_CACHE_PROPERTY_NAME,
+    QOS_GOVERNANCE_DOCUMENT_PROPERTY_NAME, QOS_PERMISSIONS_CERTIFICATE_PROPERTY_NAME,
+    QOS_PERMISSIONS_DOCUMENT_PROPERTY_NAME,
   },
 };
 
@@ -73,6 +75,32 @@ impl ParticipantAccessControl for AccessControlBuiltin {
     domain_id: u16,
     participant_qos: &QosPolicies,
   ) -> SecurityResult<PermissionsHandle> {
+    if let Some(tolerance) =
+      participant_qos.get_optional_property(QOS_CLOCK_SKEW_TOLERANCE_PROPERTY_NAME)
+    {
+      let seconds = tolerance.trim().parse::<u32>().map_err(|e| {
+        create_security_error_and_log!(
+          "Invalid value {:?} for the property {}: {}. Expected whole seconds.",
+          tolerance,
+          QOS_CLOCK_SKEW_TOLERANCE_PROPERTY_NAME,
+          e
+        )
+      })?;
+      self.clock_skew_tolerance = Duration::seconds(seconds.into());
+    }
+
+    if let Some(use_cache) = participant_qos.get_optional_property(QOS_DOCUMENT_CACHE_PROPERTY_NAME)
+    {
+      self.use_document_cache = use_cache.trim().parse::<bool>().map_err(|e| {
+        create_security_error_and_log!(
+          "Invalid value {:?} for the property {}: {}. Expected true or false.",
+          use_cache,
+          QOS_DOCUMENT_CACHE_PROPERTY_NAME,
+          e
+        )
+      })?;
+    }
+
     let permissions_ca_certificate = participant_qos
       .get_property(QOS_PERMISSIONS_CERTIFICATE_PROPERTY_NAME)
       .and_then(|certificate_uri| {
@@ -85,8 +113,9 @@ impl ParticipantAccessControl for AccessControlBuiltin {
         })
       })
       .and_then(|certificate_contents_pem| {
-        Certificate::from_pem(certificate_contents_pem)
-          .map_err(|e| create_security_error_and_log!("{e:?}"))
+        Certificate::from_pem(certificate_contents_pem).map_err(|e| {
+          create_security_error_and_log!("Failed to parse the permissions certificate: {:?}", e)
+        })
       })?;
 
     let domain_rule = participant_qos
@@ -101,21 +130,30 @@ impl ParticipantAccessControl for AccessControlBuiltin {
         })
       })
       .and_then(|governance_bytes| {
-        SignedDocument::from_bytes(&governance_bytes)
-          .map_err(SecurityError::from)
-          .and_then(|signed_document| signed_document.verify_signature(&permissions_ca_certificate))
-      })
-      .and_then(|governance_xml| {
-        DomainGovernanceDocument::from_xml(&String::from_utf8_lossy(governance_xml.as_ref()))
-          .map_err(|e| create_security_error_and_log!("{e:?}"))
+        self.document_cache.governance_document(
+          &governance_bytes,
+          &permissions_ca_certificate,
+          self.use_document_cache,
+          || {
+            verified_content(
+              "domain governance document",
+              &governance_bytes,
+              &permissions_ca_certificate,
+            )
+            .and_then(|governance_xml| {
+              DomainGovernanceDocument::from_xml(&String::from_utf8_lossy(governance_xml.as_ref()))
+                .map_err(|e| {
+                  create_security_error_and_log!(
+                    "Failed to parse the domain governance document: {:?}",
+                    e
+                  )
+                })
+            })
+          },
+        )
       })
       .and_then(|domain_governance_document| {
-        domain_governance_document
-          .find_rule(domain_id)
-          .ok_or_else(|| {
-            create_security_error_and_log!("Domain rule not found for the domain_id {}", domain_id)
-          })
-          .cloned()
+        domain_governance_document.domain_rule(domain_id).cloned()
       })?;
 
     let signed_permissions = participant_qos
@@ -129,13 +167,27 @@ impl ParticipantAccessControl for AccessControlBuiltin {
           )
         })
       })?;
-    let domain_participant_permissions = SignedDocument::from_bytes(&signed_permissions)
-      .map_err(SecurityError::from)
-      .and_then(|signed_document| signed_document.verify_signature(&permissions_ca_certificate))
-      .and_then(|permissions_xml| {
-        DomainParticipantPermissions::from_xml(&String::from_utf8_lossy(permissions_xml.as_ref()))
-          .map_err(|e| create_security_error_and_log!("{e:?}"))
-      })?;
+    let domain_participant_permissions = self.document_cache.permissions_document(
+      &signed_permissions,
+      &permissions_ca_certificate,
+      self.use_document_cache,
+      || {
+        verified_content(
+          "domain participant permissions document",
+          &signed_permissions,
+          &permissions_ca_certificate,
+        )
+        .and_then(|permissions_xml| {
+          DomainParticipantPermissions::from_xml(&String::from_utf8_lossy(permissions_xml.as_ref()))
+            .map_err(|e| {
+              create_security_error_and_log!(
+                "Failed to parse the domain participant permissions document: {:?}",
+                e
+              )
+            })
+        })
+      },
+    )?;
 
     // Check the subject name in the identity certificate matches the one from the
     // permissions document.
@@ -152,14 +204,19 @@ impl ParticipantAccessControl for AccessControlBuiltin {
         })
       })
       .and_then(|certificate_contents_pem| {
-        Certificate::from_pem(certificate_contents_pem)
-          .map_err(|e| create_security_error_and_log!("{e:?}"))
+        Certificate::from_pem(certificate_contents_pem).map_err(|e| {
+          create_security_error_and_log!("Failed to parse the identity certificate: {:?}", e)
+        })
       })
       .map(|cert| cert.subject_name().clone())?;
 
     // Then verify that we have permissions for this subject name
     if domain_participant_permissions
-      .find_grant(&subject_name, &Utc::now())
+      .find_grant(
+        &subject_name,
+        &clock::wall_now().to_utc(),
+        self.clock_skew_tolerance,
+      )
       .is_none()
     {
       Err(create_security_error_and_log!(
@@ -216,6 +273,11 @@ impl ParticipantAccessControl for AccessControlBuiltin {
     let permissions_ca_certificate =
       self.get_permissions_ca_certificate(local_permissions_handle)?;
 
+    // The remote's permissions document must be signed by our permissions CA,
+    // so any claims the remote token makes about its CA must agree with it
+    BuiltinPermissionsToken::try_from(remote_permissions_token.clone())?
+      .check_permissions_ca(permissions_ca_certificate)?;
+
     let bin_prop_map = remote_credential_token
       .data_holder
       .binary_properties_as_map();
@@ -243,34 +305,34 @@ impl ParticipantAccessControl for AccessControlBuiltin {
         "Could not find remote_domain_participant_permissions",
       ))?;
 
-    // Parse to signed document
-    let signed_permissions = SignedDocument::from_bytes(remote_permissions_bytes).map_err(|e| {
-      security_error(&format!(
-        "Could not parse signed document from permissions data: {:?}",
-        e
-      ))
-    })?;
-
-    // Verify signature
-    let permissions_xml_content = signed_permissions
-      .verify_signature(permissions_ca_certificate)
-      .map_err(|e| {
-        security_error(&format!(
-          "Could not verify signature on remote permissions document: {}",
-          e
-        ))
-      })?;
-
-    // Parse to permissions struct
-    let remote_domain_participant_permissions = DomainParticipantPermissions::from_xml(
-      &String::from_utf8_lossy(permissions_xml_content.as_ref()),
-    )
-    .map_err(|e| security_error(&format!("Could not parse permissions from XML: {:?}", e)))?;
+    // Verify signature and parse the permissions XML
+    let remote_domain_participant_permissions = self.document_cache.permissions_document(
+      remote_permissions_bytes,
+      permissions_ca_certificate,
+      self.use_document_cache,
+      || {
+        verified_content(
+          "remote domain participant permissions document",
+          remote_permissions_bytes,
+          permissions_ca_certificate,
+        )
+        .and_then(|permissions_xml_content| {
+          DomainParticipantPermissions::from_xml(&String::from_utf8_lossy(
+            permissions_xml_content.as_ref(),
+          ))
+          .map_err(|e| security_error(&format!("Could not parse permissions from XML: {:?}", e)))
+        })
+      },
+    )?;
 
     // Check the subject name in the identity certificate matches the one from the
     // permissions document.
     if remote_domain_participant_permissions
-      .find_grant(remote_subject_name, &Utc::now())
+      .find_grant(
+        remote_subject_name,
+        &clock::wall_now().to_utc(),
+        self.clock_skew_tolerance,
+      )
       .is_none()
     {
       Err(create_security_error_and_log!(
@@ -302,18 +364,16 @@ impl ParticipantAccessControl for AccessControlBuiltin {
     self.check_participant(permissions_handle, domain_id)
   }
 
-  // Currently only mocked
   fn check_remote_participant(
     &self,
     permissions_handle: PermissionsHandle,
     domain_id: u16,
     _participant_data: Option<&SpdpDiscoveredParticipantData>,
   ) -> SecurityResult<bool> {
-    // Move the following check to validate_remote_permissions from check_remote_
-    // methods, as there we have access to the tokens: "If the PluginClassName
-    // or the MajorVersion of the local permissions_token differ from those in
-    // the remote_permissions_token, the operation shall return FALSE."
-
+    // The PluginClassName and MajorVersion of the remote permissions_token are
+    // already compared with the local ones in validate_remote_permissions. The
+    // remote's PermissionsHandle combines the remote's grant with the local
+    // governance rule for the domain.
     self.check_participant(permissions_handle, domain_id)
   }
 
@@ -356,40 +416,192 @@ impl ParticipantAccessControl for AccessControlBuiltin {
     &self,
     permissions_handle: PermissionsHandle,
   ) -> SecurityResult<ParticipantSecurityAttributes> {
-    self.get_domain_rule(&permissions_handle).map(
-      |DomainRule {
-         allow_unauthenticated_participants,
-         enable_join_access_control,
-         discovery_protection_kind,
-         liveliness_protection_kind,
-         rtps_protection_kind,
-         ..
-       }| {
-        let (is_rtps_protected, is_rtps_encrypted, is_rtps_origin_authenticated) =
-          rtps_protection_kind.to_security_attributes_format();
-        let (is_discovery_protected, is_discovery_encrypted, is_discovery_origin_authenticated) =
-          discovery_protection_kind.to_security_attributes_format();
-        let (is_liveliness_protected, is_liveliness_encrypted, is_liveliness_origin_authenticated) =
-          liveliness_protection_kind.to_security_attributes_format();
-
-        ParticipantSecurityAttributes {
-          allow_unauthenticated_participants: *allow_unauthenticated_participants,
-          is_access_protected: *enable_join_access_control,
-          is_discovery_protected,
-          is_liveliness_protected,
-          is_rtps_protected,
-          plugin_participant_attributes: BuiltinPluginParticipantSecurityAttributes {
-            is_discovery_encrypted,
-            is_discovery_origin_authenticated,
-            is_liveliness_encrypted,
-            is_liveliness_origin_authenticated,
-            is_rtps_encrypted,
-            is_rtps_origin_authenticated,
-          }
-          .into(),
-          ac_participant_properties: Vec::new(),
-        }
-      },
+    self
+      .get_domain_rule(&permissions_handle)
+      .map(DomainRule::participant_security_attributes)
+  }
+
+  fn get_permissions_expiration(
+    &self,
+    permissions_handle: PermissionsHandle,
+  ) -> SecurityResult<DateTime<Utc>> {
+    self
+      .get_grant(&permissions_handle)
+      .map(|grant| grant.expiration(self.clock_skew_tolerance))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bytes::Bytes;
+  use chrono::TimeZone;
+
+  use super::{
+    super::{example_permissions_expiring_at, types::QOS_CLOCK_SKEW_TOLERANCE_PROPERTY_NAME},
+    *,
+  };
+  use crate::{
+    security::authentication::authentication_builtin::types::{
+      BuiltinAuthenticatedPeerCredentialToken, CertificateAlgorithm,
+    },
+    structure::{clock::SimulatedClock, time::Timestamp},
+    QosPolicyBuilder,
+  };
+
+  const CONFIGURATION_FILES: &str = "examples/security_configuration_files";
+  const LOCAL_IDENTITY: IdentityHandle = 1;
+
+  // The permissions of the example remote participant are valid until this
+  // instant
+  fn example_expiration() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2040, 1, 1, 0, 0, 0).unwrap()
+  }
+
+  // Steps the simulated wall clock to the given instant
+  fn set_clock(clock: &SimulatedClock, now: DateTime<Utc>) {
+    clock.step_wall_clock(Timestamp::try_from(now).unwrap() - clock::wall_now());
+  }
+
+  fn local_participant_qos(clock_skew_tolerance: Option<&str>) -> QosPolicies {
+    let mut property = DomainParticipantSecurityConfigFiles::with_ros_default_names(
+      CONFIGURATION_FILES,
+      "no_pwd".to_string(),
     )
+    .into_property_policy();
+    if let Some(tolerance) = clock_skew_tolerance {
+      property.value.push(Property {
+        name: QOS_CLOCK_SKEW_TOLERANCE_PROPERTY_NAME.to_string(),
+        value: tolerance.to_string(),
+        propagate: false,
+      });
+    }
+    QosPolicyBuilder::new().property(property).build()
+  }
+
+  // Returns the plugin, the clock and the local PermissionsHandle
+  fn local_plugin(
+    clock_skew_tolerance: Option<&str>,
+  ) -> (AccessControlBuiltin, SimulatedClock, PermissionsHandle) {
+    let clock = SimulatedClock::start();
+    set_clock(&clock, example_expiration() - Duration::hours(1));
+    let mut access = AccessControlBuiltin::new();
+    let local_handle = access
+      .validate_local_permissions(
+        &AuthenticationBuiltin::new(),
+        LOCAL_IDENTITY,
+        0,
+        &local_participant_qos(clock_skew_tolerance),
+      )
+      .unwrap();
+    (access, clock, local_handle)
+  }
+
+  // Validates a remote participant that uses the example identity and
+  // permissions, re-signed to expire at example_expiration, and advertised the
+  // given PermissionsToken
+  fn validate_example_remote(
+    access: &mut AccessControlBuiltin,
+    remote_permissions_token: &PermissionsToken,
+  ) -> SecurityResult<PermissionsHandle> {
+    let read =
+      |name: &str| Bytes::from(std::fs::read(format!("{CONFIGURATION_FILES}/{name}")).unwrap());
+    let credential_token = BuiltinAuthenticatedPeerCredentialToken {
+      c_id: read("cert.pem"),
+      c_perm: Bytes::from(example_permissions_expiring_at(example_expiration())),
+    }
+    .into();
+    access.validate_remote_permissions(
+      &AuthenticationBuiltin::new(),
+      LOCAL_IDENTITY,
+      2,
+      remote_permissions_token,
+      &credential_token,
+    )
+  }
+
+  // Returns the plugin, the clock and the PermissionsHandle of a remote
+  // participant that uses the example identity and permissions.
+  fn plugin_with_remote(
+    clock_skew_tolerance: Option<&str>,
+  ) -> (AccessControlBuiltin, SimulatedClock, PermissionsHandle) {
+    let (mut access, clock, local_handle) = local_plugin(clock_skew_tolerance);
+    let token = access.get_permissions_token(local_handle).unwrap();
+    let remote_handle = validate_example_remote(&mut access, &token).unwrap();
+    (access, clock, remote_handle)
+  }
+
+  #[test]
+  pub fn remote_is_rejected_after_its_permissions_expire() {
+    let (access, clock, remote) = plugin_with_remote(None);
+
+    assert_eq!(
+      access.get_permissions_expiration(remote).unwrap(),
+      example_expiration()
+    );
+    assert!(access.check_remote_participant(remote, 0, None).unwrap());
+
+    set_clock(&clock, example_expiration() - Duration::seconds(1));
+    assert!(access.check_remote_participant(remote, 0, None).unwrap());
+
+    set_clock(&clock, example_expiration());
+    assert!(access.check_remote_participant(remote, 0, None).is_err());
+    assert!(access.get_permissions_expiration(remote).is_err());
+  }
+
+  #[test]
+  pub fn clock_skew_tolerance_extends_validity() {
+    let (access, clock, remote) = plugin_with_remote(Some("60"));
+
+    assert_eq!(
+      access.get_permissions_expiration(remote).unwrap(),
+      example_expiration() + Duration::seconds(60)
+    );
+    set_clock(&clock, example_expiration() + Duration::seconds(59));
+    assert!(access.check_remote_participant(remote, 0, None).unwrap());
+    set_clock(&clock, example_expiration() + Duration::seconds(60));
+    assert!(access.check_remote_participant(remote, 0, None).is_err());
+  }
+
+  #[test]
+  pub fn invalid_clock_skew_tolerance_is_rejected() {
+    let mut access = AccessControlBuiltin::new();
+    let result = access.validate_local_permissions(
+      &AuthenticationBuiltin::new(),
+      1,
+      0,
+      &local_participant_qos(Some("a minute")),
+    );
+    assert!(result.is_err());
+  }
+
+  #[test]
+  pub fn remote_permissions_token_claims_are_checked() {
+    let (mut access, _clock, local_handle) = local_plugin(None);
+    let permissions_ca = access
+      .get_permissions_ca_certificate(&local_handle)
+      .unwrap()
+      .clone();
+
+    // Claims that agree with our permissions CA are accepted
+    let token = BuiltinPermissionsToken {
+      permissions_ca_subject_name: Some(permissions_ca.subject_name().clone()),
+      permissions_ca_algorithm: permissions_ca.algorithm(),
+    }
+    .into();
+    assert!(validate_example_remote(&mut access, &token).is_ok());
+
+    let token = BuiltinPermissionsToken {
+      permissions_ca_subject_name: Some(DistinguishedName::parse("CN=some_other_ca").unwrap()),
+      permissions_ca_algorithm: None,
+    }
+    .into();
+    assert!(validate_example_remote(&mut access, &token).is_err());
+
+    let token = BuiltinPermissionsToken {
+      permissions_ca_subject_name: None,
+      permissions_ca_algorithm: Some(CertificateAlgorithm::RSA2048),
+    }
+    .into();
+    assert!(validate_example_remote(&mut access, &token).is_err());
   }
 }
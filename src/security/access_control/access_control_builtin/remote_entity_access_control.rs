@@ -5,13 +5,24 @@ use crate::{
     sedp_messages::TopicBuiltinTopicData, DiscoveredReaderData, DiscoveredWriterData,
     PublicationBuiltinTopicData, SubscriptionBuiltinTopicData,
   },
+  dds::qos::policy::DataTag,
   security::{access_control::*, *},
 };
 use super::{
   domain_governance_document::TopicRule, domain_participant_permissions_document::Action,
-  types::Entity,
+  partition_names, types::Entity,
 };
 
+// The data tags of a remote endpoint as (name, value) pairs for matching
+// against the data_tags criteria of the remote's grant
+fn data_tag_pairs(data_tags: &Option<DataTag>) -> Vec<(&str, &str)> {
+  data_tags
+    .iter()
+    .flat_map(|data_tag| data_tag.tags.iter())
+    .map(|tag| (tag.name.as_str(), tag.value.as_str()))
+    .collect()
+}
+
 impl RemoteEntityAccessControl for AccessControlBuiltin {
   fn check_remote_datawriter(
     &self,
@@ -19,54 +30,54 @@ impl RemoteEntityAccessControl for AccessControlBuiltin {
     domain_id: u16,
     publication_data: &PublicationBuiltinTopicDataSecure,
   ) -> SecurityResult<bool> {
-    let partitions = &[]; // Partitions currently unsupported. TODO: get from publication_data
-    let data_tags = &[]; // Data tagging currently unsupported. TODO: get from publication_data
-
     let PublicationBuiltinTopicDataSecure {
       discovered_writer_data:
         DiscoveredWriterData {
-          publication_topic_data: PublicationBuiltinTopicData { topic_name, .. },
+          publication_topic_data:
+            PublicationBuiltinTopicData {
+              topic_name,
+              partition,
+              ..
+            },
           ..
         },
-      ..
+      data_tags,
     } = publication_data;
-
-    // Move the following check to validate_remote_permissions from check_remote_
-    // methods, as there we have access to the tokens: "If the PluginClassName
-    // or the MajorVersion of the local permissions_token differ from those in
-    // the remote_permissions_token, the operation shall return FALSE."
+    let partitions = &partition_names(partition);
+    let data_tags = &data_tag_pairs(data_tags);
 
     self.check_entity(
       permissions_handle,
       domain_id,
       topic_name,
-      partitions,
+      Some(partitions),
       data_tags,
       &Entity::Datawriter,
     )
   }
 
-  // Currently only mocked
   fn check_remote_datareader(
     &self,
     permissions_handle: PermissionsHandle,
     domain_id: u16,
     subscription_data: &SubscriptionBuiltinTopicDataSecure,
   ) -> SecurityResult<(bool, bool)> {
-    let partitions = &[]; // Partitions currently unsupported. TODO: get from publication_data
-    let data_tags = &[]; // Data tagging currently unsupported. TODO: get from publication_data
-
     let SubscriptionBuiltinTopicDataSecure {
       discovered_reader_data:
         DiscoveredReaderData {
-          subscription_topic_data: SubscriptionBuiltinTopicData { topic_name, .. },
+          subscription_topic_data:
+            subscription_topic_data @ SubscriptionBuiltinTopicData { topic_name, .. },
           ..
         },
-      ..
+      data_tags,
     } = subscription_data;
+    let partitions = &partition_names(subscription_topic_data.partition());
+    let data_tags = &data_tag_pairs(data_tags);
 
     // This method differs from the other similar ones because of the possibility of
-    // a relay only datareader
+    // a relay only datareader. Discovery passes relay_only on to the crypto
+    // plugin in register_matched_remote_datareader, so that the reader gets the
+    // keys for the RTPS submessages but not for the payload.
 
     let grant = self.get_grant(&permissions_handle)?;
     let domain_rule = self.get_domain_rule(&permissions_handle)?;
@@ -86,16 +97,11 @@ impl RemoteEntityAccessControl for AccessControlBuiltin {
         Action::Subscribe,
         domain_id,
         topic_name,
-        partitions,
+        Some(partitions),
         data_tags,
       )
       .into();
 
-    // Move the following check to validate_remote_permissions from check_remote_
-    // methods, as there we have access to the tokens: "If the PluginClassName
-    // or the MajorVersion of the local permissions_token differ from those in
-    // the remote_permissions_token, the operation shall return FALSE."
-
     let allow_to_fully_read = requested_access_is_unprotected || participant_has_read_access;
 
     let relay_only = if allow_to_fully_read {
@@ -104,7 +110,13 @@ impl RemoteEntityAccessControl for AccessControlBuiltin {
     } else {
       // Participant is not allowed to fully read the topic. But is it allowed to
       // relay it?
-      bool::from(grant.check_action(Action::Relay, domain_id, topic_name, partitions, data_tags))
+      bool::from(grant.check_action(
+        Action::Relay,
+        domain_id,
+        topic_name,
+        Some(partitions),
+        data_tags,
+      ))
     };
 
     // check_passed = true means that participant is allowed to either fully read
@@ -119,21 +131,15 @@ impl RemoteEntityAccessControl for AccessControlBuiltin {
     domain_id: u16,
     topic_data: &TopicBuiltinTopicData,
   ) -> SecurityResult<bool> {
-    let partitions = &[]; // Partitions currently unsupported. TODO: get from publication_data
     let data_tags = &[]; // Data tagging currently unsupported. TODO: get from publication_data
 
     let TopicBuiltinTopicData { name, .. } = topic_data;
 
-    // Move the following check to validate_remote_permissions from check_remote_
-    // methods, as there we have access to the tokens: "If the PluginClassName
-    // or the MajorVersion of the local permissions_token differ from those in
-    // the remote_permissions_token, the operation shall return FALSE."
-
     self.check_entity(
       permissions_handle,
       domain_id,
       name,
-      partitions,
+      None,
       data_tags,
       &Entity::Topic,
     )
@@ -204,3 +210,239 @@ impl RemoteEntityAccessControl for AccessControlBuiltin {
   }
   */
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    super::{
+      domain_governance_document::DomainGovernanceDocument,
+      domain_participant_permissions_document::DomainParticipantPermissions,
+    },
+    *,
+  };
+  use std::sync::Arc;
+
+  use crate::{
+    dds::qos::{policy::Partition, QosPolicyBuilder},
+    security::certificate::DistinguishedName,
+  };
+
+  const GOVERNANCE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<dds>
+  <domain_access_rules>
+    <domain_rule>
+      <domains><id>0</id></domains>
+      <allow_unauthenticated_participants>false</allow_unauthenticated_participants>
+      <enable_join_access_control>true</enable_join_access_control>
+      <rtps_protection_kind>SIGN</rtps_protection_kind>
+      <discovery_protection_kind>ENCRYPT</discovery_protection_kind>
+      <liveliness_protection_kind>SIGN</liveliness_protection_kind>
+      <topic_access_rules>
+        <topic_rule>
+          <topic_expression>Public</topic_expression>
+          <enable_discovery_protection>false</enable_discovery_protection>
+          <enable_liveliness_protection>false</enable_liveliness_protection>
+          <enable_read_access_control>false</enable_read_access_control>
+          <enable_write_access_control>false</enable_write_access_control>
+          <metadata_protection_kind>NONE</metadata_protection_kind>
+          <data_protection_kind>NONE</data_protection_kind>
+        </topic_rule>
+        <topic_rule>
+          <topic_expression>*</topic_expression>
+          <enable_discovery_protection>true</enable_discovery_protection>
+          <enable_liveliness_protection>false</enable_liveliness_protection>
+          <enable_read_access_control>true</enable_read_access_control>
+          <enable_write_access_control>true</enable_write_access_control>
+          <metadata_protection_kind>ENCRYPT</metadata_protection_kind>
+          <data_protection_kind>ENCRYPT</data_protection_kind>
+        </topic_rule>
+      </topic_access_rules>
+    </domain_rule>
+  </domain_access_rules>
+</dds>
+"#;
+
+  // The remote may subscribe to Square, subscribe to Sensors in the sensors.*
+  // partitions, relay Circle and publish Tagged with a matching data tag, but
+  // nothing else.
+  const REMOTE_PERMISSIONS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<dds>
+  <permissions>
+    <grant name="RemoteSubscriber">
+      <subject_name>CN=remote</subject_name>
+      <validity>
+        <not_before>2013-10-26T00:00:00Z</not_before>
+        <not_after>9999-01-01T00:00:00Z</not_after>
+      </validity>
+      <allow_rule>
+        <domains><id>0</id></domains>
+        <publish>
+          <topics><topic>Tagged</topic></topics>
+          <data_tags>
+            <tag><name>aTagName</name><value>aTagValue</value></tag>
+          </data_tags>
+        </publish>
+        <subscribe>
+          <topics><topic>Square</topic></topics>
+        </subscribe>
+        <subscribe>
+          <topics><topic>Sensors</topic></topics>
+          <partitions><partition>sensors.*</partition></partitions>
+        </subscribe>
+        <relay>
+          <topics><topic>Circle</topic></topics>
+        </relay>
+      </allow_rule>
+      <default>DENY</default>
+    </grant>
+  </permissions>
+</dds>
+"#;
+
+  // Returns the plugin and the PermissionsHandle of the remote participant, as
+  // validate_remote_permissions would have stored them.
+  fn plugin_with_remote() -> (AccessControlBuiltin, PermissionsHandle) {
+    let domain_rule = DomainGovernanceDocument::from_xml(GOVERNANCE)
+      .unwrap()
+      .find_rule(0)
+      .unwrap()
+      .clone();
+    let permissions = DomainParticipantPermissions::from_xml(REMOTE_PERMISSIONS).unwrap();
+
+    let mut access = AccessControlBuiltin::new();
+    let handle = access.generate_permissions_handle();
+    access.domain_rules.insert(handle, domain_rule);
+    access.domain_participant_permissions.insert(
+      handle,
+      (
+        DistinguishedName::parse("CN=remote").unwrap(),
+        Arc::new(permissions),
+      ),
+    );
+    (access, handle)
+  }
+
+  fn writer(topic_name: &str, tags: &[(&str, &str)]) -> PublicationBuiltinTopicDataSecure {
+    let mut writer = PublicationBuiltinTopicDataSecure::from(DiscoveredWriterData::default(
+      topic_name.to_string(),
+      "ShapeType".to_string(),
+    ));
+    writer.data_tags = Some(DataTag {
+      tags: tags
+        .iter()
+        .map(|(name, value)| Tag {
+          name: name.to_string(),
+          value: value.to_string(),
+        })
+        .collect(),
+    });
+    writer
+  }
+
+  fn reader(topic_name: &str) -> SubscriptionBuiltinTopicDataSecure {
+    SubscriptionBuiltinTopicDataSecure::from(DiscoveredReaderData::default(
+      topic_name.to_string(),
+      "ShapeType".to_string(),
+    ))
+  }
+
+  fn reader_in_partitions(
+    topic_name: &str,
+    partitions: &[&str],
+  ) -> SubscriptionBuiltinTopicDataSecure {
+    let mut reader_data =
+      DiscoveredReaderData::default(topic_name.to_string(), "ShapeType".to_string());
+    reader_data.subscription_topic_data.set_qos(
+      &QosPolicyBuilder::new()
+        .partition(Partition::new(partitions.iter().copied()))
+        .build(),
+    );
+    SubscriptionBuiltinTopicDataSecure::from(reader_data)
+  }
+
+  #[test]
+  pub fn subscriber_without_publish_grant_cannot_publish() {
+    let (access, remote) = plugin_with_remote();
+
+    assert!(access.check_remote_participant(remote, 0, None).unwrap());
+    assert_eq!(
+      access
+        .check_remote_datareader(remote, 0, &reader("Square"))
+        .unwrap(),
+      (true, false)
+    );
+    assert!(!access
+      .check_remote_datawriter(remote, 0, &writer("Square", &[]))
+      .unwrap());
+
+    // Access to an unprotected topic needs no grant
+    assert!(access
+      .check_remote_datawriter(remote, 0, &writer("Public", &[]))
+      .unwrap());
+    assert_eq!(
+      access
+        .check_remote_datareader(remote, 0, &reader("Public"))
+        .unwrap(),
+      (true, false)
+    );
+  }
+
+  #[test]
+  pub fn relay_grant_gives_relay_only_access() {
+    let (access, remote) = plugin_with_remote();
+
+    assert_eq!(
+      access
+        .check_remote_datareader(remote, 0, &reader("Circle"))
+        .unwrap(),
+      (true, true)
+    );
+    assert_eq!(
+      access
+        .check_remote_datareader(remote, 0, &reader("Triangle"))
+        .unwrap(),
+      (false, false)
+    );
+    // Only the domain in the grant is allowed
+    assert_eq!(
+      access
+        .check_remote_datareader(remote, 1, &reader("Square"))
+        .unwrap(),
+      (false, false)
+    );
+  }
+
+  #[test]
+  pub fn remote_data_tags_are_checked() {
+    let (access, remote) = plugin_with_remote();
+
+    assert!(access
+      .check_remote_datawriter(remote, 0, &writer("Tagged", &[("aTagName", "aTagValue")]))
+      .unwrap());
+    assert!(!access
+      .check_remote_datawriter(remote, 0, &writer("Tagged", &[("aTagName", "other")]))
+      .unwrap());
+  }
+
+  #[test]
+  pub fn remote_partitions_are_checked() {
+    let (access, remote) = plugin_with_remote();
+    let check = |partitions: &[&str]| {
+      access
+        .check_remote_datareader(remote, 0, &reader_in_partitions("Sensors", partitions))
+        .unwrap()
+    };
+
+    assert_eq!(check(&["sensors.temp"]), (true, false));
+    assert_eq!(check(&["control"]), (false, false));
+    assert_eq!(check(&["sensors.temp", "control"]), (false, false));
+    // The default partition is not in the grant
+    assert_eq!(check(&[]), (false, false));
+    assert_eq!(
+      access
+        .check_remote_datareader(remote, 0, &reader("Sensors"))
+        .unwrap(),
+      (false, false)
+    );
+  }
+}
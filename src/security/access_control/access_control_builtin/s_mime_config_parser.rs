@@ -17,13 +17,17 @@
 // This module is for decoding the said S/MIME encoding. The same encoding
 // applies also for the DomainParticipant Permissions Document. (Section
 // 9.4.1.3)
+//
+// Besides the spec-mandated multipart/signed format (detached signature), we
+// also accept the signed-data format application/pkcs7-mime (wrapped
+// signature), which is what e.g. `openssl smime -sign -nodetach` produces.
 
 use bytes::Bytes;
 use cms::{
   attr::MessageDigest,
   signed_data::{EncapsulatedContentInfo, SignedData},
 };
-use der::{Decode, Encode};
+use der::{asn1::OctetString, Decode, Encode};
 use ring::{digest, signature};
 
 use crate::{
@@ -46,11 +50,47 @@ pub struct SignedDocument {
   signature_der: Bytes,
 }
 
+// Verifies that a signed document (e.g. governance or permissions) is signed
+// by the given certificate and returns the signed content. document_name is
+// used in error messages.
+pub fn verified_content(
+  document_name: &str,
+  input: &[u8],
+  certificate: &Certificate,
+) -> SecurityResult<Bytes> {
+  SignedDocument::from_bytes(input)
+    .map_err(SecurityError::from)
+    .and_then(|signed_document| signed_document.verify_signature(certificate))
+    .map_err(|e| {
+      create_security_error_and_log!(
+        "Signature verification of the {} failed: {}",
+        document_name,
+        e.msg
+      )
+    })
+}
+
 impl SignedDocument {
   pub fn from_bytes(input: &[u8]) -> Result<SignedDocument, ConfigError> {
     let parsed_mail =
       mailparse::parse_mail(input).map_err(to_config_error_other("S/MIME parse failure"))?;
 
+    if matches!(
+      parsed_mail.ctype.mimetype.as_str(),
+      "application/pkcs7-mime" | "application/x-pkcs7-mime"
+    ) {
+      let signature_der = Bytes::from(
+        parsed_mail
+          .get_body_raw()
+          .map_err(to_config_error_other("S/MIME signed-data read failure"))?,
+      );
+      let content = Self::wrapped_content(&signature_der)?;
+      return Ok(SignedDocument {
+        content,
+        signature_der,
+      });
+    }
+
     match parsed_mail.subparts.as_slice() {
       [doc_content, signature] => {
         let mut content = Vec::<u8>::from(doc_content.raw_bytes);
@@ -85,11 +125,34 @@ impl SignedDocument {
     }
   }
 
+  // In the wrapped format the signed content is carried inside the SignedData
+  // as its encapsulated content, so no line ending reconstruction is needed.
+  fn wrapped_content(signature_der: &[u8]) -> Result<Bytes, ConfigError> {
+    let content_info = EncapsulatedContentInfo::from_der(signature_der)
+      .map_err(to_config_error_pkcs7("Cannot parse PKCS#7 signed-data"))?;
+    let signed_data = content_info
+      .econtent
+      .ok_or_else(|| pkcs7_config_error("SignedData: Empty container?".to_owned()))?
+      .decode_as::<SignedData>()
+      .map_err(to_config_error_pkcs7("Cannot decode SignedData"))?;
+    let content = signed_data
+      .encap_content_info
+      .econtent
+      .ok_or_else(|| {
+        pkcs7_config_error(
+          "SignedData has no encapsulated content. Is the signature detached?".to_owned(),
+        )
+      })?
+      .decode_as::<OctetString>()
+      .map_err(to_config_error_pkcs7("Cannot decode encapsulated content"))?;
+    Ok(Bytes::copy_from_slice(content.as_bytes()))
+  }
+
   // Use given X.509 certificate (in PEM format) to verify signature
   // and check that the data matches the signature.
   //
   // If successful, returns reference to the verified document.
-  pub fn verify_signature(&self, certificate: &Certificate) -> SecurityResult<impl AsRef<[u8]>> {
+  pub fn verify_signature(&self, certificate: &Certificate) -> SecurityResult<Bytes> {
     // start parsing signature
     let signature_encap = EncapsulatedContentInfo::from_der(&self.signature_der)
       .map_err(to_config_error_pkcs7("Cannot parse PKCS#7 signature"))?;
@@ -537,4 +600,112 @@ iHhbVPRB9Uxts9CwglxYgZoUdGUAxreYIIaLO4yLqw==
     //   DomainGovernanceDocument::from_xml(&
     // String::from_utf8_lossy(verified_dgd_xml.as_ref()))     .unwrap();
   }
+
+  // Signed with the permissions CA in examples/security_configuration_files,
+  // see sign-test-configurations.sh there.
+  const PERMISSIONS_CA_CERTIFICATE: &[u8] =
+    include_bytes!("../../../../examples/security_configuration_files/permissions_ca.cert.pem");
+  const GOVERNANCE_DETACHED: &[u8] =
+    include_bytes!("../../../../examples/security_configuration_files/governance.p7s");
+  const GOVERNANCE_WRAPPED: &[u8] =
+    include_bytes!("../../../../examples/security_configuration_files/governance_wrapped.p7s");
+  const PERMISSIONS_DETACHED: &[u8] =
+    include_bytes!("../../../../examples/security_configuration_files/permissions.p7s");
+  const PERMISSIONS_WRAPPED: &[u8] =
+    include_bytes!("../../../../examples/security_configuration_files/permissions_wrapped.p7s");
+
+  // Replaces the first occurrence of `from` with `to` of the same length, so
+  // that the envelope stays well-formed and only the signature check can fail.
+  fn replace_once(bytes: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    assert_eq!(from.len(), to.len());
+    let position = bytes
+      .windows(from.len())
+      .position(|window| window == from)
+      .unwrap();
+    let mut result = bytes.to_vec();
+    result[position..position + to.len()].copy_from_slice(to);
+    result
+  }
+
+  fn tamper_detached(document: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    replace_once(document, from, to)
+  }
+
+  // The wrapped content is base64 encoded, so tamper with the decoded DER and
+  // re-wrap it with a binary transfer encoding.
+  fn tamper_wrapped(document: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let der = mailparse::parse_mail(document)
+      .unwrap()
+      .get_body_raw()
+      .unwrap();
+    let mut tampered = b"MIME-Version: 1.0\r\n\
+      Content-Type: application/pkcs7-mime; smime-type=signed-data\r\n\
+      Content-Transfer-Encoding: binary\r\n\r\n"
+      .to_vec();
+    tampered.extend(replace_once(&der, from, to));
+    tampered
+  }
+
+  #[test]
+  pub fn verify_example_documents() {
+    let cert = Certificate::from_pem(PERMISSIONS_CA_CERTIFICATE).unwrap();
+
+    for signed in [GOVERNANCE_DETACHED, GOVERNANCE_WRAPPED] {
+      let xml = verified_content("governance", signed, &cert).unwrap();
+      DomainGovernanceDocument::from_xml(&String::from_utf8_lossy(&xml)).unwrap();
+    }
+    for signed in [PERMISSIONS_DETACHED, PERMISSIONS_WRAPPED] {
+      let xml = verified_content("permissions", signed, &cert).unwrap();
+      DomainParticipantPermissions::from_xml(&String::from_utf8_lossy(&xml)).unwrap();
+    }
+  }
+
+  #[test]
+  pub fn tampered_example_documents_are_rejected() {
+    let cert = Certificate::from_pem(PERMISSIONS_CA_CERTIFICATE).unwrap();
+    let governance_change: (&[u8], &[u8]) = (b"<max>100</max>", b"<max>999</max>");
+    let permissions_change: (&[u8], &[u8]) = (b"CN=participant1", b"CN=participant9");
+
+    let tampered = [
+      (
+        "domain governance document",
+        tamper_detached(
+          GOVERNANCE_DETACHED,
+          governance_change.0,
+          governance_change.1,
+        ),
+      ),
+      (
+        "domain governance document",
+        tamper_wrapped(GOVERNANCE_WRAPPED, governance_change.0, governance_change.1),
+      ),
+      (
+        "permissions document",
+        tamper_detached(
+          PERMISSIONS_DETACHED,
+          permissions_change.0,
+          permissions_change.1,
+        ),
+      ),
+      (
+        "permissions document",
+        tamper_wrapped(
+          PERMISSIONS_WRAPPED,
+          permissions_change.0,
+          permissions_change.1,
+        ),
+      ),
+    ];
+
+    for (document_name, signed) in tampered {
+      let error = verified_content(document_name, &signed, &cert).unwrap_err();
+      assert!(
+        error.msg.starts_with(&format!(
+          "Signature verification of the {document_name} failed"
+        )),
+        "{error}"
+      );
+      assert!(error.msg.contains("does not match"), "{error}");
+    }
+  }
 }
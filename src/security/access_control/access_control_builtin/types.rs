@@ -12,7 +12,7 @@ use crate::{
       TopicSecurityAttributes,
     },
     authentication::authentication_builtin::types::CertificateAlgorithm,
-    certificate::DistinguishedName,
+    certificate::{Certificate, DistinguishedName},
     security_error, BinaryProperty, DataHolder, PluginEndpointSecurityAttributesMask,
     PluginParticipantSecurityAttributesMask, PluginSecurityAttributesMask, Property, SecurityError,
     SecurityResult,
@@ -240,6 +240,16 @@ pub(in crate::security) const QOS_GOVERNANCE_DOCUMENT_PROPERTY_NAME: &str =
   "dds.sec.access.governance";
 pub(in crate::security) const QOS_PERMISSIONS_DOCUMENT_PROPERTY_NAME: &str =
   "dds.sec.access.permissions";
+// Not in the specification. Whole seconds by which a grant is still accepted
+// before or after its validity period, to allow for clock differences
+// between participants.
+pub(in crate::security) const QOS_CLOCK_SKEW_TOLERANCE_PROPERTY_NAME: &str =
+  "dds.sec.access.clock_skew_tolerance";
+// Not in the specification. "false" makes the participant verify and parse
+// its governance and permissions documents, and those of remote
+// participants, instead of sharing them through the process-wide cache.
+pub(in crate::security) const QOS_DOCUMENT_CACHE_PROPERTY_NAME: &str =
+  "dds.sec.access.document_cache";
 
 // 9.4.2.2
 pub(super) struct BuiltinPermissionsToken {
@@ -269,14 +279,75 @@ impl From<BuiltinPermissionsToken> for PermissionsToken {
           }),
         ]
         .into_iter()
-        .collect::<Option<Vec<Property>>>()
-        .unwrap_or_default(),
+        .flatten()
+        .collect(),
         binary_properties: Vec::new(),
       },
     }
   }
 }
 
+impl TryFrom<PermissionsToken> for BuiltinPermissionsToken {
+  type Error = SecurityError;
+
+  fn try_from(token: PermissionsToken) -> SecurityResult<Self> {
+    // The class ID is not checked here: validate_remote_permissions accepts
+    // any class ID with the same PluginClassName and MajorVersion.
+    let properties_map = token.data_holder.properties_as_map();
+
+    let permissions_ca_subject_name = properties_map
+      .get(PERMISSIONS_TOKEN_SUBJECT_NAME_PROPERTY_NAME)
+      .map(|prop| {
+        DistinguishedName::parse(&prop.value()).map_err(|e| {
+          create_security_error_and_log!(
+            "Invalid {PERMISSIONS_TOKEN_SUBJECT_NAME_PROPERTY_NAME} in PermissionsToken: {e:?}"
+          )
+        })
+      })
+      .transpose()?;
+
+    let permissions_ca_algorithm = properties_map
+      .get(PERMISSIONS_TOKEN_ALGORITHM_PROPERTY_NAME)
+      .map(|prop| CertificateAlgorithm::try_from(prop.value()))
+      .transpose()?;
+
+    Ok(Self {
+      permissions_ca_subject_name,
+      permissions_ca_algorithm,
+    })
+  }
+}
+
+impl BuiltinPermissionsToken {
+  // Checks that what a remote token claims about its permissions CA agrees
+  // with the permissions CA that verified the remote's permissions document.
+  // Both claims are optional, and a missing claim is not checked.
+  pub fn check_permissions_ca(
+    &self,
+    permissions_ca_certificate: &Certificate,
+  ) -> SecurityResult<()> {
+    if let Some(subject_name) = &self.permissions_ca_subject_name {
+      if !subject_name.matches(permissions_ca_certificate.subject_name()) {
+        return Err(create_security_error_and_log!(
+          "PermissionsToken claims permissions CA {subject_name}, but the permissions document \
+           was verified by {}",
+          permissions_ca_certificate.subject_name()
+        ));
+      }
+    }
+    if let Some(algorithm) = self.permissions_ca_algorithm {
+      if Some(algorithm) != permissions_ca_certificate.algorithm() {
+        return Err(create_security_error_and_log!(
+          "PermissionsToken claims permissions CA algorithm {algorithm:?}, but the permissions CA \
+           uses {:?}",
+          permissions_ca_certificate.algorithm()
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
 const PERMISSIONS_CREDENTIAL_TOKEN_CLASS_ID: &str = "DDS:Access:PermissionsCredential";
 const PERMISSIONS_CREDENTIAL_TOKEN_DOCUMENT_NAME: &str = "dds.perm.cert"; // Why is this cert, if the property contains the permissions document
 
@@ -322,7 +393,7 @@ impl TryFrom<PermissionsCredentialToken> for BuiltinPermissionsCredentialToken {
     }
 
     let builtin_token = Self {
-      permissions_document: dh.get_binary_property("dds.perm.cert")?,
+      permissions_document: dh.get_binary_property(PERMISSIONS_CREDENTIAL_TOKEN_DOCUMENT_NAME)?,
     };
     Ok(builtin_token)
   }
@@ -333,3 +404,126 @@ pub(super) enum Entity {
   Datareader,
   Topic,
 }
+
+#[cfg(test)]
+mod tests {
+  use speedy::Endianness;
+
+  use super::*;
+
+  #[test]
+  pub fn permissions_token_round_trip() {
+    let token: PermissionsToken = BuiltinPermissionsToken {
+      permissions_ca_subject_name: Some(
+        DistinguishedName::parse("CN=permissions_ca,O=Example Organization").unwrap(),
+      ),
+      permissions_ca_algorithm: Some(CertificateAlgorithm::ECPrime256v1),
+    }
+    .into();
+    assert_eq!(token.class_id(), PERMISSIONS_TOKEN_CLASS_ID);
+
+    // Through the serialization used in participant discovery
+    let bytes = token
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+    let received =
+      PermissionsToken::read_from_buffer_with_ctx(Endianness::LittleEndian, &bytes).unwrap();
+    assert_eq!(received, token);
+
+    let BuiltinPermissionsToken {
+      permissions_ca_subject_name,
+      permissions_ca_algorithm,
+    } = BuiltinPermissionsToken::try_from(received).unwrap();
+    assert_eq!(
+      permissions_ca_subject_name.unwrap().serialize(),
+      "CN=permissions_ca,O=Example Organization"
+    );
+    assert_eq!(
+      permissions_ca_algorithm,
+      Some(CertificateAlgorithm::ECPrime256v1)
+    );
+  }
+
+  #[test]
+  pub fn permissions_token_claims_are_optional() {
+    let token: PermissionsToken = BuiltinPermissionsToken {
+      permissions_ca_subject_name: None,
+      permissions_ca_algorithm: None,
+    }
+    .into();
+    assert!(token.data_holder.properties.is_empty());
+
+    let builtin_token = BuiltinPermissionsToken::try_from(token).unwrap();
+    assert!(builtin_token.permissions_ca_subject_name.is_none());
+    assert!(builtin_token.permissions_ca_algorithm.is_none());
+
+    // Either claim can be present without the other
+    let token: PermissionsToken = BuiltinPermissionsToken {
+      permissions_ca_subject_name: None,
+      permissions_ca_algorithm: Some(CertificateAlgorithm::RSA2048),
+    }
+    .into();
+    let builtin_token = BuiltinPermissionsToken::try_from(token).unwrap();
+    assert!(builtin_token.permissions_ca_subject_name.is_none());
+    assert_eq!(
+      builtin_token.permissions_ca_algorithm,
+      Some(CertificateAlgorithm::RSA2048)
+    );
+  }
+
+  #[test]
+  pub fn invalid_permissions_token_claims_are_rejected() {
+    let token_with = |name: &str, value: &str| PermissionsToken {
+      data_holder: DataHolder {
+        class_id: PERMISSIONS_TOKEN_CLASS_ID.into(),
+        properties: vec![Property {
+          name: name.into(),
+          value: value.into(),
+          propagate: true,
+        }],
+        binary_properties: Vec::new(),
+      },
+    };
+    assert!(BuiltinPermissionsToken::try_from(token_with(
+      PERMISSIONS_TOKEN_ALGORITHM_PROPERTY_NAME,
+      "ROT13"
+    ))
+    .is_err());
+    assert!(BuiltinPermissionsToken::try_from(token_with(
+      PERMISSIONS_TOKEN_SUBJECT_NAME_PROPERTY_NAME,
+      "not a distinguished name"
+    ))
+    .is_err());
+  }
+
+  #[test]
+  pub fn permissions_credential_token_round_trip() {
+    let document = Bytes::from_static(b"signed permissions document");
+    let token: PermissionsCredentialToken = BuiltinPermissionsCredentialToken {
+      permissions_document: document.clone(),
+    }
+    .into();
+    assert_eq!(
+      token.data_holder.class_id,
+      PERMISSIONS_CREDENTIAL_TOKEN_CLASS_ID
+    );
+
+    let BuiltinPermissionsCredentialToken {
+      permissions_document,
+    } = BuiltinPermissionsCredentialToken::try_from(token).unwrap();
+    assert_eq!(permissions_document, document);
+
+    let wrong_class = PermissionsCredentialToken {
+      data_holder: DataHolder {
+        class_id: PERMISSIONS_TOKEN_CLASS_ID.into(),
+        properties: Vec::new(),
+        binary_properties: vec![BinaryProperty {
+          name: PERMISSIONS_CREDENTIAL_TOKEN_DOCUMENT_NAME.into(),
+          value: document,
+          propagate: true,
+        }],
+      },
+    };
+    assert!(BuiltinPermissionsCredentialToken::try_from(wrong_class).is_err());
+  }
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+
 use crate::{
   dds::qos::QosPolicies,
   discovery::{sedp_messages::TopicBuiltinTopicData, SpdpDiscoveredParticipantData},
@@ -26,7 +28,7 @@ pub trait AccessControl:
 }
 
 /// Group1 in 8.8.3
-pub trait ParticipantAccessControl: Send {
+pub trait ParticipantAccessControl: Send + Sync {
   /// validate_local_permissions: section 8.4.2.9.1 of the Security
   /// specification
   fn validate_local_permissions(
@@ -93,13 +95,22 @@ pub trait ParticipantAccessControl: Send {
     &self,
     permissions_handle: PermissionsHandle,
   ) -> SecurityResult<ParticipantSecurityAttributes>;
+
+  /// Not in the specification. Returns the instant at which the currently
+  /// valid permissions of the handle expire. After that, the check operations
+  /// for the handle fail, so a remote participant should be dropped then.
+  fn get_permissions_expiration(
+    &self,
+    permissions_handle: PermissionsHandle,
+  ) -> SecurityResult<DateTime<Utc>>;
 }
 
 /// Group2 and Group3 in 8.8.3
-pub trait LocalEntityAccessControl: Send {
+pub trait LocalEntityAccessControl: Send + Sync {
   /// check_create_datawriter: section 8.4.2.9.4 of the Security
-  /// specification. The parameters partition and data_tag have been left out,
-  /// since RustDDS does not yet support PartitionQoS or data tagging
+  /// specification. The partition parameter is taken from the Partition QoS
+  /// policy in qos. The parameter data_tag has been left out, since RustDDS
+  /// does not yet support data tagging of local entities.
   /// In the returned Ok-variant, the boolean tells if the participant passed
   /// the permission check.
   fn check_create_datawriter(
@@ -111,8 +122,9 @@ pub trait LocalEntityAccessControl: Send {
   ) -> SecurityResult<bool>;
 
   /// check_create_datareader: section 8.4.2.9.5 of the Security
-  /// specification. The parameters partition and data_tag have been left out,
-  /// since RustDDS does not yet support PartitionQoS or data tagging
+  /// specification. The partition parameter is taken from the Partition QoS
+  /// policy in qos. The parameter data_tag has been left out, since RustDDS
+  /// does not yet support data tagging of local entities.
   /// In the returned Ok-variant, the boolean tells if the participant passed
   /// the permission check.
   fn check_create_datareader(
@@ -173,8 +185,8 @@ pub trait LocalEntityAccessControl: Send {
 
   /// get_datawriter_sec_attributes: section 8.4.2.9.24 of the Security
   /// specification.
-  /// The parameters partition and data_tag have been left out,
-  /// since RustDDS does not yet support PartitionQoS or data tagging
+  /// The parameters partition and data_tag have been left out, since the
+  /// attributes depend only on the topic in the governance document
   fn get_datawriter_sec_attributes(
     &self,
     permissions_handle: PermissionsHandle,
@@ -183,8 +195,8 @@ pub trait LocalEntityAccessControl: Send {
 
   /// get_datareader_sec_attributes: section 8.4.2.9.25 of the Security
   /// specification.
-  /// The parameters partition and data_tag have been left out,
-  /// since RustDDS does not yet support PartitionQoS or data tagging
+  /// The parameters partition and data_tag have been left out, since the
+  /// attributes depend only on the topic in the governance document
   fn get_datareader_sec_attributes(
     &self,
     permissions_handle: PermissionsHandle,
@@ -193,7 +205,7 @@ pub trait LocalEntityAccessControl: Send {
 }
 
 /// Group4 and Group5 in 8.8.3
-pub trait RemoteEntityAccessControl: Send {
+pub trait RemoteEntityAccessControl: Send + Sync {
   /// check_remote_datawriter: section 8.4.2.9.10 of the Security
   /// specification.
   /// In the returned Ok-variant, the boolean tells if the participant passed
@@ -245,7 +257,7 @@ pub trait RemoteEntityAccessControl: Send {
    /// check_local_datareader_match: section 8.4.2.9.14 of the Security
    /// specification.
    /// The parameter subscriber_partition is omitted since RustDDS does not yet
-   /// support PartitionQoS.
+   /// use partitions in matching.
    // Support for this is not yet implemented as the builtin plugin does not need it
    fn check_local_datareader_match(
      &self,
@@ -99,6 +99,18 @@ impl From<ParticipantSecurityAttributes> for ParticipantSecurityAttributesMask {
   }
 }
 
+// Outcome of the access control checks on a remote participant. Not in the
+// specification: when the governance allows unauthenticated participants, a
+// remote that does not pass the checks may still communicate on the
+// unprotected topics as an "Unauthenticated" participant (see section 8.8.2.1
+// of the Security specification).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RemoteParticipantPermission {
+  Allowed,
+  AllowedUnauthenticated,
+  Denied,
+}
+
 // TopicSecurityAttributes: section 8.4.2.6 of the Security specification
 // (v. 1.1)
 #[derive(Clone, Copy)]
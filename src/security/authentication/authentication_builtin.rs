@@ -1,6 +1,7 @@
 use std::{
   collections::HashMap,
   fmt::{self, Formatter},
+  sync::Arc,
 };
 
 use bytes::Bytes;
@@ -10,15 +11,17 @@ use ring::agreement;
 use crate::{
   create_security_error_and_log,
   security::{
-    access_control::PermissionsToken, certificate, private_key, security_error, SecurityError,
-    SecurityResult,
+    access_control::PermissionsToken,
+    certificate,
+    entropy::{random_bytes, EntropySource, SystemEntropySource},
+    private_key, SecurityError, SecurityResult,
   },
   GUID,
 };
 use self::types::{DH_MODP_KAGREE_ALGO_NAME, ECDH_KAGREE_ALGO_NAME};
 use super::{
   authentication_builtin::types::BuiltinIdentityToken, Challenge, HandshakeHandle, IdentityHandle,
-  /* IdentityToken, */ Sha256, SharedSecret,
+  IdentityToken, Sha256, SharedSecret,
 };
 
 mod authentication;
@@ -30,7 +33,11 @@ pub(in crate::security) mod types;
 // advance to the next step.
 #[derive(Debug)]
 pub(crate) enum BuiltinHandshakeState {
-  PendingRequestSend,    // We need to create & send the handshake request
+  PendingRequestSend {
+    // We need to create & send the handshake request. If the remote sent us an
+    // AuthRequestMessageToken, its future_challenge is to be used as challenge1
+    future_challenge_opt: Option<Challenge>,
+  },
   PendingRequestMessage, // We are waiting for a handshake request from remote participant
   PendingReplyMessage {
     // We have sent a handshake request and are waiting for a reply
@@ -85,6 +92,14 @@ pub(crate) enum DiscHandshakeState {
   CompletedWithFinalMessageReceived,
 }
 
+// Subject name of the identity certificate that a remote participant announces
+// in its IdentityToken. Also for use in secure Discovery.
+pub(crate) fn identity_token_certificate_subject(token: &IdentityToken) -> Option<String> {
+  BuiltinIdentityToken::try_from(token.clone())
+    .ok()?
+    .certificate_subject
+}
+
 struct LocalParticipantInfo {
   identity_handle: IdentityHandle,
   identity_token: BuiltinIdentityToken,
@@ -197,11 +212,21 @@ pub struct AuthenticationBuiltin {
   // From ring documentation (https://docs.rs/ring/latest/ring/rand/index.html):
   // "An application should create a single SystemRandom and then use it for all randomness
   // generation"
+  // This is used only for the Diffie-Hellman keys, as ring does not accept other generators.
   secure_random_generator: ring::rand::SystemRandom,
+
+  // Source of the random bytes for challenges and shared secrets
+  entropy_source: Arc<dyn EntropySource>,
 }
 
 impl AuthenticationBuiltin {
   pub fn new() -> Self {
+    Self::with_entropy_source(Arc::new(SystemEntropySource::new()))
+  }
+
+  /// Creates the plugin with a custom source for the random challenges of the
+  /// authentication handshake
+  pub fn with_entropy_source(entropy_source: Arc<dyn EntropySource>) -> Self {
     Self {
       local_participant_info: None, // No info yet
       remote_participant_infos: HashMap::new(),
@@ -209,6 +234,7 @@ impl AuthenticationBuiltin {
       next_identity_handle: 0,
       next_handshake_handle: 0,
       secure_random_generator: ring::rand::SystemRandom::new(),
+      entropy_source,
     }
   }
 
@@ -275,8 +301,6 @@ impl AuthenticationBuiltin {
   }
 
   fn generate_random_32_bytes(&self) -> SecurityResult<[u8; 32]> {
-    ring::rand::generate::<[u8; 32]>(&self.secure_random_generator)
-      .map(|random| random.expose())
-      .map_err(|e| security_error(&format!("Failed to generate random bytes: {}", e)))
+    random_bytes(self.entropy_source.as_ref())
   }
 }
@@ -2,6 +2,7 @@ use std::cmp::Ordering;
 
 use byteorder::BigEndian;
 use bytes::Bytes;
+use chrono::Utc;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 
@@ -12,8 +13,9 @@ use crate::{
     authentication::{
       authentication_builtin::{
         types::{
-          BuiltinHandshakeMessageToken, CertificateAlgorithm, HANDSHAKE_FINAL_CLASS_ID,
-          HANDSHAKE_REPLY_CLASS_ID, HANDSHAKE_REQUEST_CLASS_ID, IDENTITY_TOKEN_CLASS_ID,
+          BuiltinAuthRequestMessageToken, BuiltinHandshakeMessageToken, CertificateAlgorithm,
+          HANDSHAKE_FINAL_CLASS_ID, HANDSHAKE_REPLY_CLASS_ID, HANDSHAKE_REQUEST_CLASS_ID,
+          IDENTITY_TOKEN_CLASS_ID,
         },
         HandshakeInfo,
       },
@@ -37,6 +39,39 @@ use super::{
   BuiltinHandshakeState, DHKeys, LocalParticipantInfo, RemoteParticipantInfo,
 };
 
+// The initial handshake state depends on the lexicographic ordering of the
+// participant GUID prefixes: the participant with the lower one initiates the
+// handshake by sending the request. Note that the derived Ord trait produces
+// the required lexicographic ordering.
+pub(in crate::security) fn initial_validation_outcome(
+  local_participant_guidp: GuidPrefix,
+  remote_participant_guidp: GuidPrefix,
+) -> SecurityResult<ValidationOutcome> {
+  match local_participant_guidp.cmp(&remote_participant_guidp) {
+    // Our GUID is lower than remote's. We should send the request to remote
+    Ordering::Less => Ok(ValidationOutcome::PendingHandshakeRequest),
+    // Our GUID is higher than remote's. We should wait for the request from remote
+    Ordering::Greater => Ok(ValidationOutcome::PendingHandshakeMessage),
+    // This is an error, comparing with ourself.
+    Ordering::Equal => Err(create_security_error_and_log!(
+      "Remote GUID is equal to the local GUID"
+    )),
+  }
+}
+
+// The signature algorithm announced in a handshake message (c.dsign_algo) must
+// be the one that the key in the certificate of the message signs with
+fn check_signature_algorithm(certificate: &Certificate, dsign_algo: &[u8]) -> SecurityResult<()> {
+  if certificate.signature_algorithm_identifier()? == dsign_algo {
+    Ok(())
+  } else {
+    Err(create_security_error_and_log!(
+      "The signature algorithm in the handshake message does not match the certificate of {}",
+      certificate.subject_name()
+    ))
+  }
+}
+
 // GUID start from certificate subject name, as dictated in DDS Security spec
 // v1.1 Section "9.3.3 DDS:Auth:PKI-DH plugin behavior", Table 52
 fn guid_start_from_certificate(identity_cert: &Certificate) -> SecurityResult<[u8; 6]> {
@@ -134,25 +169,22 @@ impl Authentication for AuthenticationBuiltin {
           .map_err(|e| create_security_error_and_log!("{e:?}"))
       })?;
 
+    // The password decrypts the private key, or the PKCS#12 bundle that holds
+    // both the identity certificate and the private key
+    let password = participant_qos.get_optional_property(QOS_PASSWORD_PROPERTY_NAME);
+
     let identity_certificate = participant_qos
       .get_property(QOS_IDENTITY_CERTIFICATE_PROPERTY_NAME)
       .and_then(|certificate_uri| {
-        read_uri(&certificate_uri).map_err(|conf_err| {
+        read_uri_to_certificate(&certificate_uri, password.as_deref()).map_err(|conf_err| {
           create_security_error_and_log!(
             "Failed to read the DomainParticipant identity certificate from {}: {:?}",
             certificate_uri,
             conf_err
           )
         })
-      })
-      .and_then(|certificate_contents_pem| {
-        Certificate::from_pem(certificate_contents_pem)
-          .map_err(|e| create_security_error_and_log!("{e:?}"))
       })?;
 
-    // TODO: decrypt a password protected private key
-    let _password = participant_qos.get_optional_property(QOS_PASSWORD_PROPERTY_NAME);
-
     let id_cert_algorithm = identity_certificate.algorithm().ok_or_else(|| {
       create_security_error_and_log!("Cannot recognize identity certificate algorithm.")
     })?;
@@ -160,13 +192,15 @@ impl Authentication for AuthenticationBuiltin {
     let id_cert_private_key = participant_qos
       .get_property(QOS_PRIVATE_KEY_PROPERTY_NAME)
       .and_then(|pem_uri| {
-        read_uri_to_private_key(&pem_uri, id_cert_algorithm).map_err(|conf_err| {
-          create_security_error_and_log!(
-            "Failed to read the DomainParticipant identity private key from {}: {:?}",
-            pem_uri,
-            conf_err
-          )
-        })
+        read_uri_to_private_key(&pem_uri, id_cert_algorithm, password.as_deref()).map_err(
+          |conf_err| {
+            create_security_error_and_log!(
+              "Failed to read the DomainParticipant identity private key from {}: {:?}",
+              pem_uri,
+              conf_err
+            )
+          },
+        )
       })?;
 
     // Verify that CA has signed our identity
@@ -178,6 +212,12 @@ impl Authentication for AuthenticationBuiltin {
         )
       })?;
 
+    // An identity certificate outside its validity period would be rejected by
+    // the remotes as well
+    identity_certificate
+      .check_validity_period(Utc::now())
+      .map_err(|e| create_security_error_and_log!("{}", e.msg))?;
+
     // TODO: Check (somehow) that my identity has not been revoked.
 
     // Compute the new adjusted GUID. The start is computed from the hash of the
@@ -313,7 +353,7 @@ impl Authentication for AuthenticationBuiltin {
   // anything, but it starts the authentication protocol.
   fn validate_remote_identity(
     &mut self,
-    _remote_auth_request_token: Option<AuthRequestMessageToken>, // Unused, see below.
+    remote_auth_request_token: Option<AuthRequestMessageToken>,
     local_identity_handle: IdentityHandle,
     remote_identity_token: IdentityToken,
     remote_participant_guidp: GuidPrefix,
@@ -341,36 +381,42 @@ impl Authentication for AuthenticationBuiltin {
       ));
     }
 
-    // Since built-in authentication does not use AuthRequestMessageToken, we ignore
-    // them completely. Always return the token as None.
-    let auth_request_token = None;
-
-    // The initial handshake state depends on the lexicographic ordering of the
-    // participant GUIDs. Note that the derived Ord trait produces the required
-    // lexicographic ordering.
-    let (handshake_state, validation_outcome) =
-      match local_info.guid.prefix.cmp(&remote_participant_guidp) {
-        Ordering::Less => {
-          // Our GUID is lower than remote's. We should send the request to remote
-          (
-            BuiltinHandshakeState::PendingRequestSend,
-            ValidationOutcome::PendingHandshakeRequest,
-          )
-        }
-        Ordering::Greater => {
-          // Our GUID is higher than remote's. We should wait for the request from remote
-          (
-            BuiltinHandshakeState::PendingRequestMessage,
-            ValidationOutcome::PendingHandshakeMessage,
-          )
-        }
-        Ordering::Equal => {
-          // This is an error, comparing with ourself.
-          return Err(create_security_error_and_log!(
-            "Remote GUID is equal to the local GUID"
-          ));
-        }
-      };
+    let validation_outcome =
+      initial_validation_outcome(local_info.guid.prefix, remote_participant_guidp)?;
+
+    let (handshake_state, auth_request_token) = match validation_outcome {
+      ValidationOutcome::PendingHandshakeRequest => {
+        // If the remote has asked us to start the handshake, we use its
+        // future_challenge as challenge1 of the request
+        let future_challenge_opt = remote_auth_request_token
+          .map(BuiltinAuthRequestMessageToken::try_from)
+          .transpose()?
+          .map(|token| token.future_challenge);
+        (
+          BuiltinHandshakeState::PendingRequestSend {
+            future_challenge_opt,
+          },
+          None,
+        )
+      }
+      _ => {
+        // We are waiting for the request. Unless the remote has already
+        // asked for a handshake, ask it to start one with a fresh future
+        // challenge.
+        let auth_request_token = if remote_auth_request_token.is_none() {
+          let future_challenge = Challenge::from(self.generate_random_32_bytes()?);
+          Some(AuthRequestMessageToken::from(
+            BuiltinAuthRequestMessageToken { future_challenge },
+          ))
+        } else {
+          None
+        };
+        (
+          BuiltinHandshakeState::PendingRequestMessage,
+          auth_request_token,
+        )
+      }
+    };
 
     // Get new identity handle for the remote and associate remote info with it
     let remote_identity_handle = self.get_new_identity_handle();
@@ -415,18 +461,26 @@ impl Authentication for AuthenticationBuiltin {
     let remote_info = self.get_remote_participant_info(&replier_identity_handle)?;
 
     // Make sure we are expecting to send the authentication request message
-    if let BuiltinHandshakeState::PendingRequestSend = remote_info.handshake.state {
-      // Yes, this is what we expect. No action here.
+    let future_challenge_opt = if let BuiltinHandshakeState::PendingRequestSend {
+      future_challenge_opt,
+    } = &remote_info.handshake.state
+    {
+      // Yes, this is what we expect.
+      future_challenge_opt.clone()
     } else {
       return Err(create_security_error_and_log!(
         "We are not expecting to send a handshake request. Handshake state: {:?}",
         remote_info.handshake.state
       ));
-    }
+    };
 
     // We send the request so we get to decide the key agreement algorithm.
-    // We choose to use the elliptic curve Diffie-Hellman
-    let dh_keys = DHKeys::new_ec_keys(&self.secure_random_generator)?;
+    // We choose the elliptic curve Diffie-Hellman for elliptic curve identities
+    // and the modular exponential one for RSA identities
+    let dh_keys = match local_info.identity_certificate.algorithm() {
+      Some(CertificateAlgorithm::RSA2048) => DHKeys::new_modp_keys()?,
+      _ => DHKeys::new_ec_keys(&self.secure_random_generator)?,
+    };
 
     let pdata_bytes = Bytes::from(serialized_local_participant_data);
 
@@ -447,12 +501,16 @@ impl Authentication for AuthenticationBuiltin {
     let hash_c1 = Sha256::hash(
       &to_vec::<Vec<BinaryProperty>, BigEndian>(&c_properties).map_err(|e| SecurityError {
         msg: format!("Error serializing C1: {}", e),
+        kind: SecurityErrorKind::Internal,
       })?,
     );
 
-    // This is an initiator-generated 256-bit nonce
-    let random_bytes = self.generate_random_32_bytes()?;
-    let challenge1 = Challenge::from(random_bytes);
+    // This is an initiator-generated 256-bit nonce, unless the remote gave us
+    // one in its AuthRequestMessageToken
+    let challenge1 = match future_challenge_opt {
+      Some(future_challenge) => future_challenge,
+      None => Challenge::from(self.generate_random_32_bytes()?),
+    };
 
     let handshake_request_builtin = BuiltinHandshakeMessageToken {
       class_id: Bytes::copy_from_slice(HANDSHAKE_REQUEST_CLASS_ID),
@@ -496,7 +554,6 @@ impl Authentication for AuthenticationBuiltin {
     ))
   }
 
-  // Currently only mocked
   fn begin_handshake_reply(
     &mut self,
     handshake_message_in: HandshakeMessageToken,
@@ -534,6 +591,8 @@ impl Authentication for AuthenticationBuiltin {
 
     // Verify that 1's identity cert checks out against CA.
     cert1.verify_signed_by_certificate(&local_info.identity_ca)?;
+    cert1.check_validity_period(Utc::now())?;
+    check_signature_algorithm(&cert1, &request.c_dsign_algo)?;
 
     // Verify that the remote GUID is as specified by the spec
     let remote_pdata =
@@ -576,6 +635,7 @@ impl Authentication for AuthenticationBuiltin {
     let computed_c1_hash = Sha256::hash(
       &to_vec::<Vec<BinaryProperty>, BigEndian>(&c_properties).map_err(|e| SecurityError {
         msg: format!("Error serializing C1: {}", e),
+        kind: SecurityErrorKind::Internal,
       })?,
     );
 
@@ -616,6 +676,7 @@ impl Authentication for AuthenticationBuiltin {
     let c2_hash = Sha256::hash(
       &to_vec::<Vec<BinaryProperty>, BigEndian>(&c2_properties).map_err(|e| SecurityError {
         msg: format!("Error serializing C2: {}", e),
+        kind: SecurityErrorKind::Internal,
       })?,
     );
 
@@ -636,6 +697,7 @@ impl Authentication for AuthenticationBuiltin {
     let contents_signature = local_info.id_cert_private_key.sign(
       &to_vec::<Vec<BinaryProperty>, BigEndian>(&cc2_properties).map_err(|e| SecurityError {
         msg: format!("Error serializing CC2: {}", e),
+        kind: SecurityErrorKind::Internal,
       })?,
     )?;
 
@@ -701,7 +763,7 @@ impl Authentication for AuthenticationBuiltin {
     // key pairs, which cannot be cloned. We just move the "state" out and leave
     // a dummy value behind. At the end of this function we will overwrite the
     // dummy.
-    let mut state = BuiltinHandshakeState::PendingRequestSend; // dummy to leave behind
+    let mut state = BuiltinHandshakeState::PendingRequestMessage; // dummy to leave behind
     std::mem::swap(&mut remote_info.handshake.state, &mut state);
 
     let local_info = self.get_local_participant_info()?;
@@ -724,6 +786,8 @@ impl Authentication for AuthenticationBuiltin {
 
         // Verify that 2's identity cert checks out against CA.
         cert2.verify_signed_by_certificate(&local_info.identity_ca)?;
+        cert2.check_validity_period(Utc::now())?;
+        check_signature_algorithm(&cert2, &reply.c_dsign_algo)?;
 
         // Verify that the remote GUID is as specified by the spec.
         // Note that spec does say that this check needs to be done here. But it seems
@@ -774,6 +838,7 @@ impl Authentication for AuthenticationBuiltin {
         let c2_hash_recomputed = Sha256::hash(
           &to_vec::<Vec<BinaryProperty>, BigEndian>(&c2_properties).map_err(|e| SecurityError {
             msg: format!("Error serializing C2: {}", e),
+            kind: SecurityErrorKind::Internal,
           })?,
         );
 
@@ -820,6 +885,7 @@ impl Authentication for AuthenticationBuiltin {
         cert2.verify_signed_data_with_algorithm(
           to_vec::<Vec<BinaryProperty>, BigEndian>(&cc2_properties).map_err(|e| SecurityError {
             msg: format!("Error serializing CC2: {}", e),
+            kind: SecurityErrorKind::Internal,
           })?,
           reply.signature,
           c2_signature_algorithm,
@@ -862,6 +928,7 @@ impl Authentication for AuthenticationBuiltin {
           &to_vec::<Vec<BinaryProperty>, BigEndian>(&cc_final_properties).map_err(|e| {
             SecurityError {
               msg: format!("Error serializing CC_final: {}", e),
+              kind: SecurityErrorKind::Internal,
             }
           })?,
         )?;
@@ -996,6 +1063,7 @@ impl Authentication for AuthenticationBuiltin {
             to_vec::<Vec<BinaryProperty>, BigEndian>(&cc_final_properties).map_err(|e| {
               SecurityError {
                 msg: format!("Error serializing CC_final: {}", e),
+                kind: SecurityErrorKind::Internal,
               }
             })?,
             final_token.signature,
@@ -1103,9 +1171,334 @@ impl Authentication for AuthenticationBuiltin {
 
 #[cfg(test)]
 mod tests {
-  use crate::structure::guid::EntityKind;
+  use std::{path::Path, sync::Arc};
+
+  use crate::{
+    security::{
+      authentication::authentication_builtin::types::RSA_SIGNATURE_ALGO_NAME,
+      config::{DomainParticipantSecurityConfigFiles, PrivateSigningKey},
+      entropy::test_sources::{CountingEntropySource, FailingEntropySource},
+    },
+    serialization::pl_cdr_adapters::PlCdrSerialize,
+    structure::guid::EntityKind,
+    test::test_data::spdp_participant_data,
+    QosPolicyBuilder, RepresentationIdentifier,
+  };
   use super::*;
 
+  const CONFIG_DIR: &str = "examples/security_configuration_files";
+
+  // Participant QoS with the given identity certificate and private key from
+  // the example configuration directory
+  fn identity_qos(certificate: &str, private_key: &str) -> QosPolicies {
+    identity_qos_with_ca("identity_ca.cert.pem", certificate, private_key)
+  }
+
+  fn identity_qos_with_ca(identity_ca: &str, certificate: &str, private_key: &str) -> QosPolicies {
+    identity_qos_with_ca_and_password(identity_ca, certificate, private_key, "no_pwd")
+  }
+
+  fn identity_qos_with_ca_and_password(
+    identity_ca: &str,
+    certificate: &str,
+    private_key: &str,
+    password: &str,
+  ) -> QosPolicies {
+    let config_dir = Path::new(CONFIG_DIR);
+    let config_files = DomainParticipantSecurityConfigFiles {
+      identity_ca_certificate: config_dir.join(identity_ca),
+      participant_identity_certificate: config_dir.join(certificate),
+      participant_identity_private_key: PrivateSigningKey::Files {
+        file_path: config_dir.join(private_key),
+        file_password: password.to_string(),
+      },
+      ..DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        CONFIG_DIR,
+        "no_pwd".to_string(),
+      )
+    };
+    QosPolicyBuilder::new()
+      .property(config_files.into_property_policy())
+      .build()
+  }
+
+  // One side of a handshake, after the identities have been validated
+  struct HandshakeParty {
+    auth: AuthenticationBuiltin,
+    local: IdentityHandle,
+    remote: IdentityHandle,
+    pdata: Vec<u8>,
+  }
+
+  // A participant with the example identity, along with its serialized
+  // participant data for the handshake messages
+  fn local_participant(
+    auth: &mut AuthenticationBuiltin,
+    qos: &QosPolicies,
+  ) -> (IdentityHandle, GUID, Vec<u8>) {
+    let (outcome, identity_handle, guid) = auth
+      .validate_local_identity(0, qos, GUID::new_participant_guid())
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::Ok);
+
+    let mut participant_data = spdp_participant_data().unwrap();
+    participant_data.participant_guid = guid;
+    let pdata = participant_data
+      .to_pl_cdr_bytes(RepresentationIdentifier::PL_CDR_BE)
+      .unwrap()
+      .to_vec();
+    (identity_handle, guid, pdata)
+  }
+
+  // Two participants that have validated each other's identity. Returns the
+  // initiator and the replier of the handshake.
+  fn handshake_parties(
+    qos_a: &QosPolicies,
+    qos_b: &QosPolicies,
+  ) -> (HandshakeParty, HandshakeParty) {
+    let mut auth_a = AuthenticationBuiltin::new();
+    let mut auth_b = AuthenticationBuiltin::new();
+    let (local_a, guid_a, pdata_a) = local_participant(&mut auth_a, qos_a);
+    let (local_b, guid_b, pdata_b) = local_participant(&mut auth_b, qos_b);
+
+    let token_a = auth_a.get_identity_token(local_a).unwrap();
+    let token_b = auth_b.get_identity_token(local_b).unwrap();
+    let (outcome_a, remote_b, _) = auth_a
+      .validate_remote_identity(None, local_a, token_b, guid_b.prefix)
+      .unwrap();
+    let (outcome_b, remote_a, _) = auth_b
+      .validate_remote_identity(None, local_b, token_a, guid_a.prefix)
+      .unwrap();
+
+    let party_a = HandshakeParty {
+      auth: auth_a,
+      local: local_a,
+      remote: remote_b,
+      pdata: pdata_a,
+    };
+    let party_b = HandshakeParty {
+      auth: auth_b,
+      local: local_b,
+      remote: remote_a,
+      pdata: pdata_b,
+    };
+
+    // The participant with the lower GUID prefix initiates the handshake
+    if outcome_a == ValidationOutcome::PendingHandshakeRequest {
+      assert_eq!(outcome_b, ValidationOutcome::PendingHandshakeMessage);
+      (party_a, party_b)
+    } else {
+      assert_eq!(outcome_b, ValidationOutcome::PendingHandshakeRequest);
+      (party_b, party_a)
+    }
+  }
+
+  #[test]
+  fn handshake_derives_equal_shared_secrets() {
+    let ec_qos = identity_qos("cert.pem", "key.pem");
+    let rsa_qos = identity_qos_with_ca("rsa_identity_ca.cert.pem", "rsa_cert.pem", "rsa_key.pem");
+    for qos in [ec_qos, rsa_qos] {
+      handshake_derives_equal_shared_secrets_with(&qos);
+    }
+  }
+
+  // Both participants use the same identity configuration
+  fn handshake_derives_equal_shared_secrets_with(qos: &QosPolicies) {
+    let (mut initiator, mut replier) = handshake_parties(qos, qos);
+
+    let (outcome, initiator_handshake, request) = initiator
+      .auth
+      .begin_handshake_request(initiator.local, initiator.remote, initiator.pdata)
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::PendingHandshakeMessage);
+
+    let (outcome, replier_handshake, reply) = replier
+      .auth
+      .begin_handshake_reply(
+        request.clone(),
+        replier.remote,
+        replier.local,
+        replier.pdata,
+      )
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::PendingHandshakeMessage);
+
+    let (outcome, final_message) = initiator
+      .auth
+      .process_handshake(reply.clone(), initiator_handshake)
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::OkFinalMessage);
+
+    let (outcome, no_message) = replier
+      .auth
+      .process_handshake(final_message.unwrap(), replier_handshake)
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::Ok);
+    assert!(no_message.is_none());
+
+    let initiator_secret = initiator.auth.get_shared_secret(initiator.remote).unwrap();
+    let replier_secret = replier.auth.get_shared_secret(replier.remote).unwrap();
+    assert_eq!(initiator_secret.shared_secret, replier_secret.shared_secret);
+    assert_eq!(initiator_secret.challenge1, replier_secret.challenge1);
+    assert_eq!(initiator_secret.challenge2, replier_secret.challenge2);
+
+    // A completed handshake does not accept the messages again
+    assert!(initiator
+      .auth
+      .process_handshake(reply, initiator_handshake)
+      .is_err());
+    assert!(replier
+      .auth
+      .begin_handshake_reply(request, replier.remote, replier.local, Vec::new())
+      .is_err());
+  }
+
+  #[test]
+  fn handshake_with_an_unexpected_algorithm_fails() {
+    let ec_qos = identity_qos("cert.pem", "key.pem");
+    let rsa_qos = identity_qos_with_ca("rsa_identity_ca.cert.pem", "rsa_cert.pem", "rsa_key.pem");
+
+    // The identities are signed by different CAs
+    let (mut initiator, mut replier) = handshake_parties(&ec_qos, &rsa_qos);
+    let (_outcome, _handshake, request) = initiator
+      .auth
+      .begin_handshake_request(initiator.local, initiator.remote, initiator.pdata)
+      .unwrap();
+    assert!(replier
+      .auth
+      .begin_handshake_reply(request, replier.remote, replier.local, replier.pdata)
+      .is_err());
+
+    // The request announces another signature algorithm than that of its
+    // certificate
+    let (mut initiator, mut replier) = handshake_parties(&ec_qos, &ec_qos);
+    let (_outcome, _handshake, request) = initiator
+      .auth
+      .begin_handshake_request(initiator.local, initiator.remote, initiator.pdata)
+      .unwrap();
+    let mut builtin_request = BuiltinHandshakeMessageToken::try_from(request).unwrap();
+    builtin_request.c_dsign_algo = Some(Bytes::from_static(RSA_SIGNATURE_ALGO_NAME));
+    let error = replier
+      .auth
+      .begin_handshake_reply(
+        HandshakeMessageToken::from(builtin_request),
+        replier.remote,
+        replier.local,
+        replier.pdata,
+      )
+      .unwrap_err();
+    assert!(
+      error.msg.contains("participant1_common_name"),
+      "{}",
+      error.msg
+    );
+  }
+
+  // Identity certificates that must not be accepted, along with a part of the
+  // expected error message
+  const INVALID_IDENTITIES: [(&str, &str, &str); 3] = [
+    (
+      "expired_cert.pem",
+      "expired_key.pem",
+      "expired_participant_common_name",
+    ),
+    (
+      "not_yet_valid_cert.pem",
+      "not_yet_valid_key.pem",
+      "not_yet_valid_participant_common_name",
+    ),
+    (
+      "wrong_ca_cert.pem",
+      "wrong_ca_key.pem",
+      "does not verify against identity CA",
+    ),
+  ];
+
+  #[test]
+  fn invalid_local_identity_certificates_are_rejected() {
+    for (certificate, private_key, expected_error) in INVALID_IDENTITIES {
+      let error = AuthenticationBuiltin::new()
+        .validate_local_identity(
+          0,
+          &identity_qos(certificate, private_key),
+          GUID::new_participant_guid(),
+        )
+        .expect_err(certificate);
+      assert!(error.msg.contains(expected_error), "{}", error.msg);
+    }
+  }
+
+  #[test]
+  fn password_protected_local_identities_are_accepted() {
+    for (certificate, private_key) in [
+      ("cert.pem", "encrypted_key.pem"),
+      ("identity.p12", "identity.p12"),
+    ] {
+      let qos = identity_qos_with_ca_and_password(
+        "identity_ca.cert.pem",
+        certificate,
+        private_key,
+        "password123",
+      );
+      let (outcome, _handle, _guid) = AuthenticationBuiltin::new()
+        .validate_local_identity(0, &qos, GUID::new_participant_guid())
+        .expect(private_key);
+      assert_eq!(outcome, ValidationOutcome::Ok);
+
+      let qos = identity_qos_with_ca_and_password(
+        "identity_ca.cert.pem",
+        certificate,
+        private_key,
+        "wrong_password",
+      );
+      let error = AuthenticationBuiltin::new()
+        .validate_local_identity(0, &qos, GUID::new_participant_guid())
+        .expect_err(private_key);
+      assert!(error.msg.contains("Wrong password"), "{}", error.msg);
+    }
+  }
+
+  #[test]
+  fn challenges_are_drawn_from_the_entropy_source() {
+    let auth =
+      AuthenticationBuiltin::with_entropy_source(Arc::new(CountingEntropySource::default()));
+    let expected: Vec<u8> = (0..32).collect();
+    assert_eq!(auth.generate_random_32_bytes().unwrap().to_vec(), expected);
+
+    let auth = AuthenticationBuiltin::with_entropy_source(Arc::new(FailingEntropySource));
+    assert!(auth.generate_random_32_bytes().is_err());
+  }
+
+  #[test]
+  fn invalid_remote_identity_certificates_are_rejected() {
+    let qos = identity_qos("cert.pem", "key.pem");
+    let (mut initiator, mut replier) = handshake_parties(&qos, &qos);
+    let (_outcome, _handshake, request) = initiator
+      .auth
+      .begin_handshake_request(initiator.local, initiator.remote, initiator.pdata)
+      .unwrap();
+
+    for (certificate, _private_key, _expected_error) in INVALID_IDENTITIES {
+      let certificate_pem = std::fs::read(Path::new(CONFIG_DIR).join(certificate)).unwrap();
+      let mut builtin_request = BuiltinHandshakeMessageToken::try_from(request.clone()).unwrap();
+      builtin_request.c_id = Some(Bytes::from(certificate_pem));
+
+      let result = replier.auth.begin_handshake_reply(
+        HandshakeMessageToken::from(builtin_request),
+        replier.remote,
+        replier.local,
+        replier.pdata.clone(),
+      );
+      assert!(result.is_err(), "{certificate} was accepted");
+    }
+
+    // The replier still accepts the original request
+    assert!(replier
+      .auth
+      .begin_handshake_reply(request, replier.remote, replier.local, replier.pdata)
+      .is_ok());
+  }
+
   #[test]
   pub fn validating_invalid_remote_guid_fails() {
     let cert_pem = r#"-----BEGIN CERTIFICATE-----
@@ -1129,4 +1522,78 @@ iHhbVPRB9Uxts9CwglxYgZoUdGUAxreYIIaLO4yLqw==
       "Validating an invalid GUID passed!"
     );
   }
+
+  #[test]
+  fn participant_with_the_lower_guid_prefix_initiates_the_handshake() {
+    let lower = GuidPrefix::new(&[
+      0x01, 0x0f, 0xbb, 0x1d, 0xdf, 0x40, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01,
+    ]);
+    let higher = GuidPrefix::new(&[
+      0x01, 0x0f, 0xbb, 0x1d, 0xdf, 0x40, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00,
+    ]);
+
+    assert_eq!(
+      initial_validation_outcome(lower, higher).unwrap(),
+      ValidationOutcome::PendingHandshakeRequest
+    );
+    assert_eq!(
+      initial_validation_outcome(higher, lower).unwrap(),
+      ValidationOutcome::PendingHandshakeMessage
+    );
+    assert!(initial_validation_outcome(lower, lower).is_err());
+  }
+
+  #[test]
+  fn auth_request_future_challenge_is_used_as_challenge1() {
+    let qos = identity_qos("cert.pem", "key.pem");
+    let mut auth_a = AuthenticationBuiltin::new();
+    let mut auth_b = AuthenticationBuiltin::new();
+    let (local_a, guid_a, pdata_a) = local_participant(&mut auth_a, &qos);
+    let (local_b, guid_b, pdata_b) = local_participant(&mut auth_b, &qos);
+    let token_a = auth_a.get_identity_token(local_a).unwrap();
+    let token_b = auth_b.get_identity_token(local_b).unwrap();
+
+    let mut initiator = (auth_a, local_a, token_a, guid_a, pdata_a);
+    let mut replier = (auth_b, local_b, token_b, guid_b, pdata_b);
+    if guid_a.prefix > guid_b.prefix {
+      std::mem::swap(&mut initiator, &mut replier);
+    }
+    let (mut initiator_auth, initiator_local, initiator_token, initiator_guid, initiator_pdata) =
+      initiator;
+    let (mut replier_auth, replier_local, replier_token, replier_guid, replier_pdata) = replier;
+
+    // The waiting side asks for a handshake
+    let (outcome, initiator_remote, auth_request_token) = replier_auth
+      .validate_remote_identity(None, replier_local, initiator_token, initiator_guid.prefix)
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::PendingHandshakeMessage);
+    let auth_request_token = auth_request_token.unwrap();
+    let future_challenge = BuiltinAuthRequestMessageToken::try_from(auth_request_token.clone())
+      .unwrap()
+      .future_challenge;
+
+    let (outcome, replier_remote, no_token) = initiator_auth
+      .validate_remote_identity(
+        Some(auth_request_token),
+        initiator_local,
+        replier_token,
+        replier_guid.prefix,
+      )
+      .unwrap();
+    assert_eq!(outcome, ValidationOutcome::PendingHandshakeRequest);
+    assert!(no_token.is_none());
+
+    let (_outcome, _handshake, request) = initiator_auth
+      .begin_handshake_request(initiator_local, replier_remote, initiator_pdata)
+      .unwrap();
+    let builtin_request = BuiltinHandshakeMessageToken::try_from(request.clone())
+      .unwrap()
+      .extract_request()
+      .unwrap();
+    assert_eq!(builtin_request.challenge1, future_challenge);
+
+    assert!(replier_auth
+      .begin_handshake_reply(request, initiator_remote, replier_local, replier_pdata)
+      .is_ok());
+  }
 }
@@ -30,7 +30,7 @@ const EC_PRIME_ALGO_NAME: &str = "EC-prime256v1";
 
 pub(in crate::security) const RSA_2048_KEY_LENGTH: usize = 256;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(in crate::security) enum CertificateAlgorithm {
   RSA2048,
   ECPrime256v1,
@@ -90,7 +90,7 @@ impl TryFrom<x509_certificate::KeyAlgorithm> for CertificateAlgorithm {
 /// Security specification (v. 1.1)
 ///
 /// According to the spec, the presence of each of properties is optional
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(in crate::security) struct BuiltinIdentityToken {
   pub certificate_subject: Option<String>,
   pub certificate_algorithm: Option<CertificateAlgorithm>,
@@ -232,41 +232,38 @@ const FUTURE_CHALLENGE_PROPERTY_NAME: &str = "future_challenge";
 
 /// DDS:Auth:PKI-DH AuthRequestMessageToken type from section 9.3.2.4 of the
 /// Security specification (v. 1.1)
-pub struct BuiltinAuthRequestMessageToken {
-  // In spec future_challenge is a property (string value), but it probably needs to be a binary
-  // property
-  future_challenge: Bytes, // In spec this is
+#[derive(Debug, Clone)]
+pub(in crate::security) struct BuiltinAuthRequestMessageToken {
+  // In spec future_challenge is a property (string value), but it is binary
+  // data, so we carry it as a binary property
+  pub future_challenge: Challenge,
 }
 
 impl TryFrom<AuthRequestMessageToken> for BuiltinAuthRequestMessageToken {
-  type Error = String;
+  type Error = SecurityError;
 
   fn try_from(token: AuthRequestMessageToken) -> Result<Self, Self::Error> {
     let dh = token.data_holder;
     // Verify class id
     if dh.class_id != AUTH_REQUEST_MESSAGE_TOKEN_CLASS_ID {
-      return Err(format!(
+      return Err(create_security_error_and_log!(
         "Invalid class ID. Got {}, expected {}",
-        dh.class_id, AUTH_REQUEST_MESSAGE_TOKEN_CLASS_ID
+        dh.class_id,
+        AUTH_REQUEST_MESSAGE_TOKEN_CLASS_ID
       ));
     }
 
-    // Extract future challenge property
+    // Extract future challenge property. Challenge checks that the NONCE length
+    // is 256 bits / 32 bytes
     let bin_properties_map = dh.binary_properties_as_map();
 
     let future_challenge =
       if let Some(prop) = bin_properties_map.get(FUTURE_CHALLENGE_PROPERTY_NAME) {
-        let bytes = prop.value();
-        // Check NONCE length is 256 bits / 32 bytes
-        if bytes.len() != 32 {
-          return Err(format!(
-            "Invalid NONCE length. Got {} bytes, expected 32",
-            bytes.len()
-          ));
-        }
-        bytes
+        Challenge::try_from(prop.value().as_ref())?
       } else {
-        return Err("AuthRequestMessageToken did not contain future_challenge".to_string());
+        return Err(create_security_error_and_log!(
+          "AuthRequestMessageToken did not contain future_challenge"
+        ));
       };
 
     let builtin_token = Self { future_challenge };
@@ -279,7 +276,7 @@ impl From<BuiltinAuthRequestMessageToken> for AuthRequestMessageToken {
     DataHolderBuilder::with_class_id(AUTH_REQUEST_MESSAGE_TOKEN_CLASS_ID.to_string())
       .add_binary_property(
         FUTURE_CHALLENGE_PROPERTY_NAME,
-        builtin_token.future_challenge,
+        Bytes::copy_from_slice(builtin_token.future_challenge.as_ref()),
         true,
       )
       .build()
@@ -690,3 +687,96 @@ impl From<BuiltinAuthenticatedPeerCredentialToken> for AuthenticatedPeerCredenti
     AuthenticatedPeerCredentialToken::from(dh_builder.build())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn identity_token_round_trip() {
+    let builtin_token = BuiltinIdentityToken {
+      certificate_subject: Some("CN=participant".to_string()),
+      certificate_algorithm: Some(CertificateAlgorithm::ECPrime256v1),
+      ca_subject: Some("CN=identity_ca".to_string()),
+      ca_algorithm: Some(CertificateAlgorithm::RSA2048),
+    };
+    let token = IdentityToken::from(builtin_token.clone());
+    assert_eq!(token.class_id(), IDENTITY_TOKEN_CLASS_ID);
+    assert_eq!(
+      BuiltinIdentityToken::try_from(token).unwrap(),
+      builtin_token
+    );
+
+    // All the properties are optional
+    let empty_token = BuiltinIdentityToken {
+      certificate_subject: None,
+      certificate_algorithm: None,
+      ca_subject: None,
+      ca_algorithm: None,
+    };
+    let token = IdentityToken::from(empty_token.clone());
+    assert_eq!(BuiltinIdentityToken::try_from(token).unwrap(), empty_token);
+  }
+
+  #[test]
+  fn identity_token_with_unknown_class_id_or_algorithm_is_rejected() {
+    let token = IdentityToken::from(
+      DataHolderBuilder::with_class_id("DDS:Auth:Other:1.0".to_string()).build(),
+    );
+    assert!(BuiltinIdentityToken::try_from(token).is_err());
+
+    let token = IdentityToken::from(
+      DataHolderBuilder::with_class_id(IDENTITY_TOKEN_CLASS_ID.to_string())
+        .add_property_opt(CERT_ALGO_PROPERTY_NAME, Some("DSA".to_string()), true)
+        .build(),
+    );
+    assert!(BuiltinIdentityToken::try_from(token).is_err());
+  }
+
+  #[test]
+  fn auth_request_message_token_round_trip() {
+    let future_challenge = Challenge::from([0x5a; 32]);
+    let token = AuthRequestMessageToken::from(BuiltinAuthRequestMessageToken {
+      future_challenge: future_challenge.clone(),
+    });
+    assert_eq!(
+      BuiltinAuthRequestMessageToken::try_from(token)
+        .unwrap()
+        .future_challenge,
+      future_challenge
+    );
+  }
+
+  #[test]
+  fn invalid_auth_request_message_token_is_rejected() {
+    // Wrong class id
+    let token = AuthRequestMessageToken::from(
+      DataHolderBuilder::with_class_id(IDENTITY_TOKEN_CLASS_ID.to_string())
+        .add_binary_property(
+          FUTURE_CHALLENGE_PROPERTY_NAME,
+          Bytes::from_static(&[0; 32]),
+          true,
+        )
+        .build(),
+    );
+    assert!(BuiltinAuthRequestMessageToken::try_from(token).is_err());
+
+    // The future challenge must be a 256-bit NONCE
+    let token = AuthRequestMessageToken::from(
+      DataHolderBuilder::with_class_id(AUTH_REQUEST_MESSAGE_TOKEN_CLASS_ID.to_string())
+        .add_binary_property(
+          FUTURE_CHALLENGE_PROPERTY_NAME,
+          Bytes::from_static(&[0; 16]),
+          true,
+        )
+        .build(),
+    );
+    assert!(BuiltinAuthRequestMessageToken::try_from(token).is_err());
+
+    // The future challenge is mandatory
+    let token = AuthRequestMessageToken::from(
+      DataHolderBuilder::with_class_id(AUTH_REQUEST_MESSAGE_TOKEN_CLASS_ID.to_string()).build(),
+    );
+    assert!(BuiltinAuthRequestMessageToken::try_from(token).is_err());
+  }
+}
@@ -9,8 +9,7 @@ use crate::{
 
 // Some generic message class IDs for authentication (see section 7.4.3.5 of the
 // Security spec)
-//pub const GMCLASSID_SECURITY_AUTH_REQUEST: &str = "dds.sec.auth_request"; //
-// apparently never used?
+pub const GMCLASSID_SECURITY_AUTH_REQUEST: &str = "dds.sec.auth_request";
 pub const GMCLASSID_SECURITY_AUTH_HANDSHAKE: &str = "dds.sec.auth";
 
 // ValidationOutcome is like ValidationResult_t in the the Security
@@ -33,7 +32,7 @@ pub type IdentityHandle = u32;
 pub type HandshakeHandle = u32;
 
 // Wrapper for a SHA-256 hash, to avoid confusion
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Sha256([u8; 32]);
 
 impl Sha256 {
@@ -20,9 +20,8 @@
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use bytes::Bytes;
-use x509_certificate::{
-  certificate::CapturedX509Certificate, EcdsaCurve, KeyAlgorithm, SignatureAlgorithm,
-};
+use chrono::{DateTime, Utc};
+use x509_certificate::{certificate::CapturedX509Certificate, EcdsaCurve, KeyAlgorithm};
 use der::Decode;
 use bcder::{encode::Values, Mode};
 
@@ -30,7 +29,9 @@ use crate::security::{
   authentication::authentication_builtin::types::{
     CertificateAlgorithm, ECDSA_SIGNATURE_ALGO_NAME, RSA_2048_KEY_LENGTH, RSA_SIGNATURE_ALGO_NAME,
   },
-  config::{to_config_error_other, to_config_error_parse, ConfigError},
+  config::{
+    decrypt_pkcs12, parse_config_error, to_config_error_other, to_config_error_parse, ConfigError,
+  },
   types::{security_error, SecurityResult},
 };
 
@@ -47,7 +48,30 @@ impl Certificate {
   pub fn from_pem(pem_data: impl AsRef<[u8]>) -> Result<Self, ConfigError> {
     let cert = CapturedX509Certificate::from_pem(pem_data)
       .map_err(to_config_error_parse("Cannot read X.509 Certificate"))?;
+    Self::from_captured(cert)
+  }
+
+  pub fn from_der(der_data: impl AsRef<[u8]>) -> Result<Self, ConfigError> {
+    let cert = CapturedX509Certificate::from_der(der_data.as_ref().to_vec())
+      .map_err(to_config_error_parse("Cannot read X.509 Certificate"))?;
+    Self::from_captured(cert)
+  }
 
+  // The certificate from a PKCS#12 bundle
+  pub fn from_pkcs12(
+    der_data: impl AsRef<[u8]>,
+    password: Option<&str>,
+  ) -> Result<Self, ConfigError> {
+    let cert = decrypt_pkcs12(der_data.as_ref(), password)?
+      .cert
+      .ok_or_else(|| parse_config_error("PKCS#12 bundle contains no certificate".to_string()))?;
+    let der_data = cert.to_der().map_err(to_config_error_parse(
+      "Cannot encode the certificate of a PKCS#12 bundle",
+    ))?;
+    Self::from_der(der_data)
+  }
+
+  fn from_captured(cert: CapturedX509Certificate) -> Result<Self, ConfigError> {
     let other_cert = x509_cert::certificate::Certificate::from_der(cert.constructed_data())
       .map_err(to_config_error_parse("Cannot read X.509 Certificate(2)"))?;
 
@@ -67,18 +91,16 @@ impl Certificate {
 
   // name of the signature algoritm as a byte string accrding to Table 49
   // in DDS Security Spec v1.1 Section "9.3.2.5.1 HandshakeRequestMessageToken
-  // objects"
+  // objects". This is the algorithm that the holder of the certificate signs
+  // with, so it depends on the public key, not on how the CA signed the
+  // certificate.
   pub fn signature_algorithm_identifier(&self) -> SecurityResult<Bytes> {
-    match self.cert.signature_algorithm() {
+    match self.algorithm() {
       None => Err(security_error(
-        "Certificate has no known signature algorithm?!",
+        "Certificate has an out-of-spec public key algorithm",
       )),
-      Some(SignatureAlgorithm::RsaSha256) => Ok(Bytes::from_static(RSA_SIGNATURE_ALGO_NAME)),
-      Some(SignatureAlgorithm::EcdsaSha256) => Ok(Bytes::from_static(ECDSA_SIGNATURE_ALGO_NAME)),
-      Some(x) => Err(security_error(&format!(
-        "Certificate has out-of-spec signature algorithm {:?}",
-        x
-      ))),
+      Some(CertificateAlgorithm::RSA2048) => Ok(Bytes::from_static(RSA_SIGNATURE_ALGO_NAME)),
+      Some(CertificateAlgorithm::ECPrime256v1) => Ok(Bytes::from_static(ECDSA_SIGNATURE_ALGO_NAME)),
     }
   }
 
@@ -100,7 +122,11 @@ impl Certificate {
     let key_algorithm = &self.cert.key_algorithm();
     match key_algorithm {
       Some(KeyAlgorithm::Rsa) => {
-        let key_length = self.cert.public_key_data().len();
+        // The public key data is a DER-encoded RSAPublicKey, which contains also
+        // the exponent. The key length is the length of the modulus.
+        let key_length = openssl::rsa::Rsa::public_key_from_der_pkcs1(&self.cert.public_key_data())
+          .map(|rsa| rsa.size() as usize)
+          .unwrap_or(0);
         if key_length == RSA_2048_KEY_LENGTH {
           Some(CertificateAlgorithm::RSA2048)
         } else {
@@ -135,6 +161,25 @@ impl Certificate {
       .map_err(|e| security_error(&format!("Signature verification failure: {e:?}")))
   }
 
+  // Check that `now` is within the validity period of the certificate
+  pub fn check_validity_period(&self, now: DateTime<Utc>) -> SecurityResult<()> {
+    let not_before = self.cert.validity_not_before();
+    let not_after = self.cert.validity_not_after();
+    if now < not_before {
+      Err(security_error(&format!(
+        "Certificate of {} is not valid before {not_before}",
+        self.subject_name
+      )))
+    } else if not_after < now {
+      Err(security_error(&format!(
+        "Certificate of {} expired at {not_after}",
+        self.subject_name
+      )))
+    } else {
+      Ok(())
+    }
+  }
+
   // Verify that `self` was signed by `other` Certificate
   // e.g.
   // `someones_identity.verify_signed_by_certificate( certificate_authority )`
@@ -3,10 +3,13 @@ use std::{
   path::{Path, PathBuf},
 };
 
+use openssl::pkcs12::{ParsedPkcs12_2, Pkcs12};
+
 use crate::{
   qos, security,
   security::{
-    authentication::authentication_builtin::types::CertificateAlgorithm, private_key::PrivateKey,
+    authentication::authentication_builtin::types::CertificateAlgorithm, certificate::Certificate,
+    private_key::PrivateKey,
   },
 };
 use super::{
@@ -22,10 +25,11 @@ use super::{
 
 /// How to access Certificate's private key for signing.
 pub enum PrivateSigningKey {
-  /// Private key is stored in a regular .pem file. The contents may be
-  /// encrypted with a pasword.
+  /// Private key is stored in a regular .pem file, or in a PKCS#12 bundle
+  /// (.p12 or .pfx file) together with the identity certificate. The contents
+  /// may be encrypted with a pasword.
   Files {
-    /// Where the PEM file is stored
+    /// Where the PEM file or the PKCS#12 bundle is stored
     file_path: PathBuf,
     /// Decryption key, if private key file is encrypted
     file_password: String,
@@ -62,7 +66,8 @@ pub enum PrivateSigningKey {
 pub struct DomainParticipantSecurityConfigFiles {
   /// CA that is used to validate identities of DomainParticipants
   pub identity_ca_certificate: PathBuf,
-  /// Identity docuemnt for this Participant
+  /// Identity docuemnt for this Participant. This may be a PKCS#12 bundle
+  /// (.p12 or .pfx file), which then holds the private key as well.
   pub participant_identity_certificate: PathBuf,
   /// Private (signing) key for this participant
   pub participant_identity_private_key: PrivateSigningKey,
@@ -222,29 +227,66 @@ pub(crate) fn read_uri(uri: &str) -> Result<Bytes, ConfigError> {
   }
 }
 
-//use crate::security::certificate::Certificate;
+// PKCS#12 bundles are recognized by the file name extension
+fn is_pkcs12_path(path: &str) -> bool {
+  Path::new(path)
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("p12") || ext.eq_ignore_ascii_case("pfx"))
+}
+
+pub(in crate::security) fn read_uri_to_certificate(
+  uri: &str,
+  password: Option<&str>,
+) -> Result<Certificate, ConfigError> {
+  match uri.split_once(':') {
+    Some(("file", path)) if is_pkcs12_path(path) => {
+      read_uri(uri).and_then(|der| Certificate::from_pkcs12(der, password))
+    }
+    _ => read_uri(uri).and_then(Certificate::from_pem),
+  }
+}
 
 pub(in crate::security) fn read_uri_to_private_key(
   uri: &str,
   key_algorithm: CertificateAlgorithm,
+  password: Option<&str>,
 ) -> Result<PrivateKey, ConfigError> {
   match uri.split_once(':') {
-    Some(("data", content)) => PrivateKey::from_pem(Bytes::copy_from_slice(content.as_bytes())),
+    Some(("data", content)) => PrivateKey::from_pem(content.as_bytes(), password),
 
     Some(("pkcs11", path_and_query)) => {
       // These URIs are composed of "pkcs11" ":" path [ "?" query ]
       PrivateKey::from_pkcs11_uri_path_and_query(path_and_query, key_algorithm)
     }
-    Some(("file", path)) => std::fs::read(path)
-      .map_err(to_config_error_other(&format!("I/O error reading {path}")))
-      .map(Bytes::from)
-      .and_then(PrivateKey::from_pem),
+    Some(("file", path)) => {
+      let contents =
+        std::fs::read(path).map_err(to_config_error_other(&format!("I/O error reading {path}")))?;
+      if is_pkcs12_path(path) {
+        PrivateKey::from_pkcs12(contents, password)
+      } else {
+        PrivateKey::from_pem(contents, password)
+      }
+    }
     _ => Err(parse_config_error(
       "Config URI must begin with 'file:' , 'data:', or 'pkcs11:'.".to_owned(),
     )),
   }
 }
 
+// Decrypt a PKCS#12 bundle. A bundle without a password is encrypted with
+// the empty one.
+pub(in crate::security) fn decrypt_pkcs12(
+  der_data: &[u8],
+  password: Option<&str>,
+) -> Result<ParsedPkcs12_2, ConfigError> {
+  Pkcs12::from_der(der_data)
+    .map_err(to_config_error_parse("Not a PKCS#12 bundle"))?
+    .parse2(password.unwrap_or_default())
+    .map_err(to_config_error_password(
+      "Cannot decrypt the PKCS#12 bundle. Wrong password?",
+    ))
+}
+
 use std::fmt::Debug;
 
 #[derive(Debug)]
@@ -253,15 +295,11 @@ pub enum ConfigError {
   Pkcs7(String),
   Pkcs11(String),
   Security(String),
+  // Encrypted key material could not be decrypted with the given password
+  Password(String),
   Other(String),
 }
 
-impl From<glob::PatternError> for ConfigError {
-  fn from(e: glob::PatternError) -> ConfigError {
-    ConfigError::Parse(format!("Bad glob pattern: {e:?}"))
-  }
-}
-
 impl From<serde_xml_rs::Error> for ConfigError {
   fn from(e: serde_xml_rs::Error) -> ConfigError {
     ConfigError::Parse(format!("XML parse error: {e:?}"))
@@ -292,6 +330,16 @@ pub(crate) fn to_config_error_parse<E: Debug + 'static>(
   move |e: E| ConfigError::Parse(format!("{}: {:?}", text, e))
 }
 
+pub(crate) fn to_config_error_password<E: Debug + 'static>(
+  text: &str,
+) -> impl FnOnce(E) -> ConfigError + '_ {
+  move |e: E| ConfigError::Password(format!("{}: {:?}", text, e))
+}
+
+pub(crate) fn password_config_error(text: String) -> ConfigError {
+  ConfigError::Password(text)
+}
+
 pub(crate) fn parse_config_error(text: String) -> ConfigError {
   ConfigError::Parse(text)
 }
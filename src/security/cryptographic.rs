@@ -4,6 +4,12 @@ pub mod types;
 
 pub use types::*;
 
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::security::{security_error, SecurityResult};
+
 // Cryptographic operations are specified as three separate traits,
 // but we gather them into one, so that we can implement them in a
 // single object. Having three separate interfaces and using them as such
@@ -14,4 +20,65 @@ pub trait Cryptographic:
   + cryptographic_plugin::CryptoKeyExchange
   + cryptographic_plugin::CryptoTransform
 {
+  // Key rotation is not part of the specification, so plugins are not
+  // required to support it.
+
+  /// Replaces the key material of a local endpoint with fresh material.
+  /// Remotes need new crypto tokens to decode what the endpoint sends after
+  /// this, see [`Cryptographic::take_rekeyed_endpoints`].
+  fn regenerate_local_endpoint_keys(
+    &mut self,
+    _local_endpoint_crypto_handle: EndpointCryptoHandle,
+  ) -> SecurityResult<()> {
+    Err(security_error(
+      "The cryptographic plugin does not support key regeneration",
+    ))
+  }
+
+  /// Returns the (local endpoint, matched remote endpoint) pairs whose crypto
+  /// tokens must be sent again because the keys of the local endpoint have
+  /// been regenerated, and forgets them.
+  fn take_rekeyed_endpoints(&mut self) -> Vec<(EndpointCryptoHandle, EndpointCryptoHandle)> {
+    Vec::new()
+  }
+
+  /// A snapshot of the operation counts, if the plugin keeps them
+  fn crypto_statistics(&self) -> Option<CryptoStatistics> {
+    None
+  }
+
+  /// Serializes the key materials of the plugin for
+  /// [`Cryptographic::import_key_materials`] after a restart. The output
+  /// contains raw keys.
+  fn export_key_materials(&self) -> SecurityResult<Bytes> {
+    Err(security_error(
+      "The cryptographic plugin does not support exporting key materials",
+    ))
+  }
+
+  /// Restores key materials serialized with
+  /// [`Cryptographic::export_key_materials`]
+  fn import_key_materials(&mut self, _exported: Bytes) -> SecurityResult<()> {
+    Err(security_error(
+      "The cryptographic plugin does not support importing key materials",
+    ))
+  }
+
+  /// Sets the listener to tell about rejected data from remote participants.
+  /// The listener is called on the thread that decodes the data.
+  fn set_security_event_listener(
+    &mut self,
+    _listener: Arc<dyn SecurityEventListener>,
+  ) -> SecurityResult<()> {
+    Err(security_error(
+      "The cryptographic plugin does not support security event listeners",
+    ))
+  }
+}
+
+/// Receives the security-relevant failures of the cryptographic plugin, e.g.
+/// for an intrusion detection system: MAC failures, rejected crypto tokens and
+/// data protected with unknown keys.
+pub trait SecurityEventListener: Send + Sync {
+  fn on_crypto_event(&self, event: CryptoSecurityEvent);
 }
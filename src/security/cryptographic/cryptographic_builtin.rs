@@ -5,21 +5,44 @@ mod crypto_key_factory;
 mod crypto_transform;
 mod encode;
 mod key_material;
+mod key_material_export;
+mod key_wrap;
+mod replay_window;
+#[cfg(test)]
+mod test_fixtures;
+#[cfg(test)]
+mod test_vectors;
 pub(crate) mod types;
 mod validate_receiver_specific_macs;
 
-use std::collections::{HashMap, HashSet};
+use std::{
+  collections::{hash_map::Entry, HashMap, HashSet},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, PoisonError,
+  },
+};
+
+use bytes::Bytes;
+use log::debug;
 
 use crate::{
   create_security_error_and_log,
+  messages::submessages::elements::crypto_footer::CryptoFooter,
   security::{
-    access_control::types::*,
+    access_control::{
+      access_control_builtin::types::BuiltinPluginParticipantSecurityAttributes, types::*,
+    },
     authentication::types::*,
-    cryptographic::{cryptographic_builtin::types::*, cryptographic_plugin::*, types::*},
+    cryptographic::{
+      cryptographic_builtin::types::*, cryptographic_plugin::*, types::*, SecurityEventListener,
+    },
+    entropy::{random_bytes, EntropySource, SystemEntropySource},
     types::*,
   },
+  structure::clock,
 };
-use self::{builtin_key::*, key_material::*};
+use self::{aes_gcm_gmac::*, builtin_key::*, key_material::*, replay_window::ReplayWindow};
 
 // A struct implementing the builtin Cryptographic plugin
 // See sections 8.5 and 9.5 of the Security specification (v. 1.1)
@@ -35,11 +58,13 @@ pub struct CryptographicBuiltin {
   // if origin authentication is enabled, the receiver-specific material the local entity uses for
   // computing a receiver-specific MAC and the remote entity for verifying it. They are generated
   // locally and sent to the matched entity in encrypted crypto tokens over the volatile channel.
+  // Without origin authentication they are the common encode key materials, shared rather than
+  // copied for each receiver.
   //
   // In case of volatile entities these contain a receiver-specific key derived from a shared
   // secret as a result of key exchange, which is used for payload encoding in order to provide
   // the volatile channel.
-  receiver_specific_encode_key_materials: HashMap<CryptoHandle, KeyMaterial_AES_GCM_GMAC_seq>,
+  receiver_specific_encode_key_materials: HashMap<CryptoHandle, Arc<KeyMaterial_AES_GCM_GMAC_seq>>,
 
   // Decode key materials indexed by remote (sender) handles.
   // These are the materials the matched remote entity uses for encoding and the local entity for
@@ -48,7 +73,28 @@ pub struct CryptographicBuiltin {
   // as a result of key exchange. If origin authentication is enabled, they include the
   // receiver-specific key material, which the remote entity uses to compute a receiver-specific
   // MAC and the local entity to verify it.
-  decode_key_materials: HashMap<CryptoHandle, KeyMaterial_AES_GCM_GMAC_seq>,
+  decode_key_materials: HashMap<CryptoHandle, Arc<KeyMaterial_AES_GCM_GMAC_seq>>,
+  // Decode key materials that remote senders have replaced with new ones, indexed by remote
+  // (sender) handles. They are kept until they expire, so that messages encoded before the
+  // replacement still decode.
+  retired_decode_key_materials: HashMap<CryptoHandle, Vec<RetiredKeyMaterials>>,
+  // The remote (sender) handles that have current or retired decode key materials with a key id,
+  // indexed by the sending remote participant and the key id. Received submessages identify their
+  // key only by the key id, so this finds the sending endpoints without going through all
+  // endpoints of the sender. Independent remote participants may use the same key ids, so the
+  // key id alone does not identify the sender.
+  decode_key_id_index:
+    HashMap<(ParticipantCryptoHandle, CryptoTransformKeyId), HashSet<CryptoHandle>>,
+  // How long retired decode key materials are kept, from the property
+  // "dds.sec.crypto.rekey_grace_period" of local participants
+  rekey_grace_periods: HashMap<ParticipantCryptoHandle, std::time::Duration>,
+  // Local endpoints whose key materials have been regenerated, but whose new crypto tokens have
+  // not yet been taken for sending to the matched remote endpoints
+  rekeyed_endpoints: HashSet<EndpointCryptoHandle>,
+  // Remote datareaders registered as relay-only. Their receiver-specific encode key materials
+  // have only the key material for submessages, so they can verify and relay the submessages of
+  // the datawriter, but not decrypt its payloads.
+  relay_only_remote_datareaders: HashSet<DatareaderCryptoHandle>,
 
   participant_encrypt_options: HashMap<ParticipantCryptoHandle, ParticipantSecurityAttributes>,
   endpoint_encrypt_options: HashMap<EndpointCryptoHandle, EndpointSecurityAttributes>,
@@ -59,19 +105,43 @@ pub struct CryptographicBuiltin {
   // For generating random key IDs without collisions
   used_local_key_ids: HashSet<CryptoTransformKeyId>,
 
-  // sessions
-  //
-  // TODO: The session ids should be stored in a data structure.
-  // Associated with each should be a (sent) data block counter, so we should count how many
-  // outgoing encrypted blocks are sent. (per remote endpoint)
-  // There should be a configuration variable `max_blocks_per_session`. When a counter
-  // reaches that, the session_id is changed. Likely a simple increment is sufficient, but random
-  // successor is also ok.
-  // Initial session id values are also arbitrary.
-  // See DDS Security Spec v1.1 Section "9.5.3.3.4 Computation of ciphertext from plaintext"
-  //
-  // TODO: The current session_id is just a constant. Implementing counting above requires
-  // some access to shared mutable state from encryption/send operations.
+  // Session keys derived from master keys, indexed by the key id of the master key. An entry is
+  // only valid for the session id and master key material it was derived from, and is replaced
+  // when those change. The encode and decode operations only have shared access to self, hence
+  // the Mutex.
+  session_keys: Mutex<HashMap<(CryptoTransformKeyId, ReceiverSpecific), CachedSessionKey>>,
+
+  // Encoding sessions of local senders, indexed by the sending handle and the key id of the
+  // master key. Each session counts the blocks encoded in it, and a new session is started when
+  // the maximum number of blocks per session would be exceeded. See DDS Security Spec v1.1
+  // Section "9.5.3.3.4 Computation of ciphertext from plaintext"
+  encode_sessions: Mutex<HashMap<(CryptoHandle, CryptoTransformKeyId), EncodeSession>>,
+  // Maximum number of blocks per session for local participants and endpoints, from the
+  // property "dds.sec.crypto.maxblockspersession". Endpoints inherit the value of their
+  // participant unless they set the property themselves.
+  max_blocks_per_session: HashMap<CryptoHandle, u64>,
+
+  // Local participants that reject replayed messages and submessages, from the property
+  // "dds.sec.crypto.reject_replays". Serialized payloads are not checked, because the writer
+  // history keeps them encoded and resends the same payload on retransmission.
+  reject_replays: HashSet<ParticipantCryptoHandle>,
+  // Maximum number of receiver-specific MACs in the footer of a received message or submessage,
+  // from the property "dds.sec.crypto.max_receiver_specific_macs" of local participants. Bounds
+  // the memory a malicious sender can make the receiver allocate.
+  max_receiver_specific_macs: HashMap<ParticipantCryptoHandle, usize>,
+  // Anti-replay windows of remote senders, indexed by the sending handle and the key id of the
+  // master key
+  replay_windows: Mutex<HashMap<(CryptoHandle, CryptoTransformKeyId), ReplayWindow>>,
+
+  // Key materials shared by the local datawriters of a topic that set the property
+  // "dds.sec.crypto.share_topic_key". Sharing reduces the number of keys to exchange, but a
+  // compromised key exposes all the datawriters of the topic.
+  shared_topic_keys: HashMap<SharedTopicKeyIndex, SharedTopicKey>,
+  // For each datawriter using a shared topic key, the handle of that key. The encoding sessions
+  // of a shared key are kept under its handle, so that the datawriters do not reuse each other's
+  // initialization vectors.
+  shared_topic_key_handles: HashMap<DatawriterCryptoHandle, CryptoHandle>,
+
   /// For each (local datawriter (/datareader), remote participant) pair, stores
   /// the matched remote datareader (/datawriter)
   matched_remote_endpoint:
@@ -80,29 +150,176 @@ pub struct CryptographicBuiltin {
   /// matched local datareader (/datawriter)
   matched_local_endpoint: HashMap<EndpointCryptoHandle, EndpointCryptoHandle>,
 
+  // Remote participant crypto handles already issued for (local participant, remote identity)
+  // pairs, with the origin authentication setting their receiver-specific key was generated for.
+  // Discovery may register the same remote participant again, and a new handle and key would
+  // break decoding the messages already in flight.
+  matched_remote_participants:
+    HashMap<(ParticipantCryptoHandle, IdentityHandle), MatchedRemoteParticipant>,
+  // KxKeys of matched remote participants, indexed by the remote participant handles. They are
+  // derived from the shared secret of the participant pair and wrap the key materials in the crypto
+  // tokens exchanged with the remote participant and its endpoints. See section 9.5.2.3.2.
+  key_exchange_keys: HashMap<ParticipantCryptoHandle, BuiltinKey>,
+  // Local participants that wrap key material, from the property
+  // "dds.sec.crypto.wrap_key_material". They announce it in their participant crypto tokens.
+  key_wrap_participants: HashSet<ParticipantCryptoHandle>,
+  // Remote participants that announced key wrapping to a local participant that wraps key
+  // material. Only the crypto tokens sent to them and their endpoints are wrapped, all others
+  // carry the key material in the standard dds.cryp.keymat property.
+  key_wrap_peers: HashSet<ParticipantCryptoHandle>,
+  // Remote participants restored from exported key materials. Their identity handles were issued
+  // before the export and mean nothing to the current authentication plugin, so they are not in
+  // matched_remote_participants.
+  imported_remote_participants: HashSet<ParticipantCryptoHandle>,
+
   crypto_handle_counter: u32,
+
+  // Whether export_key_materials may hand out the raw keys, from the property
+  // "dds.sec.crypto.allow_key_export" of local participants
+  key_material_export_allowed: bool,
+
+  // Counts of the transform operations. The transform functions only have
+  // shared access to self, hence the atomics.
+  transform_counters: TransformCounters,
+  // Told about the messages, submessages, payloads and crypto tokens that are rejected
+  security_event_listener: Option<Arc<dyn SecurityEventListener>>,
+
+  // Source of the random bytes for key material, key ids and session ids
+  entropy_source: Arc<dyn EntropySource>,
+}
+
+#[derive(Clone, Copy)]
+struct MatchedRemoteParticipant {
+  crypto_handle: ParticipantCryptoHandle,
+  is_rtps_origin_authenticated: bool,
 }
 
 // Combine the trait implementations from the submodules
-impl super::Cryptographic for CryptographicBuiltin {}
+impl super::Cryptographic for CryptographicBuiltin {
+  fn regenerate_local_endpoint_keys(
+    &mut self,
+    local_endpoint_crypto_handle: EndpointCryptoHandle,
+  ) -> SecurityResult<()> {
+    CryptographicBuiltin::regenerate_local_endpoint_keys(self, local_endpoint_crypto_handle)
+  }
+
+  fn take_rekeyed_endpoints(&mut self) -> Vec<(EndpointCryptoHandle, EndpointCryptoHandle)> {
+    CryptographicBuiltin::take_rekeyed_endpoints(self)
+  }
+
+  fn crypto_statistics(&self) -> Option<CryptoStatistics> {
+    Some(CryptographicBuiltin::crypto_statistics(self))
+  }
+
+  fn export_key_materials(&self) -> SecurityResult<Bytes> {
+    CryptographicBuiltin::export_key_materials(self)
+  }
+
+  fn import_key_materials(&mut self, exported: Bytes) -> SecurityResult<()> {
+    CryptographicBuiltin::import_key_materials(self, exported)
+  }
+
+  fn set_security_event_listener(
+    &mut self,
+    listener: Arc<dyn SecurityEventListener>,
+  ) -> SecurityResult<()> {
+    self.security_event_listener = Some(listener);
+    Ok(())
+  }
+}
 
 impl CryptographicBuiltin {
   pub fn new() -> Self {
+    Self::with_entropy_source(Arc::new(SystemEntropySource::new()))
+  }
+
+  /// Creates the plugin with a custom source for all the random bytes it
+  /// generates
+  pub fn with_entropy_source(entropy_source: Arc<dyn EntropySource>) -> Self {
     CryptographicBuiltin {
       common_encode_key_materials: HashMap::new(),
       receiver_specific_encode_key_materials: HashMap::new(),
       decode_key_materials: HashMap::new(),
+      retired_decode_key_materials: HashMap::new(),
+      decode_key_id_index: HashMap::new(),
+      rekey_grace_periods: HashMap::new(),
+      rekeyed_endpoints: HashSet::new(),
+      relay_only_remote_datareaders: HashSet::new(),
       participant_encrypt_options: HashMap::new(),
       endpoint_encrypt_options: HashMap::new(),
       participant_to_endpoint_info: HashMap::new(),
       endpoint_to_participant: HashMap::new(),
       used_local_key_ids: HashSet::from([CryptoTransformKeyId::ZERO]),
+      session_keys: Mutex::new(HashMap::new()),
+      encode_sessions: Mutex::new(HashMap::new()),
+      max_blocks_per_session: HashMap::new(),
+      reject_replays: HashSet::new(),
+      max_receiver_specific_macs: HashMap::new(),
+      replay_windows: Mutex::new(HashMap::new()),
+      shared_topic_keys: HashMap::new(),
+      shared_topic_key_handles: HashMap::new(),
       matched_remote_endpoint: HashMap::new(),
       matched_local_endpoint: HashMap::new(),
+      matched_remote_participants: HashMap::new(),
+      key_exchange_keys: HashMap::new(),
+      key_wrap_participants: HashSet::new(),
+      key_wrap_peers: HashSet::new(),
+      imported_remote_participants: HashSet::new(),
       crypto_handle_counter: 0,
+      key_material_export_allowed: false,
+      transform_counters: TransformCounters::default(),
+      security_event_listener: None,
+      entropy_source,
     }
   }
 
+  /// A snapshot of the counts of the transform operations since the plugin
+  /// was created
+  pub fn crypto_statistics(&self) -> CryptoStatistics {
+    self.transform_counters.snapshot()
+  }
+
+  // Tells the security event listener if the result of an operation on data
+  // from the remote handle is an error
+  fn report_rejection<T>(
+    &self,
+    operation: CryptoOperation,
+    remote_crypto_handle: CryptoHandle,
+    result: &SecurityResult<T>,
+  ) {
+    if let (Some(listener), Err(e)) = (&self.security_event_listener, result) {
+      listener.on_crypto_event(CryptoSecurityEvent::new(
+        e.kind().into(),
+        operation,
+        remote_crypto_handle,
+      ));
+    }
+  }
+
+  // Like report_rejection, but also reports data that was dropped because its
+  // key is unknown
+  fn report_decode_outcome<T>(
+    &self,
+    operation: CryptoOperation,
+    remote_crypto_handle: CryptoHandle,
+    result: &SecurityResult<DecodeOutcome<T>>,
+  ) {
+    match (&self.security_event_listener, result) {
+      (Some(listener), Ok(DecodeOutcome::KeysNotFound(_))) => {
+        listener.on_crypto_event(CryptoSecurityEvent::new(
+          CryptoSecurityEventKind::UnknownKey,
+          operation,
+          remote_crypto_handle,
+        ));
+      }
+      _ => self.report_rejection(operation, remote_crypto_handle, result),
+    }
+  }
+
+  fn random_bytes<const N: usize>(&self) -> SecurityResult<[u8; N]> {
+    random_bytes(self.entropy_source.as_ref())
+  }
+
   fn insert_common_encode_key_materials(
     &mut self,
     local_entity_crypto_handle: CryptoHandle,
@@ -118,6 +335,7 @@ impl CryptographicBuiltin {
           .common_encode_key_materials
           .insert(local_entity_crypto_handle, old_key_materials);
         SecurityResult::Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
           "The CryptoHandle {} was already associated with common encode key materials",
           local_entity_crypto_handle
         ))
@@ -133,6 +351,7 @@ impl CryptographicBuiltin {
       .get(local_entity_crypto_handle)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find common encode key materials for the CryptoHandle {}",
           local_entity_crypto_handle
         )
@@ -142,7 +361,7 @@ impl CryptographicBuiltin {
   fn insert_receiver_specific_encode_key_materials(
     &mut self,
     remote_entity_crypto_handle: CryptoHandle,
-    key_materials: KeyMaterial_AES_GCM_GMAC_seq,
+    key_materials: Arc<KeyMaterial_AES_GCM_GMAC_seq>,
   ) -> SecurityResult<()> {
     match self
       .receiver_specific_encode_key_materials
@@ -154,6 +373,7 @@ impl CryptographicBuiltin {
           .receiver_specific_encode_key_materials
           .insert(remote_entity_crypto_handle, old_key_materials);
         SecurityResult::Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
           "The CryptoHandle {} was already associated with receiver-specific encode key materials",
           remote_entity_crypto_handle
         ))
@@ -163,33 +383,150 @@ impl CryptographicBuiltin {
   fn get_receiver_specific_encode_key_materials(
     &self,
     remote_entity_crypto_handle: &CryptoHandle,
-  ) -> SecurityResult<&KeyMaterial_AES_GCM_GMAC_seq> {
+  ) -> SecurityResult<&Arc<KeyMaterial_AES_GCM_GMAC_seq>> {
     self
       .receiver_specific_encode_key_materials
       .get(remote_entity_crypto_handle)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find receiver-specific encode key materials for the CryptoHandle {}",
           remote_entity_crypto_handle
         )
       })
   }
 
+  // The KxKey for the crypto tokens exchanged with a remote participant or
+  // endpoint
+  fn get_key_exchange_key(
+    &self,
+    remote_entity_crypto_handle: CryptoHandle,
+  ) -> SecurityResult<&BuiltinKey> {
+    let remote_participant_crypto_handle = self
+      .endpoint_to_participant
+      .get(&remote_entity_crypto_handle)
+      .unwrap_or(&remote_entity_crypto_handle);
+    self
+      .key_exchange_keys
+      .get(remote_participant_crypto_handle)
+      .ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
+          "Could not find a KxKey for the CryptoHandle {}",
+          remote_entity_crypto_handle
+        )
+      })
+  }
+
+  // Whether the local participant was registered with RTPS origin
+  // authentication, which requires receiver-specific keys
+  fn is_rtps_origin_authenticated(
+    &self,
+    local_participant_crypto_handle: ParticipantCryptoHandle,
+  ) -> SecurityResult<bool> {
+    self
+      .participant_encrypt_options
+      .get(&local_participant_crypto_handle)
+      .ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
+          "Participant encrypt options not found for the ParticipantCryptoHandle {}",
+          local_participant_crypto_handle
+        )
+      })
+      .and_then(|participant_security_attributes| {
+        BuiltinPluginParticipantSecurityAttributes::try_from(
+          participant_security_attributes.plugin_participant_attributes,
+        )
+      })
+      .map(|plugin_participant_attributes| {
+        plugin_participant_attributes.is_rtps_origin_authenticated
+      })
+  }
+
+  // Checks that the key materials received from a remote endpoint use
+  // transformation kinds at least as strong as the ones the matched local
+  // endpoint was registered with, so that a remote cannot downgrade the
+  // protection e.g. from GCM to GMAC. A remote datawriter may have upgraded
+  // its kinds with protection kind properties. The receiver-specific encode
+  // key materials of the remote are derived from the common key materials of
+  // the local endpoint, so they carry the local kinds.
+  fn check_remote_transformation_kinds(
+    &self,
+    remote_endpoint_crypto_handle: EndpointCryptoHandle,
+    key_materials: &KeyMaterial_AES_GCM_GMAC_seq,
+  ) -> SecurityResult<()> {
+    let local_key_materials =
+      self.get_receiver_specific_encode_key_materials(&remote_endpoint_crypto_handle)?;
+    // The key materials of a relay-only datareader have no payload key, and
+    // datareaders do not send payloads anyway
+    let scopes: &[KeyMaterialScope] = if self
+      .relay_only_remote_datareaders
+      .contains(&remote_endpoint_crypto_handle)
+    {
+      &[KeyMaterialScope::MessageOrSubmessage]
+    } else {
+      &[
+        KeyMaterialScope::MessageOrSubmessage,
+        KeyMaterialScope::PayloadOnly,
+      ]
+    };
+    for &scope in scopes {
+      let local_kind = local_key_materials.select(scope).transformation_kind;
+      let remote_kind = key_materials.select(scope).transformation_kind;
+      if !remote_kind.is_at_least_as_strong_as(local_kind) {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::UnsupportedTransformation,
+          "The key material from the remote endpoint {} has the transformation kind \
+           {remote_kind:?} for {scope:?}, but the local endpoint requires {local_kind:?}",
+          remote_endpoint_crypto_handle
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  // The KxKey to wrap the key materials sent to a remote participant or
+  // endpoint with, or None if the remote gets them unwrapped
+  fn get_key_wrap_key(
+    &self,
+    remote_entity_crypto_handle: CryptoHandle,
+  ) -> SecurityResult<Option<&BuiltinKey>> {
+    let remote_participant_crypto_handle = self
+      .endpoint_to_participant
+      .get(&remote_entity_crypto_handle)
+      .unwrap_or(&remote_entity_crypto_handle);
+    if self
+      .key_wrap_peers
+      .contains(remote_participant_crypto_handle)
+    {
+      self
+        .get_key_exchange_key(remote_entity_crypto_handle)
+        .map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+
   fn insert_decode_key_materials(
     &mut self,
     remote_entity_crypto_handle: CryptoHandle,
-    key_materials: KeyMaterial_AES_GCM_GMAC_seq,
+    key_materials: Arc<KeyMaterial_AES_GCM_GMAC_seq>,
   ) -> SecurityResult<()> {
     match self
       .decode_key_materials
       .insert(remote_entity_crypto_handle, key_materials)
     {
-      None => SecurityResult::Ok(()),
+      None => {
+        self.reindex_decode_key_ids(remote_entity_crypto_handle, Vec::new());
+        SecurityResult::Ok(())
+      }
       Some(old_key_materials) => {
         self
           .decode_key_materials
           .insert(remote_entity_crypto_handle, old_key_materials);
         SecurityResult::Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
           "The CryptoHandle {} was already associated with decode key material",
           remote_entity_crypto_handle
         ))
@@ -197,24 +534,173 @@ impl CryptographicBuiltin {
     }
   }
 
+  // Sets the decode key materials received from a remote endpoint. If the
+  // remote has regenerated its keys, the previous materials are retired and
+  // still used for decoding until the grace period of the local participant
+  // has passed.
+  fn set_remote_endpoint_decode_key_materials(
+    &mut self,
+    remote_endpoint_crypto_handle: EndpointCryptoHandle,
+    key_materials: Arc<KeyMaterial_AES_GCM_GMAC_seq>,
+  ) -> SecurityResult<()> {
+    let previous_key_id = self
+      .decode_key_materials
+      .get(&remote_endpoint_crypto_handle)
+      .map(|previous| previous.key_material().sender_key_id);
+    match previous_key_id {
+      Some(previous_key_id) if previous_key_id != key_materials.key_material().sender_key_id => {
+        let previous_key_ids = self.decode_key_ids(remote_endpoint_crypto_handle);
+        let now = clock::monotonic_now();
+        let expires = now + self.rekey_grace_period(remote_endpoint_crypto_handle);
+        if let Some(previous) = self
+          .decode_key_materials
+          .insert(remote_endpoint_crypto_handle, key_materials)
+        {
+          let retired = self
+            .retired_decode_key_materials
+            .entry(remote_endpoint_crypto_handle)
+            .or_default();
+          retired.retain(|retired| retired.expires > now);
+          retired.push(RetiredKeyMaterials {
+            key_materials: previous,
+            expires,
+          });
+        }
+        self.reindex_decode_key_ids(remote_endpoint_crypto_handle, previous_key_ids);
+        debug!(
+          "Remote CryptoHandle {remote_endpoint_crypto_handle} replaced its key {previous_key_id}"
+        );
+        Ok(())
+      }
+      _ => self.insert_decode_key_materials(remote_endpoint_crypto_handle, key_materials),
+    }
+  }
+
+  // Removes the decode key materials of a remote handle, including the retired
+  // ones
+  fn remove_decode_key_materials(&mut self, remote_entity_crypto_handle: CryptoHandle) {
+    let previous_key_ids = self.decode_key_ids(remote_entity_crypto_handle);
+    self
+      .decode_key_materials
+      .remove(&remote_entity_crypto_handle);
+    self
+      .retired_decode_key_materials
+      .remove(&remote_entity_crypto_handle);
+    self.reindex_decode_key_ids(remote_entity_crypto_handle, previous_key_ids);
+  }
+
+  // The key ids of the current and retired decode key materials of a remote
+  // handle
+  fn decode_key_ids(&self, remote_entity_crypto_handle: CryptoHandle) -> Vec<CryptoTransformKeyId> {
+    self
+      .decode_key_materials
+      .get(&remote_entity_crypto_handle)
+      .into_iter()
+      .chain(
+        self
+          .retired_decode_key_materials
+          .get(&remote_entity_crypto_handle)
+          .into_iter()
+          .flatten()
+          .map(|retired| &retired.key_materials),
+      )
+      .map(|key_materials| key_materials.key_material().sender_key_id)
+      .collect()
+  }
+
+  // The remote participant that sends what a remote handle decodes: the
+  // participant of a remote endpoint, or the remote participant itself
+  fn sending_participant(
+    &self,
+    remote_entity_crypto_handle: CryptoHandle,
+  ) -> ParticipantCryptoHandle {
+    self
+      .endpoint_to_participant
+      .get(&remote_entity_crypto_handle)
+      .copied()
+      .unwrap_or(remote_entity_crypto_handle)
+  }
+
+  // Updates decode_key_id_index after the decode key materials of a remote
+  // handle have changed
+  fn reindex_decode_key_ids(
+    &mut self,
+    remote_entity_crypto_handle: CryptoHandle,
+    previous_key_ids: Vec<CryptoTransformKeyId>,
+  ) {
+    let sending_participant = self.sending_participant(remote_entity_crypto_handle);
+    for key_id in previous_key_ids {
+      if let Entry::Occupied(mut entry) = self
+        .decode_key_id_index
+        .entry((sending_participant, key_id))
+      {
+        entry.get_mut().remove(&remote_entity_crypto_handle);
+        if entry.get().is_empty() {
+          entry.remove();
+        }
+      }
+    }
+    for key_id in self.decode_key_ids(remote_entity_crypto_handle) {
+      self
+        .decode_key_id_index
+        .entry((sending_participant, key_id))
+        .or_default()
+        .insert(remote_entity_crypto_handle);
+    }
+  }
+
+  // The grace period of the local participant matched with the remote endpoint
+  fn rekey_grace_period(
+    &self,
+    remote_endpoint_crypto_handle: EndpointCryptoHandle,
+  ) -> std::time::Duration {
+    self
+      .matched_local_endpoint
+      .get(&remote_endpoint_crypto_handle)
+      .and_then(|local_endpoint_crypto_handle| {
+        self
+          .endpoint_to_participant
+          .get(local_endpoint_crypto_handle)
+      })
+      .and_then(|local_participant_crypto_handle| {
+        self
+          .rekey_grace_periods
+          .get(local_participant_crypto_handle)
+      })
+      .copied()
+      .unwrap_or(DEFAULT_REKEY_GRACE_PERIOD)
+  }
+
+  // Returns the decode key material with the key id of the received message.
+  // Besides the current material of the sender, this may be one that the
+  // sender has replaced, as long as its grace period has not passed.
+  // See "9.5.3.3.5 Computation of plaintext from ciphertext"
   fn get_decode_key_material(
     &self,
     remote_entity_crypto_handle: CryptoHandle,
     key_id: CryptoTransformKeyId,
     key_material_scope: KeyMaterialScope,
   ) -> Option<&KeyMaterial_AES_GCM_GMAC> {
-    // TODO:
-    // Received packet is specifying key_id used to encrypt, but
-    // we just ignore that and assume the key_id is uniquely determined by
-    // crypto handle.
-    // So implement storing multiple keys per handle, distinguished by key_id.
-    // See "9.5.3.3.5 Computation of plaintext from ciphertext"
+    let has_key_id =
+      |KeyMaterial_AES_GCM_GMAC { sender_key_id, .. }: &&KeyMaterial_AES_GCM_GMAC| {
+        sender_key_id.eq(&key_id)
+      };
 
     self
       .decode_key_materials
       .get(&remote_entity_crypto_handle)
       .map(|key_materials| key_materials.select(key_material_scope))
-      .filter(|KeyMaterial_AES_GCM_GMAC { sender_key_id, .. }| sender_key_id.eq(&key_id))
+      .filter(has_key_id)
+      .or_else(|| {
+        let now = clock::monotonic_now();
+        self
+          .retired_decode_key_materials
+          .get(&remote_entity_crypto_handle)?
+          .iter()
+          .filter(|retired| retired.expires > now)
+          .map(|retired| retired.key_materials.select(key_material_scope))
+          .find(has_key_id)
+      })
   }
 
   fn insert_endpoint_info(
@@ -252,6 +738,7 @@ impl CryptographicBuiltin {
           .participant_encrypt_options
           .insert(participant_crypto_handle, old_attributes);
         SecurityResult::Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
           "The ParticipantCryptoHandle {} was already associated with security attributes",
           participant_crypto_handle
         ))
@@ -274,6 +761,7 @@ impl CryptographicBuiltin {
           .endpoint_encrypt_options
           .insert(endpoint_crypto_handle, old_attributes);
         SecurityResult::Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
           "The EndpointCryptoHandle {} was already associated with security attributes",
           endpoint_crypto_handle
         ))
@@ -281,49 +769,255 @@ impl CryptographicBuiltin {
     }
   }
 
-  fn session_id(&self) -> SessionId {
-    // TODO: This should change at times. See comment at struct definition.
-    SessionId::new([1, 3, 3, 7])
+  // Returns the initialization vector for encoding a plaintext of the given
+  // length and counts its blocks in the current session of the sender key.
+  // If the blocks would not fit in the current session, or the initialization
+  // vector suffix would wrap around, a new session is started with the next
+  // session id. Each session starts its suffix from a random value, so that
+  // two senders that happen to share a key and a session id (for example the
+  // two directions between a pair of participants) still do not use the same
+  // initialization vectors. An initialization
+  // vector is never used twice with the same key: once the session ids have
+  // wrapped around to the first one, the key is exhausted and encoding fails.
+  fn next_initialization_vector(
+    &self,
+    sending_local_entity_crypto_handle: CryptoHandle,
+    key_id: CryptoTransformKeyId,
+    plaintext_len: usize,
+  ) -> SecurityResult<BuiltinInitializationVector> {
+    let max_blocks_per_session = self
+      .max_blocks_per_session
+      .get(&sending_local_entity_crypto_handle)
+      .copied()
+      .unwrap_or(DEFAULT_MAX_BLOCKS_PER_SESSION);
+    // Also an empty plaintext consumes an initialization vector
+    let blocks = ((plaintext_len + AES_BLOCK_LENGTH - 1) / AES_BLOCK_LENGTH).max(1) as u64;
+
+    let session_crypto_handle = self
+      .shared_topic_key_handles
+      .get(&sending_local_entity_crypto_handle)
+      .copied()
+      .unwrap_or(sending_local_entity_crypto_handle);
+
+    let mut encode_sessions = self
+      .encode_sessions
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    let session = match encode_sessions.entry((session_crypto_handle, key_id)) {
+      Entry::Occupied(entry) => entry.into_mut(),
+      Entry::Vacant(entry) => {
+        // Initial session ids are arbitrary
+        let session_id = u32::from_be_bytes(self.random_bytes()?);
+        entry.insert(EncodeSession {
+          first_session_id: session_id,
+          session_id,
+          initialization_vector_suffix: self.initial_initialization_vector_suffix()?,
+          blocks: 0,
+        })
+      }
+    };
+
+    if (session.blocks > 0 && session.blocks.saturating_add(blocks) > max_blocks_per_session)
+      || session.initialization_vector_suffix == u64::MAX
+    {
+      let next_session_id = session.session_id.wrapping_add(1);
+      if next_session_id == session.first_session_id {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "All sessions of the key {} of the CryptoHandle {} have been used, refusing to reuse \
+           initialization vectors",
+          key_id,
+          sending_local_entity_crypto_handle
+        ));
+      }
+      session.session_id = next_session_id;
+      session.initialization_vector_suffix = self.initial_initialization_vector_suffix()?;
+      session.blocks = 0;
+      TransformCounters::increment(&self.transform_counters.sessions_rolled);
+    }
+
+    let initialization_vector = BuiltinInitializationVector::new(
+      SessionId::new(session.session_id.to_be_bytes()),
+      session.initialization_vector_suffix.to_be_bytes(),
+    );
+    session.initialization_vector_suffix += 1;
+    session.blocks = session.blocks.saturating_add(blocks);
+    Ok(initialization_vector)
+  }
+
+  // A random start for the initialization vector suffix of a session. It is
+  // drawn from 32 bits, which leaves room for 2^64 - 2^32 initialization
+  // vectors before the suffix would wrap around.
+  fn initial_initialization_vector_suffix(&self) -> SecurityResult<u64> {
+    Ok(u64::from(u32::from_be_bytes(self.random_bytes()?)))
+  }
+
+  fn remove_encode_sessions(&mut self, local_entity_crypto_handle: CryptoHandle) {
+    self
+      .max_blocks_per_session
+      .remove(&local_entity_crypto_handle);
+    self
+      .encode_sessions
+      .get_mut()
+      .unwrap_or_else(PoisonError::into_inner)
+      .retain(|(crypto_handle, _), _| *crypto_handle != local_entity_crypto_handle);
+  }
+
+  // Rejects the initialization vector of an authenticated message if the
+  // receiving participant rejects replays and the message has been received
+  // already from the sender
+  fn check_replay(
+    &self,
+    receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
+    sending_remote_entity_crypto_handle: CryptoHandle,
+    key_id: CryptoTransformKeyId,
+    initialization_vector: BuiltinInitializationVector,
+  ) -> SecurityResult<()> {
+    if !self
+      .reject_replays
+      .contains(&receiving_local_participant_crypto_handle)
+    {
+      return Ok(());
+    }
+    let mut replay_windows = self
+      .replay_windows
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    match replay_windows.entry((sending_remote_entity_crypto_handle, key_id)) {
+      Entry::Vacant(entry) => {
+        entry.insert(ReplayWindow::new(initialization_vector));
+        Ok(())
+      }
+      Entry::Occupied(mut entry) => {
+        if entry.get_mut().accept(initialization_vector) {
+          Ok(())
+        } else {
+          Err(create_security_error_and_log!(
+            SecurityErrorKind::VerificationFailed,
+            "Rejected a replayed message from the CryptoHandle {} with the key {}",
+            sending_remote_entity_crypto_handle,
+            key_id
+          ))
+        }
+      }
+    }
+  }
+
+  // Parses a received crypto footer with the limit of the receiving participant
+  fn receiver_crypto_footer(
+    &self,
+    receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
+    crypto_footer: &CryptoFooter,
+  ) -> SecurityResult<BuiltinCryptoFooter> {
+    BuiltinCryptoFooter::deserialize_with_limit(
+      &crypto_footer.data,
+      self
+        .max_receiver_specific_macs
+        .get(&receiving_local_participant_crypto_handle)
+        .copied()
+        .unwrap_or(DEFAULT_MAX_RECEIVER_SPECIFIC_MACS),
+    )
   }
 
-  fn random_initialization_vector(&self) -> BuiltinInitializationVector {
-    BuiltinInitializationVector::new(self.session_id(), rand::random())
+  fn remove_replay_windows(&mut self, crypto_handle: CryptoHandle) {
+    self.reject_replays.remove(&crypto_handle);
+    self
+      .replay_windows
+      .get_mut()
+      .unwrap_or_else(PoisonError::into_inner)
+      .retain(|(sending_crypto_handle, _), _| *sending_crypto_handle != crypto_handle);
   }
 
-  fn compute_session_key(
+  // Derives the session key of 9.5.3.3.3 or returns it from the cache. Key ids
+  // are chosen by the sender, so different senders may use the same one (e.g.
+  // volatile endpoints all use zero). Therefore the master key and salt are
+  // also compared before reusing an entry.
+  fn session_key(
+    &self,
     rec_spec: ReceiverSpecific,
+    key_id: CryptoTransformKeyId,
     master_key: &BuiltinKey,
     master_salt: &BuiltinKey,
-    iv: BuiltinInitializationVector,
+    session_id: SessionId,
   ) -> BuiltinKey {
-    if let BuiltinKey::None = master_key {
-      return BuiltinKey::None;
-    }
+    let mut session_keys = self
+      .session_keys
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
 
-    // This is the algorithm given in
-    // DDS Security spec v1.1
-    // Section "9.5.3.3.3 Computation of SessionKey and SessionReceiverSpecificKey"
-    use ring::hmac;
+    match session_keys.get(&(key_id, rec_spec)) {
+      Some(cached)
+        if cached.session_id == session_id
+          && cached.master_key == *master_key
+          && cached.master_salt == *master_salt =>
+      {
+        cached.session_key.clone()
+      }
+      _ => {
+        let session_key = match rec_spec {
+          ReceiverSpecific::No => session_key(master_key, master_salt, session_id),
+          ReceiverSpecific::Yes => {
+            session_receiver_specific_key(master_key, master_salt, session_id)
+          }
+        };
+        // Replaces any entry for an earlier session
+        session_keys.insert(
+          (key_id, rec_spec),
+          CachedSessionKey {
+            session_id,
+            master_key: master_key.clone(),
+            master_salt: master_salt.clone(),
+            session_key: session_key.clone(),
+          },
+        );
+        session_key
+      }
+    }
+  }
 
-    let magic_prefix = match rec_spec {
-      ReceiverSpecific::No => b"SessionKey".as_ref(),
-      ReceiverSpecific::Yes => b"SessionReceiverKey".as_ref(),
-    };
+  fn clear_session_keys(&mut self) {
+    self
+      .session_keys
+      .get_mut()
+      .unwrap_or_else(PoisonError::into_inner)
+      .clear();
+  }
 
-    let ring_master_key = hmac::Key::new(hmac::HMAC_SHA256, master_key.as_bytes());
-    let digest = hmac::sign(
-      &ring_master_key,
-      &[
-        magic_prefix,
-        master_salt.as_bytes(),
-        iv.session_id().as_bytes(),
-      ]
-      .concat(),
-    );
+  // True if the local entity protects the scope with
+  // CRYPTO_TRANSFORMATION_KIND_NONE, in which case encoding passes the data
+  // through without a CryptoHeader and CryptoFooter. Volatile endpoints always
+  // protect their submessages.
+  fn encodes_without_protection(
+    &self,
+    sending_local_entity_crypto_handle: CryptoHandle,
+    key_material_scope: KeyMaterialScope,
+  ) -> bool {
+    match self
+      .common_encode_key_materials
+      .get(&sending_local_entity_crypto_handle)
+    {
+      Some(CommonEncodeKeyMaterials::Some(key_materials)) => {
+        key_materials.select(key_material_scope).transformation_kind
+          == BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE
+      }
+      Some(CommonEncodeKeyMaterials::Volatile) | None => false,
+    }
+  }
 
-    // .unwrap() will succeed, because digest has is 256 bits, which
-    // is long enough for both 128- and 256-bit keys.
-    BuiltinKey::from_bytes(master_key.key_length(), digest.as_ref()).unwrap()
+  // True if the remote entity advertised CRYPTO_TRANSFORMATION_KIND_NONE for
+  // the scope, in which case its data arrives as it was before encoding.
+  fn decodes_without_protection(
+    &self,
+    remote_sender_handle: CryptoHandle,
+    key_material_scope: KeyMaterialScope,
+  ) -> bool {
+    self
+      .decode_key_materials
+      .get(&remote_sender_handle)
+      .is_some_and(|key_materials| {
+        key_materials.select(key_material_scope).transformation_kind
+          == BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE
+      })
   }
 
   // Get materials needed for encoding
@@ -332,13 +1026,14 @@ impl CryptographicBuiltin {
     sending_local_entity_crypto_handle: CryptoHandle,
     key_material_scope: KeyMaterialScope,
     receiving_remote_entity_crypto_handles: &[CryptoHandle],
+    plaintext_len: usize,
   ) -> SecurityResult<EncodeSessionMaterials> {
     let common_encode_key_materials =
       self.get_common_encode_key_materials(&sending_local_entity_crypto_handle)?;
 
     let common_encode_key_material = match common_encode_key_materials {
       CommonEncodeKeyMaterials::Some(common_encode_key_materials) => common_encode_key_materials,
-      CommonEncodeKeyMaterials::Volatile(_) => {
+      CommonEncodeKeyMaterials::Volatile => {
         if let [receiving_remote_volatile_endpoint_crypto_handle] =
           receiving_remote_entity_crypto_handles
         {
@@ -347,6 +1042,7 @@ impl CryptographicBuiltin {
           )
         } else {
           Err(create_security_error_and_log!(
+            SecurityErrorKind::Internal,
             "For volatile local endpoint, expected exactly one remote endpoint handle."
           ))
         }?
@@ -364,13 +1060,18 @@ impl CryptographicBuiltin {
 
     let transformation_kind = *transformation_kind;
 
-    let initialization_vector = self.random_initialization_vector();
+    let initialization_vector = self.next_initialization_vector(
+      sending_local_entity_crypto_handle,
+      *sender_key_id,
+      plaintext_len,
+    )?;
 
-    let session_key = Self::compute_session_key(
+    let session_key = self.session_key(
       ReceiverSpecific::No,
+      *sender_key_id,
       master_sender_key,
       master_salt,
-      initialization_vector,
+      initialization_vector.session_id(),
     );
 
     // Get the keys for computing receiver-specific MACs
@@ -392,11 +1093,12 @@ impl CryptographicBuiltin {
               if key_id.is_zero() {
                 None
               } else {
-                let session_key = Self::compute_session_key(
+                let session_key = self.session_key(
                   ReceiverSpecific::Yes,
+                  key_id,
                   &key,
                   master_salt,
-                  initialization_vector,
+                  initialization_vector.session_id(),
                 );
                 Some(ReceiverSpecificKeyMaterial {
                   key_id,
@@ -434,6 +1136,7 @@ impl CryptographicBuiltin {
       )
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find decode key materials for the CryptoHandle {}",
           remote_sender_handle
         )
@@ -458,21 +1161,23 @@ impl CryptographicBuiltin {
     } = self.get_decode_key_material(remote_sender_handle, header_key_id, key_material_scope)?;
 
     let transformation_kind = *transformation_kind;
-    let session_key = Self::compute_session_key(
+    let session_key = self.session_key(
       ReceiverSpecific::No,
+      *sender_key_id,
       master_sender_key,
       master_salt,
-      initialization_vector,
+      initialization_vector.session_id(),
     );
 
     let receiver_specific_key = if receiver_specific_key_id.is_zero() {
       None // does not exist
     } else {
-      let session_key = Self::compute_session_key(
+      let session_key = self.session_key(
         ReceiverSpecific::Yes,
+        *receiver_specific_key_id,
         master_receiver_specific_key,
         master_salt,
-        initialization_vector,
+        initialization_vector.session_id(),
       );
       Some(ReceiverSpecificKeyMaterial {
         key_id: *receiver_specific_key_id,
@@ -489,6 +1194,86 @@ impl CryptographicBuiltin {
   }
 }
 
+// Used when the property "dds.sec.crypto.maxblockspersession" is not set
+const DEFAULT_MAX_BLOCKS_PER_SESSION: u64 = 1 << 32;
+// Used when the property "dds.sec.crypto.rekey_grace_period" is not set
+const DEFAULT_REKEY_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(10);
+const AES_BLOCK_LENGTH: usize = 16;
+
+#[derive(Default)]
+struct TransformCounters {
+  encoded_submessages: AtomicU64,
+  decoded_submessages: AtomicU64,
+  mac_failures: AtomicU64,
+  missing_key_drops: AtomicU64,
+  sessions_rolled: AtomicU64,
+}
+
+impl TransformCounters {
+  // The counters are independent of each other and of other memory, so relaxed
+  // ordering is enough
+  fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  // Passes the result of a MAC check through, counting a failed one
+  fn count_mac_failure<T>(&self, result: SecurityResult<T>) -> SecurityResult<T> {
+    if matches!(&result, Err(e) if e.kind() == SecurityErrorKind::VerificationFailed) {
+      Self::increment(&self.mac_failures);
+    }
+    result
+  }
+
+  fn snapshot(&self) -> CryptoStatistics {
+    CryptoStatistics {
+      encoded_submessages: self.encoded_submessages.load(Ordering::Relaxed),
+      decoded_submessages: self.decoded_submessages.load(Ordering::Relaxed),
+      mac_failures: self.mac_failures.load(Ordering::Relaxed),
+      missing_key_drops: self.missing_key_drops.load(Ordering::Relaxed),
+      sessions_rolled: self.sessions_rolled.load(Ordering::Relaxed),
+      unprotected_submessage_drops: 0,
+    }
+  }
+}
+
+struct EncodeSession {
+  // The session id of the first session, which must not be reused
+  first_session_id: u32,
+  session_id: u32,
+  initialization_vector_suffix: u64,
+  // Number of blocks encoded in the current session
+  blocks: u64,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct SharedTopicKeyIndex {
+  participant_crypto_handle: ParticipantCryptoHandle,
+  topic_name: String,
+  submessage_transformation_kind: BuiltinCryptoTransformationKind,
+  payload_transformation_kind: BuiltinCryptoTransformationKind,
+  // Whether the payloads are protected with the submessage key, which requires
+  // the transformation kinds to be the same
+  reuse_submessage_key_for_payload: bool,
+}
+
+struct SharedTopicKey {
+  crypto_handle: CryptoHandle,
+  key_materials: Arc<KeyMaterial_AES_GCM_GMAC_seq>,
+  datawriters: HashSet<DatawriterCryptoHandle>,
+}
+
+struct RetiredKeyMaterials {
+  key_materials: Arc<KeyMaterial_AES_GCM_GMAC_seq>,
+  expires: std::time::Instant,
+}
+
+struct CachedSessionKey {
+  session_id: SessionId,
+  master_key: BuiltinKey,
+  master_salt: BuiltinKey,
+  session_key: BuiltinKey,
+}
+
 struct EncodeSessionMaterials {
   key_id: CryptoTransformKeyId, // key identifier over the wire
   transformation_kind: BuiltinCryptoTransformationKind, // encrypt/sign/none
@@ -508,3 +1293,368 @@ struct DecodeSessionMaterials {
   receiver_specific_key: Option<ReceiverSpecificKeyMaterial>,
   // Either we have receiver specific key material specific to us or not.
 }
+
+#[cfg(test)]
+impl CryptographicBuiltin {
+  /// Makes the local entity encode with the key in the given session, starting
+  /// from the given initialization vector suffix, so that the output is
+  /// deterministic
+  pub(crate) fn start_encode_session(
+    &self,
+    local_entity_crypto_handle: CryptoHandle,
+    key_id: CryptoTransformKeyId,
+    session_id: SessionId,
+    initialization_vector_suffix: [u8; 8],
+  ) {
+    let session_id = u32::from_be_bytes(session_id.into());
+    self
+      .encode_sessions
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .insert(
+        (local_entity_crypto_handle, key_id),
+        EncodeSession {
+          first_session_id: session_id,
+          session_id,
+          initialization_vector_suffix: u64::from_be_bytes(initialization_vector_suffix),
+          blocks: 0,
+        },
+      );
+  }
+
+  /// Panics if the maps that track participants, endpoints and their matches
+  /// are inconsistent with each other
+  pub(crate) fn check_invariants(&self) {
+    for (endpoint, participant) in &self.endpoint_to_participant {
+      assert!(
+        self
+          .participant_to_endpoint_info
+          .get(participant)
+          .is_some_and(|endpoints| endpoints.iter().any(|info| info.crypto_handle == *endpoint)),
+        "endpoint {endpoint} is missing from the endpoints of participant {participant}"
+      );
+    }
+    for (participant, endpoints) in &self.participant_to_endpoint_info {
+      assert!(
+        !endpoints.is_empty(),
+        "participant {participant} has an empty endpoint set"
+      );
+      for info in endpoints {
+        assert_eq!(
+          self.endpoint_to_participant.get(&info.crypto_handle),
+          Some(participant),
+          "endpoint {} has no reverse mapping to participant {participant}",
+          info.crypto_handle
+        );
+      }
+    }
+
+    for (local_endpoint, remote_endpoints) in &self.matched_remote_endpoint {
+      assert!(
+        !remote_endpoints.is_empty(),
+        "local endpoint {local_endpoint} has an empty set of matched remote endpoints"
+      );
+      assert!(
+        self.endpoint_to_participant.contains_key(local_endpoint),
+        "matched local endpoint {local_endpoint} is not registered"
+      );
+      for (remote_participant, remote_endpoint) in remote_endpoints {
+        assert_eq!(
+          self.matched_local_endpoint.get(remote_endpoint),
+          Some(local_endpoint),
+          "remote endpoint {remote_endpoint} has no reverse mapping to local endpoint \
+           {local_endpoint}"
+        );
+        assert_eq!(
+          self.endpoint_to_participant.get(remote_endpoint),
+          Some(remote_participant),
+          "remote endpoint {remote_endpoint} is not registered to participant \
+           {remote_participant}"
+        );
+      }
+    }
+    for (remote_endpoint, local_endpoint) in &self.matched_local_endpoint {
+      assert!(
+        self
+          .matched_remote_endpoint
+          .get(local_endpoint)
+          .is_some_and(|remote_endpoints| remote_endpoints.values().any(|e| e == remote_endpoint)),
+        "remote endpoint {remote_endpoint} is missing from the matches of local endpoint \
+         {local_endpoint}"
+      );
+    }
+
+    // Every handle with key materials or options belongs to a registered entity
+    let participants: HashSet<CryptoHandle> = self
+      .participant_encrypt_options
+      .keys()
+      .copied()
+      .chain(
+        self
+          .matched_remote_participants
+          .values()
+          .map(|matched| matched.crypto_handle),
+      )
+      .chain(self.imported_remote_participants.iter().copied())
+      .collect();
+    let shared_topic_keys: HashSet<CryptoHandle> = self
+      .shared_topic_keys
+      .values()
+      .map(|shared_key| shared_key.crypto_handle)
+      .collect();
+    let is_registered = |crypto_handle: &CryptoHandle| {
+      participants.contains(crypto_handle)
+        || self.endpoint_to_participant.contains_key(crypto_handle)
+    };
+    for (map_name, crypto_handles) in [
+      (
+        "common_encode_key_materials",
+        self.common_encode_key_materials.keys().collect::<Vec<_>>(),
+      ),
+      (
+        "receiver_specific_encode_key_materials",
+        self.receiver_specific_encode_key_materials.keys().collect(),
+      ),
+      (
+        "decode_key_materials",
+        self.decode_key_materials.keys().collect(),
+      ),
+      ("key_exchange_keys", self.key_exchange_keys.keys().collect()),
+      ("key_wrap_peers", self.key_wrap_peers.iter().collect()),
+      (
+        "retired_decode_key_materials",
+        self.retired_decode_key_materials.keys().collect(),
+      ),
+      ("rekeyed_endpoints", self.rekeyed_endpoints.iter().collect()),
+      (
+        "relay_only_remote_datareaders",
+        self.relay_only_remote_datareaders.iter().collect(),
+      ),
+      (
+        "endpoint_encrypt_options",
+        self.endpoint_encrypt_options.keys().collect(),
+      ),
+      (
+        "max_blocks_per_session",
+        self.max_blocks_per_session.keys().collect(),
+      ),
+      (
+        "max_receiver_specific_macs",
+        self.max_receiver_specific_macs.keys().collect(),
+      ),
+      (
+        "shared_topic_key_handles",
+        self.shared_topic_key_handles.keys().collect(),
+      ),
+    ] {
+      for crypto_handle in crypto_handles {
+        assert!(
+          is_registered(crypto_handle),
+          "{map_name} has an entry for the unregistered handle {crypto_handle}"
+        );
+      }
+    }
+    for ((sending_participant, key_id), crypto_handles) in &self.decode_key_id_index {
+      assert!(
+        !crypto_handles.is_empty(),
+        "decode_key_id_index has an empty entry for the key {key_id}"
+      );
+      for crypto_handle in crypto_handles {
+        assert!(
+          self.decode_key_ids(*crypto_handle).contains(key_id),
+          "decode_key_id_index maps the key {key_id} to {crypto_handle}, which does not have it"
+        );
+        assert_eq!(
+          self.sending_participant(*crypto_handle),
+          *sending_participant,
+          "decode_key_id_index has {crypto_handle} under another sending participant"
+        );
+      }
+    }
+    for crypto_handle in self
+      .decode_key_materials
+      .keys()
+      .chain(self.retired_decode_key_materials.keys())
+    {
+      for key_id in self.decode_key_ids(*crypto_handle) {
+        assert!(
+          self
+            .decode_key_id_index
+            .get(&(self.sending_participant(*crypto_handle), key_id))
+            .is_some_and(|crypto_handles| crypto_handles.contains(crypto_handle)),
+          "the key {key_id} of {crypto_handle} is missing from decode_key_id_index"
+        );
+      }
+    }
+    for crypto_handle in self.shared_topic_key_handles.values() {
+      assert!(
+        shared_topic_keys.contains(crypto_handle),
+        "shared topic key {crypto_handle} is in use but not stored"
+      );
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    messages::submessages::elements::parameter_list::ParameterList,
+    security::entropy::test_sources::CountingEntropySource,
+  };
+
+  // The session id and the suffix of an initialization vector
+  fn split_initialization_vector(iv: BuiltinInitializationVector) -> (u32, u64) {
+    (
+      u32::from_be_bytes(iv.session_id().into()),
+      u64::from_be_bytes(iv.initialization_vector_suffix()),
+    )
+  }
+
+  #[test]
+  fn initialization_vector_suffix_wrap_starts_a_new_session() {
+    let crypto =
+      CryptographicBuiltin::with_entropy_source(Arc::new(CountingEntropySource::default()));
+    let key_id = CryptoTransformKeyId::from([1, 2, 3, 4]);
+    let next =
+      || split_initialization_vector(crypto.next_initialization_vector(1, key_id, 16).unwrap());
+
+    // The initial session id is the first four bytes from the entropy source,
+    // and the initial suffix the next four
+    assert_eq!(next(), (0x0001_0203, 0x0405_0607));
+    crypto
+      .encode_sessions
+      .lock()
+      .unwrap()
+      .get_mut(&(1, key_id))
+      .unwrap()
+      .initialization_vector_suffix = u64::MAX - 1;
+    assert_eq!(next(), (0x0001_0203, u64::MAX - 1));
+    // The suffix u64::MAX is not used, as the counter would wrap after it. The
+    // new session starts from a new random suffix.
+    assert_eq!(next(), (0x0001_0204, 0x0809_0a0b));
+    assert_eq!(next(), (0x0001_0204, 0x0809_0a0c));
+  }
+
+  #[test]
+  fn exhausted_key_is_not_reused() {
+    let crypto =
+      CryptographicBuiltin::with_entropy_source(Arc::new(CountingEntropySource::default()));
+    let key_id = CryptoTransformKeyId::from([1, 2, 3, 4]);
+    crypto.next_initialization_vector(1, key_id, 16).unwrap();
+
+    // Move to the last session before the session ids wrap around to the first one
+    if let Some(session) = crypto.encode_sessions.lock().unwrap().get_mut(&(1, key_id)) {
+      session.session_id = session.first_session_id.wrapping_sub(1);
+      session.initialization_vector_suffix = u64::MAX - 1;
+    }
+    assert_eq!(
+      split_initialization_vector(crypto.next_initialization_vector(1, key_id, 16).unwrap()),
+      (0x0001_0202, u64::MAX - 1)
+    );
+    assert!(crypto.next_initialization_vector(1, key_id, 16).is_err());
+    assert!(crypto.next_initialization_vector(1, key_id, 16).is_err());
+    // Other keys are not affected
+    assert!(crypto
+      .next_initialization_vector(1, CryptoTransformKeyId::from([5, 6, 7, 8]), 16)
+      .is_ok());
+  }
+
+  #[test]
+  fn session_keys_are_cached_per_session() {
+    let crypto = CryptographicBuiltin::new();
+    let key_id = CryptoTransformKeyId::from(rand::random::<[u8; 4]>());
+    let master_key =
+      BuiltinKey::generate_random(KeyLength::AES256, &SystemEntropySource::new()).unwrap();
+    let master_salt =
+      BuiltinKey::generate_random(KeyLength::AES256, &SystemEntropySource::new()).unwrap();
+    let first_session = SessionId::new([1, 3, 3, 7]);
+    let second_session = SessionId::new([1, 3, 3, 8]);
+
+    let first = crypto.session_key(
+      ReceiverSpecific::No,
+      key_id,
+      &master_key,
+      &master_salt,
+      first_session,
+    );
+    assert_eq!(first, session_key(&master_key, &master_salt, first_session));
+    assert_eq!(
+      crypto.session_key(
+        ReceiverSpecific::No,
+        key_id,
+        &master_key,
+        &master_salt,
+        first_session
+      ),
+      first
+    );
+
+    // A new session id invalidates the cached entry
+    let second = crypto.session_key(
+      ReceiverSpecific::No,
+      key_id,
+      &master_key,
+      &master_salt,
+      second_session,
+    );
+    assert_eq!(
+      second,
+      session_key(&master_key, &master_salt, second_session)
+    );
+    assert_ne!(first, second);
+    assert_eq!(crypto.session_keys.lock().unwrap().len(), 1);
+
+    // The same key id with other key material is not served from the cache
+    let other_master_key =
+      BuiltinKey::generate_random(KeyLength::AES256, &SystemEntropySource::new()).unwrap();
+    assert_eq!(
+      crypto.session_key(
+        ReceiverSpecific::No,
+        key_id,
+        &other_master_key,
+        &master_salt,
+        second_session
+      ),
+      session_key(&other_master_key, &master_salt, second_session)
+    );
+
+    // Receiver-specific keys are derived with a different prefix
+    assert_eq!(
+      crypto.session_key(
+        ReceiverSpecific::Yes,
+        key_id,
+        &master_key,
+        &master_salt,
+        second_session
+      ),
+      session_receiver_specific_key(&master_key, &master_salt, second_session)
+    );
+  }
+
+  #[test]
+  fn decoding_errors_have_the_expected_kind() {
+    let crypto = CryptographicBuiltin::new();
+    let too_short = vec![0; BuiltinCryptoHeader::serialized_len()];
+    assert_eq!(
+      crypto
+        .decode_serialized_payload(too_short, ParameterList::new(), 1, 2)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::MalformedToken
+    );
+
+    assert_eq!(
+      crypto
+        .session_decode_crypto_materials(
+          2,
+          CryptoTransformKeyId::from([1, 2, 3, 4]),
+          KeyMaterialScope::PayloadOnly,
+          BuiltinInitializationVector::new(SessionId::new([0, 0, 0, 1]), [0; 8]),
+        )
+        .err()
+        .map(|e| e.kind()),
+      Some(SecurityErrorKind::NotRegistered)
+    );
+  }
+}
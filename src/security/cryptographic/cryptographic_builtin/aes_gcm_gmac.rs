@@ -1,12 +1,12 @@
-use ring::{aead::*, error::Unspecified};
+use ring::{aead::*, error::Unspecified, hmac};
 
 use crate::{
   create_security_error_and_log,
-  security::{SecurityError, SecurityResult},
+  security::{entropy::EntropySource, SecurityError, SecurityErrorKind, SecurityResult},
 };
 use super::{
   builtin_key::*,
-  types::{BuiltinInitializationVector, BuiltinMAC, MAC_LENGTH},
+  types::{BuiltinInitializationVector, BuiltinMAC, SessionId, MAC_LENGTH},
 };
 
 // By design of Secure RTPS, there is a unique Initialization Vector
@@ -36,14 +36,66 @@ impl NonceSequence for TrivialNonceSequence {
 }
 
 // Generate a key of the given length
-pub(super) fn keygen(key_length: KeyLength) -> BuiltinKey {
-  BuiltinKey::generate_random(key_length)
+pub(super) fn keygen(
+  key_length: KeyLength,
+  entropy_source: &dyn EntropySource,
+) -> SecurityResult<BuiltinKey> {
+  BuiltinKey::generate_random(key_length, entropy_source)
+}
+
+// DDS Security spec v1.1
+// Section "9.5.3.3.3 Computation of SessionKey and SessionReceiverSpecificKey":
+//
+// SessionKey = HMAC256(MasterSenderKey, "SessionKey" | MasterSalt | SessionId)
+pub(super) fn session_key(
+  master_key: &BuiltinKey,
+  master_salt: &BuiltinKey,
+  session_id: SessionId,
+) -> BuiltinKey {
+  derive_session_key(b"SessionKey", master_key, master_salt, session_id)
+}
+
+// SessionReceiverSpecificKey = HMAC256(MasterReceiverSpecificKey,
+//   "SessionReceiverKey" | MasterSalt | SessionId)
+pub(super) fn session_receiver_specific_key(
+  master_receiver_specific_key: &BuiltinKey,
+  master_salt: &BuiltinKey,
+  session_id: SessionId,
+) -> BuiltinKey {
+  derive_session_key(
+    b"SessionReceiverKey",
+    master_receiver_specific_key,
+    master_salt,
+    session_id,
+  )
+}
+
+fn derive_session_key(
+  magic_prefix: &[u8],
+  master_key: &BuiltinKey,
+  master_salt: &BuiltinKey,
+  session_id: SessionId,
+) -> BuiltinKey {
+  if let BuiltinKey::None = master_key {
+    return BuiltinKey::None;
+  }
+
+  let ring_master_key = hmac::Key::new(hmac::HMAC_SHA256, master_key.as_bytes());
+  let digest = hmac::sign(
+    &ring_master_key,
+    &[magic_prefix, master_salt.as_bytes(), session_id.as_bytes()].concat(),
+  );
+
+  // .unwrap() will succeed, because digest has is 256 bits, which
+  // is long enough for both 128- and 256-bit keys.
+  BuiltinKey::from_bytes(master_key.key_length(), digest.as_ref()).unwrap()
 }
 
 #[allow(non_snake_case)]
 fn to_unbound_AES_GCM_key(key: &BuiltinKey) -> SecurityResult<UnboundKey> {
   match key {
     BuiltinKey::None => Err(create_security_error_and_log!(
+      SecurityErrorKind::Internal,
       "Attempted to call a cryptographic function with an empty key."
     )),
     // unwraps should be safe, because builtin key lengths always match expected length
@@ -109,25 +161,28 @@ pub(super) fn encrypt(
   Ok((in_out_data, to_builtin_mac(&tag)))
 }
 
-// Validates the MAC
+// Validates the MAC. This is AES-GCM decryption of an empty ciphertext with
+// the data as AAD, so ring verifies the tag, in constant time.
 pub(super) fn validate_mac(
   key: &BuiltinKey,
   initialization_vector: BuiltinInitializationVector,
   data: &[u8],
   mac: BuiltinMAC,
 ) -> SecurityResult<()> {
-  let mut in_out = Vec::with_capacity(MAC_LENGTH);
-  in_out.extend_from_slice(&mac);
-
   let mut opening_key = OpeningKey::new(
     to_unbound_AES_GCM_key(key)?,
     TrivialNonceSequence::new(initialization_vector),
   );
-
-  // This will return `Err(..)` if verification fails
-  let _plaintext = opening_key.open_in_place(Aad::from(data), &mut in_out)?;
-  // If we get here, the mac ("tag") was valid.
-  Ok(())
+  let mut in_out = mac;
+  opening_key
+    .open_in_place(Aad::from(data), &mut in_out)
+    .map(|_| ())
+    .map_err(|_| {
+      create_security_error_and_log!(
+        SecurityErrorKind::VerificationFailed,
+        "The MAC does not match the data."
+      )
+    })
 }
 
 // Authenticated decryption: validates the MAC and decrypts the ciphertext
@@ -147,8 +202,16 @@ pub(super) fn decrypt(
     TrivialNonceSequence::new(initialization_vector),
   );
 
-  // This will return `Err(..)` if verification fails
-  let plaintext = opening_key.open_in_place(Aad::empty(), &mut in_out)?;
+  // This will return `Err(..)` if verification fails. ring compares the tags
+  // in constant time.
+  let plaintext = opening_key
+    .open_in_place(Aad::empty(), &mut in_out)
+    .map_err(|_| {
+      create_security_error_and_log!(
+        SecurityErrorKind::VerificationFailed,
+        "Decryption failed: the MAC does not match the ciphertext."
+      )
+    })?;
   // If we get here, the mac ("tag") was valid.
   // and `plaintext` is actually a slice of `in_out`
   let plain_len = plaintext.len();
@@ -156,3 +219,132 @@ pub(super) fn decrypt(
 
   Ok(in_out)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn key_bytes(first: u8) -> [u8; AES256_KEY_LENGTH] {
+    core::array::from_fn(|i| first + i as u8)
+  }
+
+  fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+      .step_by(2)
+      .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+      .collect()
+  }
+
+  // Expected values computed independently with HMAC-SHA256 over
+  // "SessionKey" | MasterSalt | SessionId as given in 9.5.3.3.3
+  #[test]
+  fn session_key_known_answer_aes256() {
+    let master_key = BuiltinKey::AES256(key_bytes(0x00));
+    let master_salt = BuiltinKey::AES256(key_bytes(0x20));
+    let session_id = SessionId::new([1, 3, 3, 7]);
+
+    assert_eq!(
+      session_key(&master_key, &master_salt, session_id).as_bytes(),
+      from_hex("b1c1239474c98e295e1325059da0349bb6e7f161370c90beeb4d2f1f83df6772")
+    );
+    assert_eq!(
+      session_receiver_specific_key(&master_key, &master_salt, session_id).as_bytes(),
+      from_hex("85aa25f8eaadcae254ac5c6c830719efb4b7b5f18990831ed62c3a7f513104c1")
+    );
+  }
+
+  #[test]
+  fn session_key_known_answer_aes128() {
+    let master_key = BuiltinKey::from_bytes(KeyLength::AES128, &key_bytes(0x00)).unwrap();
+    let master_salt = BuiltinKey::from_bytes(KeyLength::AES128, &key_bytes(0x20)).unwrap();
+
+    // The 256-bit digest is truncated to the key length
+    assert_eq!(
+      session_key(&master_key, &master_salt, SessionId::new([1, 3, 3, 7])),
+      BuiltinKey::AES128(
+        from_hex("0861f86a1fc3370fec53e047104f36c5")
+          .try_into()
+          .unwrap()
+      )
+    );
+  }
+
+  #[test]
+  fn mismatching_macs_are_rejected() {
+    let key = BuiltinKey::AES128(core::array::from_fn(|i| i as u8));
+    let initialization_vector =
+      BuiltinInitializationVector::new(SessionId::new([0, 0, 0, 1]), [0, 0, 0, 0, 0, 0, 0, 2]);
+    let data = b"some data";
+    let mac = compute_mac(&key, initialization_vector, data).unwrap();
+    assert!(validate_mac(&key, initialization_vector, data, mac).is_ok());
+
+    let mut first_byte_differs = mac;
+    first_byte_differs[0] ^= 1;
+    let mut last_byte_differs = mac;
+    last_byte_differs[MAC_LENGTH - 1] ^= 0x80;
+    for wrong_mac in [first_byte_differs, last_byte_differs, [0; MAC_LENGTH]] {
+      assert!(validate_mac(&key, initialization_vector, data, wrong_mac).is_err());
+    }
+  }
+
+  #[test]
+  fn validate_mac_rejects_tampering() {
+    let key = BuiltinKey::AES256(key_bytes(0x00));
+    let initialization_vector =
+      BuiltinInitializationVector::new(SessionId::new([1, 3, 3, 7]), [0, 0, 0, 0, 0, 0, 0, 1]);
+    let data = b"authenticated but not encrypted";
+    let mac = compute_mac(&key, initialization_vector, data).unwrap();
+
+    assert!(validate_mac(&key, initialization_vector, data, mac).is_ok());
+
+    let mut tampered_mac = mac;
+    tampered_mac[MAC_LENGTH / 2] ^= 1;
+    assert_eq!(
+      validate_mac(&key, initialization_vector, data, tampered_mac)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::VerificationFailed
+    );
+    assert!(validate_mac(&key, initialization_vector, b"tampered data", mac).is_err());
+  }
+
+  #[test]
+  fn decrypt_rejects_tampering_as_verification_failure() {
+    let key = BuiltinKey::AES128(core::array::from_fn(|i| i as u8));
+    let initialization_vector =
+      BuiltinInitializationVector::new(SessionId::new([1, 3, 3, 7]), [0, 0, 0, 0, 0, 0, 0, 2]);
+    let (ciphertext, mac) = encrypt(&key, initialization_vector, b"secret").unwrap();
+    assert_eq!(
+      decrypt(&key, initialization_vector, &ciphertext, mac).unwrap(),
+      b"secret"
+    );
+
+    let mut tampered_ciphertext = ciphertext;
+    tampered_ciphertext[0] ^= 1;
+    assert_eq!(
+      decrypt(&key, initialization_vector, &tampered_ciphertext, mac)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::VerificationFailed
+    );
+  }
+
+  #[test]
+  fn session_key_changes_with_session_id() {
+    let master_key = BuiltinKey::AES256(key_bytes(0x00));
+    let master_salt = BuiltinKey::AES256(key_bytes(0x20));
+
+    assert_ne!(
+      session_key(&master_key, &master_salt, SessionId::new([1, 3, 3, 7])),
+      session_key(&master_key, &master_salt, SessionId::new([1, 3, 3, 8]))
+    );
+    assert_eq!(
+      session_key(
+        &BuiltinKey::None,
+        &master_salt,
+        SessionId::new([1, 3, 3, 7])
+      ),
+      BuiltinKey::None
+    );
+  }
+}
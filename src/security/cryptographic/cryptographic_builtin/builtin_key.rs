@@ -1,13 +1,29 @@
-use crate::security::{security_error, SecurityResult};
+use std::fmt;
+
+use crate::security::{
+  entropy::{random_bytes, EntropySource},
+  security_error, SecurityResult,
+};
 use super::types::BuiltinCryptoTransformationKind;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq)]
 pub(super) enum BuiltinKey {
   None,
   AES128([u8; AES128_KEY_LENGTH]),
   AES256([u8; AES256_KEY_LENGTH]),
 }
 
+impl fmt::Debug for BuiltinKey {
+  // Manual implementation, so that keys and salts do not end up in logs
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      BuiltinKey::None => f.write_str("None"),
+      BuiltinKey::AES128(_) => f.write_str("AES128(<redacted>)"),
+      BuiltinKey::AES256(_) => f.write_str("AES256(<redacted>)"),
+    }
+  }
+}
+
 impl BuiltinKey {
   pub(super) fn as_bytes(&self) -> &[u8] {
     match self {
@@ -51,16 +67,15 @@ impl BuiltinKey {
     }
   }
 
-  // Rust `rand` library uses by default the 12-round chacha-algorithm, which is
-  // "widely believed" to be secure.
-  // The library documentation states that the generator may be upgraded, if it is
-  // found to be insecure.
-  pub(super) fn generate_random(key_len: KeyLength) -> Self {
-    match key_len {
+  pub(super) fn generate_random(
+    key_len: KeyLength,
+    entropy_source: &dyn EntropySource,
+  ) -> SecurityResult<Self> {
+    Ok(match key_len {
       KeyLength::None => BuiltinKey::None,
-      KeyLength::AES128 => BuiltinKey::AES128(rand::random::<[u8; AES128_KEY_LENGTH]>()),
-      KeyLength::AES256 => BuiltinKey::AES256(rand::random::<[u8; AES256_KEY_LENGTH]>()),
-    }
+      KeyLength::AES128 => BuiltinKey::AES128(random_bytes(entropy_source)?),
+      KeyLength::AES256 => BuiltinKey::AES256(random_bytes(entropy_source)?),
+    })
   }
 }
 
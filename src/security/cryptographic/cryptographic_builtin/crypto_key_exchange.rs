@@ -1,30 +1,50 @@
 use crate::security::cryptographic::cryptographic_builtin::*;
-use super::key_material::*;
+use super::{key_material::*, types::*};
 
 impl CryptoKeyExchange for CryptographicBuiltin {
   fn create_local_participant_crypto_tokens(
     &mut self,
-    _local_participant_crypto_handle: ParticipantCryptoHandle,
+    local_participant_crypto_handle: ParticipantCryptoHandle,
     remote_participant_crypto_handle: ParticipantCryptoHandle,
   ) -> SecurityResult<Vec<ParticipantCryptoToken>> {
-    //TODO: this is only a mock implementation (or is it?)
-    self
-      .get_receiver_specific_encode_key_materials(&remote_participant_crypto_handle)
-      .cloned()
-      // Convert to CryptoTokens
-      .and_then(Vec::<DatawriterCryptoToken>::try_from)
+    let key_materials =
+      self.get_receiver_specific_encode_key_materials(&remote_participant_crypto_handle)?;
+    self.check_participant_key_materials(
+      local_participant_crypto_handle,
+      remote_participant_crypto_handle,
+      key_materials,
+      SecurityErrorKind::Internal,
+    )?;
+    // Convert to CryptoTokens, wrapping the key materials if the remote
+    // announced it
+    let mut tokens =
+      key_materials.to_crypto_tokens(self.get_key_wrap_key(remote_participant_crypto_handle)?)?;
+    if self
+      .key_wrap_participants
+      .contains(&local_participant_crypto_handle)
+    {
+      tokens.iter_mut().for_each(announce_key_wrap);
+    }
+    Ok(tokens)
   }
 
   fn set_remote_participant_crypto_tokens(
     &mut self,
-    _local_participant_crypto_handle: ParticipantCryptoHandle,
+    local_participant_crypto_handle: ParticipantCryptoHandle,
     remote_participant_crypto_handle: ParticipantCryptoHandle,
     remote_participant_tokens: Vec<ParticipantCryptoToken>,
   ) -> SecurityResult<()> {
-    //TODO: this is only a mock implementation (or is it?)
-    KeyMaterial_AES_GCM_GMAC_seq::try_from(remote_participant_tokens).and_then(|key_materials| {
-      self.insert_decode_key_materials(remote_participant_crypto_handle, key_materials)
-    })
+    let result = self.set_remote_participant_key_materials(
+      local_participant_crypto_handle,
+      remote_participant_crypto_handle,
+      remote_participant_tokens,
+    );
+    self.report_rejection(
+      CryptoOperation::SetCryptoTokens,
+      remote_participant_crypto_handle,
+      &result,
+    );
+    result
   }
 
   fn create_local_datawriter_crypto_tokens(
@@ -34,11 +54,11 @@ impl CryptoKeyExchange for CryptographicBuiltin {
   ) -> SecurityResult<Vec<DatawriterCryptoToken>> {
     //TODO: this is only a mock implementation (or is it?)
 
-    self
-      .get_receiver_specific_encode_key_materials(&remote_datareader_crypto_handle)
-      .cloned()
-      // Convert to CryptoTokens
-      .and_then(Vec::<DatawriterCryptoToken>::try_from)
+    let key_materials =
+      self.get_receiver_specific_encode_key_materials(&remote_datareader_crypto_handle)?;
+    // Convert to CryptoTokens, wrapping the key materials if the remote
+    // participant announced it
+    key_materials.to_crypto_tokens(self.get_key_wrap_key(remote_datareader_crypto_handle)?)
   }
 
   fn set_remote_datawriter_crypto_tokens(
@@ -48,9 +68,14 @@ impl CryptoKeyExchange for CryptographicBuiltin {
     remote_datawriter_tokens: Vec<DatawriterCryptoToken>,
   ) -> SecurityResult<()> {
     //TODO: this is only a mock implementation
-    KeyMaterial_AES_GCM_GMAC_seq::try_from(remote_datawriter_tokens).and_then(|key_materials| {
-      self.insert_decode_key_materials(remote_datawriter_crypto_handle, key_materials)
-    })
+    let result = self
+      .set_remote_endpoint_crypto_tokens(remote_datawriter_crypto_handle, remote_datawriter_tokens);
+    self.report_rejection(
+      CryptoOperation::SetCryptoTokens,
+      remote_datawriter_crypto_handle,
+      &result,
+    );
+    result
   }
 
   fn create_local_datareader_crypto_tokens(
@@ -60,11 +85,11 @@ impl CryptoKeyExchange for CryptographicBuiltin {
   ) -> SecurityResult<Vec<DatareaderCryptoToken>> {
     //TODO: this is only a mock implementation (or is it?)
 
-    self
-      .get_receiver_specific_encode_key_materials(&remote_datawriter_crypto_handle)
-      .cloned()
-      // Convert to CryptoTokens
-      .and_then(Vec::<DatawriterCryptoToken>::try_from)
+    let key_materials =
+      self.get_receiver_specific_encode_key_materials(&remote_datawriter_crypto_handle)?;
+    // Convert to CryptoTokens, wrapping the key materials if the remote
+    // participant announced it
+    key_materials.to_crypto_tokens(self.get_key_wrap_key(remote_datawriter_crypto_handle)?)
   }
 
   fn set_remote_datareader_crypto_tokens(
@@ -74,9 +99,14 @@ impl CryptoKeyExchange for CryptographicBuiltin {
     remote_datareader_tokens: Vec<DatareaderCryptoToken>,
   ) -> SecurityResult<()> {
     //TODO: this is only a mock implementation
-    KeyMaterial_AES_GCM_GMAC_seq::try_from(remote_datareader_tokens).and_then(|key_materials| {
-      self.insert_decode_key_materials(remote_datareader_crypto_handle, key_materials)
-    })
+    let result = self
+      .set_remote_endpoint_crypto_tokens(remote_datareader_crypto_handle, remote_datareader_tokens);
+    self.report_rejection(
+      CryptoOperation::SetCryptoTokens,
+      remote_datareader_crypto_handle,
+      &result,
+    );
+    result
   }
 
   fn return_crypto_tokens(&mut self, _crypto_tokens: Vec<CryptoToken>) -> SecurityResult<()> {
@@ -84,3 +114,115 @@ impl CryptoKeyExchange for CryptographicBuiltin {
     Ok(())
   }
 }
+
+impl CryptographicBuiltin {
+  // Unwraps the key materials from the crypto tokens of a remote participant or
+  // endpoint
+  fn remote_key_materials(
+    &self,
+    remote_crypto_handle: CryptoHandle,
+    remote_tokens: Vec<CryptoToken>,
+  ) -> SecurityResult<KeyMaterial_AES_GCM_GMAC_seq> {
+    KeyMaterial_AES_GCM_GMAC_seq::from_crypto_tokens(
+      remote_tokens,
+      self.get_key_exchange_key(remote_crypto_handle)?,
+    )
+  }
+
+  // Participant key materials are a single key material, which carries a
+  // receiver-specific key when RTPS origin authentication is on
+  fn check_participant_key_materials(
+    &self,
+    local_participant_crypto_handle: ParticipantCryptoHandle,
+    remote_participant_crypto_handle: ParticipantCryptoHandle,
+    key_materials: &KeyMaterial_AES_GCM_GMAC_seq,
+    error_kind: SecurityErrorKind,
+  ) -> SecurityResult<()> {
+    let key_material = match key_materials {
+      KeyMaterial_AES_GCM_GMAC_seq::One(key_material) => key_material,
+      KeyMaterial_AES_GCM_GMAC_seq::Two(..) => {
+        return Err(create_security_error_and_log!(
+          error_kind,
+          "Expected exactly one key material for the remote participant {}, found two",
+          remote_participant_crypto_handle
+        ))
+      }
+    };
+    let has_receiver_specific_key = key_material.receiver_specific_key_id
+      != CryptoTransformKeyId::ZERO
+      && key_material.master_receiver_specific_key != BuiltinKey::None;
+    if self.is_rtps_origin_authenticated(local_participant_crypto_handle)?
+      && !has_receiver_specific_key
+    {
+      return Err(create_security_error_and_log!(
+        error_kind,
+        "The key material for the remote participant {} has no receiver-specific key, but RTPS \
+         origin authentication is on",
+        remote_participant_crypto_handle
+      ));
+    }
+    Ok(())
+  }
+
+  // Sets the key materials from the crypto tokens of a remote participant.
+  // Discovery may deliver the same tokens again, which is accepted as is, but
+  // different key materials for an already set participant are rejected.
+  fn set_remote_participant_key_materials(
+    &mut self,
+    local_participant_crypto_handle: ParticipantCryptoHandle,
+    remote_participant_crypto_handle: ParticipantCryptoHandle,
+    remote_participant_tokens: Vec<ParticipantCryptoToken>,
+  ) -> SecurityResult<()> {
+    let announces_key_wrap = announces_key_wrap(&remote_participant_tokens);
+    let key_materials =
+      self.remote_key_materials(remote_participant_crypto_handle, remote_participant_tokens)?;
+    self.check_participant_key_materials(
+      local_participant_crypto_handle,
+      remote_participant_crypto_handle,
+      &key_materials,
+      SecurityErrorKind::MalformedToken,
+    )?;
+    // Key material is wrapped only if both sides of the participant pair wrap
+    if announces_key_wrap
+      && self
+        .key_wrap_participants
+        .contains(&local_participant_crypto_handle)
+    {
+      self.key_wrap_peers.insert(remote_participant_crypto_handle);
+    }
+    match self
+      .decode_key_materials
+      .get(&remote_participant_crypto_handle)
+    {
+      Some(previous) if **previous == key_materials => {
+        debug!(
+          "Remote participant {remote_participant_crypto_handle} sent the same crypto tokens again"
+        );
+        Ok(())
+      }
+      Some(_) => Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "The crypto tokens of the remote participant {} were already set with different key \
+         material",
+        remote_participant_crypto_handle
+      )),
+      None => {
+        self.insert_decode_key_materials(remote_participant_crypto_handle, Arc::new(key_materials))
+      }
+    }
+  }
+
+  fn set_remote_endpoint_crypto_tokens(
+    &mut self,
+    remote_endpoint_crypto_handle: EndpointCryptoHandle,
+    remote_endpoint_tokens: Vec<CryptoToken>,
+  ) -> SecurityResult<()> {
+    let key_materials =
+      self.remote_key_materials(remote_endpoint_crypto_handle, remote_endpoint_tokens)?;
+    self.check_remote_transformation_kinds(remote_endpoint_crypto_handle, &key_materials)?;
+    self.set_remote_endpoint_decode_key_materials(
+      remote_endpoint_crypto_handle,
+      Arc::new(key_materials),
+    )
+  }
+}
@@ -1,3 +1,4 @@
+use log::debug;
 use ring::{digest, hmac};
 
 use crate::{
@@ -10,21 +11,79 @@ use crate::{
       types::*,
     },
     cryptographic::cryptographic_builtin::*,
+    types::{ENTITY_ID_PROPERTY_NAME, TOPIC_NAME_PROPERTY_NAME},
   },
+  security_warn,
+  structure::guid::EntityId,
 };
 use super::{aes_gcm_gmac::keygen, builtin_key::*, key_material::*};
 
 impl CryptographicBuiltin {
-  fn generate_crypto_handle(&mut self) -> CryptoHandle {
-    self.crypto_handle_counter += 1;
-    self.crypto_handle_counter
+  // Handle 0 is reserved and never returned. After the counter wraps around,
+  // handles that are still in use are skipped.
+  fn generate_crypto_handle(&mut self) -> SecurityResult<CryptoHandle> {
+    let previous = self.crypto_handle_counter;
+    loop {
+      self.crypto_handle_counter = self.crypto_handle_counter.wrapping_add(1);
+      let crypto_handle = self.crypto_handle_counter;
+      if crypto_handle != 0 && !self.crypto_handle_in_use(crypto_handle) {
+        return Ok(crypto_handle);
+      }
+      if crypto_handle == previous {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "All crypto handles are in use"
+        ));
+      }
+    }
+  }
+
+  pub(super) fn crypto_handle_in_use(&self, crypto_handle: CryptoHandle) -> bool {
+    self
+      .common_encode_key_materials
+      .contains_key(&crypto_handle)
+      || self
+        .receiver_specific_encode_key_materials
+        .contains_key(&crypto_handle)
+      || self.decode_key_materials.contains_key(&crypto_handle)
+      || self
+        .retired_decode_key_materials
+        .contains_key(&crypto_handle)
+      || self.rekey_grace_periods.contains_key(&crypto_handle)
+      || self.rekeyed_endpoints.contains(&crypto_handle)
+      || self.relay_only_remote_datareaders.contains(&crypto_handle)
+      || self
+        .participant_encrypt_options
+        .contains_key(&crypto_handle)
+      || self.endpoint_encrypt_options.contains_key(&crypto_handle)
+      || self
+        .participant_to_endpoint_info
+        .contains_key(&crypto_handle)
+      || self.endpoint_to_participant.contains_key(&crypto_handle)
+      || self.max_blocks_per_session.contains_key(&crypto_handle)
+      || self.reject_replays.contains(&crypto_handle)
+      || self.key_wrap_participants.contains(&crypto_handle)
+      || self.key_wrap_peers.contains(&crypto_handle)
+      || self.max_receiver_specific_macs.contains_key(&crypto_handle)
+      || self.matched_remote_endpoint.contains_key(&crypto_handle)
+      || self.matched_local_endpoint.contains_key(&crypto_handle)
+      || self
+        .shared_topic_key_handles
+        .values()
+        .any(|shared_key_crypto_handle| *shared_key_crypto_handle == crypto_handle)
+      || self
+        .matched_remote_participants
+        .values()
+        .any(|matched| matched.crypto_handle == crypto_handle)
+      || self.key_exchange_keys.contains_key(&crypto_handle)
+      || self.imported_remote_participants.contains(&crypto_handle)
   }
 
   fn get_or_generate_matched_remote_endpoint_crypto_handle(
     &mut self,
     remote_participant_crypto_handle: ParticipantCryptoHandle,
     local_endpoint_crypto_handle: EndpointCryptoHandle,
-  ) -> EndpointCryptoHandle {
+  ) -> SecurityResult<EndpointCryptoHandle> {
     // If a corresponding handle exists, get and return
     if let Some(remote_endpoint_crypto_handle) = self
       .matched_remote_endpoint
@@ -33,10 +92,10 @@ impl CryptographicBuiltin {
         remote_participant_to_remote_endpoint.get(&remote_participant_crypto_handle)
       })
     {
-      *remote_endpoint_crypto_handle
+      Ok(*remote_endpoint_crypto_handle)
     } else {
       // Otherwise generate a new handle
-      let remote_endpoint_crypto_handle = self.generate_crypto_handle();
+      let remote_endpoint_crypto_handle = self.generate_crypto_handle()?;
       // Associate it with the remote participant
       self.endpoint_to_participant.insert(
         remote_endpoint_crypto_handle,
@@ -66,42 +125,87 @@ impl CryptographicBuiltin {
         );
       }
       // Return the generated handle
-      remote_endpoint_crypto_handle
+      Ok(remote_endpoint_crypto_handle)
     }
   }
 
-  fn is_volatile(properties: &[Property]) -> bool {
-    properties
-      .iter()
-      .find(|property| {
-        property
-          .name
-          .eq(VOLATILE_ENDPOINT_RECOGNITION_PROPERTY_NAME)
+  // The property of the given name, if it is set. All properties of
+  // participants and endpoints are looked up through this.
+  fn find_property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Property> {
+    properties.iter().find(|property| property.name.eq(name))
+  }
+
+  // The EntityId of a local endpoint, see entity_id_property
+  fn entity_id(properties: &[Property]) -> SecurityResult<Option<EntityId>> {
+    Self::find_property(properties, ENTITY_ID_PROPERTY_NAME)
+      .map(|property| {
+        u32::from_str_radix(&property.value, 16)
+          .map(|entity_id| EntityId::from_slice(entity_id.to_be_bytes()))
+          .map_err(|_| {
+            create_security_error_and_log!(
+              SecurityErrorKind::Internal,
+              "Invalid value {:?} for {}, expected 8 hexadecimal digits",
+              property.value,
+              ENTITY_ID_PROPERTY_NAME
+            )
+          })
       })
-      .map_or(false, |property| {
-        property
+      .transpose()
+  }
+
+  // Whether a local endpoint is a volatile endpoint of the
+  // BuiltinParticipantVolatileMessageSecure topic. The recognition property
+  // (8.8.8.1) alone decides only if the EntityId of the endpoint is not known.
+  // Otherwise the EntityId decides, and an application endpoint with the
+  // property is rejected.
+  fn is_volatile(properties: &[Property]) -> SecurityResult<bool> {
+    let has_recognition_property = Self::find_property(
+      properties,
+      VOLATILE_ENDPOINT_RECOGNITION_PROPERTY_NAME,
+    )
+    .map_or(false, |property| {
+      property
+        .value
+        .eq(VOLATILE_WRITER_RECOGNITION_PROPERTY_VALUE)
+        || property
           .value
-          .eq(VOLATILE_WRITER_RECOGNITION_PROPERTY_VALUE)
-          || property
-            .value
-            .eq(VOLATILE_READER_RECOGNITION_PROPERTY_VALUE)
-      })
+          .eq(VOLATILE_READER_RECOGNITION_PROPERTY_VALUE)
+    });
+    match Self::entity_id(properties)? {
+      None => Ok(has_recognition_property),
+      Some(
+        EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_WRITER
+        | EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_READER,
+      ) => Ok(true),
+      Some(entity_id) if has_recognition_property => Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "The endpoint {:?} sets the property {}, which is reserved for the builtin volatile \
+         endpoints",
+        entity_id,
+        VOLATILE_ENDPOINT_RECOGNITION_PROPERTY_NAME
+      )),
+      Some(_) => Ok(false),
+    }
+  }
+
+  // The KxKey of a participant pair. It is the master sender key of the
+  // volatile endpoints. 9.5.2.1.2
+  fn derive_key_exchange_key(shared_secret: &SharedSecretHandle) -> SecurityResult<BuiltinKey> {
+    Self::derive_volatile_key_materials(shared_secret)
+      .map(|key_materials| key_materials.key_material().master_sender_key.clone())
   }
 
-  // 9.5.2.1.2
+  // 9.5.2.1.2 fixes the transformation kind to AES256_GCM, regardless of the
+  // key size of the endpoints, so that both sides derive the same keys
   fn derive_volatile_key_materials(
     SharedSecretHandle {
       shared_secret,
       challenge1,
       challenge2,
     }: &SharedSecretHandle,
-    use_256_bit_key: bool,
   ) -> SecurityResult<KeyMaterial_AES_GCM_GMAC_seq> {
-    let transformation_kind = if use_256_bit_key {
-      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM
-    } else {
-      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM
-    };
+    let transformation_kind =
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM;
 
     let salt_cookie: &[u8] = b"keyexchange salt".as_ref(); // Not a typo
     let key_cookie: &[u8] = b"key exchange key".as_ref();
@@ -151,36 +255,239 @@ impl CryptographicBuiltin {
     BuiltinKey::from_bytes(KeyLength::AES256, hashed_secret.as_ref()).unwrap()
   }
 
-  fn use_256_bit_key(properties: &[Property]) -> bool {
-    properties
-      .iter()
-      .find(|property| property.name.eq("dds.sec.crypto.keysize"))
-      .map_or(true, |property| !property.value.eq("128"))
+  // Whether the property "dds.sec.crypto.keysize" asks for 256-bit keys, which
+  // is the default. The only accepted values are "128" and "256".
+  fn use_256_bit_key(properties: &[Property]) -> SecurityResult<bool> {
+    Self::find_property(properties, "dds.sec.crypto.keysize").map_or(Ok(true), |property| {
+      match property.value.as_str() {
+        "128" => Ok(false),
+        "256" => Ok(true),
+        other => Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "Invalid value {:?} for dds.sec.crypto.keysize, expected \"128\" or \"256\"",
+          other
+        )),
+      }
+    })
   }
 
-  fn transformation_kind(
-    is_protected: bool,
-    is_encrypted: bool,
-    use_256_bit_key: bool,
-  ) -> BuiltinCryptoTransformationKind {
-    match (is_protected, is_encrypted, use_256_bit_key) {
-      (false, _, _) => BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE,
-      (true, false, false) => {
-        BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC
-      }
-      (true, false, true) => {
-        BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC
+  // The value of the property "dds.sec.crypto.maxblockspersession" if it is set
+  fn max_blocks_per_session(properties: &[Property]) -> SecurityResult<Option<u64>> {
+    Self::find_property(properties, "dds.sec.crypto.maxblockspersession")
+      .map(|property| {
+        property
+          .value
+          .parse::<u64>()
+          .ok()
+          .filter(|max_blocks_per_session| *max_blocks_per_session > 0)
+          .ok_or_else(|| {
+            create_security_error_and_log!(
+              SecurityErrorKind::Internal,
+              "Invalid value {:?} for dds.sec.crypto.maxblockspersession, expected a positive \
+               integer",
+              property.value
+            )
+          })
+      })
+      .transpose()
+  }
+
+  // The value of the property "dds.sec.crypto.rekey_grace_period" in seconds
+  // if it is set
+  fn rekey_grace_period_property(
+    properties: &[Property],
+  ) -> SecurityResult<Option<std::time::Duration>> {
+    Self::find_property(properties, "dds.sec.crypto.rekey_grace_period")
+      .map(|property| {
+        property
+          .value
+          .parse::<u64>()
+          .map(std::time::Duration::from_secs)
+          .map_err(|_| {
+            create_security_error_and_log!(
+              SecurityErrorKind::Internal,
+              "Invalid value {:?} for dds.sec.crypto.rekey_grace_period, expected a non-negative \
+               integer",
+              property.value
+            )
+          })
+      })
+      .transpose()
+  }
+
+  // The value of the property "dds.sec.crypto.max_receiver_specific_macs" if it
+  // is set
+  fn max_receiver_specific_macs_property(properties: &[Property]) -> SecurityResult<Option<usize>> {
+    Self::find_property(properties, "dds.sec.crypto.max_receiver_specific_macs")
+      .map(|property| {
+        property.value.parse::<usize>().map_err(|_| {
+          create_security_error_and_log!(
+            SecurityErrorKind::Internal,
+            "Invalid value {:?} for dds.sec.crypto.max_receiver_specific_macs, expected a \
+             non-negative integer",
+            property.value
+          )
+        })
+      })
+      .transpose()
+  }
+
+  // The value of a boolean property, false if it is not set
+  fn boolean_property(properties: &[Property], name: &str) -> SecurityResult<bool> {
+    Self::find_property(properties, name).map_or(Ok(false), |property| {
+      match property.value.as_str() {
+        "true" | "TRUE" | "1" => Ok(true),
+        "false" | "FALSE" | "0" => Ok(false),
+        other => Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "Invalid value {:?} for {}, expected a boolean",
+          other,
+          name
+        )),
       }
-      (true, true, false) => BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
-      (true, true, true) => BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+    })
+  }
+
+  fn reject_replays(properties: &[Property]) -> SecurityResult<bool> {
+    Self::boolean_property(properties, "dds.sec.crypto.reject_replays")
+  }
+
+  fn share_topic_key(properties: &[Property]) -> SecurityResult<bool> {
+    Self::boolean_property(properties, "dds.sec.crypto.share_topic_key")
+  }
+
+  // Whether a datawriter protects its payloads with the key of its submessages
+  // when the transformation kinds are the same (9.5.3.1). Reusing the key is
+  // the default.
+  fn reuse_submessage_key_for_payload(properties: &[Property]) -> SecurityResult<bool> {
+    let name = "dds.sec.crypto.reuse_submessage_key_for_payload";
+    Ok(Self::find_property(properties, name).is_none() || Self::boolean_property(properties, name)?)
+  }
+
+  // A datawriter may override the transformation kind that its security
+  // attributes give for submessages or payloads with a protection kind
+  // property, e.g. dds.sec.crypto.payload_protection_kind = ENCRYPT. The
+  // override may not be weaker than what the attributes require. It cannot
+  // protect what the attributes leave unprotected either, because such data
+  // is not passed to the plugin.
+  fn overridden_transformation_kind(
+    properties: &[Property],
+    name: &str,
+    required_transformation_kind: BuiltinCryptoTransformationKind,
+    use_256_bit_key: bool,
+  ) -> SecurityResult<BuiltinCryptoTransformationKind> {
+    let Some(property) = Self::find_property(properties, name) else {
+      return Ok(required_transformation_kind);
+    };
+    if property.value.ends_with("_WITH_ORIGIN_AUTHENTICATION") {
+      return Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "Invalid value {:?} for {}: origin authentication is given by the security attributes",
+        property.value,
+        name
+      ));
+    }
+    let transformation_kind = BuiltinCryptoTransformationKind::from_protection_kind(
+      &property.value,
+      if use_256_bit_key { 256 } else { 128 },
+    )?;
+    if required_transformation_kind
+      == BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE
+      && transformation_kind != required_transformation_kind
+    {
+      Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "{} = {} cannot protect what the security attributes leave unprotected",
+        name,
+        property.value
+      ))
+    } else if !transformation_kind.is_at_least_as_strong_as(required_transformation_kind) {
+      Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "{} = {} gives {}, which is weaker than {} required by the security attributes",
+        name,
+        property.value,
+        transformation_kind,
+        required_transformation_kind
+      ))
+    } else {
+      Ok(transformation_kind)
+    }
+  }
+
+  // Whether the participant allows exporting the raw key materials of the
+  // plugin
+  fn allow_key_export(properties: &[Property]) -> SecurityResult<bool> {
+    Self::boolean_property(properties, "dds.sec.crypto.allow_key_export")
+  }
+
+  // Whether the participant wraps the key material in its crypto tokens. Other
+  // implementations only read the standard unwrapped key material, so it is
+  // off by default.
+  fn wrap_key_material(properties: &[Property]) -> SecurityResult<bool> {
+    Self::boolean_property(properties, "dds.sec.crypto.wrap_key_material")
+  }
+
+  // The maximum number of blocks per session of a local endpoint. If the
+  // endpoint does not set it, the value of its participant is used.
+  fn endpoint_max_blocks_per_session(
+    &self,
+    participant_crypto_handle: ParticipantCryptoHandle,
+    endpoint_properties: &[Property],
+  ) -> SecurityResult<Option<u64>> {
+    Ok(
+      Self::max_blocks_per_session(endpoint_properties)?.or_else(|| {
+        self
+          .max_blocks_per_session
+          .get(&participant_crypto_handle)
+          .copied()
+      }),
+    )
+  }
+
+  // The plugin bits of the endpoint attributes only refine the protection
+  // flags (9.4.2.6): an encrypted or origin-authenticated bit without the
+  // corresponding protection flag would silently resolve to
+  // CRYPTO_TRANSFORMATION_KIND_NONE in BuiltinCryptoTransformationKind::
+  // from_protection, so reject such combinations instead of guessing what the
+  // access control plugin meant.
+  fn validate_endpoint_security_attributes(
+    endpoint_security_attributes: &EndpointSecurityAttributes,
+    plugin_endpoint_security_attributes: &BuiltinPluginEndpointSecurityAttributes,
+  ) -> SecurityResult<()> {
+    let mut conflicts = Vec::new();
+    if plugin_endpoint_security_attributes.is_submessage_encrypted
+      && !endpoint_security_attributes.is_submessage_protected
+    {
+      conflicts.push("is_submessage_encrypted is set but is_submessage_protected is not");
+    }
+    if plugin_endpoint_security_attributes.is_submessage_origin_authenticated
+      && !endpoint_security_attributes.is_submessage_protected
+    {
+      conflicts
+        .push("is_submessage_origin_authenticated is set but is_submessage_protected is not");
+    }
+    if plugin_endpoint_security_attributes.is_payload_encrypted
+      && !endpoint_security_attributes.is_payload_protected
+    {
+      conflicts.push("is_payload_encrypted is set but is_payload_protected is not");
+    }
+    if conflicts.is_empty() {
+      Ok(())
+    } else {
+      Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "Inconsistent endpoint security attributes: {}",
+        conflicts.join(", ")
+      ))
     }
   }
 
-  fn generate_key_id(&mut self) -> CryptoTransformKeyId {
+  fn generate_key_id(&mut self) -> SecurityResult<CryptoTransformKeyId> {
     loop {
-      let candidate = CryptoTransformKeyId::random();
+      let candidate = CryptoTransformKeyId::from(self.random_bytes()?);
       if !self.used_local_key_ids.contains(&candidate) {
-        return candidate;
+        return Ok(candidate);
       }
       // Else there was a collision, retry
     }
@@ -189,45 +496,179 @@ impl CryptographicBuiltin {
   fn generate_key_material(
     &mut self,
     transformation_kind: BuiltinCryptoTransformationKind,
-  ) -> KeyMaterial_AES_GCM_GMAC {
+  ) -> SecurityResult<KeyMaterial_AES_GCM_GMAC> {
     let key_length = KeyLength::from(transformation_kind);
-    KeyMaterial_AES_GCM_GMAC {
+    Ok(KeyMaterial_AES_GCM_GMAC {
       transformation_kind,
 
-      master_salt: BuiltinKey::generate_random(key_length),
+      master_salt: BuiltinKey::generate_random(key_length, self.entropy_source.as_ref())?,
 
-      sender_key_id: self.generate_key_id(),
-      master_sender_key: keygen(key_length),
+      sender_key_id: self.generate_key_id()?,
+      master_sender_key: keygen(key_length, self.entropy_source.as_ref())?,
       // Leave receiver-specific key empty initially
       receiver_specific_key_id: CryptoTransformKeyId::ZERO,
       master_receiver_specific_key: BuiltinKey::None,
+    })
+  }
+
+  fn generate_endpoint_key_materials(
+    &mut self,
+    submessage_transformation_kind: BuiltinCryptoTransformationKind,
+    payload_transformation_kind: BuiltinCryptoTransformationKind,
+    reuse_submessage_key_for_payload: bool,
+  ) -> SecurityResult<KeyMaterial_AES_GCM_GMAC_seq> {
+    let submessage_key_material = self.generate_key_material(submessage_transformation_kind)?;
+    // If the transformation kinds match, key reuse is possible: 9.5.3.1
+    if submessage_transformation_kind == payload_transformation_kind
+      && reuse_submessage_key_for_payload
+    {
+      Ok(KeyMaterial_AES_GCM_GMAC_seq::One(submessage_key_material))
+    } else {
+      Ok(KeyMaterial_AES_GCM_GMAC_seq::Two(
+        submessage_key_material,
+        self.generate_key_material(payload_transformation_kind)?,
+      ))
+    }
+  }
+
+  // The topic of a local endpoint, see topic_name_property
+  fn topic_name(properties: &[Property]) -> SecurityResult<String> {
+    Self::find_property(properties, TOPIC_NAME_PROPERTY_NAME)
+      .map(|property| property.value.clone())
+      .ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "dds.sec.crypto.share_topic_key is set, but the topic of the endpoint is not known"
+        )
+      })
+  }
+
+  // Returns the key materials shared by the datawriters of the topic, and
+  // generates them for the first datawriter
+  fn shared_topic_key_materials(
+    &mut self,
+    index: SharedTopicKeyIndex,
+    datawriter_crypto_handle: DatawriterCryptoHandle,
+  ) -> SecurityResult<Arc<KeyMaterial_AES_GCM_GMAC_seq>> {
+    if let Some(shared_key) = self.shared_topic_keys.get_mut(&index) {
+      shared_key.datawriters.insert(datawriter_crypto_handle);
+      self
+        .shared_topic_key_handles
+        .insert(datawriter_crypto_handle, shared_key.crypto_handle);
+      return Ok(shared_key.key_materials.clone());
+    }
+
+    let key_materials = Arc::new(self.generate_endpoint_key_materials(
+      index.submessage_transformation_kind,
+      index.payload_transformation_kind,
+      index.reuse_submessage_key_for_payload,
+    )?);
+    security_warn!(
+      "Datawriters of the topic {} share the key {}. A compromise of the key exposes all of them.",
+      index.topic_name,
+      key_materials.key_material().sender_key_id
+    );
+    let shared_key_crypto_handle = self.generate_crypto_handle()?;
+    self
+      .shared_topic_key_handles
+      .insert(datawriter_crypto_handle, shared_key_crypto_handle);
+    self.shared_topic_keys.insert(
+      index,
+      SharedTopicKey {
+        crypto_handle: shared_key_crypto_handle,
+        key_materials: Arc::clone(&key_materials),
+        datawriters: HashSet::from([datawriter_crypto_handle]),
+      },
+    );
+    Ok(key_materials)
+  }
+
+  // Forgets a shared topic key once no datawriter uses it
+  fn release_shared_topic_key(&mut self, datawriter_crypto_handle: DatawriterCryptoHandle) {
+    let Some(shared_key_crypto_handle) = self
+      .shared_topic_key_handles
+      .remove(&datawriter_crypto_handle)
+    else {
+      return;
+    };
+    self.shared_topic_keys.retain(|_, shared_key| {
+      shared_key.datawriters.remove(&datawriter_crypto_handle);
+      !shared_key.datawriters.is_empty()
+    });
+    if !self
+      .shared_topic_key_handles
+      .values()
+      .any(|crypto_handle| *crypto_handle == shared_key_crypto_handle)
+    {
+      self.remove_encode_sessions(shared_key_crypto_handle);
     }
   }
 
   fn generate_receiver_specific_key(
     &mut self,
-    key_materials: KeyMaterial_AES_GCM_GMAC_seq,
+    key_materials: &Arc<KeyMaterial_AES_GCM_GMAC_seq>,
     origin_authentication: bool,
-  ) -> KeyMaterial_AES_GCM_GMAC_seq {
+  ) -> SecurityResult<Arc<KeyMaterial_AES_GCM_GMAC_seq>> {
     if origin_authentication {
-      let master_receiver_specific_key =
-        keygen(key_materials.key_material().transformation_kind.into());
-      key_materials
-        .add_master_receiver_specific_key(self.generate_key_id(), master_receiver_specific_key)
+      let master_receiver_specific_key = keygen(
+        key_materials.key_material().transformation_kind.into(),
+        self.entropy_source.as_ref(),
+      )?;
+      Ok(
+        key_materials
+          .add_master_receiver_specific_key(self.generate_key_id()?, master_receiver_specific_key),
+      )
     } else {
-      key_materials.add_master_receiver_specific_key(CryptoTransformKeyId::ZERO, BuiltinKey::None)
+      Ok(
+        key_materials
+          .add_master_receiver_specific_key(CryptoTransformKeyId::ZERO, BuiltinKey::None),
+      )
+    }
+  }
+
+  // The key materials for a relay-only remote datareader: the key material for
+  // submessages only. If the datawriter protects its payloads with the same
+  // key, it cannot be left out.
+  fn without_payload_key_material(
+    key_materials: Arc<KeyMaterial_AES_GCM_GMAC_seq>,
+  ) -> SecurityResult<Arc<KeyMaterial_AES_GCM_GMAC_seq>> {
+    match &*key_materials {
+      KeyMaterial_AES_GCM_GMAC_seq::Two(submessage_key_material, _) => Ok(Arc::new(
+        KeyMaterial_AES_GCM_GMAC_seq::One(submessage_key_material.clone()),
+      )),
+      KeyMaterial_AES_GCM_GMAC_seq::One(key_material)
+        if key_material.transformation_kind
+          == BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE =>
+      {
+        Ok(key_materials)
+      }
+      KeyMaterial_AES_GCM_GMAC_seq::One(_) => Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "A relay-only datareader cannot be matched, because the datawriter protects its payloads \
+         with the key of its submessages. Set dds.sec.crypto.reuse_submessage_key_for_payload to \
+         false for the datawriter."
+      )),
     }
   }
 
   fn unregister_endpoint(&mut self, endpoint_info: EndpointInfo) {
     let endpoint_crypto_handle = endpoint_info.crypto_handle;
+    // Do not keep keys derived from the removed key materials around
+    self.clear_session_keys();
+    self.remove_encode_sessions(endpoint_crypto_handle);
+    self.remove_replay_windows(endpoint_crypto_handle);
+    self.release_shared_topic_key(endpoint_crypto_handle);
     self
       .common_encode_key_materials
       .remove(&endpoint_crypto_handle);
     self
       .receiver_specific_encode_key_materials
       .remove(&endpoint_crypto_handle);
-    self.decode_key_materials.remove(&endpoint_crypto_handle);
+    self.remove_decode_key_materials(endpoint_crypto_handle);
+    self.rekeyed_endpoints.remove(&endpoint_crypto_handle);
+    self
+      .relay_only_remote_datareaders
+      .remove(&endpoint_crypto_handle);
     self
       .endpoint_encrypt_options
       .remove(&endpoint_crypto_handle);
@@ -238,34 +679,294 @@ impl CryptographicBuiltin {
         .participant_to_endpoint_info
         .get_mut(&participant_crypto_handle)
       {
-        endpoint_info_set.remove(&endpoint_info);
+        // Match by handle only, the kind given by the caller may be inaccurate
+        endpoint_info_set.retain(|info| info.crypto_handle != endpoint_crypto_handle);
+        if endpoint_info_set.is_empty() {
+          self
+            .participant_to_endpoint_info
+            .remove(&participant_crypto_handle);
+        }
       }
+    }
 
-      // If the endpoint is remote remove the association to the corresponding local
-      // endpoint
-      if let Some(matched_local_endpoint_crypto_handle) =
-        self.matched_local_endpoint.remove(&endpoint_crypto_handle)
+    // If the endpoint is remote remove the association to the corresponding local
+    // endpoint
+    if let Some(matched_local_endpoint_crypto_handle) =
+      self.matched_local_endpoint.remove(&endpoint_crypto_handle)
+    {
+      if let Some(remote_participant_to_remote_endpoint) = self
+        .matched_remote_endpoint
+        .get_mut(&matched_local_endpoint_crypto_handle)
       {
-        if let Some(remote_participant_to_remote_endpoint) = self
-          .matched_remote_endpoint
-          .get_mut(&matched_local_endpoint_crypto_handle)
-        {
-          remote_participant_to_remote_endpoint.remove(&participant_crypto_handle);
+        remote_participant_to_remote_endpoint
+          .retain(|_, remote_endpoint| *remote_endpoint != endpoint_crypto_handle);
+        if remote_participant_to_remote_endpoint.is_empty() {
+          self
+            .matched_remote_endpoint
+            .remove(&matched_local_endpoint_crypto_handle);
         }
       }
-      // If the endpoint is local, unregister all associated remote entities as they serve no
-      // purpose on their own. TODO: should we do this or just sever the association?
-      else if let Some(remote_participant_to_remote_endpoint) =
-        self.matched_remote_endpoint.remove(&endpoint_crypto_handle)
-      {
-        for remote_endpoint_crypto_handle in remote_participant_to_remote_endpoint.values() {
-          self.unregister_endpoint(EndpointInfo {
-            crypto_handle: *remote_endpoint_crypto_handle,
-            kind: endpoint_info.kind.opposite(),
-          });
+    }
+    // If the endpoint is local, unregister all associated remote entities as they serve no
+    // purpose on their own. TODO: should we do this or just sever the association?
+    else if let Some(remote_participant_to_remote_endpoint) =
+      self.matched_remote_endpoint.remove(&endpoint_crypto_handle)
+    {
+      for remote_endpoint_crypto_handle in remote_participant_to_remote_endpoint.values() {
+        self.unregister_endpoint(EndpointInfo {
+          crypto_handle: *remote_endpoint_crypto_handle,
+          kind: endpoint_info.kind.opposite(),
+        });
+      }
+    }
+  }
+}
+
+/// Key regeneration, which is not part of the Security specification
+impl CryptographicBuiltin {
+  /// Replaces the key materials of a local endpoint with fresh ones that have
+  /// new key ids, and the receiver-specific keys of its matched remote
+  /// endpoints accordingly. Messages are encoded with the new keys from now
+  /// on. The endpoint is marked for sending the new crypto tokens to the
+  /// matched remote endpoints, see [`Self::take_rekeyed_endpoints`]. Remotes
+  /// keep decoding with the replaced keys for the grace period set with the
+  /// property `dds.sec.crypto.rekey_grace_period` of their participant.
+  ///
+  /// Volatile endpoints and endpoints sharing the key of their topic cannot be
+  /// rekeyed.
+  pub fn regenerate_local_endpoint_keys(
+    &mut self,
+    local_endpoint_crypto_handle: EndpointCryptoHandle,
+  ) -> SecurityResult<()> {
+    if !self
+      .endpoint_encrypt_options
+      .contains_key(&local_endpoint_crypto_handle)
+      || self
+        .matched_local_endpoint
+        .contains_key(&local_endpoint_crypto_handle)
+    {
+      return Err(create_security_error_and_log!(
+        SecurityErrorKind::NotRegistered,
+        "The CryptoHandle {} is not a local endpoint",
+        local_endpoint_crypto_handle
+      ));
+    }
+    if self
+      .shared_topic_key_handles
+      .contains_key(&local_endpoint_crypto_handle)
+    {
+      return Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "The EndpointCryptoHandle {} shares the key of its topic, which cannot be regenerated for \
+         one endpoint",
+        local_endpoint_crypto_handle
+      ));
+    }
+    let old_key_materials =
+      match self.get_common_encode_key_materials(&local_endpoint_crypto_handle)? {
+        CommonEncodeKeyMaterials::Some(key_materials) => Arc::clone(key_materials),
+        CommonEncodeKeyMaterials::Volatile => {
+          return Err(create_security_error_and_log!(
+            SecurityErrorKind::Internal,
+            "The EndpointCryptoHandle {} is volatile, its keys are derived from shared secrets",
+            local_endpoint_crypto_handle
+          ));
         }
+      };
+
+    // Keep the transformation kinds and whether the same key is used for
+    // submessages and payloads
+    let new_key_materials = Arc::new(
+      self.generate_endpoint_key_materials(
+        old_key_materials
+          .select(KeyMaterialScope::MessageOrSubmessage)
+          .transformation_kind,
+        old_key_materials
+          .select(KeyMaterialScope::PayloadOnly)
+          .transformation_kind,
+        matches!(*old_key_materials, KeyMaterial_AES_GCM_GMAC_seq::One(_)),
+      )?,
+    );
+
+    // Keep the origin authentication setting of each matched remote
+    let mut receiver_specific_encode_key_materials = Vec::new();
+    for remote_endpoint_crypto_handle in self
+      .matched_remote_endpoint
+      .get(&local_endpoint_crypto_handle)
+      .map(|remote_endpoints| remote_endpoints.values().copied().collect::<Vec<_>>())
+      .unwrap_or_default()
+    {
+      let origin_authentication = !self
+        .get_receiver_specific_encode_key_materials(&remote_endpoint_crypto_handle)?
+        .key_material()
+        .receiver_specific_key_id
+        .is_zero();
+      let mut remote_key_materials =
+        self.generate_receiver_specific_key(&new_key_materials, origin_authentication)?;
+      if self
+        .relay_only_remote_datareaders
+        .contains(&remote_endpoint_crypto_handle)
+      {
+        remote_key_materials = Self::without_payload_key_material(remote_key_materials)?;
+      }
+      receiver_specific_encode_key_materials
+        .push((remote_endpoint_crypto_handle, remote_key_materials));
+    }
+
+    let old_key_ids: Vec<CryptoTransformKeyId> =
+      Vec::from(KeyMaterial_AES_GCM_GMAC_seq::clone(&old_key_materials))
+        .into_iter()
+        .map(|key_material| key_material.sender_key_id)
+        .collect();
+    debug!(
+      "Regenerated the keys {old_key_ids:?} of the EndpointCryptoHandle \
+       {local_endpoint_crypto_handle}"
+    );
+    self.common_encode_key_materials.insert(
+      local_endpoint_crypto_handle,
+      CommonEncodeKeyMaterials::Some(new_key_materials),
+    );
+    self
+      .receiver_specific_encode_key_materials
+      .extend(receiver_specific_encode_key_materials);
+    // Never issue the replaced key ids again, remotes may still decode with them
+    self.used_local_key_ids.extend(old_key_ids.iter().copied());
+    self
+      .encode_sessions
+      .get_mut()
+      .unwrap_or_else(PoisonError::into_inner)
+      .retain(|(crypto_handle, key_id), _| {
+        *crypto_handle != local_endpoint_crypto_handle || !old_key_ids.contains(key_id)
+      });
+    self.rekeyed_endpoints.insert(local_endpoint_crypto_handle);
+    Ok(())
+  }
+
+  /// Returns the (local endpoint, matched remote endpoint) pairs that need
+  /// new crypto tokens, because the keys of the local endpoint have been
+  /// regenerated since the previous call
+  pub fn take_rekeyed_endpoints(&mut self) -> Vec<(EndpointCryptoHandle, EndpointCryptoHandle)> {
+    let mut pairs = Vec::new();
+    for local_endpoint_crypto_handle in std::mem::take(&mut self.rekeyed_endpoints) {
+      if let Some(remote_endpoints) = self
+        .matched_remote_endpoint
+        .get(&local_endpoint_crypto_handle)
+      {
+        pairs.extend(
+          remote_endpoints
+            .values()
+            .map(|remote_endpoint_crypto_handle| {
+              (local_endpoint_crypto_handle, *remote_endpoint_crypto_handle)
+            }),
+        );
       }
     }
+    pairs
+  }
+
+  // Registers the remote endpoints of the given kind that matched a local
+  // endpoint. The key materials and attributes of the local endpoint are looked
+  // up once for all of them. The result is the same as registering them one by
+  // one in the given order.
+  fn register_matched_remote_endpoints(
+    &mut self,
+    local_endpoint_crypto_handle: EndpointCryptoHandle,
+    remote_endpoints: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+    remote_endpoint_kind: EndpointKind,
+    relay_only: bool,
+  ) -> SecurityResult<Vec<EndpointCryptoHandle>> {
+    let common_encode_key_materials = self
+      .get_common_encode_key_materials(&local_endpoint_crypto_handle)
+      .cloned()?;
+    let local_endpoint_attributes = self
+      .endpoint_encrypt_options
+      .get(&local_endpoint_crypto_handle)
+      .cloned();
+    let is_submessage_origin_authenticated = match common_encode_key_materials {
+      CommonEncodeKeyMaterials::Volatile => false,
+      CommonEncodeKeyMaterials::Some(_) => local_endpoint_attributes
+        .as_ref()
+        .ok_or_else(|| {
+          create_security_error_and_log!(
+            SecurityErrorKind::NotRegistered,
+            "{:?} encrypt options not found for the handle {}",
+            remote_endpoint_kind.opposite(),
+            local_endpoint_crypto_handle
+          )
+        })
+        .and_then(|local_endpoint_attributes| {
+          BuiltinPluginEndpointSecurityAttributes::try_from(
+            local_endpoint_attributes.plugin_endpoint_attributes,
+          )
+        })
+        .map(|plugin_endpoint_attributes| {
+          plugin_endpoint_attributes.is_submessage_origin_authenticated
+        })?,
+    };
+
+    remote_endpoints
+      .iter()
+      .map(|(remote_participant_crypto_handle, shared_secret)| {
+        // Find a handle for the remote endpoint corresponding to the (remote
+        // participant, local endpoint) pair, or generate a new one
+        let remote_endpoint_crypto_handle = self
+          .get_or_generate_matched_remote_endpoint_crypto_handle(
+            *remote_participant_crypto_handle,
+            local_endpoint_crypto_handle,
+          )?;
+
+        let receiver_specific_encode_key_materials = match &common_encode_key_materials {
+          CommonEncodeKeyMaterials::Volatile => {
+            let volatile_key_materials =
+              Arc::new(Self::derive_volatile_key_materials(shared_secret)?);
+
+            // Instead of sending keys over the network like in other topics, the same
+            // key material is used for decoding
+            self.insert_decode_key_materials(
+              remote_endpoint_crypto_handle,
+              Arc::clone(&volatile_key_materials),
+            )?;
+            volatile_key_materials
+          }
+          CommonEncodeKeyMaterials::Some(common_encode_key_materials) => {
+            let key_materials = self.generate_receiver_specific_key(
+              common_encode_key_materials,
+              is_submessage_origin_authenticated,
+            )?;
+            if relay_only {
+              self
+                .relay_only_remote_datareaders
+                .insert(remote_endpoint_crypto_handle);
+              Self::without_payload_key_material(key_materials)?
+            } else {
+              key_materials
+            }
+          }
+        };
+        self.insert_receiver_specific_encode_key_materials(
+          remote_endpoint_crypto_handle,
+          receiver_specific_encode_key_materials,
+        )?;
+
+        // Add endpoint info
+        self.insert_endpoint_info(
+          *remote_participant_crypto_handle,
+          EndpointInfo {
+            crypto_handle: remote_endpoint_crypto_handle,
+            kind: remote_endpoint_kind,
+          },
+        );
+
+        // Copy the attributes
+        if let Some(attributes) = &local_endpoint_attributes {
+          self
+            .endpoint_encrypt_options
+            .insert(remote_endpoint_crypto_handle, attributes.clone());
+        }
+
+        Ok(remote_endpoint_crypto_handle)
+      })
+      .collect()
   }
 }
 
@@ -284,17 +985,50 @@ impl CryptoKeyFactory for CryptographicBuiltin {
       BuiltinPluginParticipantSecurityAttributes::try_from(
         participant_security_attributes.plugin_participant_attributes,
       )?;
-    let crypto_handle = self.generate_crypto_handle();
+    let max_blocks_per_session = Self::max_blocks_per_session(participant_properties)?;
+    let reject_replays = Self::reject_replays(participant_properties)?;
+    let rekey_grace_period = Self::rekey_grace_period_property(participant_properties)?;
+    let max_receiver_specific_macs =
+      Self::max_receiver_specific_macs_property(participant_properties)?;
+    let use_256_bit_key = Self::use_256_bit_key(participant_properties)?;
+    let allow_key_export = Self::allow_key_export(participant_properties)?;
+    let wrap_key_material = Self::wrap_key_material(participant_properties)?;
+    let key_material =
+      self.generate_key_material(BuiltinCryptoTransformationKind::from_protection(
+        participant_security_attributes.is_rtps_protected,
+        plugin_participant_security_attributes.is_rtps_encrypted,
+        use_256_bit_key,
+      ))?;
 
-    let key_material = self.generate_key_material(Self::transformation_kind(
-      participant_security_attributes.is_rtps_protected,
-      plugin_participant_security_attributes.is_rtps_encrypted,
-      Self::use_256_bit_key(participant_properties),
-    ));
+    let crypto_handle = self.generate_crypto_handle()?;
+    if let Some(max_blocks_per_session) = max_blocks_per_session {
+      self
+        .max_blocks_per_session
+        .insert(crypto_handle, max_blocks_per_session);
+    }
+    if reject_replays {
+      self.reject_replays.insert(crypto_handle);
+    }
+    if allow_key_export {
+      self.key_material_export_allowed = true;
+    }
+    if wrap_key_material {
+      self.key_wrap_participants.insert(crypto_handle);
+    }
+    if let Some(rekey_grace_period) = rekey_grace_period {
+      self
+        .rekey_grace_periods
+        .insert(crypto_handle, rekey_grace_period);
+    }
+    if let Some(max_receiver_specific_macs) = max_receiver_specific_macs {
+      self
+        .max_receiver_specific_macs
+        .insert(crypto_handle, max_receiver_specific_macs);
+    }
     self
       .insert_common_encode_key_materials(
         crypto_handle,
-        CommonEncodeKeyMaterials::Some(KeyMaterial_AES_GCM_GMAC_seq::One(key_material)),
+        CommonEncodeKeyMaterials::Some(Arc::new(KeyMaterial_AES_GCM_GMAC_seq::One(key_material))),
       )
       .and(self.insert_participant_attributes(crypto_handle, participant_security_attributes))
       .and(Ok(crypto_handle))
@@ -303,9 +1037,9 @@ impl CryptoKeyFactory for CryptographicBuiltin {
   fn register_matched_remote_participant(
     &mut self,
     local_participant_crypto_handle: ParticipantCryptoHandle,
-    _remote_participant_identity: IdentityHandle,
+    remote_participant_identity: IdentityHandle,
     _remote_participant_permissions: PermissionsHandle,
-    _shared_secret: SharedSecretHandle,
+    shared_secret: SharedSecretHandle,
   ) -> SecurityResult<ParticipantCryptoHandle> {
     //TODO: this is only a mock implementation
 
@@ -315,7 +1049,8 @@ impl CryptoKeyFactory for CryptographicBuiltin {
       .and_then(
         |common_encode_key_materials| match common_encode_key_materials {
           CommonEncodeKeyMaterials::Some(value) => Ok(value),
-          CommonEncodeKeyMaterials::Volatile(_) => Err(create_security_error_and_log!(
+          CommonEncodeKeyMaterials::Volatile => Err(create_security_error_and_log!(
+            SecurityErrorKind::Internal,
             "The local_participant_crypto_handle {} points to volatile, but a participant cannot \
              be volatile",
             local_participant_crypto_handle
@@ -323,35 +1058,66 @@ impl CryptoKeyFactory for CryptographicBuiltin {
         },
       )?;
 
-    let is_rtps_origin_authenticated = self
-      .participant_encrypt_options
-      .get(&local_participant_crypto_handle)
-      .ok_or_else(|| {
-        create_security_error_and_log!(
-          "Participant encrypt options not found for the ParticipantCryptoHandle {}",
-          local_participant_crypto_handle
-        )
-      })
-      .and_then(|participant_security_attributes| {
-        BuiltinPluginParticipantSecurityAttributes::try_from(
-          participant_security_attributes.plugin_participant_attributes,
-        )
-      })
-      .map(|plugin_participant_attributes| {
-        plugin_participant_attributes.is_rtps_origin_authenticated
-      })?;
+    let is_rtps_origin_authenticated =
+      self.is_rtps_origin_authenticated(local_participant_crypto_handle)?;
+
+    let key_exchange_key = Self::derive_key_exchange_key(&shared_secret)?;
+
+    let matched_index = (local_participant_crypto_handle, remote_participant_identity);
+    if let Some(matched) = self
+      .matched_remote_participants
+      .get(&matched_index)
+      .copied()
+    {
+      if matched.is_rtps_origin_authenticated != is_rtps_origin_authenticated {
+        // The receiver-specific key no longer matches the setting
+        let key_materials = self.generate_receiver_specific_key(
+          &local_participant_key_materials,
+          is_rtps_origin_authenticated,
+        )?;
+        self
+          .receiver_specific_encode_key_materials
+          .insert(matched.crypto_handle, key_materials);
+        self.matched_remote_participants.insert(
+          matched_index,
+          MatchedRemoteParticipant {
+            crypto_handle: matched.crypto_handle,
+            is_rtps_origin_authenticated,
+          },
+        );
+      }
+      // A new authentication gives a new shared secret
+      self
+        .key_exchange_keys
+        .insert(matched.crypto_handle, key_exchange_key);
+      debug!(
+        "Remote participant identity {remote_participant_identity} is already registered as {}",
+        matched.crypto_handle
+      );
+      return Ok(matched.crypto_handle);
+    }
 
-    let remote_participant_crypto_handle = self.generate_crypto_handle();
+    let remote_participant_crypto_handle = self.generate_crypto_handle()?;
 
     let key_materials = self.generate_receiver_specific_key(
-      local_participant_key_materials,
+      &local_participant_key_materials,
       is_rtps_origin_authenticated,
-    );
+    )?;
 
     self.insert_receiver_specific_encode_key_materials(
       remote_participant_crypto_handle,
       key_materials,
     )?;
+    self.matched_remote_participants.insert(
+      matched_index,
+      MatchedRemoteParticipant {
+        crypto_handle: remote_participant_crypto_handle,
+        is_rtps_origin_authenticated,
+      },
+    );
+    self
+      .key_exchange_keys
+      .insert(remote_participant_crypto_handle, key_exchange_key);
 
     Ok(remote_participant_crypto_handle)
   }
@@ -366,41 +1132,83 @@ impl CryptoKeyFactory for CryptographicBuiltin {
     let plugin_endpoint_security_attributes = BuiltinPluginEndpointSecurityAttributes::try_from(
       datawriter_security_attributes.plugin_endpoint_attributes,
     )?;
+    Self::validate_endpoint_security_attributes(
+      &datawriter_security_attributes,
+      &plugin_endpoint_security_attributes,
+    )?;
 
-    let local_datawriter_crypto_handle = self.generate_crypto_handle();
+    let max_blocks_per_session =
+      self.endpoint_max_blocks_per_session(participant_crypto, datawriter_properties)?;
+    let share_topic_key = Self::share_topic_key(datawriter_properties)?;
+    let reuse_submessage_key_for_payload =
+      Self::reuse_submessage_key_for_payload(datawriter_properties)?;
+    let use_256_bit_key = Self::use_256_bit_key(datawriter_properties)?;
+    // Volatile datawriters derive their key materials on matching, so they do
+    // not use these
+    let submessage_transformation_kind = Self::overridden_transformation_kind(
+      datawriter_properties,
+      "dds.sec.crypto.submessage_protection_kind",
+      BuiltinCryptoTransformationKind::from_protection(
+        datawriter_security_attributes.is_submessage_protected,
+        plugin_endpoint_security_attributes.is_submessage_encrypted,
+        use_256_bit_key,
+      ),
+      use_256_bit_key,
+    )?;
+    let payload_transformation_kind = Self::overridden_transformation_kind(
+      datawriter_properties,
+      "dds.sec.crypto.payload_protection_kind",
+      BuiltinCryptoTransformationKind::from_protection(
+        datawriter_security_attributes.is_payload_protected,
+        plugin_endpoint_security_attributes.is_payload_encrypted,
+        use_256_bit_key,
+      ),
+      use_256_bit_key,
+    )?;
 
-    let use_256_bit_key = Self::use_256_bit_key(datawriter_properties);
+    let local_datawriter_crypto_handle = self.generate_crypto_handle()?;
+    if let Some(max_blocks_per_session) = max_blocks_per_session {
+      self
+        .max_blocks_per_session
+        .insert(local_datawriter_crypto_handle, max_blocks_per_session);
+    }
 
     // The key material for volatile datawriter is derived from the shared secret in
     // register_matched_remote_datareader
-    if Self::is_volatile(datawriter_properties) {
+    if Self::is_volatile(datawriter_properties)? {
+      debug!(
+        "Registered volatile datawriter {local_datawriter_crypto_handle}, key material is derived \
+         on matching"
+      );
       self.insert_common_encode_key_materials(
         local_datawriter_crypto_handle,
-        CommonEncodeKeyMaterials::Volatile(use_256_bit_key),
+        CommonEncodeKeyMaterials::Volatile,
       )?;
     } else {
-      let submessage_transformation_kind = Self::transformation_kind(
-        datawriter_security_attributes.is_submessage_protected,
-        plugin_endpoint_security_attributes.is_submessage_encrypted,
-        use_256_bit_key,
-      );
-      let payload_transformation_kind = Self::transformation_kind(
-        datawriter_security_attributes.is_payload_protected,
-        plugin_endpoint_security_attributes.is_payload_encrypted,
-        use_256_bit_key,
+      debug!(
+        "Registered datawriter {local_datawriter_crypto_handle} with submessage transformation \
+         {submessage_transformation_kind:?} and payload transformation \
+         {payload_transformation_kind:?}"
       );
 
-      let submessage_key_material = self.generate_key_material(submessage_transformation_kind);
-      // If the transformation kinds match, key reuse is possible: 9.5.3.1
-      let key_materials = if submessage_transformation_kind == payload_transformation_kind
-      /* && additional configurable condition? */
-      {
-        KeyMaterial_AES_GCM_GMAC_seq::One(submessage_key_material)
+      let key_materials = if share_topic_key {
+        self.shared_topic_key_materials(
+          SharedTopicKeyIndex {
+            participant_crypto_handle: participant_crypto,
+            topic_name: Self::topic_name(datawriter_properties)?,
+            submessage_transformation_kind,
+            payload_transformation_kind,
+            reuse_submessage_key_for_payload: reuse_submessage_key_for_payload
+              && submessage_transformation_kind == payload_transformation_kind,
+          },
+          local_datawriter_crypto_handle,
+        )?
       } else {
-        KeyMaterial_AES_GCM_GMAC_seq::Two(
-          submessage_key_material,
-          self.generate_key_material(payload_transformation_kind),
-        )
+        Arc::new(self.generate_endpoint_key_materials(
+          submessage_transformation_kind,
+          payload_transformation_kind,
+          reuse_submessage_key_for_payload,
+        )?)
       };
       self.insert_common_encode_key_materials(
         local_datawriter_crypto_handle,
@@ -432,86 +1240,29 @@ impl CryptoKeyFactory for CryptographicBuiltin {
     local_datawriter_crypto_handle: DatawriterCryptoHandle,
     remote_participant_crypto_handle: ParticipantCryptoHandle,
     shared_secret: SharedSecretHandle,
-    _relay_only: bool,
+    relay_only: bool,
   ) -> SecurityResult<DatareaderCryptoHandle> {
-    //TODO: this is only a mock implementation
-    let common_encode_key_materials = self
-      .get_common_encode_key_materials(&local_datawriter_crypto_handle)
-      .cloned()?;
-
-    // Find a handle for the remote datareader corresponding to the (remote
-    // participant, local datawriter) pair, or generate a new one
-    let remote_datareader_crypto_handle = self
-      .get_or_generate_matched_remote_endpoint_crypto_handle(
-        remote_participant_crypto_handle,
+    self
+      .register_matched_remote_datareaders(
         local_datawriter_crypto_handle,
-      );
+        &[(remote_participant_crypto_handle, shared_secret)],
+        relay_only,
+      )
+      .map(|remote_datareader_crypto_handles| remote_datareader_crypto_handles[0])
+  }
 
-    let receiver_specific_encode_key_materials = match common_encode_key_materials {
-      CommonEncodeKeyMaterials::Volatile(use_256_bit_key) => {
-        let volatile_key_materials =
-          Self::derive_volatile_key_materials(&shared_secret, use_256_bit_key)?;
-
-        // Instead of sending keys over the network like in other topics, the same key
-        // material is used for decoding
-        self.insert_decode_key_materials(
-          remote_datareader_crypto_handle,
-          volatile_key_materials.clone(),
-        )?;
-        volatile_key_materials
-      }
-      CommonEncodeKeyMaterials::Some(common_encode_key_materials) => {
-        let is_submessage_origin_authenticated = self
-          .endpoint_encrypt_options
-          .get(&local_datawriter_crypto_handle)
-          .ok_or_else(|| {
-            create_security_error_and_log!(
-              "Datawriter encrypt options not found for the DatawriterCryptoHandle {}",
-              local_datawriter_crypto_handle
-            )
-          })
-          .and_then(|datawriter_security_attributes| {
-            BuiltinPluginEndpointSecurityAttributes::try_from(
-              datawriter_security_attributes.plugin_endpoint_attributes,
-            )
-          })
-          .map(|plugin_datawriter_attributes| {
-            plugin_datawriter_attributes.is_submessage_origin_authenticated
-          })?;
-
-        self.generate_receiver_specific_key(
-          common_encode_key_materials,
-          is_submessage_origin_authenticated,
-        )
-      }
-    };
-
-    self.insert_receiver_specific_encode_key_materials(
-      remote_datareader_crypto_handle,
-      receiver_specific_encode_key_materials,
-    )?;
-
-    // Add endpoint info
-    self.insert_endpoint_info(
-      remote_participant_crypto_handle,
-      EndpointInfo {
-        crypto_handle: remote_datareader_crypto_handle,
-        kind: EndpointKind::DataReader,
-      },
-    );
-
-    // Copy the attributes
-    if let Some(attributes) = self
-      .endpoint_encrypt_options
-      .get(&local_datawriter_crypto_handle)
-      .cloned()
-    {
-      self
-        .endpoint_encrypt_options
-        .insert(remote_datareader_crypto_handle, attributes);
-    }
-
-    Ok(remote_datareader_crypto_handle)
+  fn register_matched_remote_datareaders(
+    &mut self,
+    local_datawriter_crypto_handle: DatawriterCryptoHandle,
+    remote_datareaders: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+    relay_only: bool,
+  ) -> SecurityResult<Vec<DatareaderCryptoHandle>> {
+    self.register_matched_remote_endpoints(
+      local_datawriter_crypto_handle,
+      remote_datareaders,
+      EndpointKind::DataReader,
+      relay_only,
+    )
   }
 
   fn register_local_datareader(
@@ -524,26 +1275,58 @@ impl CryptoKeyFactory for CryptographicBuiltin {
     let plugin_endpoint_security_attributes = BuiltinPluginEndpointSecurityAttributes::try_from(
       datareader_security_attributes.plugin_endpoint_attributes,
     )?;
+    Self::validate_endpoint_security_attributes(
+      &datareader_security_attributes,
+      &plugin_endpoint_security_attributes,
+    )?;
 
-    let local_datareader_crypto_handle = self.generate_crypto_handle();
+    let max_blocks_per_session =
+      self.endpoint_max_blocks_per_session(participant_crypto_handle, datareader_properties)?;
+    let use_256_bit_key = Self::use_256_bit_key(datareader_properties)?;
+
+    let local_datareader_crypto_handle = self.generate_crypto_handle()?;
+    if let Some(max_blocks_per_session) = max_blocks_per_session {
+      self
+        .max_blocks_per_session
+        .insert(local_datareader_crypto_handle, max_blocks_per_session);
+    }
 
-    let use_256_bit_key = Self::use_256_bit_key(datareader_properties);
     // The key material for volatile datareader is derived from the shared secret in
     // register_matched_remote_datawriter
-    if Self::is_volatile(datareader_properties) {
+    if Self::is_volatile(datareader_properties)? {
+      debug!(
+        "Registered volatile datareader {local_datareader_crypto_handle}, key material is derived \
+         on matching"
+      );
       self.insert_common_encode_key_materials(
         local_datareader_crypto_handle,
-        CommonEncodeKeyMaterials::Volatile(use_256_bit_key),
+        CommonEncodeKeyMaterials::Volatile,
       )?;
     } else {
-      let key_material = self.generate_key_material(Self::transformation_kind(
+      let submessage_transformation_kind = BuiltinCryptoTransformationKind::from_protection(
         datareader_security_attributes.is_submessage_protected,
         plugin_endpoint_security_attributes.is_submessage_encrypted,
         use_256_bit_key,
-      ));
+      );
+      let payload_transformation_kind = BuiltinCryptoTransformationKind::from_protection(
+        datareader_security_attributes.is_payload_protected,
+        plugin_endpoint_security_attributes.is_payload_encrypted,
+        use_256_bit_key,
+      );
+      debug!(
+        "Registered datareader {local_datareader_crypto_handle} with submessage transformation \
+         {submessage_transformation_kind:?} and payload transformation \
+         {payload_transformation_kind:?}"
+      );
+      // Datareaders do not send payloads
+      let key_materials = Arc::new(self.generate_endpoint_key_materials(
+        submessage_transformation_kind,
+        payload_transformation_kind,
+        true,
+      )?);
       self.insert_common_encode_key_materials(
         local_datareader_crypto_handle,
-        CommonEncodeKeyMaterials::Some(KeyMaterial_AES_GCM_GMAC_seq::One(key_material)),
+        CommonEncodeKeyMaterials::Some(key_materials),
       )?;
     }
     self.insert_endpoint_attributes(
@@ -570,82 +1353,25 @@ impl CryptoKeyFactory for CryptographicBuiltin {
     remote_participant_crypto_handle: ParticipantCryptoHandle,
     shared_secret: SharedSecretHandle,
   ) -> SecurityResult<DatawriterCryptoHandle> {
-    //TODO: this is only a mock implementation
-    let common_encode_key_materials = self
-      .get_common_encode_key_materials(&local_datareader_crypto_handle)
-      .cloned()?;
-
-    // Find a handle for the remote datawriter corresponding to the (remote
-    // participant, local datareader) pair, or generate a new one
-    let remote_datawriter_crypto_handle: DatareaderCryptoHandle = self
-      .get_or_generate_matched_remote_endpoint_crypto_handle(
-        remote_participant_crypto_handle,
+    self
+      .register_matched_remote_datawriters(
         local_datareader_crypto_handle,
-      );
-
-    let receiver_specific_encode_key_materials = match common_encode_key_materials {
-      CommonEncodeKeyMaterials::Volatile(use_256_bit_key) => {
-        let volatile_key_materials =
-          Self::derive_volatile_key_materials(&shared_secret, use_256_bit_key)?;
-
-        // Instead of sending keys over the network like in other topics, the same key
-        // material is used for decoding
-        self.insert_decode_key_materials(
-          remote_datawriter_crypto_handle,
-          volatile_key_materials.clone(),
-        )?;
-        volatile_key_materials
-      }
-      CommonEncodeKeyMaterials::Some(common_encode_key_materials) => {
-        let is_submessage_origin_authenticated = self
-          .endpoint_encrypt_options
-          .get(&local_datareader_crypto_handle)
-          .ok_or_else(|| {
-            create_security_error_and_log!(
-              "Datareader encrypt options not found for the handle {}",
-              local_datareader_crypto_handle
-            )
-          })
-          .and_then(|datareader_security_attributes| {
-            BuiltinPluginEndpointSecurityAttributes::try_from(
-              datareader_security_attributes.plugin_endpoint_attributes,
-            )
-          })
-          .map(|plugin_datareader_attributes| {
-            plugin_datareader_attributes.is_submessage_origin_authenticated
-          })?;
-        self.generate_receiver_specific_key(
-          common_encode_key_materials,
-          is_submessage_origin_authenticated,
-        )
-      }
-    };
-    self.insert_receiver_specific_encode_key_materials(
-      remote_datawriter_crypto_handle,
-      receiver_specific_encode_key_materials,
-    )?;
-
-    // Add endpoint info
-    self.insert_endpoint_info(
-      remote_participant_crypto_handle,
-      EndpointInfo {
-        crypto_handle: remote_datawriter_crypto_handle,
-        kind: EndpointKind::DataWriter,
-      },
-    );
-
-    // Copy the attributes
-    if let Some(attributes) = self
-      .endpoint_encrypt_options
-      .get(&local_datareader_crypto_handle)
-      .cloned()
-    {
-      self
-        .endpoint_encrypt_options
-        .insert(remote_datawriter_crypto_handle, attributes);
-    }
+        &[(remote_participant_crypto_handle, shared_secret)],
+      )
+      .map(|remote_datawriter_crypto_handles| remote_datawriter_crypto_handles[0])
+  }
 
-    Ok(remote_datawriter_crypto_handle)
+  fn register_matched_remote_datawriters(
+    &mut self,
+    local_datareader_crypto_handle: DatareaderCryptoHandle,
+    remote_datawriters: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+  ) -> SecurityResult<Vec<DatawriterCryptoHandle>> {
+    self.register_matched_remote_endpoints(
+      local_datareader_crypto_handle,
+      remote_datawriters,
+      EndpointKind::DataWriter,
+      false,
+    )
   }
 
   fn unregister_participant(
@@ -664,13 +1390,34 @@ impl CryptoKeyFactory for CryptographicBuiltin {
         self.unregister_endpoint(endpoint_info);
       }
     }
+    self.remove_encode_sessions(participant_crypto_handle);
+    self.remove_replay_windows(participant_crypto_handle);
+    // The handle may be local or remote
+    self
+      .matched_remote_participants
+      .retain(|(local_participant_crypto_handle, _), matched| {
+        *local_participant_crypto_handle != participant_crypto_handle
+          && matched.crypto_handle != participant_crypto_handle
+      });
+    self.key_exchange_keys.remove(&participant_crypto_handle);
+    self
+      .key_wrap_participants
+      .remove(&participant_crypto_handle);
+    self.key_wrap_peers.remove(&participant_crypto_handle);
+    self
+      .imported_remote_participants
+      .remove(&participant_crypto_handle);
     self
       .common_encode_key_materials
       .remove(&participant_crypto_handle);
     self
       .receiver_specific_encode_key_materials
       .remove(&participant_crypto_handle);
-    self.decode_key_materials.remove(&participant_crypto_handle);
+    self.remove_decode_key_materials(participant_crypto_handle);
+    self.rekey_grace_periods.remove(&participant_crypto_handle);
+    self
+      .max_receiver_specific_macs
+      .remove(&participant_crypto_handle);
     Ok(())
   }
 
@@ -698,3 +1445,1470 @@ impl CryptoKeyFactory for CryptographicBuiltin {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use bytes::Bytes;
+  use speedy::Writable;
+
+  use crate::{
+    messages::submessages::submessages::WriterSubmessage,
+    security::{
+      access_control::access_control_builtin::types::BuiltinPluginParticipantSecurityAttributes,
+      cryptographic::cryptographic_builtin::test_fixtures::*,
+      entropy::test_sources::{CountingEntropySource, FailingEntropySource},
+      types::{
+        entity_id_property, topic_name_property, volatile_reader_recognition_property,
+        volatile_writer_recognition_property, DataHolder,
+      },
+    },
+    structure::guid::{EntityId, EntityKind},
+  };
+  use super::*;
+
+  // Matches a local volatile writer with a remote volatile reader on one side
+  // and a local volatile reader with a remote volatile writer on the other.
+  // Returns (writer side, remote reader handle, reader side, remote writer
+  // handle).
+  fn match_volatile_endpoints(
+    writer_side_secret: fn() -> SharedSecretHandle,
+    reader_side_secret: fn() -> SharedSecretHandle,
+    properties: &[Property],
+  ) -> (
+    CryptographicBuiltin,
+    DatareaderCryptoHandle,
+    CryptographicBuiltin,
+    DatawriterCryptoHandle,
+  ) {
+    let mut writer_side = seeded_crypto(1);
+    let (participant, remote_participant) =
+      register_participants(&mut writer_side, writer_side_secret());
+    let writer_properties = [properties, &[volatile_writer_recognition_property()]].concat();
+    let writer = writer_side
+      .register_local_datawriter(
+        participant,
+        &writer_properties,
+        volatile_endpoint_attributes(),
+      )
+      .unwrap();
+    let remote_reader = writer_side
+      .register_matched_remote_datareader(writer, remote_participant, writer_side_secret(), false)
+      .unwrap();
+
+    let mut reader_side = seeded_crypto(2);
+    let (participant, remote_participant) =
+      register_participants(&mut reader_side, reader_side_secret());
+    let reader_properties = [properties, &[volatile_reader_recognition_property()]].concat();
+    let reader = reader_side
+      .register_local_datareader(
+        participant,
+        &reader_properties,
+        volatile_endpoint_attributes(),
+      )
+      .unwrap();
+    let remote_writer = reader_side
+      .register_matched_remote_datawriter(reader, remote_participant, reader_side_secret())
+      .unwrap();
+
+    (writer_side, remote_reader, reader_side, remote_writer)
+  }
+
+  fn same_key_material(a: &KeyMaterial_AES_GCM_GMAC, b: &KeyMaterial_AES_GCM_GMAC) -> bool {
+    a.transformation_kind == b.transformation_kind
+      && a.master_salt == b.master_salt
+      && a.sender_key_id == b.sender_key_id
+      && a.master_sender_key == b.master_sender_key
+  }
+
+  #[test]
+  fn endpoint_registration_rejects_inconsistent_attributes() {
+    // Every combination of the two protection flags and the three plugin bits
+    for bits in 0..32u8 {
+      let is_submessage_protected = bits & 0b00001 != 0;
+      let is_payload_protected = bits & 0b00010 != 0;
+      let plugin_attributes = BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: bits & 0b00100 != 0,
+        is_submessage_origin_authenticated: bits & 0b01000 != 0,
+        is_payload_encrypted: bits & 0b10000 != 0,
+      };
+      let consistent = (is_submessage_protected
+        || !(plugin_attributes.is_submessage_encrypted
+          || plugin_attributes.is_submessage_origin_authenticated))
+        && (is_payload_protected || !plugin_attributes.is_payload_encrypted);
+      let attributes = EndpointSecurityAttributes {
+        is_submessage_protected,
+        is_payload_protected,
+        plugin_endpoint_attributes: plugin_attributes.into(),
+        ..EndpointSecurityAttributes::empty()
+      };
+
+      let mut crypto = seeded_crypto(0);
+      let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0x11));
+      let writer = crypto.register_local_datawriter(participant, &[], attributes.clone());
+      let reader = crypto.register_local_datareader(participant, &[], attributes);
+      assert_eq!(writer.is_ok(), consistent, "datawriter, bits {bits:05b}");
+      assert_eq!(reader.is_ok(), consistent, "datareader, bits {bits:05b}");
+    }
+  }
+
+  #[test]
+  fn volatile_key_materials_match_on_both_sides() {
+    let (writer_side, remote_reader, reader_side, remote_writer) = match_volatile_endpoints(
+      || shared_secret_handle(0x11),
+      || shared_secret_handle(0x11),
+      &[],
+    );
+
+    let writer_encode = writer_side.receiver_specific_encode_key_materials[&remote_reader]
+      .key_material()
+      .clone();
+    let writer_decode = writer_side.decode_key_materials[&remote_reader]
+      .key_material()
+      .clone();
+    let reader_encode = reader_side.receiver_specific_encode_key_materials[&remote_writer]
+      .key_material()
+      .clone();
+    let reader_decode = reader_side.decode_key_materials[&remote_writer]
+      .key_material()
+      .clone();
+
+    assert_eq!(
+      writer_encode.transformation_kind,
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM
+    );
+    assert_eq!(writer_encode.sender_key_id, CryptoTransformKeyId::ZERO);
+    assert!(matches!(
+      writer_encode.master_sender_key,
+      BuiltinKey::AES256(_)
+    ));
+    assert!(matches!(writer_encode.master_salt, BuiltinKey::AES256(_)));
+    assert_ne!(writer_encode.master_salt, writer_encode.master_sender_key);
+
+    // Both directions use the same key material
+    assert!(same_key_material(&writer_encode, &reader_decode));
+    assert!(same_key_material(&reader_encode, &writer_decode));
+    assert!(same_key_material(&writer_encode, &reader_encode));
+  }
+
+  #[test]
+  fn volatile_key_materials_use_256_bit_keys_regardless_of_keysize() {
+    let keysize = |value: &str| Property {
+      name: "dds.sec.crypto.keysize".to_string(),
+      value: value.to_string(),
+      propagate: false,
+    };
+    for properties in [vec![], vec![keysize("128")], vec![keysize("256")]] {
+      let (writer_side, remote_reader, reader_side, remote_writer) = match_volatile_endpoints(
+        || shared_secret_handle(0x11),
+        || shared_secret_handle(0x11),
+        &properties,
+      );
+
+      let writer_encode = writer_side.receiver_specific_encode_key_materials[&remote_reader]
+        .key_material()
+        .clone();
+      let reader_decode = reader_side.decode_key_materials[&remote_writer]
+        .key_material()
+        .clone();
+      assert_eq!(
+        writer_encode.transformation_kind,
+        BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM
+      );
+      assert!(matches!(
+        writer_encode.master_sender_key,
+        BuiltinKey::AES256(_)
+      ));
+      assert!(matches!(writer_encode.master_salt, BuiltinKey::AES256(_)));
+      assert!(same_key_material(&writer_encode, &reader_decode));
+    }
+  }
+
+  #[test]
+  fn invalid_keysize_property_is_rejected() {
+    let keysize = |value: &str| Property {
+      name: "dds.sec.crypto.keysize".to_string(),
+      value: value.to_string(),
+      propagate: false,
+    };
+    let participant_security_attributes = ParticipantSecurityAttributes::empty();
+    let mut crypto = seeded_crypto(0);
+    for value in ["196", "", "AES256", "0128"] {
+      assert_eq!(
+        crypto
+          .register_local_participant(
+            0,
+            0,
+            &[keysize(value)],
+            participant_security_attributes.clone()
+          )
+          .unwrap_err()
+          .kind(),
+        SecurityErrorKind::Internal
+      );
+    }
+    assert!(crypto.common_encode_key_materials.is_empty());
+
+    let participant = crypto
+      .register_local_participant(0, 0, &[keysize("256")], participant_security_attributes)
+      .unwrap();
+    assert!(crypto
+      .register_local_datawriter(
+        participant,
+        &[keysize("196")],
+        volatile_endpoint_attributes()
+      )
+      .is_err());
+    assert!(crypto
+      .register_local_datareader(
+        participant,
+        &[keysize("196")],
+        volatile_endpoint_attributes()
+      )
+      .is_err());
+    // Only the participant was registered
+    assert_eq!(crypto.common_encode_key_materials.len(), 1);
+
+    assert!(crypto
+      .register_local_datawriter(
+        participant,
+        &[keysize("128")],
+        volatile_endpoint_attributes()
+      )
+      .is_ok());
+  }
+
+  #[test]
+  fn invalid_max_receiver_specific_macs_property_is_rejected() {
+    let property = |value: &str| Property {
+      name: "dds.sec.crypto.max_receiver_specific_macs".to_string(),
+      value: value.to_string(),
+      propagate: false,
+    };
+    let mut crypto = seeded_crypto(0);
+    assert!(crypto
+      .register_local_participant(
+        0,
+        0,
+        &[property("many")],
+        ParticipantSecurityAttributes::empty()
+      )
+      .is_err());
+    let participant = crypto
+      .register_local_participant(
+        0,
+        0,
+        &[property("8")],
+        ParticipantSecurityAttributes::empty(),
+      )
+      .unwrap();
+    assert_eq!(crypto.max_receiver_specific_macs[&participant], 8);
+    crypto.unregister_participant(participant).unwrap();
+    assert!(crypto.max_receiver_specific_macs.is_empty());
+  }
+
+  #[test]
+  fn volatile_key_materials_depend_on_challenges() {
+    let (writer_side, remote_reader, reader_side, remote_writer) = match_volatile_endpoints(
+      || shared_secret_handle(0x11),
+      || shared_secret_handle(0x33),
+      &[],
+    );
+
+    let writer_encode = writer_side.receiver_specific_encode_key_materials[&remote_reader]
+      .key_material()
+      .clone();
+    let reader_decode = reader_side.decode_key_materials[&remote_writer]
+      .key_material()
+      .clone();
+    assert_ne!(writer_encode.master_salt, reader_decode.master_salt);
+    assert_ne!(
+      writer_encode.master_sender_key,
+      reader_decode.master_sender_key
+    );
+  }
+
+  #[test]
+  fn invalid_max_blocks_per_session_is_rejected() {
+    let mut crypto = seeded_crypto(0);
+    let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0x11));
+    for value in ["0", "-1", "many"] {
+      let property = Property {
+        name: "dds.sec.crypto.maxblockspersession".to_string(),
+        value: value.to_string(),
+        propagate: false,
+      };
+      assert!(crypto
+        .register_local_datawriter(participant, &[property], volatile_endpoint_attributes())
+        .is_err());
+    }
+  }
+
+  fn origin_authenticated_participant_attributes() -> ParticipantSecurityAttributes {
+    ParticipantSecurityAttributes {
+      is_rtps_protected: true,
+      plugin_participant_attributes: BuiltinPluginParticipantSecurityAttributes {
+        is_rtps_encrypted: true,
+        is_discovery_encrypted: false,
+        is_liveliness_encrypted: false,
+        is_rtps_origin_authenticated: true,
+        is_discovery_origin_authenticated: false,
+        is_liveliness_origin_authenticated: false,
+      }
+      .into(),
+      ..ParticipantSecurityAttributes::empty()
+    }
+  }
+
+  #[test]
+  fn key_material_is_drawn_from_the_entropy_source() {
+    let mut crypto =
+      CryptographicBuiltin::with_entropy_source(Arc::new(CountingEntropySource::default()));
+    let local = crypto
+      .register_local_participant(0, 0, &[], origin_authenticated_participant_attributes())
+      .unwrap();
+    let remote = crypto
+      .register_matched_remote_participant(local, 0, 0, shared_secret_handle(0x11))
+      .unwrap();
+
+    // AES-256 keys: 32 bytes of salt, 4 bytes of key id and 32 bytes of key
+    let expected_bytes = |range: std::ops::Range<u8>| range.collect::<Vec<u8>>();
+    let key_material = match crypto.get_common_encode_key_materials(&local).unwrap() {
+      CommonEncodeKeyMaterials::Some(key_materials) => key_materials.key_material().clone(),
+      CommonEncodeKeyMaterials::Volatile => panic!("participant key material is volatile"),
+    };
+    assert_eq!(key_material.master_salt.as_bytes(), expected_bytes(0..32));
+    assert_eq!(
+      key_material.sender_key_id,
+      CryptoTransformKeyId::from([32, 33, 34, 35])
+    );
+    assert_eq!(
+      key_material.master_sender_key.as_bytes(),
+      expected_bytes(36..68)
+    );
+
+    // The receiver-specific key is generated before its key id
+    let receiver_specific = crypto
+      .get_receiver_specific_encode_key_materials(&remote)
+      .unwrap()
+      .key_material();
+    assert_eq!(
+      receiver_specific.master_receiver_specific_key.as_bytes(),
+      expected_bytes(68..100)
+    );
+    assert_eq!(
+      receiver_specific.receiver_specific_key_id,
+      CryptoTransformKeyId::from([100, 101, 102, 103])
+    );
+  }
+
+  #[test]
+  fn key_material_is_reproducible_with_a_seeded_source() {
+    let writer_key_materials = |seed| {
+      let mut crypto = seeded_crypto(seed);
+      let (participant, remote_participant) =
+        register_participants(&mut crypto, shared_secret_handle(0x11));
+      let writer = crypto
+        .register_local_datawriter(participant, &[], protected_writer_attributes(true))
+        .unwrap();
+      let remote_reader = crypto
+        .register_matched_remote_datareader(
+          writer,
+          remote_participant,
+          shared_secret_handle(0x11),
+          false,
+        )
+        .unwrap();
+      let common = crypto.common_encode_key_materials[&writer].clone();
+      let receiver_specific = crypto.receiver_specific_encode_key_materials[&remote_reader]
+        .key_material()
+        .clone();
+      match common {
+        CommonEncodeKeyMaterials::Some(key_materials) => {
+          (key_materials.key_material().clone(), receiver_specific)
+        }
+        CommonEncodeKeyMaterials::Volatile => panic!("the writer is not volatile"),
+      }
+    };
+
+    let (common, receiver_specific) = writer_key_materials(7);
+    let (same_common, same_receiver_specific) = writer_key_materials(7);
+    assert!(same_key_material(&common, &same_common));
+    assert_eq!(
+      receiver_specific.master_receiver_specific_key,
+      same_receiver_specific.master_receiver_specific_key
+    );
+
+    let (other_common, other_receiver_specific) = writer_key_materials(8);
+    assert_ne!(common.master_sender_key, other_common.master_sender_key);
+    assert_ne!(
+      receiver_specific.master_receiver_specific_key,
+      other_receiver_specific.master_receiver_specific_key
+    );
+  }
+
+  #[test]
+  fn failing_entropy_source_fails_registration() {
+    let mut crypto = CryptographicBuiltin::with_entropy_source(Arc::new(FailingEntropySource));
+    assert!(crypto
+      .register_local_participant(0, 0, &[], origin_authenticated_participant_attributes())
+      .is_err());
+    // Nothing is left behind from the failed registration
+    assert!(crypto.common_encode_key_materials.is_empty());
+  }
+
+  #[test]
+  fn invalid_reject_replays_is_rejected() {
+    let property = Property {
+      name: "dds.sec.crypto.reject_replays".to_string(),
+      value: "sometimes".to_string(),
+      propagate: false,
+    };
+    let mut crypto = seeded_crypto(0);
+    assert!(crypto
+      .register_local_participant(0, 0, &[property], ParticipantSecurityAttributes::empty())
+      .is_err());
+  }
+
+  fn share_topic_key_property() -> Property {
+    Property {
+      name: "dds.sec.crypto.share_topic_key".to_string(),
+      value: "true".to_string(),
+      propagate: false,
+    }
+  }
+
+  // Registers writers on the topic, each matched with a remote reader. Returns
+  // the (writer, remote reader) handles.
+  fn register_topic_writers(
+    crypto: &mut CryptographicBuiltin,
+    topic_name: &str,
+    writer_properties: &[Property],
+    origin_authenticated: bool,
+    count: usize,
+  ) -> Vec<(DatawriterCryptoHandle, DatareaderCryptoHandle)> {
+    let (participant, remote_participant) = register_participants(crypto, shared_secret_handle(0));
+    let properties = [
+      writer_properties,
+      &[topic_name_property(topic_name.to_string())],
+    ]
+    .concat();
+    (0..count)
+      .map(|_| {
+        let writer = crypto
+          .register_local_datawriter(
+            participant,
+            &properties,
+            protected_writer_attributes(origin_authenticated),
+          )
+          .unwrap();
+        let remote_reader = crypto
+          .register_matched_remote_datareader(
+            writer,
+            remote_participant,
+            shared_secret_handle(0),
+            false,
+          )
+          .unwrap();
+        (writer, remote_reader)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn shared_topic_key_reduces_key_materials_and_tokens() {
+    const WRITERS: usize = 50;
+    let count = |writer_properties: &[Property]| {
+      let mut crypto = seeded_crypto(0);
+      let writers =
+        register_topic_writers(&mut crypto, "commands", writer_properties, false, WRITERS);
+      let tokens: HashSet<DataHolder> = writers
+        .iter()
+        .flat_map(|(writer, remote_reader)| {
+          crypto
+            .create_local_datawriter_crypto_tokens(*writer, *remote_reader)
+            .unwrap()
+        })
+        .map(|token| token.data_holder)
+        .collect();
+      (common_key_ids(&crypto, &writers).len(), tokens.len())
+    };
+
+    assert_eq!(count(&[]), (WRITERS, WRITERS));
+    assert_eq!(count(&[share_topic_key_property()]), (1, 1));
+  }
+
+  #[test]
+  fn shared_topic_key_keeps_receiver_specific_keys() {
+    let mut crypto = seeded_crypto(0);
+    let writers = register_topic_writers(
+      &mut crypto,
+      "commands",
+      &[share_topic_key_property()],
+      true,
+      10,
+    );
+    assert_eq!(common_key_ids(&crypto, &writers).len(), 1);
+
+    let receiver_specific_key_ids: HashSet<CryptoTransformKeyId> = writers
+      .iter()
+      .map(|(_, remote_reader)| {
+        crypto.receiver_specific_encode_key_materials[remote_reader]
+          .key_material()
+          .receiver_specific_key_id
+      })
+      .collect();
+    assert_eq!(receiver_specific_key_ids.len(), writers.len());
+  }
+
+  #[test]
+  fn shared_topic_key_is_per_topic_and_released() {
+    let mut crypto = seeded_crypto(0);
+    let properties = [share_topic_key_property()];
+    let commands = register_topic_writers(&mut crypto, "commands", &properties, false, 2);
+    let status = register_topic_writers(&mut crypto, "status", &properties, false, 2);
+    let commands_key_ids = common_key_ids(&crypto, &commands);
+    assert_eq!(commands_key_ids.len(), 1);
+    assert!(commands_key_ids.is_disjoint(&common_key_ids(&crypto, &status)));
+
+    // The writers share the initialization vectors of the key
+    let (_, first_iv) = encode_payload(&crypto, commands[0].0, b"payload");
+    let (_, second_iv) = encode_payload(&crypto, commands[1].0, b"payload");
+    assert_ne!(<[u8; 12]>::from(first_iv), <[u8; 12]>::from(second_iv));
+
+    for (writer, _) in &commands {
+      crypto.unregister_datawriter(*writer).unwrap();
+    }
+    assert_eq!(crypto.shared_topic_keys.len(), 1);
+    crypto.check_invariants();
+    let commands = register_topic_writers(&mut crypto, "commands", &properties, false, 1);
+    assert!(commands_key_ids.is_disjoint(&common_key_ids(&crypto, &commands)));
+  }
+
+  #[test]
+  fn shared_topic_key_needs_the_topic_name() {
+    let mut crypto = seeded_crypto(0);
+    let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0));
+    assert!(crypto
+      .register_local_datawriter(
+        participant,
+        &[share_topic_key_property()],
+        protected_writer_attributes(false),
+      )
+      .is_err());
+  }
+
+  #[test]
+  fn key_materials_are_shared_instead_of_copied() {
+    for origin_authenticated in [false, true] {
+      let mut crypto = seeded_crypto(0);
+      let writers = register_topic_writers(
+        &mut crypto,
+        "commands",
+        &[share_topic_key_property()],
+        origin_authenticated,
+        3,
+      );
+      let common_key_materials: Vec<Arc<KeyMaterial_AES_GCM_GMAC_seq>> = writers
+        .iter()
+        .map(
+          |(writer, _)| match crypto.get_common_encode_key_materials(writer).unwrap() {
+            CommonEncodeKeyMaterials::Some(key_materials) => Arc::clone(key_materials),
+            CommonEncodeKeyMaterials::Volatile => panic!("unexpected volatile writer"),
+          },
+        )
+        .collect();
+      // The datawriters of the topic hold the same shared key
+      assert!(common_key_materials
+        .iter()
+        .all(|key_materials| Arc::ptr_eq(key_materials, &common_key_materials[0])));
+
+      // Without a receiver-specific key, the remote readers use the common key
+      // material as is
+      for (_, remote_reader) in &writers {
+        let receiver_specific = crypto
+          .get_receiver_specific_encode_key_materials(remote_reader)
+          .unwrap();
+        assert_eq!(
+          Arc::ptr_eq(receiver_specific, &common_key_materials[0]),
+          !origin_authenticated
+        );
+      }
+    }
+  }
+
+  fn set_rtps_origin_authenticated(
+    crypto: &mut CryptographicBuiltin,
+    participant: ParticipantCryptoHandle,
+    is_rtps_origin_authenticated: bool,
+  ) {
+    let attributes = crypto
+      .participant_encrypt_options
+      .get_mut(&participant)
+      .unwrap();
+    let mut plugin_attributes = BuiltinPluginParticipantSecurityAttributes::try_from(
+      attributes.plugin_participant_attributes,
+    )
+    .unwrap();
+    plugin_attributes.is_rtps_origin_authenticated = is_rtps_origin_authenticated;
+    attributes.plugin_participant_attributes = plugin_attributes.into();
+  }
+
+  // (sender key id, receiver-specific key id, receiver-specific key)
+  fn receiver_specific_keys(
+    crypto: &CryptographicBuiltin,
+    remote_participant: ParticipantCryptoHandle,
+  ) -> (CryptoTransformKeyId, CryptoTransformKeyId, BuiltinKey) {
+    let key_material = crypto
+      .get_receiver_specific_encode_key_materials(&remote_participant)
+      .unwrap()
+      .key_material()
+      .clone();
+    (
+      key_material.sender_key_id,
+      key_material.receiver_specific_key_id,
+      key_material.master_receiver_specific_key,
+    )
+  }
+
+  #[test]
+  fn registering_remote_participant_again_keeps_handle_and_keys() {
+    let mut crypto = seeded_crypto(0);
+    let (local, _) = register_participants(&mut crypto, shared_secret_handle(0));
+    set_rtps_origin_authenticated(&mut crypto, local, true);
+    let register = |crypto: &mut CryptographicBuiltin, identity| {
+      crypto
+        .register_matched_remote_participant(local, identity, 0, shared_secret_handle(0))
+        .unwrap()
+    };
+
+    let remote = register(&mut crypto, 7);
+    let keys = receiver_specific_keys(&crypto, remote);
+    assert_ne!(keys.1, CryptoTransformKeyId::ZERO);
+
+    assert_eq!(register(&mut crypto, 7), remote);
+    assert_eq!(receiver_specific_keys(&crypto, remote), keys);
+    assert_ne!(register(&mut crypto, 8), remote);
+
+    // Only the receiver-specific key follows a changed origin authentication
+    set_rtps_origin_authenticated(&mut crypto, local, false);
+    assert_eq!(register(&mut crypto, 7), remote);
+    let (sender_key_id, receiver_specific_key_id, _) = receiver_specific_keys(&crypto, remote);
+    assert_eq!(sender_key_id, keys.0);
+    assert_eq!(receiver_specific_key_id, CryptoTransformKeyId::ZERO);
+
+    crypto.unregister_participant(remote).unwrap();
+    assert_ne!(register(&mut crypto, 7), remote);
+  }
+
+  #[test]
+  fn unregistering_writer_removes_its_matched_readers() {
+    let mut crypto = seeded_crypto(0);
+    let (participant, remote_participant) =
+      register_participants(&mut crypto, shared_secret_handle(0));
+    let writer = crypto
+      .register_local_datawriter(participant, &[], protected_writer_attributes(true))
+      .unwrap();
+    let remote_participants: HashSet<ParticipantCryptoHandle> = [1, 2]
+      .into_iter()
+      .map(|identity| {
+        crypto
+          .register_matched_remote_participant(participant, identity, 0, shared_secret_handle(0))
+          .unwrap()
+      })
+      .chain([remote_participant])
+      .collect();
+    for remote_participant in &remote_participants {
+      crypto
+        .register_matched_remote_datareader(
+          writer,
+          *remote_participant,
+          shared_secret_handle(0),
+          false,
+        )
+        .unwrap();
+    }
+    crypto.check_invariants();
+    assert_eq!(crypto.matched_local_endpoint.len(), 3);
+
+    crypto.unregister_datawriter(writer).unwrap();
+    crypto.check_invariants();
+    assert!(crypto.endpoint_to_participant.is_empty());
+    assert!(crypto.participant_to_endpoint_info.is_empty());
+    assert!(crypto.matched_remote_endpoint.is_empty());
+    assert!(crypto.matched_local_endpoint.is_empty());
+    assert!(crypto.endpoint_encrypt_options.is_empty());
+    assert!(crypto.decode_key_materials.is_empty());
+    // Only the participants keep their key materials
+    assert_eq!(
+      crypto
+        .common_encode_key_materials
+        .keys()
+        .collect::<Vec<_>>(),
+      [&participant]
+    );
+    assert_eq!(
+      crypto
+        .receiver_specific_encode_key_materials
+        .keys()
+        .copied()
+        .collect::<HashSet<_>>(),
+      remote_participants
+    );
+  }
+
+  #[test]
+  fn crypto_handles_are_not_reissued_after_wrap_around() {
+    let mut crypto = seeded_crypto(0);
+    let (participant, remote_participant) =
+      register_participants(&mut crypto, shared_secret_handle(0));
+    let writer = crypto
+      .register_local_datawriter(participant, &[], protected_writer_attributes(true))
+      .unwrap();
+    assert_eq!([participant, remote_participant, writer], [1, 2, 3]);
+
+    crypto.crypto_handle_counter = u32::MAX - 1;
+    let register_writer = |crypto: &mut CryptographicBuiltin| {
+      crypto
+        .register_local_datawriter(participant, &[], protected_writer_attributes(true))
+        .unwrap()
+    };
+    assert_eq!(register_writer(&mut crypto), u32::MAX);
+    // 0 is reserved and 1..=3 are still in use
+    assert_eq!(register_writer(&mut crypto), 4);
+    let remote_reader = crypto
+      .register_matched_remote_datareader(4, remote_participant, shared_secret_handle(0), false)
+      .unwrap();
+    assert_eq!(remote_reader, 5);
+    crypto.check_invariants();
+
+    // Released handles are issued again on the next round
+    crypto.unregister_datawriter(writer).unwrap();
+    crypto.crypto_handle_counter = u32::MAX;
+    assert_eq!(register_writer(&mut crypto), 3);
+    crypto.check_invariants();
+  }
+
+  #[test]
+  fn volatile_and_remote_endpoints_are_not_rekeyed() {
+    let (mut writer_side, remote_reader, ..) =
+      match_volatile_endpoints(|| shared_secret_handle(0), || shared_secret_handle(0), &[]);
+    assert!(writer_side
+      .regenerate_local_endpoint_keys(remote_reader)
+      .is_err());
+    let volatile_writer = writer_side.matched_local_endpoint[&remote_reader];
+    assert!(writer_side
+      .regenerate_local_endpoint_keys(volatile_writer)
+      .is_err());
+    assert!(writer_side.take_rekeyed_endpoints().is_empty());
+  }
+
+  fn allow_key_export_property() -> Property {
+    Property {
+      name: "dds.sec.crypto.allow_key_export".to_string(),
+      value: "true".to_string(),
+      propagate: false,
+    }
+  }
+
+  #[test]
+  fn imported_key_materials_keep_decoding() {
+    let mut endpoints = MatchedEndpoints::with_properties(
+      protected_writer_attributes(true),
+      MatchProperties {
+        reader_participant: &[allow_key_export_property()],
+        ..MatchProperties::default()
+      },
+    );
+    endpoints.send_writer_tokens();
+    let encoded = endpoints.encode_heartbeat();
+    let MatchedEndpoints {
+      reader_side,
+      reader_participant,
+      remote_writer_participant: remote_participant,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+    let exported = reader_side.export_key_materials().unwrap();
+
+    let decodes = |crypto: &CryptographicBuiltin,
+                   local_participant: ParticipantCryptoHandle,
+                   remote_participant: ParticipantCryptoHandle| {
+      matches!(
+        crypto.decode_submessage(encoded.clone(), local_participant, remote_participant),
+        Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
+          WriterSubmessage::Heartbeat(..),
+          _
+        )))
+      )
+    };
+    assert!(decodes(
+      &reader_side,
+      reader_participant,
+      remote_participant
+    ));
+
+    // In a fresh plugin the handles stay as they were, and so do the keys of
+    // the local endpoints
+    let mut restarted = seeded_crypto(3);
+    restarted.import_key_materials(exported.clone()).unwrap();
+    restarted.check_invariants();
+    assert!(decodes(&restarted, reader_participant, remote_participant));
+    assert_eq!(
+      common_key_ids(&restarted, &[(reader, remote_writer)]),
+      common_key_ids(&reader_side, &[(reader, remote_writer)])
+    );
+
+    // In a plugin that has issued handles, the imported ones are shifted past
+    // them
+    let mut in_use = seeded_crypto(4);
+    register_participants(&mut in_use, shared_secret_handle(0x33));
+    let offset = in_use.crypto_handle_counter;
+    in_use.import_key_materials(exported).unwrap();
+    in_use.check_invariants();
+    assert!(decodes(
+      &in_use,
+      reader_participant + offset,
+      remote_participant + offset
+    ));
+    let (new_participant, _) = register_participants(&mut in_use, shared_secret_handle(0x33));
+    assert!(new_participant > reader_side.crypto_handle_counter + offset);
+
+    in_use
+      .unregister_participant(remote_participant + offset)
+      .unwrap();
+    in_use.check_invariants();
+    assert!(in_use.imported_remote_participants.is_empty());
+  }
+
+  #[test]
+  fn key_export_is_opt_in_and_versioned() {
+    let mut crypto = seeded_crypto(1);
+    register_participants(&mut crypto, shared_secret_handle(0x11));
+    assert!(crypto.export_key_materials().is_err());
+
+    register_participants_with_properties(
+      &mut crypto,
+      shared_secret_handle(0x11),
+      &[allow_key_export_property()],
+    );
+    let exported = crypto.export_key_materials().unwrap();
+
+    let mut newer_version = exported.to_vec();
+    newer_version[3] += 1;
+    let mut truncated = exported.to_vec();
+    truncated.truncate(exported.len() / 2);
+    for malformed in [newer_version, truncated, b"KM".to_vec(), Vec::new()] {
+      let mut restarted = seeded_crypto(2);
+      assert_eq!(
+        restarted
+          .import_key_materials(Bytes::from(malformed))
+          .unwrap_err()
+          .kind(),
+        SecurityErrorKind::MalformedToken
+      );
+      assert!(!restarted.crypto_handle_in_use(1));
+    }
+  }
+
+  fn wrap_key_material_property() -> Property {
+    Property {
+      name: "dds.sec.crypto.wrap_key_material".to_string(),
+      value: "true".to_string(),
+      propagate: false,
+    }
+  }
+
+  fn key_material_names(tokens: &[CryptoToken]) -> Vec<&str> {
+    tokens
+      .iter()
+      .flat_map(|token| &token.data_holder.binary_properties)
+      .map(|binary_property| binary_property.name.as_str())
+      .collect()
+  }
+
+  #[test]
+  fn crypto_tokens_carry_the_standard_key_material_unless_both_sides_wrap() {
+    let wrap_key_material = [wrap_key_material_property()];
+    for (writer_participant, reader_participant, wrapped) in [
+      (&[][..], &[][..], false),
+      (&wrap_key_material[..], &[][..], false),
+      (&[][..], &wrap_key_material[..], false),
+      (&wrap_key_material[..], &wrap_key_material[..], true),
+    ] {
+      let mut endpoints = MatchedEndpoints::with_properties(
+        protected_writer_attributes(true),
+        MatchProperties {
+          writer_participant,
+          reader_participant,
+          ..MatchProperties::default()
+        },
+      );
+      // The participant tokens sent first cannot know whether the remote wraps
+      let [first_tokens, second_tokens] = endpoints.exchange_participant_tokens();
+      assert_eq!(key_material_names(&first_tokens), ["dds.cryp.keymat"]);
+      let expected_names = if wrapped {
+        ["dds.cryp.keymat.wrapped"]
+      } else {
+        ["dds.cryp.keymat"]
+      };
+      assert_eq!(key_material_names(&second_tokens), expected_names);
+
+      let writer_tokens = endpoints
+        .writer_side
+        .create_local_datawriter_crypto_tokens(endpoints.writer, endpoints.remote_reader)
+        .unwrap();
+      assert_eq!(key_material_names(&writer_tokens), expected_names);
+      let reader_tokens = endpoints
+        .reader_side
+        .create_local_datareader_crypto_tokens(endpoints.reader, endpoints.remote_writer)
+        .unwrap();
+      assert_eq!(key_material_names(&reader_tokens), expected_names);
+      endpoints.send_writer_tokens();
+      endpoints.send_reader_tokens();
+    }
+  }
+
+  #[test]
+  fn exchanged_crypto_tokens_do_not_reveal_key_material() {
+    let wrap_key_material = [wrap_key_material_property()];
+    let mut endpoints = MatchedEndpoints::with_properties(
+      protected_writer_attributes(true),
+      MatchProperties {
+        writer_participant: &wrap_key_material,
+        reader_participant: &wrap_key_material,
+        ..MatchProperties::default()
+      },
+    );
+    endpoints.exchange_participant_tokens();
+    let MatchedEndpoints {
+      mut writer_side,
+      writer,
+      remote_reader,
+      mut reader_side,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+    let tokens = writer_side
+      .create_local_datawriter_crypto_tokens(writer, remote_reader)
+      .unwrap();
+    assert_eq!(key_material_names(&tokens), ["dds.cryp.keymat.wrapped"]);
+
+    // The tokens as they are serialized into a ParticipantGenericMessage
+    let on_the_wire: Vec<u8> = tokens
+      .iter()
+      .flat_map(|token| token.data_holder.write_to_vec().unwrap())
+      .collect();
+    let key_material = writer_side.receiver_specific_encode_key_materials[&remote_reader]
+      .key_material()
+      .clone();
+    for key in [
+      &key_material.master_salt,
+      &key_material.master_sender_key,
+      &key_material.master_receiver_specific_key,
+    ] {
+      assert!(!on_the_wire
+        .windows(key.as_bytes().len())
+        .any(|window| window == key.as_bytes()));
+    }
+
+    // Only the other side of the participant pair can unwrap them
+    let register_reader = |crypto: &mut CryptographicBuiltin, shared_secret| {
+      let (reader_participant, remote_participant) = register_participants(crypto, shared_secret);
+      let reader = crypto
+        .register_local_datareader(reader_participant, &[], protected_writer_attributes(true))
+        .unwrap();
+      let remote_writer = crypto
+        .register_matched_remote_datawriter(reader, remote_participant, shared_secret_handle(0x11))
+        .unwrap();
+      (reader, remote_writer)
+    };
+    reader_side
+      .set_remote_datawriter_crypto_tokens(reader, remote_writer, tokens.clone())
+      .unwrap();
+    assert_eq!(
+      reader_side.decode_key_materials[&remote_writer]
+        .key_material()
+        .sender_key_id,
+      key_material.sender_key_id
+    );
+
+    let mut eavesdropper = seeded_crypto(3);
+    let (reader, remote_writer) = register_reader(&mut eavesdropper, shared_secret_handle(0x12));
+    assert_eq!(
+      eavesdropper
+        .set_remote_datawriter_crypto_tokens(reader, remote_writer, tokens)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::VerificationFailed
+    );
+
+    // Implementations that do not wrap key material send it as it is
+    let plain_tokens = vec![CryptoToken::try_from(key_material.clone()).unwrap()];
+    eavesdropper
+      .set_remote_datawriter_crypto_tokens(reader, remote_writer, plain_tokens)
+      .unwrap();
+  }
+
+  #[test]
+  fn crypto_tokens_with_weaker_transformation_kind_are_rejected() {
+    let endpoint_attributes =
+      |is_submessage_encrypted, is_payload_encrypted| EndpointSecurityAttributes {
+        is_submessage_protected: true,
+        is_payload_protected: true,
+        plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+          is_submessage_encrypted,
+          is_submessage_origin_authenticated: false,
+          is_payload_encrypted,
+        }
+        .into(),
+        ..EndpointSecurityAttributes::empty()
+      };
+    let writer_tokens = |writer_attributes| {
+      let mut writer_side = seeded_crypto(1);
+      let (participant, remote_participant) =
+        register_participants(&mut writer_side, shared_secret_handle(0x11));
+      let writer = writer_side
+        .register_local_datawriter(participant, &[], writer_attributes)
+        .unwrap();
+      let remote_reader = writer_side
+        .register_matched_remote_datareader(
+          writer,
+          remote_participant,
+          shared_secret_handle(0x11),
+          false,
+        )
+        .unwrap();
+      writer_side
+        .create_local_datawriter_crypto_tokens(writer, remote_reader)
+        .unwrap()
+    };
+
+    // The governance of the reader demands GCM encryption of both submessages
+    // and payloads
+    let mut reader_side = seeded_crypto(2);
+    let (participant, remote_participant) =
+      register_participants(&mut reader_side, shared_secret_handle(0x11));
+    let reader = reader_side
+      .register_local_datareader(participant, &[], endpoint_attributes(true, true))
+      .unwrap();
+    let remote_writer = reader_side
+      .register_matched_remote_datawriter(reader, remote_participant, shared_secret_handle(0x11))
+      .unwrap();
+
+    // GMAC only, and the two-element sequence with a GMAC payload key
+    for (is_submessage_encrypted, is_payload_encrypted) in [(false, false), (true, false)] {
+      let tokens = writer_tokens(endpoint_attributes(
+        is_submessage_encrypted,
+        is_payload_encrypted,
+      ));
+      assert_eq!(
+        reader_side
+          .set_remote_datawriter_crypto_tokens(reader, remote_writer, tokens)
+          .unwrap_err()
+          .kind(),
+        SecurityErrorKind::UnsupportedTransformation
+      );
+      assert!(!reader_side
+        .decode_key_materials
+        .contains_key(&remote_writer));
+    }
+
+    reader_side
+      .set_remote_datawriter_crypto_tokens(
+        reader,
+        remote_writer,
+        writer_tokens(endpoint_attributes(true, true)),
+      )
+      .unwrap();
+  }
+
+  #[test]
+  fn datawriter_may_not_weaken_its_protection() {
+    let mut crypto = seeded_crypto(1);
+    let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0x11));
+    let unprotected_payload_attributes = {
+      let mut attributes = protected_writer_attributes(false);
+      attributes.is_payload_protected = false;
+      attributes.plugin_endpoint_attributes = BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: true,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: false,
+      }
+      .into();
+      attributes
+    };
+
+    for (name, value, attributes) in [
+      // A downgrade from ENCRYPT
+      (
+        "dds.sec.crypto.payload_protection_kind",
+        "SIGN",
+        protected_writer_attributes(false),
+      ),
+      (
+        "dds.sec.crypto.submessage_protection_kind",
+        "NONE",
+        protected_writer_attributes(false),
+      ),
+      // Unprotected payloads are not passed to the plugin
+      (
+        "dds.sec.crypto.payload_protection_kind",
+        "ENCRYPT",
+        unprotected_payload_attributes,
+      ),
+      // Origin authentication is not a property of the transformation kind
+      (
+        "dds.sec.crypto.submessage_protection_kind",
+        "ENCRYPT_WITH_ORIGIN_AUTHENTICATION",
+        protected_writer_attributes(false),
+      ),
+    ] {
+      let properties = [Property {
+        name: name.to_string(),
+        value: value.to_string(),
+        propagate: false,
+      }];
+      let error = crypto
+        .register_local_datawriter(participant, &properties, attributes)
+        .unwrap_err();
+      assert_eq!(
+        error.kind(),
+        SecurityErrorKind::Internal,
+        "{name} = {value}"
+      );
+    }
+
+    // Stating the required protection is fine
+    let properties = [Property {
+      name: "dds.sec.crypto.payload_protection_kind".to_string(),
+      value: "ENCRYPT".to_string(),
+      propagate: false,
+    }];
+    crypto
+      .register_local_datawriter(participant, &properties, protected_writer_attributes(false))
+      .unwrap();
+  }
+
+  #[test]
+  fn volatile_endpoints_are_recognized_by_entity_id() {
+    let mut crypto = seeded_crypto(1);
+    let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0x11));
+    let user_writer_id = EntityId::new([0, 0, 1], EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let user_reader_id = EntityId::new([0, 0, 2], EntityKind::READER_WITH_KEY_USER_DEFINED);
+    let registers_volatile = |crypto: &CryptographicBuiltin, crypto_handle| {
+      matches!(
+        crypto.get_common_encode_key_materials(&crypto_handle),
+        Ok(CommonEncodeKeyMaterials::Volatile)
+      )
+    };
+
+    for with_property in [true, false] {
+      // The builtin volatile endpoints, with or without the property
+      let writer_properties = [
+        vec![entity_id_property(
+          EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_WRITER,
+        )],
+        with_property
+          .then(volatile_writer_recognition_property)
+          .into_iter()
+          .collect(),
+      ]
+      .concat();
+      let writer = crypto
+        .register_local_datawriter(
+          participant,
+          &writer_properties,
+          volatile_endpoint_attributes(),
+        )
+        .unwrap();
+      assert!(registers_volatile(&crypto, writer));
+      let reader_properties = [
+        vec![entity_id_property(
+          EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_READER,
+        )],
+        with_property
+          .then(volatile_reader_recognition_property)
+          .into_iter()
+          .collect(),
+      ]
+      .concat();
+      let reader = crypto
+        .register_local_datareader(
+          participant,
+          &reader_properties,
+          volatile_endpoint_attributes(),
+        )
+        .unwrap();
+      assert!(registers_volatile(&crypto, reader));
+    }
+
+    // Application endpoints are registered normally, but rejected if they set
+    // the property
+    let writer = crypto
+      .register_local_datawriter(
+        participant,
+        &[entity_id_property(user_writer_id)],
+        volatile_endpoint_attributes(),
+      )
+      .unwrap();
+    assert!(!registers_volatile(&crypto, writer));
+    let reader = crypto
+      .register_local_datareader(
+        participant,
+        &[entity_id_property(user_reader_id)],
+        volatile_endpoint_attributes(),
+      )
+      .unwrap();
+    assert!(!registers_volatile(&crypto, reader));
+    assert_eq!(
+      crypto
+        .register_local_datawriter(
+          participant,
+          &[
+            entity_id_property(user_writer_id),
+            volatile_writer_recognition_property(),
+          ],
+          volatile_endpoint_attributes(),
+        )
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::Internal
+    );
+    assert_eq!(
+      crypto
+        .register_local_datareader(
+          participant,
+          &[
+            entity_id_property(user_reader_id),
+            volatile_reader_recognition_property(),
+          ],
+          volatile_endpoint_attributes(),
+        )
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::Internal
+    );
+  }
+
+  #[test]
+  fn participant_crypto_tokens_may_be_set_again_only_if_identical() {
+    let origin_authenticated_participants = |seed| {
+      let mut crypto = seeded_crypto(seed);
+      let local = crypto
+        .register_local_participant(0, 0, &[], origin_authenticated_participant_attributes())
+        .unwrap();
+      let remote = crypto
+        .register_matched_remote_participant(local, 0, 0, shared_secret_handle(0x11))
+        .unwrap();
+      (crypto, local, remote)
+    };
+    let (mut sender, sender_local, sender_remote) = origin_authenticated_participants(1);
+    let (mut receiver, receiver_local, receiver_remote) = origin_authenticated_participants(2);
+    let (mut other_sender, other_sender_local, other_sender_remote) =
+      origin_authenticated_participants(3);
+
+    let tokens = sender
+      .create_local_participant_crypto_tokens(sender_local, sender_remote)
+      .unwrap();
+
+    // Discovery may deliver the same tokens more than once
+    for _ in 0..2 {
+      receiver
+        .set_remote_participant_crypto_tokens(receiver_local, receiver_remote, tokens.clone())
+        .unwrap();
+    }
+
+    let conflicting_tokens = other_sender
+      .create_local_participant_crypto_tokens(other_sender_local, other_sender_remote)
+      .unwrap();
+    assert_eq!(
+      receiver
+        .set_remote_participant_crypto_tokens(receiver_local, receiver_remote, conflicting_tokens)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::Internal
+    );
+
+    // The first key material is kept
+    assert!(same_key_material(
+      receiver.decode_key_materials[&receiver_remote].key_material(),
+      sender.receiver_specific_encode_key_materials[&sender_remote].key_material()
+    ));
+  }
+
+  #[test]
+  fn participant_crypto_tokens_need_a_receiver_specific_key_for_origin_authentication() {
+    let mut sender = seeded_crypto(1);
+    let (sender_local, sender_remote) =
+      register_participants(&mut sender, shared_secret_handle(0x11));
+    let tokens = sender
+      .create_local_participant_crypto_tokens(sender_local, sender_remote)
+      .unwrap();
+
+    let mut receiver = seeded_crypto(2);
+    let receiver_local = receiver
+      .register_local_participant(0, 0, &[], origin_authenticated_participant_attributes())
+      .unwrap();
+    let receiver_remote = receiver
+      .register_matched_remote_participant(receiver_local, 0, 0, shared_secret_handle(0x11))
+      .unwrap();
+    assert_eq!(
+      receiver
+        .set_remote_participant_crypto_tokens(receiver_local, receiver_remote, tokens)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::MalformedToken
+    );
+    assert!(!receiver.decode_key_materials.contains_key(&receiver_remote));
+  }
+
+  fn remote_endpoints(
+    remote_participants: &[ParticipantCryptoHandle],
+  ) -> Vec<(ParticipantCryptoHandle, SharedSecretHandle)> {
+    remote_participants
+      .iter()
+      .zip(1..)
+      .map(|(remote_participant, identity)| (*remote_participant, shared_secret_handle(identity)))
+      .collect()
+  }
+
+  #[test]
+  fn batch_registration_of_matched_remote_endpoints_equals_single_registrations() {
+    for origin_authenticated in [false, true] {
+      let (mut one_by_one, writer, reader, remote_participants) =
+        local_endpoints_and_remote_participants(1, origin_authenticated, 5);
+      let single_readers: Vec<_> = remote_endpoints(&remote_participants)
+        .into_iter()
+        .map(|(remote_participant, shared_secret)| {
+          one_by_one
+            .register_matched_remote_datareader(writer, remote_participant, shared_secret, false)
+            .unwrap()
+        })
+        .collect();
+      let single_writers: Vec<_> = remote_endpoints(&remote_participants)
+        .into_iter()
+        .map(|(remote_participant, shared_secret)| {
+          one_by_one
+            .register_matched_remote_datawriter(reader, remote_participant, shared_secret)
+            .unwrap()
+        })
+        .collect();
+
+      let (mut batched, writer, reader, remote_participants) =
+        local_endpoints_and_remote_participants(1, origin_authenticated, 5);
+      let batch_readers = batched
+        .register_matched_remote_datareaders(writer, &remote_endpoints(&remote_participants), false)
+        .unwrap();
+      let batch_writers = batched
+        .register_matched_remote_datawriters(reader, &remote_endpoints(&remote_participants))
+        .unwrap();
+
+      assert_eq!(batch_readers, single_readers);
+      assert_eq!(batch_writers, single_writers);
+      for remote_reader in batch_readers {
+        assert_eq!(
+          batched
+            .create_local_datawriter_crypto_tokens(writer, remote_reader)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.data_holder)
+            .collect::<Vec<_>>(),
+          one_by_one
+            .create_local_datawriter_crypto_tokens(writer, remote_reader)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.data_holder)
+            .collect::<Vec<_>>()
+        );
+      }
+      for remote_writer in batch_writers {
+        assert_eq!(
+          batched
+            .create_local_datareader_crypto_tokens(reader, remote_writer)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.data_holder)
+            .collect::<Vec<_>>(),
+          one_by_one
+            .create_local_datareader_crypto_tokens(reader, remote_writer)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.data_holder)
+            .collect::<Vec<_>>()
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn batch_registration_needs_a_registered_local_endpoint() {
+    let (mut crypto, _, _, remote_participants) =
+      local_endpoints_and_remote_participants(1, true, 1);
+    assert_eq!(
+      crypto
+        .register_matched_remote_datareaders(12345, &remote_endpoints(&remote_participants), false)
+        .unwrap_err()
+        .kind,
+      SecurityErrorKind::NotRegistered
+    );
+  }
+
+  // Compares matching 1000 remote datareaders one by one and in a batch. Run
+  // with `cargo test --release --features security -- --ignored --nocapture
+  // matching_1000_remote_datareaders`.
+  #[test]
+  #[ignore]
+  fn matching_1000_remote_datareaders() {
+    const COUNT: usize = 1000;
+    let identities = || (0..COUNT).map(|identity| (identity % 250) as u8 + 1);
+    let setup = || {
+      let (mut crypto, writer, _, _) = local_endpoints_and_remote_participants(1, true, 0);
+      let participant = crypto.endpoint_to_participant[&writer];
+      let remote_participants: Vec<_> = (0..COUNT)
+        .map(|identity| {
+          crypto
+            .register_matched_remote_participant(
+              participant,
+              identity as IdentityHandle + 1,
+              0,
+              shared_secret_handle(0x11),
+            )
+            .unwrap()
+        })
+        .collect();
+      (crypto, writer, remote_participants)
+    };
+
+    let (mut crypto, writer, remote_participants) = setup();
+    let start = std::time::Instant::now();
+    for (remote_participant, identity) in remote_participants.iter().zip(identities()) {
+      crypto
+        .register_matched_remote_datareader(
+          writer,
+          *remote_participant,
+          shared_secret_handle(identity),
+          false,
+        )
+        .unwrap();
+    }
+    let one_by_one = start.elapsed();
+
+    let (mut crypto, writer, remote_participants) = setup();
+    let remote_datareaders: Vec<_> = remote_participants
+      .iter()
+      .zip(identities())
+      .map(|(remote_participant, identity)| (*remote_participant, shared_secret_handle(identity)))
+      .collect();
+    let start = std::time::Instant::now();
+    crypto
+      .register_matched_remote_datareaders(writer, &remote_datareaders, false)
+      .unwrap();
+    let batched = start.elapsed();
+
+    println!("Matching {COUNT} remote datareaders: one by one {one_by_one:?}, batched {batched:?}");
+  }
+}
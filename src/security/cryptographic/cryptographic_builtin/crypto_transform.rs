@@ -1,7 +1,7 @@
 use bytes::Bytes;
 use enumflags2::BitFlags;
 use speedy::{Readable, Writable};
-use log::{debug, warn};
+use log::debug;
 
 use crate::{
   create_security_error_and_log,
@@ -37,11 +37,23 @@ impl CryptographicBuiltin {
     sending_endpoint_crypto_handle: EndpointCryptoHandle,
     receiving_endpoint_crypto_handle_list: &[EndpointCryptoHandle],
   ) -> SecurityResult<EncodedSubmessage> {
+    // Submessages of an unprotected endpoint go out as they are
+    if self.encodes_without_protection(
+      sending_endpoint_crypto_handle,
+      KeyMaterialScope::MessageOrSubmessage,
+    ) {
+      return Ok(EncodedSubmessage::Unencoded(plain_rtps_submessage));
+    }
+
     // Serialize plaintext
     // TODO: Do we respect RTPS endianness here? I.e. used and flagged encodings
     // match?
     let plaintext = plain_rtps_submessage.write_to_vec().map_err(|err| {
-      create_security_error_and_log!("Error converting Submessage to byte vector: {}", err)
+      create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "Error converting Submessage to byte vector: {}",
+        err
+      )
     })?;
 
     // Get the key material for encoding
@@ -55,6 +67,7 @@ impl CryptographicBuiltin {
       sending_endpoint_crypto_handle,
       KeyMaterialScope::MessageOrSubmessage,
       receiving_endpoint_crypto_handle_list,
+      plaintext.len(),
     )?;
 
     // Compute encoded submessage and footer
@@ -98,12 +111,80 @@ impl CryptographicBuiltin {
       crypto_footer: CryptoFooter::try_from(crypto_footer)?,
     };
 
+    TransformCounters::increment(&self.transform_counters.encoded_submessages);
     Ok(EncodedSubmessage::Encoded(
       prefix.create_submessage(speedy::Endianness::BigEndian)?, // 9.5.2.3 use BigEndian
       encoded_submessage,
       postfix.create_submessage(speedy::Endianness::BigEndian)?, // 9.5.2.5 use BigEndian
     ))
   }
+
+  // Finds the remote endpoints of the sending participant that have a decode
+  // key with the key id, paired with their matched local endpoints. None if
+  // there are no such endpoints.
+  fn secure_submessage_category(
+    &self,
+    header_key_id: CryptoTransformKeyId,
+    sending_remote_participant_crypto_handle: ParticipantCryptoHandle,
+  ) -> SecurityResult<Option<SecureSubmessageCategory>> {
+    let sending_participant_endpoints = self
+      .participant_to_endpoint_info
+      .get(&sending_remote_participant_crypto_handle)
+      .ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
+          "Could not find registered entities for the sending_remote_participant_crypto_handle {}",
+          sending_remote_participant_crypto_handle
+        )
+      })?;
+
+    let mut datawriter_pairs = Vec::new();
+    let mut datareader_pairs = Vec::new();
+    for remote_endpoint_crypto_handle in self
+      .decode_key_id_index
+      .get(&(sending_remote_participant_crypto_handle, header_key_id))
+      .into_iter()
+      .flatten()
+      .copied()
+    {
+      for kind in [EndpointKind::DataWriter, EndpointKind::DataReader] {
+        if !sending_participant_endpoints.contains(&EndpointInfo {
+          crypto_handle: remote_endpoint_crypto_handle,
+          kind,
+        }) {
+          continue;
+        }
+        let local_endpoint_crypto_handle = self
+          .matched_local_endpoint
+          .get(&remote_endpoint_crypto_handle)
+          .copied()
+          .ok_or_else(|| {
+            create_security_error_and_log!(
+              SecurityErrorKind::NotRegistered,
+              "The local endpoint matched to the remote endpoint crypto handle {} is missing.",
+              remote_endpoint_crypto_handle
+            )
+          })?;
+        let pair = (local_endpoint_crypto_handle, remote_endpoint_crypto_handle);
+        match kind {
+          EndpointKind::DataWriter => datawriter_pairs.push(pair),
+          EndpointKind::DataReader => datareader_pairs.push(pair),
+        }
+      }
+    }
+
+    Ok(
+      match (datawriter_pairs.is_empty(), datareader_pairs.is_empty()) {
+        (true, true) => None,
+        (false, true) => Some(SecureSubmessageCategory::Writer(datawriter_pairs)),
+        (true, false) => Some(SecureSubmessageCategory::Reader(datareader_pairs)),
+        (false, false) => Some(SecureSubmessageCategory::Either {
+          writer: datawriter_pairs,
+          reader: datareader_pairs,
+        }),
+      },
+    )
+  }
 }
 
 impl CryptoTransform for CryptographicBuiltin {
@@ -112,6 +193,14 @@ impl CryptoTransform for CryptographicBuiltin {
     plain_buffer: Vec<u8>,
     sending_datawriter_crypto_handle: DatawriterCryptoHandle,
   ) -> SecurityResult<(Vec<u8>, ParameterList)> {
+    // An unprotected payload is sent without a CryptoHeader and CryptoFooter
+    if self.encodes_without_protection(
+      sending_datawriter_crypto_handle,
+      KeyMaterialScope::PayloadOnly,
+    ) {
+      return Ok((plain_buffer, ParameterList::new()));
+    }
+
     // Get the key material for encrypting serialized payloads
     let EncodeSessionMaterials {
       key_id,
@@ -123,6 +212,7 @@ impl CryptoTransform for CryptographicBuiltin {
       sending_datawriter_crypto_handle,
       KeyMaterialScope::PayloadOnly,
       &[],
+      plain_buffer.len(),
     )?;
 
     // Receiver specific (signing) keys are not used.
@@ -157,6 +247,7 @@ impl CryptoTransform for CryptographicBuiltin {
             .write_to_vec()
             .map_err(|err| {
               create_security_error_and_log!(
+                SecurityErrorKind::Internal,
                 "Error converting CryptoContent to byte vector: {}",
                 err
               )
@@ -167,7 +258,11 @@ impl CryptoTransform for CryptographicBuiltin {
     };
 
     let header_vec = CryptoHeader::from(header).write_to_vec().map_err(|err| {
-      create_security_error_and_log!("Error converting CryptoHeader to byte vector: {}", err)
+      create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "Error converting CryptoHeader to byte vector: {}",
+        err
+      )
     })?;
     let footer_vec = Vec::<u8>::try_from(footer)?;
     Ok((
@@ -214,6 +309,17 @@ impl CryptoTransform for CryptographicBuiltin {
     sending_participant_crypto_handle: ParticipantCryptoHandle,
     receiving_participant_crypto_handle_list: Vec<ParticipantCryptoHandle>,
   ) -> SecurityResult<Message> {
+    // Without RTPS protection the message is sent as it is
+    if self.encodes_without_protection(
+      sending_participant_crypto_handle,
+      KeyMaterialScope::MessageOrSubmessage,
+    ) {
+      return Ok(Message {
+        header,
+        submessages,
+      });
+    }
+
     // Convert the header into an InfoSource submessage
     let info_source = InfoSource::from(header)
       .create_submessage(BitFlags::from_endianness(speedy::Endianness::BigEndian));
@@ -228,7 +334,11 @@ impl CryptoTransform for CryptographicBuiltin {
         .iter()
         .map(|submessage| {
           submessage.write_to_vec().map_err(|err| {
-            create_security_error_and_log!("Error converting Submessage to byte vector: {}", err)
+            create_security_error_and_log!(
+              SecurityErrorKind::Internal,
+              "Error converting Submessage to byte vector: {}",
+              err
+            )
           })
         }),
     )? // Deal with errors
@@ -246,14 +356,17 @@ impl CryptoTransform for CryptographicBuiltin {
       sending_participant_crypto_handle,
       KeyMaterialScope::MessageOrSubmessage,
       &receiving_participant_crypto_handle_list,
+      plaintext.len(),
     )?;
 
     // Compute encoded submessages and footer
     let (encoded_submessages, crypto_footer) = match transformation_kind {
       BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE => {
-        return Err(create_security_error_and_log!(
-          "encode_rtps_message called when transformation kind is NONE."
-        ));
+        // Drop the InfoSource that was added for protection
+        return Ok(Message {
+          header,
+          submessages: submessages_with_info_source.into_iter().skip(1).collect(),
+        });
       }
       BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC
       | BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC => (
@@ -307,14 +420,122 @@ impl CryptoTransform for CryptographicBuiltin {
   }
 
   fn decode_rtps_message(
+    &self,
+    encoded_message: Message,
+    receiving_participant_crypto_handle: ParticipantCryptoHandle,
+    sending_participant_crypto_handle: ParticipantCryptoHandle,
+  ) -> SecurityResult<DecodeOutcome<Message>> {
+    let result = self.decode_rtps_message_unreported(
+      encoded_message,
+      receiving_participant_crypto_handle,
+      sending_participant_crypto_handle,
+    );
+    self.report_decode_outcome(
+      CryptoOperation::DecodeRtpsMessage,
+      sending_participant_crypto_handle,
+      &result,
+    );
+    result
+  }
+
+  fn preprocess_secure_submessage(
+    &self,
+    SecurePrefix { crypto_header }: &SecurePrefix,
+    _receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
+    sending_remote_participant_crypto_handle: ParticipantCryptoHandle,
+  ) -> SecurityResult<SecureSubmessageCategory> {
+    let BuiltinCryptoHeader {
+      transform_identifier:
+        BuiltinCryptoTransformIdentifier {
+          transformation_key_id: header_key_id,
+          ..
+        },
+      ..
+    } = BuiltinCryptoHeader::try_from(crypto_header.clone())?;
+
+    self
+      .secure_submessage_category(header_key_id, sending_remote_participant_crypto_handle)?
+      .ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
+          "No remote endpoint of the participant {} has a decode key with the key id {:?}",
+          sending_remote_participant_crypto_handle,
+          header_key_id
+        )
+      })
+  }
+
+  fn decode_submessage(
+    &self,
+    encoded_rtps_submessage: (SecurePrefix, Submessage, SecurePostfix),
+    receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
+    sending_remote_participant_crypto_handle: ParticipantCryptoHandle,
+  ) -> SecurityResult<DecodeOutcome<DecodedSubmessage>> {
+    let result = self.decode_submessage_unreported(
+      encoded_rtps_submessage,
+      receiving_local_participant_crypto_handle,
+      sending_remote_participant_crypto_handle,
+    );
+    self.report_decode_outcome(
+      CryptoOperation::DecodeSubmessage,
+      sending_remote_participant_crypto_handle,
+      &result,
+    );
+    result
+  }
+
+  fn decode_serialized_payload(
+    &self,
+    encoded_buffer: Vec<u8>,
+    inline_qos: ParameterList,
+    receiving_datareader_crypto_handle: DatareaderCryptoHandle,
+    sending_datawriter_crypto_handle: DatawriterCryptoHandle,
+  ) -> SecurityResult<Vec<u8>> {
+    let result = self.decode_serialized_payload_unreported(
+      encoded_buffer,
+      inline_qos,
+      receiving_datareader_crypto_handle,
+      sending_datawriter_crypto_handle,
+    );
+    self.report_rejection(
+      CryptoOperation::DecodeSerializedPayload,
+      sending_datawriter_crypto_handle,
+      &result,
+    );
+    result
+  }
+}
+
+// The decode operations of CryptoTransform. The trait functions report the
+// rejections to the security event listener after these have returned, so that
+// the listener is not called while holding the internal locks.
+impl CryptographicBuiltin {
+  fn decode_rtps_message_unreported(
     &self,
     Message {
       header: rtps_header,
       submessages,
     }: Message,
-    _receiving_participant_crypto_handle: ParticipantCryptoHandle,
+    receiving_participant_crypto_handle: ParticipantCryptoHandle,
     sending_participant_crypto_handle: ParticipantCryptoHandle,
   ) -> SecurityResult<DecodeOutcome<Message>> {
+    // A participant without RTPS protection sends its messages as they are
+    if !matches!(
+      submessages.first(),
+      Some(Submessage {
+        body: SubmessageBody::Security(SecuritySubmessage::SecureRTPSPrefix(..)),
+        ..
+      })
+    ) && self.decodes_without_protection(
+      sending_participant_crypto_handle,
+      KeyMaterialScope::MessageOrSubmessage,
+    ) {
+      return Ok(DecodeOutcome::Success(Message {
+        header: rtps_header,
+        submessages,
+      }));
+    }
+
     // we expect SecureRTPSPRefix + some submessages + SecureRTPSPostfix
     if let
       [ Submessage { body:
@@ -329,6 +550,7 @@ impl CryptoTransform for CryptographicBuiltin {
             SecureRTPSPostfix { crypto_footer }, _, )), .. }
       ] = submessages.as_slice()
     {
+      let header = BuiltinCryptoHeader::try_from(crypto_header.clone())?;
       let BuiltinCryptoHeader {
         transform_identifier:
           BuiltinCryptoTransformIdentifier {
@@ -336,10 +558,10 @@ impl CryptoTransform for CryptographicBuiltin {
             transformation_key_id,
           },
         builtin_crypto_header_extra: BuiltinCryptoHeaderExtra(initialization_vector),
-      } = BuiltinCryptoHeader::try_from(crypto_header.clone())?;
+      } = header;
 
       let BuiltinCryptoFooter { common_mac, receiver_specific_macs }
-        = BuiltinCryptoFooter::try_from(crypto_footer.clone())?;
+        = self.receiver_crypto_footer(receiving_participant_crypto_handle, crypto_footer)?;
 
       // Get decode key material
       let decode_key_material = match self.get_session_decode_crypto_materials(
@@ -349,23 +571,28 @@ impl CryptoTransform for CryptographicBuiltin {
         initialization_vector,
       ){
         Some(decode_key_material)=>decode_key_material,
-        None=> return Ok(DecodeOutcome::KeysNotFound(transformation_key_id))
+        None=> {
+          TransformCounters::increment(&self.transform_counters.missing_key_drops);
+          return Ok(DecodeOutcome::KeysNotFound(transformation_key_id))
+        }
       };
 
       // Check that the key id matches the header
       if transformation_key_id != decode_key_material.key_id {
         Err(create_security_error_and_log!(
-          "The key IDs don't match. The key material has sender_key_id {}, while the header has \
-           transformation_key_id {}",
+          SecurityErrorKind::UnsupportedTransformation,
+          "The key IDs don't match. The key material has sender_key_id {}, while the header is \
+           {:?}",
           decode_key_material.key_id,
-          transformation_key_id
+          header
         ))?;
       } else if header_transformation_kind != decode_key_material.transformation_kind {
         Err(create_security_error_and_log!(
-          "The transformation_kind don't match. The key material has {:?}, while the header has \
+          SecurityErrorKind::UnsupportedTransformation,
+          "The transformation_kind don't match. The key material has {:?}, while the header is \
            {:?}",
           decode_key_material.transformation_kind,
-          header_transformation_kind
+          header
         ))?;
       }
 
@@ -383,7 +610,7 @@ impl CryptoTransform for CryptographicBuiltin {
           {
             Ok((Vec::from(submessages), *info_source))
           } else {
-            Err(create_security_error_and_log!("Expected the first submessage to be InfoSource."))
+            Err(create_security_error_and_log!(SecurityErrorKind::MalformedToken, "Expected the first submessage to be InfoSource."))
           }
         }
         BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC
@@ -404,14 +631,16 @@ impl CryptoTransform for CryptographicBuiltin {
 
             // Validate receiver-specific MAC if one is expected
             if !validate_receiver_specific_mac(&decode_key_material,&initialization_vector,&common_mac,&receiver_specific_macs){
+              TransformCounters::increment(&self.transform_counters.mac_failures);
               return Ok(DecodeOutcome::ValidatingReceiverSpecificMACFailed);
             }
             // Validate the common MAC
-            validate_mac(decode_key, initialization_vector, &serialized_submessages, common_mac)
+            self.transform_counters.count_mac_failure(
+              validate_mac(decode_key, initialization_vector, &serialized_submessages, common_mac))
               // If the MACs are ok, return content. 
               .map( |_| (Vec::from(submessages), *info_source))
           } else {
-            Err(create_security_error_and_log!("Expected the first submessage to be InfoSource."))
+            Err(create_security_error_and_log!(SecurityErrorKind::MalformedToken, "Expected the first submessage to be InfoSource."))
           }
         }
         BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM
@@ -423,29 +652,31 @@ impl CryptoTransform for CryptographicBuiltin {
           {
             // Validate receiver-specific MAC if one is expected
             if !validate_receiver_specific_mac(&decode_key_material,&initialization_vector,&common_mac,&receiver_specific_macs){
+              TransformCounters::increment(&self.transform_counters.mac_failures);
               return Ok(DecodeOutcome::ValidatingReceiverSpecificMACFailed);
             }
             // Authenticated decryption, or exit on failure
             let mut plaintext =
               Bytes::copy_from_slice(
-                &decrypt(decode_key, initialization_vector, ciphertext, common_mac)?);
+                &self.transform_counters.count_mac_failure(
+                  decrypt(decode_key, initialization_vector, ciphertext, common_mac))?);
 
             // We expect an InfoSource submessage followed by the original submessage sequence
             let info_source =
               if let Some(Submessage {body: SubmessageBody::Interpreter(
                     InterpreterSubmessage::InfoSource(info_source, _)), .. })
                   = Submessage::read_from_buffer(&mut plaintext)
-                      .map_err(|e| create_security_error_and_log!("Failed to deserialize the plaintext: {e}"))?
+                      .map_err(|e| create_security_error_and_log!(SecurityErrorKind::MalformedToken, "Failed to deserialize the plaintext: {e}"))?
               {
                 info_source
               } else {
-                Err(create_security_error_and_log!("Expected the first decrypted submessage to be InfoSource."))?
+                Err(create_security_error_and_log!(SecurityErrorKind::MalformedToken, "Expected the first decrypted submessage to be InfoSource."))?
               };
 
             let mut submessages = Vec::<Submessage>::new();
             while !plaintext.is_empty() {
               if let Some(submessage) = Submessage::read_from_buffer(&mut plaintext)
-                .map_err(|e| create_security_error_and_log!("Failed to deserialize the plaintext: {e}"))?
+                .map_err(|e| create_security_error_and_log!(SecurityErrorKind::MalformedToken, "Failed to deserialize the plaintext: {e}"))?
               {
                 submessages.push(submessage);
               }
@@ -453,36 +684,45 @@ impl CryptoTransform for CryptographicBuiltin {
 
             Ok((submessages, info_source))
           } else {
-            Err(create_security_error_and_log!("Expected only a SecureBody submessage."))
+            Err(create_security_error_and_log!(SecurityErrorKind::MalformedToken, "Expected only a SecureBody submessage."))
           }
         }
       }
       .and_then( |(submessages, info_source)| {
         if InfoSource::from(rtps_header) == info_source {
+          self.check_replay(
+            receiving_participant_crypto_handle,
+            sending_participant_crypto_handle,
+            transformation_key_id,
+            initialization_vector,
+          )?;
           Ok(DecodeOutcome::Success(Message { header: rtps_header, submessages }))
         } else {
           Err(create_security_error_and_log!(
+            SecurityErrorKind::MalformedToken,
             "The RTPS header did not match the encoded InfoSource: {:?} expected to match {:?}",
             info_source, rtps_header))
         }
       })
     } else {
       Err(create_security_error_and_log!(
+        SecurityErrorKind::MalformedToken,
         "Expected the first submessage to be SecureRTPSPrefix and the last SecureRTPSPostfix"
       ))
     }
   }
 
-  fn decode_submessage(
+  fn decode_submessage_unreported(
     &self,
     encoded_rtps_submessage: (SecurePrefix, Submessage, SecurePostfix),
-    _receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
+    receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
     sending_remote_participant_crypto_handle: ParticipantCryptoHandle,
   ) -> SecurityResult<DecodeOutcome<DecodedSubmessage>> {
     // Destructure header and footer
     let (SecurePrefix { crypto_header }, encoded_submessage, SecurePostfix { crypto_footer }) =
       encoded_rtps_submessage;
 
+    let header = BuiltinCryptoHeader::try_from(crypto_header)?;
     let BuiltinCryptoHeader {
       transform_identifier:
         BuiltinCryptoTransformIdentifier {
@@ -490,36 +730,48 @@ impl CryptoTransform for CryptographicBuiltin {
           transformation_key_id: header_key_id,
         },
       builtin_crypto_header_extra: BuiltinCryptoHeaderExtra(initialization_vector),
-    } = BuiltinCryptoHeader::try_from(crypto_header)?;
+    } = header;
 
     let BuiltinCryptoFooter {
       common_mac,
       receiver_specific_macs,
-    } = BuiltinCryptoFooter::try_from(crypto_footer)?;
+    } = self.receiver_crypto_footer(receiving_local_participant_crypto_handle, &crypto_footer)?;
 
-    // Search for matching key materials over endpoints registered to the sender
-    let sending_participant_endpoints = self
-      .participant_to_endpoint_info
-      .get(&sending_remote_participant_crypto_handle)
-      .ok_or_else(|| {
-        create_security_error_and_log!(
-          "Could not find registered entities for the sending_remote_participant_crypto_handle {}",
-          sending_remote_participant_crypto_handle
-        )
-      })?;
+    // Classify the submessage by the key id before doing any crypto work
+    let (datawriter_pairs, datareader_pairs) = match self
+      .secure_submessage_category(header_key_id, sending_remote_participant_crypto_handle)?
+    {
+      Some(SecureSubmessageCategory::Writer(pairs)) => (pairs, Vec::new()),
+      Some(SecureSubmessageCategory::Reader(pairs)) => (Vec::new(), pairs),
+      Some(SecureSubmessageCategory::Either { writer, reader }) => (writer, reader),
+      None => {
+        TransformCounters::increment(&self.transform_counters.missing_key_drops);
+        return Ok(DecodeOutcome::KeysNotFound(header_key_id));
+      }
+    };
 
-    let matching_decode_materials = sending_participant_endpoints
+    // The decode key materials of the remote endpoints, with their kind and
+    // matched local endpoint
+    let matching_decode_materials = datawriter_pairs
       .iter()
-      .filter_map(|sending_endpoint_info| {
-        self
-          .get_session_decode_crypto_materials(
-            sending_endpoint_info.crypto_handle,
-            header_key_id,
-            KeyMaterialScope::MessageOrSubmessage,
-            initialization_vector,
-          )
-          .map(|decode_materials| (decode_materials, sending_endpoint_info))
-      })
+      .map(|pair| (EndpointKind::DataWriter, pair))
+      .chain(
+        datareader_pairs
+          .iter()
+          .map(|pair| (EndpointKind::DataReader, pair)),
+      )
+      .filter_map(
+        |(kind, (local_endpoint_crypto_handle, remote_endpoint_crypto_handle))| {
+          self
+            .get_session_decode_crypto_materials(
+              *remote_endpoint_crypto_handle,
+              header_key_id,
+              KeyMaterialScope::MessageOrSubmessage,
+              initialization_vector,
+            )
+            .map(|decode_materials| (decode_materials, (kind, *local_endpoint_crypto_handle)))
+        },
+      )
       .collect::<Vec<_>>();
 
     let decode_key = matching_decode_materials
@@ -538,10 +790,11 @@ impl CryptoTransform for CryptographicBuiltin {
             Ok(session_key)
           } else {
             Err(create_security_error_and_log!(
+              SecurityErrorKind::UnsupportedTransformation,
               "Transformation kind of the submessage header does not match the key: expected \
-               {:?}, received {:?}.",
+               {:?}, received the header {:?}.",
               transformation_kind,
-              header_transformation_kind
+              header
             ))
           }
         },
@@ -554,6 +807,7 @@ impl CryptoTransform for CryptographicBuiltin {
               Ok(acc_session_key)
             } else {
               Err(create_security_error_and_log!(
+                SecurityErrorKind::Internal,
                 "Multiple different matching decode keys found for the key id {:?} for the remote \
                  participant {}",
                 header_key_id,
@@ -567,15 +821,15 @@ impl CryptoTransform for CryptographicBuiltin {
     let decode_key = match decode_key {
       Some(key_result) => key_result?,
       None => {
+        TransformCounters::increment(&self.transform_counters.missing_key_drops);
         return Ok(DecodeOutcome::KeysNotFound(header_key_id));
       }
     };
 
     let (decoded_submessage, sending_endpoint_infos) = match header_transformation_kind {
       BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE => {
-        // Does this even make sense?
-        warn!("Decode submessage success, but crypto transformation kind is none.");
-
+        // Our encoder sends unprotected submessages without a SecurePrefix, but
+        // a remote one may still wrap them. There is no MAC to check.
         let sending_endpoint_infos = matching_decode_materials
           .iter()
           .map(|(_, sending_endpoint_info)| sending_endpoint_info)
@@ -604,11 +858,18 @@ impl CryptoTransform for CryptographicBuiltin {
             })
             .collect::<Vec<_>>();
 
-          validate_mac(decode_key, initialization_vector, &data, common_mac)?; // return verify error here, or continue
+          // return verify error here, or continue
+          self.transform_counters.count_mac_failure(validate_mac(
+            decode_key,
+            initialization_vector,
+            &data,
+            common_mac,
+          ))?;
 
           (encoded_submessage.body, sending_endpoint_infos)
         } else {
           Err(create_security_error_and_log!(
+            SecurityErrorKind::MalformedToken,
             "Submessage bytes are missing."
           ))?
         }
@@ -642,20 +903,22 @@ impl CryptoTransform for CryptographicBuiltin {
             .collect::<Vec<_>>();
 
           // Authenticated decryption
-          let mut plaintext = Bytes::copy_from_slice(&decrypt(
-            decode_key,
-            initialization_vector,
-            &ciphertext,
-            common_mac,
+          let mut plaintext = Bytes::copy_from_slice(&self.transform_counters.count_mac_failure(
+            decrypt(decode_key, initialization_vector, &ciphertext, common_mac),
           )?);
 
           // Deserialize (submessage deserialization is a bit funky atm)
           let decoded_submessage =
             match Submessage::read_from_buffer(&mut plaintext).map_err(|e| {
-              create_security_error_and_log!("Failed to deserialize the plaintext: {}", e)
+              create_security_error_and_log!(
+                SecurityErrorKind::MalformedToken,
+                "Failed to deserialize the plaintext: {}",
+                e
+              )
             })? {
               Some(Submessage { body, .. }) => body,
               None => Err(create_security_error_and_log!(
+                SecurityErrorKind::MalformedToken,
                 "Failed to deserialize the plaintext into a submessage. It could have been PAD or \
                  vendor-specific or otherwise unrecognized submessage kind."
               ))?,
@@ -663,6 +926,7 @@ impl CryptoTransform for CryptographicBuiltin {
           (decoded_submessage, sending_endpoint_infos)
         } else {
           Err(create_security_error_and_log!(
+            SecurityErrorKind::MalformedToken,
             "When transformation kind is GCM, decode_datawriter_submessage expects a SecureBody, \
              received {:?}",
             encoded_submessage.header.kind
@@ -671,34 +935,38 @@ impl CryptoTransform for CryptographicBuiltin {
       }
     };
 
+    self.check_replay(
+      receiving_local_participant_crypto_handle,
+      sending_remote_participant_crypto_handle,
+      header_key_id,
+      initialization_vector,
+    )?;
+
+    // The local endpoints matched to those remote endpoints of the kind that
+    // passed the receiver-specific MAC check
+    let approved_local_endpoints = |sending_kind: EndpointKind| {
+      sending_endpoint_infos
+        .iter()
+        .filter(|(kind, _)| *kind == sending_kind)
+        .map(|(_, local_endpoint_crypto_handle)| *local_endpoint_crypto_handle)
+        .collect::<Vec<_>>()
+    };
+
     match decoded_submessage {
       SubmessageBody::Writer(writer_submessage) => {
-        let matching_readers = SecurityResult::<Vec<_>>::from_iter(
-          sending_endpoint_infos.iter().filter_map(
-            |EndpointInfo {
-               crypto_handle: remote_endpoint_crypto_handle,
-               kind,
-             }| match kind {
-              EndpointKind::DataWriter => Some(
-                self
-                  .matched_local_endpoint
-                  .get(remote_endpoint_crypto_handle)
-                  .copied()
-                  .ok_or_else(|| {
-                    create_security_error_and_log!(
-                      "The local reader matched to the remote writer crypto handle {} is missing.",
-                      remote_endpoint_crypto_handle
-                    )
-                  }),
-              ),
-              _ => None,
-            },
-          ),
-        )?;
+        if datawriter_pairs.is_empty() {
+          return Err(create_security_error_and_log!(
+            SecurityErrorKind::MalformedToken,
+            "Decoded a writer submessage protected with the key of a remote datareader."
+          ));
+        }
+        let matching_readers = approved_local_endpoints(EndpointKind::DataWriter);
         if matching_readers.is_empty() {
           // All remote writers failed the MAC check
+          TransformCounters::increment(&self.transform_counters.mac_failures);
           Ok(DecodeOutcome::ValidatingReceiverSpecificMACFailed)
         } else {
+          TransformCounters::increment(&self.transform_counters.decoded_submessages);
           Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
             writer_submessage,
             matching_readers,
@@ -706,32 +974,19 @@ impl CryptoTransform for CryptographicBuiltin {
         }
       }
       SubmessageBody::Reader(reader_submessage) => {
-        let matching_writers = SecurityResult::<Vec<_>>::from_iter(
-          sending_endpoint_infos.iter().filter_map(
-            |EndpointInfo {
-               crypto_handle: remote_endpoint_crypto_handle,
-               kind,
-             }| match kind {
-              EndpointKind::DataReader => Some(
-                self
-                  .matched_local_endpoint
-                  .get(remote_endpoint_crypto_handle)
-                  .copied()
-                  .ok_or_else(|| {
-                    create_security_error_and_log!(
-                      "The local writer matched to the remote reader crypto handle {} is missing.",
-                      remote_endpoint_crypto_handle
-                    )
-                  }),
-              ),
-              _ => None,
-            },
-          ),
-        )?;
+        if datareader_pairs.is_empty() {
+          return Err(create_security_error_and_log!(
+            SecurityErrorKind::MalformedToken,
+            "Decoded a reader submessage protected with the key of a remote datawriter."
+          ));
+        }
+        let matching_writers = approved_local_endpoints(EndpointKind::DataReader);
         if matching_writers.is_empty() {
           // All remote readers failed the MAC check
+          TransformCounters::increment(&self.transform_counters.mac_failures);
           Ok(DecodeOutcome::ValidatingReceiverSpecificMACFailed)
         } else {
+          TransformCounters::increment(&self.transform_counters.decoded_submessages);
           Ok(DecodeOutcome::Success(DecodedSubmessage::Reader(
             reader_submessage,
             matching_writers,
@@ -745,23 +1000,33 @@ impl CryptoTransform for CryptographicBuiltin {
            specification, but we accept for compatibility as we also accept unprotected \
            interpreter submessages."
         );
+        TransformCounters::increment(&self.transform_counters.decoded_submessages);
         Ok(DecodeOutcome::Success(DecodedSubmessage::Interpreter(
           interpreter_submessage,
         )))
       }
       SubmessageBody::Security(_) => Err(create_security_error_and_log!(
+        SecurityErrorKind::MalformedToken,
         "Security submessage after successful submessage decryption."
       )),
     }
   }
 
-  fn decode_serialized_payload(
+  fn decode_serialized_payload_unreported(
     &self,
     encoded_buffer: Vec<u8>,
     _inline_qos: ParameterList,
     _receiving_datareader_crypto_handle: DatareaderCryptoHandle,
     sending_datawriter_crypto_handle: DatawriterCryptoHandle,
   ) -> SecurityResult<Vec<u8>> {
+    // A writer without payload protection sends its payloads as they are
+    if self.decodes_without_protection(
+      sending_datawriter_crypto_handle,
+      KeyMaterialScope::PayloadOnly,
+    ) {
+      return Ok(encoded_buffer);
+    }
+
     // According to DDS Security spec v1.1 Section
     // "9.5.3.3.4.4 Result from encode_serialized_payload"
     // the incoming data buffer is either
@@ -782,18 +1047,39 @@ impl CryptoTransform for CryptographicBuiltin {
     // check length so that following split do not panic and subtract does not
     // underflow
     if encoded_buffer.len() < head_len + foot_len {
-      return Err(security_error("Encoded payload smaller than minimum size"));
+      return Err(security_error_of_kind(
+        SecurityErrorKind::MalformedToken,
+        "Encoded payload smaller than minimum size",
+      ));
     }
     let (header_bytes, content_and_footer_bytes) = encoded_buffer.split_at(head_len);
-    let (content_bytes, footer_bytes) =
-      content_and_footer_bytes.split_at(content_and_footer_bytes.len() - foot_len);
 
-    // Deserialize crypto header and footer
+    // DATA and DATA_FRAG pad the serialized payload to a multiple of 4 octets, so
+    // up to 3 zero octets may follow the CryptoFooter. These are the possible
+    // splits into content and footer, least padding first. There is always at
+    // least the one without padding, because of the length check above.
+    let content_and_footer_splits: Vec<(&[u8], &[u8])> = (0..4)
+      .filter_map(|padding| {
+        let content_len = content_and_footer_bytes
+          .len()
+          .checked_sub(foot_len + padding)?;
+        let (content_bytes, footer_and_padding) = content_and_footer_bytes.split_at(content_len);
+        let (footer_bytes, padding_bytes) = footer_and_padding.split_at(foot_len);
+        padding_bytes
+          .iter()
+          .all(|b| *b == 0)
+          .then_some((content_bytes, footer_bytes))
+      })
+      .collect();
+    let (content_bytes, footer_bytes) = content_and_footer_splits[0];
+
+    // Deserialize crypto header
 
     // .read_from_buffer() does not need endianness, because BuiltinCryptoHeader
     // only contains byte-oriented data, which is insensitive to endianness.
     let crypto_header = CryptoHeader::read_from_buffer(header_bytes)?;
 
+    let header = BuiltinCryptoHeader::try_from(crypto_header)?;
     let BuiltinCryptoHeader {
       transform_identifier:
         BuiltinCryptoTransformIdentifier {
@@ -801,24 +1087,28 @@ impl CryptoTransform for CryptographicBuiltin {
           transformation_key_id,
         },
       builtin_crypto_header_extra: BuiltinCryptoHeaderExtra(initialization_vector),
-    } = crypto_header.try_into()?;
-
-    let BuiltinCryptoFooter { common_mac, .. } = BuiltinCryptoFooter::try_from(footer_bytes)?;
+    } = header;
 
     // Get the payload decode key material
-    let decode_key_material = self.session_decode_crypto_materials(
-      sending_datawriter_crypto_handle,
-      transformation_key_id,
-      KeyMaterialScope::PayloadOnly,
-      initialization_vector,
-    )?;
+    let decode_key_material = self
+      .session_decode_crypto_materials(
+        sending_datawriter_crypto_handle,
+        transformation_key_id,
+        KeyMaterialScope::PayloadOnly,
+        initialization_vector,
+      )
+      .map_err(|e| {
+        TransformCounters::increment(&self.transform_counters.missing_key_drops);
+        e
+      })?;
 
     // Check that the transformation kind stays consistent
     if decode_key_material.transformation_kind != transformation_kind {
       return Err(create_security_error_and_log!(
-        "Mismatched transformation kinds: the decoded CryptoHeader has {:?}, but the key material \
+        SecurityErrorKind::UnsupportedTransformation,
+        "Mismatched transformation kinds: the decoded CryptoHeader is {:?}, but the key material \
          associated with the sending datawriter {} has {:?}.",
-        transformation_kind,
+        header,
         sending_datawriter_crypto_handle,
         decode_key_material.transformation_kind
       ));
@@ -827,23 +1117,1634 @@ impl CryptoTransform for CryptographicBuiltin {
     let decode_key = &decode_key_material.session_key;
 
     match transformation_kind {
+      // Only reached if the key material has kind NONE as well, so there is
+      // nothing to check
       BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE => {
-        Err(create_security_error_and_log!(
-          "Transformation kind NONE found in decode_serialized_payload. If the transformation \
-           kind is NONE, this method should not have been called."
-        ))
+        Ok(Vec::from(content_bytes))
       }
       BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC
       | BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC => {
-        validate_mac(decode_key, initialization_vector, content_bytes, common_mac)
-          // if validate_mac succeeds, then map result to content bytes
-          .map(|()| Vec::from(content_bytes))
+        // The plaintext has no length marker, so the split is the one whose MAC
+        // is valid. If there is none, report the error for the unpadded split.
+        let validate = |(content_bytes, footer_bytes): (&[u8], &[u8])| {
+          let BuiltinCryptoFooter { common_mac, .. } = BuiltinCryptoFooter::try_from(footer_bytes)?;
+          validate_mac(decode_key, initialization_vector, content_bytes, common_mac)
+            // if validate_mac succeeds, then map result to content bytes
+            .map(|()| Vec::from(content_bytes))
+        };
+        let result = match validate((content_bytes, footer_bytes)) {
+          Err(e) => content_and_footer_splits[1..]
+            .iter()
+            .find_map(|split| validate(*split).ok())
+            .ok_or(e),
+          ok => ok,
+        };
+        self.transform_counters.count_mac_failure(result)
       }
       BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM
       | BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM => {
+        // CryptoContent has a length marker, which tells where the footer starts.
         let ciphertext = CryptoContent::read_from_buffer(content_bytes)?.data;
-        decrypt(decode_key, initialization_vector, &ciphertext, common_mac)
+        let footer_bytes = content_and_footer_splits
+          .iter()
+          .find(|(content_bytes, _)| content_bytes.len() == 4 + ciphertext.len())
+          .map_or(footer_bytes, |(_, footer_bytes)| footer_bytes);
+        let BuiltinCryptoFooter { common_mac, .. } = BuiltinCryptoFooter::try_from(footer_bytes)?;
+        self.transform_counters.count_mac_failure(decrypt(
+          decode_key,
+          initialization_vector,
+          &ciphertext,
+          common_mac,
+        ))
       }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{Arc, RwLock},
+    thread,
+  };
+
+  use bytes::Bytes;
+  use enumflags2::BitFlags;
+  use speedy::{Readable, Writable};
+
+  use crate::{
+    messages::{
+      header::Header,
+      submessages::{
+        ack_nack::AckNack,
+        elements::{crypto_header::CryptoHeader, parameter_list::ParameterList},
+        secure_postfix::SecurePostfix,
+        secure_prefix::SecurePrefix,
+        submessage_flag::FromEndianness,
+        submessages::{
+          InterpreterSubmessage, ReaderSubmessage, SecuritySubmessage, WriterSubmessage,
+        },
+      },
+    },
+    rtps::{Message, Submessage, SubmessageBody},
+    security::{
+      access_control::{
+        access_control_builtin::types::{
+          BuiltinPluginEndpointSecurityAttributes, BuiltinPluginParticipantSecurityAttributes,
+        },
+        types::*,
+      },
+      cryptographic::cryptographic_builtin::test_fixtures::*,
+      Cryptographic,
+    },
+    serialization::round_up_to_4,
+    structure::{
+      clock::SimulatedClock,
+      duration::Duration,
+      guid::{EntityId, GuidPrefix},
+      sequence_number::{SequenceNumber, SequenceNumberSet},
+    },
+  };
+  use super::*;
+
+  #[test]
+  fn session_rolls_over_after_max_blocks() {
+    let payload_attributes = EndpointSecurityAttributes {
+      is_payload_protected: true,
+      plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: false,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: true,
+      }
+      .into(),
+      ..EndpointSecurityAttributes::empty()
+    };
+    let max_blocks_property = Property {
+      name: "dds.sec.crypto.maxblockspersession".to_string(),
+      value: "2".to_string(),
+      propagate: false,
+    };
+    let mut endpoints = MatchedEndpoints::with_properties(
+      payload_attributes,
+      MatchProperties {
+        writer: &[max_blocks_property],
+        ..MatchProperties::default()
+      },
+    );
+    endpoints.send_writer_tokens();
+    let MatchedEndpoints {
+      writer_side,
+      writer,
+      reader_side,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+
+    // One block each, so two payloads fit in a session
+    let payloads = [[1u8; 16], [2u8; 16], [3u8; 16]];
+    let encoded: Vec<_> = payloads
+      .iter()
+      .map(|payload| encode_payload(&writer_side, writer, payload))
+      .collect();
+    // The initialization vector is the session id followed by the suffix
+    let (session_ids, suffixes): (Vec<_>, Vec<_>) = encoded
+      .iter()
+      .map(|(_, iv)| {
+        let iv_bytes = <[u8; 12]>::from(*iv);
+        (
+          u32::from_be_bytes(iv_bytes[..4].try_into().unwrap()),
+          u64::from_be_bytes(iv_bytes[4..].try_into().unwrap()),
+        )
+      })
+      .unzip();
+
+    assert_eq!(session_ids[0], session_ids[1]);
+    assert_eq!(session_ids[2], session_ids[1].wrapping_add(1));
+    // Each session starts its suffix from a random value
+    assert_eq!(suffixes[1], suffixes[0] + 1);
+    assert_eq!(writer_side.crypto_statistics().sessions_rolled, 1);
+
+    // Payloads from both sessions decode
+    for ((encoded, _), payload) in encoded.into_iter().zip(payloads) {
+      assert_eq!(
+        reader_side
+          .decode_serialized_payload(encoded, ParameterList::new(), reader, remote_writer)
+          .unwrap(),
+        payload
+      );
+    }
+  }
+
+  #[test]
+  fn padded_payloads_decode() {
+    // DATA and DATA_FRAG pad the encoded payload to a multiple of 4 octets, so
+    // the CryptoFooter is not necessarily at the end of what the reader gets.
+    for is_payload_encrypted in [true, false] {
+      let payload_attributes = EndpointSecurityAttributes {
+        is_payload_protected: true,
+        plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+          is_submessage_encrypted: false,
+          is_submessage_origin_authenticated: false,
+          is_payload_encrypted,
+        }
+        .into(),
+        ..EndpointSecurityAttributes::empty()
+      };
+      let mut endpoints = MatchedEndpoints::new(payload_attributes);
+      endpoints.send_writer_tokens();
+
+      for payload_len in 13..=16 {
+        let payload = vec![0xa5u8; payload_len];
+        let (mut encoded, _) = encode_payload(&endpoints.writer_side, endpoints.writer, &payload);
+        encoded.resize(round_up_to_4(encoded.len()), 0);
+        assert_eq!(
+          endpoints
+            .reader_side
+            .decode_serialized_payload(
+              encoded,
+              ParameterList::new(),
+              endpoints.reader,
+              endpoints.remote_writer
+            )
+            .unwrap(),
+          payload,
+          "is_payload_encrypted={is_payload_encrypted} payload_len={payload_len}"
+        );
+      }
+    }
+  }
+
+  // Sends two heartbeats from a writer to a reader whose participant has the
+  // given properties, the first of them twice. Returns whether each of the
+  // three decodes succeeded.
+  fn decode_replayed_submessage(reader_participant_properties: &[Property]) -> Vec<bool> {
+    let submessage_attributes = EndpointSecurityAttributes {
+      is_submessage_protected: true,
+      plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: true,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: false,
+      }
+      .into(),
+      ..EndpointSecurityAttributes::empty()
+    };
+    let mut endpoints = MatchedEndpoints::with_properties(
+      submessage_attributes,
+      MatchProperties {
+        reader_participant: reader_participant_properties,
+        ..MatchProperties::default()
+      },
+    );
+    endpoints.send_writer_tokens();
+
+    // Each heartbeat is encoded with a new initialization vector
+    let first = endpoints.encode_heartbeat();
+    let second = endpoints.encode_heartbeat();
+
+    [first.clone(), first, second]
+      .into_iter()
+      .map(|encoded| {
+        matches!(
+          endpoints.reader_side.decode_submessage(
+            encoded,
+            endpoints.reader_participant,
+            endpoints.remote_writer_participant
+          ),
+          Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
+            WriterSubmessage::Heartbeat(..),
+            _
+          )))
+        )
+      })
+      .collect()
+  }
+
+  // The transformation kinds of the submessage and payload key materials
+  fn transformation_kinds(
+    key_materials: &KeyMaterial_AES_GCM_GMAC_seq,
+  ) -> Option<(
+    BuiltinCryptoTransformationKind,
+    BuiltinCryptoTransformationKind,
+  )> {
+    match key_materials {
+      KeyMaterial_AES_GCM_GMAC_seq::One(_) => None,
+      KeyMaterial_AES_GCM_GMAC_seq::Two(submessage, payload) => {
+        Some((submessage.transformation_kind, payload.transformation_kind))
+      }
+    }
+  }
+
+  #[test]
+  fn asymmetric_endpoint_protection_round_trips() {
+    // Submessages are only signed, payloads are encrypted
+    let asymmetric_attributes = EndpointSecurityAttributes {
+      is_submessage_protected: true,
+      is_payload_protected: true,
+      plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: false,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: true,
+      }
+      .into(),
+      ..EndpointSecurityAttributes::empty()
+    };
+    let expected_kinds = Some((
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC,
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+    ));
+
+    let mut endpoints = MatchedEndpoints::new(asymmetric_attributes);
+
+    // Like the writer, the reader has a key material for each scope
+    match &endpoints.reader_side.common_encode_key_materials[&endpoints.reader] {
+      CommonEncodeKeyMaterials::Some(key_materials) => {
+        assert_eq!(transformation_kinds(key_materials), expected_kinds);
+      }
+      CommonEncodeKeyMaterials::Volatile => panic!("the reader is not volatile"),
+    }
+
+    // Exchange the tokens both ways
+    endpoints.send_writer_tokens();
+    endpoints.send_reader_tokens();
+    let MatchedEndpoints {
+      writer_side,
+      writer_participant,
+      remote_reader_participant,
+      writer,
+      remote_reader,
+      reader_side,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+    assert_eq!(
+      transformation_kinds(&reader_side.decode_key_materials[&remote_writer]),
+      expected_kinds
+    );
+    assert_eq!(
+      transformation_kinds(&writer_side.decode_key_materials[&remote_reader]),
+      expected_kinds
+    );
+
+    // The payload is encrypted with the payload key
+    let payload = [7u8; 32];
+    let (encoded_payload, _) = encode_payload(&writer_side, writer, &payload);
+    assert!(!encoded_payload
+      .windows(payload.len())
+      .any(|window| window == payload));
+    assert_eq!(
+      reader_side
+        .decode_serialized_payload(encoded_payload, ParameterList::new(), reader, remote_writer)
+        .unwrap(),
+      payload
+    );
+
+    // A submessage of the reader is signed with the submessage key
+    let acknack = AckNack {
+      reader_id: EntityId::UNKNOWN,
+      writer_id: EntityId::UNKNOWN,
+      reader_sn_state: SequenceNumberSet::new_empty(SequenceNumber::new(1)),
+      count: 1,
+    }
+    .create_submessage(BitFlags::from_endianness(speedy::Endianness::BigEndian));
+    let (secure_prefix, body, secure_postfix) = encoded_parts(
+      &reader_side
+        .encode_datareader_submessage(acknack, reader, vec![remote_writer])
+        .unwrap(),
+    );
+    // The MAC of a signed submessage is validated against its received bytes
+    let mut bytes = Bytes::from(body.write_to_vec().unwrap());
+    let received_body = Submessage::read_from_buffer(&mut bytes).unwrap().unwrap();
+    let encoded = (secure_prefix, received_body, secure_postfix);
+    assert!(matches!(
+      writer_side.decode_submessage(encoded, writer_participant, remote_reader_participant),
+      Ok(DecodeOutcome::Success(DecodedSubmessage::Reader(
+        ReaderSubmessage::AckNack(..),
+        _
+      )))
+    ));
+  }
+
+  #[test]
+  fn tampered_submessages_are_counted() {
+    let signed_attributes = EndpointSecurityAttributes {
+      is_submessage_protected: true,
+      plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: false,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: false,
+      }
+      .into(),
+      ..EndpointSecurityAttributes::empty()
+    };
+
+    let mut endpoints = MatchedEndpoints::new(signed_attributes);
+    let (secure_prefix, body, secure_postfix) = endpoints.encode_heartbeat();
+    assert_eq!(
+      endpoints
+        .writer_side
+        .crypto_statistics()
+        .encoded_submessages,
+      1
+    );
+    let reader_participant = endpoints.reader_participant;
+    let remote_writer_participant = endpoints.remote_writer_participant;
+
+    // The MAC is validated against the received bytes, so change the count on
+    // the way
+    let body_bytes = body.write_to_vec().unwrap();
+    let mut tampered_bytes = body_bytes.clone();
+    *tampered_bytes.last_mut().unwrap() ^= 0x01;
+    let receive = |bytes: &[u8]| {
+      (
+        secure_prefix.clone(),
+        Submessage::read_from_buffer(&mut Bytes::copy_from_slice(bytes))
+          .unwrap()
+          .unwrap(),
+        secure_postfix.clone(),
+      )
+    };
+
+    // Before the key exchange the key is missing
+    assert!(matches!(
+      endpoints.reader_side.decode_submessage(
+        receive(&body_bytes),
+        reader_participant,
+        remote_writer_participant
+      ),
+      Ok(DecodeOutcome::KeysNotFound(_))
+    ));
+    endpoints.send_writer_tokens();
+    let reader_side = endpoints.reader_side;
+
+    assert_eq!(
+      reader_side
+        .decode_submessage(
+          receive(&tampered_bytes),
+          reader_participant,
+          remote_writer_participant
+        )
+        .err()
+        .map(|e| e.kind()),
+      Some(SecurityErrorKind::VerificationFailed)
+    );
+    assert!(matches!(
+      reader_side.decode_submessage(
+        receive(&body_bytes),
+        reader_participant,
+        remote_writer_participant
+      ),
+      Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(..)))
+    ));
+
+    assert_eq!(
+      reader_side.crypto_statistics(),
+      CryptoStatistics {
+        decoded_submessages: 1,
+        mac_failures: 1,
+        missing_key_drops: 1,
+        ..CryptoStatistics::default()
+      }
+    );
+  }
+
+  #[test]
+  fn secure_submessages_are_classified_by_key_id() {
+    let mut endpoints = MatchedEndpoints::new(protected_writer_attributes(false));
+    let (heartbeat_prefix, ..) = endpoints.encode_heartbeat();
+    let (acknack_prefix, ..) = encoded_parts(
+      &endpoints
+        .reader_side
+        .encode_datareader_submessage(
+          AckNack {
+            reader_id: EntityId::UNKNOWN,
+            writer_id: EntityId::UNKNOWN,
+            reader_sn_state: SequenceNumberSet::new_empty(SequenceNumber::new(1)),
+            count: 1,
+          }
+          .create_submessage(BitFlags::from_endianness(speedy::Endianness::BigEndian)),
+          endpoints.reader,
+          vec![endpoints.remote_writer],
+        )
+        .unwrap(),
+    );
+
+    // Before the key exchange the key ids are unknown, which may change later
+    assert_eq!(
+      endpoints
+        .reader_side
+        .preprocess_secure_submessage(
+          &heartbeat_prefix,
+          endpoints.reader_participant,
+          endpoints.remote_writer_participant
+        )
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::NotRegistered
+    );
+
+    endpoints.send_writer_tokens();
+    endpoints.send_reader_tokens();
+    let MatchedEndpoints {
+      writer_side,
+      writer_participant,
+      remote_reader_participant,
+      writer,
+      remote_reader,
+      reader_side,
+      reader_participant,
+      remote_writer_participant,
+      reader,
+      remote_writer,
+    } = endpoints;
+
+    // The key id tells the kind of the sender and the endpoints to decode with
+    assert_eq!(
+      reader_side
+        .preprocess_secure_submessage(
+          &heartbeat_prefix,
+          reader_participant,
+          remote_writer_participant
+        )
+        .unwrap(),
+      SecureSubmessageCategory::Writer(vec![(reader, remote_writer)])
+    );
+    assert_eq!(
+      writer_side
+        .preprocess_secure_submessage(
+          &acknack_prefix,
+          writer_participant,
+          remote_reader_participant
+        )
+        .unwrap(),
+      SecureSubmessageCategory::Reader(vec![(writer, remote_reader)])
+    );
+
+    // A key of another participant is unknown
+    assert_eq!(
+      writer_side
+        .preprocess_secure_submessage(
+          &heartbeat_prefix,
+          writer_participant,
+          remote_reader_participant
+        )
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::NotRegistered
+    );
+  }
+
+  #[test]
+  fn unprotected_writer_interoperates_with_unprotected_reader() {
+    // Neither the endpoints nor the participants have any protection, so all
+    // key materials have CRYPTO_TRANSFORMATION_KIND_NONE
+    let mut endpoints = MatchedEndpoints::new(EndpointSecurityAttributes::empty());
+    endpoints.send_writer_tokens();
+    let MatchedEndpoints {
+      mut writer_side,
+      writer_participant,
+      remote_reader_participant,
+      writer,
+      remote_reader,
+      mut reader_side,
+      reader_participant,
+      remote_writer_participant,
+      reader,
+      remote_writer,
+    } = endpoints;
+    let participant_tokens = writer_side
+      .create_local_participant_crypto_tokens(writer_participant, remote_reader_participant)
+      .unwrap();
+    reader_side
+      .set_remote_participant_crypto_tokens(
+        reader_participant,
+        remote_writer_participant,
+        participant_tokens,
+      )
+      .unwrap();
+
+    // The payload is neither wrapped nor changed
+    let payload = vec![7u8; 32];
+    let (encoded_payload, _) = writer_side
+      .encode_serialized_payload(payload.clone(), writer)
+      .unwrap();
+    assert_eq!(encoded_payload, payload);
+    assert_eq!(
+      reader_side
+        .decode_serialized_payload(encoded_payload, ParameterList::new(), reader, remote_writer)
+        .unwrap(),
+      payload
+    );
+
+    // The submessage gets no SecurePrefix or SecurePostfix
+    let heartbeat = heartbeat();
+    match writer_side
+      .encode_datawriter_submessage(heartbeat.clone(), writer, vec![remote_reader])
+      .unwrap()
+    {
+      EncodedSubmessage::Unencoded(submessage) => assert_eq!(submessage, heartbeat),
+      EncodedSubmessage::Encoded(..) => panic!("the heartbeat was encoded"),
+    }
+
+    // The RTPS message is sent and received as it is
+    let message = Message {
+      header: Header::new(GuidPrefix::UNKNOWN),
+      submessages: vec![heartbeat],
+    };
+    let encoded_message = writer_side
+      .encode_rtps_message(
+        message.clone(),
+        writer_participant,
+        vec![remote_reader_participant],
+      )
+      .unwrap();
+    assert_eq!(encoded_message.header, message.header);
+    assert_eq!(encoded_message.submessages, message.submessages);
+    match reader_side
+      .decode_rtps_message(
+        encoded_message,
+        reader_participant,
+        remote_writer_participant,
+      )
+      .unwrap()
+    {
+      DecodeOutcome::Success(decoded) => {
+        assert_eq!(decoded.header, message.header);
+        assert_eq!(decoded.submessages, message.submessages);
+      }
+      _ => panic!("the message was not passed through"),
+    }
+
+    writer_side.check_invariants();
+    reader_side.check_invariants();
+  }
+
+  fn rtps_protected_participant_attributes(
+    is_rtps_encrypted: bool,
+  ) -> ParticipantSecurityAttributes {
+    ParticipantSecurityAttributes {
+      is_rtps_protected: true,
+      plugin_participant_attributes: BuiltinPluginParticipantSecurityAttributes {
+        is_rtps_encrypted,
+        is_discovery_encrypted: false,
+        is_liveliness_encrypted: false,
+        is_rtps_origin_authenticated: true,
+        is_discovery_origin_authenticated: false,
+        is_liveliness_origin_authenticated: false,
+      }
+      .into(),
+      ..ParticipantSecurityAttributes::empty()
+    }
+  }
+
+  // Sends an RTPS message from one protected participant to the first of two
+  // remote ones, which both have the sender's key material. Returns whether
+  // each of them could decode it.
+  fn rtps_protected_message_round_trip(is_rtps_encrypted: bool) -> Vec<bool> {
+    let attributes = rtps_protected_participant_attributes(is_rtps_encrypted);
+    let mut sender = seeded_crypto(1);
+    let sender_participant = sender
+      .register_local_participant(0, 0, &[], attributes.clone())
+      .unwrap();
+    let receivers: Vec<_> = (0..2)
+      .map(|identity| {
+        let remote_receiver = sender
+          .register_matched_remote_participant(
+            sender_participant,
+            identity,
+            0,
+            shared_secret_handle(0x11),
+          )
+          .unwrap();
+        let mut receiver = seeded_crypto(2 + u64::from(identity));
+        let receiver_participant = receiver
+          .register_local_participant(0, 0, &[], attributes.clone())
+          .unwrap();
+        let remote_sender = receiver
+          .register_matched_remote_participant(
+            receiver_participant,
+            0,
+            0,
+            shared_secret_handle(0x11),
+          )
+          .unwrap();
+        let tokens = sender
+          .create_local_participant_crypto_tokens(sender_participant, remote_receiver)
+          .unwrap();
+        receiver
+          .set_remote_participant_crypto_tokens(receiver_participant, remote_sender, tokens)
+          .unwrap();
+        (
+          receiver,
+          receiver_participant,
+          remote_sender,
+          remote_receiver,
+        )
+      })
+      .collect();
+
+    let heartbeat = heartbeat();
+    let message = Message {
+      header: Header::new(GuidPrefix::new(&[0x42; 12])),
+      submessages: vec![heartbeat.clone()],
+    };
+    let encoded = sender
+      .encode_rtps_message(message.clone(), sender_participant, vec![receivers[0].3])
+      .unwrap();
+
+    // The whole message is wrapped, and only encryption hides the submessages
+    match encoded.submessages.as_slice() {
+      [Submessage {
+        body: SubmessageBody::Security(SecuritySubmessage::SecureRTPSPrefix(..)),
+        ..
+      }, content @ .., Submessage {
+        body: SubmessageBody::Security(SecuritySubmessage::SecureRTPSPostfix(postfix, _)),
+        ..
+      }] => {
+        let footer = BuiltinCryptoFooter::try_from(postfix.crypto_footer.clone()).unwrap();
+        assert_eq!(footer.receiver_specific_macs.len(), 1);
+        match content {
+          [Submessage {
+            body: SubmessageBody::Security(SecuritySubmessage::SecureBody(..)),
+            ..
+          }] => assert!(is_rtps_encrypted),
+          [Submessage {
+            body: SubmessageBody::Interpreter(InterpreterSubmessage::InfoSource(..)),
+            ..
+          }, submessage] => {
+            assert!(!is_rtps_encrypted);
+            assert_eq!(submessage, &heartbeat);
+          }
+          _ => panic!("unexpected protected content"),
+        }
+      }
+      _ => panic!("the message was not wrapped"),
+    }
+
+    // The MACs are validated against the received bytes
+    let received =
+      Message::read_from_buffer(&Bytes::from(encoded.write_to_vec().unwrap())).unwrap();
+    let decoded: Vec<bool> = receivers
+      .iter()
+      .map(|(receiver, receiver_participant, remote_sender, _)| {
+        match receiver
+          .decode_rtps_message(received.clone(), *receiver_participant, *remote_sender)
+          .unwrap()
+        {
+          DecodeOutcome::Success(decoded) => {
+            // The original header is restored from the InfoSource
+            assert_eq!(decoded.header, message.header);
+            match decoded.submessages.as_slice() {
+              [submessage] => assert_eq!(submessage.body, heartbeat.body),
+              _ => panic!("expected only the heartbeat"),
+            }
+            true
+          }
+          DecodeOutcome::ValidatingReceiverSpecificMACFailed => false,
+          _ => panic!("the message keys were not found"),
+        }
+      })
+      .collect();
+
+    sender.check_invariants();
+    for (receiver, ..) in &receivers {
+      receiver.check_invariants();
+    }
+    decoded
+  }
+
+  #[test]
+  fn rtps_messages_round_trip_with_gmac() {
+    assert_eq!(rtps_protected_message_round_trip(false), [true, false]);
+  }
+
+  #[test]
+  fn rtps_messages_round_trip_with_gcm() {
+    assert_eq!(rtps_protected_message_round_trip(true), [true, false]);
+  }
+
+  #[test]
+  fn replayed_submessages_are_rejected_when_configured() {
+    let reject_replays_property = Property {
+      name: "dds.sec.crypto.reject_replays".to_string(),
+      value: "true".to_string(),
+      propagate: false,
+    };
+    assert_eq!(
+      decode_replayed_submessage(&[reject_replays_property]),
+      [true, false, true]
+    );
+    // Replays are accepted by default
+    assert_eq!(decode_replayed_submessage(&[]), [true, true, true]);
+  }
+
+  #[test]
+  fn one_submessage_carries_a_mac_for_each_receiver() {
+    let mut writer_side = seeded_crypto(1);
+    let (participant, _) = register_participants(&mut writer_side, shared_secret_handle(0x11));
+    let writer = writer_side
+      .register_local_datawriter(participant, &[], protected_writer_attributes(true))
+      .unwrap();
+
+    // Four readers, each in a participant of its own
+    let readers: Vec<_> = (1..=4)
+      .map(|identity| {
+        let remote_participant = writer_side
+          .register_matched_remote_participant(participant, identity, 0, shared_secret_handle(0x11))
+          .unwrap();
+        let remote_reader = writer_side
+          .register_matched_remote_datareader(
+            writer,
+            remote_participant,
+            shared_secret_handle(0x11),
+            false,
+          )
+          .unwrap();
+        let tokens = writer_side
+          .create_local_datawriter_crypto_tokens(writer, remote_reader)
+          .unwrap();
+
+        let mut reader_side = seeded_crypto(1 + u64::from(identity));
+        let (reader_participant, remote_writer_participant) =
+          register_participants(&mut reader_side, shared_secret_handle(0x11));
+        let reader = reader_side
+          .register_local_datareader(reader_participant, &[], protected_writer_attributes(true))
+          .unwrap();
+        let remote_writer = reader_side
+          .register_matched_remote_datawriter(
+            reader,
+            remote_writer_participant,
+            shared_secret_handle(0x11),
+          )
+          .unwrap();
+        reader_side
+          .set_remote_datawriter_crypto_tokens(reader, remote_writer, tokens)
+          .unwrap();
+        (
+          remote_reader,
+          reader_side,
+          reader_participant,
+          remote_writer_participant,
+        )
+      })
+      .collect();
+
+    // The heartbeat is encoded once for the first three readers
+    let receivers = readers[..3]
+      .iter()
+      .map(|(remote_reader, ..)| *remote_reader)
+      .collect();
+    let encoded = encoded_parts(
+      &writer_side
+        .encode_datawriter_submessage(heartbeat(), writer, receivers)
+        .unwrap(),
+    );
+    let footer = BuiltinCryptoFooter::try_from(encoded.2.crypto_footer.clone()).unwrap();
+    assert_eq!(footer.receiver_specific_macs.len(), 3);
+
+    let decoded: Vec<bool> = readers
+      .iter()
+      .map(
+        |(_, reader_side, reader_participant, remote_writer_participant)| match reader_side
+          .decode_submessage(
+            encoded.clone(),
+            *reader_participant,
+            *remote_writer_participant,
+          ) {
+          Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
+            WriterSubmessage::Heartbeat(..),
+            _,
+          ))) => true,
+          Ok(DecodeOutcome::ValidatingReceiverSpecificMACFailed) => false,
+          _ => panic!("unexpected decode outcome"),
+        },
+      )
+      .collect();
+    assert_eq!(decoded, [true, true, true, false]);
+  }
+
+  #[test]
+  fn submessage_keys_are_found_by_key_id_among_many_remotes() {
+    const REMOTES: usize = 1000;
+    let attributes = protected_writer_attributes(false);
+
+    // A writer side with a writer for each remote writer of the reader side
+    let mut writer_side = seeded_crypto(1);
+    let (writer_participant, remote_reader_participant) =
+      register_participants(&mut writer_side, shared_secret_handle(0x11));
+    let mut reader_side = seeded_crypto(2);
+    let (reader_participant, _) =
+      register_participants(&mut reader_side, shared_secret_handle(0x11));
+    let reader = reader_side
+      .register_local_datareader(reader_participant, &[], attributes.clone())
+      .unwrap();
+
+    // (writer, remote reader, remote writer participant, remote writer)
+    let remotes: Vec<_> = (1..=REMOTES)
+      .map(|identity| {
+        let writer = writer_side
+          .register_local_datawriter(writer_participant, &[], attributes.clone())
+          .unwrap();
+        let remote_reader = writer_side
+          .register_matched_remote_datareader(
+            writer,
+            remote_reader_participant,
+            shared_secret_handle(0x11),
+            false,
+          )
+          .unwrap();
+        let remote_participant = reader_side
+          .register_matched_remote_participant(
+            reader_participant,
+            identity as IdentityHandle,
+            0,
+            shared_secret_handle(0x11),
+          )
+          .unwrap();
+        let remote_writer = reader_side
+          .register_matched_remote_datawriter(
+            reader,
+            remote_participant,
+            shared_secret_handle(0x11),
+          )
+          .unwrap();
+        let tokens = writer_side
+          .create_local_datawriter_crypto_tokens(writer, remote_reader)
+          .unwrap();
+        reader_side
+          .set_remote_datawriter_crypto_tokens(reader, remote_writer, tokens)
+          .unwrap();
+        (writer, remote_reader, remote_participant, remote_writer)
+      })
+      .collect();
+
+    for (_, _, _, remote_writer) in remotes.iter().step_by(2) {
+      reader_side.unregister_datawriter(*remote_writer).unwrap();
+    }
+    reader_side.check_invariants();
+
+    let decodes = |(writer, remote_reader, remote_participant, remote_writer): &(
+      DatawriterCryptoHandle,
+      DatareaderCryptoHandle,
+      ParticipantCryptoHandle,
+      DatawriterCryptoHandle,
+    )| {
+      let key_id = writer_side
+        .get_receiver_specific_encode_key_materials(remote_reader)
+        .unwrap()
+        .key_material()
+        .sender_key_id;
+      let indexed = reader_side
+        .decode_key_id_index
+        .get(&(*remote_participant, key_id))
+        .is_some_and(|crypto_handles| crypto_handles.contains(remote_writer));
+
+      let encoded = encoded_parts(
+        &writer_side
+          .encode_datawriter_submessage(heartbeat(), *writer, vec![*remote_reader])
+          .unwrap(),
+      );
+      let decoded =
+        match reader_side.decode_submessage(encoded, reader_participant, *remote_participant) {
+          Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
+            WriterSubmessage::Heartbeat(..),
+            readers,
+          ))) => readers == [reader],
+          _ => false,
+        };
+      assert_eq!(indexed, decoded);
+      decoded
+    };
+
+    let decoded: Vec<bool> = remotes.iter().map(decodes).collect();
+    assert_eq!(decoded, [false, true].repeat(REMOTES / 2));
+  }
+
+  #[test]
+  fn remote_participants_may_use_the_same_key_id() {
+    let (mut reader_side, _, reader, remote_participants) =
+      local_endpoints_and_remote_participants(3, false, 2);
+    let reader_participant = reader_side.endpoint_to_participant[&reader];
+
+    // Two remote datawriters with different keys, but the same key id
+    let mut shared_key_id = None;
+    let writers: Vec<_> = remote_participants
+      .iter()
+      .zip(1u8..)
+      .map(|(remote_participant, identity)| {
+        let mut writer_side = seeded_crypto(identity.into());
+        let (writer_participant, remote_reader_participant) =
+          register_participants(&mut writer_side, shared_secret_handle(identity));
+        let writer = writer_side
+          .register_local_datawriter(writer_participant, &[], protected_writer_attributes(false))
+          .unwrap();
+        let remote_reader = writer_side
+          .register_matched_remote_datareader(
+            writer,
+            remote_reader_participant,
+            shared_secret_handle(identity),
+            false,
+          )
+          .unwrap();
+        let key_id = *shared_key_id.get_or_insert(
+          writer_side
+            .get_receiver_specific_encode_key_materials(&remote_reader)
+            .unwrap()
+            .key_material()
+            .sender_key_id,
+        );
+        let with_key_id = |key_materials: &KeyMaterial_AES_GCM_GMAC_seq| {
+          Arc::new(key_materials.clone().modify_key_material(|key_material| {
+            KeyMaterial_AES_GCM_GMAC {
+              sender_key_id: key_id,
+              ..key_material
+            }
+          }))
+        };
+        let common = match &writer_side.common_encode_key_materials[&writer] {
+          CommonEncodeKeyMaterials::Some(key_materials) => with_key_id(key_materials),
+          CommonEncodeKeyMaterials::Volatile => panic!("the datawriter is not volatile"),
+        };
+        writer_side
+          .common_encode_key_materials
+          .insert(writer, CommonEncodeKeyMaterials::Some(common));
+        let receiver_specific =
+          with_key_id(&writer_side.receiver_specific_encode_key_materials[&remote_reader]);
+        writer_side
+          .receiver_specific_encode_key_materials
+          .insert(remote_reader, receiver_specific);
+
+        let remote_writer = reader_side
+          .register_matched_remote_datawriter(
+            reader,
+            *remote_participant,
+            shared_secret_handle(identity),
+          )
+          .unwrap();
+        let tokens = writer_side
+          .create_local_datawriter_crypto_tokens(writer, remote_reader)
+          .unwrap();
+        reader_side
+          .set_remote_datawriter_crypto_tokens(reader, remote_writer, tokens)
+          .unwrap();
+        (writer_side, writer, remote_reader, *remote_participant)
+      })
+      .collect();
+    assert_ne!(
+      writers[0]
+        .0
+        .get_receiver_specific_encode_key_materials(&writers[0].2)
+        .unwrap()
+        .key_material()
+        .master_sender_key,
+      writers[1]
+        .0
+        .get_receiver_specific_encode_key_materials(&writers[1].2)
+        .unwrap()
+        .key_material()
+        .master_sender_key
+    );
+    reader_side.check_invariants();
+
+    for (writer_side, writer, remote_reader, remote_participant) in &writers {
+      let encoded = encoded_parts(
+        &writer_side
+          .encode_datawriter_submessage(heartbeat(), *writer, vec![*remote_reader])
+          .unwrap(),
+      );
+      assert_eq!(
+        BuiltinCryptoHeader::try_from(encoded.0.crypto_header.clone())
+          .unwrap()
+          .transform_identifier
+          .transformation_key_id,
+        shared_key_id.unwrap()
+      );
+
+      // Each decodes with the key of its own sender
+      assert!(matches!(
+        reader_side.decode_submessage(encoded.clone(), reader_participant, *remote_participant),
+        Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
+          WriterSubmessage::Heartbeat(..),
+          readers,
+        ))) if readers == [reader]
+      ));
+      // But not as if from the other sender
+      let other_participant = remote_participants
+        .iter()
+        .find(|participant| *participant != remote_participant)
+        .unwrap();
+      assert!(!matches!(
+        reader_side.decode_submessage(encoded, reader_participant, *other_participant),
+        Ok(DecodeOutcome::Success(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn replaced_keys_decode_until_the_grace_period_passes() {
+    let clock = SimulatedClock::start();
+    let grace_period_property = Property {
+      name: "dds.sec.crypto.rekey_grace_period".to_string(),
+      value: "5".to_string(),
+      propagate: false,
+    };
+
+    let mut endpoints = MatchedEndpoints::with_properties(
+      protected_writer_attributes(true),
+      MatchProperties {
+        reader_participant: &[grace_period_property],
+        ..MatchProperties::default()
+      },
+    );
+    endpoints.send_writer_tokens();
+    let (writer, remote_reader) = (endpoints.writer, endpoints.remote_reader);
+    let old_key_ids = common_key_ids(&endpoints.writer_side, &[(writer, remote_reader)]);
+    let encoded_with_old_keys = endpoints.encode_heartbeat();
+
+    let writer_side = &mut endpoints.writer_side;
+    writer_side.regenerate_local_endpoint_keys(writer).unwrap();
+    assert_eq!(
+      writer_side.take_rekeyed_endpoints(),
+      [(writer, remote_reader)]
+    );
+    assert!(writer_side.take_rekeyed_endpoints().is_empty());
+    let new_key_ids = common_key_ids(writer_side, &[(writer, remote_reader)]);
+    assert!(old_key_ids.is_disjoint(&new_key_ids));
+    // Origin authentication is kept
+    assert!(
+      !writer_side.receiver_specific_encode_key_materials[&remote_reader]
+        .key_material()
+        .receiver_specific_key_id
+        .is_zero()
+    );
+    endpoints.send_writer_tokens();
+    let encoded_with_new_keys = endpoints.encode_heartbeat();
+    endpoints.writer_side.check_invariants();
+    endpoints.reader_side.check_invariants();
+
+    let decodes = |encoded: &(SecurePrefix, Submessage, SecurePostfix)| {
+      matches!(
+        endpoints.reader_side.decode_submessage(
+          encoded.clone(),
+          endpoints.reader_participant,
+          endpoints.remote_writer_participant
+        ),
+        Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(
+          WriterSubmessage::Heartbeat(..),
+          _
+        )))
+      )
+    };
+    clock.advance(Duration::from_secs(4));
+    assert!(decodes(&encoded_with_old_keys));
+    assert!(decodes(&encoded_with_new_keys));
+
+    clock.advance(Duration::from_secs(2));
+    assert!(!decodes(&encoded_with_old_keys));
+    assert!(decodes(&encoded_with_new_keys));
+  }
+
+  // A local writer and reader protecting their payloads, matched with a remote
+  // reader and writer. Returns (writer, remote reader, reader, remote writer).
+  fn register_payload_protected_endpoints(
+    crypto: &mut CryptographicBuiltin,
+  ) -> (
+    DatawriterCryptoHandle,
+    DatareaderCryptoHandle,
+    DatareaderCryptoHandle,
+    DatawriterCryptoHandle,
+  ) {
+    let payload_attributes = EndpointSecurityAttributes {
+      is_payload_protected: true,
+      plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: false,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: true,
+      }
+      .into(),
+      ..EndpointSecurityAttributes::empty()
+    };
+    let (participant, remote_participant) =
+      register_participants(crypto, shared_secret_handle(0x11));
+    let writer = crypto
+      .register_local_datawriter(participant, &[], payload_attributes.clone())
+      .unwrap();
+    let remote_reader = crypto
+      .register_matched_remote_datareader(
+        writer,
+        remote_participant,
+        shared_secret_handle(0x11),
+        false,
+      )
+      .unwrap();
+    let reader = crypto
+      .register_local_datareader(participant, &[], payload_attributes)
+      .unwrap();
+    let remote_writer = crypto
+      .register_matched_remote_datawriter(reader, remote_participant, shared_secret_handle(0x11))
+      .unwrap();
+    (writer, remote_reader, reader, remote_writer)
+  }
+
+  #[test]
+  fn encoding_and_decoding_run_concurrently_with_registration() {
+    const PAYLOADS: u8 = 50;
+
+    let mut crypto = seeded_crypto(1);
+    let (writer, remote_reader, reader, remote_writer) =
+      register_payload_protected_endpoints(&mut crypto);
+    let mut peer = seeded_crypto(2);
+    let (peer_writer, peer_remote_reader, peer_reader, peer_remote_writer) =
+      register_payload_protected_endpoints(&mut peer);
+
+    let tokens = crypto
+      .create_local_datawriter_crypto_tokens(writer, remote_reader)
+      .unwrap();
+    peer
+      .set_remote_datawriter_crypto_tokens(peer_reader, peer_remote_writer, tokens)
+      .unwrap();
+    let peer_tokens = peer
+      .create_local_datawriter_crypto_tokens(peer_writer, peer_remote_reader)
+      .unwrap();
+    crypto
+      .set_remote_datawriter_crypto_tokens(reader, remote_writer, peer_tokens)
+      .unwrap();
+
+    let peer_payloads: Vec<Vec<u8>> = (0..PAYLOADS)
+      .map(|i| encode_payload(&peer, peer_writer, &[i; 16]).0)
+      .collect();
+
+    // Encoding and decoding share the plugin, registration needs it exclusively
+    let crypto = RwLock::new(crypto);
+    let (encoded_payloads, decoded_payloads) = thread::scope(|scope| {
+      let encoder = scope.spawn(|| {
+        (0..PAYLOADS)
+          .map(|i| encode_payload(&crypto.read().unwrap(), writer, &[i; 16]).0)
+          .collect::<Vec<_>>()
+      });
+      let decoder = scope.spawn(|| {
+        peer_payloads
+          .into_iter()
+          .map(|encoded| {
+            crypto
+              .read()
+              .unwrap()
+              .decode_serialized_payload(encoded, ParameterList::new(), reader, remote_writer)
+              .unwrap()
+          })
+          .collect::<Vec<_>>()
+      });
+      scope.spawn(|| {
+        for _ in 0..PAYLOADS {
+          register_payload_protected_endpoints(&mut crypto.write().unwrap());
+        }
+      });
+      (encoder.join().unwrap(), decoder.join().unwrap())
+    });
+
+    for (i, decoded) in decoded_payloads.into_iter().enumerate() {
+      assert_eq!(decoded, [i as u8; 16]);
+    }
+    for (i, encoded) in encoded_payloads.into_iter().enumerate() {
+      assert_eq!(
+        peer
+          .decode_serialized_payload(
+            encoded,
+            ParameterList::new(),
+            peer_reader,
+            peer_remote_writer
+          )
+          .unwrap(),
+        [i as u8; 16]
+      );
+    }
+  }
+
+  #[derive(Default)]
+  struct RecordingListener {
+    events: Mutex<Vec<CryptoSecurityEvent>>,
+  }
+
+  impl SecurityEventListener for RecordingListener {
+    fn on_crypto_event(&self, event: CryptoSecurityEvent) {
+      self.events.lock().unwrap().push(event);
+    }
+  }
+
+  impl RecordingListener {
+    fn take(&self) -> Vec<(CryptoSecurityEventKind, CryptoOperation, CryptoHandle)> {
+      self
+        .events
+        .lock()
+        .unwrap()
+        .drain(..)
+        .map(|event| (event.kind, event.operation, event.remote_crypto_handle))
+        .collect()
+    }
+  }
+
+  #[test]
+  fn rejections_are_reported_to_the_security_event_listener() {
+    let mut endpoints = MatchedEndpoints::new(protected_writer_attributes(false));
+    let listener = Arc::new(RecordingListener::default());
+    endpoints
+      .reader_side
+      .set_security_event_listener(listener.clone())
+      .unwrap();
+    let (secure_prefix, body, secure_postfix) = endpoints.encode_heartbeat();
+    let reader_participant = endpoints.reader_participant;
+    let remote_writer_participant = endpoints.remote_writer_participant;
+    // Flip a bit of the ciphertext on the way
+    let body_bytes = body.write_to_vec().unwrap();
+    let mut tampered_bytes = body_bytes.clone();
+    *tampered_bytes.last_mut().unwrap() ^= 0x01;
+    let receive = |bytes: &[u8]| {
+      (
+        secure_prefix.clone(),
+        Submessage::read_from_buffer(&mut Bytes::copy_from_slice(bytes))
+          .unwrap()
+          .unwrap(),
+        secure_postfix.clone(),
+      )
+    };
+
+    // Before the key exchange the key is unknown
+    endpoints
+      .reader_side
+      .decode_submessage(
+        receive(&body_bytes),
+        reader_participant,
+        remote_writer_participant,
+      )
+      .unwrap();
+    assert_eq!(
+      listener.take(),
+      vec![(
+        CryptoSecurityEventKind::UnknownKey,
+        CryptoOperation::DecodeSubmessage,
+        remote_writer_participant
+      )]
+    );
+
+    let tokens_error = endpoints
+      .reader_side
+      .set_remote_datawriter_crypto_tokens(endpoints.reader, endpoints.remote_writer, Vec::new())
+      .unwrap_err();
+    assert_eq!(
+      listener.take(),
+      vec![(
+        tokens_error.kind().into(),
+        CryptoOperation::SetCryptoTokens,
+        endpoints.remote_writer
+      )]
+    );
+    endpoints.send_writer_tokens();
+
+    assert!(endpoints
+      .reader_side
+      .decode_submessage(
+        receive(&tampered_bytes),
+        reader_participant,
+        remote_writer_participant
+      )
+      .is_err());
+    assert_eq!(
+      listener.take(),
+      vec![(
+        CryptoSecurityEventKind::VerificationFailed,
+        CryptoOperation::DecodeSubmessage,
+        remote_writer_participant
+      )]
+    );
+
+    // Nothing to report of data that is accepted
+    assert!(matches!(
+      endpoints.reader_side.decode_submessage(
+        receive(&body_bytes),
+        reader_participant,
+        remote_writer_participant
+      ),
+      Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(..)))
+    ));
+    assert!(listener.take().is_empty());
+  }
+
+  #[test]
+  fn datawriter_may_use_a_distinct_payload_key() {
+    let reuse_property = |value: &str| Property {
+      name: "dds.sec.crypto.reuse_submessage_key_for_payload".to_string(),
+      value: value.to_string(),
+      propagate: false,
+    };
+
+    for (writer_properties, reuses_key) in [
+      (vec![], true),
+      (vec![reuse_property("true")], true),
+      (vec![reuse_property("false")], false),
+    ] {
+      let mut endpoints = MatchedEndpoints::with_properties(
+        protected_writer_attributes(false),
+        MatchProperties {
+          writer: &writer_properties,
+          ..MatchProperties::default()
+        },
+      );
+      endpoints.send_writer_tokens();
+      let MatchedEndpoints {
+        writer_side,
+        writer,
+        reader_side,
+        reader,
+        remote_writer,
+        ..
+      } = endpoints;
+
+      let key_materials = match writer_side
+        .get_common_encode_key_materials(&writer)
+        .unwrap()
+      {
+        CommonEncodeKeyMaterials::Some(key_materials) => Arc::clone(key_materials),
+        CommonEncodeKeyMaterials::Volatile => panic!("the datawriter is not volatile"),
+      };
+      assert_eq!(
+        matches!(*key_materials, KeyMaterial_AES_GCM_GMAC_seq::One(_)),
+        reuses_key
+      );
+
+      // The payload is protected with the payload key
+      let payload = vec![7u8; 32];
+      let (encoded_payload, _) = writer_side
+        .encode_serialized_payload(payload.clone(), writer)
+        .unwrap();
+      let header_key_id = BuiltinCryptoHeader::try_from(
+        CryptoHeader::read_from_buffer(&encoded_payload[..BuiltinCryptoHeader::serialized_len()])
+          .unwrap(),
+      )
+      .unwrap()
+      .transform_identifier
+      .transformation_key_id;
+      assert_eq!(
+        header_key_id,
+        key_materials
+          .select(KeyMaterialScope::PayloadOnly)
+          .sender_key_id
+      );
+      assert_eq!(
+        header_key_id == key_materials.key_material().sender_key_id,
+        reuses_key
+      );
+      assert_eq!(
+        reader_side
+          .decode_serialized_payload(encoded_payload, ParameterList::new(), reader, remote_writer)
+          .unwrap(),
+        payload
+      );
+    }
+
+    let mut crypto = seeded_crypto(1);
+    let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0x11));
+    assert!(crypto
+      .register_local_datawriter(
+        participant,
+        &[reuse_property("sometimes")],
+        protected_writer_attributes(false),
+      )
+      .is_err());
+  }
+
+  #[test]
+  fn datawriter_may_upgrade_its_payload_protection() {
+    let mut signed_payload_attributes = protected_writer_attributes(false);
+    signed_payload_attributes.plugin_endpoint_attributes =
+      BuiltinPluginEndpointSecurityAttributes {
+        is_submessage_encrypted: true,
+        is_submessage_origin_authenticated: false,
+        is_payload_encrypted: false,
+      }
+      .into();
+    let encrypt_payload = [Property {
+      name: "dds.sec.crypto.payload_protection_kind".to_string(),
+      value: "ENCRYPT".to_string(),
+      propagate: false,
+    }];
+
+    // A datareader that requires only signing accepts the stronger protection
+    let mut endpoints = MatchedEndpoints::with_properties(
+      signed_payload_attributes,
+      MatchProperties {
+        writer: &encrypt_payload,
+        ..MatchProperties::default()
+      },
+    );
+    match endpoints
+      .writer_side
+      .get_common_encode_key_materials(&endpoints.writer)
+      .unwrap()
+    {
+      CommonEncodeKeyMaterials::Some(key_materials) => assert_eq!(
+        key_materials
+          .select(KeyMaterialScope::PayloadOnly)
+          .transformation_kind,
+        BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM
+      ),
+      CommonEncodeKeyMaterials::Volatile => panic!("the datawriter is not volatile"),
+    }
+    endpoints.send_writer_tokens();
+    let MatchedEndpoints {
+      writer_side,
+      writer,
+      reader_side,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+
+    let payload = vec![7u8; 32];
+    let (encoded_payload, _) = writer_side
+      .encode_serialized_payload(payload.clone(), writer)
+      .unwrap();
+    assert!(!encoded_payload
+      .windows(payload.len())
+      .any(|window| window == payload));
+    assert_eq!(
+      reader_side
+        .decode_serialized_payload(encoded_payload, ParameterList::new(), reader, remote_writer)
+        .unwrap(),
+      payload
+    );
+  }
+
+  #[test]
+  fn relay_only_datareader_can_validate_but_not_decrypt() {
+    let distinct_payload_key = [Property {
+      name: "dds.sec.crypto.reuse_submessage_key_for_payload".to_string(),
+      value: "false".to_string(),
+      propagate: false,
+    }];
+
+    let mut writer_side = seeded_crypto(1);
+    let (writer_participant, remote_reader_participant) =
+      register_participants(&mut writer_side, shared_secret_handle(0x11));
+
+    // With a single key for submessages and payloads there is nothing to leave out
+    let shared_key_writer = writer_side
+      .register_local_datawriter(writer_participant, &[], protected_writer_attributes(true))
+      .unwrap();
+    assert!(writer_side
+      .register_matched_remote_datareader(
+        shared_key_writer,
+        remote_reader_participant,
+        shared_secret_handle(0x11),
+        true,
+      )
+      .is_err());
+
+    let writer = writer_side
+      .register_local_datawriter(
+        writer_participant,
+        &distinct_payload_key,
+        protected_writer_attributes(true),
+      )
+      .unwrap();
+    let relay = writer_side
+      .register_matched_remote_datareader(
+        writer,
+        remote_reader_participant,
+        shared_secret_handle(0x11),
+        true,
+      )
+      .unwrap();
+    assert!(matches!(
+      **writer_side
+        .get_receiver_specific_encode_key_materials(&relay)
+        .unwrap(),
+      KeyMaterial_AES_GCM_GMAC_seq::One(_)
+    ));
+
+    let mut reader_side = seeded_crypto(2);
+    let (reader_participant, remote_writer_participant) =
+      register_participants(&mut reader_side, shared_secret_handle(0x11));
+    let reader = reader_side
+      .register_local_datareader(reader_participant, &[], protected_writer_attributes(true))
+      .unwrap();
+    let remote_writer = reader_side
+      .register_matched_remote_datawriter(
+        reader,
+        remote_writer_participant,
+        shared_secret_handle(0x11),
+      )
+      .unwrap();
+    let writer_tokens = writer_side
+      .create_local_datawriter_crypto_tokens(writer, relay)
+      .unwrap();
+    assert_eq!(writer_tokens.len(), 1);
+    reader_side
+      .set_remote_datawriter_crypto_tokens(reader, remote_writer, writer_tokens)
+      .unwrap();
+
+    // The submessages of the datawriter can be validated
+    let encoded = encoded_parts(
+      &writer_side
+        .encode_datawriter_submessage(heartbeat(), writer, vec![relay])
+        .unwrap(),
+    );
+    assert!(matches!(
+      reader_side.decode_submessage(encoded, reader_participant, remote_writer_participant),
+      Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(..)))
+    ));
+
+    // But its payloads cannot be decrypted
+    let (encoded_payload, _) = writer_side
+      .encode_serialized_payload(vec![7u8; 32], writer)
+      .unwrap();
+    assert!(reader_side
+      .decode_serialized_payload(encoded_payload, ParameterList::new(), reader, remote_writer)
+      .is_err());
+  }
+
+  #[test]
+  fn decoding_does_not_use_encode_key_materials() {
+    let mut endpoints = MatchedEndpoints::new(protected_writer_attributes(true));
+    endpoints.send_writer_tokens();
+    let (secure_prefix, body, secure_postfix) = endpoints.encode_heartbeat();
+    let MatchedEndpoints {
+      writer_side,
+      writer,
+      mut reader_side,
+      reader_participant,
+      remote_writer_participant,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+    let payload = vec![7u8; 32];
+    let (encoded_payload, _) = writer_side
+      .encode_serialized_payload(payload.clone(), writer)
+      .unwrap();
+
+    // Decoding relies on the key materials received in the crypto tokens only
+    reader_side.common_encode_key_materials.clear();
+    reader_side.receiver_specific_encode_key_materials.clear();
+    reader_side.encode_sessions.lock().unwrap().clear();
+
+    let received_body =
+      Submessage::read_from_buffer(&mut Bytes::from(body.write_to_vec().unwrap()))
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+      reader_side.decode_submessage(
+        (secure_prefix, received_body, secure_postfix),
+        reader_participant,
+        remote_writer_participant
+      ),
+      Ok(DecodeOutcome::Success(DecodedSubmessage::Writer(_, readers))) if readers == vec![reader]
+    ));
+    assert_eq!(
+      reader_side
+        .decode_serialized_payload(encoded_payload, ParameterList::new(), reader, remote_writer)
+        .unwrap(),
+      payload
+    );
+  }
+}
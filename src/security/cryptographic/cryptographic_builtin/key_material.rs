@@ -1,22 +1,24 @@
+use std::sync::Arc;
+
 use byteorder::BigEndian;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
 use crate::{
   create_security_error_and_log,
-  security::{SecurityError, SecurityResult},
+  security::{SecurityError, SecurityErrorKind, SecurityResult},
   serialization::to_vec,
   CdrDeserializer,
 };
 use super::{
   builtin_key::*, BuiltinCryptoToken, BuiltinCryptoTransformationKind, CryptoToken,
-  CryptoTransformKeyId, CryptoTransformKind,
+  CryptoTransformKeyId, CryptoTransformKind, TokenKeyMaterial,
 };
 
 /// KeyMaterial_AES_GCM_GMAC type from section 9.5.2.1.1 of the Security
 /// specification (v. 1.1)
 #[allow(non_camel_case_types)] // We use the name from the spec
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub(super) struct KeyMaterial_AES_GCM_GMAC {
   pub transformation_kind: BuiltinCryptoTransformationKind,
   pub master_salt: BuiltinKey, // Salt length should match the keys by 9.5.3.3.2
@@ -38,6 +40,7 @@ impl TryFrom<Bytes> for KeyMaterial_AES_GCM_GMAC {
       // Map deserialization error to SecurityError
       |e| Self::Error {
         msg: format!("Error deserializing KeyMaterial_AES_GCM_GMAC: {}", e),
+        kind: SecurityErrorKind::MalformedToken,
       },
     )
     .and_then(KeyMaterial_AES_GCM_GMAC::try_from)
@@ -53,6 +56,7 @@ impl TryFrom<KeyMaterial_AES_GCM_GMAC> for Bytes {
       .map(Bytes::from)
       .map_err(|e| Self::Error {
         msg: format!("Error serializing KeyMaterial_AES_GCM_GMAC: {}", e),
+        kind: SecurityErrorKind::Internal,
       })
   }
 }
@@ -61,7 +65,13 @@ impl TryFrom<KeyMaterial_AES_GCM_GMAC> for Bytes {
 impl TryFrom<CryptoToken> for KeyMaterial_AES_GCM_GMAC {
   type Error = SecurityError;
   fn try_from(token: CryptoToken) -> Result<Self, Self::Error> {
-    BuiltinCryptoToken::try_from(token).map(KeyMaterial_AES_GCM_GMAC::from)
+    match BuiltinCryptoToken::try_from(token)?.key_material {
+      TokenKeyMaterial::KeyMaterial(key_material) => Ok(key_material),
+      TokenKeyMaterial::WrappedKeyMaterial(_) => Err(create_security_error_and_log!(
+        SecurityErrorKind::MalformedToken,
+        "The key material of the CryptoToken is wrapped"
+      )),
+    }
   }
 }
 impl TryFrom<KeyMaterial_AES_GCM_GMAC> for CryptoToken {
@@ -77,7 +87,7 @@ impl TryFrom<KeyMaterial_AES_GCM_GMAC> for CryptoToken {
 /// In case of Two, the first one is for metadata(headers) and payload and
 /// the second for payload only.
 #[allow(non_camel_case_types)] // We use the name from the spec
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub(super) enum KeyMaterial_AES_GCM_GMAC_seq {
   One(KeyMaterial_AES_GCM_GMAC),
   Two(KeyMaterial_AES_GCM_GMAC, KeyMaterial_AES_GCM_GMAC),
@@ -118,26 +128,37 @@ impl KeyMaterial_AES_GCM_GMAC_seq {
     }
   }
 
+  // Returns the key materials with the given receiver-specific key. Stored key
+  // materials are shared, so this makes a new copy only if the key differs
+  // from the one already in place.
   pub fn add_master_receiver_specific_key(
-    self,
+    self: &Arc<Self>,
     receiver_specific_key_id: CryptoTransformKeyId,
     master_receiver_specific_key: BuiltinKey,
-  ) -> KeyMaterial_AES_GCM_GMAC_seq {
-    self.modify_key_material(
-      |KeyMaterial_AES_GCM_GMAC {
-         transformation_kind,
-         master_salt,
-         master_sender_key,
-         sender_key_id,
-         ..
-       }| KeyMaterial_AES_GCM_GMAC {
-        transformation_kind,
-        master_salt,
-        master_sender_key,
-        sender_key_id,
-        receiver_specific_key_id,
-        master_receiver_specific_key,
-      },
+  ) -> Arc<KeyMaterial_AES_GCM_GMAC_seq> {
+    let key_material = self.key_material();
+    if key_material.receiver_specific_key_id == receiver_specific_key_id
+      && key_material.master_receiver_specific_key == master_receiver_specific_key
+    {
+      return Arc::clone(self);
+    }
+    Arc::new(
+      KeyMaterial_AES_GCM_GMAC_seq::clone(self).modify_key_material(
+        |KeyMaterial_AES_GCM_GMAC {
+           transformation_kind,
+           master_salt,
+           master_sender_key,
+           sender_key_id,
+           ..
+         }| KeyMaterial_AES_GCM_GMAC {
+          transformation_kind,
+          master_salt,
+          master_sender_key,
+          sender_key_id,
+          receiver_specific_key_id,
+          master_receiver_specific_key,
+        },
+      ),
     )
   }
 }
@@ -152,6 +173,7 @@ impl TryFrom<Vec<KeyMaterial_AES_GCM_GMAC>> for KeyMaterial_AES_GCM_GMAC_seq {
         payload_key_material.clone(),
       )),
       _ => Err(create_security_error_and_log!(
+        SecurityErrorKind::MalformedToken,
         "Expected 1 or 2 key materials in KeyMaterial_AES_GCM_GMAC_seq, received {}",
         value.len()
       )),
@@ -181,6 +203,7 @@ impl TryFrom<Bytes> for KeyMaterial_AES_GCM_GMAC_seq {
       // Map deserialization error to SecurityError
       |e| Self::Error {
         msg: format!("Error deserializing Vec<KeyMaterial_AES_GCM_GMAC>: {}", e),
+        kind: SecurityErrorKind::MalformedToken,
       },
     )?;
 
@@ -211,6 +234,7 @@ impl TryFrom<KeyMaterial_AES_GCM_GMAC_seq> for Bytes {
       .map(Bytes::from)
       .map_err(|e| Self::Error {
         msg: format!("Error serializing KeyMaterial_AES_GCM_GMAC_seq: {}", e),
+        kind: SecurityErrorKind::Internal,
       })
   }
 }
@@ -220,45 +244,38 @@ impl KeyMaterial_AES_GCM_GMAC {
   /// returns the receiver-specific material
   pub fn receiver_key_material_for(
     &self,
-    KeyMaterial_AES_GCM_GMAC {
+    common_key_material: &KeyMaterial_AES_GCM_GMAC,
+  ) -> SecurityResult<ReceiverSpecificKeyMaterial> {
+    let KeyMaterial_AES_GCM_GMAC {
       transformation_kind,
       master_salt,
       sender_key_id,
       master_sender_key,
       ..
-    }: &KeyMaterial_AES_GCM_GMAC,
-  ) -> SecurityResult<ReceiverSpecificKeyMaterial> {
-    if !self.sender_key_id.eq(sender_key_id) {
-      Err(create_security_error_and_log!(
-        "The receiver-specific key material has a wrong sender_key_id: expected {:?}, received \
-         {:?}.",
-        sender_key_id,
-        self.sender_key_id
-      ))
+    } = common_key_material;
+    let mismatch = if !self.sender_key_id.eq(sender_key_id) {
+      Some("sender_key_id")
     } else if !self.transformation_kind.eq(transformation_kind) {
-      Err(create_security_error_and_log!(
-        "The receiver-specific key material has a wrong transformation_kind: expected {:?}, \
-         received {:?}.",
-        transformation_kind,
-        self.transformation_kind
-      ))
+      Some("transformation_kind")
     } else if !self.master_sender_key.eq(master_sender_key) {
-      Err(create_security_error_and_log!(
-        "The receiver-specific key has a wrong master_sender_key: expected {:?}, received {:?}.",
-        master_sender_key,
-        self.master_sender_key
-      ))
+      Some("master_sender_key")
     } else if !self.master_salt.eq(master_salt) {
-      Err(create_security_error_and_log!(
-        "The receiver-specific key has a wrong master_salt: expected {:?}, received {:?}.",
-        master_salt,
-        self.master_salt
-      ))
+      Some("master_salt")
     } else {
-      Ok(ReceiverSpecificKeyMaterial {
+      None
+    };
+    match mismatch {
+      Some(field) => Err(create_security_error_and_log!(
+        SecurityErrorKind::MalformedToken,
+        "The receiver-specific key material has a wrong {}: expected to match {:?}, received {:?}.",
+        field,
+        common_key_material,
+        self
+      )),
+      None => Ok(ReceiverSpecificKeyMaterial {
         key_id: self.receiver_specific_key_id,
         key: self.master_receiver_specific_key.clone(),
-      })
+      }),
     }
   }
 }
@@ -269,39 +286,51 @@ pub(super) struct ReceiverSpecificKeyMaterial {
   pub key: BuiltinKey,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub(super) enum ReceiverSpecific {
   No,
   Yes,
 }
 
-// Conversions from and into Vec<CryptoToken> for KeyMaterial_AES_GCM_GMAC_seq
-impl TryFrom<Vec<CryptoToken>> for KeyMaterial_AES_GCM_GMAC_seq {
-  type Error = SecurityError;
-  fn try_from(tokens: Vec<CryptoToken>) -> Result<Self, Self::Error> {
+// Conversions from and into Vec<CryptoToken> for KeyMaterial_AES_GCM_GMAC_seq.
+// Received tokens may carry the key materials wrapped with the KxKey of the
+// participant pair. Sent tokens are wrapped only if a KxKey is given.
+impl KeyMaterial_AES_GCM_GMAC_seq {
+  pub fn from_crypto_tokens(
+    tokens: Vec<CryptoToken>,
+    key_exchange_key: &BuiltinKey,
+  ) -> SecurityResult<Self> {
     tokens
-      .iter()
-      .map(|token| KeyMaterial_AES_GCM_GMAC::try_from(token.clone()))
-      .collect::<Result<Vec<KeyMaterial_AES_GCM_GMAC>, Self::Error>>()
+      .into_iter()
+      .map(|token| BuiltinCryptoToken::try_from(token)?.unwrap(key_exchange_key))
+      .collect::<SecurityResult<Vec<KeyMaterial_AES_GCM_GMAC>>>()
       // Convert the Vec
       .and_then(KeyMaterial_AES_GCM_GMAC_seq::try_from)
   }
-}
-impl TryFrom<KeyMaterial_AES_GCM_GMAC_seq> for Vec<CryptoToken> {
-  type Error = SecurityError;
-  fn try_from(key_materials: KeyMaterial_AES_GCM_GMAC_seq) -> Result<Self, Self::Error> {
-    Vec::from(key_materials)
-      .iter()
-      .map(|key_material| CryptoToken::try_from(key_material.clone()))
+
+  pub fn to_crypto_tokens(
+    &self,
+    key_wrap_key: Option<&BuiltinKey>,
+  ) -> SecurityResult<Vec<CryptoToken>> {
+    Vec::from(self.clone())
+      .into_iter()
+      .map(|key_material| {
+        match key_wrap_key {
+          Some(key_wrap_key) => BuiltinCryptoToken::wrap(key_material, key_wrap_key),
+          None => Ok(BuiltinCryptoToken::from(key_material)),
+        }
+        .and_then(CryptoToken::try_from)
+      })
       .collect()
   }
 }
+
 //For (de)serialization
 // See definition in DDS Security spec v1.1
 // "9.5.2.1.1 KeyMaterial_AES_GCM_GMAC structure"
 #[allow(non_camel_case_types)] // We use the name from the spec
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
-struct Serializable_KeyMaterial_AES_GCM_GMAC {
+pub(super) struct Serializable_KeyMaterial_AES_GCM_GMAC {
   transformation_kind: CryptoTransformKind,
   master_salt: Vec<u8>, // sequence<octet, 32>
   sender_key_id: CryptoTransformKeyId,
@@ -331,6 +360,20 @@ impl TryFrom<Serializable_KeyMaterial_AES_GCM_GMAC> for KeyMaterial_AES_GCM_GMAC
 
     let key_length = KeyLength::from(transformation_kind);
 
+    // The session keys are derived from the salt, so a salt of the wrong length
+    // would silently give keys that do not match the sender's. 9.5.3.3.2
+    // Keys of the wrong length would be truncated or fail later in the cipher
+    // setup, so reject them here as well.
+    check_key_length("master_salt", transformation_kind, &master_salt)?;
+    check_key_length("master_sender_key", transformation_kind, &master_sender_key)?;
+    if receiver_specific_key_id != CryptoTransformKeyId::ZERO {
+      check_key_length(
+        "master_receiver_specific_key",
+        transformation_kind,
+        &master_receiver_specific_key,
+      )?;
+    }
+
     let master_receiver_specific_key = if receiver_specific_key_id.eq(&CryptoTransformKeyId::ZERO) {
       BuiltinKey::None
     } else {
@@ -348,6 +391,26 @@ impl TryFrom<Serializable_KeyMaterial_AES_GCM_GMAC> for KeyMaterial_AES_GCM_GMAC
   }
 }
 
+fn check_key_length(
+  field_name: &str,
+  transformation_kind: BuiltinCryptoTransformationKind,
+  bytes: &[u8],
+) -> SecurityResult<()> {
+  let key_length = KeyLength::from(transformation_kind) as usize;
+  if bytes.len() == key_length {
+    Ok(())
+  } else {
+    Err(create_security_error_and_log!(
+      SecurityErrorKind::MalformedToken,
+      "The {} of {:?} key material must be {} bytes long, received {} bytes.",
+      field_name,
+      transformation_kind,
+      key_length,
+      bytes.len()
+    ))
+  }
+}
+
 impl From<KeyMaterial_AES_GCM_GMAC> for Serializable_KeyMaterial_AES_GCM_GMAC {
   fn from(
     KeyMaterial_AES_GCM_GMAC {
@@ -376,9 +439,164 @@ impl From<KeyMaterial_AES_GCM_GMAC> for Serializable_KeyMaterial_AES_GCM_GMAC {
 // macs. Conversely, volatile endpoints only have receiver-specific payload
 // encryption key materials.
 #[derive(Clone)]
-// The small variant appears only 2 times per participant
-#[allow(clippy::large_enum_variant)]
 pub(super) enum CommonEncodeKeyMaterials {
-  Some(KeyMaterial_AES_GCM_GMAC_seq),
-  Volatile(bool), // bool is for use_256_bit_key
+  Some(Arc<KeyMaterial_AES_GCM_GMAC_seq>),
+  // The key materials are derived from the shared secret when a remote is
+  // matched, always with 256-bit keys
+  Volatile,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::security::entropy::SystemEntropySource;
+
+  fn key_material(
+    transformation_kind: BuiltinCryptoTransformationKind,
+  ) -> KeyMaterial_AES_GCM_GMAC {
+    let key_length = KeyLength::from(transformation_kind);
+    let entropy_source = SystemEntropySource::new();
+    KeyMaterial_AES_GCM_GMAC {
+      transformation_kind,
+      master_salt: BuiltinKey::generate_random(key_length, &entropy_source).unwrap(),
+      sender_key_id: CryptoTransformKeyId::from(rand::random::<[u8; 4]>()),
+      master_sender_key: BuiltinKey::generate_random(key_length, &entropy_source).unwrap(),
+      receiver_specific_key_id: CryptoTransformKeyId::ZERO,
+      master_receiver_specific_key: BuiltinKey::None,
+    }
+  }
+
+  #[test]
+  fn master_salt_survives_crypto_token() {
+    for transformation_kind in [
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC,
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC,
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+    ] {
+      let original = key_material(transformation_kind);
+      let token = CryptoToken::try_from(original.clone()).unwrap();
+      let received = KeyMaterial_AES_GCM_GMAC::try_from(token).unwrap();
+
+      assert_eq!(
+        original.master_salt.as_bytes().len(),
+        KeyLength::from(transformation_kind) as usize
+      );
+      assert_eq!(received.master_salt, original.master_salt);
+      assert_eq!(received.master_sender_key, original.master_sender_key);
+      assert_eq!(received.sender_key_id, original.sender_key_id);
+    }
+  }
+
+  #[test]
+  fn master_salt_of_wrong_length_is_rejected() {
+    let mut serializable = Serializable_KeyMaterial_AES_GCM_GMAC::from(key_material(
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+    ));
+    serializable.master_salt = vec![0x55; AES256_KEY_LENGTH];
+    assert!(KeyMaterial_AES_GCM_GMAC::try_from(serializable.clone()).is_err());
+
+    serializable.master_salt = vec![0x55; AES128_KEY_LENGTH - 1];
+    assert!(KeyMaterial_AES_GCM_GMAC::try_from(serializable.clone()).is_err());
+
+    serializable.master_salt = Vec::new();
+    assert_eq!(
+      KeyMaterial_AES_GCM_GMAC::try_from(serializable.clone())
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::MalformedToken
+    );
+
+    serializable.master_salt = vec![0x55; AES128_KEY_LENGTH];
+    assert!(KeyMaterial_AES_GCM_GMAC::try_from(serializable).is_ok());
+  }
+
+  // Replaces the key material inside a valid CryptoToken with the serialized
+  // form of the given structure
+  fn token_with(serializable: &Serializable_KeyMaterial_AES_GCM_GMAC) -> CryptoToken {
+    let mut token = CryptoToken::try_from(key_material(
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+    ))
+    .unwrap();
+    token.data_holder.binary_properties[0].value = Bytes::from(
+      to_vec::<Serializable_KeyMaterial_AES_GCM_GMAC, BigEndian>(serializable).unwrap(),
+    );
+    token
+  }
+
+  #[test]
+  fn keys_of_wrong_length_are_rejected_from_crypto_token() {
+    for (transformation_kind, key_length) in [
+      (
+        BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC,
+        AES128_KEY_LENGTH,
+      ),
+      (
+        BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+        AES256_KEY_LENGTH,
+      ),
+    ] {
+      let valid = Serializable_KeyMaterial_AES_GCM_GMAC::from(key_material(transformation_kind));
+      assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&valid)).is_ok());
+
+      for wrong_length in [3, key_length - 1, key_length + 1, 2 * AES256_KEY_LENGTH] {
+        let mut sender_key = valid.clone();
+        sender_key.master_sender_key = vec![0x55; wrong_length];
+        assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&sender_key)).is_err());
+
+        let mut salt = valid.clone();
+        salt.master_salt = vec![0x55; wrong_length];
+        assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&salt)).is_err());
+
+        let mut receiver_specific_key = valid.clone();
+        receiver_specific_key.receiver_specific_key_id = CryptoTransformKeyId::from([0, 0, 0, 1]);
+        receiver_specific_key.master_receiver_specific_key = vec![0x55; wrong_length];
+        assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&receiver_specific_key)).is_err());
+      }
+
+      // The receiver-specific key is not checked when there is none
+      let mut no_receiver_specific_key = valid.clone();
+      no_receiver_specific_key.master_receiver_specific_key = vec![0x55; 3];
+      assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&no_receiver_specific_key)).is_ok());
+
+      let mut receiver_specific_key = valid;
+      receiver_specific_key.receiver_specific_key_id = CryptoTransformKeyId::from([0, 0, 0, 1]);
+      receiver_specific_key.master_receiver_specific_key = vec![0x55; key_length];
+      assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&receiver_specific_key)).is_ok());
+    }
+  }
+
+  #[test]
+  fn keys_of_none_kind_must_be_empty() {
+    let mut serializable = Serializable_KeyMaterial_AES_GCM_GMAC::from(key_material(
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_NONE,
+    ));
+    assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&serializable)).is_ok());
+
+    serializable.master_sender_key = vec![0x55; AES128_KEY_LENGTH];
+    assert!(KeyMaterial_AES_GCM_GMAC::try_from(token_with(&serializable)).is_err());
+  }
+
+  #[test]
+  fn unparsable_crypto_token_is_malformed() {
+    let mut token = CryptoToken::try_from(key_material(
+      BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+    ))
+    .unwrap();
+    token.data_holder.binary_properties[0].value = Bytes::from_static(&[1, 2, 3]);
+    assert_eq!(
+      KeyMaterial_AES_GCM_GMAC::try_from(token.clone())
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::MalformedToken
+    );
+
+    token.data_holder.binary_properties.clear();
+    assert_eq!(
+      KeyMaterial_AES_GCM_GMAC::try_from(token)
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::MalformedToken
+    );
+  }
 }
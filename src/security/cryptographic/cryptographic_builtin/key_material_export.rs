@@ -0,0 +1,722 @@
+use byteorder::BigEndian;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  security::{access_control::types::*, cryptographic::cryptographic_builtin::*, Property},
+  serialization::to_vec,
+  CdrDeserializer,
+};
+use super::{builtin_key::*, key_material::*};
+
+// The export starts with a header that identifies the format and its version,
+// followed by ExportedKeyMaterials in big-endian CDR. A new version is needed
+// whenever ExportedKeyMaterials changes.
+const EXPORT_FORMAT_IDENTIFIER: [u8; 2] = *b"KM";
+const EXPORT_FORMAT_VERSION: u16 = 3;
+const EXPORT_HEADER_LENGTH: usize = 4;
+
+impl CryptographicBuiltin {
+  /// Serializes the key materials of the registered participants and
+  /// endpoints, and the handles that tie them together, so that
+  /// [`CryptographicBuiltin::import_key_materials`] can restore them after a
+  /// restart. Exporting is disabled by default, and fails unless a local
+  /// participant has set the property `dds.sec.crypto.allow_key_export` to
+  /// `true`.
+  ///
+  /// **Warning**: the output contains the raw master keys of both the local
+  /// and the remote entities. Anyone who gets hold of it can decode and forge
+  /// their traffic, so it must be stored with the same care as the private
+  /// keys of the participant.
+  ///
+  /// Retired key materials, encoding sessions and replay windows are not
+  /// exported.
+  pub fn export_key_materials(&self) -> SecurityResult<Bytes> {
+    if !self.key_material_export_allowed {
+      return Err(create_security_error_and_log!(
+        SecurityErrorKind::Other,
+        "Exporting key materials is not allowed, set dds.sec.crypto.allow_key_export to enable it"
+      ));
+    }
+
+    let exported = ExportedKeyMaterials::from(self);
+    let body = to_vec::<ExportedKeyMaterials, BigEndian>(&exported).map_err(|e| {
+      create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "Error serializing key materials for export: {}",
+        e
+      )
+    })?;
+
+    let mut bytes = Vec::with_capacity(EXPORT_HEADER_LENGTH + body.len());
+    bytes.extend_from_slice(&EXPORT_FORMAT_IDENTIFIER);
+    bytes.extend_from_slice(&EXPORT_FORMAT_VERSION.to_be_bytes());
+    bytes.extend_from_slice(&body);
+    Ok(Bytes::from(bytes))
+  }
+
+  /// Restores key materials exported with
+  /// [`CryptographicBuiltin::export_key_materials`].
+  ///
+  /// The imported handles are shifted past the handles this plugin has
+  /// issued, so that they do not collide with handles generated later.
+  /// Importing into a fresh plugin keeps them as they were. Remote
+  /// participants are restored without their identity handles, which are
+  /// only valid within the authentication plugin that issued them.
+  ///
+  /// Nothing is imported if the input is malformed or any of the shifted
+  /// handles is already in use.
+  #[allow(clippy::needless_pass_by_value)] // The export is handed over as a whole
+  pub fn import_key_materials(&mut self, bytes: Bytes) -> SecurityResult<()> {
+    let body = match bytes.as_ref() {
+      [i0, i1, v0, v1, body @ ..] if [*i0, *i1] == EXPORT_FORMAT_IDENTIFIER => {
+        match u16::from_be_bytes([*v0, *v1]) {
+          EXPORT_FORMAT_VERSION => body,
+          version => {
+            return Err(create_security_error_and_log!(
+              SecurityErrorKind::MalformedToken,
+              "Unsupported version {} of exported key materials, expected {}",
+              version,
+              EXPORT_FORMAT_VERSION
+            ))
+          }
+        }
+      }
+      _ => {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::MalformedToken,
+          "The input is not exported key materials"
+        ))
+      }
+    };
+
+    let exported = ExportedKeyMaterials::deserialize(&mut CdrDeserializer::<BigEndian>::new(body))
+      .map_err(|e| {
+        create_security_error_and_log!(
+          SecurityErrorKind::MalformedToken,
+          "Error deserializing exported key materials: {}",
+          e
+        )
+      })?;
+
+    let offset = self.crypto_handle_counter;
+    let max_crypto_handle = exported
+      .crypto_handles()
+      .max()
+      .map(|max_crypto_handle| {
+        max_crypto_handle.checked_add(offset).ok_or_else(|| {
+          create_security_error_and_log!(
+            SecurityErrorKind::Internal,
+            "The imported crypto handles do not fit after the handle {}",
+            offset
+          )
+        })
+      })
+      .transpose()?;
+    let remap = |crypto_handle: CryptoHandle| crypto_handle + offset;
+    if let Some(crypto_handle) = exported
+      .crypto_handles()
+      .map(remap)
+      .find(|crypto_handle| *crypto_handle == 0 || self.crypto_handle_in_use(*crypto_handle))
+    {
+      return Err(create_security_error_and_log!(
+        SecurityErrorKind::Internal,
+        "The imported crypto handle {} is already in use",
+        crypto_handle
+      ));
+    }
+
+    let imported = exported.remap(remap)?;
+    self.insert_imported(imported)?;
+    if let Some(max_crypto_handle) = max_crypto_handle {
+      self.crypto_handle_counter = self.crypto_handle_counter.max(max_crypto_handle);
+    }
+    Ok(())
+  }
+
+  fn insert_imported(&mut self, imported: ImportedKeyMaterials) -> SecurityResult<()> {
+    let ImportedKeyMaterials {
+      common_encode_key_materials,
+      receiver_specific_encode_key_materials,
+      decode_key_materials,
+      key_exchange_keys,
+      participant_encrypt_options,
+      endpoint_encrypt_options,
+      endpoints,
+      matched_endpoints,
+      remote_participants,
+      shared_topic_keys,
+      rekey_grace_periods,
+      max_blocks_per_session,
+      reject_replays,
+      max_receiver_specific_macs,
+      key_wrap_participants,
+      key_wrap_peers,
+    } = imported;
+
+    for (crypto_handle, key_materials) in common_encode_key_materials {
+      if let CommonEncodeKeyMaterials::Some(key_materials) = &key_materials {
+        self.reserve_local_key_ids(key_materials);
+      }
+      self.insert_common_encode_key_materials(crypto_handle, key_materials)?;
+    }
+    for (crypto_handle, key_materials) in receiver_specific_encode_key_materials {
+      self.reserve_local_key_ids(&key_materials);
+      self.insert_receiver_specific_encode_key_materials(crypto_handle, key_materials)?;
+    }
+    // The decode key ids are indexed by the participants of the endpoints
+    for (participant_crypto_handle, endpoint_info) in endpoints {
+      self.insert_endpoint_info(participant_crypto_handle, endpoint_info);
+      self
+        .endpoint_to_participant
+        .insert(endpoint_info.crypto_handle, participant_crypto_handle);
+    }
+    for (crypto_handle, key_materials) in decode_key_materials {
+      self.insert_decode_key_materials(crypto_handle, key_materials)?;
+    }
+    self.key_exchange_keys.extend(key_exchange_keys);
+    for (crypto_handle, attributes) in participant_encrypt_options {
+      self.insert_participant_attributes(crypto_handle, attributes)?;
+    }
+    for (crypto_handle, attributes) in endpoint_encrypt_options {
+      self.insert_endpoint_attributes(crypto_handle, attributes)?;
+    }
+    for (
+      local_endpoint_crypto_handle,
+      remote_participant_crypto_handle,
+      remote_endpoint_crypto_handle,
+    ) in matched_endpoints
+    {
+      self
+        .matched_remote_endpoint
+        .entry(local_endpoint_crypto_handle)
+        .or_default()
+        .insert(
+          remote_participant_crypto_handle,
+          remote_endpoint_crypto_handle,
+        );
+      self
+        .matched_local_endpoint
+        .insert(remote_endpoint_crypto_handle, local_endpoint_crypto_handle);
+    }
+    self
+      .imported_remote_participants
+      .extend(remote_participants);
+    for (index, shared_key) in shared_topic_keys {
+      self.reserve_local_key_ids(&shared_key.key_materials);
+      for datawriter_crypto_handle in &shared_key.datawriters {
+        self
+          .shared_topic_key_handles
+          .insert(*datawriter_crypto_handle, shared_key.crypto_handle);
+      }
+      self.shared_topic_keys.insert(index, shared_key);
+    }
+    self.rekey_grace_periods.extend(rekey_grace_periods);
+    self.max_blocks_per_session.extend(max_blocks_per_session);
+    self.reject_replays.extend(reject_replays);
+    self.key_wrap_participants.extend(key_wrap_participants);
+    self.key_wrap_peers.extend(key_wrap_peers);
+    self
+      .max_receiver_specific_macs
+      .extend(max_receiver_specific_macs);
+    Ok(())
+  }
+
+  // Keeps newly generated key ids from colliding with imported local ones
+  fn reserve_local_key_ids(&mut self, key_materials: &KeyMaterial_AES_GCM_GMAC_seq) {
+    for key_material in Vec::from(key_materials.clone()) {
+      self.used_local_key_ids.insert(key_material.sender_key_id);
+      self
+        .used_local_key_ids
+        .insert(key_material.receiver_specific_key_id);
+    }
+  }
+}
+
+// The serialized form of the exportable state of CryptographicBuiltin. The
+// maps are stored as sequences of their entries.
+#[derive(Serialize, Deserialize)]
+struct ExportedKeyMaterials {
+  common_encode_key_materials: Vec<(CryptoHandle, ExportedCommonEncodeKeyMaterials)>,
+  receiver_specific_encode_key_materials: Vec<(CryptoHandle, ExportedKeyMaterialSeq)>,
+  decode_key_materials: Vec<(CryptoHandle, ExportedKeyMaterialSeq)>,
+  key_exchange_keys: Vec<(ParticipantCryptoHandle, Vec<u8>)>,
+  participant_encrypt_options: Vec<(ParticipantCryptoHandle, ExportedParticipantAttributes)>,
+  endpoint_encrypt_options: Vec<(EndpointCryptoHandle, ExportedEndpointAttributes)>,
+  // (participant, endpoint, endpoint kind)
+  endpoints: Vec<(ParticipantCryptoHandle, EndpointCryptoHandle, EndpointKind)>,
+  // (local endpoint, remote participant, remote endpoint)
+  matched_endpoints: Vec<(
+    EndpointCryptoHandle,
+    ParticipantCryptoHandle,
+    EndpointCryptoHandle,
+  )>,
+  remote_participants: Vec<ParticipantCryptoHandle>,
+  shared_topic_keys: Vec<ExportedSharedTopicKey>,
+  // (participant, seconds, nanoseconds)
+  rekey_grace_periods: Vec<(ParticipantCryptoHandle, u64, u32)>,
+  max_blocks_per_session: Vec<(CryptoHandle, u64)>,
+  reject_replays: Vec<ParticipantCryptoHandle>,
+  max_receiver_specific_macs: Vec<(ParticipantCryptoHandle, u64)>,
+  key_wrap_participants: Vec<ParticipantCryptoHandle>,
+  key_wrap_peers: Vec<ParticipantCryptoHandle>,
+}
+
+type ExportedKeyMaterialSeq = Vec<Serializable_KeyMaterial_AES_GCM_GMAC>;
+
+#[derive(Serialize, Deserialize)]
+enum ExportedCommonEncodeKeyMaterials {
+  Some(ExportedKeyMaterialSeq),
+  Volatile,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedParticipantAttributes {
+  allow_unauthenticated_participants: bool,
+  is_access_protected: bool,
+  is_rtps_protected: bool,
+  is_discovery_protected: bool,
+  is_liveliness_protected: bool,
+  plugin_participant_attributes: u32,
+  ac_participant_properties: Vec<Property>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedEndpointAttributes {
+  is_read_protected: bool,
+  is_write_protected: bool,
+  is_topic_discovery_protected: bool,
+  is_topic_liveliness_protected: bool,
+  is_submessage_protected: bool,
+  is_payload_protected: bool,
+  is_key_protected: bool,
+  plugin_endpoint_attributes: u32,
+  ac_endpoint_properties: Vec<Property>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedSharedTopicKey {
+  participant_crypto_handle: ParticipantCryptoHandle,
+  topic_name: String,
+  submessage_transformation_kind: CryptoTransformKind,
+  payload_transformation_kind: CryptoTransformKind,
+  crypto_handle: CryptoHandle,
+  key_materials: ExportedKeyMaterialSeq,
+  datawriters: Vec<DatawriterCryptoHandle>,
+}
+
+// The exported state converted back, with remapped handles
+struct ImportedKeyMaterials {
+  common_encode_key_materials: Vec<(CryptoHandle, CommonEncodeKeyMaterials)>,
+  receiver_specific_encode_key_materials: Vec<(CryptoHandle, Arc<KeyMaterial_AES_GCM_GMAC_seq>)>,
+  decode_key_materials: Vec<(CryptoHandle, Arc<KeyMaterial_AES_GCM_GMAC_seq>)>,
+  key_exchange_keys: Vec<(ParticipantCryptoHandle, BuiltinKey)>,
+  participant_encrypt_options: Vec<(ParticipantCryptoHandle, ParticipantSecurityAttributes)>,
+  endpoint_encrypt_options: Vec<(EndpointCryptoHandle, EndpointSecurityAttributes)>,
+  endpoints: Vec<(ParticipantCryptoHandle, EndpointInfo)>,
+  matched_endpoints: Vec<(
+    EndpointCryptoHandle,
+    ParticipantCryptoHandle,
+    EndpointCryptoHandle,
+  )>,
+  remote_participants: Vec<ParticipantCryptoHandle>,
+  shared_topic_keys: Vec<(SharedTopicKeyIndex, SharedTopicKey)>,
+  rekey_grace_periods: Vec<(ParticipantCryptoHandle, std::time::Duration)>,
+  max_blocks_per_session: Vec<(CryptoHandle, u64)>,
+  reject_replays: Vec<ParticipantCryptoHandle>,
+  max_receiver_specific_macs: Vec<(ParticipantCryptoHandle, usize)>,
+  key_wrap_participants: Vec<ParticipantCryptoHandle>,
+  key_wrap_peers: Vec<ParticipantCryptoHandle>,
+}
+
+fn export_key_material_seq(key_materials: &KeyMaterial_AES_GCM_GMAC_seq) -> ExportedKeyMaterialSeq {
+  Vec::from(key_materials.clone())
+    .into_iter()
+    .map(Serializable_KeyMaterial_AES_GCM_GMAC::from)
+    .collect()
+}
+
+fn import_key_material_seq(
+  key_materials: ExportedKeyMaterialSeq,
+) -> SecurityResult<Arc<KeyMaterial_AES_GCM_GMAC_seq>> {
+  key_materials
+    .into_iter()
+    .map(KeyMaterial_AES_GCM_GMAC::try_from)
+    .collect::<SecurityResult<Vec<_>>>()
+    .and_then(KeyMaterial_AES_GCM_GMAC_seq::try_from)
+    .map(Arc::new)
+}
+
+impl From<&CryptographicBuiltin> for ExportedKeyMaterials {
+  fn from(crypto: &CryptographicBuiltin) -> Self {
+    ExportedKeyMaterials {
+      common_encode_key_materials: crypto
+        .common_encode_key_materials
+        .iter()
+        .map(|(crypto_handle, key_materials)| {
+          let key_materials = match key_materials {
+            CommonEncodeKeyMaterials::Some(key_materials) => {
+              ExportedCommonEncodeKeyMaterials::Some(export_key_material_seq(key_materials))
+            }
+            CommonEncodeKeyMaterials::Volatile => ExportedCommonEncodeKeyMaterials::Volatile,
+          };
+          (*crypto_handle, key_materials)
+        })
+        .collect(),
+      receiver_specific_encode_key_materials: crypto
+        .receiver_specific_encode_key_materials
+        .iter()
+        .map(|(crypto_handle, key_materials)| {
+          (*crypto_handle, export_key_material_seq(key_materials))
+        })
+        .collect(),
+      decode_key_materials: crypto
+        .decode_key_materials
+        .iter()
+        .map(|(crypto_handle, key_materials)| {
+          (*crypto_handle, export_key_material_seq(key_materials))
+        })
+        .collect(),
+      key_exchange_keys: crypto
+        .key_exchange_keys
+        .iter()
+        .map(|(crypto_handle, key)| (*crypto_handle, key.as_bytes().to_vec()))
+        .collect(),
+      participant_encrypt_options: crypto
+        .participant_encrypt_options
+        .iter()
+        .map(|(crypto_handle, attributes)| {
+          (
+            *crypto_handle,
+            ExportedParticipantAttributes::from(attributes.clone()),
+          )
+        })
+        .collect(),
+      endpoint_encrypt_options: crypto
+        .endpoint_encrypt_options
+        .iter()
+        .map(|(crypto_handle, attributes)| {
+          (
+            *crypto_handle,
+            ExportedEndpointAttributes::from(attributes.clone()),
+          )
+        })
+        .collect(),
+      endpoints: crypto
+        .participant_to_endpoint_info
+        .iter()
+        .flat_map(|(participant_crypto_handle, endpoint_infos)| {
+          endpoint_infos
+            .iter()
+            .map(|info| (*participant_crypto_handle, info.crypto_handle, info.kind))
+        })
+        .collect(),
+      matched_endpoints: crypto
+        .matched_remote_endpoint
+        .iter()
+        .flat_map(|(local_endpoint_crypto_handle, remote_endpoints)| {
+          remote_endpoints.iter().map(
+            |(remote_participant_crypto_handle, remote_endpoint_crypto_handle)| {
+              (
+                *local_endpoint_crypto_handle,
+                *remote_participant_crypto_handle,
+                *remote_endpoint_crypto_handle,
+              )
+            },
+          )
+        })
+        .collect(),
+      remote_participants: crypto
+        .matched_remote_participants
+        .values()
+        .map(|matched| matched.crypto_handle)
+        .chain(crypto.imported_remote_participants.iter().copied())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect(),
+      shared_topic_keys: crypto
+        .shared_topic_keys
+        .iter()
+        .map(|(index, shared_key)| ExportedSharedTopicKey {
+          participant_crypto_handle: index.participant_crypto_handle,
+          topic_name: index.topic_name.clone(),
+          submessage_transformation_kind: index.submessage_transformation_kind.into(),
+          payload_transformation_kind: index.payload_transformation_kind.into(),
+          crypto_handle: shared_key.crypto_handle,
+          key_materials: export_key_material_seq(&shared_key.key_materials),
+          datawriters: shared_key.datawriters.iter().copied().collect(),
+        })
+        .collect(),
+      rekey_grace_periods: crypto
+        .rekey_grace_periods
+        .iter()
+        .map(|(crypto_handle, grace_period)| {
+          (
+            *crypto_handle,
+            grace_period.as_secs(),
+            grace_period.subsec_nanos(),
+          )
+        })
+        .collect(),
+      max_blocks_per_session: crypto
+        .max_blocks_per_session
+        .iter()
+        .map(|(crypto_handle, max_blocks)| (*crypto_handle, *max_blocks))
+        .collect(),
+      reject_replays: crypto.reject_replays.iter().copied().collect(),
+      max_receiver_specific_macs: crypto
+        .max_receiver_specific_macs
+        .iter()
+        .map(|(crypto_handle, max_macs)| (*crypto_handle, *max_macs as u64))
+        .collect(),
+      key_wrap_participants: crypto.key_wrap_participants.iter().copied().collect(),
+      key_wrap_peers: crypto.key_wrap_peers.iter().copied().collect(),
+    }
+  }
+}
+
+impl ExportedKeyMaterials {
+  // All the crypto handles in the export
+  fn crypto_handles(&self) -> impl Iterator<Item = CryptoHandle> + '_ {
+    let handles = |entries: &[(CryptoHandle, u64)]| {
+      entries
+        .iter()
+        .map(|(crypto_handle, _)| *crypto_handle)
+        .collect::<Vec<_>>()
+    };
+    (self.common_encode_key_materials.iter().map(|(h, _)| *h))
+      .chain(
+        self
+          .receiver_specific_encode_key_materials
+          .iter()
+          .map(|(h, _)| *h),
+      )
+      .chain(self.decode_key_materials.iter().map(|(h, _)| *h))
+      .chain(self.key_exchange_keys.iter().map(|(h, _)| *h))
+      .chain(self.participant_encrypt_options.iter().map(|(h, _)| *h))
+      .chain(self.endpoint_encrypt_options.iter().map(|(h, _)| *h))
+      .chain(self.endpoints.iter().flat_map(|(p, e, _)| [*p, *e]))
+      .chain(
+        self
+          .matched_endpoints
+          .iter()
+          .flat_map(|(l, p, r)| [*l, *p, *r]),
+      )
+      .chain(self.remote_participants.iter().copied())
+      .chain(self.shared_topic_keys.iter().flat_map(|shared_key| {
+        [
+          shared_key.participant_crypto_handle,
+          shared_key.crypto_handle,
+        ]
+        .into_iter()
+        .chain(shared_key.datawriters.iter().copied())
+      }))
+      .chain(self.rekey_grace_periods.iter().map(|(h, _, _)| *h))
+      .chain(handles(&self.max_blocks_per_session))
+      .chain(self.reject_replays.iter().copied())
+      .chain(handles(&self.max_receiver_specific_macs))
+      .chain(self.key_wrap_participants.iter().copied())
+      .chain(self.key_wrap_peers.iter().copied())
+  }
+
+  fn remap(
+    self,
+    remap: impl Fn(CryptoHandle) -> CryptoHandle,
+  ) -> SecurityResult<ImportedKeyMaterials> {
+    Ok(ImportedKeyMaterials {
+      common_encode_key_materials: self
+        .common_encode_key_materials
+        .into_iter()
+        .map(|(crypto_handle, key_materials)| {
+          let key_materials = match key_materials {
+            ExportedCommonEncodeKeyMaterials::Some(key_materials) => {
+              CommonEncodeKeyMaterials::Some(import_key_material_seq(key_materials)?)
+            }
+            ExportedCommonEncodeKeyMaterials::Volatile => CommonEncodeKeyMaterials::Volatile,
+          };
+          Ok((remap(crypto_handle), key_materials))
+        })
+        .collect::<SecurityResult<_>>()?,
+      receiver_specific_encode_key_materials: self
+        .receiver_specific_encode_key_materials
+        .into_iter()
+        .map(|(crypto_handle, key_materials)| {
+          Ok((
+            remap(crypto_handle),
+            import_key_material_seq(key_materials)?,
+          ))
+        })
+        .collect::<SecurityResult<_>>()?,
+      decode_key_materials: self
+        .decode_key_materials
+        .into_iter()
+        .map(|(crypto_handle, key_materials)| {
+          Ok((
+            remap(crypto_handle),
+            import_key_material_seq(key_materials)?,
+          ))
+        })
+        .collect::<SecurityResult<_>>()?,
+      key_exchange_keys: self
+        .key_exchange_keys
+        .into_iter()
+        .map(|(crypto_handle, key)| {
+          Ok((
+            remap(crypto_handle),
+            BuiltinKey::from_bytes(KeyLength::AES256, &key)?,
+          ))
+        })
+        .collect::<SecurityResult<_>>()?,
+      participant_encrypt_options: self
+        .participant_encrypt_options
+        .into_iter()
+        .map(|(crypto_handle, attributes)| (remap(crypto_handle), attributes.into()))
+        .collect(),
+      endpoint_encrypt_options: self
+        .endpoint_encrypt_options
+        .into_iter()
+        .map(|(crypto_handle, attributes)| (remap(crypto_handle), attributes.into()))
+        .collect(),
+      endpoints: self
+        .endpoints
+        .into_iter()
+        .map(
+          |(participant_crypto_handle, endpoint_crypto_handle, kind)| {
+            (
+              remap(participant_crypto_handle),
+              EndpointInfo {
+                crypto_handle: remap(endpoint_crypto_handle),
+                kind,
+              },
+            )
+          },
+        )
+        .collect(),
+      matched_endpoints: self
+        .matched_endpoints
+        .into_iter()
+        .map(|(local, participant, remote)| (remap(local), remap(participant), remap(remote)))
+        .collect(),
+      remote_participants: self.remote_participants.into_iter().map(&remap).collect(),
+      shared_topic_keys: self
+        .shared_topic_keys
+        .into_iter()
+        .map(|shared_key| {
+          let key_materials = import_key_material_seq(shared_key.key_materials)?;
+          let index = SharedTopicKeyIndex {
+            participant_crypto_handle: remap(shared_key.participant_crypto_handle),
+            topic_name: shared_key.topic_name,
+            submessage_transformation_kind: BuiltinCryptoTransformationKind::try_from(
+              shared_key.submessage_transformation_kind,
+            )?,
+            payload_transformation_kind: BuiltinCryptoTransformationKind::try_from(
+              shared_key.payload_transformation_kind,
+            )?,
+            reuse_submessage_key_for_payload: matches!(
+              *key_materials,
+              KeyMaterial_AES_GCM_GMAC_seq::One(_)
+            ),
+          };
+          let shared_key = SharedTopicKey {
+            crypto_handle: remap(shared_key.crypto_handle),
+            key_materials,
+            datawriters: shared_key.datawriters.into_iter().map(&remap).collect(),
+          };
+          Ok((index, shared_key))
+        })
+        .collect::<SecurityResult<_>>()?,
+      rekey_grace_periods: self
+        .rekey_grace_periods
+        .into_iter()
+        .map(|(crypto_handle, secs, nanos)| {
+          (remap(crypto_handle), std::time::Duration::new(secs, nanos))
+        })
+        .collect(),
+      max_blocks_per_session: self
+        .max_blocks_per_session
+        .into_iter()
+        .map(|(crypto_handle, max_blocks)| (remap(crypto_handle), max_blocks))
+        .collect(),
+      reject_replays: self.reject_replays.into_iter().map(&remap).collect(),
+      max_receiver_specific_macs: self
+        .max_receiver_specific_macs
+        .into_iter()
+        .map(|(crypto_handle, max_macs)| {
+          (
+            remap(crypto_handle),
+            usize::try_from(max_macs).unwrap_or(usize::MAX),
+          )
+        })
+        .collect(),
+      key_wrap_participants: self.key_wrap_participants.into_iter().map(&remap).collect(),
+      key_wrap_peers: self.key_wrap_peers.into_iter().map(remap).collect(),
+    })
+  }
+}
+
+impl From<ParticipantSecurityAttributes> for ExportedParticipantAttributes {
+  fn from(attributes: ParticipantSecurityAttributes) -> Self {
+    ExportedParticipantAttributes {
+      allow_unauthenticated_participants: attributes.allow_unauthenticated_participants,
+      is_access_protected: attributes.is_access_protected,
+      is_rtps_protected: attributes.is_rtps_protected,
+      is_discovery_protected: attributes.is_discovery_protected,
+      is_liveliness_protected: attributes.is_liveliness_protected,
+      plugin_participant_attributes: attributes.plugin_participant_attributes.0,
+      ac_participant_properties: attributes.ac_participant_properties,
+    }
+  }
+}
+
+impl From<ExportedParticipantAttributes> for ParticipantSecurityAttributes {
+  fn from(attributes: ExportedParticipantAttributes) -> Self {
+    ParticipantSecurityAttributes {
+      allow_unauthenticated_participants: attributes.allow_unauthenticated_participants,
+      is_access_protected: attributes.is_access_protected,
+      is_rtps_protected: attributes.is_rtps_protected,
+      is_discovery_protected: attributes.is_discovery_protected,
+      is_liveliness_protected: attributes.is_liveliness_protected,
+      plugin_participant_attributes: PluginSecurityAttributesMask(
+        attributes.plugin_participant_attributes,
+      ),
+      ac_participant_properties: attributes.ac_participant_properties,
+    }
+  }
+}
+
+impl From<EndpointSecurityAttributes> for ExportedEndpointAttributes {
+  fn from(attributes: EndpointSecurityAttributes) -> Self {
+    let topic_attributes = attributes.topic_security_attributes;
+    ExportedEndpointAttributes {
+      is_read_protected: topic_attributes.is_read_protected,
+      is_write_protected: topic_attributes.is_write_protected,
+      is_topic_discovery_protected: topic_attributes.is_discovery_protected,
+      is_topic_liveliness_protected: topic_attributes.is_liveliness_protected,
+      is_submessage_protected: attributes.is_submessage_protected,
+      is_payload_protected: attributes.is_payload_protected,
+      is_key_protected: attributes.is_key_protected,
+      plugin_endpoint_attributes: attributes.plugin_endpoint_attributes.0,
+      ac_endpoint_properties: attributes.ac_endpoint_properties,
+    }
+  }
+}
+
+impl From<ExportedEndpointAttributes> for EndpointSecurityAttributes {
+  fn from(attributes: ExportedEndpointAttributes) -> Self {
+    EndpointSecurityAttributes {
+      topic_security_attributes: TopicSecurityAttributes {
+        is_read_protected: attributes.is_read_protected,
+        is_write_protected: attributes.is_write_protected,
+        is_discovery_protected: attributes.is_topic_discovery_protected,
+        is_liveliness_protected: attributes.is_topic_liveliness_protected,
+      },
+      is_submessage_protected: attributes.is_submessage_protected,
+      is_payload_protected: attributes.is_payload_protected,
+      is_key_protected: attributes.is_key_protected,
+      plugin_endpoint_attributes: PluginSecurityAttributesMask(
+        attributes.plugin_endpoint_attributes,
+      ),
+      ac_endpoint_properties: attributes.ac_endpoint_properties,
+    }
+  }
+}
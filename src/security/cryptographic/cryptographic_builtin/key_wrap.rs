@@ -0,0 +1,100 @@
+use openssl::aes::{unwrap_key, wrap_key, AesKey};
+
+use crate::{
+  create_security_error_and_log,
+  security::{SecurityError, SecurityErrorKind, SecurityResult},
+};
+use super::builtin_key::*;
+
+// AES key wrap (RFC 3394) works on 64-bit blocks and adds one block for the
+// integrity check
+const KEY_WRAP_BLOCK_LENGTH: usize = 8;
+// The shortest input the key wrap accepts is two blocks
+const MIN_KEY_WRAP_INPUT_LENGTH: usize = 2 * KEY_WRAP_BLOCK_LENGTH;
+
+// Wraps serialized key material with the key encryption key. The plaintext is
+// padded with zeros to whole blocks, so the decoder of the unwrapped bytes has
+// to ignore trailing zeros, as CDR deserialization does.
+pub(super) fn wrap(key_encryption_key: &BuiltinKey, plaintext: &[u8]) -> SecurityResult<Vec<u8>> {
+  let aes_key = AesKey::new_encrypt(key_encryption_key.as_bytes()).map_err(|_| {
+    create_security_error_and_log!(
+      SecurityErrorKind::Internal,
+      "Invalid key encryption key of {:?}",
+      key_encryption_key.key_length()
+    )
+  })?;
+
+  let padded_length = ((plaintext.len() + KEY_WRAP_BLOCK_LENGTH - 1) / KEY_WRAP_BLOCK_LENGTH
+    * KEY_WRAP_BLOCK_LENGTH)
+    .max(MIN_KEY_WRAP_INPUT_LENGTH);
+  let mut padded = plaintext.to_vec();
+  padded.resize(padded_length, 0);
+
+  let mut wrapped = vec![0; padded_length + KEY_WRAP_BLOCK_LENGTH];
+  wrap_key(&aes_key, None, &mut wrapped, &padded).map_err(|_| {
+    create_security_error_and_log!(SecurityErrorKind::Internal, "AES key wrap failed")
+  })?;
+  Ok(wrapped)
+}
+
+// Reverses wrap. The result keeps the padding.
+pub(super) fn unwrap(key_encryption_key: &BuiltinKey, wrapped: &[u8]) -> SecurityResult<Vec<u8>> {
+  // The key wrap functions panic on inputs that are not whole blocks
+  if wrapped.len() % KEY_WRAP_BLOCK_LENGTH != 0
+    || wrapped.len() < MIN_KEY_WRAP_INPUT_LENGTH + KEY_WRAP_BLOCK_LENGTH
+  {
+    return Err(create_security_error_and_log!(
+      SecurityErrorKind::MalformedToken,
+      "Wrapped key material of {} bytes is not a whole number of {}-byte blocks",
+      wrapped.len(),
+      KEY_WRAP_BLOCK_LENGTH
+    ));
+  }
+  let aes_key = AesKey::new_decrypt(key_encryption_key.as_bytes()).map_err(|_| {
+    create_security_error_and_log!(
+      SecurityErrorKind::Internal,
+      "Invalid key encryption key of {:?}",
+      key_encryption_key.key_length()
+    )
+  })?;
+
+  let mut unwrapped = vec![0; wrapped.len() - KEY_WRAP_BLOCK_LENGTH];
+  unwrap_key(&aes_key, None, &mut unwrapped, wrapped).map_err(|_| {
+    create_security_error_and_log!(
+      SecurityErrorKind::VerificationFailed,
+      "The wrapped key material failed the integrity check"
+    )
+  })?;
+  Ok(unwrapped)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unwrap_reverses_wrap_with_padding() {
+    let key_encryption_key = BuiltinKey::AES256([0x42; AES256_KEY_LENGTH]);
+    let plaintext = [
+      1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+    ];
+
+    let wrapped = wrap(&key_encryption_key, &plaintext).unwrap();
+    assert_eq!(wrapped.len(), 32);
+    let unwrapped = unwrap(&key_encryption_key, &wrapped).unwrap();
+    assert_eq!(unwrapped[..plaintext.len()], plaintext);
+    assert!(unwrapped[plaintext.len()..].iter().all(|byte| *byte == 0));
+
+    let other_key = BuiltinKey::AES256([0x43; AES256_KEY_LENGTH]);
+    assert_eq!(
+      unwrap(&other_key, &wrapped).unwrap_err().kind(),
+      SecurityErrorKind::VerificationFailed
+    );
+    assert_eq!(
+      unwrap(&key_encryption_key, &wrapped[1..])
+        .unwrap_err()
+        .kind(),
+      SecurityErrorKind::MalformedToken
+    );
+  }
+}
@@ -0,0 +1,109 @@
+use super::types::{BuiltinInitializationVector, SessionId};
+
+// Number of initialization vector suffixes below the highest one received that
+// are still accepted, so that messages reordered by the network are not
+// dropped
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+// Anti-replay window of a remote sender key, in the style of RFC 4303 Section
+// 3.4.3. A message is accepted once per initialization vector: its suffix must
+// be above the window or inside it and not seen yet. Session ids are compared
+// with serial number arithmetic (RFC 1982), as senders increment them when
+// starting a new session. A message from an older session is rejected, a
+// message from a newer session restarts the window.
+pub(super) struct ReplayWindow {
+  session_id: SessionId,
+  highest_suffix: u64,
+  // Bit i is set if the suffix highest_suffix - i has been accepted
+  seen: u64,
+}
+
+impl ReplayWindow {
+  pub(super) fn new(initialization_vector: BuiltinInitializationVector) -> Self {
+    Self {
+      session_id: initialization_vector.session_id(),
+      highest_suffix: u64::from_be_bytes(initialization_vector.initialization_vector_suffix()),
+      seen: 1,
+    }
+  }
+
+  // Records the initialization vector of an authenticated message. Returns false
+  // if it is a replay, in which case the message must be dropped.
+  pub(super) fn accept(&mut self, initialization_vector: BuiltinInitializationVector) -> bool {
+    let session_id = initialization_vector.session_id();
+    let suffix = u64::from_be_bytes(initialization_vector.initialization_vector_suffix());
+
+    if session_id != self.session_id {
+      let session_distance = u32::from_be_bytes(session_id.into())
+        .wrapping_sub(u32::from_be_bytes(self.session_id.into()));
+      if (session_distance as i32) < 0 {
+        return false; // An older session
+      }
+      *self = Self::new(initialization_vector);
+      true
+    } else if suffix > self.highest_suffix {
+      let shift = suffix - self.highest_suffix;
+      self.seen = if shift < REPLAY_WINDOW_SIZE {
+        (self.seen << shift) | 1
+      } else {
+        1
+      };
+      self.highest_suffix = suffix;
+      true
+    } else {
+      let offset = self.highest_suffix - suffix;
+      if offset >= REPLAY_WINDOW_SIZE || self.seen & (1 << offset) != 0 {
+        false // Too old to tell, or seen already
+      } else {
+        self.seen |= 1 << offset;
+        true
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn iv(session_id: u32, suffix: u64) -> BuiltinInitializationVector {
+    BuiltinInitializationVector::new(
+      SessionId::new(session_id.to_be_bytes()),
+      suffix.to_be_bytes(),
+    )
+  }
+
+  #[test]
+  fn replays_are_rejected() {
+    let mut window = ReplayWindow::new(iv(7, 0));
+    assert!(!window.accept(iv(7, 0)));
+    assert!(window.accept(iv(7, 1)));
+    assert!(!window.accept(iv(7, 1)));
+    assert!(!window.accept(iv(7, 0)));
+  }
+
+  #[test]
+  fn reordered_messages_inside_the_window_are_accepted_once() {
+    let mut window = ReplayWindow::new(iv(7, 0));
+    assert!(window.accept(iv(7, 10)));
+    assert!(window.accept(iv(7, 5)));
+    assert!(!window.accept(iv(7, 5)));
+    assert!(window.accept(iv(7, 10 + REPLAY_WINDOW_SIZE - 1)));
+    // 10 is now the oldest suffix in the window and 11 is still unseen
+    assert!(!window.accept(iv(7, 10)));
+    assert!(window.accept(iv(7, 11)));
+    // Below the window
+    assert!(!window.accept(iv(7, 9)));
+  }
+
+  #[test]
+  fn sessions_move_forward_only() {
+    let mut window = ReplayWindow::new(iv(u32::MAX, 100));
+    // The session id wraps around to zero
+    assert!(window.accept(iv(0, 0)));
+    assert!(!window.accept(iv(u32::MAX, 101)));
+    assert!(window.accept(iv(0, 1)));
+    assert!(window.accept(iv(1, 0)));
+    assert!(!window.accept(iv(0, 2)));
+  }
+}
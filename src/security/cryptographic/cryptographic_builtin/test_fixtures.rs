@@ -0,0 +1,373 @@
+// Fixtures shared by the tests of the builtin cryptographic plugin: seeded
+// plugins, registered participants, and a datawriter and a datareader matched
+// across two plugins
+
+use std::{collections::HashSet, sync::Arc};
+
+use enumflags2::BitFlags;
+use speedy::Readable;
+
+use super::{types::*, CommonEncodeKeyMaterials, CryptographicBuiltin};
+use crate::{
+  messages::submessages::{
+    elements::crypto_header::CryptoHeader, heartbeat::Heartbeat, secure_postfix::SecurePostfix,
+    secure_prefix::SecurePrefix, submessage_flag::FromEndianness, submessages::SecuritySubmessage,
+  },
+  rtps::{Submessage, SubmessageBody},
+  security::{
+    access_control::{access_control_builtin::types::*, types::*},
+    authentication::types::*,
+    cryptographic::{cryptographic_plugin::*, types::*},
+    entropy::test_sources::SeededEntropySource,
+    types::*,
+  },
+  structure::{guid::EntityId, sequence_number::SequenceNumber},
+};
+
+// Key material and handles drawn from a seeded source are reproducible
+pub(super) fn seeded_crypto(seed: u64) -> CryptographicBuiltin {
+  CryptographicBuiltin::with_entropy_source(Arc::new(SeededEntropySource::new(seed)))
+}
+
+pub(super) fn shared_secret_handle(challenge1: u8) -> SharedSecretHandle {
+  SharedSecretHandle {
+    shared_secret: SharedSecret::from([0x5a; 32]),
+    challenge1: Challenge::from([challenge1; 32]),
+    challenge2: Challenge::from([0x22; 32]),
+  }
+}
+
+pub(super) fn volatile_endpoint_attributes() -> EndpointSecurityAttributes {
+  EndpointSecurityAttributes {
+    is_submessage_protected: true,
+    plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+      is_submessage_encrypted: true,
+      is_submessage_origin_authenticated: false,
+      is_payload_encrypted: false,
+    }
+    .into(),
+    ..EndpointSecurityAttributes::empty()
+  }
+}
+
+pub(super) fn protected_writer_attributes(
+  origin_authenticated: bool,
+) -> EndpointSecurityAttributes {
+  EndpointSecurityAttributes {
+    is_submessage_protected: true,
+    is_payload_protected: true,
+    plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+      is_submessage_encrypted: true,
+      is_submessage_origin_authenticated: origin_authenticated,
+      is_payload_encrypted: true,
+    }
+    .into(),
+    ..EndpointSecurityAttributes::empty()
+  }
+}
+
+// Registers a local participant and a matched remote participant, returns
+// their handles
+pub(super) fn register_participants(
+  crypto: &mut CryptographicBuiltin,
+  shared_secret: SharedSecretHandle,
+) -> (ParticipantCryptoHandle, ParticipantCryptoHandle) {
+  register_participants_with_properties(crypto, shared_secret, &[])
+}
+
+pub(super) fn register_participants_with_properties(
+  crypto: &mut CryptographicBuiltin,
+  shared_secret: SharedSecretHandle,
+  participant_properties: &[Property],
+) -> (ParticipantCryptoHandle, ParticipantCryptoHandle) {
+  let participant_security_attributes = ParticipantSecurityAttributes {
+    plugin_participant_attributes: BuiltinPluginParticipantSecurityAttributes {
+      is_rtps_encrypted: false,
+      is_discovery_encrypted: false,
+      is_liveliness_encrypted: false,
+      is_rtps_origin_authenticated: false,
+      is_discovery_origin_authenticated: false,
+      is_liveliness_origin_authenticated: false,
+    }
+    .into(),
+    ..ParticipantSecurityAttributes::empty()
+  };
+  let local = crypto
+    .register_local_participant(
+      0,
+      0,
+      participant_properties,
+      participant_security_attributes,
+    )
+    .unwrap();
+  let remote = crypto
+    .register_matched_remote_participant(local, 0, 0, shared_secret)
+    .unwrap();
+  (local, remote)
+}
+
+// A local datawriter and datareader matching endpoints in count remote
+// participants of their own
+pub(super) fn local_endpoints_and_remote_participants(
+  seed: u64,
+  origin_authenticated: bool,
+  count: u8,
+) -> (
+  CryptographicBuiltin,
+  DatawriterCryptoHandle,
+  DatareaderCryptoHandle,
+  Vec<ParticipantCryptoHandle>,
+) {
+  let mut crypto = seeded_crypto(seed);
+  let (participant, _) = register_participants(&mut crypto, shared_secret_handle(0x11));
+  let writer = crypto
+    .register_local_datawriter(
+      participant,
+      &[],
+      protected_writer_attributes(origin_authenticated),
+    )
+    .unwrap();
+  let reader = crypto
+    .register_local_datareader(
+      participant,
+      &[],
+      protected_writer_attributes(origin_authenticated),
+    )
+    .unwrap();
+  let remote_participants = (1..=count)
+    .map(|identity| {
+      crypto
+        .register_matched_remote_participant(
+          participant,
+          identity.into(),
+          0,
+          shared_secret_handle(identity),
+        )
+        .unwrap()
+    })
+    .collect();
+  (crypto, writer, reader, remote_participants)
+}
+
+pub(super) fn common_key_ids(
+  crypto: &CryptographicBuiltin,
+  writers: &[(DatawriterCryptoHandle, DatareaderCryptoHandle)],
+) -> HashSet<CryptoTransformKeyId> {
+  writers
+    .iter()
+    .map(
+      |(writer, _)| match crypto.get_common_encode_key_materials(writer).unwrap() {
+        CommonEncodeKeyMaterials::Some(key_materials) => key_materials.key_material().sender_key_id,
+        CommonEncodeKeyMaterials::Volatile => panic!("unexpected volatile writer"),
+      },
+    )
+    .collect()
+}
+
+// Encodes a payload and returns it with the initialization vector from its
+// crypto header
+pub(super) fn encode_payload(
+  crypto: &CryptographicBuiltin,
+  writer: DatawriterCryptoHandle,
+  payload: &[u8],
+) -> (Vec<u8>, BuiltinInitializationVector) {
+  let (encoded, _) = crypto
+    .encode_serialized_payload(payload.to_vec(), writer)
+    .unwrap();
+  let header: BuiltinCryptoHeader = CryptoHeader::read_from_buffer(&encoded)
+    .unwrap()
+    .try_into()
+    .unwrap();
+  (
+    encoded,
+    header.builtin_crypto_header_extra.initialization_vector(),
+  )
+}
+
+pub(super) fn heartbeat() -> Submessage {
+  Heartbeat {
+    reader_id: EntityId::UNKNOWN,
+    writer_id: EntityId::UNKNOWN,
+    first_sn: SequenceNumber::new(1),
+    last_sn: SequenceNumber::new(1),
+    count: 1,
+  }
+  .create_submessage(BitFlags::from_endianness(speedy::Endianness::BigEndian))
+  .unwrap()
+}
+
+// The SecurePrefix, body and SecurePostfix of an encoded submessage, as they
+// are passed to decode_submessage
+pub(super) fn encoded_parts(
+  encoded: &EncodedSubmessage,
+) -> (SecurePrefix, Submessage, SecurePostfix) {
+  match encoded {
+    EncodedSubmessage::Encoded(
+      Submessage {
+        body: SubmessageBody::Security(SecuritySubmessage::SecurePrefix(secure_prefix, _)),
+        ..
+      },
+      body,
+      Submessage {
+        body: SubmessageBody::Security(SecuritySubmessage::SecurePostfix(secure_postfix, _)),
+        ..
+      },
+    ) => (secure_prefix.clone(), body.clone(), secure_postfix.clone()),
+    _ => panic!("the submessage was not encoded"),
+  }
+}
+
+// Properties of the endpoints in MatchedEndpoints and of their participants
+#[derive(Clone, Copy, Default)]
+pub(super) struct MatchProperties<'a> {
+  pub writer: &'a [Property],
+  pub reader: &'a [Property],
+  pub writer_participant: &'a [Property],
+  pub reader_participant: &'a [Property],
+}
+
+// A local datawriter in one plugin matched with a local datareader in another,
+// as discovery leaves them before the crypto tokens are exchanged
+pub(super) struct MatchedEndpoints {
+  pub writer_side: CryptographicBuiltin,
+  pub writer_participant: ParticipantCryptoHandle,
+  pub remote_reader_participant: ParticipantCryptoHandle,
+  pub writer: DatawriterCryptoHandle,
+  pub remote_reader: DatareaderCryptoHandle,
+  pub reader_side: CryptographicBuiltin,
+  pub reader_participant: ParticipantCryptoHandle,
+  pub remote_writer_participant: ParticipantCryptoHandle,
+  pub reader: DatareaderCryptoHandle,
+  pub remote_writer: DatawriterCryptoHandle,
+}
+
+impl MatchedEndpoints {
+  // Both endpoints have the given attributes and no properties
+  pub(super) fn new(attributes: EndpointSecurityAttributes) -> Self {
+    Self::with_properties(attributes, MatchProperties::default())
+  }
+
+  pub(super) fn with_properties(
+    attributes: EndpointSecurityAttributes,
+    properties: MatchProperties,
+  ) -> Self {
+    let mut writer_side = seeded_crypto(1);
+    let (writer_participant, remote_reader_participant) = register_participants_with_properties(
+      &mut writer_side,
+      shared_secret_handle(0x11),
+      properties.writer_participant,
+    );
+    let writer = writer_side
+      .register_local_datawriter(writer_participant, properties.writer, attributes.clone())
+      .unwrap();
+    let remote_reader = writer_side
+      .register_matched_remote_datareader(
+        writer,
+        remote_reader_participant,
+        shared_secret_handle(0x11),
+        false,
+      )
+      .unwrap();
+
+    let mut reader_side = seeded_crypto(2);
+    let (reader_participant, remote_writer_participant) = register_participants_with_properties(
+      &mut reader_side,
+      shared_secret_handle(0x11),
+      properties.reader_participant,
+    );
+    let reader = reader_side
+      .register_local_datareader(reader_participant, properties.reader, attributes)
+      .unwrap();
+    let remote_writer = reader_side
+      .register_matched_remote_datawriter(
+        reader,
+        remote_writer_participant,
+        shared_secret_handle(0x11),
+      )
+      .unwrap();
+
+    Self {
+      writer_side,
+      writer_participant,
+      remote_reader_participant,
+      writer,
+      remote_reader,
+      reader_side,
+      reader_participant,
+      remote_writer_participant,
+      reader,
+      remote_writer,
+    }
+  }
+
+  // Sends the participant crypto tokens of the writer side to the reader side
+  // and then the other way round. Returns the tokens in the order they were
+  // sent.
+  pub(super) fn exchange_participant_tokens(&mut self) -> [Vec<CryptoToken>; 2] {
+    let writer_side_tokens = self
+      .writer_side
+      .create_local_participant_crypto_tokens(
+        self.writer_participant,
+        self.remote_reader_participant,
+      )
+      .unwrap();
+    self
+      .reader_side
+      .set_remote_participant_crypto_tokens(
+        self.reader_participant,
+        self.remote_writer_participant,
+        writer_side_tokens.clone(),
+      )
+      .unwrap();
+    let reader_side_tokens = self
+      .reader_side
+      .create_local_participant_crypto_tokens(
+        self.reader_participant,
+        self.remote_writer_participant,
+      )
+      .unwrap();
+    self
+      .writer_side
+      .set_remote_participant_crypto_tokens(
+        self.writer_participant,
+        self.remote_reader_participant,
+        reader_side_tokens.clone(),
+      )
+      .unwrap();
+    [writer_side_tokens, reader_side_tokens]
+  }
+
+  // Sends the crypto tokens of the datawriter to the datareader
+  pub(super) fn send_writer_tokens(&mut self) {
+    let tokens = self
+      .writer_side
+      .create_local_datawriter_crypto_tokens(self.writer, self.remote_reader)
+      .unwrap();
+    self
+      .reader_side
+      .set_remote_datawriter_crypto_tokens(self.reader, self.remote_writer, tokens)
+      .unwrap();
+  }
+
+  // Sends the crypto tokens of the datareader to the datawriter
+  pub(super) fn send_reader_tokens(&mut self) {
+    let tokens = self
+      .reader_side
+      .create_local_datareader_crypto_tokens(self.reader, self.remote_writer)
+      .unwrap();
+    self
+      .writer_side
+      .set_remote_datareader_crypto_tokens(self.writer, self.remote_reader, tokens)
+      .unwrap();
+  }
+
+  // A heartbeat of the datawriter, encoded for the datareader
+  pub(super) fn encode_heartbeat(&self) -> (SecurePrefix, Submessage, SecurePostfix) {
+    encoded_parts(
+      &self
+        .writer_side
+        .encode_datawriter_submessage(heartbeat(), self.writer, vec![self.remote_reader])
+        .unwrap(),
+    )
+  }
+}
@@ -0,0 +1,202 @@
+// Known-answer tests of the AES-GCM-GMAC transform. The vectors in
+// test_vectors/ were computed independently of this crate by generate.py,
+// which also describes their format. They pin the exact bytes of the
+// CryptoHeader, CryptoContent and CryptoFooter, so that a change in the
+// output format shows up here before it breaks interoperability.
+
+use std::sync::Arc;
+
+use super::{
+  builtin_key::*, key_material::*, test_fixtures::*, types::*, CommonEncodeKeyMaterials,
+};
+use crate::{
+  messages::submessages::elements::parameter_list::ParameterList,
+  security::{
+    access_control::{access_control_builtin::types::*, types::*},
+    cryptographic::{cryptographic_plugin::*, types::*},
+    types::*,
+  },
+};
+
+struct TestVector {
+  master_sender_key: Vec<u8>,
+  master_salt: Vec<u8>,
+  sender_key_id: [u8; 4],
+  session_id: [u8; 4],
+  initialization_vector_suffix: [u8; 8],
+  plaintext: Vec<u8>,
+  crypto_header: Vec<u8>,
+  // The CryptoContent when encrypting, the plaintext when only signing
+  body: Vec<u8>,
+  crypto_footer: Vec<u8>,
+}
+
+// Reads a field of a fixture: a big-endian u32 length and that many bytes
+fn field(fixture: &mut &[u8]) -> Vec<u8> {
+  let (length, rest) = fixture.split_at(4);
+  let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+  let (field, rest) = rest.split_at(length);
+  *fixture = rest;
+  field.to_vec()
+}
+
+// Reads a fixture, which is a sequence of vectors
+fn load(mut fixture: &[u8]) -> Vec<TestVector> {
+  let mut vectors = Vec::new();
+  while !fixture.is_empty() {
+    let fixture = &mut fixture;
+    vectors.push(TestVector {
+      master_sender_key: field(fixture),
+      master_salt: field(fixture),
+      sender_key_id: field(fixture).try_into().unwrap(),
+      session_id: field(fixture).try_into().unwrap(),
+      initialization_vector_suffix: field(fixture).try_into().unwrap(),
+      plaintext: field(fixture),
+      crypto_header: field(fixture),
+      body: field(fixture),
+      crypto_footer: field(fixture),
+    });
+  }
+  vectors
+}
+
+// Checks that a local datawriter with the key material of the vector encodes
+// the plaintext to the expected bytes, and that a matched remote datareader
+// decodes the expected bytes to the plaintext
+fn check(transformation_kind: BuiltinCryptoTransformationKind, fixture: &[u8]) {
+  let is_encrypted = matches!(
+    transformation_kind,
+    BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM
+      | BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM
+  );
+  let key_length = KeyLength::from(transformation_kind);
+  let properties = [Property {
+    name: "dds.sec.crypto.keysize".to_string(),
+    value: match key_length {
+      KeyLength::AES128 => "128",
+      _ => "256",
+    }
+    .to_string(),
+    propagate: false,
+  }];
+  let attributes = EndpointSecurityAttributes {
+    is_submessage_protected: true,
+    is_payload_protected: true,
+    plugin_endpoint_attributes: BuiltinPluginEndpointSecurityAttributes {
+      is_submessage_encrypted: is_encrypted,
+      is_submessage_origin_authenticated: false,
+      is_payload_encrypted: is_encrypted,
+    }
+    .into(),
+    ..EndpointSecurityAttributes::empty()
+  };
+
+  let vectors = load(fixture);
+  assert!(!vectors.is_empty());
+  for (index, vector) in vectors.into_iter().enumerate() {
+    let sender_key_id = CryptoTransformKeyId::from(vector.sender_key_id);
+
+    let mut endpoints = MatchedEndpoints::with_properties(
+      attributes.clone(),
+      MatchProperties {
+        writer: &properties,
+        reader: &properties,
+        ..MatchProperties::default()
+      },
+    );
+    // Replace the generated key material with the one of the vector, also
+    // where the matched remote datareader shares it
+    let key_materials = Arc::new(KeyMaterial_AES_GCM_GMAC_seq::One(
+      KeyMaterial_AES_GCM_GMAC {
+        transformation_kind,
+        master_salt: BuiltinKey::from_bytes(key_length, &vector.master_salt).unwrap(),
+        sender_key_id,
+        master_sender_key: BuiltinKey::from_bytes(key_length, &vector.master_sender_key).unwrap(),
+        receiver_specific_key_id: CryptoTransformKeyId::ZERO,
+        master_receiver_specific_key: BuiltinKey::None,
+      },
+    ));
+    endpoints.writer_side.common_encode_key_materials.insert(
+      endpoints.writer,
+      CommonEncodeKeyMaterials::Some(key_materials.clone()),
+    );
+    endpoints
+      .writer_side
+      .receiver_specific_encode_key_materials
+      .insert(endpoints.remote_reader, key_materials);
+    endpoints.send_writer_tokens();
+    let MatchedEndpoints {
+      writer_side,
+      writer,
+      reader_side,
+      reader,
+      remote_writer,
+      ..
+    } = endpoints;
+
+    writer_side.start_encode_session(
+      writer,
+      sender_key_id,
+      SessionId::new(vector.session_id),
+      vector.initialization_vector_suffix,
+    );
+    let (encoded, _) = writer_side
+      .encode_serialized_payload(vector.plaintext.clone(), writer)
+      .unwrap();
+    let (crypto_header, rest) = encoded.split_at(vector.crypto_header.len().min(encoded.len()));
+    let (body, crypto_footer) = rest.split_at(vector.body.len().min(rest.len()));
+    assert_eq!(
+      crypto_header, vector.crypto_header,
+      "CryptoHeader of vector {index} of {transformation_kind}"
+    );
+    assert_eq!(
+      body, vector.body,
+      "body of vector {index} of {transformation_kind}"
+    );
+    assert_eq!(
+      crypto_footer, vector.crypto_footer,
+      "CryptoFooter of vector {index} of {transformation_kind}"
+    );
+
+    let expected = [vector.crypto_header, vector.body, vector.crypto_footer].concat();
+    assert_eq!(
+      reader_side
+        .decode_serialized_payload(expected, ParameterList::new(), reader, remote_writer)
+        .unwrap(),
+      vector.plaintext,
+      "decoding vector {index} of {transformation_kind}"
+    );
+  }
+}
+
+#[test]
+fn aes128_gmac_known_answers() {
+  check(
+    BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC,
+    include_bytes!("test_vectors/aes128_gmac.bin"),
+  );
+}
+
+#[test]
+fn aes128_gcm_known_answers() {
+  check(
+    BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+    include_bytes!("test_vectors/aes128_gcm.bin"),
+  );
+}
+
+#[test]
+fn aes256_gmac_known_answers() {
+  check(
+    BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC,
+    include_bytes!("test_vectors/aes256_gmac.bin"),
+  );
+}
+
+#[test]
+fn aes256_gcm_known_answers() {
+  check(
+    BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+    include_bytes!("test_vectors/aes256_gcm.bin"),
+  );
+}
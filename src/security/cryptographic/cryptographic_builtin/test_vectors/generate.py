@@ -0,0 +1,106 @@
+#!/usr/bin/env python3
+"""Generates the known-answer vectors of the builtin AES-GCM-GMAC transform.
+
+The expected bytes are computed independently of RustDDS with the pyca/cryptography
+package, following section 9.5.3.3 of the DDS Security specification (v. 1.1):
+
+  SessionKey = HMAC-SHA256(MasterSenderKey, "SessionKey" | MasterSalt | SessionId),
+               truncated to the key length
+  IV         = SessionId | InitializationVectorSuffix
+  GCM        : CryptoContent = ciphertext, common MAC = tag, no AAD
+  GMAC       : the plaintext is sent as is, common MAC = AES-GCM tag with the
+               plaintext as AAD and an empty plaintext
+
+Each output file holds the vectors of one transformation kind. A vector is a
+sequence of fields, each a big-endian u32 length followed by that many bytes:
+
+  master_sender_key, master_salt, sender_key_id, session_id,
+  initialization_vector_suffix, plaintext,
+  crypto_header, body, crypto_footer
+
+where body is the CryptoContent for GCM and the plaintext for GMAC.
+
+Usage: python3 generate.py (writes the .bin files next to this script)
+"""
+
+import hashlib
+import hmac
+import os
+import struct
+
+from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+KINDS = {
+    # file name: (CryptoTransformKind, key length, encrypt)
+    "aes128_gmac": (1, 16, False),
+    "aes128_gcm": (2, 16, True),
+    "aes256_gmac": (3, 32, False),
+    "aes256_gcm": (4, 32, True),
+}
+
+PLAINTEXTS = [
+    b"",
+    # A serialized payload: CDR_BE encapsulation and a string
+    bytes([0, 0, 0, 0]) + struct.pack(">I", 24) + b"Interop test payload 01\x00",
+    bytes(range(256)),
+]
+
+
+def field(data):
+    return struct.pack(">I", len(data)) + data
+
+
+def vector(kind, key_length, encrypt, index, plaintext):
+    master_sender_key = bytes((kind * 0x10 + index + i) % 256 for i in range(key_length))
+    master_salt = bytes((0xA0 + kind * 3 + index * 7 + i * 5) % 256 for i in range(key_length))
+    sender_key_id = bytes([0x4B, 0x45, kind, index])
+    session_id = bytes([0x00, 0x00, 0x10 + kind, 0x20 + index])
+    initialization_vector_suffix = bytes([0, 0, 0, 0, 0, 0, kind, index])
+
+    session_key = hmac.new(
+        master_sender_key,
+        b"SessionKey" + master_salt + session_id,
+        hashlib.sha256,
+    ).digest()[:key_length]
+    initialization_vector = session_id + initialization_vector_suffix
+    aead = AESGCM(session_key)
+    if encrypt:
+        sealed = aead.encrypt(initialization_vector, plaintext, None)
+        ciphertext, mac = sealed[:-16], sealed[-16:]
+        body = struct.pack(">I", len(ciphertext)) + ciphertext
+    else:
+        mac = aead.encrypt(initialization_vector, b"", plaintext)
+        body = plaintext
+
+    crypto_header = (
+        bytes([0, 0, 0, kind]) + sender_key_id + session_id + initialization_vector_suffix
+    )
+    # The common MAC and an empty sequence of receiver-specific MACs
+    crypto_footer = mac + struct.pack(">I", 0)
+
+    return b"".join(
+        field(data)
+        for data in [
+            master_sender_key,
+            master_salt,
+            sender_key_id,
+            session_id,
+            initialization_vector_suffix,
+            plaintext,
+            crypto_header,
+            body,
+            crypto_footer,
+        ]
+    )
+
+
+def main():
+    directory = os.path.dirname(os.path.abspath(__file__))
+    for name, (kind, key_length, encrypt) in KINDS.items():
+        with open(os.path.join(directory, name + ".bin"), "wb") as file:
+            for index, plaintext in enumerate(PLAINTEXTS):
+                file.write(vector(kind, key_length, encrypt, index, plaintext))
+
+
+if __name__ == "__main__":
+    main()
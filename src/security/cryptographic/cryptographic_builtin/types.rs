@@ -1,4 +1,7 @@
 use byteorder::BigEndian;
+use bytes::Bytes;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
 use speedy::Readable;
 
@@ -8,98 +11,227 @@ use crate::{
     crypto_footer::CryptoFooter,
     crypto_header::{CryptoHeader, PluginCryptoHeaderExtra},
   },
-  security::{cryptographic::EndpointCryptoHandle, BinaryProperty, DataHolder, SecurityError},
+  security::{
+    cryptographic::EndpointCryptoHandle, BinaryProperty, DataHolder, Property, SecurityError,
+    SecurityErrorKind, SecurityResult,
+  },
   serialization::to_vec,
   CdrDeserializer,
 };
 use super::{
-  key_material::*, CryptoToken, CryptoTransformIdentifier, CryptoTransformKeyId,
-  CryptoTransformKind,
+  builtin_key::BuiltinKey, key_material::*, key_wrap, CryptoToken, CryptoTransformIdentifier,
+  CryptoTransformKeyId, CryptoTransformKind,
 };
 
 const CRYPTO_TOKEN_CLASS_ID: &str = "DDS:Crypto:AES_GCM_GMAC";
 const CRYPTO_TOKEN_KEY_MATERIAL_NAME: &str = "dds.cryp.keymat";
+const CRYPTO_TOKEN_WRAPPED_KEY_MATERIAL_NAME: &str = "dds.cryp.keymat.wrapped";
+// Property of the participant crypto tokens of a participant that wraps key
+// material. Key material is sent wrapped only to participants that announce it.
+const CRYPTO_TOKEN_KEY_WRAP_NAME: &str = "dds.cryp.keymat.wrap";
+
+pub(super) fn announce_key_wrap(token: &mut CryptoToken) {
+  token.data_holder.properties.push(Property {
+    name: String::from(CRYPTO_TOKEN_KEY_WRAP_NAME),
+    value: String::from("true"),
+    propagate: true,
+  });
+}
+
+pub(super) fn announces_key_wrap(tokens: &[CryptoToken]) -> bool {
+  tokens.iter().any(|token| {
+    token
+      .data_holder
+      .properties
+      .iter()
+      .any(|property| property.name == CRYPTO_TOKEN_KEY_WRAP_NAME && property.value == "true")
+  })
+}
 
 /// DDS:Crypto:AES-GCM-GMAC CryptoToken type from section 9.5.2.1 of the
 /// Security specification (v. 1.1)
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub(super) struct BuiltinCryptoToken {
-  pub key_material: KeyMaterial_AES_GCM_GMAC,
+  pub key_material: TokenKeyMaterial,
+  // Properties that other implementations attach to the token. They are not
+  // used, but kept so that the token converts back unchanged.
+  pub extra_properties: Vec<Property>,
+  pub extra_binary_properties: Vec<BinaryProperty>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub(super) enum TokenKeyMaterial {
+  KeyMaterial(KeyMaterial_AES_GCM_GMAC),
+  // The serialized key material wrapped with the KxKey of the participant pair,
+  // see section 9.5.2.3.2
+  WrappedKeyMaterial(Bytes),
+}
+
+impl BuiltinCryptoToken {
+  pub fn wrap(
+    key_material: KeyMaterial_AES_GCM_GMAC,
+    key_exchange_key: &BuiltinKey,
+  ) -> SecurityResult<Self> {
+    let serialized = Bytes::try_from(key_material)?;
+    key_wrap::wrap(key_exchange_key, &serialized)
+      .map(|wrapped| TokenKeyMaterial::WrappedKeyMaterial(Bytes::from(wrapped)).into())
+  }
+
+  // Returns the key material, unwrapping it if needed. Tokens from
+  // implementations that do not wrap key material are accepted as they are.
+  pub fn unwrap(self, key_exchange_key: &BuiltinKey) -> SecurityResult<KeyMaterial_AES_GCM_GMAC> {
+    match self.key_material {
+      TokenKeyMaterial::KeyMaterial(key_material) => Ok(key_material),
+      TokenKeyMaterial::WrappedKeyMaterial(wrapped) => key_wrap::unwrap(key_exchange_key, &wrapped)
+        // The CDR deserializer ignores the padding of the key wrap
+        .and_then(|serialized| KeyMaterial_AES_GCM_GMAC::try_from(Bytes::from(serialized))),
+    }
+  }
+}
+
+impl std::fmt::Debug for TokenKeyMaterial {
+  // Wrapped key material is printed by its length only
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::KeyMaterial(key_material) => f.debug_tuple("KeyMaterial").field(key_material).finish(),
+      Self::WrappedKeyMaterial(wrapped) => {
+        write!(f, "WrappedKeyMaterial(<{} bytes>)", wrapped.len())
+      }
+    }
+  }
 }
+
+// Tokens of other implementations may carry extra properties and binary
+// properties, which are ignored. Only the key material binary property is
+// required, and there must be exactly one.
 impl TryFrom<CryptoToken> for BuiltinCryptoToken {
   type Error = SecurityError;
   fn try_from(value: CryptoToken) -> Result<Self, Self::Error> {
-    let dh = value.data_holder;
-    match (
-      dh.class_id.as_str(),
-      dh.properties.as_slice(),
-      dh.binary_properties.as_slice(),
-    ) {
-      (CRYPTO_TOKEN_CLASS_ID, [], [bp0]) => {
-        if bp0.name.eq(CRYPTO_TOKEN_KEY_MATERIAL_NAME) {
-          Ok(Self {
-            key_material: KeyMaterial_AES_GCM_GMAC::try_from(bp0.value.clone())?,
-          })
-        } else {
-          Err(Self::Error {
-            msg: format!(
-              "The binary property of CryptoToken has the wrong name. Expected {}, got {}.",
-              CRYPTO_TOKEN_KEY_MATERIAL_NAME, bp0.name
-            ),
-          })
-        }
-      }
-
-      (CRYPTO_TOKEN_CLASS_ID, [], _) => Err(Self::Error {
-        msg: String::from(
-          "CryptoToken has wrong binary_properties. Expected exactly 1 binary property.",
-        ),
-      }),
-      (CRYPTO_TOKEN_CLASS_ID, _, _) => Err(Self::Error {
-        msg: String::from("CryptoToken has wrong properties. Expected properties to be empty."),
-      }),
-
-      (cid, _, _) => Err(Self::Error {
+    let DataHolder {
+      class_id,
+      properties: extra_properties,
+      binary_properties,
+    } = value.data_holder;
+    if class_id != CRYPTO_TOKEN_CLASS_ID {
+      return Err(Self::Error {
         msg: format!(
           "CryptoToken has wrong class_id. Expected {}, got {}",
-          CRYPTO_TOKEN_CLASS_ID, cid
+          CRYPTO_TOKEN_CLASS_ID, class_id
         ),
-      }),
+        kind: SecurityErrorKind::MalformedToken,
+      });
+    }
+
+    let (key_material_properties, extra_binary_properties): (Vec<_>, Vec<_>) =
+      binary_properties.into_iter().partition(|binary_property| {
+        [
+          CRYPTO_TOKEN_KEY_MATERIAL_NAME,
+          CRYPTO_TOKEN_WRAPPED_KEY_MATERIAL_NAME,
+        ]
+        .contains(&binary_property.name.as_str())
+      });
+    for name in extra_properties
+      .iter()
+      .map(|property| &property.name)
+      .filter(|name| *name != CRYPTO_TOKEN_KEY_WRAP_NAME)
+      .chain(
+        extra_binary_properties
+          .iter()
+          .map(|property| &property.name),
+      )
+    {
+      debug!("Ignoring the unknown property {name} of a CryptoToken");
     }
+
+    let key_material = match key_material_properties.as_slice() {
+      [key_material_property] => {
+        if key_material_property.name == CRYPTO_TOKEN_KEY_MATERIAL_NAME {
+          TokenKeyMaterial::KeyMaterial(KeyMaterial_AES_GCM_GMAC::try_from(
+            key_material_property.value.clone(),
+          )?)
+        } else {
+          TokenKeyMaterial::WrappedKeyMaterial(key_material_property.value.clone())
+        }
+      }
+      [] => {
+        return Err(Self::Error {
+          msg: format!(
+            "CryptoToken has no key material. Expected a binary property {} or {}.",
+            CRYPTO_TOKEN_KEY_MATERIAL_NAME, CRYPTO_TOKEN_WRAPPED_KEY_MATERIAL_NAME
+          ),
+          kind: SecurityErrorKind::MalformedToken,
+        })
+      }
+      _ => {
+        return Err(Self::Error {
+          msg: String::from("CryptoToken has more than one key material binary property."),
+          kind: SecurityErrorKind::MalformedToken,
+        })
+      }
+    };
+
+    Ok(Self {
+      key_material,
+      extra_properties,
+      extra_binary_properties,
+    })
   }
 }
 
 impl TryFrom<BuiltinCryptoToken> for CryptoToken {
   type Error = SecurityError;
-  fn try_from(value: BuiltinCryptoToken) -> Result<Self, Self::Error> {
+  fn try_from(
+    BuiltinCryptoToken {
+      key_material,
+      extra_properties,
+      extra_binary_properties,
+    }: BuiltinCryptoToken,
+  ) -> Result<Self, Self::Error> {
+    let (name, value) = match key_material {
+      TokenKeyMaterial::KeyMaterial(key_material) => {
+        (CRYPTO_TOKEN_KEY_MATERIAL_NAME, key_material.try_into()?)
+      }
+      TokenKeyMaterial::WrappedKeyMaterial(wrapped) => {
+        (CRYPTO_TOKEN_WRAPPED_KEY_MATERIAL_NAME, wrapped)
+      }
+    };
     Ok(CryptoToken {
       data_holder: DataHolder {
         class_id: String::from(CRYPTO_TOKEN_CLASS_ID),
-        properties: Vec::new(),
-        binary_properties: Vec::from([BinaryProperty {
-          name: String::from(CRYPTO_TOKEN_KEY_MATERIAL_NAME),
-          value: value.key_material.try_into()?,
+        properties: extra_properties,
+        binary_properties: [BinaryProperty {
+          name: String::from(name),
+          value,
           propagate: true,
-        }]),
+        }]
+        .into_iter()
+        .chain(extra_binary_properties)
+        .collect(),
       },
     })
   }
 }
 
-impl From<KeyMaterial_AES_GCM_GMAC> for BuiltinCryptoToken {
-  fn from(key_material: KeyMaterial_AES_GCM_GMAC) -> Self {
-    Self { key_material }
+impl From<TokenKeyMaterial> for BuiltinCryptoToken {
+  fn from(key_material: TokenKeyMaterial) -> Self {
+    Self {
+      key_material,
+      extra_properties: Vec::new(),
+      extra_binary_properties: Vec::new(),
+    }
   }
 }
-impl From<BuiltinCryptoToken> for KeyMaterial_AES_GCM_GMAC {
-  fn from(BuiltinCryptoToken { key_material }: BuiltinCryptoToken) -> Self {
-    key_material
+
+impl From<KeyMaterial_AES_GCM_GMAC> for BuiltinCryptoToken {
+  fn from(key_material: KeyMaterial_AES_GCM_GMAC) -> Self {
+    TokenKeyMaterial::KeyMaterial(key_material).into()
   }
 }
 
 /// Valid values for CryptoTransformKind from section 9.5.2.1.1 of the Security
 /// specification (v. 1.1)
 #[allow(non_camel_case_types)] // We use the names from the spec
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub(crate) enum BuiltinCryptoTransformationKind {
   CRYPTO_TRANSFORMATION_KIND_NONE,
   CRYPTO_TRANSFORMATION_KIND_AES128_GMAC,
@@ -118,6 +250,7 @@ impl TryFrom<CryptoTransformKind> for BuiltinCryptoTransformationKind {
       [0, 0, 0, 4] => Ok(Self::CRYPTO_TRANSFORMATION_KIND_AES256_GCM),
       _ => Err(Self::Error {
         msg: String::from("Invalid CryptoTransformKind"),
+        kind: SecurityErrorKind::MalformedToken,
       }),
     }
   }
@@ -134,8 +267,115 @@ impl From<BuiltinCryptoTransformationKind> for CryptoTransformKind {
   }
 }
 
+impl BuiltinCryptoTransformationKind {
+  const ALL: [Self; 5] = [
+    Self::CRYPTO_TRANSFORMATION_KIND_NONE,
+    Self::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC,
+    Self::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+    Self::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC,
+    Self::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+  ];
+
+  /// The name of the transformation kind in the specification
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::CRYPTO_TRANSFORMATION_KIND_NONE => "CRYPTO_TRANSFORMATION_KIND_NONE",
+      Self::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC => "CRYPTO_TRANSFORMATION_KIND_AES128_GMAC",
+      Self::CRYPTO_TRANSFORMATION_KIND_AES128_GCM => "CRYPTO_TRANSFORMATION_KIND_AES128_GCM",
+      Self::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC => "CRYPTO_TRANSFORMATION_KIND_AES256_GMAC",
+      Self::CRYPTO_TRANSFORMATION_KIND_AES256_GCM => "CRYPTO_TRANSFORMATION_KIND_AES256_GCM",
+    }
+  }
+
+  /// The transformation kind that gives the protection described by the
+  /// is_{aspect}_protected and is_{aspect}_encrypted security attributes
+  pub fn from_protection(is_protected: bool, is_encrypted: bool, use_256_bit_key: bool) -> Self {
+    match (is_protected, is_encrypted, use_256_bit_key) {
+      (false, _, _) => Self::CRYPTO_TRANSFORMATION_KIND_NONE,
+      (true, false, false) => Self::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC,
+      (true, false, true) => Self::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC,
+      (true, true, false) => Self::CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+      (true, true, true) => Self::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+    }
+  }
+
+  /// The transformation kind for a protection kind of the governance document,
+  /// such as "SIGN" or "ENCRYPT_WITH_ORIGIN_AUTHENTICATION", and a key size in
+  /// bits. Origin authentication does not affect the transformation kind.
+  pub fn from_protection_kind(protection_kind: &str, key_size: u32) -> SecurityResult<Self> {
+    let (is_protected, is_encrypted) = match protection_kind {
+      "NONE" => (false, false),
+      "SIGN" | "SIGN_WITH_ORIGIN_AUTHENTICATION" => (true, false),
+      "ENCRYPT" | "ENCRYPT_WITH_ORIGIN_AUTHENTICATION" => (true, true),
+      other => {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::UnsupportedTransformation,
+          "Unknown protection kind {:?}",
+          other
+        ))
+      }
+    };
+    let use_256_bit_key = match key_size {
+      128 => false,
+      256 => true,
+      other => {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::UnsupportedTransformation,
+          "Unsupported key size {}, expected 128 or 256",
+          other
+        ))
+      }
+    };
+    Ok(Self::from_protection(
+      is_protected,
+      is_encrypted,
+      use_256_bit_key,
+    ))
+  }
+
+  /// Whether the transformation kind protects at least as well as `other`.
+  /// Encrypting is stronger than signing, which is stronger than no
+  /// protection, and 256-bit keys are stronger than 128-bit keys.
+  pub fn is_at_least_as_strong_as(self, other: Self) -> bool {
+    let (level, key_bits) = self.strength();
+    let (other_level, other_key_bits) = other.strength();
+    level >= other_level && key_bits >= other_key_bits
+  }
+
+  fn strength(self) -> (u8, u16) {
+    match self {
+      Self::CRYPTO_TRANSFORMATION_KIND_NONE => (0, 0),
+      Self::CRYPTO_TRANSFORMATION_KIND_AES128_GMAC => (1, 128),
+      Self::CRYPTO_TRANSFORMATION_KIND_AES256_GMAC => (1, 256),
+      Self::CRYPTO_TRANSFORMATION_KIND_AES128_GCM => (2, 128),
+      Self::CRYPTO_TRANSFORMATION_KIND_AES256_GCM => (2, 256),
+    }
+  }
+}
+impl std::fmt::Display for BuiltinCryptoTransformationKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.name())
+  }
+}
+impl std::str::FromStr for BuiltinCryptoTransformationKind {
+  type Err = SecurityError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::ALL
+      .into_iter()
+      .find(|kind| kind.name() == s)
+      .ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::UnsupportedTransformation,
+          "Unknown transformation kind {:?}",
+          s
+        )
+      })
+  }
+}
+
 /// CryptoTransformIdentifier type from section 9.5.2.2 of the Security
 /// specification (v. 1.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(super) struct BuiltinCryptoTransformIdentifier {
   pub transformation_kind: BuiltinCryptoTransformationKind,
   pub transformation_key_id: CryptoTransformKeyId,
@@ -170,7 +410,7 @@ impl From<BuiltinCryptoTransformIdentifier> for CryptoTransformIdentifier {
 /// consists of the session_id and initialization_vector_suffix. 9.5.2.3
 pub(super) const INITIALIZATION_VECTOR_LENGTH: usize = 12;
 
-#[derive(Debug, Clone, Copy, Readable)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Readable)]
 pub(super) struct BuiltinInitializationVector([u8; INITIALIZATION_VECTOR_LENGTH]);
 
 impl BuiltinInitializationVector {
@@ -190,10 +430,10 @@ impl BuiltinInitializationVector {
     // Succeeds as the slice length is 4
     SessionId::new(<[u8; 4]>::try_from(&self.0[..4]).unwrap())
   }
-  // pub(super) fn initialization_vector_suffix(&self) -> [u8; 8] {
-  //   // Succeeds as the slice length is 12-4=8
-  //   <[u8; 8]>::try_from(&self.0[4..]).unwrap()
-  // }
+  pub(super) fn initialization_vector_suffix(&self) -> [u8; 8] {
+    // Succeeds as the slice length is 12-4=8
+    <[u8; 8]>::try_from(&self.0[4..]).unwrap()
+  }
 
   pub fn try_from_slice(s: impl AsRef<[u8]>) -> Result<Self, std::array::TryFromSliceError> {
     Ok(Self(<[u8; INITIALIZATION_VECTOR_LENGTH]>::try_from(
@@ -208,7 +448,7 @@ impl From<BuiltinInitializationVector> for [u8; INITIALIZATION_VECTOR_LENGTH] {
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct SessionId([u8; 4]);
 
 impl SessionId {
@@ -221,7 +461,13 @@ impl SessionId {
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl From<SessionId> for [u8; 4] {
+  fn from(value: SessionId) -> [u8; 4] {
+    value.0
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct BuiltinCryptoHeaderExtra(pub(super) BuiltinInitializationVector);
 
 /// Methods for getting the contained data
@@ -279,6 +525,7 @@ impl TryFrom<PluginCryptoHeaderExtra> for BuiltinCryptoHeaderExtra {
     BuiltinInitializationVector::try_from_slice(data)
       .map_err(|_| {
         create_security_error_and_log!(
+          SecurityErrorKind::MalformedToken,
           "plugin_crypto_header_extra was of length {}. Expected {}.",
           plugin_crypto_header_length,
           INITIALIZATION_VECTOR_LENGTH
@@ -291,6 +538,7 @@ impl TryFrom<PluginCryptoHeaderExtra> for BuiltinCryptoHeaderExtra {
 
 /// CryptoHeader type from section 9.5.2.3 of the Security specification (v.
 /// 1.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(super) struct BuiltinCryptoHeader {
   pub transform_identifier: BuiltinCryptoTransformIdentifier, // 4+4 bytes
   pub builtin_crypto_header_extra: BuiltinCryptoHeaderExtra,  // 4+8 bytes
@@ -340,6 +588,12 @@ impl From<BuiltinCryptoHeader> for CryptoHeader {
 pub(super) const MAC_LENGTH: usize = 16;
 pub(super) type BuiltinMAC = [u8; MAC_LENGTH];
 
+// Serialized length of a ReceiverSpecificMAC: the key id and the MAC
+const RECEIVER_SPECIFIC_MAC_LENGTH: usize = 4 + MAC_LENGTH;
+// Used when the property "dds.sec.crypto.max_receiver_specific_macs" is not
+// set
+pub(super) const DEFAULT_MAX_RECEIVER_SPECIFIC_MACS: usize = 1024;
+
 /// CryptoFooter type from section 9.5.2.5 of the Security specification (v.
 /// 1.1)
 #[derive(Deserialize, Serialize, PartialEq)]
@@ -360,20 +614,54 @@ impl BuiltinCryptoFooter {
       receiver_specific_macs: Vec::new(),
     }
   }
-}
 
-impl TryFrom<&[u8]> for BuiltinCryptoFooter {
-  type Error = SecurityError;
-  fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+  // Deserializes a footer that has at most max_receiver_specific_macs
+  // receiver-specific MACs. The declared number of MACs is checked against the
+  // length of the data before deserializing, so that a bogus sequence length
+  // cannot cause a large allocation.
+  pub fn deserialize_with_limit(
+    data: &[u8],
+    max_receiver_specific_macs: usize,
+  ) -> SecurityResult<Self> {
+    if let Some(length_bytes) = data.get(MAC_LENGTH..Self::minimal_serialized_len()) {
+      let declared_macs = u32::from_be_bytes(length_bytes.try_into().unwrap()) as usize;
+      let available_macs =
+        (data.len() - Self::minimal_serialized_len()) / RECEIVER_SPECIFIC_MAC_LENGTH;
+      if declared_macs > available_macs {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::MalformedToken,
+          "BuiltinCryptoFooter declares {} receiver-specific MACs, but only has room for {}",
+          declared_macs,
+          available_macs
+        ));
+      }
+      if declared_macs > max_receiver_specific_macs {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::MalformedToken,
+          "BuiltinCryptoFooter has {} receiver-specific MACs, more than the maximum of {}",
+          declared_macs,
+          max_receiver_specific_macs
+        ));
+      }
+    }
+
     // Deserialize the data
     BuiltinCryptoFooter::deserialize(&mut CdrDeserializer::<BigEndian>::new(data)).map_err(
       // Map deserialization error to SecurityError
-      |e| Self::Error {
+      |e| SecurityError {
         msg: format!("Error deserializing BuiltinCryptoFooter: {}", e),
+        kind: SecurityErrorKind::MalformedToken,
       },
     )
   }
 }
+
+impl TryFrom<&[u8]> for BuiltinCryptoFooter {
+  type Error = SecurityError;
+  fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+    Self::deserialize_with_limit(data, DEFAULT_MAX_RECEIVER_SPECIFIC_MACS)
+  }
+}
 impl TryFrom<CryptoFooter> for BuiltinCryptoFooter {
   type Error = SecurityError;
   fn try_from(CryptoFooter { data }: CryptoFooter) -> Result<Self, Self::Error> {
@@ -386,6 +674,7 @@ impl TryFrom<BuiltinCryptoFooter> for Vec<u8> {
     // Serialize
     to_vec::<BuiltinCryptoFooter, BigEndian>(&value).map_err(|e| Self::Error {
       msg: format!("Error serializing BuiltinCryptoFooter: {}", e),
+      kind: SecurityErrorKind::Internal,
     })
   }
 }
@@ -404,7 +693,7 @@ pub(super) struct ReceiverSpecificMAC {
   pub receiver_mac: BuiltinMAC,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub(super) enum EndpointKind {
   DataReader,
   DataWriter,
@@ -423,3 +712,314 @@ pub(super) struct EndpointInfo {
   pub crypto_handle: EndpointCryptoHandle,
   pub kind: EndpointKind,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn footer_bytes(receiver_macs: u8) -> Vec<u8> {
+    Vec::<u8>::try_from(BuiltinCryptoFooter {
+      common_mac: [1; MAC_LENGTH],
+      receiver_specific_macs: (0..receiver_macs)
+        .map(|i| ReceiverSpecificMAC {
+          receiver_mac_key_id: CryptoTransformKeyId::from([i; 4]),
+          receiver_mac: [i; MAC_LENGTH],
+        })
+        .collect(),
+    })
+    .unwrap()
+  }
+
+  fn crypto_header(plugin_crypto_header_extra: Vec<u8>) -> CryptoHeader {
+    CryptoHeader {
+      transformation_id: BuiltinCryptoTransformIdentifier {
+        transformation_kind: BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+        transformation_key_id: CryptoTransformKeyId::from([1, 2, 3, 4]),
+      }
+      .into(),
+      plugin_crypto_header_extra: plugin_crypto_header_extra.into(),
+    }
+  }
+
+  fn key_material(key_byte: u8) -> KeyMaterial_AES_GCM_GMAC {
+    KeyMaterial_AES_GCM_GMAC {
+      transformation_kind: BuiltinCryptoTransformationKind::CRYPTO_TRANSFORMATION_KIND_AES256_GCM,
+      master_salt: BuiltinKey::AES256([0xcd; 32]),
+      sender_key_id: CryptoTransformKeyId::from([1, 2, 3, 4]),
+      master_sender_key: BuiltinKey::AES256([key_byte; 32]),
+      receiver_specific_key_id: CryptoTransformKeyId::from([5, 6, 7, 8]),
+      master_receiver_specific_key: BuiltinKey::AES256([key_byte; 32]),
+    }
+  }
+
+  #[test]
+  fn debug_output_redacts_keys_and_salts() {
+    let key_material = key_material(0xab);
+    let token = BuiltinCryptoToken::from(key_material.clone());
+    let wrapped =
+      BuiltinCryptoToken::wrap(key_material.clone(), &BuiltinKey::AES256([0xef; 32])).unwrap();
+
+    for debug in [
+      format!("{key_material:?}"),
+      format!("{token:?}"),
+      format!("{wrapped:?}"),
+    ] {
+      // The bytes 0xab, 0xcd and 0xef
+      for secret in ["171", "205", "239"] {
+        assert!(!debug.contains(secret), "{debug}");
+      }
+    }
+    let debug = format!("{token:?}");
+    assert!(debug.contains("CRYPTO_TRANSFORMATION_KIND_AES256_GCM"));
+    assert!(debug.contains(&format!("{:?}", key_material.sender_key_id)));
+    assert!(debug.contains(&format!("{:?}", key_material.receiver_specific_key_id)));
+    assert!(debug.contains("AES256(<redacted>)"));
+    assert!(format!("{wrapped:?}").contains("WrappedKeyMaterial(<"));
+  }
+
+  // A token of another vendor, with extra properties around the key material
+  fn token_with_vendor_extras(token: BuiltinCryptoToken) -> CryptoToken {
+    let mut token = CryptoToken::try_from(token).unwrap();
+    token.data_holder.properties.push(Property {
+      name: String::from("com.example.keymat.version"),
+      value: String::from("2"),
+      propagate: true,
+    });
+    token.data_holder.binary_properties.insert(
+      0,
+      BinaryProperty::with_propagate("com.example.hint", Bytes::from_static(&[1, 2, 3])),
+    );
+    token
+      .data_holder
+      .binary_properties
+      .push(BinaryProperty::with_propagate(
+        "com.example.padding",
+        Bytes::new(),
+      ));
+    token
+  }
+
+  #[test]
+  fn crypto_tokens_with_vendor_extras_round_trip() {
+    let key_exchange_key = BuiltinKey::AES256([0xef; 32]);
+    for builtin_token in [
+      BuiltinCryptoToken::from(key_material(0xab)),
+      BuiltinCryptoToken::wrap(key_material(0xab), &key_exchange_key).unwrap(),
+    ] {
+      let token = token_with_vendor_extras(builtin_token.clone());
+      let parsed = BuiltinCryptoToken::try_from(token.clone()).unwrap();
+      assert_eq!(parsed.key_material, builtin_token.key_material);
+      assert_eq!(parsed.extra_properties.len(), 1);
+      assert_eq!(parsed.extra_binary_properties.len(), 2);
+      assert_eq!(
+        parsed.clone().unwrap(&key_exchange_key).unwrap(),
+        key_material(0xab)
+      );
+
+      let converted_back = CryptoToken::try_from(parsed).unwrap();
+      assert_eq!(
+        converted_back.data_holder.properties,
+        token.data_holder.properties
+      );
+      // The key material comes first when converting back
+      let mut expected_binary_properties = token.data_holder.binary_properties.clone();
+      expected_binary_properties.swap(0, 1);
+      assert_eq!(
+        converted_back.data_holder.binary_properties,
+        expected_binary_properties
+      );
+      assert_eq!(
+        BuiltinCryptoToken::try_from(converted_back).unwrap(),
+        BuiltinCryptoToken::try_from(token).unwrap()
+      );
+    }
+  }
+
+  #[test]
+  fn crypto_tokens_need_exactly_one_key_material() {
+    let token = token_with_vendor_extras(BuiltinCryptoToken::from(key_material(0xab)));
+    let key_material_property = token.data_holder.binary_properties[1].clone();
+    let wrapped = CryptoToken::try_from(
+      BuiltinCryptoToken::wrap(key_material(0xab), &BuiltinKey::AES256([0xef; 32])).unwrap(),
+    )
+    .unwrap()
+    .data_holder
+    .binary_properties[0]
+      .clone();
+
+    let mut missing = token.clone();
+    missing.data_holder.binary_properties.remove(1);
+    let mut duplicate = token.clone();
+    duplicate
+      .data_holder
+      .binary_properties
+      .push(key_material_property);
+    let mut plain_and_wrapped = token.clone();
+    plain_and_wrapped
+      .data_holder
+      .binary_properties
+      .push(wrapped);
+    let mut wrong_class_id = token;
+    wrong_class_id.data_holder.class_id = String::from("DDS:Crypto:Other");
+
+    for token in [missing, duplicate, plain_and_wrapped, wrong_class_id] {
+      assert_eq!(
+        BuiltinCryptoToken::try_from(token).unwrap_err().kind,
+        SecurityErrorKind::MalformedToken
+      );
+    }
+  }
+
+  #[test]
+  fn crypto_builtin_types_compare_by_contents() {
+    let token = BuiltinCryptoToken::from(key_material(0xab));
+    assert_eq!(token.clone(), token);
+    assert_ne!(BuiltinCryptoToken::from(key_material(0xac)), token);
+
+    let header = BuiltinCryptoHeader::try_from(crypto_header((0..12).collect())).unwrap();
+    assert_eq!(
+      BuiltinCryptoHeader::try_from(CryptoHeader::from(header)).unwrap(),
+      header
+    );
+    assert_ne!(
+      BuiltinCryptoHeader::try_from(crypto_header((1..13).collect())).unwrap(),
+      header
+    );
+    assert!(format!("{header:?}").contains("CRYPTO_TRANSFORMATION_KIND_AES256_GCM"));
+  }
+
+  #[test]
+  fn transformation_kind_names_round_trip() {
+    for kind in BuiltinCryptoTransformationKind::ALL {
+      assert_eq!(
+        kind
+          .to_string()
+          .parse::<BuiltinCryptoTransformationKind>()
+          .unwrap(),
+        kind
+      );
+      assert_eq!(
+        BuiltinCryptoTransformationKind::try_from(CryptoTransformKind::from(kind)).unwrap(),
+        kind
+      );
+    }
+  }
+
+  #[test]
+  fn unknown_transformation_kind_names_are_rejected() {
+    for name in [
+      "",
+      "AES256_GCM",
+      "crypto_transformation_kind_aes256_gcm",
+      "CRYPTO_TRANSFORMATION_KIND_AES512_GCM",
+    ] {
+      let error = name.parse::<BuiltinCryptoTransformationKind>().unwrap_err();
+      assert_eq!(error.kind, SecurityErrorKind::UnsupportedTransformation);
+    }
+  }
+
+  #[test]
+  fn protection_kinds_map_to_transformation_kinds() {
+    use BuiltinCryptoTransformationKind::*;
+    for (protection_kind, key_size, expected) in [
+      ("NONE", 128, CRYPTO_TRANSFORMATION_KIND_NONE),
+      ("NONE", 256, CRYPTO_TRANSFORMATION_KIND_NONE),
+      ("SIGN", 128, CRYPTO_TRANSFORMATION_KIND_AES128_GMAC),
+      ("SIGN", 256, CRYPTO_TRANSFORMATION_KIND_AES256_GMAC),
+      (
+        "SIGN_WITH_ORIGIN_AUTHENTICATION",
+        256,
+        CRYPTO_TRANSFORMATION_KIND_AES256_GMAC,
+      ),
+      ("ENCRYPT", 128, CRYPTO_TRANSFORMATION_KIND_AES128_GCM),
+      ("ENCRYPT", 256, CRYPTO_TRANSFORMATION_KIND_AES256_GCM),
+      (
+        "ENCRYPT_WITH_ORIGIN_AUTHENTICATION",
+        128,
+        CRYPTO_TRANSFORMATION_KIND_AES128_GCM,
+      ),
+    ] {
+      assert_eq!(
+        BuiltinCryptoTransformationKind::from_protection_kind(protection_kind, key_size).unwrap(),
+        expected
+      );
+    }
+
+    for (protection_kind, key_size) in [
+      ("encrypt", 256),
+      ("NONE_WITH_ORIGIN_AUTHENTICATION", 256),
+      ("ENCRYPT", 192),
+      ("SIGN", 0),
+    ] {
+      let error = BuiltinCryptoTransformationKind::from_protection_kind(protection_kind, key_size)
+        .unwrap_err();
+      assert_eq!(error.kind, SecurityErrorKind::UnsupportedTransformation);
+    }
+  }
+
+  #[test]
+  fn header_extra_must_be_an_initialization_vector() {
+    for length in [0, 3, 4, 11, 12, 13, 20] {
+      let extra: Vec<u8> = (0..length).collect();
+      match BuiltinCryptoHeader::try_from(crypto_header(extra.clone())) {
+        Ok(header) => {
+          assert_eq!(length, 12);
+          let initialization_vector = header.builtin_crypto_header_extra.initialization_vector();
+          assert_eq!(
+            <[u8; 4]>::from(initialization_vector.session_id()),
+            extra[..4]
+          );
+          assert_eq!(
+            initialization_vector.initialization_vector_suffix(),
+            extra[4..]
+          );
+        }
+        Err(e) => {
+          assert_ne!(length, 12);
+          assert_eq!(e.kind(), SecurityErrorKind::MalformedToken);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn footer_round_trips_within_the_limit() {
+    let bytes = footer_bytes(3);
+    assert_eq!(
+      bytes.len(),
+      BuiltinCryptoFooter::minimal_serialized_len() + 3 * RECEIVER_SPECIFIC_MAC_LENGTH
+    );
+    let footer = BuiltinCryptoFooter::deserialize_with_limit(&bytes, 3).unwrap();
+    assert_eq!(footer.receiver_specific_macs.len(), 3);
+    assert_eq!(
+      BuiltinCryptoFooter::try_from(bytes.as_slice())
+        .unwrap()
+        .receiver_specific_macs,
+      footer.receiver_specific_macs
+    );
+  }
+
+  #[test]
+  fn footer_with_too_many_receiver_specific_macs_is_rejected() {
+    let error = BuiltinCryptoFooter::deserialize_with_limit(&footer_bytes(3), 2)
+      .err()
+      .unwrap();
+    assert_eq!(error.kind(), SecurityErrorKind::MalformedToken);
+  }
+
+  #[test]
+  fn footer_with_bogus_sequence_length_is_rejected() {
+    // The sequence length claims 2^32 - 1 MACs, but there is data for one
+    let mut bytes = footer_bytes(1);
+    bytes[MAC_LENGTH..MAC_LENGTH + 4].copy_from_slice(&[0xff; 4]);
+    let error = BuiltinCryptoFooter::deserialize_with_limit(&bytes, usize::MAX)
+      .err()
+      .unwrap();
+    assert_eq!(error.kind(), SecurityErrorKind::MalformedToken);
+
+    // A truncated MAC does not count
+    let mut bytes = footer_bytes(2);
+    bytes.pop();
+    assert!(BuiltinCryptoFooter::try_from(bytes.as_slice()).is_err());
+  }
+}
@@ -13,7 +13,7 @@ use crate::{
 use crate::{messages::submessages::submessage::SecuritySubmessage, rtps::SubmessageBody};
 
 /// CryptoKeyFactory: section 8.5.1.7 of the Security specification (v. 1.1)
-pub trait CryptoKeyFactory: Send {
+pub trait CryptoKeyFactory: Send + Sync {
   /// register_local_participant: section 8.5.1.7.1 of the Security
   /// specification (v. 1.1)
   fn register_local_participant(
@@ -53,6 +53,17 @@ pub trait CryptoKeyFactory: Send {
     relay_only: bool,
   ) -> SecurityResult<DatareaderCryptoHandle>;
 
+  /// Registers remote datareaders that matched the same local datawriter, for
+  /// example when a network partition heals. The result is the same as calling
+  /// register_matched_remote_datareader for each of them in order, but the
+  /// local datawriter is looked up only once.
+  fn register_matched_remote_datareaders(
+    &mut self,
+    local_datawriter_crypto_handle: DatawriterCryptoHandle,
+    remote_datareaders: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+    relay_only: bool,
+  ) -> SecurityResult<Vec<DatareaderCryptoHandle>>;
+
   /// register_local_datareader: section 8.5.1.7.5 of the Security specification
   /// (v. 1.1)
   fn register_local_datareader(
@@ -71,6 +82,15 @@ pub trait CryptoKeyFactory: Send {
     shared_secret: SharedSecretHandle,
   ) -> SecurityResult<DatawriterCryptoHandle>;
 
+  /// Registers remote datawriters that matched the same local datareader. The
+  /// result is the same as calling register_matched_remote_datawriter for each
+  /// of them in order, but the local datareader is looked up only once.
+  fn register_matched_remote_datawriters(
+    &mut self,
+    local_datareader_crypto_handle: DatareaderCryptoHandle,
+    remote_datawriters: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+  ) -> SecurityResult<Vec<DatawriterCryptoHandle>>;
+
   /// unregister_participant: section 8.5.1.7.7 of the Security specification
   /// (v. 1.1)
   fn unregister_participant(
@@ -162,7 +182,7 @@ pub trait CryptoKeyExchange {
 ///
 /// Differs from the specification by returning the results instead of writing
 /// them to provided buffers.
-pub trait CryptoTransform: Send {
+pub trait CryptoTransform: Send + Sync {
   /// encode_serialized_payload: section 8.5.1.9.1 of the Security specification
   /// (v. 1.1)
   ///
@@ -247,8 +267,23 @@ pub trait CryptoTransform: Send {
     sending_participant_crypto_handle: ParticipantCryptoHandle,
   ) -> SecurityResult<DecodeOutcome<Message>>;
 
-  // Combines the functionality of preprocess_secure_submsg and the subsequent
-  // call of decode_datawriter_submessage or decode_datareader_submessage from
+  /// preprocess_secure_submsg: section 8.5.1.9.6 of the Security
+  /// specification (v. 1.1)
+  ///
+  /// Classify the submessage protected by `secure_prefix` by the key id of its
+  /// CryptoHeader, without decoding it. If no remote endpoint of the sending
+  /// participant has a key with that id, the error is of the kind
+  /// [SecurityErrorKind::NotRegistered], as the key exchange may not have
+  /// completed yet.
+  fn preprocess_secure_submessage(
+    &self,
+    secure_prefix: &SecurePrefix,
+    receiving_local_participant_crypto_handle: ParticipantCryptoHandle,
+    sending_remote_participant_crypto_handle: ParticipantCryptoHandle,
+  ) -> SecurityResult<SecureSubmessageCategory>;
+
+  // Combines preprocess_secure_submessage and the subsequent call of
+  // decode_datawriter_submessage or decode_datareader_submessage from
   // sections 8.5.1.9.6–8 of the Security specification
   /// (v. 1.1)
   ///
@@ -6,8 +6,8 @@ use speedy::{Readable, Writable};
 use crate::{
   messages::submessages::submessage::{InterpreterSubmessage, ReaderSubmessage, WriterSubmessage},
   rtps::Submessage,
-  security::types::DataHolder,
-  structure::guid::GuidPrefix,
+  security::types::{DataHolder, SecurityErrorKind},
+  structure::{guid::GuidPrefix, time::Timestamp},
 };
 
 // Crypto related message class IDs for GenericMessageClassId:
@@ -70,11 +70,6 @@ impl CryptoTransformKeyId {
   pub fn is_zero(&self) -> bool {
     *self == Self::ZERO
   }
-
-  pub fn random() -> Self {
-    let random_array: [u8; 4] = rand::random();
-    Self(random_array)
-  }
 }
 
 impl From<[u8; 4]> for CryptoTransformKeyId {
@@ -121,6 +116,118 @@ pub enum DecodedSubmessage {
   Reader(ReaderSubmessage, Vec<EndpointCryptoHandle>),
 }
 
+/// Counts of the operations of a cryptographic plugin since it was created.
+/// Protection with the transformation kind NONE is not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CryptoStatistics {
+  /// Submessages encoded with a SecurePrefix and SecurePostfix
+  pub encoded_submessages: u64,
+  /// Protected submessages that were decoded successfully
+  pub decoded_submessages: u64,
+  /// Messages, submessages and payloads rejected because a common or
+  /// receiver-specific MAC did not match
+  pub mac_failures: u64,
+  /// Messages, submessages and payloads dropped because there was no key
+  /// material for them
+  pub missing_key_drops: u64,
+  /// Encoding sessions started because the previous one reached its maximum
+  /// number of blocks
+  pub sessions_rolled: u64,
+  /// Submessages received without protection for a local endpoint that
+  /// requires submessage protection. Counted by the DomainParticipant, not by
+  /// the plugin.
+  pub unprotected_submessage_drops: u64,
+}
+
+/// Tells about data from a remote participant that the cryptographic plugin
+/// rejected, see [`SecurityEventListener`](super::SecurityEventListener)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CryptoSecurityEvent {
+  pub kind: CryptoSecurityEventKind,
+  pub operation: CryptoOperation,
+  /// The remote participant or endpoint the data came from
+  pub remote_crypto_handle: CryptoHandle,
+  /// The GUID prefix of the remote participant, if it is still known when the
+  /// event is delivered
+  pub remote_guid_prefix: Option<GuidPrefix>,
+  pub timestamp: Timestamp,
+}
+
+impl CryptoSecurityEvent {
+  pub fn new(
+    kind: CryptoSecurityEventKind,
+    operation: CryptoOperation,
+    remote_crypto_handle: CryptoHandle,
+  ) -> Self {
+    Self {
+      kind,
+      operation,
+      remote_crypto_handle,
+      remote_guid_prefix: None,
+      timestamp: Timestamp::now(),
+    }
+  }
+}
+
+/// Why the data was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoSecurityEventKind {
+  /// A MAC did not match, or the data was a replay
+  VerificationFailed,
+  /// The data could not be parsed
+  Malformed,
+  /// There was no key material for the key id of the data
+  UnknownKey,
+  /// The transformation kind did not match the key material
+  UnsupportedTransformation,
+  /// A submessage arrived without protection for a local endpoint that
+  /// requires submessage protection
+  Unprotected,
+  Other,
+}
+
+impl From<SecurityErrorKind> for CryptoSecurityEventKind {
+  fn from(kind: SecurityErrorKind) -> Self {
+    match kind {
+      SecurityErrorKind::VerificationFailed => Self::VerificationFailed,
+      SecurityErrorKind::MalformedToken => Self::Malformed,
+      SecurityErrorKind::NotRegistered => Self::UnknownKey,
+      SecurityErrorKind::UnsupportedTransformation => Self::UnsupportedTransformation,
+      SecurityErrorKind::Internal | SecurityErrorKind::Other => Self::Other,
+    }
+  }
+}
+
+/// The operation that rejected the data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoOperation {
+  DecodeRtpsMessage,
+  DecodeSubmessage,
+  DecodeSerializedPayload,
+  /// Setting the crypto tokens received from a remote participant or endpoint
+  SetCryptoTokens,
+}
+
+/// Result of preprocess_secure_submsg (8.5.1.9.6 of the Security specification
+/// v. 1.1): what kind of submessage the sending remote endpoint protected, and
+/// the (local, remote) endpoint crypto handle pairs to decode it with. A remote
+/// endpoint matched with several local ones gives several pairs.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SecureSubmessageCategory {
+  /// A writer submessage from remote datawriters: (local datareader, remote
+  /// datawriter) pairs
+  Writer(Vec<(DatareaderCryptoHandle, DatawriterCryptoHandle)>),
+  /// A reader submessage from remote datareaders: (local datawriter, remote
+  /// datareader) pairs
+  Reader(Vec<(DatawriterCryptoHandle, DatareaderCryptoHandle)>),
+  /// The key is shared by remote datawriters and datareaders, which happens on
+  /// the volatile topic, so the kind is known only after decoding.
+  Either {
+    writer: Vec<(DatareaderCryptoHandle, DatawriterCryptoHandle)>,
+    reader: Vec<(DatawriterCryptoHandle, DatareaderCryptoHandle)>,
+  },
+}
+
 pub enum DecodeOutcome<T> {
   Success(T),
   /// It is normal to receive encoded communication that is meant for another
@@ -0,0 +1,134 @@
+use std::error::Error;
+
+use ring::rand::SecureRandom;
+
+use crate::{
+  create_security_error_and_log,
+  security::{SecurityError, SecurityResult},
+};
+
+/// Source of the random bytes used by the builtin security plugins: key
+/// material, key ids, session ids and the challenges of the authentication
+/// handshake.
+///
+/// The Diffie-Hellman key pairs of the handshake are generated by the
+/// underlying crypto libraries and do not use this source.
+pub trait EntropySource: Send + Sync {
+  /// Fills `dest` entirely with random bytes. An error fails the operation
+  /// that needed the bytes; no other source is used instead.
+  fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// The default [`EntropySource`], the random number generator of the
+/// operating system.
+pub struct SystemEntropySource {
+  rng: ring::rand::SystemRandom,
+}
+
+impl SystemEntropySource {
+  pub fn new() -> Self {
+    Self {
+      rng: ring::rand::SystemRandom::new(),
+    }
+  }
+}
+
+impl Default for SystemEntropySource {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl EntropySource for SystemEntropySource {
+  fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    self
+      .rng
+      .fill(dest)
+      .map_err(|_| "the system random number generator failed".into())
+  }
+}
+
+pub(crate) fn random_bytes<const N: usize>(
+  entropy_source: &dyn EntropySource,
+) -> SecurityResult<[u8; N]> {
+  let mut bytes = [0; N];
+  entropy_source
+    .fill_bytes(&mut bytes)
+    .map_err(|e| create_security_error_and_log!("Failed to generate random bytes: {}", e))?;
+  Ok(bytes)
+}
+
+// Entropy sources for making tests reproducible
+#[cfg(test)]
+pub(crate) mod test_sources {
+  use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Mutex,
+  };
+
+  use ring::digest;
+
+  use super::*;
+
+  // Returns the bytes 0, 1, 2, ... wrapping around after 255
+  #[derive(Default)]
+  pub(crate) struct CountingEntropySource {
+    next: AtomicU8,
+  }
+
+  impl EntropySource for CountingEntropySource {
+    fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+      for byte in dest {
+        *byte = self.next.fetch_add(1, Ordering::Relaxed);
+      }
+      Ok(())
+    }
+  }
+
+  // Returns a pseudorandom stream determined by the seed: the SHA-256 digests
+  // of the seed followed by a block counter. Sources with the same seed
+  // return the same bytes, sources with different seeds unrelated ones.
+  pub(crate) struct SeededEntropySource {
+    seed: u64,
+    // The number of the next block and the unused bytes of the current one
+    state: Mutex<(u64, Vec<u8>)>,
+  }
+
+  impl SeededEntropySource {
+    pub(crate) fn new(seed: u64) -> Self {
+      Self {
+        seed,
+        state: Mutex::new((0, Vec::new())),
+      }
+    }
+  }
+
+  impl EntropySource for SeededEntropySource {
+    fn fill_bytes(&self, dest: &mut [u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+      let mut state = self.state.lock().unwrap();
+      let (next_block, unused) = &mut *state;
+      for byte in dest {
+        if unused.is_empty() {
+          let block = [self.seed.to_be_bytes(), next_block.to_be_bytes()].concat();
+          unused.extend(
+            digest::digest(&digest::SHA256, &block)
+              .as_ref()
+              .iter()
+              .rev(),
+          );
+          *next_block += 1;
+        }
+        *byte = unused.pop().unwrap();
+      }
+      Ok(())
+    }
+  }
+
+  pub(crate) struct FailingEntropySource;
+
+  impl EntropySource for FailingEntropySource {
+    fn fill_bytes(&self, _dest: &mut [u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+      Err("hardware random number generator unavailable".into())
+    }
+  }
+}
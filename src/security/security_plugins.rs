@@ -1,7 +1,11 @@
 use core::fmt;
 use std::{
   collections::{HashMap, HashSet},
-  sync::{Arc, Mutex, MutexGuard},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    mpsc, Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, TryLockResult,
+  },
+  thread,
 };
 
 use bytes::Bytes;
@@ -26,14 +30,29 @@ use super::{
   access_control::*,
   authentication::*,
   cryptographic::{
+    CryptoHandle, CryptoOperation, CryptoSecurityEvent, CryptoSecurityEventKind, CryptoStatistics,
     DatareaderCryptoHandle, DatareaderCryptoToken, DatawriterCryptoHandle, DatawriterCryptoToken,
     DecodeOutcome, DecodedSubmessage, EncodedSubmessage, EndpointCryptoHandle,
-    ParticipantCryptoHandle, ParticipantCryptoToken,
+    ParticipantCryptoHandle, ParticipantCryptoToken, SecurityEventListener,
   },
   types::*,
   Cryptographic,
 };
 
+// Participant property to hand submessages without protection also to local
+// endpoints that require submessage protection, e.g. while migrating remote
+// participants to a governance that protects submessages
+pub(crate) const QOS_ACCEPT_UNPROTECTED_SUBMESSAGES_PROPERTY_NAME: &str =
+  "rustdds.sec.accept_unprotected_submessages";
+
+// The handles of a new handshake with a remote participant that is
+// authenticated already
+#[derive(Default)]
+struct ReauthenticationHandles {
+  identity_handle: Option<IdentityHandle>,
+  handshake_handle: Option<HandshakeHandle>,
+}
+
 pub(crate) struct SecurityPlugins {
   auth: Box<dyn Authentication>,
   access: Box<dyn AccessControl>,
@@ -42,6 +61,9 @@ pub(crate) struct SecurityPlugins {
   identity_handle_cache: HashMap<GuidPrefix, IdentityHandle>,
   permissions_handle_cache: HashMap<GuidPrefix, PermissionsHandle>,
   handshake_handle_cache: HashMap<GuidPrefix, HandshakeHandle>,
+  // The caches above keep the current authentication of these remotes in use
+  // until the new handshake succeeds
+  reauthentication_handles: HashMap<GuidPrefix, ReauthenticationHandles>,
 
   local_participant_crypto_handle: Option<ParticipantCryptoHandle>,
   remote_participant_crypto_handle_cache: HashMap<GuidPrefix, ParticipantCryptoHandle>,
@@ -53,13 +75,22 @@ pub(crate) struct SecurityPlugins {
   rtps_not_protected: HashSet<GuidPrefix>,
   submessage_not_protected: HashSet<GUID>,
   payload_not_protected: HashSet<GUID>,
+
+  // Whether submessages without protection are handed to local endpoints that
+  // require submessage protection, see
+  // QOS_ACCEPT_UNPROTECTED_SUBMESSAGES_PROPERTY_NAME
+  accept_unprotected_submessages: bool,
+  unprotected_submessage_drops: AtomicU64,
+  remote_endpoint_crypto_tokens_set: u64,
+  // Set by SecurityPluginsHandle::dispatch_security_events
+  security_event_listener: Option<Arc<dyn SecurityEventListener>>,
 }
 
 impl SecurityPlugins {
   pub fn new(
-    auth: Box<impl Authentication + 'static>,
-    access: Box<impl AccessControl + 'static>,
-    crypto: Box<impl Cryptographic + 'static>,
+    auth: Box<dyn Authentication>,
+    access: Box<dyn AccessControl>,
+    crypto: Box<dyn Cryptographic>,
   ) -> Self {
     Self {
       auth,
@@ -68,6 +99,7 @@ impl SecurityPlugins {
       identity_handle_cache: HashMap::new(),
       permissions_handle_cache: HashMap::new(),
       handshake_handle_cache: HashMap::new(),
+      reauthentication_handles: HashMap::new(),
       local_participant_crypto_handle: None,
       remote_participant_crypto_handle_cache: HashMap::new(),
       local_endpoint_crypto_handle_cache: HashMap::new(),
@@ -76,6 +108,11 @@ impl SecurityPlugins {
       rtps_not_protected: HashSet::new(),
       submessage_not_protected: HashSet::new(),
       payload_not_protected: HashSet::new(),
+
+      accept_unprotected_submessages: false,
+      unprotected_submessage_drops: AtomicU64::new(0),
+      remote_endpoint_crypto_tokens_set: 0,
+      security_event_listener: None,
     }
   }
 
@@ -85,6 +122,7 @@ impl SecurityPlugins {
       .get(guidp)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find an IdentityHandle for the GUID prefix {:?}",
           guidp
         )
@@ -98,6 +136,7 @@ impl SecurityPlugins {
       .get(guidp)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find a PermissionsHandle for the GUID prefix {:?}",
           guidp
         )
@@ -111,6 +150,7 @@ impl SecurityPlugins {
       .get(remote_guidp)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find a HandshakeHandle for the GUID prefix {:?}",
           remote_guidp
         )
@@ -118,10 +158,53 @@ impl SecurityPlugins {
       .copied()
   }
 
+  // The handles of the handshake with a remote, which are those of the new
+  // handshake if the remote is being authenticated again
+  fn get_remote_identity_handle_for_handshake(
+    &self,
+    remote_guidp: &GuidPrefix,
+  ) -> SecurityResult<IdentityHandle> {
+    match self.reauthentication_handles.get(remote_guidp) {
+      Some(ReauthenticationHandles {
+        identity_handle: Some(handle),
+        ..
+      }) => Ok(*handle),
+      _ => self.get_identity_handle(remote_guidp),
+    }
+  }
+
+  fn get_handshake_handle_for_handshake(
+    &self,
+    remote_guidp: &GuidPrefix,
+  ) -> SecurityResult<HandshakeHandle> {
+    match self.reauthentication_handles.get(remote_guidp) {
+      Some(handles) => handles.handshake_handle.ok_or_else(|| {
+        create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
+          "The new handshake with the GUID prefix {:?} has not begun",
+          remote_guidp
+        )
+      }),
+      None => self.get_handshake_handle(remote_guidp),
+    }
+  }
+
+  fn insert_handshake_handle(&mut self, remote_guidp: GuidPrefix, handle: HandshakeHandle) {
+    match self.reauthentication_handles.get_mut(&remote_guidp) {
+      Some(handles) => handles.handshake_handle = Some(handle),
+      None => {
+        self.handshake_handle_cache.insert(remote_guidp, handle);
+      }
+    }
+  }
+
   fn get_local_participant_crypto_handle(&self) -> SecurityResult<ParticipantCryptoHandle> {
-    self
-      .local_participant_crypto_handle
-      .ok_or_else(|| security_error("Local participant crypto handle has not been set"))
+    self.local_participant_crypto_handle.ok_or_else(|| {
+      security_error_of_kind(
+        SecurityErrorKind::NotRegistered,
+        "Local participant crypto handle has not been set",
+      )
+    })
   }
 
   // TODO do we need this?
@@ -141,6 +224,7 @@ impl SecurityPlugins {
       .get(guid_prefix)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find a ParticipantCryptoHandle for the GuidPrefix {:?}",
           guid_prefix
         )
@@ -170,6 +254,7 @@ impl SecurityPlugins {
       .get(guid)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find a local EndpointCryptoHandle for the GUID {:?}",
           guid
         )
@@ -264,6 +349,7 @@ impl SecurityPlugins {
       .get(&local_and_proxy_guid_pair)
       .ok_or_else(|| {
         create_security_error_and_log!(
+          SecurityErrorKind::NotRegistered,
           "Could not find a remote EndpointCryptoHandle for the (local_endpoint_guid, proxy_guid) \
            pair {:?}",
           local_and_proxy_guid_pair
@@ -392,7 +478,13 @@ impl SecurityPlugins {
     )?;
 
     // Add remote identity handle to cache
-    self.insert_to_identity_handle_cache(remote_participant_guidp, remote_id_handle);
+    match self
+      .reauthentication_handles
+      .get_mut(&remote_participant_guidp)
+    {
+      Some(handles) => handles.identity_handle = Some(remote_id_handle),
+      None => self.insert_to_identity_handle_cache(remote_participant_guidp, remote_id_handle),
+    }
 
     Ok((outcome, auth_req_token_opt))
   }
@@ -404,7 +496,7 @@ impl SecurityPlugins {
     serialized_local_participant_data: Vec<u8>,
   ) -> SecurityResult<(ValidationOutcome, HandshakeMessageToken)> {
     let initiator_identity_handle = self.get_identity_handle(&local_guidp)?;
-    let replier_identity_handle = self.get_identity_handle(&remote_guidp)?;
+    let replier_identity_handle = self.get_remote_identity_handle_for_handshake(&remote_guidp)?;
 
     let (outcome, handshake_handle, handshake_token) = self.auth.begin_handshake_request(
       initiator_identity_handle,
@@ -413,9 +505,7 @@ impl SecurityPlugins {
     )?;
 
     // Store handshake handle
-    self
-      .handshake_handle_cache
-      .insert(remote_guidp, handshake_handle);
+    self.insert_handshake_handle(remote_guidp, handshake_handle);
 
     Ok((outcome, handshake_token))
   }
@@ -427,7 +517,8 @@ impl SecurityPlugins {
     handshake_message_in: HandshakeMessageToken,
     serialized_local_participant_data: Vec<u8>,
   ) -> SecurityResult<(ValidationOutcome, HandshakeMessageToken)> {
-    let initiator_identity_handle = self.get_identity_handle(&remote_participant_guidp)?;
+    let initiator_identity_handle =
+      self.get_remote_identity_handle_for_handshake(&remote_participant_guidp)?;
     let replier_identity_handle = self.get_identity_handle(&local_participant_guidp)?;
 
     let (outcome, handshake_handle, handshake_token) = self.auth.begin_handshake_reply(
@@ -438,9 +529,7 @@ impl SecurityPlugins {
     )?;
 
     // Store handshake handle
-    self
-      .handshake_handle_cache
-      .insert(remote_participant_guidp, handshake_handle);
+    self.insert_handshake_handle(remote_participant_guidp, handshake_handle);
 
     Ok((outcome, handshake_token))
   }
@@ -450,13 +539,50 @@ impl SecurityPlugins {
     remote_participant_guidp: GuidPrefix,
     handshake_message_in: HandshakeMessageToken,
   ) -> SecurityResult<(ValidationOutcome, Option<HandshakeMessageToken>)> {
-    let handshake_handle = self.get_handshake_handle(&remote_participant_guidp)?;
+    let handshake_handle = self.get_handshake_handle_for_handshake(&remote_participant_guidp)?;
 
     self
       .auth
       .process_handshake(handshake_message_in, handshake_handle)
   }
 
+  // Begin a new handshake with an authenticated remote participant. Its
+  // current authentication stays in use until the handshake is completed.
+  pub fn begin_remote_reauthentication(&mut self, remote_participant_guidp: GuidPrefix) {
+    self
+      .reauthentication_handles
+      .insert(remote_participant_guidp, ReauthenticationHandles::default());
+  }
+
+  // The new handshake has succeeded. Unregister the remote participant, and
+  // take the authentication of the new handshake in use.
+  pub fn complete_remote_reauthentication(
+    &mut self,
+    remote_participant_guidp: GuidPrefix,
+  ) -> SecurityResult<()> {
+    let handles = self
+      .reauthentication_handles
+      .remove(&remote_participant_guidp)
+      .unwrap_or_default();
+    let result = self.unregister_remote_participant(&remote_participant_guidp);
+    if let Some(handle) = handles.identity_handle {
+      self.insert_to_identity_handle_cache(remote_participant_guidp, handle);
+    }
+    if let Some(handle) = handles.handshake_handle {
+      self
+        .handshake_handle_cache
+        .insert(remote_participant_guidp, handle);
+    }
+    result
+  }
+
+  // The new handshake has failed. The current authentication stays.
+  pub fn discard_remote_reauthentication(&mut self, remote_participant_guidp: &GuidPrefix) {
+    self
+      .reauthentication_handles
+      .remove(remote_participant_guidp);
+  }
+
   pub fn get_authenticated_peer_credential_token(
     &self,
     remote_participant_guidp: GuidPrefix,
@@ -502,20 +628,35 @@ impl SecurityPlugins {
     remote_participant_guidp: GuidPrefix,
     remote_permissions_token: &PermissionsToken,
     remote_credential_token: &AuthenticatedPeerCredentialToken,
-  ) -> SecurityResult<()> {
+  ) -> SecurityResult<RemoteParticipantPermission> {
     let local_id_handle = self.get_identity_handle(&local_participant_guidp)?;
     let remote_id_handle = self.get_identity_handle(&remote_participant_guidp)?;
 
-    let permissions_handle = self.access.validate_remote_permissions(
+    let validation_result = self.access.validate_remote_permissions(
       &*self.auth,
       local_id_handle,
       remote_id_handle,
       remote_permissions_token,
       remote_credential_token,
-    )?;
+    );
 
-    self.insert_to_permissions_handle_cache(remote_participant_guidp, permissions_handle);
-    Ok(())
+    match validation_result {
+      Ok(permissions_handle) => {
+        self.insert_to_permissions_handle_cache(remote_participant_guidp, permissions_handle);
+        Ok(RemoteParticipantPermission::Allowed)
+      }
+      Err(e) => {
+        if self.allows_unauthenticated_participants(local_participant_guidp)? {
+          debug!(
+            "Permissions of remote {:?} are not valid ({}), but it is allowed as Unauthenticated",
+            remote_participant_guidp, e
+          );
+          Ok(RemoteParticipantPermission::AllowedUnauthenticated)
+        } else {
+          Err(e)
+        }
+      }
+    }
   }
 
   pub fn check_create_participant(
@@ -570,12 +711,41 @@ impl SecurityPlugins {
   pub fn check_remote_participant(
     &self,
     domain_id: u16,
-    participant_guidp: GuidPrefix,
+    local_participant_guidp: GuidPrefix,
+    remote_participant_guidp: GuidPrefix,
+  ) -> SecurityResult<RemoteParticipantPermission> {
+    let handle = self.get_permissions_handle(&remote_participant_guidp)?;
+    let check_result = self
+      .access
+      .check_remote_participant(handle, domain_id, None);
+
+    match check_result {
+      Ok(true) => Ok(RemoteParticipantPermission::Allowed),
+      _ if self.allows_unauthenticated_participants(local_participant_guidp)? => {
+        Ok(RemoteParticipantPermission::AllowedUnauthenticated)
+      }
+      Ok(false) => Ok(RemoteParticipantPermission::Denied),
+      Err(e) => Err(e),
+    }
+  }
+
+  // Whether the local governance lets remotes that do not pass authentication
+  // or access control communicate as Unauthenticated participants
+  fn allows_unauthenticated_participants(
+    &self,
+    local_participant_guidp: GuidPrefix,
   ) -> SecurityResult<bool> {
-    let handle = self.get_permissions_handle(&participant_guidp)?;
     self
-      .access
-      .check_remote_participant(handle, domain_id, None)
+      .get_participant_sec_attributes(local_participant_guidp)
+      .map(|attributes| attributes.allow_unauthenticated_participants)
+  }
+
+  pub fn get_permissions_expiration(
+    &self,
+    participant_guidp: GuidPrefix,
+  ) -> SecurityResult<chrono::DateTime<chrono::Utc>> {
+    let handle = self.get_permissions_handle(&participant_guidp)?;
+    self.access.get_permissions_expiration(handle)
   }
 
   // This function is called when DataReaders from non-secure discovery
@@ -728,6 +898,22 @@ impl SecurityPlugins {
     if !participant_security_attributes.is_rtps_protected {
       self.rtps_not_protected.insert(participant_guidp);
     }
+    self.accept_unprotected_submessages = match properties
+      .iter()
+      .find(|property| property.name == QOS_ACCEPT_UNPROTECTED_SUBMESSAGES_PROPERTY_NAME)
+      .map(|property| property.value.as_str())
+    {
+      None | Some("false") => false,
+      Some("true") => true,
+      Some(other) => {
+        return Err(create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "Invalid value {:?} for {}, expected true or false",
+          other,
+          QOS_ACCEPT_UNPROTECTED_SUBMESSAGES_PROPERTY_NAME
+        ))
+      }
+    };
 
     let crypto_handle = self.crypto.register_local_participant(
       identity_handle,
@@ -749,6 +935,9 @@ impl SecurityPlugins {
     let local_participant_crypto_handle = self.get_local_participant_crypto_handle()?;
 
     let mut properties = reader_properties.map(|prop| prop.value).unwrap_or_default();
+    // Only the EntityId given here is trusted
+    properties.retain(|property| property.name != ENTITY_ID_PROPERTY_NAME);
+    properties.push(entity_id_property(reader_guid.entity_id));
 
     if reader_guid.entity_id == EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_READER {
       // Add a property which the crypto plugin expects for the Volatile Reader
@@ -778,12 +967,17 @@ impl SecurityPlugins {
   pub fn register_local_writer(
     &mut self,
     writer_guid: GUID,
+    topic_name: String,
     writer_properties: Option<qos::policy::Property>,
     writer_security_attributes: EndpointSecurityAttributes,
   ) -> SecurityResult<()> {
     let local_participant_crypto_handle = self.get_local_participant_crypto_handle()?;
 
     let mut properties = writer_properties.map(|prop| prop.value).unwrap_or_default();
+    properties.push(topic_name_property(topic_name));
+    // Only the EntityId given here is trusted
+    properties.retain(|property| property.name != ENTITY_ID_PROPERTY_NAME);
+    properties.push(entity_id_property(writer_guid.entity_id));
 
     if writer_guid.entity_id == EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_WRITER {
       // Add a property which the crypto plugin expects for the Volatile Writer
@@ -936,6 +1130,9 @@ impl SecurityPlugins {
     self
       .handshake_handle_cache
       .remove(remote_participant_guid_prefix);
+    self
+      .reauthentication_handles
+      .remove(remote_participant_guid_prefix);
     self
       .remove_remote_participant_crypto_handle(remote_participant_guid_prefix)
       .map_or(Ok(()), |handle| self.crypto.unregister_participant(handle))
@@ -1033,11 +1230,14 @@ impl SecurityPlugins {
     let remote_writer_crypto_handle =
       self.get_remote_endpoint_crypto_handle((&local_reader_guid, &remote_writer_guid))?;
 
-    self.crypto.set_remote_datawriter_crypto_tokens(
-      local_reader_crypto_handle,
-      remote_writer_crypto_handle,
-      remote_crypto_tokens,
-    )
+    self
+      .crypto
+      .set_remote_datawriter_crypto_tokens(
+        local_reader_crypto_handle,
+        remote_writer_crypto_handle,
+        remote_crypto_tokens,
+      )
+      .map(|()| self.remote_endpoint_crypto_tokens_set += 1)
   }
 
   pub fn set_remote_reader_crypto_tokens(
@@ -1050,11 +1250,69 @@ impl SecurityPlugins {
     let remote_reader_crypto_handle =
       self.get_remote_endpoint_crypto_handle((&local_writer_guid, &remote_reader_guid))?;
 
-    self.crypto.set_remote_datareader_crypto_tokens(
-      local_writer_crypto_handle,
-      remote_reader_crypto_handle,
-      remote_crypto_tokens,
-    )
+    self
+      .crypto
+      .set_remote_datareader_crypto_tokens(
+        local_writer_crypto_handle,
+        remote_reader_crypto_handle,
+        remote_crypto_tokens,
+      )
+      .map(|()| self.remote_endpoint_crypto_tokens_set += 1)
+  }
+
+  // How many times crypto tokens of remote endpoints have been set. Secure
+  // submessages that were waiting for the key material are decoded again when
+  // this changes.
+  pub fn remote_endpoint_crypto_tokens_set(&self) -> u64 {
+    self.remote_endpoint_crypto_tokens_set
+  }
+
+  /// A snapshot of the operation counts of the cryptographic plugin, if it
+  /// keeps them
+  pub fn crypto_statistics(&self) -> Option<CryptoStatistics> {
+    self
+      .crypto
+      .crypto_statistics()
+      .map(|statistics| CryptoStatistics {
+        unprotected_submessage_drops: self.unprotected_submessage_drops.load(Ordering::Relaxed),
+        ..statistics
+      })
+  }
+
+  // The GUID prefix of the remote participant that a remote participant or
+  // endpoint crypto handle belongs to
+  fn remote_guid_prefix(&self, remote_crypto_handle: CryptoHandle) -> Option<GuidPrefix> {
+    self
+      .remote_participant_crypto_handle_cache
+      .iter()
+      .find(|(_, crypto_handle)| **crypto_handle == remote_crypto_handle)
+      .map(|(guid_prefix, _)| *guid_prefix)
+      .or_else(|| {
+        self
+          .remote_endpoint_crypto_handle_cache
+          .iter()
+          .find(|(_, crypto_handle)| **crypto_handle == remote_crypto_handle)
+          .map(|((_, remote_guid), _)| remote_guid.prefix)
+      })
+  }
+
+  /// Returns the (local endpoint, matched remote endpoint) pairs whose crypto
+  /// tokens must be sent again after regenerating the keys of the local
+  /// endpoint
+  pub fn take_rekeyed_endpoints(&mut self) -> Vec<(GUID, GUID)> {
+    self
+      .crypto
+      .take_rekeyed_endpoints()
+      .into_iter()
+      .filter_map(|(_local_crypto_handle, remote_crypto_handle)| {
+        // The remote handle alone identifies the pair
+        self
+          .remote_endpoint_crypto_handle_cache
+          .iter()
+          .find(|(_guids, crypto_handle)| **crypto_handle == remote_crypto_handle)
+          .map(|(guids, _crypto_handle)| *guids)
+      })
+      .collect()
   }
 }
 
@@ -1332,34 +1590,134 @@ impl SecurityPlugins {
   pub fn submessage_not_protected(&self, local_endpoint_guid: &GUID) -> bool {
     self.submessage_not_protected.contains(local_endpoint_guid)
   }
+
+  // Whether a submessage that arrived without a SecurePrefix may be handed to
+  // the local endpoint. If the endpoint requires submessage protection, the
+  // drop is counted and reported to the security event listener, unless the
+  // participant has been configured to accept such submessages.
+  pub fn accept_unprotected_submessage(
+    &self,
+    local_endpoint_guid: &GUID,
+    source_guid_prefix: GuidPrefix,
+  ) -> bool {
+    if self.submessage_not_protected(local_endpoint_guid) {
+      return true;
+    }
+    if self.accept_unprotected_submessages {
+      debug!(
+        "Accepting an unprotected submessage from {source_guid_prefix:?} for the protected \
+         endpoint {local_endpoint_guid:?}"
+      );
+      return true;
+    }
+    self
+      .unprotected_submessage_drops
+      .fetch_add(1, Ordering::Relaxed);
+    // Only authenticated participants have a crypto handle to report
+    if let (Some(listener), Some(remote_crypto_handle)) = (
+      self.security_event_listener.as_ref(),
+      self
+        .remote_participant_crypto_handle_cache
+        .get(&source_guid_prefix),
+    ) {
+      listener.on_crypto_event(CryptoSecurityEvent::new(
+        CryptoSecurityEventKind::Unprotected,
+        CryptoOperation::DecodeSubmessage,
+        *remote_crypto_handle,
+      ));
+    }
+    false
+  }
   pub fn payload_not_protected(&self, local_endpoint_guid: &GUID) -> bool {
     self.payload_not_protected.contains(local_endpoint_guid)
   }
 }
 
+// The plugins are shared by the RTPS send and receive paths, which only need
+// shared access to encode and decode. Registering and unregistering entities
+// takes exclusive access.
 #[derive(Clone)]
 pub(crate) struct SecurityPluginsHandle {
-  inner: Arc<Mutex<SecurityPlugins>>,
+  inner: Arc<RwLock<SecurityPlugins>>,
   who_has_it: Arc<Mutex<Option<String>>>,
 }
 
 impl SecurityPluginsHandle {
   pub(crate) fn new(s: SecurityPlugins) -> Self {
     Self {
-      inner: Arc::new(Mutex::new(s)),
+      inner: Arc::new(RwLock::new(s)),
       who_has_it: Arc::new(Mutex::new(None)),
     }
   }
 
-  pub(crate) fn get_plugins(&self) -> MutexGuard<SecurityPlugins> {
+  // Exclusive access, for everything that modifies the plugins
+  pub(crate) fn get_plugins(&self) -> RwLockWriteGuard<'_, SecurityPlugins> {
+    self.acquire(|| self.try_write())
+  }
+
+  // Shared access, for encoding and decoding and other queries. Must not be
+  // held while calling get_plugins.
+  pub(crate) fn read_plugins(&self) -> RwLockReadGuard<'_, SecurityPlugins> {
+    self.acquire(|| self.try_read())
+  }
+
+  // Delivers the security events of the cryptographic plugin, and the drops of
+  // unprotected submessages, to the listener on a thread of its own, with the
+  // GUID prefix of the remote participant filled in. The events are reported
+  // while the plugins are locked, so calling the listener directly could
+  // deadlock if it uses the DomainParticipant. The thread ends when the plugins
+  // are dropped.
+  pub(crate) fn dispatch_security_events(
+    &self,
+    listener: Arc<dyn SecurityEventListener>,
+  ) -> SecurityResult<()> {
+    let (event_sender, event_receiver) = mpsc::channel();
+    let queued_listener: Arc<dyn SecurityEventListener> = Arc::new(QueuedSecurityEventListener {
+      event_sender: Mutex::new(event_sender),
+    });
+    {
+      let mut plugins = self.get_plugins();
+      plugins
+        .crypto
+        .set_security_event_listener(queued_listener.clone())?;
+      plugins.security_event_listener = Some(queued_listener);
+    }
+
+    // A weak reference, so that the thread does not keep the plugins and with
+    // them the sending end of the channel alive
+    let plugins = Arc::downgrade(&self.inner);
+    thread::Builder::new()
+      .name("RustDDS security events".to_string())
+      .spawn(move || {
+        for mut event in event_receiver {
+          if let Some(plugins) = plugins.upgrade() {
+            event.remote_guid_prefix = plugins
+              .read()
+              .ok()
+              .and_then(|plugins| plugins.remote_guid_prefix(event.remote_crypto_handle));
+          }
+          listener.on_crypto_event(event);
+        }
+      })
+      .map(|_join_handle| ())
+      .map_err(|e| {
+        create_security_error_and_log!(
+          SecurityErrorKind::Internal,
+          "Could not start the security event thread: {}",
+          e
+        )
+      })
+  }
+
+  fn acquire<G>(&self, try_acquire: impl Fn() -> TryLockResult<G>) -> G {
     let mut count = 0;
     loop {
-      match self.try_lock() {
+      match try_acquire() {
         Ok(guard) => {
           *self.who_has_it.lock().unwrap() = std::thread::current().name().map(|s| s.to_owned());
           return guard;
         }
-        Err(std::sync::TryLockError::WouldBlock) => {
+        Err(TryLockError::WouldBlock) => {
           if count > 10 {
             error!(
               "I need my lock!! {:?} Looks like {:?} has it.",
@@ -1370,8 +1728,8 @@ impl SecurityPluginsHandle {
           count += 1;
           std::thread::sleep(std::time::Duration::from_millis(100));
         }
-        Err(poisoned) => {
-          create_security_error_and_log!("Security plugins are poisoned! {poisoned:?}");
+        Err(TryLockError::Poisoned(_)) => {
+          create_security_error_and_log!("Security plugins are poisoned!");
           panic!("Security plugins are poisoned!");
         }
       }
@@ -1379,6 +1737,21 @@ impl SecurityPluginsHandle {
   } // fn
 }
 
+// Passes the events from the cryptographic plugin to the thread started in
+// SecurityPluginsHandle::dispatch_security_events
+struct QueuedSecurityEventListener {
+  event_sender: Mutex<mpsc::Sender<CryptoSecurityEvent>>,
+}
+
+impl SecurityEventListener for QueuedSecurityEventListener {
+  fn on_crypto_event(&self, event: CryptoSecurityEvent) {
+    if let Ok(event_sender) = self.event_sender.lock() {
+      // Fails only if the thread has stopped, and then there is no one to tell
+      event_sender.send(event).unwrap_or(());
+    }
+  }
+}
+
 impl fmt::Debug for SecurityPluginsHandle {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     f.write_str("SecurityPluginsHandle")
@@ -1386,7 +1759,7 @@ impl fmt::Debug for SecurityPluginsHandle {
 }
 
 impl std::ops::Deref for SecurityPluginsHandle {
-  type Target = Mutex<SecurityPlugins>;
+  type Target = RwLock<SecurityPlugins>;
   fn deref(&self) -> &Self::Target {
     &self.inner
   }
@@ -22,7 +22,10 @@ use crate::{
     },
     speedy_pl_cdr_helpers::*,
   },
-  structure::{guid::GuidPrefix, parameter_id::ParameterId},
+  structure::{
+    guid::{EntityId, GuidPrefix},
+    parameter_id::ParameterId,
+  },
   Keyed, QosPolicies, RepresentationIdentifier, GUID,
 };
 
@@ -34,11 +37,44 @@ pub type SecurityResult<T> = std::result::Result<T, SecurityError>;
 #[error("Security exception: {msg}")]
 pub struct SecurityError {
   pub(crate) msg: String,
+  pub(crate) kind: SecurityErrorKind,
+}
+
+impl SecurityError {
+  pub fn kind(&self) -> SecurityErrorKind {
+    self.kind
+  }
+}
+
+/// Classifies a [`SecurityError`], so that callers can tell e.g. an error that
+/// may go away by retrying later from one that calls for dropping a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityErrorKind {
+  /// An entity, or key material for it, has not been registered (yet).
+  /// Retrying after the registration or key exchange has completed may succeed.
+  NotRegistered,
+  /// Received data failed verification: a MAC did not match, or the message
+  /// was a replay. The data should be dropped.
+  VerificationFailed,
+  /// A received CryptoToken, key material or crypto header could not be
+  /// parsed. The peer sent something invalid.
+  MalformedToken,
+  /// The transformation kind is not supported, or does not match the key.
+  UnsupportedTransformation,
+  /// Local state or configuration does not allow the operation.
+  Internal,
+  /// Not classified
+  Other,
 }
 
 pub fn security_error(msg: &str) -> SecurityError {
+  security_error_of_kind(SecurityErrorKind::Other, msg)
+}
+
+pub fn security_error_of_kind(kind: SecurityErrorKind, msg: &str) -> SecurityError {
   SecurityError {
     msg: msg.to_string(),
+    kind,
   }
 }
 
@@ -48,6 +84,7 @@ impl From<ring::error::Unspecified> for SecurityError {
       msg: "The ring crypto library gives 'Unspecified' error. That's all we are authorized to \
             know. Sorry."
         .to_string(),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
@@ -56,6 +93,7 @@ impl From<speedy::Error> for SecurityError {
   fn from(e: speedy::Error) -> Self {
     SecurityError {
       msg: format!("Serialization/deserialization error: {e:?}"),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
@@ -64,13 +102,17 @@ impl From<&str> for SecurityError {
   fn from(e: &str) -> Self {
     SecurityError {
       msg: format!("SecurityError {e}"),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
 
 impl From<String> for SecurityError {
   fn from(msg: String) -> Self {
-    SecurityError { msg }
+    SecurityError {
+      msg,
+      kind: SecurityErrorKind::Other,
+    }
   }
 }
 
@@ -78,6 +120,7 @@ impl From<ConfigError> for SecurityError {
   fn from(e: ConfigError) -> Self {
     SecurityError {
       msg: format!("ConfigError {e:?}"),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
@@ -88,6 +131,7 @@ impl From<X509CertificateError> for SecurityError {
   fn from(e: X509CertificateError) -> Self {
     SecurityError {
       msg: format!("X509CertificateError {e:?}"),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
@@ -96,6 +140,7 @@ impl From<openssl::error::ErrorStack> for SecurityError {
   fn from(e: openssl::error::ErrorStack) -> Self {
     SecurityError {
       msg: format!("openssl Error: {e:?}"),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
@@ -104,16 +149,28 @@ impl From<cryptoki::error::Error> for SecurityError {
   fn from(e: cryptoki::error::Error) -> Self {
     SecurityError {
       msg: format!("cryptoki (PKCS#11) Error: {e:?}"),
+      kind: SecurityErrorKind::Other,
     }
   }
 }
 
+// The first argument may be a SecurityErrorKind variant, e.g.
+// create_security_error_and_log!(SecurityErrorKind::NotRegistered, "format", args).
+// Without one, the kind is Other.
 #[doc(hidden)]
 #[macro_export]
 macro_rules! create_security_error_and_log {
+  ($kind:path, $($arg:tt)*) => (
+      { log::error!($($arg)*);  // Note: this needs to be security-specific logging
+        SecurityError{ msg: format!($($arg)*), kind: $kind }
+      }
+    );
   ($($arg:tt)*) => (
       { log::error!($($arg)*);  // Note: this needs to be security-specific logging
-        SecurityError{ msg: format!($($arg)*) }
+        SecurityError{
+          msg: format!($($arg)*),
+          kind: $crate::security::types::SecurityErrorKind::Other,
+        }
       }
     )
 }
@@ -723,6 +780,16 @@ impl EndpointSecurityAttributesMask {
     let Self(value) = self;
     value.contains(EndpointSecurityAttributesMaskFlags::IsValid)
   }
+
+  // Whether the endpoint needs cryptographic keys to communicate
+  pub fn is_crypto_protected(&self) -> bool {
+    let Self(value) = self;
+    value.intersects(
+      EndpointSecurityAttributesMaskFlags::IsSubmessageProtected
+        | EndpointSecurityAttributesMaskFlags::IsPayloadProtected
+        | EndpointSecurityAttributesMaskFlags::IsKeyProtected,
+    )
+  }
 }
 
 impl<'a, C: Context> Readable<'a, C> for EndpointSecurityAttributesMask {
@@ -1043,6 +1110,33 @@ pub fn volatile_reader_recognition_property() -> Property {
   }
 }
 
+// Property from which the crypto plugin learns the topic of a local endpoint.
+// Not from the Security spec, the builtin plugin needs it for sharing key
+// material between the endpoints of a topic.
+pub const TOPIC_NAME_PROPERTY_NAME: &str = "rustdds.sec.topic_name";
+
+pub fn topic_name_property(topic_name: String) -> Property {
+  Property {
+    name: TOPIC_NAME_PROPERTY_NAME.to_string(),
+    value: topic_name,
+    propagate: false,
+  }
+}
+
+// Property from which the crypto plugin learns the EntityId of a local
+// endpoint, as 8 hexadecimal digits. Not from the Security spec, the builtin
+// plugin needs it to tell the builtin volatile endpoints from application
+// endpoints that set the volatile recognition property.
+pub const ENTITY_ID_PROPERTY_NAME: &str = "rustdds.sec.entity_id";
+
+pub fn entity_id_property(entity_id: EntityId) -> Property {
+  Property {
+    name: ENTITY_ID_PROPERTY_NAME.to_string(),
+    value: format!("{:08x}", u32::from_be_bytes(entity_id.to_slice())),
+    propagate: false,
+  }
+}
+
 // ParticipantVolatileMessageSecure from section 7.4.4.3 of the Security
 // specification
 //
@@ -1,9 +1,11 @@
 pub mod cache_change;
+pub(crate) mod clock;
 pub mod dds_cache;
 pub mod duration;
 pub mod endpoint;
 pub mod entity;
 pub mod guid;
+pub(crate) mod invariants;
 pub mod locator;
 pub mod parameter_id;
 pub mod sequence_number;
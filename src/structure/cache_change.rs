@@ -11,6 +11,25 @@ pub enum ChangeKind {
   Alive,
   NotAliveDisposed,
   NotAliveUnregistered,
+  // Both disposed and unregistered by the writer, i.e. both bits set in
+  // PID_STATUS_INFO
+  NotAliveDisposedUnregistered,
+}
+
+impl ChangeKind {
+  pub fn is_disposed(self) -> bool {
+    matches!(
+      self,
+      Self::NotAliveDisposed | Self::NotAliveDisposedUnregistered
+    )
+  }
+
+  pub fn is_unregistered(self) -> bool {
+    matches!(
+      self,
+      Self::NotAliveUnregistered | Self::NotAliveDisposedUnregistered
+    )
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,278 @@
+//! Clocks for local timing.
+//!
+//! The system (wall) clock can be stepped, e.g. when NTP synchronizes it after
+//! boot. Timers, leases, timeouts and the local ordering of received and
+//! written changes must therefore use the monotonic clock, which is not
+//! affected by such steps. Source timestamps, which are exchanged with remote
+//! participants, are still taken from the wall clock.
+//!
+//! In unit tests, and when replaying captured traffic, the clocks of the
+//! current thread can be replaced with a [`SimulatedClock`], which is advanced
+//! and stepped explicitly. A DomainParticipant created while a simulated clock
+//! is in use follows it in its background threads too.
+
+use std::{sync::OnceLock, time::Instant};
+
+use super::{duration::Duration, time::Timestamp};
+
+/// Reading of the monotonic clock
+pub(crate) fn monotonic_now() -> Instant {
+  #[cfg(any(test, feature = "replay"))]
+  if let Some(now) = simulated::monotonic_now() {
+    return now;
+  }
+  Instant::now()
+}
+
+/// Reading of the wall clock. To be used for source timestamps and for
+/// comparisons against them.
+pub(crate) fn wall_now() -> Timestamp {
+  #[cfg(any(test, feature = "replay"))]
+  if let Some(now) = simulated::wall_now() {
+    return now;
+  }
+  Timestamp::now()
+}
+
+/// A Timestamp that follows the monotonic clock. It is anchored to the wall
+/// clock on first use, but does not follow later steps of the wall clock, so
+/// it never goes backwards. To be used for timestamps that are only compared
+/// locally, such as receive timestamps and the keys of local caches.
+pub(crate) fn local_timestamp() -> Timestamp {
+  #[cfg(any(test, feature = "replay"))]
+  if let Some(now) = simulated::local_timestamp() {
+    return now;
+  }
+  static ANCHOR: OnceLock<(Instant, Timestamp)> = OnceLock::new();
+  let (anchor_instant, anchor_timestamp) =
+    ANCHOR.get_or_init(|| (Instant::now(), Timestamp::now()));
+  *anchor_timestamp + Duration::from_std(anchor_instant.elapsed())
+}
+
+/// Steps of the wall clock smaller than this are not reported. This leaves
+/// room for the difference of reading the two clocks.
+pub(crate) const CLOCK_JUMP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Detects steps of the wall clock by comparing how much the wall clock and
+/// the monotonic clock have advanced since the previous check.
+pub(crate) struct ClockJumpDetector {
+  monotonic: Instant,
+  wall: Timestamp,
+}
+
+impl ClockJumpDetector {
+  pub fn new() -> Self {
+    Self {
+      monotonic: monotonic_now(),
+      wall: wall_now(),
+    }
+  }
+
+  /// Returns the size of the step, if the wall clock has been stepped since
+  /// the previous check. Negative steps are backwards.
+  pub fn check(&mut self) -> Option<Duration> {
+    let (monotonic, wall) = (monotonic_now(), wall_now());
+    let monotonic_elapsed = Duration::from_std(monotonic.duration_since(self.monotonic));
+    let wall_elapsed = wall - self.wall;
+    self.monotonic = monotonic;
+    self.wall = wall;
+
+    let step = Duration::from_ticks(wall_elapsed.to_ticks() - monotonic_elapsed.to_ticks());
+    if step.to_ticks().abs() > CLOCK_JUMP_THRESHOLD.to_ticks() {
+      Some(step)
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(any(test, feature = "replay"))]
+pub(crate) use simulated::SimulatedClock;
+
+#[cfg(any(test, feature = "replay"))]
+mod simulated {
+  use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+  };
+
+  use super::*;
+
+  struct SimulatedTime {
+    start_instant: Instant,
+    start_timestamp: Timestamp,
+    elapsed: Duration,
+    wall_clock_offset: Duration,
+  }
+
+  thread_local! {
+    static SIMULATED_TIME: RefCell<Option<Arc<Mutex<SimulatedTime>>>> = const { RefCell::new(None) };
+  }
+
+  fn with_simulated<T>(f: impl FnOnce(&mut SimulatedTime) -> T) -> Option<T> {
+    SIMULATED_TIME.with(|simulated| {
+      simulated
+        .borrow()
+        .as_ref()
+        .map(|time| f(&mut time.lock().unwrap()))
+    })
+  }
+
+  pub(super) fn monotonic_now() -> Option<Instant> {
+    with_simulated(|time| time.start_instant + time.elapsed.to_std())
+  }
+
+  pub(super) fn wall_now() -> Option<Timestamp> {
+    with_simulated(|time| {
+      let ticks = time.elapsed.to_ticks() + time.wall_clock_offset.to_ticks();
+      Timestamp::from_ticks(time.start_timestamp.to_ticks().wrapping_add_signed(ticks))
+    })
+  }
+
+  pub(super) fn local_timestamp() -> Option<Timestamp> {
+    with_simulated(|time| time.start_timestamp + time.elapsed)
+  }
+
+  /// Replaces the clocks of the current thread until dropped. Time stands
+  /// still unless advanced.
+  ///
+  /// Clones refer to the same simulated time, but do not replace the clocks of
+  /// any thread until [`enter`](Self::enter)ed.
+  pub(crate) struct SimulatedClock {
+    time: Arc<Mutex<SimulatedTime>>,
+    entered: bool,
+  }
+
+  impl SimulatedClock {
+    pub fn start() -> Self {
+      let clock = Self {
+        time: Arc::new(Mutex::new(SimulatedTime {
+          start_instant: Instant::now(),
+          start_timestamp: Timestamp::now(),
+          elapsed: Duration::ZERO,
+          wall_clock_offset: Duration::ZERO,
+        })),
+        entered: false,
+      };
+      clock.enter()
+    }
+
+    /// The simulated clock in use in the current thread, if any
+    pub fn current() -> Option<Self> {
+      SIMULATED_TIME.with(|simulated| {
+        simulated.borrow().as_ref().map(|time| Self {
+          time: Arc::clone(time),
+          entered: false,
+        })
+      })
+    }
+
+    /// Replaces the clocks of the current thread with this one, until the
+    /// returned handle is dropped.
+    pub fn enter(&self) -> Self {
+      SIMULATED_TIME.with(|simulated| *simulated.borrow_mut() = Some(Arc::clone(&self.time)));
+      Self {
+        time: Arc::clone(&self.time),
+        entered: true,
+      }
+    }
+
+    /// Lets time pass on both clocks
+    pub fn advance(&self, duration: Duration) {
+      let mut time = self.time.lock().unwrap();
+      time.elapsed = time.elapsed + duration;
+    }
+
+    /// Steps the wall clock only. Negative steps are backwards.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn step_wall_clock(&self, step: Duration) {
+      let mut time = self.time.lock().unwrap();
+      time.wall_clock_offset = time.wall_clock_offset + step;
+    }
+  }
+
+  impl Clone for SimulatedClock {
+    fn clone(&self) -> Self {
+      Self {
+        time: Arc::clone(&self.time),
+        entered: false,
+      }
+    }
+  }
+
+  impl Drop for SimulatedClock {
+    fn drop(&mut self) {
+      if self.entered {
+        SIMULATED_TIME.with(|simulated| *simulated.borrow_mut() = None);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn monotonic_clocks_ignore_wall_clock_steps() {
+    let clock = SimulatedClock::start();
+    let (monotonic, local, wall) = (monotonic_now(), local_timestamp(), wall_now());
+
+    clock.advance(Duration::from_secs(1));
+    clock.step_wall_clock(Duration::from_secs(-600));
+
+    assert_eq!(
+      monotonic_now() - monotonic,
+      std::time::Duration::from_secs(1)
+    );
+    assert_eq!(local_timestamp() - local, Duration::from_secs(1));
+    assert_eq!(wall_now() - wall, Duration::from_secs(-599));
+  }
+
+  #[test]
+  fn clock_jump_detector_reports_steps() {
+    let clock = SimulatedClock::start();
+    let mut detector = ClockJumpDetector::new();
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(detector.check(), None);
+
+    for step in [Duration::from_secs(600), Duration::from_secs(-600)] {
+      clock.advance(Duration::from_secs(5));
+      clock.step_wall_clock(step);
+      assert_eq!(detector.check(), Some(step));
+      // Reported only once
+      clock.advance(Duration::from_secs(5));
+      assert_eq!(detector.check(), None);
+    }
+  }
+
+  #[test]
+  fn entered_clock_is_followed_in_other_threads() {
+    let clock = SimulatedClock::start();
+    let shared = SimulatedClock::current().unwrap();
+    let start = wall_now();
+
+    clock.advance(Duration::from_secs(3));
+    let elapsed = std::thread::spawn(move || {
+      let _clock = shared.enter();
+      wall_now() - start
+    })
+    .join()
+    .unwrap();
+    assert_eq!(elapsed, Duration::from_secs(3));
+
+    // Leaving the other thread did not affect this one
+    assert_eq!(wall_now() - start, Duration::from_secs(3));
+  }
+
+  #[test]
+  fn local_timestamp_does_not_go_backwards() {
+    let mut previous = local_timestamp();
+    for _ in 0..1000 {
+      let now = local_timestamp();
+      assert!(now >= previous);
+      previous = now;
+    }
+  }
+}
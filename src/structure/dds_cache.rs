@@ -18,7 +18,7 @@ use crate::{
     typedesc::TypeDesc,
     CreateError, CreateResult,
   },
-  structure::{sequence_number::SequenceNumber, time::Timestamp},
+  structure::{clock, guid::GuidPrefix, sequence_number::SequenceNumber, time::Timestamp},
   GUID,
 };
 use super::cache_change::CacheChange;
@@ -97,6 +97,16 @@ impl DDSCache {
     }
   }
 
+  // A lost participant may come back with the same GUID prefix, and then its
+  // Writers number their samples from the start again. Forget the sequence
+  // numbers received from the participant, so that the new samples are not
+  // discarded as duplicates.
+  pub fn forget_participant_sequence_numbers(&mut self, guid_prefix: GuidPrefix) {
+    for tc in self.topic_caches.values() {
+      tc.lock().unwrap().forget_sequence_numbers(guid_prefix);
+    }
+  }
+
   pub fn garbage_collect(&mut self) {
     for tc in self.topic_caches.values_mut() {
       let mut tc = tc.lock().unwrap();
@@ -140,6 +150,12 @@ pub(crate) struct TopicCache {
   // Therefore, data before the marker SN can be handed off to a Reliable DataReader.
   // Initially, we consider the marker for each Writer (GUID) to be SequenceNumber::new(1)
   received_reliably_before: BTreeMap<GUID, SequenceNumber>,
+
+  // Writers whose sequence numbers have been forgotten, and the value of
+  // forget_count at that time. Reliable DataReaders use these to reset their
+  // read pointers.
+  forgotten_writers: BTreeMap<GUID, u64>,
+  forget_count: u64,
 }
 
 impl TopicCache {
@@ -155,6 +171,8 @@ impl TopicCache {
       changes_reallocated_up_to: Timestamp::ZERO,
       sequence_numbers: BTreeMap::new(),
       received_reliably_before: BTreeMap::new(),
+      forgotten_writers: BTreeMap::new(),
+      forget_count: 0,
     };
 
     new_self.update_keep_limits(topic_qos);
@@ -203,6 +221,44 @@ impl TopicCache {
     prev_sn.unwrap_or(SequenceNumber::new(1)) < sn
   }
 
+  fn forget_sequence_numbers(&mut self, guid_prefix: GuidPrefix) {
+    let writers: Vec<GUID> = self
+      .sequence_numbers
+      .range(guid_prefix.range())
+      .map(|(writer, _)| *writer)
+      .chain(
+        self
+          .received_reliably_before
+          .range(guid_prefix.range())
+          .map(|(writer, _)| *writer),
+      )
+      .collect();
+    if writers.is_empty() {
+      return;
+    }
+
+    // The changes themselves stay in the cache until garbage collected
+    self.forget_count += 1;
+    for writer in writers {
+      self.sequence_numbers.remove(&writer);
+      self.received_reliably_before.remove(&writer);
+      self.forgotten_writers.insert(writer, self.forget_count);
+    }
+  }
+
+  pub fn forget_count(&self) -> u64 {
+    self.forget_count
+  }
+
+  // Writers forgotten after the given forget_count
+  pub fn writers_forgotten_after(&self, forget_count: u64) -> impl Iterator<Item = &GUID> {
+    self
+      .forgotten_writers
+      .iter()
+      .filter(move |(_, count)| **count > forget_count)
+      .map(|(writer, _)| writer)
+  }
+
   pub fn get_change(&self, instant: &Timestamp) -> Option<&CacheChange> {
     self.changes.get(instant)
   }
@@ -261,7 +317,7 @@ impl TopicCache {
           "DDSHistoryCache already contained element with key {:?} !!!",
           instant
         );
-        self.remove_sn(&old_cc);
+        self.remove_sn(*instant, &old_cc);
         old_cc
       })
     }
@@ -328,11 +384,15 @@ impl TopicCache {
     // received reliably, since no such samples exist.
   }
 
-  fn remove_sn(&mut self, cc: &CacheChange) {
+  fn remove_sn(&mut self, instant: Timestamp, cc: &CacheChange) {
     let mut emptied = false;
 
     self.sequence_numbers.entry(cc.writer_guid).and_modify(|s| {
-      s.remove(&cc.sequence_number);
+      // If the sequence numbers of the Writer have been forgotten, the SN may now
+      // index a newer change
+      if s.get(&cc.sequence_number) == Some(&instant) {
+        s.remove(&cc.sequence_number);
+      }
       emptied = s.is_empty();
     });
     if emptied {
@@ -398,11 +458,13 @@ impl TopicCache {
     let to_remove = std::mem::replace(&mut self.changes, to_retain);
 
     // update also SequenceNumber map
-    to_remove.values().for_each(|r| self.remove_sn(r));
+    to_remove
+      .iter()
+      .for_each(|(instant, r)| self.remove_sn(*instant, r));
 
     // Now, reallocate old cache changes
     let reallocate_timeout = crate::Duration::from_secs(5);
-    let now = Timestamp::now();
+    let now = clock::local_timestamp();
     let reallocate_limit = now - reallocate_timeout;
 
     self
@@ -509,4 +571,49 @@ mod tests {
       3
     );
   }
+
+  #[test]
+  fn forgotten_participant_sequence_numbers_are_reused() {
+    let mut dds_cache = DDSCache::new();
+    let topic_cache_handle = dds_cache.add_new_topic(
+      String::from("Restarts"),
+      TypeDesc::new("Type".to_string()),
+      &QosPolicies::qos_none(),
+    );
+    let writer =
+      GUID::dummy_test_guid(crate::structure::guid::EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let change = || {
+      CacheChange::new(
+        writer,
+        SequenceNumber::new(1),
+        WriteOptions::default(),
+        DDSData::new(SerializedPayload::default()),
+      )
+    };
+    let count_changes = || {
+      topic_cache_handle
+        .lock()
+        .unwrap()
+        .get_changes_in_range_best_effort(crate::Timestamp::ZERO, crate::Timestamp::now())
+        .count()
+    };
+
+    let mut tc = topic_cache_handle.lock().unwrap();
+    tc.add_change(&crate::Timestamp::now(), change());
+    // A duplicate is discarded
+    tc.add_change(&crate::Timestamp::now(), change());
+    drop(tc);
+    assert_eq!(count_changes(), 1);
+
+    dds_cache.forget_participant_sequence_numbers(writer.prefix);
+    let mut tc = topic_cache_handle.lock().unwrap();
+    assert_eq!(
+      tc.writers_forgotten_after(0).collect::<Vec<_>>(),
+      vec![&writer]
+    );
+    assert_eq!(tc.writers_forgotten_after(tc.forget_count()).count(), 0);
+    tc.add_change(&crate::Timestamp::now(), change());
+    drop(tc);
+    assert_eq!(count_changes(), 2);
+  }
 }
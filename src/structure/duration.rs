@@ -77,6 +77,11 @@ impl Duration {
     }
   }
 
+  // Saturates to INFINITE
+  pub(crate) fn saturating_add(self, other: Self) -> Self {
+    Self::from_ticks(self.to_ticks().saturating_add(other.to_ticks()))
+  }
+
   pub fn to_nanoseconds(&self) -> i64 {
     ((i128::from(self.to_ticks()) * 1_000_000_000) >> 32) as i64
   }
@@ -0,0 +1,22 @@
+//! Consistency checks of the internal state of caches and proxies.
+//!
+//! Components with internal consistency rules have a `verify_invariants`
+//! method, which returns the violated rules as descriptive messages. With the
+//! feature "verify_invariants", the rules are also checked after every
+//! mutation, and a violation panics.
+
+/// Checks the invariants of a component that was just mutated, if the feature
+/// "verify_invariants" is enabled. Otherwise does nothing.
+#[inline]
+pub(crate) fn check_after_mutation(component: &str, verify: impl FnOnce() -> Vec<String>) {
+  #[cfg(feature = "verify_invariants")]
+  {
+    let violations = verify();
+    assert!(
+      violations.is_empty(),
+      "{component}: invariants violated: {violations:#?}"
+    );
+  }
+  #[cfg(not(feature = "verify_invariants"))]
+  let _ = (component, verify);
+}
@@ -101,6 +101,85 @@ impl ParameterId {
   // Section 7.4.1.6 "New DCPSParticipantSecure Builtin Topic"
   // Table 13
   pub const PID_IDENTITY_STATUS_TOKEN: Self = Self { value: 0x1006 };
+  // RTPS Spec v2.5 Section "9.6.2.2.1 ParameterId space": A parameter with the
+  // must-understand bit set must not be ignored. If we do not recognize it, the
+  // whole ParameterList must be rejected.
+  const MUST_UNDERSTAND_BIT: u16 = 0x4000;
+
+  // The parameters that we recognize, i.e. all of the above, except for PID_PAD
+  // and PID_SENTINEL, which are not parameters.
+  const KNOWN: &'static [Self] = &[
+    Self::PID_USER_DATA,
+    Self::PID_TOPIC_NAME,
+    Self::PID_TYPE_NAME,
+    Self::PID_GROUP_DATA,
+    Self::PID_TOPIC_DATA,
+    Self::PID_DURABILITY,
+    Self::PID_DURABILITY_SERVICE,
+    Self::PID_DEADLINE,
+    Self::PID_LATENCY_BUDGET,
+    Self::PID_LIVELINESS,
+    Self::PID_RELIABILITY,
+    Self::PID_LIFESPAN,
+    Self::PID_DESTINATION_ORDER,
+    Self::PID_HISTORY,
+    Self::PID_RESOURCE_LIMITS,
+    Self::PID_OWNERSHIP,
+    Self::PID_OWNERSHIP_STRENGTH,
+    Self::PID_PRESENTATION,
+    Self::PID_PARTITION,
+    Self::PID_TIME_BASED_FILTER,
+    Self::PID_TRANSPORT_PRIO,
+    Self::PID_PROTOCOL_VERSION,
+    Self::PID_VENDOR_ID,
+    Self::PID_UNICAST_LOCATOR,
+    Self::PID_MULTICAST_LOCATOR,
+    Self::PID_MULTICAST_IPADDRESS,
+    Self::PID_DEFAULT_UNICAST_LOCATOR,
+    Self::PID_DEFAULT_MULTICAST_LOCATOR,
+    Self::PID_METATRAFFIC_UNICAST_LOCATOR,
+    Self::PID_METATRAFFIC_MULTICAST_LOCATOR,
+    Self::PID_DEFAULT_UNICAST_IPADDRESS,
+    Self::PID_DEFAULT_UNICAST_PORT,
+    Self::PID_METATRAFFIC_UNICAST_IPADDRESS,
+    Self::PID_METATRAFFIC_UNICAST_PORT,
+    Self::PID_METATRAFFIC_MULTICAST_IPADDRESS,
+    Self::PID_METATRAFFIC_MULTICAST_PORT,
+    Self::PID_EXPECTS_INLINE_QOS,
+    Self::PID_PARTICIPANT_MANUAL_LIVELINESS_COUNT,
+    Self::PID_PARTICIPANT_BUILTIN_ENDPOINTS,
+    Self::PID_PARTICIPANT_LEASE_DURATION,
+    Self::PID_CONTENT_FILTER_PROPERTY,
+    Self::PID_PARTICIPANT_GUID,
+    Self::PID_GROUP_GUID,
+    Self::PID_GROUP_ENTITYID,
+    Self::PID_BUILTIN_ENDPOINT_SET,
+    Self::PID_ENDPOINT_GUID,
+    Self::PID_BUILTIN_ENDPOINT_QOS,
+    Self::PID_PROPERTY_LIST,
+    Self::PID_TYPE_MAX_SIZE_SERIALIZED,
+    Self::PID_ENTITY_NAME,
+    Self::PID_KEY_HASH,
+    Self::PID_STATUS_INFO,
+    Self::PID_SERVICE_INSTANCE_NAME,
+    Self::PID_RELATED_ENTITY_GUID,
+    Self::PID_TOPIC_ALIASES,
+    Self::PID_RELATED_SAMPLE_IDENTITY,
+    Self::PID_IDENTITY_TOKEN,
+    Self::PID_PERMISSIONS_TOKEN,
+    Self::PID_DATA_TAGS,
+    Self::PID_ENDPOINT_SECURITY_INFO,
+    Self::PID_PARTICIPANT_SECURITY_INFO,
+    Self::PID_IDENTITY_STATUS_TOKEN,
+  ];
+
+  pub fn must_understand(self) -> bool {
+    self.value & Self::MUST_UNDERSTAND_BIT != 0
+  }
+
+  pub fn is_known(self) -> bool {
+    Self::KNOWN.contains(&self)
+  }
 }
 
 #[cfg(test)]
@@ -320,7 +320,9 @@ where
                      * bitmap bits are numbered from MSB to LSB. Bit 0 (MSB of bitmap[0])
                      * represents SequenceNumber bitmap_base. Bit 31 (LSB of bitmap[0])
                      * represents SequenceNumber (bitmap_base + 31).
-                     * When num_bits == 0 , bitmap.len() == 0 */
+                     * When num_bits == 0 , bitmap.len() == 0
+                     * Bits beyond num_bits are always zero, so that equal sets
+                     * compare equal. */
 }
 
 // Not that empty sets also have a valid "base".
@@ -330,8 +332,22 @@ where
   N: Clone + Copy + Debug + Hash + PartialEq + Eq + NumOps + From<i64> + Ord + PartialOrd,
   i64: From<N>,
 {
+  /// Maximum size of the bitmap. RTPS spec v2.5 Sections
+  /// "8.3.5.5 SequenceNumberSet" and "8.3.5.7 FragmentNumberSet".
+  pub const MAX_BITS: u32 = 256;
+
   // Construct an empty set from given base number
   pub fn new(bitmap_base: N, num_bits: u32) -> Self {
+    let num_bits = if num_bits > Self::MAX_BITS {
+      error!(
+        "NumberSet::new: num_bits={} exceeds {}. Truncating.",
+        num_bits,
+        Self::MAX_BITS
+      );
+      Self::MAX_BITS
+    } else {
+      num_bits
+    };
     let word_count = (num_bits + 31) / 32;
     Self {
       bitmap_base,
@@ -340,6 +356,49 @@ where
     }
   }
 
+  /// Construct a set from its serialized parts: the base, the number of bits,
+  /// and (num_bits+31)/32 bitmap words. Fails if num_bits exceeds 256, the
+  /// number of words is wrong, or a bit beyond num_bits is set.
+  pub fn from_bitmap(bitmap_base: N, num_bits: u32, bitmap: Vec<u32>) -> Result<Self, String> {
+    if num_bits > Self::MAX_BITS {
+      return Err(format!(
+        "NumberSet size too large: {} > {}.",
+        num_bits,
+        Self::MAX_BITS
+      ));
+    }
+    let word_count = (num_bits + 31) / 32;
+    if bitmap.len() != word_count as usize {
+      return Err(format!(
+        "NumberSet of {} bits needs {} bitmap words, got {}.",
+        num_bits,
+        word_count,
+        bitmap.len()
+      ));
+    }
+    if let Some(&last) = bitmap.last() {
+      if last & Self::unused_bits_mask(num_bits) != 0 {
+        return Err(format!(
+          "NumberSet of {} bits has bits set beyond num_bits: last word = {:#010x}.",
+          num_bits, last
+        ));
+      }
+    }
+    Ok(Self {
+      bitmap_base,
+      num_bits,
+      bitmap,
+    })
+  }
+
+  // Bits of the last bitmap word that lie beyond num_bits
+  fn unused_bits_mask(num_bits: u32) -> u32 {
+    match num_bits % 32 {
+      0 => 0,
+      used => u32::MAX >> used,
+    }
+  }
+
   pub fn base(&self) -> N {
     self.bitmap_base
   }
@@ -447,32 +506,48 @@ where
 
 impl<'a, C: Context, N> Readable<'a, C> for NumberSet<N>
 where
-  N:
-    Clone + Debug + Hash + PartialEq + Eq + NumOps + From<i64> + Ord + PartialOrd + Readable<'a, C>,
+  N: Clone
+    + Copy
+    + Debug
+    + Hash
+    + PartialEq
+    + Eq
+    + NumOps
+    + From<i64>
+    + Ord
+    + PartialOrd
+    + Readable<'a, C>,
   i64: From<N>,
 {
   #[inline]
   fn read_from<R: Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
     let bitmap_base: N = reader.read_value()?;
     let num_bits: u32 = reader.read_value()?;
-    if num_bits > 256 {
+    if num_bits > Self::MAX_BITS {
       // Set size check accoring to RTPS spec v2.5 Section "8.3.5.5 SequenceNumberSet"
       // and "8.3.5.7 FragmentNumberSet"
       //
       // Without this chek the addition operation below could overflow.
-      Err(speedy::Error::custom(format!("NumberSet size too large: {} > 256.", num_bits)).into())
-    } else {
-      let word_count = (num_bits + 31) / 32;
-      let mut bitmap: Vec<u32> = Vec::with_capacity(word_count as usize);
-      for _ in 0..word_count {
-        bitmap.push(reader.read_value()?);
-      }
-      Ok(Self {
-        bitmap_base,
-        num_bits,
-        bitmap,
-      })
+      return Err(
+        speedy::Error::custom(format!(
+          "NumberSet size too large: {} > {}.",
+          num_bits,
+          Self::MAX_BITS
+        ))
+        .into(),
+      );
+    }
+    let word_count = (num_bits + 31) / 32;
+    let mut bitmap: Vec<u32> = Vec::with_capacity(word_count as usize);
+    for _ in 0..word_count {
+      bitmap.push(reader.read_value()?);
     }
+    // The bits beyond num_bits are undefined on the wire, and some
+    // implementations may set them. They carry no meaning, so clear them.
+    if let Some(last) = bitmap.last_mut() {
+      *last &= !Self::unused_bits_mask(num_bits);
+    }
+    Self::from_bitmap(bitmap_base, num_bits, bitmap).map_err(|e| speedy::Error::custom(e).into())
   }
 
   #[inline]
@@ -561,6 +636,8 @@ where
 
 #[cfg(test)]
 mod tests {
+  use speedy::Endianness;
+
   use super::*;
 
   #[test]
@@ -720,4 +797,244 @@ mod tests {
             0x00, 0x00, 0x00, 0x0E,
             0x5A, 0xA4, 0x00, 0x00]
   });
+
+  // Decodes a captured set in both byte orders, checks its members, and that
+  // encoding it again gives the same bytes.
+  fn check_capture<N>(le: &[u8], be: &[u8], members: &[N])
+  where
+    N: Copy
+      + Debug
+      + Hash
+      + Eq
+      + NumOps
+      + From<i64>
+      + Ord
+      + for<'a> Readable<'a, Endianness>
+      + Writable<Endianness>,
+    i64: From<N>,
+  {
+    for (endianness, bytes) in [(Endianness::LittleEndian, le), (Endianness::BigEndian, be)] {
+      let set = NumberSet::<N>::read_from_buffer_with_ctx(endianness, bytes).unwrap();
+      assert_eq!(set.iter().collect::<Vec<N>>(), members, "{endianness:?}");
+      assert_eq!(set.iter().rev().count(), members.len());
+      assert_eq!(set.len_serialized(), bytes.len());
+      assert_eq!(set.write_to_vec_with_ctx(endianness).unwrap(), bytes);
+    }
+  }
+
+  #[test]
+  fn sequence_number_set_captures() {
+    let sns = |sns: &[i64]| {
+      sns
+        .iter()
+        .map(|&sn| SequenceNumber::new(sn))
+        .collect::<Vec<_>>()
+    };
+    // bitmapBase = 10
+    let le_base = [0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00];
+    let be_base = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0A];
+
+    // numBits = 0, no bitmap words
+    check_capture(
+      &[&le_base[..], &[0x00, 0x00, 0x00, 0x00]].concat(),
+      &[&be_base[..], &[0x00, 0x00, 0x00, 0x00]].concat(),
+      &sns(&[]),
+    );
+    // numBits = 1, bitmap 0x8000_0000
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00],
+      ]
+      .concat(),
+      &sns(&[10]),
+    );
+    // numBits = 31, bitmap 0x8000_0002
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x1F, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x80],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x00, 0x1F, 0x80, 0x00, 0x00, 0x02],
+      ]
+      .concat(),
+      &sns(&[10, 40]),
+    );
+    // numBits = 33, bitmap 0x0000_0001 0x8000_0000
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x21, 0x00, 0x00, 0x00],
+        &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x00, 0x21],
+        &[0x00, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00],
+      ]
+      .concat(),
+      &sns(&[41, 42]),
+    );
+    // numBits = 256, bitmap 0x8000_0000, six zero words, 0x0000_0001
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80],
+        &[0x00; 24],
+        &[0x01, 0x00, 0x00, 0x00],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00],
+        &[0x00; 24],
+        &[0x00, 0x00, 0x00, 0x01],
+      ]
+      .concat(),
+      &sns(&[10, 265]),
+    );
+  }
+
+  #[test]
+  fn fragment_number_set_captures() {
+    let fns = |fns: &[u32]| {
+      fns
+        .iter()
+        .map(|&f| FragmentNumber::new(f))
+        .collect::<Vec<_>>()
+    };
+    // bitmapBase = 1000
+    let le_base = [0xE8, 0x03, 0x00, 0x00];
+    let be_base = [0x00, 0x00, 0x03, 0xE8];
+
+    check_capture(
+      &[&le_base[..], &[0x00, 0x00, 0x00, 0x00]].concat(),
+      &[&be_base[..], &[0x00, 0x00, 0x00, 0x00]].concat(),
+      &fns(&[]),
+    );
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00],
+      ]
+      .concat(),
+      &fns(&[1000]),
+    );
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x1F, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x80],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x00, 0x1F, 0x80, 0x00, 0x00, 0x02],
+      ]
+      .concat(),
+      &fns(&[1000, 1030]),
+    );
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x21, 0x00, 0x00, 0x00],
+        &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x00, 0x21],
+        &[0x00, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00],
+      ]
+      .concat(),
+      &fns(&[1031, 1032]),
+    );
+    check_capture(
+      &[
+        &le_base[..],
+        &[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80],
+        &[0x00; 24],
+        &[0x01, 0x00, 0x00, 0x00],
+      ]
+      .concat(),
+      &[
+        &be_base[..],
+        &[0x00, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00],
+        &[0x00; 24],
+        &[0x00, 0x00, 0x00, 0x01],
+      ]
+      .concat(),
+      &fns(&[1000, 1255]),
+    );
+  }
+
+  #[test]
+  fn bits_beyond_num_bits_are_ignored_when_decoding() {
+    // numBits = 1, but the whole bitmap word is ones
+    let bytes = [
+      0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
+      0xFF,
+    ];
+    let set =
+      SequenceNumberSet::read_from_buffer_with_ctx(Endianness::LittleEndian, &bytes).unwrap();
+    let mut expected = SequenceNumberSet::new(SequenceNumber::new(10), 1);
+    expected.insert(SequenceNumber::new(10));
+    assert_eq!(set, expected);
+    assert_eq!(
+      set.write_to_vec_with_ctx(Endianness::LittleEndian).unwrap()[12..],
+      [0x00, 0x00, 0x00, 0x80]
+    );
+  }
+
+  #[test]
+  fn too_large_set_is_rejected() {
+    // numBits = 257, followed by 9 words
+    let bytes = [
+      &[0xE8, 0x03, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00][..],
+      &[0x00; 36],
+    ]
+    .concat();
+    assert!(
+      FragmentNumberSet::read_from_buffer_with_ctx(Endianness::LittleEndian, &bytes).is_err()
+    );
+  }
+
+  #[test]
+  fn from_bitmap_validates() {
+    let base = SequenceNumber::new(1);
+    let set = SequenceNumberSet::from_bitmap(base, 33, vec![0x0000_0001, 0x8000_0000]).unwrap();
+    assert_eq!(
+      set.iter().collect::<Vec<_>>(),
+      [SequenceNumber::new(32), SequenceNumber::new(33)]
+    );
+    assert!(SequenceNumberSet::from_bitmap(base, 256, vec![0; 8]).is_ok());
+
+    // Too many bits
+    assert!(SequenceNumberSet::from_bitmap(base, 257, vec![0; 9]).is_err());
+    // Wrong number of words
+    assert!(SequenceNumberSet::from_bitmap(base, 33, vec![0]).is_err());
+    assert!(SequenceNumberSet::from_bitmap(base, 0, vec![0]).is_err());
+    // A bit beyond num_bits
+    assert!(SequenceNumberSet::from_bitmap(base, 33, vec![0, 0x4000_0000]).is_err());
+    assert!(SequenceNumberSet::from_bitmap(base, 31, vec![0x0000_0001]).is_err());
+  }
+
+  #[test]
+  fn new_truncates_to_256_bits() {
+    let set = SequenceNumberSet::new(SequenceNumber::new(1), 1000);
+    assert_eq!(set.len_serialized(), 8 + 4 + 32);
+  }
 }
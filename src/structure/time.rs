@@ -85,6 +85,13 @@ impl Timestamp {
   pub fn duration_since(&self, since: Self) -> Duration {
     *self - since
   }
+
+  // The same instant as a chrono DateTime, for comparing against the validity
+  // periods in security documents
+  pub(crate) fn to_utc(self) -> DateTime<Utc> {
+    let nanos = (u64::from(self.fraction) * 1_000_000_000) >> 32;
+    DateTime::from_timestamp(i64::from(self.seconds), nanos as u32).unwrap_or_default()
+  }
 }
 
 /// Error from this means "out of range"
@@ -56,3 +56,2685 @@ fn timestamp() -> Result<()> {
   }
   Ok(())
 }
+
+#[test]
+fn large_samples_are_fragmented_and_reassembled() -> Result<()> {
+  // Best effort: a sample is lost if any of its fragments is, so write the
+  // sample again until it arrives.
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::BestEffort)
+    .history(History::KeepLast { depth: 1 })
+    .build();
+  let sample: Vec<u8> = (0..1_000_000).map(|i: u32| (i % 251) as u8).collect();
+
+  // The default fragment size and a configured one
+  for (fragment_size, topic_name) in [(None, "large_sample"), (Some(16_000), "large_sample_16k")] {
+    let mut builder = crate::DomainParticipantBuilder::new(0);
+    if let Some(size) = fragment_size {
+      builder = builder.fragment_size(size);
+    }
+    let sender = builder.build()?;
+    let receiver = DomainParticipant::new(0)?;
+
+    let topic = sender.create_topic(
+      topic_name.to_string(),
+      "Bytes".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let writer = sender
+      .create_publisher(&qos)?
+      .create_datawriter_no_key_cdr::<Vec<u8>>(&topic, None)?;
+    let topic = receiver.create_topic(
+      topic_name.to_string(),
+      "Bytes".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let mut reader = receiver
+      .create_subscriber(&qos)?
+      .create_datareader_no_key_cdr::<Vec<u8>>(&topic, None)?;
+
+    let mut received = None;
+    for _ in 0..30 {
+      writer.write(sample.clone(), None)?;
+      thread::sleep(Duration::from_millis(500));
+      if let Some(s) = reader.take_next_sample()? {
+        received = Some(s.into_value());
+        break;
+      }
+    }
+    let received = received.expect("The large sample was not received");
+    assert!(
+      received == sample,
+      "The sample was corrupted. Fragment size {fragment_size:?}"
+    );
+  }
+  Ok(())
+}
+
+// Loses a tenth of the DATA_FRAGs that Readers of the registered topics
+// receive, as if the network had dropped them.
+pub(crate) mod fragment_loss {
+  use std::{collections::BTreeSet, sync::Mutex};
+
+  static LOSSY_TOPICS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+  pub fn make_lossy(topic_name: &str) {
+    LOSSY_TOPICS.lock().unwrap().insert(topic_name.to_string());
+  }
+
+  pub fn lose_fragment(topic_name: &str) -> bool {
+    LOSSY_TOPICS.lock().unwrap().contains(topic_name) && rand::random::<f64>() < 0.1
+  }
+}
+
+#[test]
+fn reliable_fragmented_samples_survive_fragment_loss() -> Result<()> {
+  let topic_name = "lossy_fragments";
+  fragment_loss::make_lossy(topic_name);
+
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .history(History::KeepAll)
+    .build();
+  let sender = DomainParticipant::new(0)?;
+  let receiver = DomainParticipant::new(0)?;
+
+  let topic = sender.create_topic(
+    topic_name.to_string(),
+    "Bytes".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = sender
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<Vec<u8>>(&topic, None)?;
+  let topic = receiver.create_topic(
+    topic_name.to_string(),
+    "Bytes".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = receiver
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<Vec<u8>>(&topic, None)?;
+
+  // Let discovery match the writer and the reader
+  thread::sleep(Duration::from_secs(2));
+
+  // About 100 fragments each
+  let samples: Vec<Vec<u8>> = (0..5_u8)
+    .map(|n| (0..100_000).map(|i: u32| (i % 251) as u8 ^ n).collect())
+    .collect();
+  for sample in &samples {
+    writer.write(sample.clone(), None)?;
+  }
+
+  let mut received = Vec::new();
+  for _ in 0..60 {
+    while let Some(s) = reader.take_next_sample()? {
+      received.push(s.into_value());
+    }
+    if received.len() >= samples.len() {
+      break;
+    }
+    thread::sleep(Duration::from_millis(500));
+  }
+  assert_eq!(
+    received.len(),
+    samples.len(),
+    "Not every sample was received"
+  );
+  assert!(received == samples, "The samples were corrupted");
+  Ok(())
+}
+
+#[cfg(feature = "fastdds_statistics")]
+#[test]
+fn statistics_topics_are_discovered() -> Result<()> {
+  use crate::{
+    statistics::{self, EntityCount, StatisticsPublisher},
+    with_key::Sample,
+    RTPSEntity,
+  };
+
+  let participant2 = DomainParticipant::new(0)?;
+  // Create the reader before the remote statistics writers are discovered
+  let statistics_qos = statistics::statistics_qos();
+  let data_count_topic = participant2.create_topic(
+    statistics::DATA_COUNT_TOPIC.to_string(),
+    "eprosima::fastdds::statistics::EntityCount".to_string(),
+    &statistics_qos,
+    TopicKind::WithKey,
+  )?;
+  let mut data_count_reader = participant2
+    .create_subscriber(&statistics_qos)?
+    .create_datareader_cdr::<EntityCount>(&data_count_topic, Some(statistics_qos.clone()))?;
+
+  let participant = DomainParticipant::new(0)?;
+  let _statistics = StatisticsPublisher::new(&participant, Duration::from_millis(100))?;
+
+  let expected = [
+    (
+      statistics::HISTORY_LATENCY_TOPIC,
+      "eprosima::fastdds::statistics::WriterReaderData",
+    ),
+    (
+      statistics::NETWORK_LATENCY_TOPIC,
+      "eprosima::fastdds::statistics::Locator2LocatorData",
+    ),
+    (
+      statistics::PUBLICATION_THROUGHPUT_TOPIC,
+      "eprosima::fastdds::statistics::EntityData",
+    ),
+    (
+      statistics::SUBSCRIPTION_THROUGHPUT_TOPIC,
+      "eprosima::fastdds::statistics::EntityData",
+    ),
+    (
+      statistics::HEARTBEAT_COUNT_TOPIC,
+      "eprosima::fastdds::statistics::EntityCount",
+    ),
+    (
+      statistics::ACKNACK_COUNT_TOPIC,
+      "eprosima::fastdds::statistics::EntityCount",
+    ),
+    (
+      statistics::GAP_COUNT_TOPIC,
+      "eprosima::fastdds::statistics::EntityCount",
+    ),
+    (
+      statistics::DATA_COUNT_TOPIC,
+      "eprosima::fastdds::statistics::EntityCount",
+    ),
+    (
+      statistics::RTPS_LOST_TOPIC,
+      "eprosima::fastdds::statistics::Entity2LocatorTraffic",
+    ),
+  ];
+  let mut all_discovered = false;
+  for _ in 0..100 {
+    let discovered = participant2.discovered_topics();
+    if expected
+      .iter()
+      .all(|(name, _)| discovered.iter().any(|t| t.topic_name() == *name))
+    {
+      for (name, type_name) in expected {
+        for topic in discovered.iter().filter(|t| t.topic_name() == name) {
+          assert_eq!(topic.type_name(), type_name, "type of {name}");
+        }
+      }
+      all_discovered = true;
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert!(all_discovered, "Statistics topics were not discovered");
+
+  // A user DataWriter sending data shows up in the data count topic
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(0).into(),
+    })
+    .build();
+  let user_topic = participant.create_topic(
+    "statistics_user_topic".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let user_writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<TestType>(&user_topic, None)?;
+  let user_topic2 = participant2.create_topic(
+    "statistics_user_topic".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let _user_reader = participant2
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<TestType>(&user_topic2, None)?;
+
+  let user_writer_guid = user_writer.guid().into();
+  for _ in 0..100 {
+    user_writer.write(TestType, None)?;
+    while let Ok(Some(sample)) = data_count_reader.take_next_sample() {
+      if let Sample::Value(count) = sample.into_value() {
+        if count.guid == user_writer_guid && count.count > 0 {
+          return Ok(());
+        }
+      }
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  panic!("No data count received for the user DataWriter");
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn secure_goodbye_removes_participant_promptly() -> Result<()> {
+  use std::time::Instant;
+
+  use crate::{
+    discovery::discovery_db::discovery_db_read, security::config::*, structure::guid::EntityId,
+    DomainParticipantStatusEvent, LostReason, RTPSEntity, StatusEvented,
+  };
+
+  // Lets a remote participant leave and reports why the other one lost it, if
+  // it did so within the given fraction of the lease duration.
+  fn leave(goodbye: bool, lease_fraction: f64) -> Result<Option<LostReason>> {
+    let configs = || {
+      DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        "examples/security_configuration_files",
+        "no_pwd".to_string(),
+      )
+    };
+    let faults = faulty_cryptography::CryptoFaults::default();
+    let leaving = crate::DomainParticipantBuilder::new(0)
+      .builtin_security_with_crypto(
+        configs(),
+        Box::new(faulty_cryptography::FaultyCryptography::new(faults.clone())),
+      )
+      .secure_goodbye(goodbye)
+      .build()?;
+    let staying = crate::DomainParticipantBuilder::new(0)
+      .builtin_security(configs())
+      .build()?;
+    let leaving_prefix = leaving.guid().prefix;
+    let status = staying.status_listener();
+
+    // Wait until the key exchange is done, i.e. protected data gets through
+    let qos = QosPolicyBuilder::new()
+      .reliability(Reliability::Reliable {
+        max_blocking_time: Duration::from_secs(0).into(),
+      })
+      .build();
+    let leaving_topic = leaving.create_topic(
+      "Square".to_string(),
+      "TestType".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let writer = leaving
+      .create_publisher(&qos)?
+      .create_datawriter_no_key_cdr::<TestType>(&leaving_topic, None)?;
+    let topic = staying.create_topic(
+      "Square".to_string(),
+      "TestType".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let mut reader = staying
+      .create_subscriber(&qos)?
+      .create_datareader_no_key_cdr::<TestType>(&topic, None)?;
+    let mut received = false;
+    for _ in 0..300 {
+      // Other tests create participants in the same domain, so keep the status
+      // channel from filling up
+      while status.try_recv_status().is_some() {}
+      let _ = writer.write(TestType, None);
+      if let Ok(Some(_)) = reader.take_next_sample() {
+        received = true;
+        break;
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+    assert!(received, "Protected data did not get through");
+    let lease = discovery_db_read(&staying.discovery_db())
+      .find_participant_proxy(leaving_prefix)
+      .and_then(|participant| participant.lease_duration)
+      .expect("Lease duration of the remote participant is not known")
+      .to_std();
+
+    // The secure participant dispose would also remove the participant, so keep
+    // it from getting out. Only the goodbye or lease expiry are left.
+    faults
+      .silenced_writers
+      .lock()
+      .unwrap()
+      .push(EntityId::SPDP_RELIABLE_BUILTIN_PARTICIPANT_SECURE_WRITER);
+    let left = Instant::now();
+    drop(writer);
+    drop(leaving_topic);
+    drop(leaving);
+
+    while left.elapsed() < lease.mul_f64(lease_fraction) {
+      while let Some(event) = status.try_recv_status() {
+        if let DomainParticipantStatusEvent::ParticipantLost { id, reason } = event {
+          if id == leaving_prefix {
+            return Ok(Some(reason));
+          }
+        }
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+    Ok(None)
+  }
+
+  // Without the goodbye, the participant stays until its lease expires
+  assert!(
+    leave(false, 0.5)?.is_none(),
+    "Remote participant was lost before its lease expired"
+  );
+
+  match leave(true, 0.5)? {
+    Some(reason) => assert!(matches!(reason, LostReason::Disposed)),
+    None => panic!("Remote participant did not notice the goodbye within half of its lease"),
+  }
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn unprotected_submessages_for_protected_endpoints_are_dropped() -> Result<()> {
+  use std::{
+    net::UdpSocket,
+    sync::{Arc, Mutex},
+  };
+
+  use bytes::Bytes;
+  use enumflags2::BitFlags;
+  use speedy::Writable;
+
+  use crate::{
+    messages::submessages::{
+      data::Data, heartbeat::Heartbeat, submessage_flag::FromEndianness, submessages::*,
+    },
+    network::constant::user_traffic_unicast_port,
+    rtps::{MessageBuilder, Submessage, SubmessageBody},
+    security::{
+      config::*,
+      cryptographic::{CryptoSecurityEvent, CryptoSecurityEventKind, SecurityEventListener},
+    },
+    structure::{guid::EntityId, sequence_number::SequenceNumber},
+    RTPSEntity,
+  };
+
+  #[derive(Default)]
+  struct Events(Mutex<Vec<CryptoSecurityEvent>>);
+
+  impl SecurityEventListener for Events {
+    fn on_crypto_event(&self, event: CryptoSecurityEvent) {
+      self.0.lock().unwrap().push(event);
+    }
+  }
+
+  let secure_participant_builder = || {
+    crate::DomainParticipantBuilder::new(0).builtin_security(
+      DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        "examples/security_configuration_files",
+        "no_pwd".to_string(),
+      ),
+    )
+  };
+  let events = Arc::new(Events::default());
+  let sender = secure_participant_builder().build()?;
+  let receiver = secure_participant_builder()
+    .security_event_listener(events.clone())
+    .build()?;
+
+  // Wait until the participants have authenticated each other, i.e. protected
+  // data gets through
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(0).into(),
+    })
+    .build();
+  let sender_topic = sender.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = sender
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<TestType>(&sender_topic, None)?;
+  let receiver_topic = receiver.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = receiver
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<TestType>(&receiver_topic, None)?;
+  let mut received = false;
+  for _ in 0..300 {
+    let _ = writer.write(TestType, None);
+    if let Ok(Some(_)) = reader.take_next_sample() {
+      received = true;
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert!(received, "Protected data did not get through");
+
+  // The builtin volatile secure reader requires submessage protection. Send it
+  // a DATA and a HEARTBEAT without protection, as if from the sender.
+  let endianness = speedy::Endianness::LittleEndian;
+  let data = Data {
+    reader_id: EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_READER,
+    writer_id: EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_WRITER,
+    writer_sn: SequenceNumber::new(1),
+    inline_qos: None,
+    serialized_payload: Some(Bytes::from_static(&[0, 1, 0, 0, 1, 2, 3, 4])),
+  };
+  let data_flags = BitFlags::<DATA_Flags>::from_endianness(endianness) | DATA_Flags::Data;
+  let data = Submessage {
+    header: SubmessageHeader {
+      kind: SubmessageKind::DATA,
+      flags: data_flags.bits(),
+      content_length: data.len_serialized() as u16,
+    },
+    body: SubmessageBody::Writer(WriterSubmessage::Data(data, data_flags)),
+    original_bytes: None,
+  };
+  let heartbeat = Heartbeat {
+    reader_id: EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_READER,
+    writer_id: EntityId::P2P_BUILTIN_PARTICIPANT_VOLATILE_SECURE_WRITER,
+    first_sn: SequenceNumber::new(1),
+    last_sn: SequenceNumber::new(1),
+    count: 1,
+  }
+  .create_submessage(BitFlags::from_endianness(endianness))
+  .unwrap();
+  let mut message = MessageBuilder::new().add_header_and_build(sender.guid().prefix);
+  message.add_submessage(data);
+  message.add_submessage(heartbeat);
+  let socket = UdpSocket::bind("127.0.0.1:0")?;
+  socket.send_to(
+    &message.write_to_vec()?,
+    (
+      "127.0.0.1",
+      user_traffic_unicast_port(0, receiver.participant_id()),
+    ),
+  )?;
+
+  let is_drop_report = |event: &CryptoSecurityEvent| {
+    event.kind == CryptoSecurityEventKind::Unprotected
+      && event.remote_guid_prefix == Some(sender.guid().prefix)
+  };
+  for _ in 0..50 {
+    let drops = receiver
+      .crypto_statistics()
+      .unwrap()
+      .unprotected_submessage_drops;
+    let reported = events
+      .0
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|event| is_drop_report(event))
+      .count();
+    if drops >= 2 && reported >= 2 {
+      return Ok(());
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  panic!("The unprotected submessages were not dropped and reported");
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn reliable_samples_sent_before_the_key_exchange_are_not_lost() -> Result<()> {
+  use std::sync::atomic::Ordering;
+
+  use crate::{
+    security::{config::*, AccessControlBuiltin, AuthenticationBuiltin},
+    DataReaderStatus, RTPSEntity, StatusEvented,
+  };
+
+  let configs = || {
+    DomainParticipantSecurityConfigFiles::with_ros_default_names(
+      "examples/security_configuration_files",
+      "no_pwd".to_string(),
+    )
+  };
+  let secure_participant = || {
+    crate::DomainParticipantBuilder::new(0)
+      .builtin_security(configs())
+      .build()
+  };
+  let sender = secure_participant()?;
+  let faults = faulty_cryptography::CryptoFaults::default();
+  let mut builder = crate::DomainParticipantBuilder::new(0);
+  builder.security(
+    Box::new(AuthenticationBuiltin::new()),
+    Box::new(AccessControlBuiltin::new()),
+    Box::new(faulty_cryptography::FaultyCryptography::new(faults.clone())),
+    configs().into_property_policy(),
+  );
+  let receiver = builder.build()?;
+
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .history(History::KeepAll)
+    .build();
+  let topic = |participant: &DomainParticipant| {
+    participant.create_topic(
+      "Square".to_string(),
+      "i32".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )
+  };
+  let receiver_topic = topic(&receiver)?;
+  let sender_topic = topic(&sender)?;
+
+  // Complete the key exchange of the builtin endpoints first, so that only
+  // the tokens of the writer under test are held. The data flows the other
+  // way, and the endpoints are gone before the test, since the builtin plugin
+  // does not take a second writer of the same participant for a reader.
+  // Secure discovery is slow when other tests run in parallel.
+  let warm_up_writer = receiver
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<i32>(&receiver_topic, None)?;
+  let mut warm_up_reader = sender
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<i32>(&sender_topic, None)?;
+  let mut warmed_up = false;
+  for _ in 0..200 {
+    let _ = warm_up_writer.write(-1, None);
+    if let Ok(Some(_)) = warm_up_reader.take_next_sample() {
+      warmed_up = true;
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert!(warmed_up, "Protected data did not get through");
+  drop(warm_up_writer);
+  drop(warm_up_reader);
+
+  let subscriber = receiver.create_subscriber(&qos)?;
+  let mut reader = subscriber.create_datareader_no_key_cdr::<i32>(&receiver_topic, None)?;
+  let take_all = |reader: &mut crate::no_key::DataReader<i32, _>| {
+    let mut taken = Vec::new();
+    while let Ok(Some(sample)) = reader.take_next_sample() {
+      taken.push(sample.into_value());
+    }
+    taken
+  };
+
+  // The crypto tokens of the writer are delayed, so its protected submessages
+  // arrive at the reader before the keys to decode them
+  faults.hold_writer_tokens.store(true, Ordering::SeqCst);
+  let missing_keys_before = faults.keys_not_found.lock().unwrap().len();
+  let writer = sender
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<i32>(&sender_topic, None)?;
+  let deadline = std::time::Instant::now() + Duration::from_secs(20);
+  let mut matched = false;
+  while !matched && std::time::Instant::now() < deadline {
+    match reader.try_recv_status() {
+      Some(DataReaderStatus::SubscriptionMatched { writer: guid, .. }) => {
+        matched = guid == writer.guid();
+      }
+      Some(_) => {}
+      None => thread::sleep(Duration::from_millis(1)),
+    }
+  }
+  assert!(matched, "The reader was not matched");
+  let sent: Vec<i32> = (0..20).collect();
+  for sample in &sent {
+    writer.write(*sample, None)?;
+  }
+  let mut waited_for_keys = false;
+  for _ in 0..500 {
+    waited_for_keys = !faults.held_writer_tokens.lock().unwrap().is_empty()
+      && faults.keys_not_found.lock().unwrap().len() > missing_keys_before;
+    if waited_for_keys {
+      break;
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  assert!(waited_for_keys, "No submessages waited for the keys");
+  let mut received = take_all(&mut reader);
+  assert!(received.is_empty(), "Samples were decoded without the keys");
+
+  // The tokens of the builtin writers of a participant that joins bring the
+  // held ones along
+  faults.hold_writer_tokens.store(false, Ordering::SeqCst);
+  let _joining = secure_participant()?;
+  for _ in 0..200 {
+    received.extend(take_all(&mut reader));
+    if received.len() >= sent.len() {
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert_eq!(received, sent);
+  assert!(
+    faults.decoded_later.load(Ordering::SeqCst) > 0,
+    "The submessages waiting for the keys were not decoded"
+  );
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn unauthenticated_participant_uses_only_unprotected_topics() -> Result<()> {
+  use crate::security::config::*;
+
+  // The governance allows unauthenticated participants. Topics matching
+  // "Encrypted*" have their data and submessages encrypted, but no access
+  // control, so only the endpoint protection keeps them from the plain
+  // participant.
+  let secure = crate::DomainParticipantBuilder::new(0)
+    .builtin_security(DomainParticipantSecurityConfigFiles {
+      domain_governance_document: "examples/security_configuration_files/\
+                                   governance_allow_unauthenticated.p7s"
+        .into(),
+      ..DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        "examples/security_configuration_files",
+        "no_pwd".to_string(),
+      )
+    })
+    .build()?;
+  let plain = DomainParticipant::new(0)?;
+
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(0).into(),
+    })
+    .build();
+  // Whether a sample written in `from` is received in `to` within the attempts
+  let exchanged = |from: &DomainParticipant,
+                   to: &DomainParticipant,
+                   topic_name: &str,
+                   attempts: usize|
+   -> Result<bool> {
+    let from_topic = from.create_topic(
+      topic_name.to_string(),
+      "i32".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let writer = from
+      .create_publisher(&qos)?
+      .create_datawriter_no_key_cdr::<i32>(&from_topic, None)?;
+    let to_topic = to.create_topic(
+      topic_name.to_string(),
+      "i32".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let mut reader = to
+      .create_subscriber(&qos)?
+      .create_datareader_no_key_cdr::<i32>(&to_topic, None)?;
+    for _ in 0..attempts {
+      let _ = writer.write(1, None);
+      if let Ok(Some(_)) = reader.take_next_sample() {
+        return Ok(true);
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+    Ok(false)
+  };
+
+  assert!(exchanged(&secure, &plain, "UnauthenticatedOpenA", 300)?);
+  assert!(exchanged(&plain, &secure, "UnauthenticatedOpenB", 300)?);
+  // Discovery has completed, so these would have matched by now
+  assert!(!exchanged(
+    &secure,
+    &plain,
+    "EncryptedUnauthenticatedA",
+    30
+  )?);
+  assert!(!exchanged(
+    &plain,
+    &secure,
+    "EncryptedUnauthenticatedB",
+    30
+  )?);
+  Ok(())
+}
+
+// Authentication plugin that loses incoming handshake messages while the
+// shared budget lasts, as if the network had dropped them. A fixed participant
+// GUID makes a new participant look like a restarted one.
+#[cfg(feature = "security")]
+mod lossy_authentication {
+  use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  };
+
+  use crate::{
+    security::{
+      access_control::*, authentication::*, security_error, AuthenticationBuiltin, SecurityResult,
+    },
+    structure::guid::GuidPrefix,
+    QosPolicies, GUID,
+  };
+
+  pub struct LossyAuthentication {
+    pub inner: AuthenticationBuiltin,
+    pub messages_to_drop: Arc<AtomicUsize>,
+    pub participant_guid: Option<GUID>,
+  }
+
+  impl LossyAuthentication {
+    // Ok if the message is lost
+    fn lose_message(&self) -> SecurityResult<()> {
+      let lost = self
+        .messages_to_drop
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok();
+      if lost {
+        Err(security_error("Handshake message lost"))
+      } else {
+        Ok(())
+      }
+    }
+  }
+
+  impl Authentication for LossyAuthentication {
+    fn validate_local_identity(
+      &mut self,
+      domain_id: u16,
+      participant_qos: &QosPolicies,
+      candidate_participant_guid: GUID,
+    ) -> SecurityResult<(ValidationOutcome, IdentityHandle, GUID)> {
+      self.inner.validate_local_identity(
+        domain_id,
+        participant_qos,
+        self.participant_guid.unwrap_or(candidate_participant_guid),
+      )
+    }
+    fn validate_remote_identity(
+      &mut self,
+      remote_auth_request_token: Option<AuthRequestMessageToken>,
+      local_identity_handle: IdentityHandle,
+      remote_identity_token: IdentityToken,
+      remote_participant_guidp: GuidPrefix,
+    ) -> SecurityResult<(
+      ValidationOutcome,
+      IdentityHandle,
+      Option<AuthRequestMessageToken>,
+    )> {
+      self.inner.validate_remote_identity(
+        remote_auth_request_token,
+        local_identity_handle,
+        remote_identity_token,
+        remote_participant_guidp,
+      )
+    }
+    fn begin_handshake_request(
+      &mut self,
+      initiator_identity_handle: IdentityHandle,
+      replier_identity_handle: IdentityHandle,
+      serialized_local_participant_data: Vec<u8>,
+    ) -> SecurityResult<(ValidationOutcome, HandshakeHandle, HandshakeMessageToken)> {
+      self.inner.begin_handshake_request(
+        initiator_identity_handle,
+        replier_identity_handle,
+        serialized_local_participant_data,
+      )
+    }
+    fn begin_handshake_reply(
+      &mut self,
+      handshake_message_in: HandshakeMessageToken,
+      initiator_identity_handle: IdentityHandle,
+      replier_identity_handle: IdentityHandle,
+      serialized_local_participant_data: Vec<u8>,
+    ) -> SecurityResult<(ValidationOutcome, HandshakeHandle, HandshakeMessageToken)> {
+      self.lose_message()?;
+      self.inner.begin_handshake_reply(
+        handshake_message_in,
+        initiator_identity_handle,
+        replier_identity_handle,
+        serialized_local_participant_data,
+      )
+    }
+    fn process_handshake(
+      &mut self,
+      handshake_message_in: HandshakeMessageToken,
+      handshake_handle: HandshakeHandle,
+    ) -> SecurityResult<(ValidationOutcome, Option<HandshakeMessageToken>)> {
+      self.lose_message()?;
+      self
+        .inner
+        .process_handshake(handshake_message_in, handshake_handle)
+    }
+    fn get_shared_secret(
+      &self,
+      remote_identity_handle: IdentityHandle,
+    ) -> SecurityResult<SharedSecretHandle> {
+      self.inner.get_shared_secret(remote_identity_handle)
+    }
+    fn get_authenticated_peer_credential_token(
+      &self,
+      handshake_handle: HandshakeHandle,
+    ) -> SecurityResult<AuthenticatedPeerCredentialToken> {
+      self
+        .inner
+        .get_authenticated_peer_credential_token(handshake_handle)
+    }
+    fn get_identity_token(&self, handle: IdentityHandle) -> SecurityResult<IdentityToken> {
+      self.inner.get_identity_token(handle)
+    }
+    fn get_identity_status_token(
+      &self,
+      handle: IdentityHandle,
+    ) -> SecurityResult<IdentityStatusToken> {
+      self.inner.get_identity_status_token(handle)
+    }
+    fn set_permissions_credential_and_token(
+      &mut self,
+      handle: IdentityHandle,
+      permissions_credential_token: PermissionsCredentialToken,
+      permissions_token: PermissionsToken,
+    ) -> SecurityResult<()> {
+      self.inner.set_permissions_credential_and_token(
+        handle,
+        permissions_credential_token,
+        permissions_token,
+      )
+    }
+    fn set_listener(&self) -> SecurityResult<()> {
+      self.inner.set_listener()
+    }
+  }
+}
+
+#[cfg(feature = "security")]
+fn lossy_secure_participant(
+  messages_to_drop: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+  resend_period: Duration,
+  max_attempts: u32,
+) -> Result<DomainParticipant> {
+  use crate::security::{
+    config::*, AccessControlBuiltin, AuthenticationBuiltin, CryptographicBuiltin,
+  };
+
+  let configs = DomainParticipantSecurityConfigFiles::with_ros_default_names(
+    "examples/security_configuration_files",
+    "no_pwd".to_string(),
+  );
+  let auth = lossy_authentication::LossyAuthentication {
+    inner: AuthenticationBuiltin::new(),
+    messages_to_drop,
+    participant_guid: None,
+  };
+  let mut builder = crate::DomainParticipantBuilder::new(0);
+  builder.security(
+    Box::new(auth),
+    Box::new(AccessControlBuiltin::new()),
+    Box::new(CryptographicBuiltin::new()),
+    configs.into_property_policy(),
+  );
+  Ok(
+    builder
+      .handshake_resends(resend_period, max_attempts)
+      .build()?,
+  )
+}
+
+// Builtin cryptography with faults injected by the tests. Once crashed, it
+// stops protecting anything. Protected messages are then not sent at all, so
+// the participant vanishes without a goodbye, as if it had been killed. While
+// holding the tokens of remote DataWriters, it accepts them but applies them
+// only with the first tokens set after the hold, as if they had been delayed.
+#[cfg(feature = "security")]
+mod faulty_cryptography {
+  use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+  };
+
+  use crate::{
+    messages::submessages::{
+      elements::parameter_list::ParameterList, secure_postfix::SecurePostfix,
+      secure_prefix::SecurePrefix, submessage::HasEntityIds,
+    },
+    rtps::{Message, Submessage, SubmessageBody},
+    security::{
+      access_control::types::*,
+      authentication::types::*,
+      cryptographic::{cryptographic_plugin::*, *},
+      security_error, CryptographicBuiltin, Property, SecurityResult,
+    },
+    structure::guid::EntityId,
+  };
+
+  type HeldWriterTokens = (
+    DatareaderCryptoHandle,
+    DatawriterCryptoHandle,
+    Vec<DatawriterCryptoToken>,
+  );
+
+  #[derive(Clone, Default)]
+  pub struct CryptoFaults {
+    pub crashed: Arc<AtomicBool>,
+    pub hold_writer_tokens: Arc<AtomicBool>,
+    pub held_writer_tokens: Arc<Mutex<Vec<HeldWriterTokens>>>,
+    // Submessages that could not be decoded for the lack of keys
+    pub keys_not_found: Arc<Mutex<Vec<SecurePrefix>>>,
+    // How many of those were decoded later
+    pub decoded_later: Arc<AtomicUsize>,
+    // Writers whose submessages fail to encode, so they never get out
+    pub silenced_writers: Arc<Mutex<Vec<EntityId>>>,
+  }
+
+  pub struct FaultyCryptography {
+    inner: CryptographicBuiltin,
+    faults: CryptoFaults,
+  }
+
+  impl FaultyCryptography {
+    pub fn new(faults: CryptoFaults) -> Self {
+      Self {
+        inner: CryptographicBuiltin::new(),
+        faults,
+      }
+    }
+
+    fn check_alive(&self) -> SecurityResult<()> {
+      if self.faults.crashed.load(Ordering::SeqCst) {
+        Err(security_error("Crashed"))
+      } else {
+        Ok(())
+      }
+    }
+  }
+
+  impl CryptoKeyFactory for FaultyCryptography {
+    fn register_local_participant(
+      &mut self,
+      participant_identity: IdentityHandle,
+      participant_permissions: PermissionsHandle,
+      participant_properties: &[Property],
+      participant_security_attributes: ParticipantSecurityAttributes,
+    ) -> SecurityResult<ParticipantCryptoHandle> {
+      self.inner.register_local_participant(
+        participant_identity,
+        participant_permissions,
+        participant_properties,
+        participant_security_attributes,
+      )
+    }
+    fn register_matched_remote_participant(
+      &mut self,
+      local_participant_crypto_handle: ParticipantCryptoHandle,
+      remote_participant_identity: IdentityHandle,
+      remote_participant_permissions: PermissionsHandle,
+      shared_secret: SharedSecretHandle,
+    ) -> SecurityResult<ParticipantCryptoHandle> {
+      self.inner.register_matched_remote_participant(
+        local_participant_crypto_handle,
+        remote_participant_identity,
+        remote_participant_permissions,
+        shared_secret,
+      )
+    }
+    fn register_local_datawriter(
+      &mut self,
+      participant_crypto_handle: ParticipantCryptoHandle,
+      datawriter_properties: &[Property],
+      datawriter_security_attributes: EndpointSecurityAttributes,
+    ) -> SecurityResult<DatawriterCryptoHandle> {
+      self.inner.register_local_datawriter(
+        participant_crypto_handle,
+        datawriter_properties,
+        datawriter_security_attributes,
+      )
+    }
+    fn register_matched_remote_datareader(
+      &mut self,
+      local_datawriter_crypto_handle: DatawriterCryptoHandle,
+      remote_participant_crypto: ParticipantCryptoHandle,
+      shared_secret: SharedSecretHandle,
+      relay_only: bool,
+    ) -> SecurityResult<DatareaderCryptoHandle> {
+      self.inner.register_matched_remote_datareader(
+        local_datawriter_crypto_handle,
+        remote_participant_crypto,
+        shared_secret,
+        relay_only,
+      )
+    }
+    fn register_matched_remote_datareaders(
+      &mut self,
+      local_datawriter_crypto_handle: DatawriterCryptoHandle,
+      remote_datareaders: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+      relay_only: bool,
+    ) -> SecurityResult<Vec<DatareaderCryptoHandle>> {
+      self.inner.register_matched_remote_datareaders(
+        local_datawriter_crypto_handle,
+        remote_datareaders,
+        relay_only,
+      )
+    }
+    fn register_local_datareader(
+      &mut self,
+      participant_crypto_handle: ParticipantCryptoHandle,
+      datareader_properties: &[Property],
+      datareader_security_attributes: EndpointSecurityAttributes,
+    ) -> SecurityResult<DatareaderCryptoHandle> {
+      self.inner.register_local_datareader(
+        participant_crypto_handle,
+        datareader_properties,
+        datareader_security_attributes,
+      )
+    }
+    fn register_matched_remote_datawriter(
+      &mut self,
+      local_datareader_crypto_handle: DatareaderCryptoHandle,
+      remote_participant_crypt: ParticipantCryptoHandle,
+      shared_secret: SharedSecretHandle,
+    ) -> SecurityResult<DatawriterCryptoHandle> {
+      self.inner.register_matched_remote_datawriter(
+        local_datareader_crypto_handle,
+        remote_participant_crypt,
+        shared_secret,
+      )
+    }
+    fn register_matched_remote_datawriters(
+      &mut self,
+      local_datareader_crypto_handle: DatareaderCryptoHandle,
+      remote_datawriters: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+    ) -> SecurityResult<Vec<DatawriterCryptoHandle>> {
+      self
+        .inner
+        .register_matched_remote_datawriters(local_datareader_crypto_handle, remote_datawriters)
+    }
+    fn unregister_participant(
+      &mut self,
+      participant_crypto_handle: ParticipantCryptoHandle,
+    ) -> SecurityResult<()> {
+      self.inner.unregister_participant(participant_crypto_handle)
+    }
+    fn unregister_datawriter(
+      &mut self,
+      datawriter_crypto_handle: DatawriterCryptoHandle,
+    ) -> SecurityResult<()> {
+      self.inner.unregister_datawriter(datawriter_crypto_handle)
+    }
+    fn unregister_datareader(
+      &mut self,
+      datareader_crypto_handle: DatareaderCryptoHandle,
+    ) -> SecurityResult<()> {
+      self.inner.unregister_datareader(datareader_crypto_handle)
+    }
+  }
+
+  impl CryptoKeyExchange for FaultyCryptography {
+    fn create_local_participant_crypto_tokens(
+      &mut self,
+      local_participant_crypto: ParticipantCryptoHandle,
+      remote_participant_crypto: ParticipantCryptoHandle,
+    ) -> SecurityResult<Vec<ParticipantCryptoToken>> {
+      self
+        .inner
+        .create_local_participant_crypto_tokens(local_participant_crypto, remote_participant_crypto)
+    }
+    fn set_remote_participant_crypto_tokens(
+      &mut self,
+      local_participant_crypto: ParticipantCryptoHandle,
+      remote_participant_crypto: ParticipantCryptoHandle,
+      remote_participant_tokens: Vec<ParticipantCryptoToken>,
+    ) -> SecurityResult<()> {
+      self.inner.set_remote_participant_crypto_tokens(
+        local_participant_crypto,
+        remote_participant_crypto,
+        remote_participant_tokens,
+      )
+    }
+    fn create_local_datawriter_crypto_tokens(
+      &mut self,
+      local_datawriter_crypto: DatawriterCryptoHandle,
+      remote_datareader_crypto: DatareaderCryptoHandle,
+    ) -> SecurityResult<Vec<DatawriterCryptoToken>> {
+      self
+        .inner
+        .create_local_datawriter_crypto_tokens(local_datawriter_crypto, remote_datareader_crypto)
+    }
+    fn set_remote_datawriter_crypto_tokens(
+      &mut self,
+      local_datareader_crypto: DatareaderCryptoHandle,
+      remote_datawriter_crypto: DatawriterCryptoHandle,
+      remote_datawriter_tokens: Vec<DatawriterCryptoToken>,
+    ) -> SecurityResult<()> {
+      let mut held_writer_tokens = self.faults.held_writer_tokens.lock().unwrap();
+      if self.faults.hold_writer_tokens.load(Ordering::SeqCst) {
+        held_writer_tokens.push((
+          local_datareader_crypto,
+          remote_datawriter_crypto,
+          remote_datawriter_tokens,
+        ));
+        return Ok(());
+      }
+      for (local_datareader_crypto, remote_datawriter_crypto, remote_datawriter_tokens) in
+        held_writer_tokens.drain(..)
+      {
+        self.inner.set_remote_datawriter_crypto_tokens(
+          local_datareader_crypto,
+          remote_datawriter_crypto,
+          remote_datawriter_tokens,
+        )?;
+      }
+      self.inner.set_remote_datawriter_crypto_tokens(
+        local_datareader_crypto,
+        remote_datawriter_crypto,
+        remote_datawriter_tokens,
+      )
+    }
+    fn create_local_datareader_crypto_tokens(
+      &mut self,
+      local_datareader_crypto: DatareaderCryptoHandle,
+      remote_datawriter_crypto: DatawriterCryptoHandle,
+    ) -> SecurityResult<Vec<DatareaderCryptoToken>> {
+      self
+        .inner
+        .create_local_datareader_crypto_tokens(local_datareader_crypto, remote_datawriter_crypto)
+    }
+    fn set_remote_datareader_crypto_tokens(
+      &mut self,
+      local_datawriter_crypto: DatawriterCryptoHandle,
+      remote_datareader_crypto: DatareaderCryptoHandle,
+      remote_datareader_tokens: Vec<DatareaderCryptoToken>,
+    ) -> SecurityResult<()> {
+      self.inner.set_remote_datareader_crypto_tokens(
+        local_datawriter_crypto,
+        remote_datareader_crypto,
+        remote_datareader_tokens,
+      )
+    }
+    fn return_crypto_tokens(&mut self, crypto_tokens: Vec<CryptoToken>) -> SecurityResult<()> {
+      self.inner.return_crypto_tokens(crypto_tokens)
+    }
+  }
+
+  impl CryptoTransform for FaultyCryptography {
+    fn encode_serialized_payload(
+      &self,
+      plain_buffer: Vec<u8>,
+      sending_datawriter_crypto: DatawriterCryptoHandle,
+    ) -> SecurityResult<(Vec<u8>, ParameterList)> {
+      self.check_alive()?;
+      self
+        .inner
+        .encode_serialized_payload(plain_buffer, sending_datawriter_crypto)
+    }
+    fn encode_datawriter_submessage(
+      &self,
+      plain_rtps_submessage: Submessage,
+      sending_datawriter_crypto: DatawriterCryptoHandle,
+      receiving_datareader_crypto_list: Vec<DatareaderCryptoHandle>,
+    ) -> SecurityResult<EncodedSubmessage> {
+      self.check_alive()?;
+      if let SubmessageBody::Writer(writer_submessage) = &plain_rtps_submessage.body {
+        let silenced_writers = self.faults.silenced_writers.lock().unwrap();
+        if silenced_writers.contains(&writer_submessage.sender_entity_id()) {
+          return Err(security_error("Silenced"));
+        }
+      }
+      self.inner.encode_datawriter_submessage(
+        plain_rtps_submessage,
+        sending_datawriter_crypto,
+        receiving_datareader_crypto_list,
+      )
+    }
+    fn encode_datareader_submessage(
+      &self,
+      plain_rtps_submessage: Submessage,
+      sending_datareader_crypto: DatareaderCryptoHandle,
+      receiving_datawriter_crypto_list: Vec<DatawriterCryptoHandle>,
+    ) -> SecurityResult<EncodedSubmessage> {
+      self.check_alive()?;
+      self.inner.encode_datareader_submessage(
+        plain_rtps_submessage,
+        sending_datareader_crypto,
+        receiving_datawriter_crypto_list,
+      )
+    }
+    fn encode_rtps_message(
+      &self,
+      plain_rtps_message: Message,
+      sending_participant_crypto: ParticipantCryptoHandle,
+      receiving_participant_crypto_list: Vec<ParticipantCryptoHandle>,
+    ) -> SecurityResult<Message> {
+      self.check_alive()?;
+      self.inner.encode_rtps_message(
+        plain_rtps_message,
+        sending_participant_crypto,
+        receiving_participant_crypto_list,
+      )
+    }
+    fn decode_rtps_message(
+      &self,
+      encoded_message: Message,
+      receiving_participant_crypto: ParticipantCryptoHandle,
+      sending_participant_crypto: ParticipantCryptoHandle,
+    ) -> SecurityResult<DecodeOutcome<Message>> {
+      self.inner.decode_rtps_message(
+        encoded_message,
+        receiving_participant_crypto,
+        sending_participant_crypto,
+      )
+    }
+    fn preprocess_secure_submessage(
+      &self,
+      secure_prefix: &SecurePrefix,
+      receiving_participant_crypto: ParticipantCryptoHandle,
+      sending_participant_crypto: ParticipantCryptoHandle,
+    ) -> SecurityResult<SecureSubmessageCategory> {
+      self.inner.preprocess_secure_submessage(
+        secure_prefix,
+        receiving_participant_crypto,
+        sending_participant_crypto,
+      )
+    }
+    fn decode_submessage(
+      &self,
+      encoded_rtps_submessage: (SecurePrefix, Submessage, SecurePostfix),
+      receiving_local_participant_crypto: ParticipantCryptoHandle,
+      sending_remote_participant_crypto: ParticipantCryptoHandle,
+    ) -> SecurityResult<DecodeOutcome<DecodedSubmessage>> {
+      let secure_prefix = encoded_rtps_submessage.0.clone();
+      let outcome = self.inner.decode_submessage(
+        encoded_rtps_submessage,
+        receiving_local_participant_crypto,
+        sending_remote_participant_crypto,
+      )?;
+      let mut keys_not_found = self.faults.keys_not_found.lock().unwrap();
+      match outcome {
+        DecodeOutcome::KeysNotFound(_) => keys_not_found.push(secure_prefix),
+        DecodeOutcome::Success(_) if keys_not_found.contains(&secure_prefix) => {
+          self.faults.decoded_later.fetch_add(1, Ordering::SeqCst);
+        }
+        _ => {}
+      }
+      Ok(outcome)
+    }
+    fn decode_serialized_payload(
+      &self,
+      encoded_buffer: Vec<u8>,
+      inline_qos: ParameterList,
+      receiving_datareader_crypto: DatareaderCryptoHandle,
+      sending_datawriter_crypto: DatawriterCryptoHandle,
+    ) -> SecurityResult<Vec<u8>> {
+      self.inner.decode_serialized_payload(
+        encoded_buffer,
+        inline_qos,
+        receiving_datareader_crypto,
+        sending_datawriter_crypto,
+      )
+    }
+  }
+
+  impl Cryptographic for FaultyCryptography {}
+}
+
+// A participant that can crash, and be restarted with the same GUID
+#[cfg(feature = "security")]
+fn restartable_secure_participant(
+  participant_guid: crate::GUID,
+  crashed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> Result<DomainParticipant> {
+  use std::sync::{atomic::AtomicUsize, Arc};
+
+  use crate::security::{config::*, AccessControlBuiltin, AuthenticationBuiltin};
+
+  let configs = DomainParticipantSecurityConfigFiles::with_ros_default_names(
+    "examples/security_configuration_files",
+    "no_pwd".to_string(),
+  );
+  let auth = lossy_authentication::LossyAuthentication {
+    inner: AuthenticationBuiltin::new(),
+    messages_to_drop: Arc::new(AtomicUsize::new(0)),
+    participant_guid: Some(participant_guid),
+  };
+  let crypto = faulty_cryptography::FaultyCryptography::new(faulty_cryptography::CryptoFaults {
+    crashed,
+    ..Default::default()
+  });
+  let mut builder = crate::DomainParticipantBuilder::new(0);
+  builder.security(
+    Box::new(auth),
+    Box::new(AccessControlBuiltin::new()),
+    Box::new(crypto),
+    configs.into_property_policy(),
+  );
+  Ok(
+    builder
+      .handshake_resends(Duration::from_millis(200), 10)
+      .build()?,
+  )
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn authentication_survives_lost_handshake_messages() -> Result<()> {
+  use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  };
+
+  // The first two handshake messages, whichever participant receives them, are
+  // lost
+  let messages_to_drop = Arc::new(AtomicUsize::new(2));
+  let resend_period = Duration::from_millis(200);
+  let sender = lossy_secure_participant(messages_to_drop.clone(), resend_period, 10)?;
+  let receiver = lossy_secure_participant(messages_to_drop.clone(), resend_period, 10)?;
+
+  // Protected data gets through only after the participants have
+  // authenticated each other
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(0).into(),
+    })
+    .build();
+  let sender_topic = sender.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = sender
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<TestType>(&sender_topic, None)?;
+  let receiver_topic = receiver.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = receiver
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<TestType>(&receiver_topic, None)?;
+  let mut received = false;
+  for _ in 0..300 {
+    let _ = writer.write(TestType, None);
+    if let Ok(Some(_)) = reader.take_next_sample() {
+      received = true;
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert_eq!(messages_to_drop.load(Ordering::SeqCst), 0);
+  assert!(received, "Protected data did not get through");
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn unanswered_handshake_is_given_up() -> Result<()> {
+  use std::sync::{atomic::AtomicUsize, Arc};
+
+  use crate::{DomainParticipantStatusEvent, LostReason, RTPSEntity, StatusEvented};
+
+  // One participant loses every handshake message it receives, so the other
+  // one never gets a valid answer
+  let resend_period = Duration::from_millis(100);
+  let deaf = lossy_secure_participant(Arc::new(AtomicUsize::new(usize::MAX)), resend_period, 2)?;
+  let participant = lossy_secure_participant(Arc::new(AtomicUsize::new(0)), resend_period, 2)?;
+  let deaf_prefix = deaf.guid().prefix;
+  let status = participant.status_listener();
+
+  for _ in 0..100 {
+    while let Some(event) = status.try_recv_status() {
+      if let DomainParticipantStatusEvent::ParticipantLost { id, reason } = event {
+        if id == deaf_prefix {
+          assert!(matches!(reason, LostReason::AuthenticationFailed));
+          return Ok(());
+        }
+      }
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  panic!("The unanswered handshake was not given up");
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn participant_with_expired_permissions_is_removed() -> Result<()> {
+  use crate::{
+    discovery::discovery_db::discovery_db_read,
+    security::{
+      access_control::access_control_builtin::example_permissions_expiring_at, config::*,
+    },
+    DomainParticipantStatusEvent, LostReason, ParticipantAuthenticationStatus, RTPSEntity,
+    StatusEvented,
+  };
+
+  let configs = || {
+    DomainParticipantSecurityConfigFiles::with_ros_default_names(
+      "examples/security_configuration_files",
+      "no_pwd".to_string(),
+    )
+  };
+  let participant = crate::DomainParticipantBuilder::new(0)
+    .builtin_security(configs())
+    .build()?;
+  let status = participant.status_listener();
+
+  // Long enough for the authentication to complete before the expiration. The
+  // permissions document has a resolution of one second.
+  let expiration =
+    chrono::SubsecRound::trunc_subsecs(chrono::Utc::now() + chrono::Duration::seconds(10), 0);
+  let permissions_dir = tempfile::tempdir()?;
+  let permissions = permissions_dir.path().join("permissions.p7s");
+  std::fs::write(&permissions, example_permissions_expiring_at(expiration))?;
+  let expiring = crate::DomainParticipantBuilder::new(0)
+    .builtin_security(DomainParticipantSecurityConfigFiles {
+      participant_permissions_document: permissions,
+      ..configs()
+    })
+    .build()?;
+  let expiring_prefix = expiring.guid().prefix;
+
+  let mut authenticated = false;
+  for _ in 0..300 {
+    while let Some(event) = status.try_recv_status() {
+      match event {
+        DomainParticipantStatusEvent::ParticipantAuthenticationStatus {
+          participant,
+          status: ParticipantAuthenticationStatus::Authenticated,
+          ..
+        } if participant == expiring_prefix => authenticated = true,
+        DomainParticipantStatusEvent::ParticipantLost { id, reason } if id == expiring_prefix => {
+          assert!(authenticated, "Lost before it was authenticated");
+          assert!(
+            matches!(reason, LostReason::PermissionsExpired),
+            "{reason:?}"
+          );
+          // The expiring participant is still running, so only the expiration
+          // can have removed it
+          assert!(chrono::Utc::now() >= expiration);
+          assert!(!discovery_db_read(&participant.discovery_db())
+            .authenticated_participants()
+            .any(|prefix| prefix == expiring_prefix));
+          return Ok(());
+        }
+        _ => (),
+      }
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  panic!(
+    "The participant with expired permissions was not removed (authenticated: {authenticated})"
+  );
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn authentication_status_is_reported_to_the_application() -> Result<()> {
+  use std::path::Path;
+
+  use crate::{
+    security::config::*, DomainParticipantStatusEvent, ParticipantAuthenticationStatus, RTPSEntity,
+    StatusEvented,
+  };
+
+  fn participant_with_identity(
+    identity_ca: &str,
+    certificate: &str,
+    private_key: &str,
+  ) -> Result<DomainParticipant> {
+    let config_dir = Path::new("examples/security_configuration_files");
+    let configs = DomainParticipantSecurityConfigFiles {
+      identity_ca_certificate: config_dir.join(identity_ca),
+      participant_identity_certificate: config_dir.join(certificate),
+      participant_identity_private_key: PrivateSigningKey::Files {
+        file_path: config_dir.join(private_key),
+        file_password: "no_pwd".to_string(),
+      },
+      ..DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        config_dir,
+        "no_pwd".to_string(),
+      )
+    };
+    Ok(
+      crate::DomainParticipantBuilder::new(0)
+        .builtin_security(configs)
+        .handshake_resends(Duration::from_millis(200), 10)
+        .build()?,
+    )
+  }
+
+  // No readers or writers are created, so the events come from the
+  // authentication alone
+  let participant = participant_with_identity("identity_ca.cert.pem", "cert.pem", "key.pem")?;
+  let status_listener = participant.status_listener();
+  let trusted = participant_with_identity("identity_ca.cert.pem", "cert.pem", "key.pem")?;
+  // Valid in its own eyes, but signed by a CA that the others do not trust
+  let untrusted = participant_with_identity(
+    "other_ca.cert.pem",
+    "untrusted_cert.pem",
+    "untrusted_key.pem",
+  )?;
+
+  let mut trusted_events = Vec::new();
+  let mut untrusted_events = Vec::new();
+  // Other tests create participants in the same domain, so keep the status
+  // channel from filling up
+  for _ in 0..3000 {
+    while let Some(event) = status_listener.try_recv_status() {
+      if let DomainParticipantStatusEvent::ParticipantAuthenticationStatus {
+        participant,
+        subject_name,
+        status,
+      } = event
+      {
+        if participant == trusted.guid().prefix {
+          trusted_events.push((subject_name, status));
+        } else if participant == untrusted.guid().prefix {
+          untrusted_events.push((subject_name, status));
+        }
+      }
+    }
+    if !trusted_events.is_empty() && !untrusted_events.is_empty() {
+      break;
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+
+  let (subject_name, status) = trusted_events
+    .first()
+    .expect("No status for the trusted peer");
+  assert_eq!(*status, ParticipantAuthenticationStatus::Authenticated);
+  assert!(subject_name
+    .as_ref()
+    .is_some_and(|name| name.contains("participant1_common_name")));
+
+  let (subject_name, status) = untrusted_events
+    .first()
+    .expect("No status for the untrusted peer");
+  assert!(
+    matches!(status, ParticipantAuthenticationStatus::Rejected { .. }),
+    "{status:?}"
+  );
+  assert!(subject_name
+    .as_ref()
+    .is_some_and(|name| name.contains("participant2_common_name")));
+
+  // A rejected peer is not authenticated later on
+  thread::sleep(Duration::from_secs(1));
+  while let Some(event) = status_listener.try_recv_status() {
+    if let DomainParticipantStatusEvent::ParticipantAuthenticationStatus {
+      participant,
+      subject_name,
+      status,
+    } = event
+    {
+      if participant == untrusted.guid().prefix {
+        untrusted_events.push((subject_name, status));
+      }
+    }
+  }
+  assert!(untrusted_events
+    .iter()
+    .all(|(_, status)| *status != ParticipantAuthenticationStatus::Authenticated));
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn restarted_participant_is_authenticated_again() -> Result<()> {
+  use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+  };
+
+  use crate::{RTPSEntity, GUID};
+
+  let restarting_guid = GUID::new_participant_guid();
+  let stable = lossy_secure_participant(
+    Arc::new(AtomicUsize::new(0)),
+    Duration::from_millis(200),
+    10,
+  )?;
+
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(0).into(),
+    })
+    .build();
+  let stable_topic = stable.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = stable
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<TestType>(&stable_topic, None)?;
+
+  // Protected data gets through only after the participants have
+  // authenticated each other and exchanged keys
+  let receives_data = |participant: &DomainParticipant| -> Result<bool> {
+    let topic = participant.create_topic(
+      "Square".to_string(),
+      "TestType".to_string(),
+      &qos,
+      TopicKind::NoKey,
+    )?;
+    let mut reader = participant
+      .create_subscriber(&qos)?
+      .create_datareader_no_key_cdr::<TestType>(&topic, None)?;
+    for _ in 0..300 {
+      let _ = writer.write(TestType, None);
+      if let Ok(Some(_)) = reader.take_next_sample() {
+        return Ok(true);
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+    Ok(false)
+  };
+
+  let crashed = Arc::new(AtomicBool::new(false));
+  let first = restartable_secure_participant(restarting_guid, crashed.clone())?;
+  let guid_prefix = first.guid().prefix;
+  assert!(receives_data(&first)?, "Protected data did not get through");
+  // No protected dispose gets out, so the stable participant still considers
+  // the crashed one authenticated
+  crashed.store(true, Ordering::SeqCst);
+  drop(first);
+
+  let second = restartable_secure_participant(restarting_guid, Arc::new(AtomicBool::new(false)))?;
+  assert_eq!(second.guid().prefix, guid_prefix);
+  assert!(
+    receives_data(&second)?,
+    "Protected data did not get through after the restart"
+  );
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn spoofed_authentication_messages_do_not_remove_an_authenticated_peer() -> Result<()> {
+  use std::net::UdpSocket;
+
+  use bytes::Bytes;
+  use byteorder::LittleEndian;
+  use enumflags2::BitFlags;
+  use speedy::Writable;
+
+  use crate::{
+    messages::submessages::{data::Data, submessage_flag::FromEndianness, submessages::*},
+    network::constant::user_traffic_unicast_port,
+    rtps::{MessageBuilder, Submessage, SubmessageBody},
+    security::{
+      authentication::{GMCLASSID_SECURITY_AUTH_HANDSHAKE, GMCLASSID_SECURITY_AUTH_REQUEST},
+      config::*,
+      types::{DataHolderBuilder, ParticipantGenericMessage, ParticipantStatelessMessage},
+    },
+    structure::{guid::EntityId, rpc::SampleIdentity, sequence_number::SequenceNumber},
+    DomainParticipantStatusEvent, RTPSEntity, StatusEvented, GUID,
+  };
+
+  let secure_participant = || {
+    crate::DomainParticipantBuilder::new(0)
+      .builtin_security(
+        DomainParticipantSecurityConfigFiles::with_ros_default_names(
+          "examples/security_configuration_files",
+          "no_pwd".to_string(),
+        ),
+      )
+      .handshake_resends(Duration::from_millis(200), 5)
+      .build()
+  };
+  // An authentication request to the participant that begins the handshakes
+  // makes it begin a new handshake with the genuine peer. Spoof the other one,
+  // which only waits for handshake requests.
+  let (peer, participant) = {
+    let (first, second) = (secure_participant()?, secure_participant()?);
+    if first.guid().prefix < second.guid().prefix {
+      (first, second)
+    } else {
+      (second, first)
+    }
+  };
+  let status = participant.status_listener();
+
+  let qos = QosPolicyBuilder::new()
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(0).into(),
+    })
+    .build();
+  let peer_topic = peer.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = peer
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<TestType>(&peer_topic, None)?;
+  let topic = participant.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = participant
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<TestType>(&topic, None)?;
+  let mut receives_data = || {
+    for _ in 0..300 {
+      let _ = writer.write(TestType, None);
+      if let Ok(Some(_)) = reader.take_next_sample() {
+        return true;
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+    false
+  };
+  assert!(receives_data(), "Protected data did not get through");
+  // Forget what happened during the authentication
+  while status.try_recv_status().is_some() {}
+
+  // Stateless messages are not protected, so anyone can claim to be the peer.
+  // Claim that it has restarted: ask for a new handshake, and send a handshake
+  // request that is not the one that was answered.
+  let stateless_message = |sequence_number: i64, message_class_id: &str, token_class_id: &str| {
+    let token = DataHolderBuilder::with_class_id(token_class_id.to_string())
+      .add_binary_property(
+        "future_challenge",
+        Bytes::copy_from_slice(&rand::random::<[u8; 32]>()),
+        true,
+      )
+      .build();
+    ParticipantStatelessMessage::from(ParticipantGenericMessage {
+      message_identity: SampleIdentity {
+        writer_guid: peer.guid(),
+        sequence_number: SequenceNumber::new(sequence_number),
+      },
+      related_message_identity: SampleIdentity {
+        writer_guid: GUID::GUID_UNKNOWN,
+        sequence_number: SequenceNumber::zero(),
+      },
+      destination_participant_guid: participant.guid(),
+      destination_endpoint_guid: GUID::GUID_UNKNOWN,
+      source_endpoint_guid: GUID::GUID_UNKNOWN,
+      message_class_id: message_class_id.to_string(),
+      message_data: vec![token],
+    })
+  };
+  let spoofs = [
+    stateless_message(
+      1_000_000,
+      GMCLASSID_SECURITY_AUTH_REQUEST,
+      "DDS:Auth:PKI-DH:1.0+AuthReq",
+    ),
+    stateless_message(
+      1_000_001,
+      GMCLASSID_SECURITY_AUTH_HANDSHAKE,
+      "DDS:Auth:PKI-DH:1.0+Req",
+    ),
+  ];
+  let socket = UdpSocket::bind("127.0.0.1:0")?;
+  let endianness = speedy::Endianness::LittleEndian;
+  for (sequence_number, spoof) in (1_000_000..).zip(spoofs) {
+    // CDR_LE encapsulation header, then the message
+    let payload = [
+      &[0x00, 0x01, 0x00, 0x00][..],
+      &crate::serialization::to_vec::<_, LittleEndian>(&spoof)?,
+    ]
+    .concat();
+    let data = Data {
+      reader_id: EntityId::P2P_BUILTIN_PARTICIPANT_STATELESS_READER,
+      writer_id: EntityId::P2P_BUILTIN_PARTICIPANT_STATELESS_WRITER,
+      writer_sn: SequenceNumber::new(sequence_number),
+      inline_qos: None,
+      serialized_payload: Some(Bytes::from(payload)),
+    };
+    let data_flags = BitFlags::<DATA_Flags>::from_endianness(endianness) | DATA_Flags::Data;
+    let data = Submessage {
+      header: SubmessageHeader {
+        kind: SubmessageKind::DATA,
+        flags: data_flags.bits(),
+        content_length: data.len_serialized() as u16,
+      },
+      body: SubmessageBody::Writer(WriterSubmessage::Data(data, data_flags)),
+      original_bytes: None,
+    };
+    let mut message = MessageBuilder::new().add_header_and_build(peer.guid().prefix);
+    message.add_submessage(data);
+    socket.send_to(
+      &message.write_to_vec()?,
+      (
+        "127.0.0.1",
+        user_traffic_unicast_port(0, participant.participant_id()),
+      ),
+    )?;
+    thread::sleep(Duration::from_millis(100));
+  }
+
+  // The new handshakes go nowhere, and the peer stays authenticated with its
+  // keys all along
+  for _ in 0..3 {
+    assert!(
+      receives_data(),
+      "Protected data did not get through after the spoof"
+    );
+    thread::sleep(Duration::from_secs(1));
+  }
+  while let Some(event) = status.try_recv_status() {
+    match event {
+      DomainParticipantStatusEvent::ParticipantLost { id, .. }
+      | DomainParticipantStatusEvent::ParticipantAuthenticationStatus {
+        participant: id, ..
+      } => assert_ne!(id, peer.guid().prefix, "{event:?}"),
+      _ => (),
+    }
+  }
+  Ok(())
+}
+
+#[cfg(feature = "history_spill")]
+#[test]
+fn late_joiner_receives_spilled_history() -> Result<()> {
+  use crate::policy::HistorySpill;
+
+  #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+  struct Blob(Vec<u8>);
+
+  const SAMPLES: usize = 50;
+
+  let directory = std::env::temp_dir().join(format!(
+    "rustdds_late_joiner_receives_spilled_history_{}",
+    std::process::id()
+  ));
+  let qos = QosPolicyBuilder::new()
+    .history(History::KeepAll)
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .durability(Durability::TransientLocal)
+    .build();
+  let writer_qos = qos.modify_by(
+    &QosPolicyBuilder::new()
+      .history_spill(HistorySpill {
+        memory_budget: 1024,
+        directory: directory.clone(),
+      })
+      .build(),
+  );
+
+  let participant = DomainParticipant::new(0)?;
+  let topic = participant.create_topic(
+    "late_joiner_receives_spilled_history".to_string(),
+    "Blob".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<Blob>(&topic, Some(writer_qos))?;
+
+  let written: Vec<Blob> = (0..SAMPLES)
+    .map(|i| Blob((0..200).map(|j| (i * 7 + j) as u8).collect()))
+    .collect();
+  for blob in &written {
+    writer.write(Blob(blob.0.clone()), None)?;
+  }
+  // Give the writer time to process the samples
+  thread::sleep(Duration::from_secs(1));
+  let spill_file_size: u64 = std::fs::read_dir(&directory)?
+    .map(|entry| entry.and_then(|entry| entry.metadata()).map(|m| m.len()))
+    .sum::<std::io::Result<u64>>()?;
+  assert!(
+    spill_file_size > 0,
+    "the history should not fit in the memory budget"
+  );
+
+  let participant2 = DomainParticipant::new(0)?;
+  let topic2 = participant2.create_topic(
+    "late_joiner_receives_spilled_history".to_string(),
+    "Blob".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = participant2
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<Blob>(&topic2, None)?;
+
+  let mut received = vec![];
+  for _ in 0..200 {
+    while let Ok(Some(sample)) = reader.take_next_sample() {
+      received.push(sample.into_value());
+    }
+    if received.len() >= SAMPLES {
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert_eq!(received, written);
+
+  drop(writer);
+  let _ = std::fs::remove_dir(&directory);
+  Ok(())
+}
+
+#[test]
+fn concurrent_endpoint_creation() -> Result<()> {
+  use std::{collections::HashSet, sync::Arc};
+
+  use crate::{
+    with_key::{DataReader, DataWriter},
+    Publisher, RTPSEntity, Subscriber, Topic, GUID,
+  };
+
+  fn is_send_sync<T: Send + Sync>() {}
+  is_send_sync::<DomainParticipant>();
+  is_send_sync::<Publisher>();
+  is_send_sync::<Subscriber>();
+  is_send_sync::<Topic>();
+  is_send_sync::<DataWriter<random_data::RandomData>>();
+  is_send_sync::<DataReader<random_data::RandomData>>();
+
+  const THREADS: usize = 8;
+  const ENDPOINTS: usize = 100;
+
+  let participant = DomainParticipant::new(0)?;
+  let qos = QosPolicyBuilder::new().build();
+  let barrier = Arc::new(std::sync::Barrier::new(THREADS));
+
+  let handles: Vec<_> = (0..THREADS)
+    .map(|t| {
+      let participant = participant.clone();
+      let qos = qos.clone();
+      let barrier = Arc::clone(&barrier);
+      thread::spawn(move || -> Result<Vec<GUID>> {
+        barrier.wait();
+        let topic = participant.create_topic(
+          format!("concurrent_endpoint_creation_{t}"),
+          "RandomData".to_string(),
+          &qos,
+          TopicKind::WithKey,
+        )?;
+        let publisher = participant.create_publisher(&qos)?;
+        let subscriber = participant.create_subscriber(&qos)?;
+        let mut guids = vec![];
+        // Spread ENDPOINTS over the threads, alternating writers and readers.
+        for i in (t..ENDPOINTS).step_by(THREADS) {
+          if i % 2 == 0 {
+            let w = publisher.create_datawriter_cdr::<random_data::RandomData>(&topic, None)?;
+            guids.push(w.guid());
+          } else {
+            let r = subscriber.create_datareader_cdr::<random_data::RandomData>(&topic, None)?;
+            guids.push(r.guid());
+          }
+        }
+        Ok(guids)
+      })
+    })
+    .collect();
+
+  let mut all = HashSet::new();
+  for h in handles {
+    for guid in h.join().expect("creating thread panicked")? {
+      assert!(all.insert(guid), "duplicate GUID {guid:?}");
+    }
+  }
+  assert_eq!(all.len(), ENDPOINTS);
+  Ok(())
+}
+
+#[test]
+fn late_joiner_receives_dispose() -> Result<()> {
+  use crate::{with_key::Sample, InstanceState, Keyed};
+
+  #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+  struct Keyed32(i32);
+  impl Keyed for Keyed32 {
+    type K = i32;
+    fn key(&self) -> i32 {
+      self.0
+    }
+  }
+
+  let qos = QosPolicyBuilder::new()
+    .history(History::KeepLast { depth: 1 })
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .durability(Durability::TransientLocal)
+    .build();
+
+  let participant = DomainParticipant::new(0)?;
+  let topic = participant.create_topic(
+    "late_joiner_receives_dispose".to_string(),
+    "Keyed32".to_string(),
+    &qos,
+    TopicKind::WithKey,
+  )?;
+  let writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_cdr::<Keyed32>(&topic, None)?;
+  writer.write(Keyed32(1), None)?;
+  writer.dispose(&1, None)?;
+  writer.write(Keyed32(2), None)?;
+  thread::sleep(Duration::from_secs(1));
+
+  let participant2 = DomainParticipant::new(0)?;
+  let topic2 = participant2.create_topic(
+    "late_joiner_receives_dispose".to_string(),
+    "Keyed32".to_string(),
+    &qos,
+    TopicKind::WithKey,
+  )?;
+  let mut reader = participant2
+    .create_subscriber(&qos)?
+    .create_datareader_cdr::<Keyed32>(&topic2, None)?;
+
+  let mut received = vec![];
+  for _ in 0..100 {
+    while let Ok(Some(sample)) = reader.take_next_sample() {
+      received.push(sample);
+    }
+    // Wait for the final state of each instance rather than for a sample
+    // count. The value of instance 1 may or may not precede its dispose.
+    let has_dispose = received
+      .iter()
+      .any(|sample| matches!(sample.value(), Sample::Dispose(1)));
+    let has_value = received
+      .iter()
+      .any(|sample| matches!(sample.value(), Sample::Value(Keyed32(2))));
+    if has_dispose && has_value {
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+
+  let (disposed, alive): (Vec<_>, Vec<_>) =
+    received.iter().partition(|sample| match sample.value() {
+      Sample::Value(value) => value.0 == 1,
+      Sample::Dispose(key) => *key == 1,
+    });
+  let last = disposed
+    .last()
+    .expect("no samples of the disposed instance");
+  assert!(matches!(last.value(), Sample::Dispose(1)));
+  let info = last.sample_info();
+  assert_eq!(info.instance_state(), InstanceState::NotAliveDisposed);
+  assert_eq!(info.disposed_generation_count(), 0);
+  assert_eq!(info.no_writers_generation_count(), 0);
+  assert_eq!(alive.len(), 1);
+  assert_eq!(
+    alive[0].sample_info().instance_state(),
+    InstanceState::Alive
+  );
+  Ok(())
+}
+
+#[test]
+fn dispose_of_long_key_reaches_reader() -> Result<()> {
+  use crate::{with_key::Sample, InstanceState, Keyed};
+
+  // The key is a String, so its key hash is an MD5 digest
+  #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+  struct Named {
+    name: String,
+    value: i32,
+  }
+  impl Keyed for Named {
+    type K = String;
+    fn key(&self) -> String {
+      self.name.clone()
+    }
+  }
+
+  let qos = QosPolicyBuilder::new()
+    .history(History::KeepAll)
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .build();
+
+  let participant = DomainParticipant::new(0)?;
+  let topic = participant.create_topic(
+    "dispose_of_long_key_reaches_reader".to_string(),
+    "Named".to_string(),
+    &qos,
+    TopicKind::WithKey,
+  )?;
+  let mut reader = participant
+    .create_subscriber(&qos)?
+    .create_datareader_cdr::<Named>(&topic, None)?;
+  let writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_cdr::<Named>(&topic, None)?;
+  thread::sleep(Duration::from_millis(500));
+
+  let name = "a name longer than sixteen bytes".to_string();
+  writer.write(
+    Named {
+      name: name.clone(),
+      value: 1,
+    },
+    None,
+  )?;
+  writer.dispose(&name, None)?;
+
+  let mut received = vec![];
+  for _ in 0..100 {
+    while let Ok(Some(sample)) = reader.take_next_sample() {
+      received.push(sample);
+    }
+    if received.len() >= 9 {
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+
+  assert_eq!(received.len(), 2);
+  assert!(matches!(received[0].value(), Sample::Value(v) if v.value == 1));
+  assert!(matches!(received[1].value(), Sample::Dispose(k) if *k == name));
+  assert_eq!(
+    received[1].sample_info().instance_state(),
+    InstanceState::NotAliveDisposed
+  );
+  Ok(())
+}
+
+#[test]
+fn unregister_of_last_writer_reaches_reader() -> Result<()> {
+  use crate::{with_key::Sample, InstanceState, Keyed};
+
+  #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+  struct Keyed32(i32);
+  impl Keyed for Keyed32 {
+    type K = i32;
+    fn key(&self) -> i32 {
+      self.0
+    }
+  }
+
+  let qos = QosPolicyBuilder::new()
+    .history(History::KeepAll)
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .build();
+
+  let participant = DomainParticipant::new(0)?;
+  let topic = participant.create_topic(
+    "unregister_of_last_writer_reaches_reader".to_string(),
+    "Keyed32".to_string(),
+    &qos,
+    TopicKind::WithKey,
+  )?;
+  let mut reader = participant
+    .create_subscriber(&qos)?
+    .create_datareader_cdr::<Keyed32>(&topic, None)?;
+  let writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_cdr::<Keyed32>(&topic, None)?;
+  thread::sleep(Duration::from_millis(500));
+
+  let mut take_samples = |count| {
+    let mut received = vec![];
+    for _ in 0..100 {
+      while let Ok(Some(sample)) = reader.take_next_sample() {
+        let info = sample.sample_info();
+        received.push((
+          sample.value().clone(),
+          info.instance_state(),
+          info.no_writers_generation_count(),
+        ));
+      }
+      if received.len() >= count {
+        break;
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+    received
+  };
+
+  writer.write(Keyed32(1), None)?;
+  writer.unregister_instance(&1, None)?;
+  // The instance state is that of the instance when the samples are taken
+  assert_eq!(
+    take_samples(2),
+    [
+      (
+        Sample::Value(Keyed32(1)),
+        InstanceState::NotAliveNoWriters,
+        0
+      ),
+      (Sample::Dispose(1), InstanceState::NotAliveNoWriters, 0),
+    ]
+  );
+
+  writer.write(Keyed32(1), None)?;
+  assert_eq!(
+    take_samples(1),
+    [(Sample::Value(Keyed32(1)), InstanceState::Alive, 1)]
+  );
+  Ok(())
+}
+
+#[test]
+fn aggregated_samples_reach_reader() -> Result<()> {
+  let qos = QosPolicyBuilder::new()
+    .history(History::KeepAll)
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .build();
+
+  // Samples wait for more to fill the message, until the DataWriter flushes
+  let participant = crate::DomainParticipantBuilder::new(0)
+    .max_message_size(1000)
+    .message_flush_delay(Duration::from_secs(60))
+    .build()?;
+  let topic = participant.create_topic(
+    "aggregated_samples_reach_reader".to_string(),
+    "i32".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = participant
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<i32>(&topic, None)?;
+  let writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<i32>(&topic, None)?;
+  thread::sleep(Duration::from_millis(500));
+
+  for i in 0..100 {
+    writer.write(i, None)?;
+  }
+  writer.flush()?;
+
+  let mut received = vec![];
+  for _ in 0..100 {
+    while let Ok(Some(sample)) = reader.take_next_sample() {
+      received.push(*sample.value());
+    }
+    if received.len() >= 100 {
+      break;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  assert_eq!(received, (0..100).collect::<Vec<i32>>());
+  Ok(())
+}
+
+#[test]
+fn late_joiner_with_keep_last_history_converges() -> Result<()> {
+  let qos = QosPolicyBuilder::new()
+    .history(History::KeepLast { depth: 1 })
+    .reliability(Reliability::Reliable {
+      max_blocking_time: Duration::from_secs(1).into(),
+    })
+    .durability(Durability::Volatile)
+    .build();
+
+  let participant = DomainParticipant::new(0)?;
+  let topic = participant.create_topic(
+    "late_joiner_with_keep_last_history_converges".to_string(),
+    "i32".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<i32>(&topic, None)?;
+  // Samples that the late joiner must not wait for
+  for value in 0..300 {
+    writer.write(value, None)?;
+  }
+
+  let participant2 = DomainParticipant::new(0)?;
+  let topic2 = participant2.create_topic(
+    "late_joiner_with_keep_last_history_converges".to_string(),
+    "i32".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let mut reader = participant2
+    .create_subscriber(&qos)?
+    .create_datareader_no_key_cdr::<i32>(&topic2, None)?;
+
+  let mut received = vec![];
+  for value in 300..400 {
+    writer.write(value, None)?;
+    thread::sleep(Duration::from_millis(100));
+    while let Ok(Some(sample)) = reader.take_next_sample() {
+      received.push(sample.into_value());
+    }
+    if !received.is_empty() {
+      break;
+    }
+  }
+  assert!(!received.is_empty(), "the late joiner received nothing");
+  assert!(received.iter().all(|value| *value >= 300), "{received:?}");
+
+  // The Reader must acknowledge everything, including the samples written
+  // before it joined, instead of requesting them over and over
+  assert!(matches!(
+    writer.wait_for_acknowledgments(Duration::from_secs(5)),
+    Ok(true)
+  ));
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn custom_crypto_plugin_is_used() -> Result<()> {
+  use std::sync::{Arc, Mutex};
+
+  use crate::{
+    messages::submessages::{
+      elements::parameter_list::ParameterList, secure_postfix::SecurePostfix,
+      secure_prefix::SecurePrefix,
+    },
+    rtps::{Message, Submessage},
+    security::{
+      access_control::types::*,
+      authentication::types::*,
+      config::*,
+      cryptographic::{cryptographic_plugin::*, *},
+      security_error, Property, SecurityResult,
+    },
+  };
+
+  // Protects nothing, just records what it is asked to do
+  #[derive(Default)]
+  struct RecordingCrypto {
+    calls: Arc<Mutex<Vec<&'static str>>>,
+    handle_counter: CryptoHandle,
+  }
+
+  impl RecordingCrypto {
+    fn record(&self, call: &'static str) {
+      self.calls.lock().unwrap().push(call);
+    }
+
+    fn new_handle(&mut self, call: &'static str) -> SecurityResult<CryptoHandle> {
+      self.record(call);
+      self.handle_counter += 1;
+      Ok(self.handle_counter)
+    }
+  }
+
+  impl CryptoKeyFactory for RecordingCrypto {
+    fn register_local_participant(
+      &mut self,
+      _: IdentityHandle,
+      _: PermissionsHandle,
+      _: &[Property],
+      _: ParticipantSecurityAttributes,
+    ) -> SecurityResult<ParticipantCryptoHandle> {
+      self.new_handle("register_local_participant")
+    }
+    fn register_matched_remote_participant(
+      &mut self,
+      _: ParticipantCryptoHandle,
+      _: IdentityHandle,
+      _: PermissionsHandle,
+      _: SharedSecretHandle,
+    ) -> SecurityResult<ParticipantCryptoHandle> {
+      self.new_handle("register_matched_remote_participant")
+    }
+    fn register_local_datawriter(
+      &mut self,
+      _: ParticipantCryptoHandle,
+      _: &[Property],
+      _: EndpointSecurityAttributes,
+    ) -> SecurityResult<DatawriterCryptoHandle> {
+      self.new_handle("register_local_datawriter")
+    }
+    fn register_matched_remote_datareader(
+      &mut self,
+      _: DatawriterCryptoHandle,
+      _: ParticipantCryptoHandle,
+      _: SharedSecretHandle,
+      _: bool,
+    ) -> SecurityResult<DatareaderCryptoHandle> {
+      self.new_handle("register_matched_remote_datareader")
+    }
+    fn register_matched_remote_datareaders(
+      &mut self,
+      _: DatawriterCryptoHandle,
+      remote_datareaders: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+      _: bool,
+    ) -> SecurityResult<Vec<DatareaderCryptoHandle>> {
+      remote_datareaders
+        .iter()
+        .map(|_| self.new_handle("register_matched_remote_datareaders"))
+        .collect()
+    }
+    fn register_local_datareader(
+      &mut self,
+      _: ParticipantCryptoHandle,
+      _: &[Property],
+      _: EndpointSecurityAttributes,
+    ) -> SecurityResult<DatareaderCryptoHandle> {
+      self.new_handle("register_local_datareader")
+    }
+    fn register_matched_remote_datawriter(
+      &mut self,
+      _: DatareaderCryptoHandle,
+      _: ParticipantCryptoHandle,
+      _: SharedSecretHandle,
+    ) -> SecurityResult<DatawriterCryptoHandle> {
+      self.new_handle("register_matched_remote_datawriter")
+    }
+    fn register_matched_remote_datawriters(
+      &mut self,
+      _: DatareaderCryptoHandle,
+      remote_datawriters: &[(ParticipantCryptoHandle, SharedSecretHandle)],
+    ) -> SecurityResult<Vec<DatawriterCryptoHandle>> {
+      remote_datawriters
+        .iter()
+        .map(|_| self.new_handle("register_matched_remote_datawriters"))
+        .collect()
+    }
+    fn unregister_participant(&mut self, _: ParticipantCryptoHandle) -> SecurityResult<()> {
+      self.record("unregister_participant");
+      Ok(())
+    }
+    fn unregister_datawriter(&mut self, _: DatawriterCryptoHandle) -> SecurityResult<()> {
+      self.record("unregister_datawriter");
+      Ok(())
+    }
+    fn unregister_datareader(&mut self, _: DatareaderCryptoHandle) -> SecurityResult<()> {
+      self.record("unregister_datareader");
+      Ok(())
+    }
+  }
+
+  impl CryptoKeyExchange for RecordingCrypto {
+    fn create_local_participant_crypto_tokens(
+      &mut self,
+      _: ParticipantCryptoHandle,
+      _: ParticipantCryptoHandle,
+    ) -> SecurityResult<Vec<ParticipantCryptoToken>> {
+      self.record("create_local_participant_crypto_tokens");
+      Ok(Vec::new())
+    }
+    fn set_remote_participant_crypto_tokens(
+      &mut self,
+      _: ParticipantCryptoHandle,
+      _: ParticipantCryptoHandle,
+      _: Vec<ParticipantCryptoToken>,
+    ) -> SecurityResult<()> {
+      self.record("set_remote_participant_crypto_tokens");
+      Ok(())
+    }
+    fn create_local_datawriter_crypto_tokens(
+      &mut self,
+      _: DatawriterCryptoHandle,
+      _: DatareaderCryptoHandle,
+    ) -> SecurityResult<Vec<DatawriterCryptoToken>> {
+      self.record("create_local_datawriter_crypto_tokens");
+      Ok(Vec::new())
+    }
+    fn set_remote_datawriter_crypto_tokens(
+      &mut self,
+      _: DatareaderCryptoHandle,
+      _: DatawriterCryptoHandle,
+      _: Vec<DatawriterCryptoToken>,
+    ) -> SecurityResult<()> {
+      self.record("set_remote_datawriter_crypto_tokens");
+      Ok(())
+    }
+    fn create_local_datareader_crypto_tokens(
+      &mut self,
+      _: DatareaderCryptoHandle,
+      _: DatawriterCryptoHandle,
+    ) -> SecurityResult<Vec<DatareaderCryptoToken>> {
+      self.record("create_local_datareader_crypto_tokens");
+      Ok(Vec::new())
+    }
+    fn set_remote_datareader_crypto_tokens(
+      &mut self,
+      _: DatawriterCryptoHandle,
+      _: DatareaderCryptoHandle,
+      _: Vec<DatareaderCryptoToken>,
+    ) -> SecurityResult<()> {
+      self.record("set_remote_datareader_crypto_tokens");
+      Ok(())
+    }
+    fn return_crypto_tokens(&mut self, _: Vec<CryptoToken>) -> SecurityResult<()> {
+      self.record("return_crypto_tokens");
+      Ok(())
+    }
+  }
+
+  impl CryptoTransform for RecordingCrypto {
+    fn encode_serialized_payload(
+      &self,
+      plain_buffer: Vec<u8>,
+      _: DatawriterCryptoHandle,
+    ) -> SecurityResult<(Vec<u8>, ParameterList)> {
+      self.record("encode_serialized_payload");
+      Ok((plain_buffer, ParameterList::new()))
+    }
+    fn encode_datawriter_submessage(
+      &self,
+      plain_rtps_submessage: Submessage,
+      _: DatawriterCryptoHandle,
+      _: Vec<DatareaderCryptoHandle>,
+    ) -> SecurityResult<EncodedSubmessage> {
+      self.record("encode_datawriter_submessage");
+      Ok(EncodedSubmessage::Unencoded(plain_rtps_submessage))
+    }
+    fn encode_datareader_submessage(
+      &self,
+      plain_rtps_submessage: Submessage,
+      _: DatareaderCryptoHandle,
+      _: Vec<DatawriterCryptoHandle>,
+    ) -> SecurityResult<EncodedSubmessage> {
+      self.record("encode_datareader_submessage");
+      Ok(EncodedSubmessage::Unencoded(plain_rtps_submessage))
+    }
+    fn encode_rtps_message(
+      &self,
+      plain_rtps_message: Message,
+      _: ParticipantCryptoHandle,
+      _: Vec<ParticipantCryptoHandle>,
+    ) -> SecurityResult<Message> {
+      self.record("encode_rtps_message");
+      Ok(plain_rtps_message)
+    }
+    fn decode_rtps_message(
+      &self,
+      encoded_message: Message,
+      _: ParticipantCryptoHandle,
+      _: ParticipantCryptoHandle,
+    ) -> SecurityResult<DecodeOutcome<Message>> {
+      self.record("decode_rtps_message");
+      Ok(DecodeOutcome::Success(encoded_message))
+    }
+    fn preprocess_secure_submessage(
+      &self,
+      _: &SecurePrefix,
+      _: ParticipantCryptoHandle,
+      _: ParticipantCryptoHandle,
+    ) -> SecurityResult<SecureSubmessageCategory> {
+      self.record("preprocess_secure_submessage");
+      Err(security_error("Nothing is protected"))
+    }
+    fn decode_submessage(
+      &self,
+      _: (SecurePrefix, Submessage, SecurePostfix),
+      _: ParticipantCryptoHandle,
+      _: ParticipantCryptoHandle,
+    ) -> SecurityResult<DecodeOutcome<DecodedSubmessage>> {
+      self.record("decode_submessage");
+      Err(security_error("Nothing is protected"))
+    }
+    fn decode_serialized_payload(
+      &self,
+      encoded_buffer: Vec<u8>,
+      _: ParameterList,
+      _: DatareaderCryptoHandle,
+      _: DatawriterCryptoHandle,
+    ) -> SecurityResult<Vec<u8>> {
+      self.record("decode_serialized_payload");
+      Ok(encoded_buffer)
+    }
+  }
+
+  impl Cryptographic for RecordingCrypto {}
+
+  let crypto = RecordingCrypto::default();
+  let calls = crypto.calls.clone();
+  let participant = crate::DomainParticipantBuilder::new(0)
+    .builtin_security_with_crypto(
+      DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        "examples/security_configuration_files",
+        "no_pwd".to_string(),
+      ),
+      Box::new(crypto),
+    )
+    .build()?;
+  let qos = QosPolicyBuilder::new().build();
+  let topic = participant.create_topic(
+    "Square".to_string(),
+    "TestType".to_string(),
+    &qos,
+    TopicKind::NoKey,
+  )?;
+  let _writer = participant
+    .create_publisher(&qos)?
+    .create_datawriter_no_key_cdr::<TestType>(&topic, None)?;
+
+  let calls = calls.lock().unwrap();
+  assert_eq!(calls.first(), Some(&"register_local_participant"));
+  // The builtin secure endpoints and the DataWriter
+  assert!(calls.contains(&"register_local_datawriter"));
+  assert!(calls.contains(&"register_local_datareader"));
+  Ok(())
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn tampered_governance_prevents_participant_creation() -> Result<()> {
+  use crate::security::config::*;
+
+  let configs = DomainParticipantSecurityConfigFiles::with_ros_default_names(
+    "examples/security_configuration_files",
+    "no_pwd".to_string(),
+  );
+  let governance = std::fs::read(&configs.domain_governance_document)?;
+  let governance = String::from_utf8(governance)?.replacen("<max>100</max>", "<max>999</max>", 1);
+  let tampered_dir = tempfile::tempdir()?;
+  let tampered_governance = tampered_dir.path().join("governance.p7s");
+  std::fs::write(&tampered_governance, governance)?;
+
+  let error = crate::DomainParticipantBuilder::new(0)
+    .builtin_security(DomainParticipantSecurityConfigFiles {
+      domain_governance_document: tampered_governance,
+      ..configs
+    })
+    .build()
+    .expect_err("Participant creation must fail with a tampered governance document");
+  assert!(
+    format!("{error:?}").contains("Signature verification of the domain governance document"),
+    "{error:?}"
+  );
+  Ok(())
+}
@@ -72,6 +72,36 @@ pub(crate) fn spdp_publication_data_raw() -> Bytes {
   Bytes::from_static(&DATA)
 }
 
+// Synthetic SPDP participant data, not a capture. It was assembled by hand
+// after the Fast DDS capture in spdp_participant_data_raw, and keeps its
+// header, vendor id and GUID prefix. Besides the usual parameters, it has
+// PID_DOMAIN_ID and a vendor-specific parameter, which we do not use.
+pub(crate) fn spdp_participant_data_with_unknown_parameters_raw() -> Bytes {
+  const DATA: [u8; 316] = [
+    0x52, 0x54, 0x50, 0x53, 0x02, 0x04, 0x01, 0x03, 0x01, 0x03, 0x00, 0x0c, 0x29, 0x2d, 0x31, 0xa2,
+    0x28, 0x20, 0x02, 0x08, 0x15, 0x05, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x01, 0x00, 0xc2, 0x00, 0x00, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00,
+    0x77, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x15, 0x00, 0x04, 0x00, 0x02, 0x04, 0x00, 0x00, 0x50, 0x00, 0x10, 0x00, 0x01, 0x03, 0x00, 0x0c,
+    0x29, 0x2d, 0x31, 0xa2, 0x28, 0x20, 0x02, 0x08, 0x00, 0x00, 0x01, 0xc1, 0x16, 0x00, 0x04, 0x00,
+    0x01, 0x03, 0x00, 0x00, 0x44, 0x00, 0x04, 0x00, 0x3f, 0x0c, 0x00, 0x00, 0x58, 0x00, 0x04, 0x00,
+    0x3f, 0x0c, 0x00, 0x00, 0x32, 0x00, 0x18, 0x00, 0x01, 0x00, 0x00, 0x00, 0x9f, 0xa4, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x50, 0x8e, 0xc9,
+    0x32, 0x00, 0x18, 0x00, 0x01, 0x00, 0x00, 0x00, 0x9f, 0xa4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0xa8, 0x45, 0x14, 0x32, 0x00, 0x18, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x9f, 0xa4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xac, 0x11, 0x00, 0x01, 0x33, 0x00, 0x18, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0xea, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xef, 0xff, 0x00, 0x01, 0x31, 0x00, 0x18, 0x00, 0x01, 0x00, 0x00, 0x00, 0x39, 0x30, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0x00, 0x00, 0x01,
+    0x48, 0x00, 0x18, 0x00, 0x01, 0x00, 0x00, 0x00, 0x39, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0x00, 0x00, 0x01, 0x34, 0x00, 0x04, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x05, 0xb0, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x08, 0x00,
+    0x2c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+  ];
+  Bytes::from_static(&DATA)
+}
+
 use std::{net::SocketAddr, time::Duration as StdDuration};
 
 use bytes::Bytes;
@@ -86,7 +116,7 @@ use crate::{
     qos::{
       policy::{
         Deadline, DestinationOrder, Durability, History, LatencyBudget, Lifespan, Liveliness,
-        Ownership, Presentation, PresentationAccessScope, Reliability, ResourceLimits,
+        Ownership, Partition, Presentation, PresentationAccessScope, Reliability, ResourceLimits,
         TimeBasedFilter,
       },
       QosPolicyBuilder,
@@ -256,6 +286,7 @@ pub(crate) fn subscription_builtin_topic_data() -> Option<SubscriptionBuiltinTop
     .lifespan(Lifespan {
       duration: Duration::from(StdDuration::from_secs(6 * 60)),
     })
+    .partition(Partition::new(["a", "sensors.temp"]))
     .build();
 
   let sub_topic_data = SubscriptionBuiltinTopicData::new(
@@ -298,6 +329,7 @@ pub(crate) fn publication_builtin_topic_data() -> Option<PublicationBuiltinTopic
       coherent_access: true,
       ordered_access: false,
     }),
+    partition: Some(Partition::new(["sensors.*"])),
     related_datareader_key: None,
     service_instance_name: None,
     topic_aliases: None,
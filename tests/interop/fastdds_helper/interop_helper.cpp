@@ -0,0 +1,645 @@
+// Fast DDS side of the RustDDS interoperability tests in tests/interop.
+//
+// Publishes or subscribes InteropSample data and reports events as lines on
+// stdout, which the Rust harness parses:
+//
+//   READY                              entities have been created
+//   MATCHED <current count>            publication/subscription matched
+//   WRITTEN                            (publisher) all samples have been written
+//   ACKED                              (publisher) all samples have been acknowledged
+//   SAMPLE <key> <seq> <len> <fnv1a>   (subscriber) a sample was received
+//   DISPOSED <key>                     (subscriber) an instance was disposed
+//
+// The sample payloads are generated from (key, seq) in the same way as in the
+// harness, so that both sides can check the content.
+//
+// Written against the Fast DDS 2.x API.
+
+#include <atomic>
+#include <chrono>
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <functional>
+#include <iostream>
+#include <map>
+#include <mutex>
+#include <sstream>
+#include <stdexcept>
+#include <string>
+#include <thread>
+#include <vector>
+
+#include <fastdds/dds/domain/DomainParticipant.hpp>
+#include <fastdds/dds/domain/DomainParticipantFactory.hpp>
+#include <fastdds/dds/publisher/DataWriter.hpp>
+#include <fastdds/dds/publisher/DataWriterListener.hpp>
+#include <fastdds/dds/publisher/Publisher.hpp>
+#include <fastdds/dds/subscriber/DataReader.hpp>
+#include <fastdds/dds/subscriber/DataReaderListener.hpp>
+#include <fastdds/dds/subscriber/SampleInfo.hpp>
+#include <fastdds/dds/subscriber/Subscriber.hpp>
+#include <fastdds/dds/topic/TopicDataType.hpp>
+#include <fastdds/dds/topic/TypeSupport.hpp>
+#include <fastrtps/utils/md5.h>
+
+using namespace eprosima::fastdds::dds;
+using eprosima::fastrtps::rtps::InstanceHandle_t;
+using eprosima::fastrtps::rtps::SerializedPayload_t;
+
+namespace {
+
+// IDL:
+//   struct InteropSample {
+//     @key string key;
+//     unsigned long seq;
+//     sequence<octet> payload;
+//   };
+struct InteropSample
+{
+    std::string key;
+    uint32_t seq = 0;
+    std::vector<uint8_t> payload;
+};
+
+// Must match payload() in harness.rs
+std::vector<uint8_t> make_payload(
+        const std::string& key,
+        uint32_t seq,
+        size_t len)
+{
+    std::vector<uint8_t> payload(len);
+    for (size_t i = 0; i < len; ++i)
+    {
+        payload[i] = static_cast<uint8_t>(
+            (static_cast<uint64_t>(i) * 31 + static_cast<uint64_t>(seq) * 7 + key.size()) % 251);
+    }
+    return payload;
+}
+
+// Must match fnv1a() in harness.rs
+uint64_t fnv1a(
+        const std::vector<uint8_t>& data)
+{
+    uint64_t hash = 0xcbf29ce484222325ULL;
+    for (uint8_t byte : data)
+    {
+        hash ^= byte;
+        hash *= 0x100000001b3ULL;
+    }
+    return hash;
+}
+
+std::mutex output_mutex;
+
+void report(
+        const std::string& line)
+{
+    std::lock_guard<std::mutex> lock(output_mutex);
+    std::cout << line << std::endl;
+}
+
+// Plain CDR encoding written out by hand, so that the helper does not depend
+// on the Fast CDR version or on generated type support code.
+class CdrWriter
+{
+public:
+
+    explicit CdrWriter(
+            bool big_endian)
+        : big_endian_(big_endian)
+    {
+    }
+
+    void u32(
+            uint32_t value)
+    {
+        align(4);
+        for (int i = 0; i < 4; ++i)
+        {
+            int shift = big_endian_ ? 8 * (3 - i) : 8 * i;
+            bytes.push_back(static_cast<uint8_t>(value >> shift));
+        }
+    }
+
+    void string(
+            const std::string& value)
+    {
+        u32(static_cast<uint32_t>(value.size() + 1));
+        bytes.insert(bytes.end(), value.begin(), value.end());
+        bytes.push_back(0);
+    }
+
+    void octets(
+            const std::vector<uint8_t>& value)
+    {
+        u32(static_cast<uint32_t>(value.size()));
+        bytes.insert(bytes.end(), value.begin(), value.end());
+    }
+
+    std::vector<uint8_t> bytes;
+
+private:
+
+    void align(
+            size_t alignment)
+    {
+        while (bytes.size() % alignment != 0)
+        {
+            bytes.push_back(0);
+        }
+    }
+
+    bool big_endian_;
+};
+
+class CdrReader
+{
+public:
+
+    CdrReader(
+            const uint8_t* data,
+            size_t len,
+            bool big_endian)
+        : data_(data)
+        , len_(len)
+        , big_endian_(big_endian)
+    {
+    }
+
+    bool u32(
+            uint32_t& value)
+    {
+        pos_ = (pos_ + 3) & ~static_cast<size_t>(3);
+        if (pos_ + 4 > len_)
+        {
+            return false;
+        }
+        value = 0;
+        for (int i = 0; i < 4; ++i)
+        {
+            int shift = big_endian_ ? 8 * (3 - i) : 8 * i;
+            value |= static_cast<uint32_t>(data_[pos_ + i]) << shift;
+        }
+        pos_ += 4;
+        return true;
+    }
+
+    bool string(
+            std::string& value)
+    {
+        uint32_t len = 0;
+        if (!u32(len) || len == 0 || pos_ + len > len_)
+        {
+            return false;
+        }
+        value.assign(reinterpret_cast<const char*>(data_ + pos_), len - 1);
+        pos_ += len;
+        return true;
+    }
+
+    bool octets(
+            std::vector<uint8_t>& value)
+    {
+        uint32_t len = 0;
+        if (!u32(len) || pos_ + len > len_)
+        {
+            return false;
+        }
+        value.assign(data_ + pos_, data_ + pos_ + len);
+        pos_ += len;
+        return true;
+    }
+
+private:
+
+    const uint8_t* data_;
+    size_t len_;
+    size_t pos_ = 0;
+    bool big_endian_;
+};
+
+const size_t ENCAPSULATION_HEADER_LEN = 4;
+const uint32_t MAX_SERIALIZED_SIZE = 16 * 1024 * 1024;
+
+class InteropSampleType : public TopicDataType
+{
+public:
+
+    InteropSampleType()
+    {
+        setName("InteropSample");
+        m_typeSize = MAX_SERIALIZED_SIZE;
+        m_isGetKeyDefined = true;
+    }
+
+    bool serialize(
+            void* data,
+            SerializedPayload_t* payload) override
+    {
+        const InteropSample* sample = static_cast<InteropSample*>(data);
+        CdrWriter writer(false);
+        writer.string(sample->key);
+        writer.u32(sample->seq);
+        writer.octets(sample->payload);
+
+        uint32_t len = static_cast<uint32_t>(ENCAPSULATION_HEADER_LEN + writer.bytes.size());
+        if (len > payload->max_size)
+        {
+            return false;
+        }
+        // CDR_LE
+        const uint8_t header[ENCAPSULATION_HEADER_LEN] = {0x00, 0x01, 0x00, 0x00};
+        std::memcpy(payload->data, header, ENCAPSULATION_HEADER_LEN);
+        std::memcpy(payload->data + ENCAPSULATION_HEADER_LEN, writer.bytes.data(), writer.bytes.size());
+        payload->length = len;
+        payload->encapsulation = CDR_LE;
+        return true;
+    }
+
+    bool deserialize(
+            SerializedPayload_t* payload,
+            void* data) override
+    {
+        if (payload->length < ENCAPSULATION_HEADER_LEN)
+        {
+            return false;
+        }
+        bool big_endian = payload->data[1] == 0x00;
+        InteropSample* sample = static_cast<InteropSample*>(data);
+        CdrReader reader(payload->data + ENCAPSULATION_HEADER_LEN,
+                payload->length - ENCAPSULATION_HEADER_LEN, big_endian);
+        return reader.string(sample->key) && reader.u32(sample->seq) && reader.octets(sample->payload);
+    }
+
+    std::function<uint32_t()> getSerializedSizeProvider(
+            void* data) override
+    {
+        const InteropSample* sample = static_cast<InteropSample*>(data);
+        return [sample]() -> uint32_t
+               {
+                   // header, string length, string with NUL and padding, seq,
+                   // sequence length, payload
+                   return static_cast<uint32_t>(ENCAPSULATION_HEADER_LEN + 4 + sample->key.size() + 1 + 3 + 4 +
+                          4 + sample->payload.size());
+               };
+    }
+
+    void* createData() override
+    {
+        return new InteropSample();
+    }
+
+    void deleteData(
+            void* data) override
+    {
+        delete static_cast<InteropSample*>(data);
+    }
+
+    // The key is an unbounded string, so the key hash is always the MD5 digest
+    // of its big-endian CDR encoding
+    bool getKey(
+            void* data,
+            InstanceHandle_t* handle,
+            bool /*force_md5*/) override
+    {
+        const InteropSample* sample = static_cast<InteropSample*>(data);
+        CdrWriter writer(true);
+        writer.string(sample->key);
+        eprosima::fastrtps::MD5 md5;
+        md5.init();
+        md5.update(writer.bytes.data(), static_cast<unsigned int>(writer.bytes.size()));
+        md5.finalize();
+        for (int i = 0; i < 16; ++i)
+        {
+            handle->value[i] = md5.digest[i];
+        }
+        return true;
+    }
+
+};
+
+struct Options
+{
+    std::string role;
+    uint32_t domain = 0;
+    std::string topic;
+    bool reliable = false;
+    bool transient_local = false;
+    std::vector<std::string> keys;
+    uint32_t samples = 1;
+    size_t payload_size = 16;
+    bool dispose = false;
+    bool repeat = false;
+    int wait_matches = 0;
+    std::string security_dir;
+};
+
+std::vector<std::string> split(
+        const std::string& value,
+        char separator)
+{
+    std::vector<std::string> parts;
+    std::stringstream stream(value);
+    std::string part;
+    while (std::getline(stream, part, separator))
+    {
+        if (!part.empty())
+        {
+            parts.push_back(part);
+        }
+    }
+    return parts;
+}
+
+bool parse_options(
+        int argc,
+        char** argv,
+        Options& options)
+{
+    for (int i = 1; i < argc; ++i)
+    {
+        std::string arg = argv[i];
+        auto value = [&]() -> std::string
+                {
+                    if (i + 1 >= argc)
+                    {
+                        throw std::runtime_error("missing value for " + arg);
+                    }
+                    return argv[++i];
+                };
+        if (arg == "--role")
+        {
+            options.role = value();
+        }
+        else if (arg == "--domain")
+        {
+            options.domain = static_cast<uint32_t>(std::stoul(value()));
+        }
+        else if (arg == "--topic")
+        {
+            options.topic = value();
+        }
+        else if (arg == "--reliable")
+        {
+            options.reliable = true;
+        }
+        else if (arg == "--transient-local")
+        {
+            options.transient_local = true;
+        }
+        else if (arg == "--keys")
+        {
+            options.keys = split(value(), ',');
+        }
+        else if (arg == "--samples")
+        {
+            options.samples = static_cast<uint32_t>(std::stoul(value()));
+        }
+        else if (arg == "--payload-size")
+        {
+            options.payload_size = static_cast<size_t>(std::stoul(value()));
+        }
+        else if (arg == "--dispose")
+        {
+            options.dispose = true;
+        }
+        else if (arg == "--repeat")
+        {
+            options.repeat = true;
+        }
+        else if (arg == "--wait-matches")
+        {
+            options.wait_matches = std::stoi(value());
+        }
+        else if (arg == "--security-dir")
+        {
+            options.security_dir = value();
+        }
+        else
+        {
+            std::cerr << "Unknown argument " << arg << std::endl;
+            return false;
+        }
+    }
+    return (options.role == "pub" || options.role == "sub") && !options.topic.empty();
+}
+
+void add_security_properties(
+        DomainParticipantQos& qos,
+        const std::string& dir)
+{
+    auto& properties = qos.properties().properties();
+    auto file = [&](const char* name)
+            {
+                return "file://" + dir + "/" + name;
+            };
+    properties.emplace_back("dds.sec.auth.plugin", "builtin.PKI-DH");
+    properties.emplace_back("dds.sec.auth.builtin.PKI-DH.identity_ca", file("identity_ca.cert.pem"));
+    properties.emplace_back("dds.sec.auth.builtin.PKI-DH.identity_certificate", file("cert.pem"));
+    properties.emplace_back("dds.sec.auth.builtin.PKI-DH.private_key", file("key.pem"));
+    properties.emplace_back("dds.sec.access.plugin", "builtin.Access-Permissions");
+    properties.emplace_back("dds.sec.access.builtin.Access-Permissions.permissions_ca",
+            file("permissions_ca.cert.pem"));
+    properties.emplace_back("dds.sec.access.builtin.Access-Permissions.governance", file("governance.p7s"));
+    properties.emplace_back("dds.sec.access.builtin.Access-Permissions.permissions", file("permissions.p7s"));
+    properties.emplace_back("dds.sec.crypto.plugin", "builtin.AES-GCM-GMAC");
+}
+
+class WriterListener : public DataWriterListener
+{
+public:
+
+    void on_publication_matched(
+            DataWriter*,
+            const PublicationMatchedStatus& status) override
+    {
+        matched = status.current_count;
+        report("MATCHED " + std::to_string(status.current_count));
+    }
+
+    std::atomic<int> matched{0};
+};
+
+class ReaderListener : public DataReaderListener
+{
+public:
+
+    void on_subscription_matched(
+            DataReader*,
+            const SubscriptionMatchedStatus& status) override
+    {
+        report("MATCHED " + std::to_string(status.current_count));
+    }
+
+    void on_data_available(
+            DataReader* reader) override
+    {
+        InteropSample sample;
+        SampleInfo info;
+        while (reader->take_next_sample(&sample, &info) == ReturnCode_t::RETCODE_OK)
+        {
+            if (info.valid_data)
+            {
+                keys_[info.instance_handle] = sample.key;
+                std::ostringstream line;
+                line << "SAMPLE " << sample.key << " " << sample.seq << " " << sample.payload.size() << " "
+                     << std::hex << fnv1a(sample.payload);
+                report(line.str());
+            }
+            else if (info.instance_state == NOT_ALIVE_DISPOSED_INSTANCE_STATE)
+            {
+                // Dispose notifications carry no data, so the key is looked up
+                // from earlier samples of the instance
+                auto key = keys_.find(info.instance_handle);
+                report("DISPOSED " + (key != keys_.end() ? key->second : std::string("?")));
+            }
+        }
+    }
+
+private:
+
+    std::map<InstanceHandle_t, std::string> keys_;
+};
+
+void publish(
+        DataWriter* writer,
+        const Options& options)
+{
+    for (uint32_t seq = 0; seq < options.samples; ++seq)
+    {
+        for (const std::string& key : options.keys)
+        {
+            InteropSample sample;
+            sample.key = key;
+            sample.seq = seq;
+            sample.payload = make_payload(key, seq, options.payload_size);
+            writer->write(&sample);
+        }
+    }
+    if (options.dispose)
+    {
+        for (const std::string& key : options.keys)
+        {
+            InteropSample sample;
+            sample.key = key;
+            writer->dispose(&sample, HANDLE_NIL);
+        }
+    }
+}
+
+// Keeps the entities alive until the harness stops the process
+[[noreturn]] void run_until_killed()
+{
+    for (;;)
+    {
+        std::this_thread::sleep_for(std::chrono::seconds(1));
+    }
+}
+
+} // namespace
+
+int main(
+        int argc,
+        char** argv)
+{
+    Options options;
+    try
+    {
+        if (!parse_options(argc, argv, options))
+        {
+            std::cerr << "Usage: " << argv[0] << " --role pub|sub --topic NAME [--domain ID] [--reliable]"
+                      << " [--transient-local] [--keys K1,K2] [--samples N] [--payload-size BYTES]"
+                      << " [--dispose] [--repeat] [--wait-matches N] [--security-dir DIR]" << std::endl;
+            return 2;
+        }
+    }
+    catch (const std::exception& e)
+    {
+        std::cerr << e.what() << std::endl;
+        return 2;
+    }
+
+    DomainParticipantQos participant_qos = PARTICIPANT_QOS_DEFAULT;
+    participant_qos.name("rustdds_interop_helper");
+    if (!options.security_dir.empty())
+    {
+        add_security_properties(participant_qos, options.security_dir);
+    }
+    DomainParticipant* participant =
+            DomainParticipantFactory::get_instance()->create_participant(options.domain, participant_qos);
+    if (participant == nullptr)
+    {
+        std::cerr << "Could not create DomainParticipant" << std::endl;
+        return 1;
+    }
+
+    TypeSupport type(new InteropSampleType());
+    type.register_type(participant);
+    Topic* topic = participant->create_topic(options.topic, type.get_type_name(), TOPIC_QOS_DEFAULT);
+    if (topic == nullptr)
+    {
+        std::cerr << "Could not create Topic" << std::endl;
+        return 1;
+    }
+
+    ReliabilityQosPolicyKind reliability =
+            options.reliable ? RELIABLE_RELIABILITY_QOS : BEST_EFFORT_RELIABILITY_QOS;
+    DurabilityQosPolicyKind durability =
+            options.transient_local ? TRANSIENT_LOCAL_DURABILITY_QOS : VOLATILE_DURABILITY_QOS;
+
+    if (options.role == "pub")
+    {
+        Publisher* publisher = participant->create_publisher(PUBLISHER_QOS_DEFAULT);
+        DataWriterQos qos = DATAWRITER_QOS_DEFAULT;
+        qos.reliability().kind = reliability;
+        qos.durability().kind = durability;
+        qos.history().kind = KEEP_ALL_HISTORY_QOS;
+        qos.endpoint().history_memory_policy = eprosima::fastrtps::rtps::DYNAMIC_REUSABLE_MEMORY_MODE;
+        WriterListener listener;
+        DataWriter* writer = publisher->create_datawriter(topic, qos, &listener);
+        if (writer == nullptr)
+        {
+            std::cerr << "Could not create DataWriter" << std::endl;
+            return 1;
+        }
+        report("READY");
+
+        while (listener.matched < options.wait_matches)
+        {
+            std::this_thread::sleep_for(std::chrono::milliseconds(10));
+        }
+        do
+        {
+            publish(writer, options);
+            report("WRITTEN");
+            if (options.reliable && options.wait_matches > 0 &&
+                    writer->wait_for_acknowledgments(eprosima::fastrtps::Duration_t(30, 0)) ==
+                    ReturnCode_t::RETCODE_OK)
+            {
+                report("ACKED");
+            }
+            std::this_thread::sleep_for(std::chrono::milliseconds(200));
+        } while (options.repeat);
+        run_until_killed();
+    }
+    else
+    {
+        Subscriber* subscriber = participant->create_subscriber(SUBSCRIBER_QOS_DEFAULT);
+        DataReaderQos qos = DATAREADER_QOS_DEFAULT;
+        qos.reliability().kind = reliability;
+        qos.durability().kind = durability;
+        qos.history().kind = KEEP_ALL_HISTORY_QOS;
+        qos.endpoint().history_memory_policy = eprosima::fastrtps::rtps::DYNAMIC_REUSABLE_MEMORY_MODE;
+        ReaderListener listener;
+        DataReader* reader = subscriber->create_datareader(topic, qos, &listener);
+        if (reader == nullptr)
+        {
+            std::cerr << "Could not create DataReader" << std::endl;
+            return 1;
+        }
+        report("READY");
+        run_until_killed();
+    }
+}
@@ -0,0 +1,723 @@
+// Harness for running interoperability scenarios between RustDDS and the Fast
+// DDS helper process. See README.md.
+
+use std::{
+  collections::{BTreeSet, VecDeque},
+  env,
+  fs::{self, File},
+  io::{BufRead, BufReader, Write},
+  path::{Path, PathBuf},
+  process::{Child, Command, Stdio},
+  sync::{
+    mpsc::{self, Receiver, RecvTimeoutError},
+    Mutex, OnceLock, PoisonError,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "security")]
+use rustdds::DomainParticipantSecurityConfigFiles;
+use rustdds::{
+  policy::{Durability, History, Reliability},
+  with_key::{DataReader, DataWriter, Sample},
+  DataReaderStatus, DataWriterStatus, DomainParticipant, DomainParticipantBuilder, Keyed,
+  QosPolicies, QosPolicyBuilder, StatusEvented, Topic, TopicKind,
+};
+
+// The security configuration files only permit domain 0
+const DOMAIN_ID: u16 = 0;
+// Topic permitted by the security configuration files
+#[cfg(feature = "security")]
+const SECURE_TOPIC_NAME: &str = "Square";
+const TYPE_NAME: &str = "InteropSample";
+// Interval of resending the samples in best-effort scenarios
+const BEST_EFFORT_RESEND_INTERVAL: Duration = Duration::from_millis(200);
+
+// Scenarios share the network and the packet capture, so they are run one at
+// a time
+static SCENARIO_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InteropSample {
+  pub key: String,
+  pub seq: u32,
+  pub payload: Vec<u8>,
+}
+
+impl Keyed for InteropSample {
+  type K = String;
+  fn key(&self) -> String {
+    self.key.clone()
+  }
+}
+
+impl InteropSample {
+  fn new(key: &str, seq: u32, payload_size: usize) -> Self {
+    InteropSample {
+      key: key.to_string(),
+      seq,
+      payload: payload(key, seq, payload_size),
+    }
+  }
+}
+
+// Must match make_payload() in interop_helper.cpp
+fn payload(key: &str, seq: u32, len: usize) -> Vec<u8> {
+  (0..len as u64)
+    .map(|i| ((i * 31 + u64::from(seq) * 7 + key.len() as u64) % 251) as u8)
+    .collect()
+}
+
+// Must match fnv1a() in interop_helper.cpp
+fn fnv1a(data: &[u8]) -> u64 {
+  data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+  RustDdsToFastDds,
+  FastDdsToRustDds,
+}
+
+/// An interoperability scenario. The publishing side writes
+/// `samples_per_key` samples for each key, and optionally disposes the keys
+/// afterwards. The scenario passes when the subscribing side has received
+/// every sample intact, and all dispose notifications.
+pub struct Scenario {
+  name: String,
+  direction: Direction,
+  reliable: bool,
+  late_join: bool,
+  keys: Vec<String>,
+  samples_per_key: u32,
+  payload_size: usize,
+  dispose: bool,
+  #[cfg(feature = "security")]
+  secure: bool,
+  timeout: Duration,
+}
+
+impl Scenario {
+  pub fn new(name: &str) -> Self {
+    Scenario {
+      name: name.to_string(),
+      direction: Direction::RustDdsToFastDds,
+      reliable: false,
+      late_join: false,
+      keys: vec!["A".to_string()],
+      samples_per_key: 10,
+      payload_size: 64,
+      dispose: false,
+      #[cfg(feature = "security")]
+      secure: false,
+      timeout: Duration::from_secs(30),
+    }
+  }
+
+  pub fn direction(mut self, direction: Direction) -> Self {
+    self.direction = direction;
+    self
+  }
+
+  pub fn reliable(mut self) -> Self {
+    self.reliable = true;
+    self
+  }
+
+  /// The publisher is TransientLocal and writes all samples before the
+  /// subscriber is created. Implies Reliable.
+  pub fn transient_local_late_join(mut self) -> Self {
+    self.reliable = true;
+    self.late_join = true;
+    self
+  }
+
+  pub fn keys(mut self, keys: &[&str]) -> Self {
+    self.keys = keys.iter().map(|key| key.to_string()).collect();
+    self
+  }
+
+  pub fn samples_per_key(mut self, samples_per_key: u32) -> Self {
+    self.samples_per_key = samples_per_key;
+    self
+  }
+
+  pub fn payload_size(mut self, payload_size: usize) -> Self {
+    self.payload_size = payload_size;
+    self
+  }
+
+  /// Dispose all keys after writing the samples. Needs a reliable scenario,
+  /// as dispose notifications are not resent.
+  pub fn dispose(mut self) -> Self {
+    self.dispose = true;
+    self
+  }
+
+  /// Enable DDS Security on both sides, with the configuration files from
+  /// `examples/security_configuration_files`.
+  #[cfg(feature = "security")]
+  pub fn secure(mut self) -> Self {
+    self.secure = true;
+    self
+  }
+
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Runs the scenario, or skips it if the Fast DDS helper is not available.
+  /// Panics if the scenario fails.
+  pub fn run(self) {
+    assert!(
+      self.reliable || !self.dispose,
+      "Dispose is only checked in reliable scenarios"
+    );
+    let Some(helper) = helper_binary() else {
+      eprintln!(
+        "Skipping {}: the Fast DDS interop helper is not available. See tests/interop/README.md",
+        self.name
+      );
+      return;
+    };
+    let _one_at_a_time = SCENARIO_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let artifacts = Artifacts::start(&self.name);
+    let result = match self.direction {
+      Direction::RustDdsToFastDds => self.run_rustdds_publisher(&helper, &artifacts),
+      Direction::FastDdsToRustDds => self.run_rustdds_subscriber(&helper, &artifacts),
+    };
+    match result {
+      Ok(()) => artifacts.finish(true),
+      Err(e) => {
+        let dir = artifacts.dir.clone();
+        artifacts.finish(false);
+        panic!(
+          "Scenario {} failed: {e}\nArtifacts are in {}",
+          self.name,
+          dir.display()
+        );
+      }
+    }
+  }
+
+  fn run_rustdds_publisher(&self, helper: &Path, artifacts: &Artifacts) -> Result<(), String> {
+    let deadline = Instant::now() + self.timeout;
+    let participant = self.participant()?;
+    let (topic, qos) = self.topic(&participant)?;
+    let writer = participant
+      .create_publisher(&qos)
+      .and_then(|publisher| publisher.create_datawriter_cdr::<InteropSample>(&topic, None))
+      .map_err(|e| format!("Creating the DataWriter failed: {e:?}"))?;
+
+    if self.late_join {
+      self.write_samples(&writer)?;
+    }
+
+    let mut fastdds = HelperProcess::spawn(helper, &self.helper_args("sub", 0), artifacts)?;
+    fastdds.expect_line("READY", deadline)?;
+    wait_for_writer_match(&writer, deadline)?;
+    fastdds.expect_line("MATCHED 1", deadline)?;
+
+    if !self.late_join {
+      self.write_samples(&writer)?;
+    }
+
+    let mut missing_samples = self.expected_samples();
+    let mut missing_disposes = self.expected_disposes();
+    let mut last_write = Instant::now();
+    while !missing_samples.is_empty() || !missing_disposes.is_empty() {
+      let now = Instant::now();
+      if now >= deadline {
+        return Err(format!(
+          "Timed out. Fast DDS did not receive samples {missing_samples:?} and disposes \
+           {missing_disposes:?}"
+        ));
+      }
+      if !self.reliable && now - last_write >= BEST_EFFORT_RESEND_INTERVAL {
+        self.write_samples(&writer)?;
+        last_write = now;
+      }
+
+      let Some(line) = fastdds.next_line(BEST_EFFORT_RESEND_INTERVAL)? else {
+        continue;
+      };
+      let fields: Vec<&str> = line.split_whitespace().collect();
+      match fields.as_slice() {
+        ["SAMPLE", key, seq, len, hash] => {
+          let seq: u32 = seq
+            .parse()
+            .map_err(|_| format!("Bad sequence number in {line:?}"))?;
+          let expected = InteropSample::new(key, seq, self.payload_size);
+          let expected_len = expected.payload.len().to_string();
+          let expected_hash = format!("{:x}", fnv1a(&expected.payload));
+          if *len != expected_len || *hash != expected_hash {
+            return Err(format!(
+              "Fast DDS received sample {key}/{seq} with length {len} and hash {hash}, expected \
+               {expected_len} and {expected_hash}"
+            ));
+          }
+          missing_samples.remove(&(key.to_string(), seq));
+        }
+        ["DISPOSED", key] => {
+          missing_disposes.remove(*key);
+        }
+        _ => {}
+      }
+    }
+
+    if self.reliable {
+      writer
+        .wait_for_acknowledgments(Duration::from_secs(5))
+        .map_err(|e| format!("Waiting for acknowledgments failed: {e:?}"))?;
+    }
+    check_writer_statuses(&writer)
+  }
+
+  fn run_rustdds_subscriber(&self, helper: &Path, artifacts: &Artifacts) -> Result<(), String> {
+    let deadline = Instant::now() + self.timeout;
+    let wait_matches = if self.late_join { 0 } else { 1 };
+    let mut fastdds =
+      HelperProcess::spawn(helper, &self.helper_args("pub", wait_matches), artifacts)?;
+    fastdds.expect_line("READY", deadline)?;
+    if self.late_join {
+      fastdds.expect_line("WRITTEN", deadline)?;
+    }
+
+    let participant = self.participant()?;
+    let (topic, qos) = self.topic(&participant)?;
+    let mut reader = participant
+      .create_subscriber(&qos)
+      .and_then(|subscriber| {
+        subscriber.create_datareader_cdr::<InteropSample>(&topic, Some(qos.clone()))
+      })
+      .map_err(|e| format!("Creating the DataReader failed: {e:?}"))?;
+
+    wait_for_reader_match(&reader, deadline)?;
+    fastdds.expect_line("MATCHED 1", deadline)?;
+
+    let mut missing_samples = self.expected_samples();
+    let mut missing_disposes = self.expected_disposes();
+    while !missing_samples.is_empty() || !missing_disposes.is_empty() {
+      if Instant::now() >= deadline {
+        return Err(format!(
+          "Timed out. RustDDS did not receive samples {missing_samples:?} and disposes \
+           {missing_disposes:?}"
+        ));
+      }
+      match reader.take_next_sample() {
+        Ok(Some(sample)) => match sample.into_value() {
+          Sample::Value(sample) => {
+            let expected = InteropSample::new(&sample.key, sample.seq, self.payload_size);
+            if sample != expected {
+              return Err(format!(
+                "RustDDS received sample {}/{} with a payload of {} bytes that differs from the \
+                 expected one",
+                sample.key,
+                sample.seq,
+                sample.payload.len()
+              ));
+            }
+            missing_samples.remove(&(sample.key, sample.seq));
+          }
+          Sample::Dispose(key) => {
+            missing_disposes.remove(&key);
+          }
+        },
+        Ok(None) => thread::sleep(Duration::from_millis(10)),
+        Err(e) => return Err(format!("Reading failed: {e:?}")),
+      }
+    }
+    check_reader_statuses(&reader, self.reliable)
+  }
+
+  fn participant(&self) -> Result<DomainParticipant, String> {
+    let builder = DomainParticipantBuilder::new(DOMAIN_ID);
+    #[cfg(feature = "security")]
+    let builder = if self.secure {
+      builder.builtin_security(
+        DomainParticipantSecurityConfigFiles::with_ros_default_names(
+          security_configuration_dir(),
+          "no_pwd".to_string(),
+        ),
+      )
+    } else {
+      builder
+    };
+    builder
+      .build()
+      .map_err(|e| format!("Creating the DomainParticipant failed: {e:?}"))
+  }
+
+  fn topic_name(&self) -> String {
+    #[cfg(feature = "security")]
+    if self.secure {
+      return SECURE_TOPIC_NAME.to_string();
+    }
+    format!("interop_{}", self.name)
+  }
+
+  fn qos(&self) -> QosPolicies {
+    QosPolicyBuilder::new()
+      .reliability(if self.reliable {
+        Reliability::Reliable {
+          max_blocking_time: rustdds::Duration::from_secs(1),
+        }
+      } else {
+        Reliability::BestEffort
+      })
+      .durability(if self.late_join {
+        Durability::TransientLocal
+      } else {
+        Durability::Volatile
+      })
+      .history(History::KeepAll)
+      .build()
+  }
+
+  fn topic(&self, participant: &DomainParticipant) -> Result<(Topic, QosPolicies), String> {
+    let qos = self.qos();
+    participant
+      .create_topic(
+        self.topic_name(),
+        TYPE_NAME.to_string(),
+        &qos,
+        TopicKind::WithKey,
+      )
+      .map(|topic| (topic, qos))
+      .map_err(|e| format!("Creating the Topic failed: {e:?}"))
+  }
+
+  fn helper_args(&self, role: &str, wait_matches: u32) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+      "--role".into(),
+      role.into(),
+      "--domain".into(),
+      DOMAIN_ID.to_string(),
+      "--topic".into(),
+      self.topic_name(),
+      "--keys".into(),
+      self.keys.join(","),
+      "--samples".into(),
+      self.samples_per_key.to_string(),
+      "--payload-size".into(),
+      self.payload_size.to_string(),
+      "--wait-matches".into(),
+      wait_matches.to_string(),
+    ];
+    if self.reliable {
+      args.push("--reliable".into());
+    } else {
+      args.push("--repeat".into());
+    }
+    if self.late_join {
+      args.push("--transient-local".into());
+    }
+    if self.dispose {
+      args.push("--dispose".into());
+    }
+    #[cfg(feature = "security")]
+    if self.secure {
+      args.push("--security-dir".into());
+      args.push(security_configuration_dir().display().to_string());
+    }
+    args
+  }
+
+  // Samples are written in the same order as the helper writes them
+  fn write_samples(&self, writer: &DataWriter<InteropSample>) -> Result<(), String> {
+    for seq in 0..self.samples_per_key {
+      for key in &self.keys {
+        writer
+          .write(InteropSample::new(key, seq, self.payload_size), None)
+          .map_err(|e| format!("Writing sample {key}/{seq} failed: {e:?}"))?;
+      }
+    }
+    if self.dispose {
+      for key in &self.keys {
+        writer
+          .dispose(key, None)
+          .map_err(|e| format!("Disposing {key} failed: {e:?}"))?;
+      }
+    }
+    Ok(())
+  }
+
+  fn expected_samples(&self) -> BTreeSet<(String, u32)> {
+    self
+      .keys
+      .iter()
+      .flat_map(|key| (0..self.samples_per_key).map(move |seq| (key.clone(), seq)))
+      .collect()
+  }
+
+  fn expected_disposes(&self) -> BTreeSet<String> {
+    if self.dispose {
+      self.keys.iter().cloned().collect()
+    } else {
+      BTreeSet::new()
+    }
+  }
+}
+
+#[cfg(feature = "security")]
+fn security_configuration_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/security_configuration_files")
+}
+
+fn wait_for_writer_match(
+  writer: &DataWriter<InteropSample>,
+  deadline: Instant,
+) -> Result<(), String> {
+  while Instant::now() < deadline {
+    while let Some(status) = writer.try_recv_status() {
+      if let DataWriterStatus::PublicationMatched { current, .. } = status {
+        if current.count() > 0 {
+          return Ok(());
+        }
+      }
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  Err("Timed out waiting for the RustDDS DataWriter to match".to_string())
+}
+
+fn wait_for_reader_match(
+  reader: &DataReader<InteropSample>,
+  deadline: Instant,
+) -> Result<(), String> {
+  while Instant::now() < deadline {
+    while let Some(status) = reader.try_recv_status() {
+      if let DataReaderStatus::SubscriptionMatched { current, .. } = status {
+        if current.count() > 0 {
+          return Ok(());
+        }
+      }
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  Err("Timed out waiting for the RustDDS DataReader to match".to_string())
+}
+
+// Fails on statuses that indicate a problem with the remote endpoint
+fn check_writer_statuses(writer: &DataWriter<InteropSample>) -> Result<(), String> {
+  while let Some(status) = writer.try_recv_status() {
+    if let DataWriterStatus::OfferedIncompatibleQos { .. } = status {
+      return Err(format!("Unexpected DataWriter status {status:?}"));
+    }
+  }
+  Ok(())
+}
+
+// Samples may be lost only in best-effort scenarios
+fn check_reader_statuses(reader: &DataReader<InteropSample>, reliable: bool) -> Result<(), String> {
+  while let Some(status) = reader.try_recv_status() {
+    match status {
+      DataReaderStatus::RequestedIncompatibleQos { .. } => {}
+      DataReaderStatus::SampleLost { .. } if reliable => {}
+      _ => continue,
+    }
+    return Err(format!("Unexpected DataReader status {status:?}"));
+  }
+  Ok(())
+}
+
+/// Returns the path of the Fast DDS helper executable, or None if it is not
+/// available. The path is taken from the environment variable
+/// FASTDDS_INTEROP_HELPER, or the helper is built with CMake.
+pub fn helper_binary() -> Option<PathBuf> {
+  static HELPER: OnceLock<Option<PathBuf>> = OnceLock::new();
+  HELPER
+    .get_or_init(|| match env::var_os("FASTDDS_INTEROP_HELPER") {
+      Some(path) => Some(PathBuf::from(path)),
+      None => build_helper(),
+    })
+    .clone()
+}
+
+fn build_helper() -> Option<PathBuf> {
+  let source_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/interop/fastdds_helper");
+  let build_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fastdds_helper");
+
+  let run = |command: &mut Command| match command.output() {
+    Ok(output) if output.status.success() => true,
+    Ok(output) => {
+      eprintln!(
+        "Building the Fast DDS interop helper failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+      );
+      false
+    }
+    Err(e) => {
+      eprintln!("Could not run cmake to build the Fast DDS interop helper: {e}");
+      false
+    }
+  };
+
+  let built = run(
+    Command::new("cmake")
+      .arg("-S")
+      .arg(&source_dir)
+      .arg("-B")
+      .arg(&build_dir)
+      .arg("-DCMAKE_BUILD_TYPE=Release"),
+  ) && run(Command::new("cmake").arg("--build").arg(&build_dir));
+
+  let helper = build_dir.join("interop_helper");
+  (built && helper.exists()).then_some(helper)
+}
+
+/// Files collected while running a scenario: the output of the helper and a
+/// packet capture, if tcpdump is available. They are kept only if the
+/// scenario fails.
+pub struct Artifacts {
+  dir: PathBuf,
+  capture: Option<Child>,
+}
+
+impl Artifacts {
+  fn start(scenario_name: &str) -> Self {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+      .join("interop-artifacts")
+      .join(scenario_name);
+    // Artifacts of an earlier run may or may not exist
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap_or_else(|e| panic!("Could not create {}: {e}", dir.display()));
+
+    let capture = File::create(dir.join("tcpdump.log")).ok().and_then(|log| {
+      Command::new("tcpdump")
+        .args(["-i", "any", "-U", "-w"])
+        .arg(dir.join("capture.pcap"))
+        .arg("udp")
+        .stdout(Stdio::null())
+        .stderr(log)
+        .spawn()
+        .ok()
+    });
+    if capture.is_some() {
+      // Give tcpdump time to start capturing
+      thread::sleep(Duration::from_millis(500));
+    }
+    Artifacts { dir, capture }
+  }
+
+  fn path(&self, file_name: &str) -> PathBuf {
+    self.dir.join(file_name)
+  }
+
+  fn finish(mut self, passed: bool) {
+    if let Some(mut capture) = self.capture.take() {
+      let _ = capture.kill();
+      let _ = capture.wait();
+    }
+    if passed {
+      let _ = fs::remove_dir_all(&self.dir);
+    }
+  }
+}
+
+/// A running Fast DDS helper process. Its stdout is read line by line in a
+/// background thread and logged to the artifacts. The process is killed on
+/// drop.
+pub struct HelperProcess {
+  child: Child,
+  lines: Receiver<String>,
+  // Lines that were skipped while waiting for an expected line
+  pending: VecDeque<String>,
+}
+
+impl HelperProcess {
+  pub fn spawn(program: &Path, args: &[String], artifacts: &Artifacts) -> Result<Self, String> {
+    let role = args
+      .iter()
+      .skip_while(|arg| *arg != "--role")
+      .nth(1)
+      .map_or("helper", String::as_str);
+    let log_error = |e| format!("Could not create a log file in the artifacts: {e}");
+    let stderr =
+      File::create(artifacts.path(&format!("fastdds_{role}.stderr.log"))).map_err(log_error)?;
+    let mut stdout_log =
+      File::create(artifacts.path(&format!("fastdds_{role}.stdout.log"))).map_err(log_error)?;
+
+    let mut child = Command::new(program)
+      .args(args)
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(stderr)
+      .spawn()
+      .map_err(|e| format!("Could not start {}: {e}", program.display()))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+      for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        let _ = writeln!(stdout_log, "{line}");
+        if sender.send(line).is_err() {
+          break;
+        }
+      }
+    });
+
+    Ok(HelperProcess {
+      child,
+      lines,
+      pending: VecDeque::new(),
+    })
+  }
+
+  /// The next line of output, or None if there was none within the timeout.
+  /// Fails if the helper has exited.
+  pub fn next_line(&mut self, timeout: Duration) -> Result<Option<String>, String> {
+    if let Some(line) = self.pending.pop_front() {
+      return Ok(Some(line));
+    }
+    match self.lines.recv_timeout(timeout) {
+      Ok(line) => Ok(Some(line)),
+      Err(RecvTimeoutError::Timeout) => Ok(None),
+      Err(RecvTimeoutError::Disconnected) => Err(format!(
+        "The Fast DDS helper exited: {}",
+        self
+          .child
+          .wait()
+          .map_or_else(|e| e.to_string(), |status| status.to_string())
+      )),
+    }
+  }
+
+  /// Waits for the given line. Other lines received meanwhile are returned
+  /// later by next_line.
+  pub fn expect_line(&mut self, expected: &str, deadline: Instant) -> Result<(), String> {
+    if let Some(position) = self.pending.iter().position(|line| line == expected) {
+      self.pending.remove(position);
+      return Ok(());
+    }
+    loop {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return Err(format!("Timed out waiting for {expected:?} from Fast DDS"));
+      }
+      match self.lines.recv_timeout(remaining) {
+        Ok(line) if line == expected => return Ok(()),
+        Ok(line) => self.pending.push_back(line),
+        Err(RecvTimeoutError::Timeout) => {}
+        Err(RecvTimeoutError::Disconnected) => {
+          return Err(format!(
+            "The Fast DDS helper exited while waiting for {expected:?}"
+          ))
+        }
+      }
+    }
+  }
+}
+
+impl Drop for HelperProcess {
+  fn drop(&mut self) {
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+  }
+}
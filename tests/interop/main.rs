@@ -0,0 +1,128 @@
+//! Interoperability tests against eProsima Fast DDS. See README.md in this
+//! directory.
+//!
+//! Each scenario is run in both directions: a RustDDS publisher with a Fast DDS
+//! subscriber, and a Fast DDS publisher with a RustDDS subscriber.
+
+mod harness;
+
+use std::time::Duration;
+
+use harness::{Direction, Scenario};
+
+// Large enough to need fragmentation
+const LARGE_PAYLOAD_SIZE: usize = 256 * 1024;
+
+#[test]
+fn best_effort_rustdds_to_fastdds() {
+  Scenario::new("best_effort_rustdds_to_fastdds")
+    .direction(Direction::RustDdsToFastDds)
+    .run();
+}
+
+#[test]
+fn best_effort_fastdds_to_rustdds() {
+  Scenario::new("best_effort_fastdds_to_rustdds")
+    .direction(Direction::FastDdsToRustDds)
+    .run();
+}
+
+#[test]
+fn reliable_rustdds_to_fastdds() {
+  Scenario::new("reliable_rustdds_to_fastdds")
+    .direction(Direction::RustDdsToFastDds)
+    .reliable()
+    .samples_per_key(100)
+    .run();
+}
+
+#[test]
+fn reliable_fastdds_to_rustdds() {
+  Scenario::new("reliable_fastdds_to_rustdds")
+    .direction(Direction::FastDdsToRustDds)
+    .reliable()
+    .samples_per_key(100)
+    .run();
+}
+
+#[test]
+fn keyed_dispose_rustdds_to_fastdds() {
+  Scenario::new("keyed_dispose_rustdds_to_fastdds")
+    .direction(Direction::RustDdsToFastDds)
+    .reliable()
+    .keys(&["RED", "GREEN", "BLUE"])
+    .dispose()
+    .run();
+}
+
+#[test]
+fn keyed_dispose_fastdds_to_rustdds() {
+  Scenario::new("keyed_dispose_fastdds_to_rustdds")
+    .direction(Direction::FastDdsToRustDds)
+    .reliable()
+    .keys(&["RED", "GREEN", "BLUE"])
+    .dispose()
+    .run();
+}
+
+#[test]
+fn transient_local_late_join_rustdds_to_fastdds() {
+  Scenario::new("transient_local_late_join_rustdds_to_fastdds")
+    .direction(Direction::RustDdsToFastDds)
+    .transient_local_late_join()
+    .keys(&["RED", "BLUE"])
+    .run();
+}
+
+#[test]
+fn transient_local_late_join_fastdds_to_rustdds() {
+  Scenario::new("transient_local_late_join_fastdds_to_rustdds")
+    .direction(Direction::FastDdsToRustDds)
+    .transient_local_late_join()
+    .keys(&["RED", "BLUE"])
+    .run();
+}
+
+#[test]
+fn large_fragmented_rustdds_to_fastdds() {
+  Scenario::new("large_fragmented_rustdds_to_fastdds")
+    .direction(Direction::RustDdsToFastDds)
+    .reliable()
+    .samples_per_key(3)
+    .payload_size(LARGE_PAYLOAD_SIZE)
+    .timeout(Duration::from_secs(60))
+    .run();
+}
+
+#[test]
+fn large_fragmented_fastdds_to_rustdds() {
+  Scenario::new("large_fragmented_fastdds_to_rustdds")
+    .direction(Direction::FastDdsToRustDds)
+    .reliable()
+    .samples_per_key(3)
+    .payload_size(LARGE_PAYLOAD_SIZE)
+    .timeout(Duration::from_secs(60))
+    .run();
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn secure_rustdds_to_fastdds() {
+  Scenario::new("secure_rustdds_to_fastdds")
+    .direction(Direction::RustDdsToFastDds)
+    .reliable()
+    .secure()
+    .timeout(Duration::from_secs(60))
+    .run();
+}
+
+#[cfg(feature = "security")]
+#[test]
+fn secure_fastdds_to_rustdds() {
+  Scenario::new("secure_fastdds_to_rustdds")
+    .direction(Direction::FastDdsToRustDds)
+    .reliable()
+    .secure()
+    .timeout(Duration::from_secs(60))
+    .run();
+}